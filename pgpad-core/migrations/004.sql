@@ -0,0 +1,9 @@
+-- Directories of .sql files listed in the scripts sidebar.
+-- Their scripts are read from disk, so only the directory is stored here.
+CREATE TABLE linked_script_directories (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL UNIQUE,
+    connection_id TEXT,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (connection_id) REFERENCES connections(id) ON DELETE SET NULL
+);
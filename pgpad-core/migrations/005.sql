@@ -0,0 +1,9 @@
+-- Named sets of options for exporting results as delimited text.
+-- `options` is the JSON-serialized ExportOptions.
+CREATE TABLE export_templates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    options TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Per-statement timing breakdown of a run, as a JSON-serialized Vec<TimingBreakdown>.
+ALTER TABLE query_history ADD COLUMN timings TEXT;
@@ -0,0 +1,12 @@
+-- User-defined starter templates for new scripts, per database type.
+-- A template named like a built-in one replaces it.
+CREATE TABLE script_templates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    db_type TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    body TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    UNIQUE (db_type, name)
+);
@@ -0,0 +1,3 @@
+-- Supports paging through a connection's history, newest first, with (executed_at, id) as the cursor.
+CREATE INDEX idx_query_history_connection_executed_at
+    ON query_history(connection_id, executed_at DESC, id DESC);
@@ -0,0 +1,7 @@
+-- Expansion state of each connection's Items panel, as versioned JSON. Dropped along with the connection.
+CREATE TABLE tree_states (
+    connection_id TEXT PRIMARY KEY,
+    state TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (connection_id) REFERENCES connections(id) ON DELETE CASCADE
+);
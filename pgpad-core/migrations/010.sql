@@ -0,0 +1,3 @@
+-- Where the file of a SQLite connection is relative to the home directory (e.g. `~/data/app.db`), for when the
+-- storage is synced to a machine where the absolute path doesn't exist. NULL for files outside the home directory.
+ALTER TABLE connections ADD COLUMN home_relative_path TEXT;
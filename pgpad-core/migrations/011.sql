@@ -0,0 +1,10 @@
+-- Embeddings of each connection's tables for semantic search, rebuilt incrementally as schemas change.
+-- `content_hash` covers the text a vector was computed from and the model that computed it.
+CREATE TABLE schema_vectors (
+    connection_id TEXT NOT NULL,
+    object_key TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    vector BLOB NOT NULL,
+    PRIMARY KEY (connection_id, object_key),
+    FOREIGN KEY (connection_id) REFERENCES connections(id) ON DELETE CASCADE
+);
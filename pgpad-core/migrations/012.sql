@@ -0,0 +1,3 @@
+-- The SSH tunnel of a Postgres connection, as JSON, for servers only reachable through a bastion host. Passwords
+-- are kept in the keyring rather than here.
+ALTER TABLE connections ADD COLUMN ssh_tunnel TEXT;
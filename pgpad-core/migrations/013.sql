@@ -0,0 +1,3 @@
+-- How many times the query of a history entry was run, since running the same query again updates its entry
+-- rather than adding another.
+ALTER TABLE query_history ADD COLUMN run_count INTEGER NOT NULL DEFAULT 1;
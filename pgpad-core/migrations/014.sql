@@ -0,0 +1,2 @@
+-- MySQL and MariaDB connections, whose connection strings are kept in connection_data like Postgres ones
+INSERT INTO database_types (id, name) VALUES (3, 'mysql');
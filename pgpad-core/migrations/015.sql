@@ -0,0 +1,2 @@
+-- Folders that group connections, e.g. `client/staging`. NULL for connections outside of folders.
+ALTER TABLE connections ADD COLUMN folder TEXT;
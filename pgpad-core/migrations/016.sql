@@ -0,0 +1,2 @@
+-- What each connection is used for (dev, staging, prod or custom). Its color goes in the `color` column, unused until now
+ALTER TABLE connections ADD COLUMN environment TEXT;
@@ -0,0 +1,35 @@
+-- Snippets expanded by completion when their prefix is typed, with tab stops like `$1` or `${1:table}` in their body.
+-- Scoped to a connection, to a database type, or to neither. The defaults below can be edited or deleted like any other.
+CREATE TABLE snippets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    body TEXT NOT NULL,
+    connection_id TEXT,
+    db_type TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (connection_id) REFERENCES connections(id) ON DELETE CASCADE
+);
+
+INSERT INTO snippets (name, prefix, body, created_at, updated_at)
+SELECT name, prefix, body, CAST(strftime('%s', 'now') AS INTEGER), CAST(strftime('%s', 'now') AS INTEGER)
+FROM (
+    SELECT 'Select rows' AS name, 'sel*' AS prefix, 'SELECT * FROM $1 LIMIT 100;' AS body
+    UNION ALL SELECT 'Count rows', 'count', 'SELECT count(*) FROM $1;'
+    UNION ALL SELECT 'Count by group', 'group', 'SELECT ${2:column}, count(*)
+FROM ${1:table}
+GROUP BY ${2:column}
+ORDER BY count(*) DESC;'
+    UNION ALL SELECT 'Insert a row', 'ins', 'INSERT INTO ${1:table} (${2:columns})
+VALUES (${3:values});'
+    UNION ALL SELECT 'Update rows', 'upd', 'UPDATE ${1:table}
+SET ${2:column} = ${3:value}
+WHERE $0;'
+    UNION ALL SELECT 'Delete rows', 'del', 'DELETE FROM ${1:table}
+WHERE $0;'
+    UNION ALL SELECT 'Common table expression', 'cte', 'WITH ${1:name} AS (
+    $2
+)
+SELECT * FROM ${1:name};'
+);
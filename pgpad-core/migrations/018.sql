@@ -0,0 +1,13 @@
+-- The open tabs and layout of each window, so that windows don't overwrite each other's. `version` is the version
+-- of the frontend's format of `data`, so that it can upgrade sessions saved by older versions.
+CREATE TABLE sessions (
+    session_id TEXT PRIMARY KEY,
+    version INTEGER NOT NULL,
+    data TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+-- The single session of earlier versions becomes the main window's, in the first version of the format
+INSERT INTO sessions (session_id, version, data, updated_at)
+SELECT 'main', 1, value, updated_at FROM app_settings WHERE key = 'session_state';
+DELETE FROM app_settings WHERE key = 'session_state';
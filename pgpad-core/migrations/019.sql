@@ -0,0 +1,11 @@
+-- The latest contents of editor buffers with unsaved changes, autosaved so that they can be recovered after a crash.
+-- Contents over a few KB are stored deflated, as flagged by `compressed`. Drafts outlive their connection.
+CREATE TABLE draft_buffers (
+    buffer_id TEXT PRIMARY KEY,
+    connection_id TEXT,
+    content BLOB NOT NULL,
+    compressed INTEGER NOT NULL DEFAULT 0,
+    cursor INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (connection_id) REFERENCES connections(id) ON DELETE SET NULL
+);
@@ -0,0 +1,2 @@
+-- The schema a Postgres connection puts first in its search path once connected
+ALTER TABLE connections ADD COLUMN default_schema TEXT;
@@ -0,0 +1,4 @@
+-- The plan of runs explained with ANALYZE, as JSON. Plans over a few KB are stored deflated, as flagged by
+-- `plan_compressed`.
+ALTER TABLE query_history ADD COLUMN plan BLOB;
+ALTER TABLE query_history ADD COLUMN plan_compressed INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+-- Variables substituted into queries as `${name}`. Those of no connection in particular apply to every connection,
+-- unless it has one of the same name.
+CREATE TABLE query_variables (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    connection_id TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (connection_id) REFERENCES connections(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX query_variables_name ON query_variables (name, COALESCE(connection_id, ''));
@@ -0,0 +1,20 @@
+-- Queries run in the background, every `interval_secs` or when the local time matches `cron`. The outcome of their
+-- last run is kept with them, and each run that wasn't skipped goes into the query history flagged as `scheduled`.
+CREATE TABLE scheduled_queries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    connection_id TEXT NOT NULL,
+    query_text TEXT NOT NULL,
+    interval_secs INTEGER,
+    cron TEXT,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    alert_if_rows INTEGER NOT NULL DEFAULT 0,
+    last_run_at INTEGER,
+    last_status TEXT,
+    last_error TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (connection_id) REFERENCES connections(id) ON DELETE CASCADE
+);
+
+ALTER TABLE query_history ADD COLUMN scheduled INTEGER NOT NULL DEFAULT 0;
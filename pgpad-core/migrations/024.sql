@@ -0,0 +1,3 @@
+-- Whether the statements of a scheduled query that the policy of its connection's environment holds back were
+-- confirmed to run unattended
+ALTER TABLE scheduled_queries ADD COLUMN confirmed_destructive INTEGER NOT NULL DEFAULT 0;
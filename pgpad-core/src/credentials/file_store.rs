@@ -0,0 +1,390 @@
+//! An encrypted file that secrets go to when there's no keyring to keep them, e.g. on a headless Linux box or in a
+//! Flatpak sandbox without a secret service.
+//!
+//! Each secret is sealed with ChaCha20-Poly1305, under a key derived from a passphrase the user picks with
+//! PBKDF2-HMAC-SHA256. The key is only held in memory once the file is unlocked, the passphrase never is. The names
+//! of entries aren't secret, and are each sealed secret's associated data, so that sealed secrets can't be swapped
+//! between entries. Removing an entry doesn't need the key, so a connection can be removed while the file's locked.
+
+use std::{collections::BTreeMap, fs, num::NonZeroU32, path::PathBuf, sync::Mutex};
+
+use anyhow::Context;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+pub const FILE_NAME: &str = "credentials.json";
+
+/// OWASP's recommendation for PBKDF2-HMAC-SHA256
+const DEFAULT_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+/// Sealed when the file's created, to tell a wrong passphrase from a right one
+const CHECK_NAME: &str = "pgpad.check";
+const CHECK_VALUE: &[u8] = b"pgpad";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Contents {
+    /// Base64, like the sealed secrets
+    salt: String,
+    iterations: NonZeroU32,
+    check: String,
+    /// Sealed secrets by name, each being its nonce followed by the ciphertext and tag
+    entries: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct CredentialFile {
+    path: PathBuf,
+    /// For files created from now on, existing ones keep theirs
+    iterations: NonZeroU32,
+    key: Mutex<Option<LessSafeKey>>,
+    rng: SystemRandom,
+}
+
+impl CredentialFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::with_iterations(path, DEFAULT_ITERATIONS)
+    }
+
+    fn with_iterations(path: impl Into<PathBuf>, iterations: u32) -> Self {
+        Self {
+            path: path.into(),
+            iterations: NonZeroU32::new(iterations).expect("iterations can't be 0"),
+            key: Mutex::new(None),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.lock().unwrap().is_some()
+    }
+
+    /// Derives the key from the passphrase, creating the file with it if there's no file yet
+    pub fn unlock(&self, passphrase: &str) -> Result<(), Error> {
+        if passphrase.is_empty() {
+            return Err(
+                anyhow::anyhow!("The passphrase of the credential file can't be empty").into(),
+            );
+        }
+
+        let mut key = self.key.lock().unwrap();
+        match self.read()? {
+            Some(contents) => {
+                let salt = decode(&contents.salt)?;
+                let candidate = derive_key(passphrase, &salt, contents.iterations);
+                match open(&candidate, CHECK_NAME, &contents.check) {
+                    Ok(check) if check == CHECK_VALUE => {}
+                    _ => {
+                        return Err(
+                            anyhow::anyhow!("Wrong passphrase for the credential file").into()
+                        )
+                    }
+                }
+                *key = Some(candidate);
+            }
+            None => {
+                let mut salt = [0; SALT_LEN];
+                self.rng
+                    .fill(&mut salt)
+                    .map_err(|_| anyhow::anyhow!("Failed to generate a salt"))?;
+                let candidate = derive_key(passphrase, &salt, self.iterations);
+                let contents = Contents {
+                    salt: BASE64_STANDARD.encode(salt),
+                    iterations: self.iterations,
+                    check: self.seal(&candidate, CHECK_NAME, CHECK_VALUE)?,
+                    entries: BTreeMap::new(),
+                };
+                self.write(&contents)?;
+                log::info!("Created the credential file at {}", self.path.display());
+                *key = Some(candidate);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A secret, or None if the file doesn't have it. Fails if it has it but is locked.
+    pub fn get(&self, name: &str) -> Result<Option<String>, Error> {
+        let key = self.key.lock().unwrap();
+        let Some(sealed) = self
+            .read()?
+            .and_then(|mut contents| contents.entries.remove(name))
+        else {
+            return Ok(None);
+        };
+        let key = key.as_ref().ok_or_else(locked)?;
+
+        let secret = open(key, name, &sealed)?;
+        Ok(Some(
+            String::from_utf8(secret).context("A secret of the credential file isn't UTF-8")?,
+        ))
+    }
+
+    pub fn insert(&self, name: &str, secret: &str) -> Result<(), Error> {
+        let key = self.key.lock().unwrap();
+        let key = key.as_ref().ok_or_else(locked)?;
+        let mut contents = self.read()?.ok_or_else(locked)?;
+
+        let sealed = self.seal(key, name, secret.as_bytes())?;
+        contents.entries.insert(name.to_owned(), sealed);
+        self.write(&contents)
+    }
+
+    /// Returns whether the file had the secret
+    pub fn remove(&self, name: &str) -> Result<bool, Error> {
+        let _key = self.key.lock().unwrap();
+        let Some(mut contents) = self.read()? else {
+            return Ok(false);
+        };
+        if contents.entries.remove(name).is_none() {
+            return Ok(false);
+        }
+        self.write(&contents)?;
+        Ok(true)
+    }
+
+    fn read(&self) -> Result<Option<Contents>, Error> {
+        let json = match fs::read_to_string(&self.path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!("Failed to read {}", self.path.display()))
+                    .into())
+            }
+        };
+        let contents = serde_json::from_str(&json)
+            .with_context(|| format!("{} isn't a credential file", self.path.display()))?;
+        Ok(Some(contents))
+    }
+
+    /// Writes to a temporary file first, so that a failed write can't lose the secrets already there
+    fn write(&self, contents: &Contents) -> Result<(), Error> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let temp = self.path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec_pretty(contents)?)
+            .with_context(|| format!("Failed to write {}", temp.display()))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&temp, fs::Permissions::from_mode(0o600))
+                .context("Failed to restrict the permissions of the credential file")?;
+        }
+
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        Ok(())
+    }
+
+    fn seal(&self, key: &LessSafeKey, name: &str, secret: &[u8]) -> Result<String, Error> {
+        seal(&self.rng, key, name, secret)
+    }
+}
+
+/// How to derive the key of secrets sealed outside of the credential file, e.g. in an exported connection file,
+/// with the same scheme
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Seal {
+    salt: String,
+    iterations: NonZeroU32,
+    check: String,
+}
+
+#[derive(Debug)]
+pub struct SealKey {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl Seal {
+    /// A seal with a new salt, and its key
+    pub fn new(passphrase: &str) -> Result<(Self, SealKey), Error> {
+        Self::with_iterations(passphrase, DEFAULT_ITERATIONS)
+    }
+
+    pub(crate) fn with_iterations(
+        passphrase: &str,
+        iterations: u32,
+    ) -> Result<(Self, SealKey), Error> {
+        if passphrase.is_empty() {
+            return Err(anyhow::anyhow!("The passphrase can't be empty").into());
+        }
+
+        let rng = SystemRandom::new();
+        let mut salt = [0; SALT_LEN];
+        rng.fill(&mut salt)
+            .map_err(|_| anyhow::anyhow!("Failed to generate a salt"))?;
+        let iterations = NonZeroU32::new(iterations).expect("iterations can't be 0");
+        let key = derive_key(passphrase, &salt, iterations);
+
+        let seal = Self {
+            salt: BASE64_STANDARD.encode(salt),
+            iterations,
+            check: seal(&rng, &key, CHECK_NAME, CHECK_VALUE)?,
+        };
+        Ok((seal, SealKey { key, rng }))
+    }
+
+    pub fn unlock(&self, passphrase: &str) -> Result<SealKey, Error> {
+        let key = derive_key(passphrase, &decode(&self.salt)?, self.iterations);
+        match open(&key, CHECK_NAME, &self.check) {
+            Ok(check) if check == CHECK_VALUE => Ok(SealKey {
+                key,
+                rng: SystemRandom::new(),
+            }),
+            _ => Err(anyhow::anyhow!("Wrong passphrase").into()),
+        }
+    }
+}
+
+impl SealKey {
+    /// Seals a secret, bound to its name so that it can't be opened under another
+    pub fn seal(&self, name: &str, secret: &str) -> Result<String, Error> {
+        seal(&self.rng, &self.key, name, secret.as_bytes())
+    }
+
+    pub fn open(&self, name: &str, sealed: &str) -> Result<String, Error> {
+        let secret = open(&self.key, name, sealed)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt the secret of {name}"))?;
+        Ok(String::from_utf8(secret)
+            .with_context(|| format!("The secret of {name} isn't UTF-8"))?)
+    }
+}
+
+fn seal(rng: &SystemRandom, key: &LessSafeKey, name: &str, secret: &[u8]) -> Result<String, Error> {
+    let mut nonce = [0; NONCE_LEN];
+    rng.fill(&mut nonce)
+        .map_err(|_| anyhow::anyhow!("Failed to generate a nonce"))?;
+
+    let mut sealed = secret.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(name.as_bytes()),
+        &mut sealed,
+    )
+    .map_err(|_| anyhow::anyhow!("Failed to encrypt a secret"))?;
+
+    Ok(BASE64_STANDARD.encode([&nonce[..], &sealed].concat()))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: NonZeroU32) -> LessSafeKey {
+    let mut key = [0; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    LessSafeKey::new(
+        UnboundKey::new(&CHACHA20_POLY1305, &key).expect("the key has the right length"),
+    )
+}
+
+fn open(key: &LessSafeKey, name: &str, sealed: &str) -> Result<Vec<u8>, Error> {
+    let sealed = decode(sealed)?;
+    if sealed.len() < NONCE_LEN {
+        return Err(anyhow::anyhow!("A secret of the credential file is truncated").into());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| anyhow::anyhow!("A secret of the credential file is truncated"))?;
+
+    let mut secret = ciphertext.to_vec();
+    let len = key
+        .open_in_place(nonce, Aad::from(name.as_bytes()), &mut secret)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt {name} from the credential file"))?
+        .len();
+    secret.truncate(len);
+    Ok(secret)
+}
+
+fn decode(base64: &str) -> Result<Vec<u8>, Error> {
+    Ok(BASE64_STANDARD
+        .decode(base64)
+        .context("The credential file is corrupted")?)
+}
+
+fn locked() -> Error {
+    anyhow::anyhow!("The credential file is locked, its passphrase is needed to use it").into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn temp_file() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("pgpad-credentials-{}", Uuid::new_v4()))
+            .join(FILE_NAME)
+    }
+
+    #[test]
+    fn keeps_secrets_encrypted() {
+        let path = temp_file();
+        let file = CredentialFile::with_iterations(&path, 1_000);
+        assert!(!file.exists());
+        assert!(file.insert("a", "s3cr3t").is_err());
+
+        file.unlock("correct horse").unwrap();
+        file.insert("a", "s3cr3t").unwrap();
+        file.insert("b.ssh", "hunter2").unwrap();
+        assert_eq!(file.get("a").unwrap().as_deref(), Some("s3cr3t"));
+        assert_eq!(file.get("c").unwrap(), None);
+        assert!(!fs::read_to_string(&path).unwrap().contains("s3cr3t"));
+
+        // Another session has to unlock it again, with the same passphrase
+        let file = CredentialFile::with_iterations(&path, 1_000);
+        assert!(file.exists());
+        assert!(file.get("a").is_err());
+        assert!(file
+            .unlock("wrong horse")
+            .unwrap_err()
+            .to_string()
+            .contains("Wrong passphrase"));
+        assert!(!file.is_unlocked());
+
+        // Entries can be removed while it's locked
+        assert!(file.remove("b.ssh").unwrap());
+        assert!(!file.remove("b.ssh").unwrap());
+
+        file.unlock("correct horse").unwrap();
+        assert_eq!(file.get("a").unwrap().as_deref(), Some("s3cr3t"));
+        assert_eq!(file.get("b.ssh").unwrap(), None);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn secrets_cannot_be_swapped_between_entries() {
+        let path = temp_file();
+        let file = CredentialFile::with_iterations(&path, 1_000);
+        file.unlock("passphrase").unwrap();
+        file.insert("a", "for a").unwrap();
+
+        let mut contents = file.read().unwrap().unwrap();
+        let sealed = contents.entries["a"].clone();
+        contents.entries.insert("b".to_owned(), sealed);
+        file.write(&contents).unwrap();
+
+        assert!(file.get("b").is_err());
+        assert_eq!(file.get("a").unwrap().as_deref(), Some("for a"));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
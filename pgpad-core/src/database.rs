@@ -1,6 +1,40 @@
+pub mod browse;
+pub mod column_stats;
+pub mod csv_import;
+pub mod definition;
+pub mod delimited;
+pub mod diagnostics;
+pub mod error_hints;
+pub mod execution_marks;
 pub mod export;
+pub mod filtering;
+pub mod formatter;
+pub mod grouping;
+pub mod hover;
+pub mod insert_export;
+pub mod join_conditions;
+pub mod json_export;
+pub mod lineage;
+pub mod mysql;
+pub mod params;
 pub mod postgres;
+pub mod preflight;
+pub mod probe;
+pub mod query_tags;
+pub mod reconnect;
+pub mod relationship_graph;
+pub mod result_cache;
+pub mod result_diff;
+pub mod sanitize;
+pub mod scheduler;
+pub mod schema_cache;
+pub mod semantic_search;
+pub mod sorting;
 pub mod sqlite;
+pub mod table_ddl;
+pub mod trace;
+pub mod transactions;
+pub mod variables;
 
 pub use postgres::tls::Certificates;
 
@@ -11,6 +45,9 @@ pub mod services;
 pub mod stmt_manager;
 pub mod types;
 
-pub use connection_monitor::{ConnectionDropNotifier, ConnectionMonitor};
+pub use connection_monitor::{
+    ConnectionDropNotifier, ConnectionHealth, ConnectionMonitor, HealthStatus, PING_INTERVAL,
+    PING_TIMEOUT,
+};
 
 use crate::database::types::QueryExecEvent;
@@ -0,0 +1,442 @@
+//! Browsing a table page by page, with each page fetched by a query of its own, so that a page never needs more
+//! than its own rows in memory however big the table is.
+//!
+//! Pages are ordered by the table's primary key, or by its first column if it has none, so that consecutive
+//! pages neither skip nor repeat rows.
+
+use anyhow::Context;
+use mysql_async::{prelude::Queryable, Conn};
+use serde::Serialize;
+
+use crate::{
+    database::{
+        mysql,
+        types::{Database, QueryId},
+    },
+    utils::{quote_identifier, quote_mysql_identifier},
+    Error,
+};
+
+/// Most rows a page may have
+pub const MAX_BROWSE_PAGE_SIZE: usize = 10_000;
+
+/// Postgres and MySQL tables estimated to have more rows than this aren't counted exactly, since that means
+/// reading all of them
+const EXACT_COUNT_THRESHOLD: i64 = 1_000_000;
+
+/// Postgres tables without an estimate are only counted up to this size, which is quick to read
+const UNESTIMATED_COUNT_MAX_BYTES: i64 = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BrowsePage {
+    /// The query fetching the page, whose results are read like any other's
+    pub query_id: QueryId,
+    pub query: String,
+    /// The columns the rows are ordered by
+    pub order_by: Vec<String>,
+    /// Rows in the whole table. Only counted for the first page, since counting a big table again for every page
+    /// would take longer than fetching it. None as well for Postgres tables too big to count that the planner has
+    /// no estimate for.
+    pub total_rows: Option<u64>,
+    /// Whether `total_rows` is the planner's estimate rather than an exact count
+    pub total_is_estimate: bool,
+}
+
+/// `"schema"."table"`, or just `"table"` without a schema, as for SQLite
+pub fn qualified_name(schema: &str, table: &str) -> String {
+    match schema {
+        "" => quote_identifier(table),
+        schema => format!("{}.{}", quote_identifier(schema), quote_identifier(table)),
+    }
+}
+
+/// The query fetching a page of a table. Pages are 0-based.
+pub fn page_query(
+    database: Database,
+    schema: &str,
+    table: &str,
+    order_by: &[String],
+    page_index: usize,
+    page_size: usize,
+) -> Result<String, Error> {
+    if page_size == 0 || page_size > MAX_BROWSE_PAGE_SIZE {
+        return Err(anyhow::anyhow!(
+            "Pages have between 1 and {MAX_BROWSE_PAGE_SIZE} rows, not {page_size}"
+        )
+        .into());
+    }
+    let offset = page_index
+        .checked_mul(page_size)
+        .context("The page is past the end of any table")?;
+
+    let (table, quote): (_, fn(&str) -> String) = match database {
+        Database::MySql => (
+            format!(
+                "{}.{}",
+                quote_mysql_identifier(schema),
+                quote_mysql_identifier(table)
+            ),
+            quote_mysql_identifier,
+        ),
+        Database::Postgres | Database::Sqlite => (qualified_name(schema, table), quote_identifier),
+    };
+    let order_by = match order_by {
+        [] => String::new(),
+        columns => format!(
+            " ORDER BY {}",
+            columns
+                .iter()
+                .map(|column| quote(column))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+
+    Ok(format!(
+        "SELECT * FROM {table}{order_by} LIMIT {page_size} OFFSET {offset}"
+    ))
+}
+
+/// The columns of the primary key of a Postgres table, in key order, or else its first column
+pub async fn postgres_order_columns(
+    client: &tokio_postgres::Client,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<String>, Error> {
+    let relation = qualified_name(schema, table);
+
+    let primary_key: Vec<String> = client
+        .query(
+            "SELECT a.attname::text
+             FROM pg_index i
+             JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY (i.indkey)
+             WHERE i.indrelid = $1::text::regclass AND i.indisprimary
+             ORDER BY array_position(i.indkey::int2[], a.attnum)",
+            &[&relation],
+        )
+        .await
+        .with_context(|| format!("Failed to read the primary key of {relation}"))?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    if !primary_key.is_empty() {
+        return Ok(primary_key);
+    }
+
+    let first_column = client
+        .query_opt(
+            "SELECT attname::text FROM pg_attribute
+             WHERE attrelid = $1::text::regclass AND attnum > 0 AND NOT attisdropped
+             ORDER BY attnum
+             LIMIT 1",
+            &[&relation],
+        )
+        .await
+        .with_context(|| format!("Failed to read the columns of {relation}"))?;
+    Ok(first_column.map(|row| row.get(0)).into_iter().collect())
+}
+
+/// Rows in a Postgres table: the planner's estimate for big tables, an exact count otherwise. Returns whether
+/// it's an estimate, or None for tables that have no estimate and are too big to count quickly.
+pub async fn postgres_row_count(
+    client: &tokio_postgres::Client,
+    schema: &str,
+    table: &str,
+) -> Result<Option<(u64, bool)>, Error> {
+    let relation = qualified_name(schema, table);
+
+    let row = client
+        .query_one(
+            "SELECT reltuples::int8, pg_relation_size(oid) FROM pg_class WHERE oid = $1::text::regclass",
+            &[&relation],
+        )
+        .await
+        .with_context(|| format!("Failed to estimate the size of {relation}"))?;
+    // -1 for tables that were never vacuumed or analyzed
+    let (estimate, bytes): (i64, i64) = (row.get(0), row.get(1));
+    if estimate > EXACT_COUNT_THRESHOLD {
+        return Ok(Some((estimate as u64, true)));
+    }
+    if estimate < 0 && bytes > UNESTIMATED_COUNT_MAX_BYTES {
+        return Ok(None);
+    }
+
+    let count = postgres_exact_row_count(client, schema, table).await?;
+    Ok(Some((count, false)))
+}
+
+/// Rows in a Postgres table, counted however many there are
+pub async fn postgres_exact_row_count(
+    client: &tokio_postgres::Client,
+    schema: &str,
+    table: &str,
+) -> Result<u64, Error> {
+    let relation = qualified_name(schema, table);
+    let count: i64 = client
+        .query_one(&format!("SELECT count(*) FROM {relation}"), &[])
+        .await
+        .with_context(|| format!("Failed to count the rows of {relation}"))?
+        .get(0);
+    Ok(count as u64)
+}
+
+/// The database of a SQLite table in `schema`, which is that of an attached database or else the main one
+fn sqlite_database(schema: &str) -> &str {
+    match schema {
+        "" => "main",
+        schema => schema,
+    }
+}
+
+/// The columns of the primary key of a SQLite table, in key order, or else its first column
+pub fn sqlite_order_columns(
+    conn: &rusqlite::Connection,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<String>, Error> {
+    let mut stmt = conn.prepare("SELECT name, pk FROM pragma_table_info(?1, ?2) ORDER BY cid")?;
+    let columns = stmt
+        .query_map([table, sqlite_database(schema)], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut primary_key: Vec<_> = columns.iter().filter(|(_, pk)| *pk > 0).collect();
+    primary_key.sort_by_key(|(_, pk)| *pk);
+    if !primary_key.is_empty() {
+        return Ok(primary_key
+            .into_iter()
+            .map(|(name, _)| name.clone())
+            .collect());
+    }
+
+    Ok(columns.into_iter().take(1).map(|(name, _)| name).collect())
+}
+
+pub fn sqlite_row_count(
+    conn: &rusqlite::Connection,
+    schema: &str,
+    table: &str,
+) -> Result<u64, Error> {
+    let count: i64 = conn
+        .query_row(
+            &format!("SELECT count(*) FROM {}", qualified_name(schema, table)),
+            [],
+            |row| row.get(0),
+        )
+        .with_context(|| format!("Failed to count the rows of {table}"))?;
+    Ok(count as u64)
+}
+
+/// The columns of the primary key of a MySQL table, in key order, or else its first column
+pub async fn mysql_order_columns(
+    conn: &mut Conn,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<String>, Error> {
+    let primary_key: Vec<String> = conn
+        .exec(
+            "SELECT COLUMN_NAME FROM information_schema.KEY_COLUMN_USAGE
+             WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ? AND CONSTRAINT_NAME = 'PRIMARY'
+             ORDER BY ORDINAL_POSITION",
+            (schema, table),
+        )
+        .await
+        .with_context(|| format!("Failed to read the primary key of {schema}.{table}"))?;
+    if !primary_key.is_empty() {
+        return Ok(primary_key);
+    }
+
+    let first_column: Option<String> = conn
+        .exec_first(
+            "SELECT COLUMN_NAME FROM information_schema.COLUMNS
+             WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ?
+             ORDER BY ORDINAL_POSITION
+             LIMIT 1",
+            (schema, table),
+        )
+        .await
+        .with_context(|| format!("Failed to read the columns of {schema}.{table}"))?;
+    Ok(first_column.into_iter().collect())
+}
+
+/// Rows in a MySQL table: InnoDB's estimate for big tables, an exact count otherwise. Returns whether it's an
+/// estimate.
+pub async fn mysql_row_count(
+    conn: &mut Conn,
+    schema: &str,
+    table: &str,
+) -> Result<(u64, bool), Error> {
+    let estimate: Option<Option<u64>> = conn
+        .exec_first(
+            "SELECT TABLE_ROWS FROM information_schema.TABLES
+             WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ?",
+            (schema, table),
+        )
+        .await
+        .with_context(|| format!("Failed to estimate the size of {schema}.{table}"))?;
+    match estimate.flatten() {
+        Some(estimate) if estimate > EXACT_COUNT_THRESHOLD as u64 => Ok((estimate, true)),
+        _ => Ok((mysql::schema::count_rows(conn, schema, table).await?, false)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_page_queries() {
+        let order_by = ["tenant id".to_owned(), "id".to_owned()];
+        assert_eq!(
+            page_query(Database::Postgres, "sales", "Orders", &order_by, 3, 100).unwrap(),
+            r#"SELECT * FROM "sales"."Orders" ORDER BY "tenant id", "id" LIMIT 100 OFFSET 300"#
+        );
+        assert_eq!(
+            page_query(Database::Sqlite, "", r#"odd"name"#, &[], 0, 50).unwrap(),
+            r#"SELECT * FROM "odd""name" LIMIT 50 OFFSET 0"#
+        );
+        assert_eq!(
+            page_query(Database::MySql, "shop", "odd`name", &order_by, 1, 20).unwrap(),
+            "SELECT * FROM `shop`.`odd``name` ORDER BY `tenant id`, `id` LIMIT 20 OFFSET 20"
+        );
+
+        assert!(page_query(Database::Sqlite, "", "t", &[], 0, 0).is_err());
+        assert!(page_query(Database::Sqlite, "", "t", &[], 0, MAX_BROWSE_PAGE_SIZE + 1).is_err());
+        assert!(page_query(Database::Sqlite, "", "t", &[], usize::MAX, 2).is_err());
+    }
+
+    #[test]
+    fn orders_sqlite_tables_by_their_primary_key() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE memberships (note TEXT, user_id INTEGER, team_id INTEGER, PRIMARY KEY (team_id, user_id));
+             CREATE TABLE events (name TEXT, at TEXT);
+             INSERT INTO events VALUES ('a', '1'), ('b', '2'), ('c', '3');",
+        )
+        .unwrap();
+
+        assert_eq!(
+            sqlite_order_columns(&conn, "", "memberships").unwrap(),
+            ["team_id", "user_id"]
+        );
+        assert_eq!(sqlite_order_columns(&conn, "", "events").unwrap(), ["name"]);
+        assert_eq!(sqlite_row_count(&conn, "", "events").unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn orders_postgres_tables_by_their_primary_key() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        client
+            .batch_execute(
+                r#"CREATE SCHEMA "Sales";
+                CREATE TABLE "Sales".memberships (note text, user_id int, team_id int, PRIMARY KEY (team_id, user_id));
+                CREATE TABLE events (name text, at timestamptz);
+                INSERT INTO events SELECT 'e' || i, now() FROM generate_series(1, 120) i;"#,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            postgres_order_columns(&client, "Sales", "memberships")
+                .await
+                .unwrap(),
+            ["team_id", "user_id"]
+        );
+        assert_eq!(
+            postgres_order_columns(&client, "public", "events")
+                .await
+                .unwrap(),
+            ["name"]
+        );
+        // Never analyzed, so counted exactly
+        assert_eq!(
+            postgres_row_count(&client, "public", "events")
+                .await
+                .unwrap(),
+            Some((120, false))
+        );
+
+        // Too big to count without an estimate
+        client
+            .batch_execute(
+                "CREATE TABLE big AS SELECT repeat('x', 1000) AS filler FROM generate_series(1, 40000)",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            postgres_row_count(&client, "public", "big").await.unwrap(),
+            None
+        );
+        client.batch_execute("ANALYZE big").await.unwrap();
+        assert_eq!(
+            postgres_row_count(&client, "public", "big").await.unwrap(),
+            Some((40000, false))
+        );
+
+        let query = page_query(
+            Database::Postgres,
+            "public",
+            "events",
+            &["name".into()],
+            1,
+            50,
+        )
+        .unwrap();
+        assert_eq!(client.query(&query, &[]).await.unwrap().len(), 50);
+    }
+
+    #[tokio::test]
+    async fn orders_mysql_tables_by_their_primary_key() {
+        let Some(db) = mysql::test_db::TestDb::new().await else {
+            return;
+        };
+        let mut conn = db.conn().await;
+        conn.query_drop(
+            "CREATE TABLE memberships (note text, user_id int, team_id int, PRIMARY KEY (team_id, user_id));
+            CREATE TABLE `odd``events` (name varchar(20), attendees int);
+            INSERT INTO `odd``events` VALUES ('b', 2), ('a', 1), ('c', 3);",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            mysql_order_columns(&mut conn, &db.name, "memberships")
+                .await
+                .unwrap(),
+            ["team_id", "user_id"]
+        );
+        assert_eq!(
+            mysql_order_columns(&mut conn, &db.name, "odd`events")
+                .await
+                .unwrap(),
+            ["name"]
+        );
+        assert_eq!(
+            mysql_row_count(&mut conn, &db.name, "odd`events")
+                .await
+                .unwrap(),
+            (3, false)
+        );
+
+        let query = page_query(
+            Database::MySql,
+            &db.name,
+            "odd`events",
+            &["name".into()],
+            1,
+            2,
+        )
+        .unwrap();
+        let rows: Vec<(String, i32)> = conn.query(query).await.unwrap();
+        assert_eq!(rows, [("c".to_owned(), 3)]);
+
+        drop(conn);
+        db.close().await;
+    }
+}
@@ -0,0 +1,609 @@
+//! Statistics of a single column: how many values are null or distinct, the smallest and largest, and the
+//! average of numbers or the shortest and longest of strings.
+//!
+//! They're either computed over the pages of a result the statement manager already holds, page by page, or
+//! left to the server with a single `SELECT` over a whole table, for tables that were never fetched in full.
+
+use std::{cmp::Ordering, collections::HashSet};
+
+use anyhow::Context;
+use mysql_async::prelude::Queryable;
+use serde::Serialize;
+use serde_json::{Number, Value};
+
+use crate::{database::browse::qualified_name, utils::quote_mysql_identifier, Error};
+
+/// Distinct values are only told apart up to this many, so that a column of unique values can't take up as
+/// much memory again as the result
+const MAX_DISTINCT_VALUES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnKind {
+    Numeric,
+    Text,
+    /// Dates, times and timestamps, whose text sorts chronologically
+    Temporal,
+    Boolean,
+    /// Anything else, e.g. JSON or arrays, of which only nulls and distinct values are counted
+    Other,
+}
+
+impl ColumnKind {
+    /// From the name of a type as any of the backends spell it, e.g. `int4`, `character varying` or `DATETIME`
+    pub fn from_type(data_type: &str) -> Self {
+        let ty = data_type.to_ascii_lowercase();
+        let numeric = [
+            "int", "serial", "numeric", "decimal", "real", "float", "double", "money",
+        ];
+
+        // Postgres names array types after their elements, e.g. `_int4`
+        if ty.starts_with('_') || ty.ends_with("[]") || ty == "array" {
+            ColumnKind::Other
+        } else if ty.contains("char") || ty.contains("text") || ty.contains("clob") {
+            ColumnKind::Text
+        } else if ty.starts_with("bool") {
+            ColumnKind::Boolean
+        } else if ty.contains("date") || ty.contains("time") || ty == "year" {
+            ColumnKind::Temporal
+        } else if numeric.iter().any(|name| ty.contains(name))
+            && !ty.contains("interval")
+            && !ty.contains("point")
+        {
+            ColumnKind::Numeric
+        } else {
+            ColumnKind::Other
+        }
+    }
+
+    /// For columns of unknown type, from their first value that isn't null
+    fn from_value(value: &Value) -> Self {
+        match value {
+            Value::Number(_) => ColumnKind::Numeric,
+            Value::String(_) => ColumnKind::Text,
+            Value::Bool(_) => ColumnKind::Boolean,
+            _ => ColumnKind::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnStats {
+    pub column: String,
+    pub column_type: Option<String>,
+    pub kind: ColumnKind,
+    pub row_count: u64,
+    pub null_count: u64,
+    pub distinct_count: u64,
+    /// Whether there were too many distinct values to count, making `distinct_count` a lower bound
+    pub distinct_count_capped: bool,
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    /// Numeric columns only
+    pub avg: Option<f64>,
+    /// Text columns only
+    pub shortest: Option<String>,
+    pub longest: Option<String>,
+    /// Whether some rows weren't counted, since the query is still running or its result was truncated
+    pub partial: bool,
+}
+
+/// Computes the statistics of a column from its values, which can be given a page at a time
+#[derive(Debug)]
+pub struct ColumnStatsBuilder {
+    stats: ColumnStats,
+    /// Until the first value that isn't null, for columns of unknown type
+    kind: Option<ColumnKind>,
+    distinct: HashSet<String>,
+    sum: f64,
+    numbers: u64,
+}
+
+impl ColumnStatsBuilder {
+    pub fn new(column: String, column_type: Option<String>) -> Self {
+        let kind = column_type.as_deref().map(ColumnKind::from_type);
+        Self {
+            stats: ColumnStats {
+                column,
+                column_type,
+                kind: ColumnKind::Other,
+                row_count: 0,
+                null_count: 0,
+                distinct_count: 0,
+                distinct_count_capped: false,
+                min: None,
+                max: None,
+                avg: None,
+                shortest: None,
+                longest: None,
+                partial: false,
+            },
+            kind,
+            distinct: HashSet::new(),
+            sum: 0.0,
+            numbers: 0,
+        }
+    }
+
+    /// Adds the column's values from a page of rows, as the statement manager keeps them
+    pub fn add_page(&mut self, page: &str, column_index: usize) -> Result<(), Error> {
+        let rows: Vec<Vec<Value>> =
+            serde_json::from_str(page).context("Failed to read a page of results")?;
+        for row in rows {
+            self.add(row.get(column_index).unwrap_or(&Value::Null));
+        }
+        Ok(())
+    }
+
+    pub fn add(&mut self, value: &Value) {
+        self.stats.row_count += 1;
+        if value.is_null() {
+            self.stats.null_count += 1;
+            return;
+        }
+
+        if self.distinct.len() < MAX_DISTINCT_VALUES {
+            self.distinct.insert(value.to_string());
+        } else if !self.distinct.contains(&value.to_string()) {
+            self.stats.distinct_count_capped = true;
+        }
+
+        let kind = *self
+            .kind
+            .get_or_insert_with(|| ColumnKind::from_value(value));
+        match kind {
+            ColumnKind::Numeric => {
+                let Some(number) = as_f64(value) else {
+                    return;
+                };
+                self.sum += number;
+                self.numbers += 1;
+                self.update_bounds(value, |a, b| as_f64(a).partial_cmp(&as_f64(b)));
+            }
+            ColumnKind::Text | ColumnKind::Temporal | ColumnKind::Boolean => {
+                self.update_bounds(value, compare);
+                if let (ColumnKind::Text, Value::String(text)) = (kind, value) {
+                    let len = text.chars().count();
+                    let shortest = &mut self.stats.shortest;
+                    if shortest.as_ref().is_none_or(|s| len < s.chars().count()) {
+                        *shortest = Some(text.clone());
+                    }
+                    let longest = &mut self.stats.longest;
+                    if longest.as_ref().is_none_or(|l| len > l.chars().count()) {
+                        *longest = Some(text.clone());
+                    }
+                }
+            }
+            ColumnKind::Other => {}
+        }
+    }
+
+    /// Values that can't be compared to the current bounds, e.g. a number in a column of strings, are left out
+    fn update_bounds(&mut self, value: &Value, cmp: impl Fn(&Value, &Value) -> Option<Ordering>) {
+        match &self.stats.min {
+            Some(min) => {
+                if cmp(value, min) == Some(Ordering::Less) {
+                    self.stats.min = Some(value.clone());
+                }
+            }
+            None => self.stats.min = Some(value.clone()),
+        }
+        match &self.stats.max {
+            Some(max) => {
+                if cmp(value, max) == Some(Ordering::Greater) {
+                    self.stats.max = Some(value.clone());
+                }
+            }
+            None => self.stats.max = Some(value.clone()),
+        }
+    }
+
+    pub fn finish(mut self) -> ColumnStats {
+        self.stats.kind = self.kind.unwrap_or(ColumnKind::Other);
+        self.stats.distinct_count = self.distinct.len() as u64;
+        if self.numbers > 0 {
+            self.stats.avg = Some(self.sum / self.numbers as f64);
+        }
+        self.stats
+    }
+}
+
+/// Numbers, and numbers as text, which is how arbitrary precision numerics are kept
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Number(_), Value::Number(_)) => as_f64(a).partial_cmp(&as_f64(b)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerDialect {
+    Postgres,
+    Sqlite,
+    Mysql,
+}
+
+/// What the query of [`table_stats_query`] returns: the row count, the non-null count, the distinct count, then
+/// the minimum, maximum, average, shortest and longest values as text
+type TableStatsRow = (
+    i64,
+    i64,
+    i64,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// A single `SELECT` computing the statistics of a column over a whole table. The values it can't have for the
+/// column's kind are NULL.
+pub fn table_stats_query(
+    dialect: ServerDialect,
+    schema: &str,
+    table: &str,
+    column: &str,
+    kind: ColumnKind,
+) -> String {
+    let (table, column) = match dialect {
+        ServerDialect::Mysql => {
+            let table = match schema {
+                "" => quote_mysql_identifier(table),
+                schema => format!(
+                    "{}.{}",
+                    quote_mysql_identifier(schema),
+                    quote_mysql_identifier(table)
+                ),
+            };
+            (table, quote_mysql_identifier(column))
+        }
+        ServerDialect::Postgres | ServerDialect::Sqlite => {
+            (qualified_name(schema, table), qualified_name("", column))
+        }
+    };
+    let text = |expr: &str| match dialect {
+        ServerDialect::Mysql => format!("CAST({expr} AS CHAR)"),
+        ServerDialect::Postgres | ServerDialect::Sqlite => format!("CAST({expr} AS TEXT)"),
+    };
+    let by_length = |direction: &str| {
+        let length = match dialect {
+            ServerDialect::Mysql => "CHAR_LENGTH",
+            ServerDialect::Postgres | ServerDialect::Sqlite => "LENGTH",
+        };
+        text(&format!(
+            "(SELECT {column} FROM {table} WHERE {column} IS NOT NULL \
+             ORDER BY {length}({column}) {direction} LIMIT 1)"
+        ))
+    };
+
+    let distinct = match kind {
+        // Not every type can be compared, e.g. Postgres' json
+        ColumnKind::Other => format!("COUNT(DISTINCT {})", text(&column)),
+        _ => format!("COUNT(DISTINCT {column})"),
+    };
+    let (min, max) = match kind {
+        ColumnKind::Numeric | ColumnKind::Text | ColumnKind::Temporal => (
+            text(&format!("MIN({column})")),
+            text(&format!("MAX({column})")),
+        ),
+        ColumnKind::Boolean | ColumnKind::Other => ("NULL".to_owned(), "NULL".to_owned()),
+    };
+    let avg = match kind {
+        ColumnKind::Numeric => text(&format!("AVG({column})")),
+        _ => "NULL".to_owned(),
+    };
+    let (shortest, longest) = match kind {
+        ColumnKind::Text => (by_length("ASC"), by_length("DESC")),
+        _ => ("NULL".to_owned(), "NULL".to_owned()),
+    };
+
+    format!(
+        "SELECT COUNT(*), COUNT({column}), {distinct}, {min}, {max}, {avg}, {shortest}, {longest} \
+         FROM {table}"
+    )
+}
+
+/// The statistics from the row of [`table_stats_query`]
+fn table_stats(
+    column: &str,
+    column_type: &str,
+    kind: ColumnKind,
+    row: TableStatsRow,
+) -> ColumnStats {
+    let (rows, non_null, distinct, min, max, avg, shortest, longest) = row;
+    let bound = |text: String| match kind {
+        ColumnKind::Numeric => text
+            .parse::<i64>()
+            .map(Value::from)
+            .ok()
+            .or_else(|| {
+                text.parse::<f64>()
+                    .ok()
+                    .and_then(Number::from_f64)
+                    .map(Value::Number)
+            })
+            .unwrap_or(Value::String(text)),
+        _ => Value::String(text),
+    };
+
+    ColumnStats {
+        column: column.to_owned(),
+        column_type: Some(column_type.to_owned()),
+        kind,
+        row_count: rows as u64,
+        null_count: (rows - non_null) as u64,
+        distinct_count: distinct as u64,
+        distinct_count_capped: false,
+        min: min.map(bound),
+        max: max.map(bound),
+        avg: avg.and_then(|avg| avg.parse().ok()),
+        shortest,
+        longest,
+        partial: false,
+    }
+}
+
+pub async fn postgres_table_stats(
+    client: &tokio_postgres::Client,
+    schema: &str,
+    table: &str,
+    column: &str,
+    column_type: &str,
+) -> Result<ColumnStats, Error> {
+    let kind = ColumnKind::from_type(column_type);
+    let query = table_stats_query(ServerDialect::Postgres, schema, table, column, kind);
+    let row = client
+        .query_one(&query, &[])
+        .await
+        .with_context(|| format!("Failed to compute the statistics of {column}"))?;
+
+    let row = (
+        row.get(0),
+        row.get(1),
+        row.get(2),
+        row.get(3),
+        row.get(4),
+        row.get(5),
+        row.get(6),
+        row.get(7),
+    );
+    Ok(table_stats(column, column_type, kind, row))
+}
+
+pub fn sqlite_table_stats(
+    conn: &rusqlite::Connection,
+    schema: &str,
+    table: &str,
+    column: &str,
+    column_type: &str,
+) -> Result<ColumnStats, Error> {
+    let kind = ColumnKind::from_type(column_type);
+    let query = table_stats_query(ServerDialect::Sqlite, schema, table, column, kind);
+    let row = conn
+        .query_row(&query, [], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get(6)?,
+                row.get(7)?,
+            ))
+        })
+        .with_context(|| format!("Failed to compute the statistics of {column}"))?;
+    Ok(table_stats(column, column_type, kind, row))
+}
+
+pub async fn mysql_table_stats(
+    conn: &mut mysql_async::Conn,
+    schema: &str,
+    table: &str,
+    column: &str,
+    column_type: &str,
+) -> Result<ColumnStats, Error> {
+    let kind = ColumnKind::from_type(column_type);
+    let query = table_stats_query(ServerDialect::Mysql, schema, table, column, kind);
+    let row: TableStatsRow = conn
+        .query_first(query)
+        .await
+        .with_context(|| format!("Failed to compute the statistics of {column}"))?
+        .context("The statistics query returned no row")?;
+    Ok(table_stats(column, column_type, kind, row))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn stats_of(column_type: Option<&str>, values: &[Value]) -> ColumnStats {
+        let mut builder = ColumnStatsBuilder::new("c".to_owned(), column_type.map(Into::into));
+        for value in values {
+            builder.add(value);
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn classifies_types() {
+        let kinds = [
+            ("int4", ColumnKind::Numeric),
+            ("double precision", ColumnKind::Numeric),
+            ("DECIMAL(10,2)", ColumnKind::Numeric),
+            ("character varying", ColumnKind::Text),
+            ("TINYTEXT", ColumnKind::Text),
+            ("timestamp with time zone", ColumnKind::Temporal),
+            ("DATETIME", ColumnKind::Temporal),
+            ("bool", ColumnKind::Boolean),
+            ("_int4", ColumnKind::Other),
+            ("interval", ColumnKind::Other),
+            ("point", ColumnKind::Other),
+            ("jsonb", ColumnKind::Other),
+        ];
+        for (data_type, kind) in kinds {
+            assert_eq!(ColumnKind::from_type(data_type), kind, "{data_type}");
+        }
+    }
+
+    #[test]
+    fn computes_numeric_stats() {
+        // Numerics come as text, to keep their precision
+        let values = [
+            json!(3),
+            Value::Null,
+            json!("1.5"),
+            json!(10),
+            json!(3),
+            Value::Null,
+        ];
+        let stats = stats_of(Some("numeric"), &values);
+        assert_eq!(stats.kind, ColumnKind::Numeric);
+        assert_eq!((stats.row_count, stats.null_count), (6, 2));
+        assert_eq!(stats.distinct_count, 3);
+        assert_eq!(stats.min, Some(json!("1.5")));
+        assert_eq!(stats.max, Some(json!(10)));
+        assert_eq!(stats.avg, Some(4.375));
+        assert_eq!(stats.shortest, None);
+    }
+
+    #[test]
+    fn computes_text_and_temporal_stats() {
+        let values = [json!("pear"), json!("fig"), json!("banana"), json!("kiwi")];
+        let stats = stats_of(None, &values);
+        assert_eq!(stats.kind, ColumnKind::Text);
+        assert_eq!(stats.min, Some(json!("banana")));
+        assert_eq!(stats.max, Some(json!("pear")));
+        assert_eq!(stats.shortest.as_deref(), Some("fig"));
+        assert_eq!(stats.longest.as_deref(), Some("banana"));
+        assert_eq!(stats.avg, None);
+
+        let values = [json!("2024-03-01T10:00:00"), json!("2023-12-31T23:59:59")];
+        let stats = stats_of(Some("timestamp"), &values);
+        assert_eq!(stats.kind, ColumnKind::Temporal);
+        assert_eq!(stats.min, Some(json!("2023-12-31T23:59:59")));
+        assert_eq!(stats.shortest, None);
+
+        let stats = stats_of(Some("text"), &[Value::Null, Value::Null]);
+        assert_eq!((stats.null_count, stats.distinct_count), (2, 0));
+        assert_eq!(stats.min, None);
+    }
+
+    #[test]
+    fn reads_pages() {
+        let mut builder = ColumnStatsBuilder::new("b".to_owned(), None);
+        builder.add_page(r#"[[1, true], [2, false]]"#, 1).unwrap();
+        builder
+            .add_page(r#"[[3, null], [4, {"a": 1}]]"#, 1)
+            .unwrap();
+        let stats = builder.finish();
+        assert_eq!(stats.kind, ColumnKind::Boolean);
+        assert_eq!((stats.row_count, stats.null_count), (4, 1));
+        assert_eq!(stats.distinct_count, 3);
+        assert_eq!(
+            (stats.min, stats.max),
+            (Some(json!(false)), Some(json!(true)))
+        );
+    }
+
+    #[test]
+    fn builds_table_stats_queries() {
+        assert_eq!(
+            table_stats_query(
+                ServerDialect::Postgres,
+                "public",
+                "users",
+                "age",
+                ColumnKind::Numeric
+            ),
+            r#"SELECT COUNT(*), COUNT("age"), COUNT(DISTINCT "age"), CAST(MIN("age") AS TEXT), CAST(MAX("age") AS TEXT), CAST(AVG("age") AS TEXT), NULL, NULL FROM "public"."users""#
+        );
+        assert_eq!(
+            table_stats_query(ServerDialect::Mysql, "shop", "items", "tags", ColumnKind::Other),
+            "SELECT COUNT(*), COUNT(`tags`), COUNT(DISTINCT CAST(`tags` AS CHAR)), NULL, NULL, NULL, NULL, NULL FROM `shop`.`items`"
+        );
+    }
+
+    #[test]
+    fn computes_table_stats_on_sqlite() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE t (name TEXT, score REAL, extra BLOB);
+             INSERT INTO t VALUES ('ann', 1.5, NULL), ('bartholomew', NULL, x'00'), (NULL, 4, NULL), ('ann', 3, NULL);",
+        )
+        .unwrap();
+
+        let stats = sqlite_table_stats(&conn, "main", "t", "name", "TEXT").unwrap();
+        assert_eq!(
+            (stats.row_count, stats.null_count, stats.distinct_count),
+            (4, 1, 2)
+        );
+        assert_eq!(stats.min, Some(json!("ann")));
+        assert_eq!(stats.shortest.as_deref(), Some("ann"));
+        assert_eq!(stats.longest.as_deref(), Some("bartholomew"));
+
+        let stats = sqlite_table_stats(&conn, "main", "t", "score", "REAL").unwrap();
+        assert_eq!(stats.min, Some(json!(1.5)));
+        assert_eq!(stats.max, Some(json!(4.0)));
+        assert!((stats.avg.unwrap() - 8.5 / 3.0).abs() < 1e-9);
+
+        let stats = sqlite_table_stats(&conn, "main", "t", "extra", "BLOB").unwrap();
+        assert_eq!((stats.kind, stats.null_count), (ColumnKind::Other, 3));
+        assert_eq!(stats.max, None);
+    }
+
+    #[tokio::test]
+    async fn computes_table_stats_on_postgres() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        client
+            .batch_execute(
+                "CREATE TABLE t (amount numeric, at timestamptz, doc json, ok bool);
+                 INSERT INTO t VALUES (1.25, '2024-01-02', '{}', true), (NULL, '2023-05-06', '[]', NULL);",
+            )
+            .await
+            .unwrap();
+
+        let stats = postgres_table_stats(&client, "public", "t", "amount", "numeric")
+            .await
+            .unwrap();
+        assert_eq!((stats.row_count, stats.null_count), (2, 1));
+        assert_eq!(stats.max, Some(json!(1.25)));
+        assert_eq!(stats.avg, Some(1.25));
+
+        let stats = postgres_table_stats(&client, "public", "t", "at", "timestamp with time zone")
+            .await
+            .unwrap();
+        assert!(stats
+            .min
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .starts_with("2023-05-06"));
+
+        // json has no equality, and bool no MIN
+        let stats = postgres_table_stats(&client, "public", "t", "doc", "json")
+            .await
+            .unwrap();
+        assert_eq!(stats.distinct_count, 2);
+        let stats = postgres_table_stats(&client, "public", "t", "ok", "boolean")
+            .await
+            .unwrap();
+        assert_eq!((stats.null_count, stats.distinct_count), (1, 1));
+    }
+}
@@ -1,40 +1,181 @@
-use tokio::sync::mpsc;
+//! Tells the app when connections drop, and keeps track of how the pings of connected ones go, so that their health
+//! can be shown.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use uuid::Uuid;
 
+/// How often connected connections are pinged
+pub const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// Pings taking longer than this failed
+pub const PING_TIMEOUT: Duration = Duration::from_secs(10);
+/// Latencies of the last pings kept per connection
+const LATENCY_WINDOW: usize = 10;
+/// Connections whose recent pings took this long on average are slow
+const SLOW_LATENCY_MS: u64 = 500;
+
 pub type DroppedConnectionReceiver = mpsc::UnboundedReceiver<Uuid>;
 type DroppedConnectionSender = mpsc::UnboundedSender<Uuid>;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Healthy,
+    /// Recent pings took over [`SLOW_LATENCY_MS`] on average
+    Slow,
+    /// The last ping failed
+    Failing,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConnectionHealth {
+    pub connection_id: Uuid,
+    pub status: HealthStatus,
+    /// Unix timestamp in milliseconds
+    pub last_ping_at: i64,
+    /// None if the last ping failed
+    pub last_latency_ms: Option<u64>,
+    pub consecutive_failures: u32,
+    /// Of the last successful pings, oldest first
+    pub recent_latencies_ms: VecDeque<u64>,
+    pub last_error: Option<String>,
+}
+
+impl ConnectionHealth {
+    fn new(connection_id: Uuid) -> Self {
+        Self {
+            connection_id,
+            status: HealthStatus::Healthy,
+            last_ping_at: 0,
+            last_latency_ms: None,
+            consecutive_failures: 0,
+            recent_latencies_ms: VecDeque::with_capacity(LATENCY_WINDOW),
+            last_error: None,
+        }
+    }
+
+    fn record(&mut self, ping: Result<Duration, String>) {
+        self.last_ping_at = chrono::Utc::now().timestamp_millis();
+        match ping {
+            Ok(latency) => {
+                let latency_ms = latency.as_millis() as u64;
+                if self.recent_latencies_ms.len() == LATENCY_WINDOW {
+                    self.recent_latencies_ms.pop_front();
+                }
+                self.recent_latencies_ms.push_back(latency_ms);
+                self.last_latency_ms = Some(latency_ms);
+                self.consecutive_failures = 0;
+                self.last_error = None;
+            }
+            Err(error) => {
+                self.last_latency_ms = None;
+                self.consecutive_failures += 1;
+                self.last_error = Some(error);
+            }
+        }
+
+        let average_ms = self.recent_latencies_ms.iter().sum::<u64>()
+            / self.recent_latencies_ms.len().max(1) as u64;
+        self.status = if self.consecutive_failures > 0 {
+            HealthStatus::Failing
+        } else if average_ms > SLOW_LATENCY_MS {
+            HealthStatus::Slow
+        } else {
+            HealthStatus::Healthy
+        };
+    }
+}
+
+/// The health of every pinged connection, shared by the monitor and its notifiers
+#[derive(Default)]
+struct HealthTracker {
+    connections: DashMap<Uuid, ConnectionHealth>,
+    /// Told whenever the status of a connection changes
+    sender: Mutex<Option<UnboundedSender<ConnectionHealth>>>,
+}
+
 #[derive(Clone)]
 pub struct ConnectionDropNotifier {
     connection_id: Uuid,
     sender: DroppedConnectionSender,
+    health: Arc<HealthTracker>,
 }
 
 impl ConnectionDropNotifier {
     pub fn notify(&self) {
+        // The health of the next connection starts over
+        self.health.connections.remove(&self.connection_id);
         if let Err(e) = self.sender.send(self.connection_id) {
             log::error!("Failed to publish dropped connection event: {e}");
         }
     }
+
+    /// Records how a ping went: how long it took, or why it failed
+    pub fn record_ping(&self, ping: Result<Duration, String>) {
+        let changed = {
+            let mut health = self
+                .health
+                .connections
+                .entry(self.connection_id)
+                .or_insert_with(|| ConnectionHealth::new(self.connection_id));
+            let previous = (health.last_ping_at != 0).then_some(health.status);
+            health.record(ping);
+            (previous != Some(health.status)).then(|| health.clone())
+        };
+
+        if let Some(health) = changed {
+            if let Some(sender) = self.health.sender.lock().unwrap().as_ref() {
+                let _ = sender.send(health);
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct ConnectionMonitor {
     sender: DroppedConnectionSender,
+    health: Arc<HealthTracker>,
 }
 
 impl ConnectionMonitor {
     pub fn new() -> (Self, DroppedConnectionReceiver) {
         let (sender, receiver) = mpsc::unbounded_channel();
-        (Self { sender }, receiver)
+        let monitor = Self {
+            sender,
+            health: Arc::default(),
+        };
+        (monitor, receiver)
     }
 
     pub fn notifier(&self, connection_id: Uuid) -> ConnectionDropNotifier {
         ConnectionDropNotifier {
             connection_id,
             sender: self.sender.clone(),
+            health: self.health.clone(),
         }
     }
+
+    /// How the pings of a connection went, None if it wasn't pinged since it connected
+    pub fn health(&self, connection_id: Uuid) -> Option<ConnectionHealth> {
+        self.health
+            .connections
+            .get(&connection_id)
+            .map(|health| health.clone())
+    }
+
+    /// Receives the health of connections whenever their status changes, including after their first ping
+    pub fn subscribe(&self) -> UnboundedReceiver<ConnectionHealth> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.health.sender.lock().unwrap() = Some(sender);
+        receiver
+    }
 }
 
 #[cfg(test)]
@@ -66,4 +207,44 @@ mod tests {
             "connection should only be reported once"
         );
     }
+
+    #[test]
+    fn tracks_ping_health() {
+        let (monitor, _dropped_connections) = ConnectionMonitor::new();
+        let mut health_changes = monitor.subscribe();
+        let connection_id = Uuid::new_v4();
+        let notifier = monitor.notifier(connection_id);
+        assert!(monitor.health(connection_id).is_none());
+
+        notifier.record_ping(Ok(Duration::from_millis(20)));
+        let health = health_changes.try_recv().unwrap();
+        assert_eq!(health.status, HealthStatus::Healthy);
+        assert_eq!(health.last_latency_ms, Some(20));
+
+        // Unchanged statuses aren't sent
+        notifier.record_ping(Ok(Duration::from_millis(40)));
+        assert!(health_changes.try_recv().is_err());
+
+        for _ in 0..2 {
+            notifier.record_ping(Err("timed out".to_owned()));
+        }
+        let health = health_changes.try_recv().unwrap();
+        assert_eq!(health.status, HealthStatus::Failing);
+        assert!(health_changes.try_recv().is_err());
+        let health = monitor.health(connection_id).unwrap();
+        assert_eq!(health.consecutive_failures, 2);
+        assert_eq!(health.last_error.as_deref(), Some("timed out"));
+        assert_eq!(health.recent_latencies_ms, [20, 40]);
+
+        for _ in 0..LATENCY_WINDOW {
+            notifier.record_ping(Ok(Duration::from_secs(1)));
+        }
+        let health = monitor.health(connection_id).unwrap();
+        assert_eq!(health.status, HealthStatus::Slow);
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.recent_latencies_ms.len(), LATENCY_WINDOW);
+
+        notifier.notify();
+        assert!(monitor.health(connection_id).is_none());
+    }
 }
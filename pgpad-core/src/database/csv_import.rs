@@ -0,0 +1,1096 @@
+//! Loading a CSV file into a table, existing or created with column types inferred from the first rows.
+//!
+//! The file is read a record at a time, so that it's never all in memory, and loaded in batches, all in one
+//! transaction: with `COPY FROM STDIN` on Postgres, and multi-row inserts on SQLite. A batch the database rejects
+//! is loaded again row by row, so that only the rows it rejects are left out, each reported with its line.
+
+use std::{collections::VecDeque, io::BufRead, sync::Mutex};
+
+use anyhow::Context;
+use bytes::Bytes;
+use futures_util::SinkExt;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Client;
+
+use crate::{
+    database::{
+        browse::qualified_name,
+        postgres::{execute::DbError, pool::PostgresPool},
+    },
+    Error,
+};
+
+/// Rejected rows kept in a report, the others are only counted
+pub const MAX_REJECTED_ROWS: usize = 1_000;
+
+/// Most parameters a SQLite statement may have
+const SQLITE_MAX_VARIABLES: usize = 32_766;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvImportOptions {
+    /// The table to load into. If empty, it's named after the file.
+    pub table: String,
+    /// Of the table, on Postgres. If None, it's looked up and created through the search path.
+    pub schema: Option<String>,
+    /// Whether to create the table if it doesn't exist yet
+    pub create_table: bool,
+    pub delimiter: char,
+    pub quote_char: char,
+    pub header: bool,
+    /// Unquoted fields equal to it are loaded as NULLs
+    pub null_value: String,
+    /// Rows the types of the columns of a created table are inferred from
+    pub sample_rows: usize,
+    pub batch_size: usize,
+    /// Whether the first rejected row fails the whole import, rather than being left out
+    pub stop_on_error: bool,
+}
+
+impl Default for CsvImportOptions {
+    fn default() -> Self {
+        Self {
+            table: String::new(),
+            schema: None,
+            create_table: true,
+            delimiter: ',',
+            quote_char: '"',
+            header: true,
+            null_value: String::new(),
+            sample_rows: 1_000,
+            batch_size: 1_000,
+            stop_on_error: false,
+        }
+    }
+}
+
+impl CsvImportOptions {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.delimiter != self.quote_char,
+            "The delimiter and the quote character must differ"
+        );
+        for c in [self.delimiter, self.quote_char] {
+            anyhow::ensure!(
+                !matches!(c, '\n' | '\r'),
+                "Line breaks can't be delimiters or quote characters"
+            );
+        }
+        anyhow::ensure!(self.sample_rows > 0, "At least one row must be sampled");
+        anyhow::ensure!(
+            (1..=100_000).contains(&self.batch_size),
+            "Batches must have between 1 and 100000 rows"
+        );
+        Ok(())
+    }
+}
+
+/// Where the file goes
+#[derive(Debug, Clone)]
+pub struct TargetTable {
+    pub schema: Option<String>,
+    pub name: String,
+}
+
+impl TargetTable {
+    fn qualified_name(&self) -> String {
+        qualified_name(self.schema.as_deref().unwrap_or(""), &self.name)
+    }
+}
+
+/// The type of a column of a created table, the narrowest one all of its sampled values fit in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InferredType {
+    Boolean,
+    Integer,
+    Float,
+    Date,
+    Timestamp,
+    TimestampTz,
+    Text,
+}
+
+impl InferredType {
+    fn of(value: &str) -> Self {
+        let value = value.trim();
+        let numeric = !value.is_empty()
+            && value.bytes().any(|b| b.is_ascii_digit())
+            && value
+                .bytes()
+                .all(|b| b.is_ascii_digit() || matches!(b, b'+' | b'-' | b'.' | b'e' | b'E'));
+        // Leading zeros are kept, as in zip codes or phone numbers
+        let digits = value.trim_start_matches(['+', '-']);
+        let leading_zero = digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.");
+
+        if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+            Self::Boolean
+        } else if numeric && !leading_zero && value.parse::<i64>().is_ok() {
+            Self::Integer
+        } else if numeric && !leading_zero && value.parse::<f64>().is_ok_and(f64::is_finite) {
+            Self::Float
+        } else if chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok() {
+            Self::Date
+        } else if chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f").is_ok()
+            || chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+        {
+            Self::Timestamp
+        } else if chrono::DateTime::parse_from_rfc3339(value).is_ok() {
+            Self::TimestampTz
+        } else {
+            Self::Text
+        }
+    }
+
+    /// The narrowest type both fit in
+    fn merge(self, other: Self) -> Self {
+        use InferredType::*;
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Integer, Float) | (Float, Integer) => Float,
+            (Date, Timestamp) | (Timestamp, Date) => Timestamp,
+            _ => Text,
+        }
+    }
+
+    fn accepts(self, value: &str) -> bool {
+        self.merge(Self::of(value)) == self
+    }
+
+    fn postgres_type(self) -> &'static str {
+        match self {
+            Self::Boolean => "boolean",
+            Self::Integer => "bigint",
+            Self::Float => "double precision",
+            Self::Date => "date",
+            Self::Timestamp => "timestamp",
+            Self::TimestampTz => "timestamptz",
+            Self::Text => "text",
+        }
+    }
+
+    fn sqlite_type(self) -> &'static str {
+        match self {
+            Self::Boolean | Self::Integer => "INTEGER",
+            Self::Float => "REAL",
+            Self::Date | Self::Timestamp | Self::TimestampTz | Self::Text => "TEXT",
+        }
+    }
+
+    /// A value as it's bound on SQLite, where booleans are 0 or 1
+    fn sqlite_value(self, value: &str) -> rusqlite::types::Value {
+        use rusqlite::types::Value;
+        let trimmed = value.trim();
+        match self {
+            Self::Boolean => Value::Integer(trimmed.eq_ignore_ascii_case("true") as i64),
+            Self::Integer => trimmed
+                .parse()
+                .map_or_else(|_| Value::Text(value.to_owned()), Value::Integer),
+            Self::Float => trimmed
+                .parse()
+                .map_or_else(|_| Value::Text(value.to_owned()), Value::Real),
+            _ => Value::Text(value.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CsvColumn {
+    pub name: String,
+    /// Set for the columns of a created table
+    #[serde(rename = "type")]
+    pub inferred_type: Option<InferredType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RejectedRow {
+    /// 1-based, of the row's first line
+    pub line: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CsvImportReport {
+    pub table: String,
+    pub created_table: bool,
+    pub columns: Vec<CsvColumn>,
+    pub rows_loaded: u64,
+    pub bytes_read: u64,
+    pub rejected_count: u64,
+    /// The first [`MAX_REJECTED_ROWS`] of them
+    pub rejected_rows: Vec<RejectedRow>,
+}
+
+/// A record, or why it couldn't be read
+type ReadRecord = (u64, Result<Vec<Option<String>>, String>);
+
+/// Reads the records of a CSV file, which may span several lines when quoted fields have line breaks
+pub struct CsvReader<R> {
+    reader: R,
+    delimiter: char,
+    quote: char,
+    null_value: String,
+    /// Lines read so far
+    line: u64,
+    bytes_read: u64,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(reader: R, options: &CsvImportOptions) -> Self {
+        Self {
+            reader,
+            delimiter: options.delimiter,
+            quote: options.quote_char,
+            null_value: options.null_value.clone(),
+            line: 0,
+            bytes_read: 0,
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// The next record along with its first line, skipping blank lines. A broken record doesn't keep the ones
+    /// after it from being read.
+    pub fn next_record(&mut self) -> Result<Option<ReadRecord>, Error> {
+        loop {
+            let first_line = self.line + 1;
+            let mut record = String::new();
+            let mut valid_utf8 = true;
+
+            loop {
+                self.buf.clear();
+                let read = self
+                    .reader
+                    .read_until(b'\n', &mut self.buf)
+                    .context("Failed to read the CSV file")?;
+                if read == 0 {
+                    if record.is_empty() {
+                        return Ok(None);
+                    }
+                    return Ok(Some((
+                        first_line,
+                        Err("The file ends inside a quoted field".to_owned()),
+                    )));
+                }
+                if self.line == 0 && self.buf.starts_with(b"\xEF\xBB\xBF") {
+                    self.buf.drain(..3);
+                }
+                self.line += 1;
+                self.bytes_read += read as u64;
+
+                match std::str::from_utf8(&self.buf) {
+                    Ok(text) => record.push_str(text),
+                    Err(_) => {
+                        valid_utf8 = false;
+                        record.push_str(&String::from_utf8_lossy(&self.buf));
+                    }
+                }
+                // Quotes come in pairs, escaped ones included, so an odd count means a quoted field goes on
+                if record.chars().filter(|c| *c == self.quote).count() % 2 == 0 {
+                    break;
+                }
+            }
+
+            let record = record.trim_end_matches(['\n', '\r']);
+            if record.is_empty() {
+                continue;
+            }
+            if !valid_utf8 {
+                return Ok(Some((
+                    first_line,
+                    Err("The row isn't valid UTF-8".to_owned()),
+                )));
+            }
+            return Ok(Some((first_line, self.parse_fields(record))));
+        }
+    }
+
+    fn parse_fields(&self, record: &str) -> Result<Vec<Option<String>>, String> {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+        let mut closed = false;
+
+        let mut chars = record.chars().peekable();
+        while let Some(c) = chars.next() {
+            if in_quotes {
+                if c != self.quote {
+                    field.push(c);
+                } else if chars.peek() == Some(&self.quote) {
+                    chars.next();
+                    field.push(c);
+                } else {
+                    in_quotes = false;
+                    closed = true;
+                }
+            } else if c == self.delimiter {
+                fields.push(self.finish_field(std::mem::take(&mut field), quoted));
+                quoted = false;
+                closed = false;
+            } else if closed {
+                return Err(format!(
+                    "Unexpected {c:?} after the closing quote of a field"
+                ));
+            } else if c == self.quote && field.is_empty() && !quoted {
+                quoted = true;
+                in_quotes = true;
+            } else {
+                field.push(c);
+            }
+        }
+        fields.push(self.finish_field(field, quoted));
+
+        Ok(fields)
+    }
+
+    fn finish_field(&self, field: String, quoted: bool) -> Option<String> {
+        (quoted || field != self.null_value).then_some(field)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvRow {
+    pub line: u64,
+    pub values: Vec<Option<String>>,
+}
+
+/// The rows of a file in batches, with those that can't be loaded left out and reported
+pub struct CsvBatches<R> {
+    reader: CsvReader<R>,
+    /// Read ahead to infer the types of the columns
+    sampled: VecDeque<ReadRecord>,
+    /// From the header. None without one, in which case the table's columns are loaded in order.
+    header: Option<Vec<String>>,
+    columns: Vec<String>,
+    types: Vec<InferredType>,
+    /// Whether the rows are checked against `types`, for created tables
+    check_types: bool,
+    batch_size: usize,
+    stop_on_error: bool,
+    report: CsvImportReport,
+}
+
+impl<R: BufRead> CsvBatches<R> {
+    pub fn new(reader: R, options: &CsvImportOptions) -> Result<Self, Error> {
+        let mut reader = CsvReader::new(reader, options);
+
+        let header = if options.header {
+            match reader.next_record()? {
+                Some((_, Ok(fields))) => Some(column_names(&fields)),
+                Some((line, Err(message))) => {
+                    return Err(anyhow::anyhow!(
+                        "Failed to read the header on line {line}: {message}"
+                    )
+                    .into())
+                }
+                None => return Err(anyhow::anyhow!("The CSV file is empty").into()),
+            }
+        } else {
+            None
+        };
+
+        let mut sampled = VecDeque::new();
+        while sampled.len() < options.sample_rows {
+            match reader.next_record()? {
+                Some(record) => sampled.push_back(record),
+                None => break,
+            }
+        }
+
+        let columns = match &header {
+            Some(header) => header.clone(),
+            None => {
+                let count = sampled
+                    .iter()
+                    .find_map(|(_, fields)| fields.as_ref().ok().map(Vec::len))
+                    .ok_or_else(|| anyhow::anyhow!("The CSV file has no rows"))?;
+                column_names(&vec![None; count])
+            }
+        };
+        let types = infer_types(
+            columns.len(),
+            sampled
+                .iter()
+                .filter_map(|(_, fields)| fields.as_ref().ok()),
+        );
+
+        Ok(Self {
+            reader,
+            sampled,
+            header,
+            columns,
+            types,
+            check_types: false,
+            batch_size: options.batch_size,
+            stop_on_error: options.stop_on_error,
+            report: CsvImportReport::default(),
+        })
+    }
+
+    /// Checks the rows against the inferred types, for a table created with them
+    fn created_table(&mut self) {
+        self.check_types = true;
+        self.report.created_table = true;
+    }
+
+    /// The next rows to load, None once the file's over
+    pub fn next_batch(&mut self) -> Result<Option<Vec<CsvRow>>, Error> {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        while batch.len() < self.batch_size {
+            let record = match self.sampled.pop_front() {
+                Some(record) => record,
+                None => match self.reader.next_record()? {
+                    Some(record) => record,
+                    None => break,
+                },
+            };
+
+            match self.check(record) {
+                Ok(row) => batch.push(row),
+                Err((line, message)) => self.reject(line, message)?,
+            }
+        }
+        self.report.bytes_read = self.reader.bytes_read();
+
+        Ok((!batch.is_empty()).then_some(batch))
+    }
+
+    /// [`Self::next_batch`] on a blocking thread, so that reading the file doesn't hold up the runtime
+    async fn read_batch(mut self) -> Result<(Self, Option<Vec<CsvRow>>), Error>
+    where
+        R: Send + 'static,
+    {
+        tokio::task::spawn_blocking(move || {
+            let batch = self.next_batch()?;
+            Ok((self, batch))
+        })
+        .await?
+    }
+
+    fn check(&self, (line, fields): ReadRecord) -> Result<CsvRow, (u64, String)> {
+        let values = fields.map_err(|message| (line, message))?;
+        if values.len() != self.columns.len() {
+            return Err((
+                line,
+                format!(
+                    "Expected {} fields, got {}",
+                    self.columns.len(),
+                    values.len()
+                ),
+            ));
+        }
+        if self.check_types {
+            for ((value, column), ty) in values.iter().zip(&self.columns).zip(&self.types) {
+                if let Some(value) = value {
+                    if !ty.accepts(value) {
+                        return Err((
+                            line,
+                            format!(
+                                "{value:?} doesn't fit the {} column {column}",
+                                ty.postgres_type()
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(CsvRow { line, values })
+    }
+
+    /// Leaves a row out, or fails the import if it's to stop at the first rejected row
+    pub fn reject(&mut self, line: u64, message: String) -> Result<(), Error> {
+        if self.stop_on_error {
+            return Err(anyhow::anyhow!("Line {line} was rejected: {message}").into());
+        }
+        self.report.rejected_count += 1;
+        if self.report.rejected_rows.len() < MAX_REJECTED_ROWS {
+            self.report
+                .rejected_rows
+                .push(RejectedRow { line, message });
+        }
+        Ok(())
+    }
+
+    fn loaded(&mut self, rows: usize) {
+        self.report.rows_loaded += rows as u64;
+    }
+
+    pub fn report(&self) -> &CsvImportReport {
+        &self.report
+    }
+
+    fn finish(mut self, table: &TargetTable) -> CsvImportReport {
+        self.report.table = table.qualified_name();
+        self.report.columns = self
+            .columns
+            .iter()
+            .zip(&self.types)
+            .map(|(name, ty)| CsvColumn {
+                name: name.clone(),
+                inferred_type: self.report.created_table.then_some(*ty),
+            })
+            .collect();
+        self.report
+    }
+
+    /// ` ("a", "b")`, the columns rows are loaded into, or nothing to load them into the table's columns in order
+    fn column_list(&self) -> String {
+        match &self.header {
+            Some(header) => format!(
+                " ({})",
+                header
+                    .iter()
+                    .map(|name| qualified_name("", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            None => String::new(),
+        }
+    }
+
+    fn create_table_sql(
+        &self,
+        table: &TargetTable,
+        type_name: fn(InferredType) -> &'static str,
+    ) -> String {
+        let columns = self
+            .columns
+            .iter()
+            .zip(&self.types)
+            .map(|(name, ty)| format!("{} {}", qualified_name("", name), type_name(*ty)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("CREATE TABLE {} ({columns})", table.qualified_name())
+    }
+}
+
+/// Names for the columns, `column1`, ... for missing ones, with duplicates numbered
+fn column_names(header: &[Option<String>]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::with_capacity(header.len());
+    for (i, name) in header.iter().enumerate() {
+        let name = name
+            .as_deref()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map_or_else(|| format!("column{}", i + 1), ToOwned::to_owned);
+
+        let mut unique = name.clone();
+        let mut n = 2;
+        while names.contains(&unique) {
+            unique = format!("{name}_{n}");
+            n += 1;
+        }
+        names.push(unique);
+    }
+    names
+}
+
+/// The types of the columns, Text for those with only NULLs
+fn infer_types<'a>(
+    columns: usize,
+    rows: impl Iterator<Item = &'a Vec<Option<String>>>,
+) -> Vec<InferredType> {
+    let mut types: Vec<Option<InferredType>> = vec![None; columns];
+    for row in rows.filter(|row| row.len() == columns) {
+        for (ty, value) in types.iter_mut().zip(row) {
+            if let Some(value) = value {
+                let of = InferredType::of(value);
+                *ty = Some(ty.map_or(of, |ty| ty.merge(of)));
+            }
+        }
+    }
+    types
+        .into_iter()
+        .map(|ty| ty.unwrap_or(InferredType::Text))
+        .collect()
+}
+
+/// Loads the file into a Postgres table, in a transaction of its own on a reserved client. `progress` is called
+/// after each batch, and stops the import if it fails. The file is read on a blocking thread, a batch at a time.
+pub async fn load_postgres<R: BufRead + Send + 'static>(
+    pool: &PostgresPool,
+    mut batches: CsvBatches<R>,
+    table: &TargetTable,
+    create_table: bool,
+    mut progress: impl FnMut(&CsvImportReport) -> Result<(), Error>,
+) -> Result<CsvImportReport, Error> {
+    let pool = pool.reserve_session().context(
+        "Every client of the connection is busy with a transaction, commit or roll one back to import",
+    )?;
+    let client = pool.session();
+    let qualified = table.qualified_name();
+
+    let exists: bool = client
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&qualified])
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", DbError(&e)))?
+        .get(0);
+    if !exists && !create_table {
+        return Err(anyhow::anyhow!("Table {qualified} doesn't exist").into());
+    }
+
+    execute(client, "BEGIN").await?;
+    let result = async move {
+        if !exists {
+            client
+                .batch_execute(&batches.create_table_sql(table, InferredType::postgres_type))
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to create {qualified}: {}", DbError(&e)))?;
+            batches.created_table();
+        }
+
+        let copy = format!(
+            "COPY {qualified}{} FROM STDIN WITH (FORMAT csv)",
+            batches.column_list()
+        );
+        // Fails right away if the table doesn't have the columns, rather than on every row
+        copy_rows(client, &copy, &[])
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", DbError(&e)))?;
+
+        loop {
+            let batch;
+            (batches, batch) = batches.read_batch().await?;
+            let Some(batch) = batch else {
+                break;
+            };
+
+            execute(client, "SAVEPOINT csv_import_batch").await?;
+            match copy_rows(client, &copy, &batch).await {
+                Ok(_) => batches.loaded(batch.len()),
+                Err(_) => {
+                    execute(client, "ROLLBACK TO SAVEPOINT csv_import_batch").await?;
+                    for row in &batch {
+                        execute(client, "SAVEPOINT csv_import_row").await?;
+                        match copy_rows(client, &copy, std::slice::from_ref(row)).await {
+                            Ok(_) => batches.loaded(1),
+                            Err(e) => {
+                                execute(client, "ROLLBACK TO SAVEPOINT csv_import_row").await?;
+                                batches.reject(row.line, DbError(&e).to_string())?;
+                            }
+                        }
+                        execute(client, "RELEASE SAVEPOINT csv_import_row").await?;
+                    }
+                }
+            }
+            execute(client, "RELEASE SAVEPOINT csv_import_batch").await?;
+            progress(batches.report())?;
+        }
+        Ok::<_, Error>(batches)
+    }
+    .await;
+
+    match result {
+        Ok(batches) => {
+            execute(client, "COMMIT").await?;
+            Ok(batches.finish(table))
+        }
+        Err(e) => {
+            if let Err(e) = client.batch_execute("ROLLBACK").await {
+                log::warn!("Failed to roll back a CSV import: {e}");
+            }
+            Err(e)
+        }
+    }
+}
+
+async fn execute(client: &Client, statement: &str) -> Result<(), Error> {
+    client
+        .batch_execute(statement)
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", DbError(&e)).into())
+}
+
+/// Sends rows through `COPY ... FROM STDIN WITH (FORMAT csv)`, every value quoted so that NULLs, sent as nothing,
+/// are told apart from empty strings
+async fn copy_rows(
+    client: &Client,
+    statement: &str,
+    rows: &[CsvRow],
+) -> Result<u64, tokio_postgres::Error> {
+    let mut data = String::new();
+    for row in rows {
+        for (i, value) in row.values.iter().enumerate() {
+            if i > 0 {
+                data.push(',');
+            }
+            if let Some(value) = value {
+                data.push('"');
+                data.push_str(&value.replace('"', "\"\""));
+                data.push('"');
+            }
+        }
+        data.push('\n');
+    }
+
+    let sink = client.copy_in::<_, Bytes>(statement).await?;
+    futures_util::pin_mut!(sink);
+    if !data.is_empty() {
+        sink.send(Bytes::from(data)).await?;
+    }
+    sink.finish().await
+}
+
+/// Loads the file into a SQLite table, within a savepoint so that it works inside a transaction as well.
+/// `progress` is called after each batch, and stops the import if it fails. Blocks, so it's meant to be run on a
+/// blocking thread.
+pub fn load_sqlite<R: BufRead>(
+    connection: &Mutex<rusqlite::Connection>,
+    mut batches: CsvBatches<R>,
+    table: &TargetTable,
+    create_table: bool,
+    mut progress: impl FnMut(&CsvImportReport) -> Result<(), Error>,
+) -> Result<CsvImportReport, Error> {
+    let conn = connection.lock().unwrap();
+    let qualified = table.qualified_name();
+
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type IN ('table', 'view') AND name = ?1)",
+        [&table.name],
+        |row| row.get(0),
+    )?;
+    if !exists && !create_table {
+        return Err(anyhow::anyhow!("Table {qualified} doesn't exist").into());
+    }
+
+    conn.execute_batch("SAVEPOINT csv_import")?;
+    let result = (|| {
+        if !exists {
+            conn.execute_batch(&batches.create_table_sql(table, InferredType::sqlite_type))
+                .with_context(|| format!("Failed to create {qualified}"))?;
+            batches.created_table();
+        }
+
+        let column_count = batches.columns.len();
+        let column_list = batches.column_list();
+        let insert = |rows: usize| {
+            let row = format!("({})", vec!["?"; column_count].join(", "));
+            format!(
+                "INSERT INTO {qualified}{column_list} VALUES {}",
+                vec![row; rows].join(", ")
+            )
+        };
+        // Fails right away if the table doesn't have the columns, rather than on every row
+        conn.prepare_cached(&insert(1))?;
+
+        let typed = batches.check_types;
+        let types = batches.types.clone();
+        let values = |row: &CsvRow| -> Vec<rusqlite::types::Value> {
+            row.values
+                .iter()
+                .zip(&types)
+                .map(|(value, ty)| match value {
+                    None => rusqlite::types::Value::Null,
+                    Some(value) if typed => ty.sqlite_value(value),
+                    Some(value) => rusqlite::types::Value::Text(value.clone()),
+                })
+                .collect()
+        };
+
+        let max_rows = (SQLITE_MAX_VARIABLES / column_count.max(1)).max(1);
+        while let Some(batch) = batches.next_batch()? {
+            for chunk in batch.chunks(max_rows) {
+                conn.execute_batch("SAVEPOINT csv_import_batch")?;
+                let inserted =
+                    conn.prepare_cached(&insert(chunk.len()))
+                        .and_then(|mut statement| {
+                            statement
+                                .execute(rusqlite::params_from_iter(chunk.iter().flat_map(values)))
+                        });
+                match inserted {
+                    Ok(_) => batches.loaded(chunk.len()),
+                    Err(_) => {
+                        conn.execute_batch("ROLLBACK TO csv_import_batch")?;
+                        let mut statement = conn.prepare_cached(&insert(1))?;
+                        for row in chunk {
+                            match statement.execute(rusqlite::params_from_iter(values(row))) {
+                                Ok(_) => batches.loaded(1),
+                                Err(e) => batches.reject(row.line, e.to_string())?,
+                            }
+                        }
+                    }
+                }
+                conn.execute_batch("RELEASE csv_import_batch")?;
+            }
+            progress(batches.report())?;
+        }
+        Ok::<_, Error>(())
+    })();
+
+    match result {
+        Ok(()) => {
+            conn.execute_batch("RELEASE csv_import")?;
+            Ok(batches.finish(table))
+        }
+        Err(e) => {
+            if let Err(e) = conn.execute_batch("ROLLBACK TO csv_import; RELEASE csv_import") {
+                log::warn!("Failed to roll back a CSV import: {e}");
+            }
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn read_all(csv: &str, options: &CsvImportOptions) -> Vec<ReadRecord> {
+        let mut reader = CsvReader::new(Cursor::new(csv.as_bytes()), options);
+        std::iter::from_fn(|| reader.next_record().unwrap()).collect()
+    }
+
+    fn fields(values: &[Option<&str>]) -> Vec<Option<String>> {
+        values.iter().map(|v| v.map(ToOwned::to_owned)).collect()
+    }
+
+    #[test]
+    fn reads_quoted_multiline_records() {
+        let options = CsvImportOptions {
+            null_value: "NULL".to_owned(),
+            ..Default::default()
+        };
+        let records = read_all(
+            "\u{feff}id,note\r\n1,\"say \"\"hi\"\"\"\r\n\n2,\"two\nlines\"\n3,NULL\n4,\"NULL\"\n5,\"x\"y\n6,\"open",
+            &options,
+        );
+
+        assert_eq!(records[0], (1, Ok(fields(&[Some("id"), Some("note")]))));
+        assert_eq!(
+            records[1],
+            (2, Ok(fields(&[Some("1"), Some("say \"hi\"")])))
+        );
+        assert_eq!(
+            records[2],
+            (4, Ok(fields(&[Some("2"), Some("two\nlines")])))
+        );
+        assert_eq!(records[3], (6, Ok(fields(&[Some("3"), None]))));
+        assert_eq!(records[4], (7, Ok(fields(&[Some("4"), Some("NULL")]))));
+        assert!(records[5].1.is_err());
+        assert_eq!(
+            records[6],
+            (9, Err("The file ends inside a quoted field".to_owned()))
+        );
+        assert_eq!(records.len(), 7);
+    }
+
+    #[test]
+    fn infers_column_types() {
+        let csv = "id,price,zip,active,day,at,name,,name\n\
+                   1,9.5,02134,true,2024-01-31,2024-01-31 10:00:00,a,,x\n\
+                   2,10,10001,FALSE,2024-02-01,2024-02-01,b,,y\n";
+        let batches = CsvBatches::new(Cursor::new(csv), &CsvImportOptions::default()).unwrap();
+        assert_eq!(
+            batches.columns,
+            ["id", "price", "zip", "active", "day", "at", "name", "column8", "name_2"]
+        );
+        assert_eq!(
+            batches.types,
+            [
+                InferredType::Integer,
+                InferredType::Float,
+                InferredType::Text,
+                InferredType::Boolean,
+                InferredType::Date,
+                InferredType::Timestamp,
+                InferredType::Text,
+                InferredType::Text,
+                InferredType::Text,
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_into_sqlite_reporting_rejected_rows() {
+        let connection = Mutex::new(rusqlite::Connection::open_in_memory().unwrap());
+        let table = TargetTable {
+            schema: None,
+            name: "people".to_owned(),
+        };
+        let options = CsvImportOptions {
+            sample_rows: 2,
+            batch_size: 2,
+            ..Default::default()
+        };
+        let csv = "id,name,age\n1,ada,36\n2,alan,41\n3,grace,old\n4,linus\n5,\"\",\n";
+
+        let mut reports = 0;
+        let report = load_sqlite(
+            &connection,
+            CsvBatches::new(Cursor::new(csv), &options).unwrap(),
+            &table,
+            true,
+            |_| {
+                reports += 1;
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert!(report.created_table);
+        assert_eq!(report.rows_loaded, 3);
+        assert_eq!(report.bytes_read, csv.len() as u64);
+        assert_eq!(reports, 2);
+        assert_eq!(
+            report
+                .rejected_rows
+                .iter()
+                .map(|row| row.line)
+                .collect::<Vec<_>>(),
+            [4, 5]
+        );
+        assert_eq!(report.columns[2].inferred_type, Some(InferredType::Integer));
+
+        let conn = connection.lock().unwrap();
+        let rows: Vec<(i64, String, Option<i64>)> = conn
+            .prepare("SELECT id, name, age FROM people ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                (1, "ada".to_owned(), Some(36)),
+                (2, "alan".to_owned(), Some(41)),
+                (5, String::new(), None)
+            ]
+        );
+        drop(conn);
+
+        // Into the existing table, stopping at the first rejected row without loading anything
+        let options = CsvImportOptions {
+            stop_on_error: true,
+            create_table: false,
+            ..Default::default()
+        };
+        let csv = "id,name\n6,edsger\n7\n";
+        let error = load_sqlite(
+            &connection,
+            CsvBatches::new(Cursor::new(csv), &options).unwrap(),
+            &table,
+            false,
+            |_| Ok(()),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("Line 3"), "{error}");
+        let count: i64 = connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT count(*) FROM people", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[tokio::test]
+    async fn copies_into_postgres_reporting_rejected_rows() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let (client, connection) =
+            tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+        let (metadata, connection) =
+            tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+        let pool = PostgresPool::new(client, vec![metadata]).await.unwrap();
+
+        pool.session()
+            .batch_execute("CREATE TABLE people (id int PRIMARY KEY, name text NOT NULL)")
+            .await
+            .unwrap();
+        let table = TargetTable {
+            schema: Some("public".to_owned()),
+            name: "people".to_owned(),
+        };
+        let options = CsvImportOptions {
+            batch_size: 2,
+            create_table: false,
+            ..Default::default()
+        };
+        let csv = "id,name\n1,ada\n2,\n3,grace\n1,again\n";
+        let report = load_postgres(
+            &pool,
+            CsvBatches::new(Cursor::new(csv), &options).unwrap(),
+            &table,
+            false,
+            |_| Ok(()),
+        )
+        .await
+        .unwrap();
+        assert!(!report.created_table);
+        assert_eq!(report.rows_loaded, 2);
+        assert_eq!(
+            report
+                .rejected_rows
+                .iter()
+                .map(|row| row.line)
+                .collect::<Vec<_>>(),
+            [3, 5]
+        );
+        assert!(report.rejected_rows[1].message.contains("duplicate key"));
+
+        // Created from the file, with inferred types
+        let table = TargetTable {
+            schema: None,
+            name: "measurements".to_owned(),
+        };
+        let report = load_postgres(
+            &pool,
+            CsvBatches::new(
+                Cursor::new("at,value\n2024-01-31 10:00:00,1.5\n2024-02-01 11:30:00,\n"),
+                &CsvImportOptions::default(),
+            )
+            .unwrap(),
+            &table,
+            true,
+            |_| Ok(()),
+        )
+        .await
+        .unwrap();
+        assert!(report.created_table);
+        assert_eq!(report.rows_loaded, 2);
+        let row = pool
+            .session()
+            .query_one(
+                "SELECT pg_typeof(at)::text, pg_typeof(value)::text, count(value) FROM measurements GROUP BY 1, 2",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(row.get::<_, String>(0), "timestamp without time zone");
+        assert_eq!(row.get::<_, String>(1), "double precision");
+        assert_eq!(row.get::<_, i64>(2), 1);
+
+        // Cancelling rolls everything back
+        let table = TargetTable {
+            schema: None,
+            name: "cancelled".to_owned(),
+        };
+        assert!(load_postgres(
+            &pool,
+            CsvBatches::new(Cursor::new("a\n1\n"), &CsvImportOptions::default()).unwrap(),
+            &table,
+            true,
+            |_| Err(anyhow::anyhow!("Operation cancelled").into()),
+        )
+        .await
+        .is_err());
+        let exists: bool = pool
+            .session()
+            .query_one("SELECT to_regclass('cancelled') IS NOT NULL", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert!(!exists);
+    }
+}
@@ -0,0 +1,455 @@
+//! Go-to-definition in the editor. CTEs are found in the statement they're used in, and views and routines in
+//! the database's catalog, whose definitions are fetched so that they can be opened in a tab.
+
+use std::ops::Range;
+
+use anyhow::Context;
+use mysql_async::prelude::Queryable;
+use serde::Serialize;
+use sqlparser::{
+    dialect::Dialect,
+    keywords::Keyword,
+    tokenizer::{Token, Word},
+};
+
+use crate::{
+    database::{
+        browse::qualified_name,
+        hover::{self, StatementTokens},
+        types::Database,
+    },
+    utils::quote_mysql_identifier,
+    Error,
+};
+
+/// What the name under the cursor refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DefinitionTarget {
+    /// A CTE of the statement, by the bytes of its name where it's defined
+    Local { bytes: Range<usize> },
+    /// An object of the database, by its name as the catalog has it
+    Object {
+        schema: Option<String>,
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectType {
+    View,
+    MaterializedView,
+    Function,
+    Procedure,
+    Table,
+    Trigger,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ObjectDefinition {
+    pub schema: String,
+    pub name: String,
+    pub object_type: ObjectType,
+    /// The statement creating the object, or every overload of a routine
+    pub definition: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Definition {
+    /// Where a CTE is defined in the document, in UTF-16 code units like editor positions
+    Local {
+        from: usize,
+        to: usize,
+    },
+    Object(ObjectDefinition),
+}
+
+/// A name as the catalog of `database` has it: Postgres folds unquoted names to lowercase, while SQLite and MySQL
+/// compare them without regard to case
+fn catalog_name(word: &Word, database: Database) -> String {
+    match (word.quote_style, database) {
+        (None, Database::Postgres) => word.value.to_lowercase(),
+        _ => word.value.clone(),
+    }
+}
+
+fn same_identifier(a: &Word, b: &Word, database: Database) -> bool {
+    match database {
+        Database::Postgres => catalog_name(a, database) == catalog_name(b, database),
+        Database::Sqlite | Database::MySql => a.value.eq_ignore_ascii_case(&b.value),
+    }
+}
+
+fn is_keyword(tokens: &[(Token, Range<usize>)], idx: usize, keyword: Keyword) -> bool {
+    hover::word(tokens, idx).is_some_and(|w| w.quote_style.is_none() && w.keyword == keyword)
+}
+
+/// The token indices of the names of the statement's CTEs, from every `WITH` of it
+pub(crate) fn cte_names(tokens: &[(Token, Range<usize>)]) -> Vec<usize> {
+    let mut names = vec![];
+
+    for start in (0..tokens.len()).filter(|&idx| is_keyword(tokens, idx, Keyword::WITH)) {
+        let mut idx = start + 1;
+        if is_keyword(tokens, idx, Keyword::RECURSIVE) {
+            idx += 1;
+        }
+        while hover::word(tokens, idx).is_some() {
+            let name = idx;
+            idx += 1;
+            // The CTE's column list
+            if matches!(tokens.get(idx), Some((Token::LParen, _))) {
+                idx = hover::skip_parens(tokens, idx);
+            }
+            if !is_keyword(tokens, idx, Keyword::AS) {
+                break;
+            }
+            idx += 1;
+            if is_keyword(tokens, idx, Keyword::NOT) {
+                idx += 1;
+            }
+            if is_keyword(tokens, idx, Keyword::MATERIALIZED) {
+                idx += 1;
+            }
+            if !matches!(tokens.get(idx), Some((Token::LParen, _))) {
+                break;
+            }
+            idx = hover::skip_parens(tokens, idx);
+            names.push(name);
+
+            if !matches!(tokens.get(idx), Some((Token::Comma, _))) {
+                break;
+            }
+            idx += 1;
+        }
+    }
+
+    names
+}
+
+fn resolve(
+    tokens: &StatementTokens,
+    hovered: usize,
+    database: Database,
+) -> Option<DefinitionTarget> {
+    let ctes = cte_names(tokens);
+    if ctes.contains(&hovered) {
+        return None;
+    }
+
+    let mut start = hovered;
+    while start >= 2
+        && hover::is_period(tokens, start - 1)
+        && hover::word(tokens, start - 2).is_some()
+    {
+        start -= 2;
+    }
+    let (parts, _) = hover::object_name(tokens, start)?;
+    let position = (hovered - start) / 2;
+
+    // A CTE, or the CTE a column is qualified with. The closest one defined before wins, as CTEs of subqueries
+    // shadow the outer ones.
+    if position == 0 {
+        let name = parts[0];
+        let cte = ctes
+            .iter()
+            .rev()
+            .filter(|&&idx| idx < hovered)
+            .chain(ctes.iter().filter(|&&idx| idx > hovered))
+            .find(|&&idx| {
+                hover::word(tokens, idx).is_some_and(|cte| same_identifier(cte, name, database))
+            });
+        if let Some(&cte) = cte {
+            return Some(DefinitionTarget::Local {
+                bytes: tokens[cte].1.clone(),
+            });
+        }
+    }
+
+    // A name in the catalog, possibly qualified with its schema
+    if position + 1 != parts.len() {
+        return None;
+    }
+    let name = parts[position];
+    if name.quote_style.is_none() && name.keyword != Keyword::NoKeyword && parts.len() == 1 {
+        // Keywords aren't names, unless they're used as function names like `left(...)`
+        if !matches!(tokens.get(hovered + 1), Some((Token::LParen, _))) {
+            return None;
+        }
+    }
+    Some(DefinitionTarget::Object {
+        schema: position
+            .checked_sub(1)
+            .map(|idx| catalog_name(parts[idx], database)),
+        name: catalog_name(name, database),
+    })
+}
+
+/// What the name at `position` of `query` refers to, a position in UTF-16 code units like editor positions
+pub fn definition_at(
+    dialect: &dyn Dialect,
+    database: Database,
+    query: &str,
+    position: usize,
+) -> Option<DefinitionTarget> {
+    let (statement, hovered) = hover::statement_at(dialect, query, position)?;
+    resolve(&statement, hovered, database)
+}
+
+const POSTGRES_VIEW_DEFINITION: &str = "
+SELECT n.nspname::text, c.relname::text, c.relkind = 'm', pg_catalog.pg_get_viewdef(c.oid, true)
+FROM pg_catalog.pg_class c
+JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+WHERE c.relkind IN ('v', 'm')
+  AND c.relname = $1
+  AND ($2::text IS NULL OR n.nspname = $2)
+ORDER BY array_position(current_schemas(true), n.nspname) NULLS LAST, n.nspname
+LIMIT 1";
+
+/// Aggregates have no definition to show, only functions and procedures
+const POSTGRES_ROUTINE_DEFINITIONS: &str = "
+SELECT n.nspname::text, p.proname::text, p.prokind = 'p', pg_catalog.pg_get_functiondef(p.oid)
+FROM pg_catalog.pg_proc p
+JOIN pg_catalog.pg_namespace n ON n.oid = p.pronamespace
+WHERE p.prokind IN ('f', 'p', 'w')
+  AND p.proname = $1
+  AND ($2::text IS NULL OR n.nspname = $2)
+ORDER BY array_position(current_schemas(true), n.nspname) NULLS LAST, n.nspname, p.oid";
+
+pub async fn postgres_definition(
+    client: &tokio_postgres::Client,
+    schema: Option<&str>,
+    name: &str,
+) -> Result<Option<ObjectDefinition>, Error> {
+    let view = client
+        .query_opt(POSTGRES_VIEW_DEFINITION, &[&name, &schema])
+        .await
+        .with_context(|| format!("Failed to look up the definition of {name}"))?;
+    if let Some(row) = view {
+        let (schema, name, materialized, body): (String, String, bool, String) =
+            (row.get(0), row.get(1), row.get(2), row.get(3));
+        let create = if materialized {
+            "CREATE MATERIALIZED VIEW"
+        } else {
+            "CREATE OR REPLACE VIEW"
+        };
+        return Ok(Some(ObjectDefinition {
+            definition: format!("{create} {} AS\n{body}", qualified_name(&schema, &name)),
+            schema,
+            name,
+            object_type: if materialized {
+                ObjectType::MaterializedView
+            } else {
+                ObjectType::View
+            },
+        }));
+    }
+
+    let routines = client
+        .query(POSTGRES_ROUTINE_DEFINITIONS, &[&name, &schema])
+        .await
+        .with_context(|| format!("Failed to look up the definition of {name}"))?;
+    let Some(first) = routines.first() else {
+        return Ok(None);
+    };
+    let (schema, name, is_procedure): (String, String, bool) =
+        (first.get(0), first.get(1), first.get(2));
+    // Every overload, from the schema the name resolves to
+    let definition = routines
+        .iter()
+        .filter(|row| row.get::<_, String>(0) == schema)
+        .map(|row| row.get::<_, String>(3))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(Some(ObjectDefinition {
+        schema,
+        name,
+        object_type: if is_procedure {
+            ObjectType::Procedure
+        } else {
+            ObjectType::Function
+        },
+        definition,
+    }))
+}
+
+pub fn sqlite_definition(
+    conn: &rusqlite::Connection,
+    schema: Option<&str>,
+    name: &str,
+) -> Result<Option<ObjectDefinition>, Error> {
+    let schema = schema.unwrap_or("main");
+    let query = format!(
+        "SELECT type, name, sql FROM {}
+         WHERE type IN ('view', 'table', 'trigger') AND sql IS NOT NULL AND name = ?1 COLLATE NOCASE
+         ORDER BY type = 'view' DESC
+         LIMIT 1",
+        qualified_name(schema, "sqlite_master")
+    );
+
+    let mut stmt = conn
+        .prepare(&query)
+        .with_context(|| format!("Failed to look up the definition of {name}"))?;
+    let mut rows = stmt
+        .query([name])
+        .with_context(|| format!("Failed to look up the definition of {name}"))?;
+    let Some(row) = rows.next().context("Failed to read the definition")? else {
+        return Ok(None);
+    };
+
+    let object_type = match row.get::<_, String>(0)?.as_str() {
+        "view" => ObjectType::View,
+        "trigger" => ObjectType::Trigger,
+        _ => ObjectType::Table,
+    };
+    Ok(Some(ObjectDefinition {
+        schema: schema.to_owned(),
+        name: row.get(1)?,
+        object_type,
+        definition: row.get::<_, String>(2)? + ";",
+    }))
+}
+
+pub async fn mysql_definition(
+    conn: &mut mysql_async::Conn,
+    schema: Option<&str>,
+    name: &str,
+) -> Result<Option<ObjectDefinition>, Error> {
+    let view: Option<(String, String)> = conn
+        .exec_first(
+            "SELECT TABLE_SCHEMA, TABLE_NAME FROM information_schema.VIEWS
+             WHERE TABLE_NAME = ? AND TABLE_SCHEMA = COALESCE(?, DATABASE())
+             LIMIT 1",
+            (name, schema),
+        )
+        .await
+        .with_context(|| format!("Failed to look up the definition of {name}"))?;
+    if let Some((schema, name)) = view {
+        let row: Option<mysql_async::Row> = conn
+            .query_first(format!(
+                "SHOW CREATE VIEW {}.{}",
+                quote_mysql_identifier(&schema),
+                quote_mysql_identifier(&name)
+            ))
+            .await
+            .with_context(|| format!("Failed to fetch the definition of {name}"))?;
+        // `View`, `Create View`, then the character set and collation
+        let definition: Option<String> = row.and_then(|row| row.get(1));
+        return Ok(definition.map(|definition| ObjectDefinition {
+            schema,
+            name,
+            object_type: ObjectType::View,
+            definition: definition + ";",
+        }));
+    }
+
+    let routine: Option<(String, String, String)> = conn
+        .exec_first(
+            "SELECT ROUTINE_SCHEMA, ROUTINE_NAME, ROUTINE_TYPE FROM information_schema.ROUTINES
+             WHERE ROUTINE_NAME = ? AND ROUTINE_SCHEMA = COALESCE(?, DATABASE())
+             LIMIT 1",
+            (name, schema),
+        )
+        .await
+        .with_context(|| format!("Failed to look up the definition of {name}"))?;
+    let Some((schema, name, routine_type)) = routine else {
+        return Ok(None);
+    };
+    let (object_type, keyword) = match routine_type.as_str() {
+        "PROCEDURE" => (ObjectType::Procedure, "PROCEDURE"),
+        _ => (ObjectType::Function, "FUNCTION"),
+    };
+    let row: Option<mysql_async::Row> = conn
+        .query_first(format!(
+            "SHOW CREATE {keyword} {}.{}",
+            quote_mysql_identifier(&schema),
+            quote_mysql_identifier(&name)
+        ))
+        .await
+        .with_context(|| format!("Failed to fetch the definition of {name}"))?;
+    // `Create Function` is null without the privileges to see the routine's body
+    let definition: Option<String> = row.and_then(|row| row.get::<Option<String>, _>(2).flatten());
+    let definition = definition.with_context(|| {
+        format!("The definition of {name} can't be read, it may take more privileges to see it")
+    })?;
+
+    Ok(Some(ObjectDefinition {
+        schema,
+        name,
+        object_type,
+        definition,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::dialect::PostgreSqlDialect;
+
+    use super::*;
+
+    fn definition(query: &str, at: &str) -> Option<DefinitionTarget> {
+        let position = query.rfind(at).unwrap();
+        definition_at(&PostgreSqlDialect {}, Database::Postgres, query, position)
+    }
+
+    fn local(query: &str, definition: &str) -> Option<DefinitionTarget> {
+        let start = query.find(definition).unwrap();
+        Some(DefinitionTarget::Local {
+            bytes: start..start + definition.len(),
+        })
+    }
+
+    #[test]
+    fn finds_ctes_in_the_statement() {
+        let query =
+            "WITH RECURSIVE \"Recent\" (id) AS (SELECT 1), totals AS MATERIALIZED (SELECT 2)\n\
+                     SELECT * FROM \"Recent\" r JOIN TOTALS ON totals.id = r.id";
+
+        assert_eq!(definition(query, "TOTALS ON"), local(query, "totals"));
+        assert_eq!(definition(query, "totals.id"), local(query, "totals"));
+        assert_eq!(
+            definition(query, "\"Recent\" r"),
+            local(query, "\"Recent\"")
+        );
+        // Quoted names keep their case, so this isn't the CTE
+        assert_eq!(
+            definition(
+                "WITH recent AS (SELECT 1) SELECT * FROM \"Recent\"",
+                "\"Recent\""
+            ),
+            Some(DefinitionTarget::Object {
+                schema: None,
+                name: "Recent".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn finds_database_objects() {
+        let query = "SELECT Reporting.Monthly_Sales(1) FROM \"Reporting\".\"ActiveUsers\" WHERE x";
+        assert_eq!(
+            definition(query, "Monthly_Sales"),
+            Some(DefinitionTarget::Object {
+                schema: Some("reporting".to_owned()),
+                name: "monthly_sales".to_owned()
+            })
+        );
+        assert_eq!(
+            definition(query, "\"ActiveUsers\""),
+            Some(DefinitionTarget::Object {
+                schema: Some("Reporting".to_owned()),
+                name: "ActiveUsers".to_owned()
+            })
+        );
+        assert_eq!(definition(query, "\"Reporting\""), None);
+        assert_eq!(definition(query, "WHERE"), None);
+        assert_eq!(
+            definition("SELECT left(name, 1) FROM users", "left"),
+            Some(DefinitionTarget::Object {
+                schema: None,
+                name: "left".to_owned()
+            })
+        );
+    }
+}
@@ -0,0 +1,564 @@
+//! Exporting results as delimited text (CSV, TSV, pipe-delimited, ...), configured through [`ExportOptions`]
+
+use std::{fmt::Write, io};
+
+use anyhow::{bail, ensure, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotePolicy {
+    /// Quote every field, except NULLs
+    Always,
+    /// Quote fields containing the delimiter, the quote character or a line break
+    Minimal,
+    /// Never quote. Fields that would need quoting fail the export.
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportEncoding {
+    Utf8,
+    Utf8Bom,
+    Latin1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub delimiter: char,
+    pub quote_char: char,
+    pub quote_policy: QuotePolicy,
+    pub line_ending: LineEnding,
+    pub header: bool,
+    /// Written in place of NULLs
+    pub null_value: String,
+    /// strftime-like format for DATE values, e.g. `%d.%m.%Y`
+    pub date_format: Option<String>,
+    /// strftime-like format for TIMESTAMP and TIMESTAMPTZ values
+    pub timestamp_format: Option<String>,
+    /// Replaces the `.` in non-integer numbers, e.g. `,` for German locales.
+    /// Numbers that are sent as text (e.g. Postgres' NUMERIC) are left as-is.
+    pub decimal_separator: Option<char>,
+    pub encoding: ExportEncoding,
+    /// Without the leading dot
+    pub file_extension: String,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote_char: '"',
+            quote_policy: QuotePolicy::Minimal,
+            line_ending: LineEnding::Lf,
+            header: true,
+            null_value: String::new(),
+            date_format: None,
+            timestamp_format: None,
+            decimal_separator: None,
+            encoding: ExportEncoding::Utf8,
+            file_extension: "csv".to_owned(),
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Rejects combinations of options that can't produce a readable file
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let is_line_break = |c: char| c == '\r' || c == '\n';
+
+        ensure!(
+            !is_line_break(self.delimiter),
+            "The delimiter can't be a line break"
+        );
+        ensure!(
+            !is_line_break(self.quote_char),
+            "The quote character can't be a line break"
+        );
+        ensure!(
+            self.quote_char != self.delimiter,
+            "The quote character can't be the same as the delimiter"
+        );
+
+        if let Some(separator) = self.decimal_separator {
+            ensure!(
+                separator != self.delimiter || self.quote_policy != QuotePolicy::Never,
+                "The decimal separator can't be the same as the delimiter unless fields are quoted"
+            );
+        }
+
+        if self.quote_policy == QuotePolicy::Never {
+            ensure!(
+                !self.null_value.contains(self.delimiter) && !self.null_value.contains(is_line_break),
+                "The NULL representation can't contain the delimiter or a line break unless fields are quoted"
+            );
+        }
+        ensure!(
+            !self.null_value.contains(self.quote_char),
+            "The NULL representation can't contain the quote character"
+        );
+
+        ensure!(
+            !self.file_extension.is_empty() && !self.file_extension.contains(['.', '/', '\\']),
+            "The file extension should be non-empty, without dots or slashes (e.g. `csv`)"
+        );
+
+        let sample = chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+            .and_then(|date| date.and_hms_opt(13, 45, 0))
+            .expect("valid sample date");
+        if let Some(format) = &self.date_format {
+            ensure!(
+                write!(String::new(), "{}", sample.date().format(format)).is_ok(),
+                "Invalid date format: {format}"
+            );
+        }
+        if let Some(format) = &self.timestamp_format {
+            ensure!(
+                write!(String::new(), "{}", sample.format(format)).is_ok(),
+                "Invalid timestamp format: {format}"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes rows as delimited text to `out`, encoding them a page at a time
+pub struct DelimitedWriter<'a, W> {
+    out: W,
+    options: &'a ExportOptions,
+    /// Text that wasn't encoded and written yet
+    buffer: String,
+    /// How many characters were replaced because Latin-1 can't represent them
+    unrepresentable: usize,
+}
+
+impl<'a, W: io::Write> DelimitedWriter<'a, W> {
+    pub fn new(out: W, options: &'a ExportOptions, columns: &[String]) -> anyhow::Result<Self> {
+        options.validate()?;
+
+        let mut writer = Self {
+            out,
+            options,
+            buffer: String::new(),
+            unrepresentable: 0,
+        };
+
+        if options.encoding == ExportEncoding::Utf8Bom {
+            writer.out.write_all(b"\xEF\xBB\xBF")?;
+        }
+        if options.header {
+            let mut first = true;
+            for column in columns {
+                writer.write_separator(&mut first);
+                writer.write_field(column)?;
+            }
+            writer.buffer.push_str(options.line_ending.as_str());
+        }
+        writer.write_buffer()?;
+
+        Ok(writer)
+    }
+
+    /// Writes a page produced by our row writers, i.e. `[[1, "a"], [2, "b"]]`
+    pub fn write_page(&mut self, page: &str) -> anyhow::Result<()> {
+        let rows: Vec<Vec<Value>> =
+            serde_json::from_str(page).context("Expected page to be an array of rows")?;
+
+        for row in rows {
+            let mut first = true;
+            for value in &row {
+                self.write_separator(&mut first);
+                self.write_value(value)?;
+            }
+            self.buffer.push_str(self.options.line_ending.as_str());
+        }
+
+        self.write_buffer()
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        if self.unrepresentable > 0 {
+            log::warn!(
+                "Replaced {} characters that Latin-1 can't represent",
+                self.unrepresentable
+            );
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    /// Encodes the buffered text into `out`
+    fn write_buffer(&mut self) -> anyhow::Result<()> {
+        match self.options.encoding {
+            ExportEncoding::Utf8 | ExportEncoding::Utf8Bom => {
+                self.out.write_all(self.buffer.as_bytes())?
+            }
+            ExportEncoding::Latin1 => {
+                let bytes: Vec<u8> = self
+                    .buffer
+                    .chars()
+                    .map(|c| {
+                        u8::try_from(u32::from(c)).unwrap_or_else(|_| {
+                            self.unrepresentable += 1;
+                            b'?'
+                        })
+                    })
+                    .collect();
+                self.out.write_all(&bytes)?;
+            }
+        }
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn write_separator(&mut self, first: &mut bool) {
+        if !*first {
+            self.buffer.push(self.options.delimiter);
+        }
+        *first = false;
+    }
+
+    fn write_value(&mut self, value: &Value) -> anyhow::Result<()> {
+        match value {
+            // Written as-is, so it can be told apart from an (always quoted) empty string
+            Value::Null => {
+                self.buffer.push_str(&self.options.null_value);
+                Ok(())
+            }
+            Value::Bool(b) => self.write_field(if *b { "true" } else { "false" }),
+            Value::Number(n) => {
+                let n = n.to_string();
+                match self.options.decimal_separator {
+                    Some(separator) => self.write_field(&n.replace('.', &separator.to_string())),
+                    None => self.write_field(&n),
+                }
+            }
+            Value::String(s) => match self.format_temporal(s) {
+                Some(formatted) => self.write_field(&formatted),
+                None => self.write_field(s),
+            },
+            Value::Array(_) | Value::Object(_) => self.write_field(&value.to_string()),
+        }
+    }
+
+    /// Reformats dates and timestamps (as written by our row writers) if a format was given for them
+    fn format_temporal(&self, s: &str) -> Option<String> {
+        use chrono::{NaiveDate, NaiveDateTime};
+
+        if let Some(format) = &self.options.timestamp_format {
+            let timestamp = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+                .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f UTC"));
+            if let Ok(timestamp) = timestamp {
+                return Some(timestamp.format(format).to_string());
+            }
+        }
+
+        if let Some(format) = &self.options.date_format {
+            if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+                return Some(date.format(format).to_string());
+            }
+        }
+
+        None
+    }
+
+    fn write_field(&mut self, field: &str) -> anyhow::Result<()> {
+        let ExportOptions {
+            delimiter,
+            quote_char,
+            quote_policy,
+            ..
+        } = *self.options;
+
+        let needs_quoting = field.contains([delimiter, quote_char, '\r', '\n']);
+
+        let quote = match quote_policy {
+            QuotePolicy::Always => true,
+            QuotePolicy::Minimal => needs_quoting,
+            QuotePolicy::Never if needs_quoting => bail!(
+                "Can't export {field:?} without quoting, since it contains the delimiter, the quote character or a line break"
+            ),
+            QuotePolicy::Never => false,
+        };
+
+        if quote {
+            self.buffer.push(quote_char);
+            for c in field.chars() {
+                // Quotes inside a quoted field are escaped by doubling them
+                if c == quote_char {
+                    self.buffer.push(quote_char);
+                }
+                self.buffer.push(c);
+            }
+            self.buffer.push(quote_char);
+        } else {
+            self.buffer.push_str(field);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> Vec<String> {
+        ["id", "name", "price", "sold_on", "sold_at", "tags"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    const PAGES: [&str; 2] = [
+        r#"[[1, "Açaí; \"fresh\"", 2.5, "2024-01-31", "2024-01-31 13:45:00 UTC", ["a", "b"]]]"#,
+        r#"[[2, "", null, null, "2024-02-01 08:00:00.5", null], [3, "multi\nline", 10, "2024-03-01", null, {"k": 1}]]"#,
+    ];
+
+    fn export(options: &ExportOptions) -> Vec<u8> {
+        let mut writer = DelimitedWriter::new(Vec::new(), options, &columns()).unwrap();
+        for page in PAGES {
+            writer.write_page(page).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    /// A minimal reader for the files we write, returning None for unquoted NULLs
+    fn read_back(bytes: &[u8], options: &ExportOptions) -> Vec<Vec<Option<String>>> {
+        let text: String = match options.encoding {
+            ExportEncoding::Utf8 => String::from_utf8(bytes.to_vec()).unwrap(),
+            ExportEncoding::Utf8Bom => {
+                String::from_utf8(bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap().to_vec()).unwrap()
+            }
+            ExportEncoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+        };
+        let line_ending = options.line_ending.as_str();
+        assert!(text.ends_with(line_ending));
+
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut chars = text.chars().peekable();
+        let mut at_line_start = true;
+
+        while chars.peek().is_some() {
+            let mut field = String::new();
+            let quoted = chars.peek() == Some(&options.quote_char);
+
+            if quoted {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == options.quote_char {
+                        if chars.peek() == Some(&options.quote_char) {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    field.push(c);
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c == options.delimiter || line_ending.starts_with(c) {
+                        break;
+                    }
+                    field.push(c);
+                    chars.next();
+                }
+            }
+            at_line_start = false;
+
+            row.push(if !quoted && field == options.null_value {
+                None
+            } else {
+                Some(field)
+            });
+
+            match chars.next() {
+                Some(c) if c == options.delimiter => {}
+                Some(c) if line_ending.starts_with(c) => {
+                    if line_ending.len() > 1 {
+                        assert_eq!(chars.next(), Some('\n'));
+                    }
+                    rows.push(std::mem::take(&mut row));
+                    at_line_start = true;
+                }
+                None => break,
+                Some(c) => panic!("Unexpected {c:?} after a field"),
+            }
+        }
+        assert!(at_line_start);
+
+        rows
+    }
+
+    fn cells(row: &[Option<&str>]) -> Vec<Option<String>> {
+        row.iter().map(|cell| cell.map(String::from)).collect()
+    }
+
+    #[test]
+    fn round_trips_default_csv() {
+        let options = ExportOptions {
+            null_value: "NULL".to_owned(),
+            ..Default::default()
+        };
+        let rows = read_back(&export(&options), &options);
+
+        assert_eq!(
+            rows,
+            [
+                cells(&[
+                    Some("id"),
+                    Some("name"),
+                    Some("price"),
+                    Some("sold_on"),
+                    Some("sold_at"),
+                    Some("tags")
+                ]),
+                cells(&[
+                    Some("1"),
+                    Some("Açaí; \"fresh\""),
+                    Some("2.5"),
+                    Some("2024-01-31"),
+                    Some("2024-01-31 13:45:00 UTC"),
+                    Some(r#"["a","b"]"#)
+                ]),
+                cells(&[
+                    Some("2"),
+                    Some(""),
+                    None,
+                    None,
+                    Some("2024-02-01 08:00:00.5"),
+                    None
+                ]),
+                cells(&[
+                    Some("3"),
+                    Some("multi\nline"),
+                    Some("10"),
+                    Some("2024-03-01"),
+                    None,
+                    Some(r#"{"k":1}"#)
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_excel_de() {
+        let options = ExportOptions {
+            delimiter: ';',
+            quote_policy: QuotePolicy::Always,
+            line_ending: LineEnding::Crlf,
+            decimal_separator: Some(','),
+            date_format: Some("%d.%m.%Y".to_owned()),
+            timestamp_format: Some("%d.%m.%Y %H:%M".to_owned()),
+            encoding: ExportEncoding::Latin1,
+            ..Default::default()
+        };
+        let bytes = export(&options);
+        assert!(bytes.starts_with(b"\"id\";\"name\";"));
+
+        let rows = read_back(&bytes, &options);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows[1],
+            cells(&[
+                Some("1"),
+                Some("Açaí; \"fresh\""),
+                Some("2,5"),
+                Some("31.01.2024"),
+                Some("31.01.2024 13:45"),
+                Some(r#"["a","b"]"#)
+            ])
+        );
+        // The empty string is quoted, the NULL isn't
+        assert_eq!(rows[2][1], Some(String::new()));
+        assert_eq!(rows[2][2], None);
+        assert_eq!(rows[2][4].as_deref(), Some("01.02.2024 08:00"));
+    }
+
+    #[test]
+    fn round_trips_pipe_delimited_without_header() {
+        let options = ExportOptions {
+            delimiter: '|',
+            quote_policy: QuotePolicy::Never,
+            line_ending: LineEnding::Crlf,
+            header: false,
+            encoding: ExportEncoding::Utf8Bom,
+            file_extension: "txt".to_owned(),
+            ..Default::default()
+        };
+
+        let mut writer = DelimitedWriter::new(Vec::new(), &options, &columns()).unwrap();
+        writer
+            .write_page(r#"[[1, "a;b", 2.5, "2024-01-31", null, null]]"#)
+            .unwrap();
+        let rows = read_back(&writer.finish().unwrap(), &options);
+        assert_eq!(
+            rows,
+            [cells(&[
+                Some("1"),
+                Some("a;b"),
+                Some("2.5"),
+                Some("2024-01-31"),
+                None,
+                None
+            ])]
+        );
+
+        // Can't be written unquoted
+        let mut writer = DelimitedWriter::new(Vec::new(), &options, &columns()).unwrap();
+        assert!(writer.write_page(PAGES[1]).is_err());
+    }
+
+    #[test]
+    fn rejects_incoherent_options() {
+        let invalid = [
+            ExportOptions {
+                quote_char: ',',
+                ..Default::default()
+            },
+            ExportOptions {
+                delimiter: '\n',
+                ..Default::default()
+            },
+            ExportOptions {
+                delimiter: ',',
+                decimal_separator: Some(','),
+                quote_policy: QuotePolicy::Never,
+                ..Default::default()
+            },
+            ExportOptions {
+                file_extension: ".csv".to_owned(),
+                ..Default::default()
+            },
+            ExportOptions {
+                date_format: Some("%H:%M".to_owned()),
+                ..Default::default()
+            },
+        ];
+
+        for options in invalid {
+            assert!(options.validate().is_err(), "{options:?}");
+        }
+        assert!(ExportOptions::default().validate().is_ok());
+    }
+}
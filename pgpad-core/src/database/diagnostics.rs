@@ -0,0 +1,488 @@
+//! Diagnostics of the editor: tables and columns the cached schema doesn't have, and column names that could be
+//! more than one column. A wrong diagnostic is worse than a missing one, so anything the tokens can't tell for
+//! sure, like names from subqueries or set-returning functions, isn't reported.
+
+use std::{collections::HashSet, ops::Range};
+
+use serde::Serialize;
+use sqlparser::{
+    dialect::Dialect,
+    keywords::Keyword,
+    tokenizer::{Token, Word},
+};
+
+use crate::database::{
+    definition,
+    hover::{self, TableRef},
+    types::{Database, DatabaseSchema, TableInfo},
+};
+
+type TokenSpan = (Token, Range<usize>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    /// The range of the name, in UTF-16 code units like editor positions
+    pub from: usize,
+    pub to: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Tables of the catalog that aren't part of the introspected schema but are always there
+fn is_system_table(database: Database, name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    match database {
+        Database::Postgres => name.starts_with("pg_"),
+        Database::Sqlite => name.starts_with("sqlite_"),
+        Database::MySql => name == "dual",
+    }
+}
+
+/// Columns every table has without them being listed
+fn is_system_column(database: Database, name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    match database {
+        Database::Postgres => {
+            matches!(
+                name.as_str(),
+                "ctid" | "xmin" | "xmax" | "cmin" | "cmax" | "tableoid"
+            )
+        }
+        Database::Sqlite => matches!(name.as_str(), "rowid" | "oid" | "_rowid_"),
+        Database::MySql => false,
+    }
+}
+
+fn is_keyword(word: &Word) -> bool {
+    word.quote_style.is_none() && word.keyword != Keyword::NoKeyword
+}
+
+fn keyword_at(tokens: &[TokenSpan], idx: usize) -> Option<Keyword> {
+    hover::word(tokens, idx)
+        .filter(|w| w.quote_style.is_none())
+        .map(|w| w.keyword)
+}
+
+/// The names of the tables and views the script creates, which the schema doesn't know of yet
+fn created_names(tokens: &[TokenSpan]) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for idx in (0..tokens.len()).filter(|&idx| keyword_at(tokens, idx) == Some(Keyword::CREATE)) {
+        let Some(kind) = (idx + 1..tokens.len().min(idx + 5)).find(|&idx| {
+            matches!(
+                keyword_at(tokens, idx),
+                Some(Keyword::TABLE | Keyword::VIEW)
+            )
+        }) else {
+            continue;
+        };
+        let mut start = kind + 1;
+        if keyword_at(tokens, start) == Some(Keyword::IF) {
+            start += 3;
+        }
+        if let Some((name, _)) = hover::object_name(tokens, start) {
+            names.insert(name[name.len() - 1].value.to_lowercase());
+        }
+    }
+    names
+}
+
+/// A table the statement reads from, if the schema has it with its columns
+enum Source<'a> {
+    Table(&'a TableInfo),
+    /// A CTE, subquery, function or table the schema doesn't know the columns of
+    Unknown,
+}
+
+struct StatementChecker<'a> {
+    database: Database,
+    schema: &'a DatabaseSchema,
+    query: &'a str,
+    created: &'a HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> StatementChecker<'a> {
+    fn report(&mut self, bytes: Range<usize>, severity: Severity, message: String) {
+        self.diagnostics.push(Diagnostic {
+            from: hover::utf16_len(&self.query[..bytes.start]),
+            to: hover::utf16_len(&self.query[..bytes.end]),
+            severity,
+            message,
+        });
+    }
+
+    /// What the table reference is, reporting it if it's a table that doesn't exist
+    fn resolve_source(
+        &mut self,
+        tokens: &[TokenSpan],
+        ctes: &[&Word],
+        table_ref: &TableRef,
+    ) -> Source<'a> {
+        if table_ref.function {
+            return Source::Unknown;
+        }
+        if let Some(table) = hover::find_table(self.schema, &table_ref.name) {
+            return match table.columns_pending {
+                true => Source::Unknown,
+                false => Source::Table(table),
+            };
+        }
+
+        let (name, qualifier) = table_ref.name.split_last().expect("names have a part");
+        let unknown_schema = qualifier.last().is_some_and(|schema| {
+            !self
+                .schema
+                .schemas
+                .iter()
+                .any(|known| hover::same_name(schema, known))
+        });
+        let exists_elsewhere = unknown_schema
+            || (qualifier.is_empty()
+                && (ctes.iter().any(|cte| hover::same_name(name, &cte.value))
+                    || is_system_table(self.database, &name.value)))
+            || self.created.contains(&name.value.to_lowercase())
+            || is_keyword(name);
+        if !exists_elsewhere {
+            let bytes = table_ref.name_bytes(tokens);
+            let message = format!("Table \"{}\" doesn't exist", &self.query[bytes.clone()]);
+            self.report(bytes, Severity::Error, message);
+        }
+        Source::Unknown
+    }
+
+    fn check(&mut self, tokens: &[TokenSpan]) {
+        if !matches!(
+            keyword_at(tokens, 0),
+            Some(
+                Keyword::SELECT
+                    | Keyword::WITH
+                    | Keyword::INSERT
+                    | Keyword::UPDATE
+                    | Keyword::DELETE
+            )
+        ) {
+            return;
+        }
+
+        let cte_indices = definition::cte_names(tokens);
+        let ctes: Vec<&Word> = cte_indices
+            .iter()
+            .filter_map(|&idx| hover::word(tokens, idx))
+            .collect();
+
+        let all_refs = hover::table_refs(tokens);
+        let ref_tokens: HashSet<usize> = all_refs
+            .iter()
+            .flat_map(|r| (r.start..r.start + 2 * r.name.len()).chain(r.alias.map(|(idx, _)| idx)))
+            .collect();
+        // `SELECT ... INTO new_table` creates the table rather than reading it
+        let refs: Vec<_> = all_refs
+            .into_iter()
+            .filter(|r| {
+                r.keyword != Keyword::INTO
+                    || matches!(
+                        keyword_at(tokens, r.start.saturating_sub(2)),
+                        Some(Keyword::INSERT | Keyword::REPLACE | Keyword::IGNORE | Keyword::MERGE)
+                    )
+            })
+            .collect();
+        let sources: Vec<_> = refs
+            .iter()
+            .map(|r| (r, self.resolve_source(tokens, &ctes, r)))
+            .collect();
+
+        // Names can only be checked when every table they could come from is known
+        let derived = tokens.iter().enumerate().any(|(idx, (token, _))| {
+            matches!(token, Token::LParen)
+                && idx > 0
+                && matches!(
+                    keyword_at(tokens, idx - 1),
+                    Some(Keyword::FROM | Keyword::JOIN | Keyword::LATERAL)
+                )
+        }) || tokens.iter().enumerate().any(|(idx, _)| {
+            keyword_at(tokens, idx) == Some(Keyword::USING)
+                && !matches!(tokens.get(idx + 1), Some((Token::LParen, _)))
+        });
+        let all_known = !derived
+            && !refs.is_empty()
+            && sources.iter().all(|(_, s)| matches!(s, Source::Table(_)));
+        // Columns of joins with USING or NATURAL aren't ambiguous, nor are those of subqueries, which are resolved
+        // against their own tables first
+        let selects = (0..tokens.len())
+            .filter(|&idx| keyword_at(tokens, idx) == Some(Keyword::SELECT))
+            .count();
+        let check_ambiguity = selects <= 1
+            && !(0..tokens.len()).any(|idx| {
+                matches!(
+                    keyword_at(tokens, idx),
+                    Some(Keyword::USING | Keyword::NATURAL)
+                )
+            });
+
+        let aliases = self.aliases(tokens);
+
+        let mut idx = 0;
+        while idx < tokens.len() {
+            let start = idx;
+            let Some((parts, end)) = hover::object_name(tokens, idx) else {
+                idx += 1;
+                continue;
+            };
+            idx = end;
+
+            if ref_tokens.contains(&start)
+                || cte_indices.contains(&start)
+                || self.is_not_a_column(tokens, start, end)
+            {
+                continue;
+            }
+
+            let (column, qualifier) = parts.split_last().expect("names have a part");
+            if column.value.starts_with(['@', '$']) {
+                continue;
+            }
+            let bytes = tokens[end - 1].1.clone();
+
+            if !qualifier.is_empty() {
+                let Some((table_ref, Source::Table(table))) =
+                    sources.iter().find(|(r, _)| hover::qualifies(r, qualifier))
+                else {
+                    continue;
+                };
+                if !table
+                    .columns
+                    .iter()
+                    .any(|c| hover::same_name(column, &c.name))
+                    && !is_system_column(self.database, &column.value)
+                {
+                    let table_name = table_ref.alias.map_or(&table.name, |(_, a)| &a.value);
+                    let message = format!(
+                        "Column \"{}\" doesn't exist in \"{table_name}\"",
+                        column.value
+                    );
+                    self.report(bytes, Severity::Error, message);
+                }
+                continue;
+            }
+
+            if !all_known
+                || aliases.iter().any(|alias| hover::same_name(column, alias))
+                || is_system_column(self.database, &column.value)
+            {
+                continue;
+            }
+            let candidates: Vec<_> = sources
+                .iter()
+                .filter_map(|(r, source)| match source {
+                    Source::Table(table) => table
+                        .columns
+                        .iter()
+                        .any(|c| hover::same_name(column, &c.name))
+                        .then(|| {
+                            r.alias
+                                .map_or(table.name.as_str(), |(_, a)| a.value.as_str())
+                        }),
+                    Source::Unknown => None,
+                })
+                .collect();
+            match candidates.len() {
+                // Many keywords, like `name` or `id`, are also column names
+                0 if is_keyword(column) => {}
+                0 => {
+                    let message = format!("Column \"{}\" doesn't exist", column.value);
+                    self.report(bytes, Severity::Error, message);
+                }
+                1 => {}
+                _ if check_ambiguity => {
+                    let message = format!(
+                        "Column \"{}\" is ambiguous, it could be in {}",
+                        column.value,
+                        candidates
+                            .iter()
+                            .map(|table| format!("\"{table}\""))
+                            .collect::<Vec<_>>()
+                            .join(" or ")
+                    );
+                    self.report(bytes, Severity::Warning, message);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the name from `start` to `end` is something else than a column: a function, a type, a qualifier of
+    /// `*`, a name given with AS, or the alias of an expression
+    fn is_not_a_column(&self, tokens: &[TokenSpan], start: usize, end: usize) -> bool {
+        let next = tokens.get(end).map(|(token, _)| token);
+        if matches!(next, Some(Token::LParen | Token::Period)) {
+            return true;
+        }
+        // Names of CTEs and windows, as in `name AS (...)`
+        if keyword_at(tokens, end) == Some(Keyword::AS)
+            && matches!(tokens.get(end + 1), Some((Token::LParen, _)))
+        {
+            return true;
+        }
+        let Some(previous) = start.checked_sub(1).and_then(|idx| tokens.get(idx)) else {
+            return false;
+        };
+        match &previous.0 {
+            Token::DoubleColon | Token::Colon | Token::AtSign => true,
+            Token::Word(word) if word.quote_style.is_none() => matches!(
+                word.keyword,
+                Keyword::AS
+                    | Keyword::CONSTRAINT
+                    | Keyword::NoKeyword
+                    | Keyword::END
+                    | Keyword::NULL
+                    | Keyword::TRUE
+                    | Keyword::FALSE
+            ),
+            // An expression right before a name makes it the expression's alias
+            Token::Word(_) | Token::Number(..) | Token::SingleQuotedString(_) | Token::RParen => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Names given to expressions, which ORDER BY and the like may refer to
+    fn aliases<'t>(&self, tokens: &'t [TokenSpan]) -> Vec<&'t str> {
+        (1..tokens.len())
+            .filter_map(|idx| {
+                let word = hover::word(tokens, idx)?;
+                let named = match &tokens[idx - 1].0 {
+                    Token::Word(previous) if previous.quote_style.is_none() => matches!(
+                        previous.keyword,
+                        Keyword::AS | Keyword::NoKeyword | Keyword::END
+                    ),
+                    Token::Word(_) | Token::Number(..) | Token::SingleQuotedString(_) => true,
+                    Token::RParen => true,
+                    _ => false,
+                };
+                (named && !is_keyword(word)).then_some(word.value.as_str())
+            })
+            .collect()
+    }
+}
+
+/// What's wrong with the names of `query`, checked against `schema`
+pub fn diagnostics(
+    dialect: &dyn Dialect,
+    database: Database,
+    schema: &DatabaseSchema,
+    query: &str,
+) -> Vec<Diagnostic> {
+    let Some(tokens) = hover::tokens(dialect, query) else {
+        return vec![];
+    };
+    let created = created_names(&tokens);
+    let mut checker = StatementChecker {
+        database,
+        schema,
+        query,
+        created: &created,
+        diagnostics: vec![],
+    };
+
+    for statement in tokens.split(|(token, _)| *token == Token::SemiColon) {
+        checker.check(statement);
+    }
+    checker.diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::dialect::PostgreSqlDialect;
+
+    use super::*;
+    use crate::database::types::ColumnInfo;
+
+    fn table(schema: &str, name: &str, columns: &[&str]) -> TableInfo {
+        TableInfo {
+            name: name.to_owned(),
+            schema: schema.to_owned(),
+            columns: columns
+                .iter()
+                .map(|name| ColumnInfo {
+                    name: (*name).to_owned(),
+                    data_type: "int4".to_owned(),
+                    is_nullable: true,
+                    default_value: None,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn check(query: &str) -> Vec<(String, Severity)> {
+        let schema = DatabaseSchema::new(
+            vec![
+                table("public", "users", &["id", "name", "team_id"]),
+                table("public", "teams", &["id", "title"]),
+                table("billing", "invoices", &["id", "user_id", "total"]),
+            ],
+            vec!["public".to_owned(), "billing".to_owned()],
+            vec![],
+        );
+        let found = diagnostics(&PostgreSqlDialect {}, Database::Postgres, &schema, query);
+        found
+            .into_iter()
+            .map(|d| {
+                let name: String = query
+                    .encode_utf16()
+                    .skip(d.from)
+                    .take(d.to - d.from)
+                    .map(|c| char::from_u32(c as u32).unwrap())
+                    .collect();
+                (name, d.severity)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_unknown_and_ambiguous_names() {
+        assert_eq!(
+            check(
+                "SELECT u.name, u.email, nmae, id FROM users u JOIN teams t ON t.id = u.team_id;\n\
+                 SELECT * FROM billing.invoice;\n\
+                 DELETE FROM userz WHERE id = 1"
+            ),
+            [
+                ("email".to_owned(), Severity::Error),
+                ("nmae".to_owned(), Severity::Error),
+                ("id".to_owned(), Severity::Warning),
+                ("billing.invoice".to_owned(), Severity::Error),
+                ("userz".to_owned(), Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_out_what_it_cannot_tell() {
+        for query in [
+            // CTEs, and their columns
+            "WITH recent AS (SELECT id FROM users) SELECT id, whatever FROM recent",
+            // Schemas that weren't introspected, system tables and functions
+            "SELECT * FROM audit.events, pg_stat_activity, generate_series(1, 3) g",
+            "SELECT now(), extract(year FROM now()), count(*) FROM users",
+            // Aliases, casts, system columns and tables created by the script
+            "SELECT name AS n, total::numeric, ctid FROM users JOIN billing.invoices ON user_id = users.id ORDER BY n",
+            "CREATE TEMP TABLE scratch (x int); SELECT x FROM scratch",
+            "SELECT name INTO archive FROM users",
+            // Joins with USING and subqueries resolve names their own way
+            "SELECT id FROM users JOIN teams USING (id)",
+            "SELECT id FROM users WHERE EXISTS (SELECT 1 FROM teams WHERE teams.id = users.team_id)",
+            "SELECT id FROM users WHERE team_id IS DISTINCT FROM 1",
+        ] {
+            assert_eq!(check(query), [], "{query}");
+        }
+    }
+}
@@ -0,0 +1,481 @@
+//! Schema-aware suggestions for "column/table does not exist" errors, e.g. the nearest-matching column names of
+//! the table a query references, using the schema cached for autocomplete.
+
+use std::ops::ControlFlow;
+
+use serde::Serialize;
+use sqlparser::{
+    ast::{ObjectName, TableFactor, Visit, Visitor},
+    dialect::GenericDialect,
+    parser::Parser,
+};
+
+use crate::{
+    database::types::{DatabaseSchema, TableInfo},
+    utils::quote_identifier,
+};
+
+/// At most this many suggestions are given for an error
+pub const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionKind {
+    /// A similarly named column or table
+    DidYouMean,
+    /// The column exists, but in another table of the query
+    OtherTable,
+    /// The identifier exists with a different case, which Postgres only matches when it's quoted
+    QuoteIdentifier,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorSuggestion {
+    pub kind: SuggestionKind,
+    pub message: String,
+    /// What to write instead of the missing identifier
+    pub replacement: String,
+}
+
+/// The object an error says doesn't exist
+#[derive(Debug, PartialEq)]
+enum MissingObject {
+    Column {
+        /// The table or alias the column was qualified with
+        qualifier: Option<String>,
+        name: String,
+    },
+    Table {
+        name: String,
+    },
+}
+
+/// Recognizes Postgres' and SQLite's undefined column and table errors, which we only get as text
+fn missing_object(error: &str) -> Option<MissingObject> {
+    let line = error.lines().next()?;
+
+    // Postgres: `column "x" does not exist`, `column t.x does not exist`, `column "x" of relation "t" does not exist`
+    if let Some(rest) = line.strip_prefix("column ") {
+        let (column, relation) = match rest.split_once(" of relation ") {
+            Some((column, rest)) => (column, rest.strip_suffix(" does not exist")),
+            None => (rest.strip_suffix(" does not exist")?, None),
+        };
+        let (qualifier, name) = split_qualified(column);
+        let qualifier = qualifier.or(relation.map(unquote));
+        return Some(MissingObject::Column { qualifier, name });
+    }
+    if let Some(name) = line
+        .strip_prefix("relation ")
+        .and_then(|rest| rest.strip_suffix(" does not exist"))
+    {
+        return Some(MissingObject::Table {
+            name: split_qualified(name).1,
+        });
+    }
+
+    // SQLite: `no such column: x`, `no such column: t.x`, `no such table: x`
+    if let Some((_, column)) = line.split_once("no such column: ") {
+        let (qualifier, name) = split_qualified(column.trim());
+        return Some(MissingObject::Column { qualifier, name });
+    }
+    if let Some((_, table)) = line.split_once("no such table: ") {
+        return Some(MissingObject::Table {
+            name: split_qualified(table.trim()).1,
+        });
+    }
+
+    None
+}
+
+fn unquote(s: &str) -> String {
+    s.trim_matches('"').to_owned()
+}
+
+/// Splits `t.x` or `"t"."x"` into its qualifier and name
+fn split_qualified(s: &str) -> (Option<String>, String) {
+    let s = unquote(s);
+    match s.rsplit_once('.') {
+        Some((qualifier, name)) => (
+            Some(unquote(qualifier.rsplit('.').next().unwrap_or(qualifier))),
+            unquote(name),
+        ),
+        None => (None, s),
+    }
+}
+
+/// The tables a query references, along with the aliases they're referenced through
+#[derive(Default)]
+struct QueryTables {
+    tables: Vec<String>,
+    /// (alias, table)
+    aliases: Vec<(String, String)>,
+}
+
+fn table_name(name: &ObjectName) -> Option<String> {
+    name.0
+        .last()
+        .and_then(|part| part.as_ident())
+        .map(|ident| ident.value.clone())
+}
+
+impl Visitor for QueryTables {
+    type Break = ();
+
+    fn pre_visit_relation(&mut self, relation: &ObjectName) -> ControlFlow<Self::Break> {
+        if let Some(name) = table_name(relation) {
+            if !self.tables.contains(&name) {
+                self.tables.push(name);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_table_factor(&mut self, table_factor: &TableFactor) -> ControlFlow<Self::Break> {
+        if let TableFactor::Table {
+            name,
+            alias: Some(alias),
+            ..
+        } = table_factor
+        {
+            if let Some(table) = table_name(name) {
+                self.aliases.push((alias.name.value.clone(), table));
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl QueryTables {
+    fn of(query: &str) -> Self {
+        let mut tables = Self::default();
+        if let Ok(statements) = Parser::parse_sql(&GenericDialect {}, query) {
+            let _ = statements.visit(&mut tables);
+        }
+        tables
+    }
+
+    fn resolve(&self, qualifier: &str) -> String {
+        self.aliases
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(qualifier))
+            .map(|(_, table)| table.clone())
+            .unwrap_or_else(|| qualifier.to_owned())
+    }
+}
+
+/// Edit distance counting swapped adjacent characters as one edit, since that's a common typo
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    d[0] = (0..=b.len()).collect();
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// Names close enough to `name` to be a typo of it, closest first
+fn nearest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let name = name.to_lowercase();
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    let mut matches: Vec<_> = candidates
+        .filter_map(|candidate| {
+            let distance = edit_distance(&name, &candidate.to_lowercase());
+            (distance > 0 && distance <= max_distance).then_some((distance, candidate))
+        })
+        .collect();
+    // Columns like `id` are in many tables
+    matches.sort();
+    matches.dedup();
+    matches
+        .into_iter()
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+fn find_table<'a>(schema: &'a DatabaseSchema, name: &str) -> Option<&'a TableInfo> {
+    schema
+        .tables
+        .iter()
+        .find(|table| table.name == name)
+        .or_else(|| {
+            schema
+                .tables
+                .iter()
+                .find(|table| table.name.eq_ignore_ascii_case(name))
+        })
+}
+
+fn column_suggestions(
+    qualifier: Option<&str>,
+    name: &str,
+    query: &str,
+    schema: &DatabaseSchema,
+) -> Vec<ErrorSuggestion> {
+    let query_tables = QueryTables::of(query);
+    let qualified_table = qualifier.map(|qualifier| query_tables.resolve(qualifier));
+
+    // The tables the column should have been in: the one it was qualified with, or all of the query's
+    let tables: Vec<&TableInfo> = match &qualified_table {
+        Some(table) => find_table(schema, table).into_iter().collect(),
+        None => query_tables
+            .tables
+            .iter()
+            .filter_map(|table| find_table(schema, table))
+            .collect(),
+    };
+    let columns = || {
+        tables
+            .iter()
+            .flat_map(|table| table.columns.iter().map(|column| column.name.as_str()))
+    };
+
+    let mut suggestions = Vec::new();
+
+    for column in columns() {
+        if column != name && column.eq_ignore_ascii_case(name) {
+            suggestions.push(ErrorSuggestion {
+                kind: SuggestionKind::QuoteIdentifier,
+                message: format!(
+                    "The column is named {column}: unquoted identifiers are folded to lowercase, so it must be quoted"
+                ),
+                replacement: quote_identifier(column),
+            });
+        }
+    }
+
+    if let Some(qualified_table) = &qualified_table {
+        let other_tables = query_tables
+            .tables
+            .iter()
+            .filter(|table| !table.eq_ignore_ascii_case(qualified_table))
+            .filter_map(|table| find_table(schema, table));
+
+        for table in other_tables {
+            if table.columns.iter().any(|column| column.name == name) {
+                let reference = query_tables
+                    .aliases
+                    .iter()
+                    .find(|(_, aliased)| *aliased == table.name)
+                    .map(|(alias, _)| alias.as_str())
+                    .unwrap_or(&table.name);
+
+                suggestions.push(ErrorSuggestion {
+                    kind: SuggestionKind::OtherTable,
+                    message: format!(
+                        "{name} is a column of {}, not {qualified_table}",
+                        table.name
+                    ),
+                    replacement: format!("{reference}.{name}"),
+                });
+            }
+        }
+    }
+
+    for column in nearest(name, columns()) {
+        if suggestions
+            .iter()
+            .any(|s| s.replacement.trim_matches('"') == column)
+        {
+            continue;
+        }
+        suggestions.push(ErrorSuggestion {
+            kind: SuggestionKind::DidYouMean,
+            message: format!("Did you mean {column}?"),
+            replacement: column.to_owned(),
+        });
+    }
+
+    suggestions
+}
+
+fn table_suggestions(name: &str, schema: &DatabaseSchema) -> Vec<ErrorSuggestion> {
+    let mut suggestions = Vec::new();
+
+    for table in &schema.tables {
+        if table.name != name && table.name.eq_ignore_ascii_case(name) {
+            suggestions.push(ErrorSuggestion {
+                kind: SuggestionKind::QuoteIdentifier,
+                message: format!(
+                    "The table is named {}: unquoted identifiers are folded to lowercase, so it must be quoted",
+                    table.name
+                ),
+                replacement: quote_identifier(&table.name),
+            });
+        }
+    }
+
+    for table in nearest(name, schema.tables.iter().map(|table| table.name.as_str())) {
+        suggestions.push(ErrorSuggestion {
+            kind: SuggestionKind::DidYouMean,
+            message: format!("Did you mean {table}?"),
+            replacement: table.to_owned(),
+        });
+    }
+
+    suggestions
+}
+
+/// Suggestions for an error returned by `query`, if it's about a column or table that doesn't exist
+pub fn suggest(error: &str, query: &str, schema: &DatabaseSchema) -> Vec<ErrorSuggestion> {
+    let mut suggestions = match missing_object(error) {
+        Some(MissingObject::Column { qualifier, name }) => {
+            column_suggestions(qualifier.as_deref(), &name, query, schema)
+        }
+        Some(MissingObject::Table { name }) => table_suggestions(&name, schema),
+        None => Vec::new(),
+    };
+
+    suggestions.truncate(MAX_SUGGESTIONS);
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::types::ColumnInfo;
+
+    use super::*;
+
+    fn schema() -> DatabaseSchema {
+        let table = |name: &str, columns: &[&str]| TableInfo {
+            name: name.into(),
+            schema: "public".into(),
+            columns: columns
+                .iter()
+                .map(|column| ColumnInfo {
+                    name: column.to_string(),
+                    data_type: "text".into(),
+                    is_nullable: true,
+                    default_value: None,
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        DatabaseSchema::new(
+            vec![
+                table("users", &["id", "name", "createdAt"]),
+                table("orders", &["id", "user_id", "total"]),
+                table("Invoices", &["id"]),
+            ],
+            vec!["public".into()],
+            Vec::new(),
+        )
+    }
+
+    fn replacements(suggestions: &[ErrorSuggestion]) -> Vec<(SuggestionKind, &str)> {
+        suggestions
+            .iter()
+            .map(|s| (s.kind.clone(), s.replacement.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn recognizes_errors() {
+        assert_eq!(
+            missing_object("column \"user_id\" does not exist\nHINT: Perhaps ..."),
+            Some(MissingObject::Column {
+                qualifier: None,
+                name: "user_id".into()
+            })
+        );
+        assert_eq!(
+            missing_object("column u.user_id does not exist"),
+            Some(MissingObject::Column {
+                qualifier: Some("u".into()),
+                name: "user_id".into()
+            })
+        );
+        assert_eq!(
+            missing_object("column \"nme\" of relation \"users\" does not exist"),
+            Some(MissingObject::Column {
+                qualifier: Some("users".into()),
+                name: "nme".into()
+            })
+        );
+        assert_eq!(
+            missing_object("relation \"public.userz\" does not exist"),
+            Some(MissingObject::Table {
+                name: "userz".into()
+            })
+        );
+        assert_eq!(
+            missing_object("no such column: o.totl"),
+            Some(MissingObject::Column {
+                qualifier: Some("o".into()),
+                name: "totl".into()
+            })
+        );
+        assert_eq!(missing_object("division by zero"), None);
+    }
+
+    #[test]
+    fn suggests_typo_fixes() {
+        let suggestions = suggest(
+            "column \"nmae\" does not exist",
+            "SELECT nmae FROM users",
+            &schema(),
+        );
+        assert_eq!(
+            replacements(&suggestions),
+            [(SuggestionKind::DidYouMean, "name")]
+        );
+
+        let suggestions = suggest("no such table: userz", "SELECT * FROM userz", &schema());
+        assert_eq!(
+            replacements(&suggestions),
+            [(SuggestionKind::DidYouMean, "users")]
+        );
+    }
+
+    #[test]
+    fn suggests_other_tables_of_the_query() {
+        let suggestions = suggest(
+            "column u.user_id does not exist",
+            "SELECT u.user_id FROM users u JOIN orders o ON o.user_id = u.id",
+            &schema(),
+        );
+        assert_eq!(
+            replacements(&suggestions),
+            [(SuggestionKind::OtherTable, "o.user_id")]
+        );
+    }
+
+    #[test]
+    fn suggests_quoting_case_folded_identifiers() {
+        let suggestions = suggest(
+            "column \"createdat\" does not exist",
+            "SELECT createdAt FROM users",
+            &schema(),
+        );
+        assert_eq!(
+            replacements(&suggestions),
+            [(SuggestionKind::QuoteIdentifier, "\"createdAt\"")]
+        );
+
+        let suggestions = suggest(
+            "relation \"invoices\" does not exist",
+            "SELECT * FROM Invoices",
+            &schema(),
+        );
+        assert_eq!(suggestions[0].kind, SuggestionKind::QuoteIdentifier);
+        assert_eq!(suggestions[0].replacement, "\"Invoices\"");
+        assert!(suggestions.len() <= MAX_SUGGESTIONS);
+    }
+}
@@ -0,0 +1,321 @@
+//! Which statements of an editor buffer were run this session, and how their latest run went, so that the editor
+//! can mark them in its gutter.
+//!
+//! Marks are keyed by a hash of each statement's tokens rather than by its position, so they follow statements
+//! around as the rest of the buffer is edited, and a statement only loses its mark when it's changed itself.
+//! Whitespace and comments aren't part of the hash, so reformatting a statement keeps its mark too. Hashes are
+//! persisted with the session, so they have to be stable across versions, which rules out the standard hasher.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sqlparser::{
+    dialect::GenericDialect,
+    tokenizer::{Token, Tokenizer},
+};
+
+use crate::{
+    database::{
+        stmt_manager::StatementManager,
+        types::{QueryId, QueryStatus},
+    },
+    utils::{fnv1a, FNV_OFFSET_BASIS},
+    Error,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStatus {
+    Running,
+    Success,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct ExecutionMark {
+    status: ExecutionStatus,
+    /// Unix timestamp, in milliseconds
+    executed_at: i64,
+}
+
+/// A statement of a buffer, by the lines it spans
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatementSpan {
+    pub hash: u64,
+    /// 1-based, like the editor's line numbers
+    pub start_line: u64,
+    pub end_line: u64,
+}
+
+/// A statement of a buffer that was run, with the outcome of its latest run
+#[derive(Debug, Clone, Serialize)]
+pub struct StatementMarker {
+    pub start_line: u64,
+    pub end_line: u64,
+    pub status: ExecutionStatus,
+    pub executed_at: i64,
+}
+
+/// The statements the statement manager is running, which are the only marks that can still change
+#[derive(Debug)]
+struct CurrentRun {
+    tab_id: String,
+    statements: Vec<(u64, QueryId)>,
+}
+
+/// Splits a buffer into its statements. Empty if the buffer can't be tokenized, e.g. while a string is left open.
+pub fn split_statements(text: &str) -> Vec<StatementSpan> {
+    let Ok(tokens) = Tokenizer::new(&GenericDialect {}, text).tokenize_with_location() else {
+        return Vec::new();
+    };
+
+    let mut spans = Vec::new();
+    let mut current: Option<StatementSpan> = None;
+    for token in tokens {
+        match token.token {
+            Token::Whitespace(_) => {}
+            Token::SemiColon | Token::EOF => spans.extend(current.take()),
+            other => {
+                let span = current.get_or_insert(StatementSpan {
+                    hash: FNV_OFFSET_BASIS,
+                    start_line: token.span.start.line,
+                    end_line: token.span.end.line,
+                });
+                span.hash = fnv1a(fnv1a(span.hash, other.to_string().as_bytes()), b" ");
+                span.end_line = token.span.end.line;
+            }
+        }
+    }
+    spans.extend(current);
+
+    spans
+}
+
+/// The execution marks of every buffer, by tab
+#[derive(Debug, Default)]
+pub struct ExecutionMarks {
+    buffers: DashMap<String, HashMap<u64, ExecutionMark>>,
+    current: Mutex<Option<CurrentRun>>,
+}
+
+impl ExecutionMarks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_loaded(&self, tab_id: &str) -> bool {
+        self.buffers.contains_key(tab_id)
+    }
+
+    /// Restores the marks saved by [`ExecutionMarks::to_json`], e.g. in an earlier session
+    pub fn load(&self, tab_id: &str, json: Option<&str>) -> Result<(), Error> {
+        let marks = match json {
+            Some(json) => serde_json::from_str(json)?,
+            None => HashMap::new(),
+        };
+        self.buffers.entry(tab_id.to_owned()).or_insert(marks);
+        Ok(())
+    }
+
+    /// The finished marks of a buffer, to be persisted. Running statements are left out, since they won't be
+    /// running anymore when the marks are restored.
+    pub fn to_json(&self, tab_id: &str) -> Result<String, Error> {
+        let marks: HashMap<u64, ExecutionMark> = self
+            .buffers
+            .get(tab_id)
+            .map(|marks| {
+                marks
+                    .iter()
+                    .filter(|(_, mark)| mark.status != ExecutionStatus::Running)
+                    .map(|(hash, mark)| (*hash, *mark))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(serde_json::to_string(&marks)?)
+    }
+
+    /// Marks the statements of `query` as running in the given tab. `query_ids` are the statement manager's ids
+    /// for them, in order.
+    pub fn start_run(&self, tab_id: Option<&str>, query: &str, query_ids: &[QueryId]) {
+        let mut current = self.current.lock().unwrap();
+        *current = None;
+
+        let Some(tab_id) = tab_id else {
+            return;
+        };
+        let spans = split_statements(query);
+        if spans.len() != query_ids.len() {
+            // The parser split the query differently than the tokenizer did, so statements can't be told apart
+            log::debug!(
+                "Not marking the statements of tab {tab_id}: {} spans for {} statements",
+                spans.len(),
+                query_ids.len()
+            );
+            return;
+        }
+
+        let executed_at = chrono::Utc::now().timestamp_millis();
+        let mut marks = self.buffers.entry(tab_id.to_owned()).or_default();
+        for span in &spans {
+            marks.insert(
+                span.hash,
+                ExecutionMark {
+                    status: ExecutionStatus::Running,
+                    executed_at,
+                },
+            );
+        }
+
+        *current = Some(CurrentRun {
+            tab_id: tab_id.to_owned(),
+            statements: spans
+                .iter()
+                .map(|span| span.hash)
+                .zip(query_ids.iter().copied())
+                .collect(),
+        });
+    }
+
+    /// Updates the marks of the current run with how its statements are doing. Returns the tab whose marks
+    /// changed, if any.
+    ///
+    /// With `abandon`, statements still running are unmarked, for when the statement manager is about to drop them.
+    pub fn refresh(&self, stmt_manager: &StatementManager, abandon: bool) -> Option<String> {
+        let mut current = self.current.lock().unwrap();
+        let run = current.as_ref()?;
+        let mut marks = self.buffers.get_mut(&run.tab_id)?;
+
+        let mut changed = false;
+        let mut running = false;
+        for (hash, query_id) in &run.statements {
+            if marks.get(hash).map(|mark| mark.status) != Some(ExecutionStatus::Running) {
+                continue;
+            }
+
+            let status = match stmt_manager.get_query_status(*query_id) {
+                Ok(QueryStatus::Completed) => Some(ExecutionStatus::Success),
+                Ok(QueryStatus::Error) => Some(ExecutionStatus::Error),
+                Ok(_) if !abandon => {
+                    running = true;
+                    continue;
+                }
+                // Dropped before it finished, so there's no telling how it went
+                _ => None,
+            };
+            match (status, marks.get_mut(hash)) {
+                (Some(status), Some(mark)) => mark.status = status,
+                _ => {
+                    marks.remove(hash);
+                }
+            }
+            changed = true;
+        }
+
+        let tab_id = run.tab_id.clone();
+        drop(marks);
+        if !running {
+            *current = None;
+        }
+        changed.then_some(tab_id)
+    }
+
+    /// The marked statements of a buffer, given its current text. Marks of statements that aren't in the text
+    /// anymore are dropped. Returns whether any were.
+    pub fn markers(&self, tab_id: &str, text: &str) -> (Vec<StatementMarker>, bool) {
+        let Some(mut marks) = self.buffers.get_mut(tab_id) else {
+            return (Vec::new(), false);
+        };
+        let spans = split_statements(text);
+        if spans.is_empty() && !text.trim().is_empty() {
+            // Likely mid-edit, e.g. with a string left open, so keep the marks for when it can be split again
+            return (Vec::new(), false);
+        }
+
+        let before = marks.len();
+        marks.retain(|hash, _| spans.iter().any(|span| span.hash == *hash));
+
+        let markers = spans
+            .iter()
+            .filter_map(|span| {
+                let mark = marks.get(&span.hash)?;
+                Some(StatementMarker {
+                    start_line: span.start_line,
+                    end_line: span.end_line,
+                    status: mark.status,
+                    executed_at: mark.executed_at,
+                })
+            })
+            .collect();
+        (markers, marks.len() != before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_statements() {
+        let spans = split_statements(
+            "SELECT 1;\n\n-- a comment; not a statement\nSELECT ';' AS semicolon\nFROM t;;\n\nSELECT 3",
+        );
+        let lines: Vec<_> = spans.iter().map(|s| (s.start_line, s.end_line)).collect();
+        assert_eq!(lines, [(1, 1), (4, 5), (7, 7)]);
+
+        let reformatted =
+            split_statements("select 1 ;\n/* moved */ SELECT ';'   AS semicolon FROM t");
+        assert_eq!(reformatted[0].hash, split_statements("select 1")[0].hash);
+        assert_eq!(reformatted[1].hash, spans[1].hash);
+        assert_ne!(spans[0].hash, spans[2].hash);
+
+        assert_eq!(
+            split_statements("DO $$ BEGIN PERFORM 1; END $$;\nSELECT 1").len(),
+            2
+        );
+        assert!(split_statements("SELECT 'unterminated").is_empty());
+        assert!(split_statements(" -- nothing\n").is_empty());
+    }
+
+    #[test]
+    fn keeps_marks_of_unchanged_statements() {
+        let marks = ExecutionMarks::new();
+        let text = "SELECT 1;\nSELECT 2;\nSELECT 3;";
+        marks.start_run(Some("tab"), text, &[0, 1, 2]);
+        for mark in marks.buffers.get_mut("tab").unwrap().values_mut() {
+            mark.status = ExecutionStatus::Success;
+        }
+
+        let (markers, pruned) = marks.markers("tab", text);
+        assert_eq!(markers.len(), 3);
+        assert!(!pruned);
+
+        // Edits elsewhere only move marks around
+        let edited = "-- new header\nSELECT 0;\nSELECT 1;\nSELECT 2;\nSELECT 3;";
+        let (markers, pruned) = marks.markers("tab", edited);
+        let lines: Vec<_> = markers.iter().map(|m| m.start_line).collect();
+        assert_eq!(lines, [3, 4, 5]);
+        assert!(!pruned);
+
+        // Only the changed statement loses its mark
+        let (markers, pruned) = marks.markers("tab", "SELECT 1;\nSELECT 22;\nSELECT 3;");
+        let lines: Vec<_> = markers.iter().map(|m| m.start_line).collect();
+        assert_eq!(lines, [1, 3]);
+        assert!(pruned);
+
+        // Unfinished edits keep the marks
+        let (markers, pruned) = marks.markers("tab", "SELECT 1;\nSELECT 'x");
+        assert!(markers.is_empty() && !pruned);
+        assert_eq!(marks.markers("tab", "SELECT 3").0.len(), 1);
+
+        // Running statements aren't persisted
+        marks.start_run(Some("tab"), "SELECT 4", &[0]);
+        let restored = ExecutionMarks::new();
+        restored
+            .load("tab", Some(&marks.to_json("tab").unwrap()))
+            .unwrap();
+        let (markers, _) = restored.markers("tab", "SELECT 3;\nSELECT 4");
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].status, ExecutionStatus::Success);
+    }
+}
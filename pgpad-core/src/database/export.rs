@@ -1,6 +1,7 @@
-use anyhow::{bail, ensure};
+use anyhow::{bail, ensure, Context};
 use jsax::Event;
-use std::fmt::Write;
+use rust_xlsxwriter::{Format, Note, Workbook, Worksheet};
+use std::{fmt::Write, path::Path};
 
 fn write_nested_item(
     parser: &mut jsax::Parser<'_>,
@@ -167,4 +168,303 @@ pub fn export_to_csv(columns: &str, page: &str) -> anyhow::Result<String> {
     Ok(output)
 }
 
+/// Excel's hard limit of rows per worksheet, header row included
+const XLSX_MAX_ROWS: u32 = 1_048_576;
+/// Excel's hard limit of characters in a single cell
+const XLSX_MAX_CELL_LEN: usize = 32_767;
+/// Numbers above this can't be stored in an Excel cell (an f64) without losing precision
+const XLSX_MAX_SAFE_INTEGER: u64 = 1 << 53;
+const XLSX_SHEET_NAME: &str = "Results";
+
+/// What an Excel cell is written as, from the type of its column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CellKind {
+    Boolean,
+    /// Integers, floats and decimals. Decimals come as text to keep their precision, and are written as numbers if
+    /// an Excel number holds them.
+    Number,
+    Date,
+    DateTime,
+    /// Written as text even if it looks like something else, e.g. a zip code or a date kept in a `varchar`
+    Text,
+}
+
+impl CellKind {
+    /// Maps the type names our drivers report, e.g. `int8` on Postgres, `datetime` on MySQL or `VARCHAR(20)` on
+    /// SQLite. None for types that don't map to one in particular, whose values are written as what they look like.
+    fn of_column_type(column_type: &str) -> Option<Self> {
+        let column_type = column_type.to_ascii_lowercase();
+        // Postgres names array types after their elements, e.g. `_int4`
+        if column_type.starts_with('_') {
+            return Some(Self::Text);
+        }
+        // e.g. `varchar(20)` or `bigint unsigned`
+        let base = column_type
+            .split(['(', ' '])
+            .next()
+            .unwrap_or_default()
+            .trim();
+
+        let kind = match base {
+            "bool" | "boolean" => Self::Boolean,
+            "int2" | "int4" | "int8" | "smallint" | "integer" | "int" | "bigint" | "tinyint"
+            | "mediumint" | "year" | "oid" | "float4" | "float8" | "real" | "float" | "double"
+            | "numeric" | "decimal" => Self::Number,
+            "date" => Self::Date,
+            "timestamp" | "timestamptz" | "datetime" => Self::DateTime,
+            "text" | "varchar" | "char" | "bpchar" | "character" | "name" | "citext" | "uuid"
+            | "enum" | "set" | "time" | "timetz" | "interval" | "clob" | "json" | "jsonb"
+            | "bytea" | "blob" | "binary" | "varbinary" | "bit" | "geometry" => Self::Text,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+/// Streams pages of results into an Excel workbook.
+///
+/// Worksheets are created in "constant memory" mode, so rows are flushed to disk as they're written,
+/// regardless of how big the result set is.
+pub struct XlsxExporter {
+    workbook: Workbook,
+    columns: Vec<String>,
+    /// One per column, None where its type is unknown
+    kinds: Vec<Option<CellKind>>,
+    /// Index of the worksheet currently being written to
+    sheet_idx: usize,
+    /// Next row to be written in the current worksheet
+    row: u32,
+    header_format: Format,
+    datetime_format: Format,
+    date_format: Format,
+}
+
+impl XlsxExporter {
+    /// `column_types` are in the same order as `columns`, and may be empty if unknown
+    pub fn new(columns: &str, column_types: &[Option<String>]) -> anyhow::Result<Self> {
+        let columns: Vec<String> = serde_json::from_str(columns)
+            .context(r#"Columns should be in the format `["col1", "col2", "col3"]"#)?;
+        ensure!(!columns.is_empty(), "Expected at least one column");
+        let kinds = (0..columns.len())
+            .map(|idx| {
+                column_types
+                    .get(idx)
+                    .and_then(|t| t.as_deref())
+                    .and_then(CellKind::of_column_type)
+            })
+            .collect();
+
+        let mut exporter = Self {
+            workbook: Workbook::new(),
+            columns,
+            kinds,
+            sheet_idx: 0,
+            row: 0,
+            header_format: Format::new().set_bold(),
+            datetime_format: Format::new().set_num_format("yyyy-mm-dd hh:mm:ss"),
+            date_format: Format::new().set_num_format("yyyy-mm-dd"),
+        };
+        exporter.add_sheet(XLSX_SHEET_NAME)?;
+
+        Ok(exporter)
+    }
+
+    /// Writes a page (`[[cell, ...], ...]`) to the workbook, starting new worksheets as needed
+    pub fn write_page(&mut self, page: &str) -> anyhow::Result<()> {
+        let rows: Vec<Vec<serde_json::Value>> =
+            serde_json::from_str(page).context("Expected page to be an array of rows")?;
+
+        for row in &rows {
+            if self.row == XLSX_MAX_ROWS {
+                self.split_sheet()?;
+            }
+
+            let row_idx = self.row;
+            let sheet = self.workbook.worksheet_from_index(self.sheet_idx)?;
+            for (col_idx, cell) in row.iter().enumerate() {
+                let col_idx = u16::try_from(col_idx).context("Too many columns for Excel")?;
+                let kind = self.kinds.get(usize::from(col_idx)).copied().flatten();
+                write_xlsx_cell(
+                    sheet,
+                    row_idx,
+                    col_idx,
+                    cell,
+                    kind,
+                    &self.datetime_format,
+                    &self.date_format,
+                )?;
+            }
+            self.row += 1;
+        }
+
+        Ok(())
+    }
+
+    pub fn save(mut self, path: &Path) -> anyhow::Result<()> {
+        self.workbook
+            .save(path)
+            .with_context(|| format!("Failed to save Excel file to {}", path.display()))
+    }
+
+    /// Starts a new worksheet once the current one is full.
+    /// Sheets are named `Results 1`, `Results 2`, and so on.
+    fn split_sheet(&mut self) -> anyhow::Result<()> {
+        if self.sheet_idx == 0 {
+            self.workbook
+                .worksheet_from_index(0)?
+                .set_name(format!("{XLSX_SHEET_NAME} 1"))?;
+        }
+
+        let name = format!("{XLSX_SHEET_NAME} {}", self.sheet_idx + 2);
+        self.add_sheet(&name)?;
+        self.sheet_idx += 1;
+
+        Ok(())
+    }
+
+    fn add_sheet(&mut self, name: &str) -> anyhow::Result<()> {
+        let sheet = self.workbook.add_worksheet_with_constant_memory();
+        sheet.set_name(name)?;
+        sheet.set_freeze_panes(1, 0)?;
+
+        for (col_idx, column) in self.columns.iter().enumerate() {
+            let col_idx = u16::try_from(col_idx).context("Too many columns for Excel")?;
+            sheet.write_string_with_format(0, col_idx, column, &self.header_format)?;
+        }
+        self.row = 1;
+
+        Ok(())
+    }
+}
+
+fn write_xlsx_cell(
+    sheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    cell: &serde_json::Value,
+    kind: Option<CellKind>,
+    datetime_format: &Format,
+    date_format: &Format,
+) -> anyhow::Result<()> {
+    use serde_json::Value;
+
+    match (cell, kind) {
+        (Value::Null, _) => {}
+        (Value::Bool(b), _) => {
+            sheet.write_boolean(row, col, *b)?;
+        }
+        (Value::Number(n), _) => {
+            let loses_precision = n
+                .as_i64()
+                .map(i64::unsigned_abs)
+                .or_else(|| n.as_u64())
+                .is_some_and(|int| int > XLSX_MAX_SAFE_INTEGER);
+
+            match n.as_f64() {
+                Some(float) if !loses_precision => {
+                    sheet.write_number(row, col, float)?;
+                }
+                _ => {
+                    sheet.write_string(row, col, n.to_string())?;
+                }
+            }
+        }
+        (Value::String(s), Some(CellKind::Number)) => match parse_precise_number(s) {
+            Some(number) => {
+                sheet.write_number(row, col, number)?;
+            }
+            None => write_xlsx_string(sheet, row, col, s)?,
+        },
+        (Value::String(s), Some(CellKind::DateTime)) => {
+            match parse_datetime(s).filter(excel_has_date) {
+                Some(datetime) => {
+                    sheet.write_datetime_with_format(row, col, datetime, datetime_format)?;
+                }
+                None => write_xlsx_string(sheet, row, col, s)?,
+            }
+        }
+        (Value::String(s), Some(CellKind::Date)) => match parse_date(s).filter(excel_has_date) {
+            Some(date) => {
+                sheet.write_datetime_with_format(row, col, date, date_format)?;
+            }
+            None => write_xlsx_string(sheet, row, col, s)?,
+        },
+        (Value::String(s), Some(_)) => write_xlsx_string(sheet, row, col, s)?,
+        // Without a type, strings that look like timestamps or dates are taken for them
+        (Value::String(s), None) => {
+            if let Some(datetime) = parse_datetime(s).filter(excel_has_date) {
+                sheet.write_datetime_with_format(row, col, datetime, datetime_format)?;
+            } else if let Some(date) = parse_date(s).filter(excel_has_date) {
+                sheet.write_datetime_with_format(row, col, date, date_format)?;
+            } else {
+                write_xlsx_string(sheet, row, col, s)?;
+            }
+        }
+        (Value::Array(_) | Value::Object(_), _) => {
+            write_xlsx_string(sheet, row, col, &cell.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A decimal written as text, if an Excel number holds it without losing digits
+fn parse_precise_number(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let number: f64 = s.parse().ok()?;
+    let mut digits = s.trim_start_matches(['-', '+']);
+    // Trailing zeros after the point are only the scale, e.g. of a `NUMERIC(20, 10)`
+    if digits.contains('.') && !digits.contains(['e', 'E']) {
+        digits = digits.trim_end_matches('0');
+    }
+    let significant_digits = digits
+        .trim_start_matches(['0', '.'])
+        .bytes()
+        .filter(u8::is_ascii_digit)
+        .count();
+    (number.is_finite() && significant_digits <= 15).then_some(number)
+}
+
+/// Writes a string cell, truncating it to Excel's cell limit.
+/// Truncated cells get a note explaining how much was cut off.
+fn write_xlsx_string(sheet: &mut Worksheet, row: u32, col: u16, s: &str) -> anyhow::Result<()> {
+    let char_count = s.chars().count();
+    if char_count <= XLSX_MAX_CELL_LEN {
+        sheet.write_string(row, col, s)?;
+        return Ok(());
+    }
+
+    let truncated: String = s.chars().take(XLSX_MAX_CELL_LEN).collect();
+    sheet.write_string(row, col, truncated)?;
+    sheet.insert_note(
+        row,
+        col,
+        &Note::new(format!(
+            "Value truncated from {char_count} to {XLSX_MAX_CELL_LEN} characters (Excel's per-cell limit)"
+        ))
+        .set_author("pgpad"),
+    )?;
+
+    Ok(())
+}
+
+/// Parses the timestamp formats our row writers produce
+fn parse_datetime(s: &str) -> Option<chrono::NaiveDateTime> {
+    // Postgres' TIMESTAMPTZ, which we always render in UTC
+    let s = s.strip_suffix(" UTC").unwrap_or(s);
+
+    chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f"))
+        .ok()
+}
+
+fn parse_date(s: &str) -> Option<chrono::NaiveDate> {
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+}
+
+/// Excel's dates go from 1900 to 9999, others are written as text
+fn excel_has_date(date: &impl chrono::Datelike) -> bool {
+    (1900..=9999).contains(&date.year())
+}
+
 // Tests for CSVs exports are alongside tests for the StatementManager
@@ -0,0 +1,275 @@
+//! Filtering of cached results by per-column predicates, so that rows can be found without querying the server
+//! again. Filtered results are views of the rows of the original query, see
+//! [`StatementManager::register_row_view`](super::stmt_manager::StatementManager::register_row_view).
+
+use anyhow::{ensure, Context};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::utils::numeric_value;
+
+/// Which rows of a result to keep. Columns are referred to by their index, since results can have several columns
+/// of the same name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum RowFilter {
+    /// Rows matching every filter. Without filters, every row.
+    And {
+        filters: Vec<RowFilter>,
+    },
+    /// Rows matching any of the filters. Without filters, none.
+    Or {
+        filters: Vec<RowFilter>,
+    },
+    /// Values whose text is `value`, e.g. `42` for the number 42
+    Equals {
+        column: usize,
+        value: String,
+    },
+    Contains {
+        column: usize,
+        text: String,
+        #[serde(default)]
+        case_sensitive: bool,
+    },
+    /// Values whose text the regular expression matches anywhere in
+    Regex {
+        column: usize,
+        pattern: String,
+        #[serde(default)]
+        case_sensitive: bool,
+    },
+    IsNull {
+        column: usize,
+    },
+    NotNull {
+        column: usize,
+    },
+    /// Numbers, or text that spells one, within the bounds, which are inclusive. A missing bound doesn't limit.
+    Range {
+        column: usize,
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+}
+
+/// The result of filtering a query, which is registered in the StatementManager as its own query
+#[derive(Debug, Clone, Serialize)]
+pub struct FilteredQuery {
+    pub query_id: usize,
+    pub row_count: usize,
+    /// Rows of the query that was filtered
+    pub source_row_count: usize,
+}
+
+/// A [`RowFilter`] ready to be matched against rows, with its patterns compiled
+pub enum CompiledFilter {
+    And(Vec<CompiledFilter>),
+    Or(Vec<CompiledFilter>),
+    Equals(usize, String),
+    /// The text is lowercased if the comparison ignores case
+    Contains {
+        column: usize,
+        text: String,
+        case_sensitive: bool,
+    },
+    Regex(usize, Regex),
+    IsNull(usize),
+    NotNull(usize),
+    Range {
+        column: usize,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+}
+
+impl CompiledFilter {
+    /// Fails if the filter refers to a column the results don't have, or if a pattern is invalid
+    pub fn new(filter: &RowFilter, column_count: usize) -> anyhow::Result<Self> {
+        let column = |column: usize| -> anyhow::Result<usize> {
+            ensure!(column < column_count, "The results have no column {column}");
+            Ok(column)
+        };
+
+        Ok(match filter {
+            RowFilter::And { filters } => Self::And(
+                filters
+                    .iter()
+                    .map(|filter| Self::new(filter, column_count))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            RowFilter::Or { filters } => Self::Or(
+                filters
+                    .iter()
+                    .map(|filter| Self::new(filter, column_count))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            RowFilter::Equals { column: idx, value } => Self::Equals(column(*idx)?, value.clone()),
+            RowFilter::Contains {
+                column: idx,
+                text,
+                case_sensitive,
+            } => Self::Contains {
+                column: column(*idx)?,
+                text: if *case_sensitive {
+                    text.clone()
+                } else {
+                    text.to_lowercase()
+                },
+                case_sensitive: *case_sensitive,
+            },
+            RowFilter::Regex {
+                column: idx,
+                pattern,
+                case_sensitive,
+            } => Self::Regex(
+                column(*idx)?,
+                RegexBuilder::new(pattern)
+                    .case_insensitive(!case_sensitive)
+                    .build()
+                    .with_context(|| format!("Invalid regular expression {pattern}"))?,
+            ),
+            RowFilter::IsNull { column: idx } => Self::IsNull(column(*idx)?),
+            RowFilter::NotNull { column: idx } => Self::NotNull(column(*idx)?),
+            RowFilter::Range {
+                column: idx,
+                min,
+                max,
+            } => Self::Range {
+                column: column(*idx)?,
+                min: *min,
+                max: *max,
+            },
+        })
+    }
+
+    pub fn matches(&self, row: &[Value]) -> bool {
+        let value = |column: &usize| row.get(*column).unwrap_or(&Value::Null);
+        match self {
+            Self::And(filters) => filters.iter().all(|filter| filter.matches(row)),
+            Self::Or(filters) => filters.iter().any(|filter| filter.matches(row)),
+            Self::Equals(column, expected) => {
+                text_of(value(column)).is_some_and(|text| text == *expected)
+            }
+            Self::Contains {
+                column,
+                text,
+                case_sensitive,
+            } => text_of(value(column)).is_some_and(|value| {
+                if *case_sensitive {
+                    value.contains(text.as_str())
+                } else {
+                    value.to_lowercase().contains(text.as_str())
+                }
+            }),
+            Self::Regex(column, regex) => {
+                text_of(value(column)).is_some_and(|text| regex.is_match(&text))
+            }
+            Self::IsNull(column) => value(column).is_null(),
+            Self::NotNull(column) => !value(column).is_null(),
+            Self::Range { column, min, max } => {
+                numeric_value(value(column)).is_some_and(|number| {
+                    min.is_none_or(|min| number >= min) && max.is_none_or(|max| number <= max)
+                })
+            }
+        }
+    }
+}
+
+/// The text of a value as the results show it. None for nulls, which no text matches.
+fn text_of(value: &Value) -> Option<std::borrow::Cow<'_, str>> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.as_str().into()),
+        other => Some(other.to_string().into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn rows() -> Vec<Vec<Value>> {
+        vec![
+            vec![json!(1), json!("ana@gmail.com"), json!("12.50")],
+            vec![json!(2), json!("bo@example.org"), Value::Null],
+            vec![json!(3), json!("CY@GMAIL.COM"), json!("300")],
+            vec![json!(4), Value::Null, json!(7)],
+        ]
+    }
+
+    fn matching(filter: serde_json::Value) -> Vec<i64> {
+        let filter: RowFilter = serde_json::from_value(filter).unwrap();
+        let filter = CompiledFilter::new(&filter, 3).unwrap();
+        rows()
+            .iter()
+            .filter(|row| filter.matches(row))
+            .map(|row| row[0].as_i64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn matches_column_predicates() {
+        assert_eq!(
+            matching(json!({"op": "contains", "column": 1, "text": "gmail.com"})),
+            [1, 3]
+        );
+        assert_eq!(
+            matching(
+                json!({"op": "contains", "column": 1, "text": "gmail.com", "case_sensitive": true})
+            ),
+            [1]
+        );
+        assert_eq!(
+            matching(json!({"op": "equals", "column": 0, "value": "2"})),
+            [2]
+        );
+        assert_eq!(
+            matching(json!({"op": "regex", "column": 1, "pattern": "^[a-z]+@gmail"})),
+            [1, 3]
+        );
+        assert_eq!(matching(json!({"op": "is_null", "column": 2})), [2]);
+        assert_eq!(matching(json!({"op": "not_null", "column": 1})), [1, 2, 3]);
+        // Numbers sent as text are compared by value
+        assert_eq!(
+            matching(json!({"op": "range", "column": 2, "min": 7, "max": 100})),
+            [1, 4]
+        );
+        assert_eq!(
+            matching(json!({"op": "range", "column": 2, "min": 100})),
+            [3]
+        );
+    }
+
+    #[test]
+    fn combines_predicates() {
+        let gmail = json!({"op": "contains", "column": 1, "text": "gmail.com"});
+        let cheap = json!({"op": "range", "column": 2, "max": 20});
+        assert_eq!(
+            matching(json!({"op": "and", "filters": [gmail, cheap]})),
+            [1]
+        );
+        assert_eq!(
+            matching(json!({"op": "or", "filters": [gmail, cheap]})),
+            [1, 3, 4]
+        );
+        assert_eq!(matching(json!({"op": "and", "filters": []})), [1, 2, 3, 4]);
+        assert!(matching(json!({"op": "or", "filters": []})).is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_filters() {
+        let filter: RowFilter =
+            serde_json::from_value(json!({"op": "is_null", "column": 3})).unwrap();
+        assert!(CompiledFilter::new(&filter, 3).is_err());
+
+        let filter: RowFilter =
+            serde_json::from_value(json!({"op": "regex", "column": 0, "pattern": "("})).unwrap();
+        assert!(CompiledFilter::new(&filter, 3).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+//! Formatting of SQL scripts. Statements are formatted one by one, and whatever's between them (semicolons, blank
+//! lines, comments) is kept as it is, so that a script keeps its layout. Statements that can't be parsed in the
+//! script's dialect are kept as they are too, since formatting them could break syntax the formatter doesn't know.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use sqlformat::{FormatOptions, Indent, QueryParams};
+use sqlparser::{
+    dialect::Dialect,
+    parser::Parser,
+    tokenizer::{Token, TokenWithSpan, Tokenizer, Whitespace},
+};
+
+use crate::database::{
+    parser::{self, ByteOffsets},
+    types::Database,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeywordCase {
+    Upper,
+    Lower,
+    /// Keywords are left as they're written
+    #[default]
+    Preserve,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommaStyle {
+    /// `a,` then `b` on the next line
+    #[default]
+    Trailing,
+    /// `a` then `, b` on the next line
+    Leading,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FormatSettings {
+    pub keyword_case: KeywordCase,
+    /// Spaces per indentation level, unless tabs are used
+    pub indent_width: u8,
+    pub use_tabs: bool,
+    pub comma_style: CommaStyle,
+}
+
+impl Default for FormatSettings {
+    fn default() -> Self {
+        Self {
+            keyword_case: KeywordCase::Preserve,
+            indent_width: 2,
+            use_tabs: false,
+            comma_style: CommaStyle::Trailing,
+        }
+    }
+}
+
+/// Why a statement of the script was left as it is
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FormatWarning {
+    /// Line of the statement in the script, from 1
+    pub line: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FormattedSql {
+    pub text: String,
+    pub warnings: Vec<FormatWarning>,
+}
+
+fn format_options(database: Option<Database>, settings: &FormatSettings) -> FormatOptions<'static> {
+    FormatOptions {
+        indent: if settings.use_tabs {
+            Indent::Tabs
+        } else {
+            Indent::Spaces(settings.indent_width)
+        },
+        uppercase: match settings.keyword_case {
+            KeywordCase::Upper => Some(true),
+            KeywordCase::Lower => Some(false),
+            KeywordCase::Preserve => None,
+        },
+        dialect: match database {
+            Some(Database::Postgres) => sqlformat::Dialect::PostgreSql,
+            _ => sqlformat::Dialect::Generic,
+        },
+        ..Default::default()
+    }
+}
+
+fn is_whitespace(token: &TokenWithSpan) -> bool {
+    matches!(token.token, Token::Whitespace(_))
+}
+
+/// A statement of the script, without its semicolon nor the comments around it
+struct StatementSpan {
+    bytes: Range<usize>,
+    line: u64,
+}
+
+/// Splits the tokens of a script at its semicolons
+fn statement_spans(tokens: &[TokenWithSpan], offsets: &ByteOffsets) -> Vec<StatementSpan> {
+    let mut spans = vec![];
+    let mut current: Option<(&TokenWithSpan, &TokenWithSpan)> = None;
+
+    for token in tokens {
+        if token.token == Token::SemiColon || token.token == Token::EOF {
+            if let Some((first, last)) = current.take() {
+                spans.push(StatementSpan {
+                    bytes: offsets.of(first.span.start)..offsets.of(last.span.end),
+                    line: first.span.start.line,
+                });
+            }
+        } else if !is_whitespace(token) {
+            current = Some(match current {
+                Some((first, _)) => (first, token),
+                None => (token, token),
+            });
+        }
+    }
+    if let Some((first, last)) = current {
+        spans.push(StatementSpan {
+            bytes: offsets.of(first.span.start)..offsets.of(last.span.end),
+            line: first.span.start.line,
+        });
+    }
+
+    spans
+}
+
+/// Moves the commas ending lines to the start of the next ones. Commas in literals and comments are tokens of their
+/// own, so they're left alone.
+fn leading_commas(dialect: &dyn Dialect, sql: &str) -> String {
+    let Ok(tokens) = Tokenizer::new(dialect, sql).tokenize_with_location() else {
+        return sql.to_owned();
+    };
+    let offsets = ByteOffsets::new(sql);
+
+    // Commas to remove, and where to insert them back
+    let mut moves = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        if token.token != Token::Comma {
+            continue;
+        }
+        let mut rest = tokens[i + 1..].iter().skip_while(|t| {
+            matches!(
+                t.token,
+                Token::Whitespace(Whitespace::Space | Whitespace::Tab)
+            )
+        });
+        if rest.next().map(|t| &t.token) != Some(&Token::Whitespace(Whitespace::Newline)) {
+            continue;
+        }
+        // A comment after the comma stays where it is, and so does the comma
+        let Some(next) = rest.find(|t| {
+            !matches!(
+                t.token,
+                Token::Whitespace(Whitespace::Space | Whitespace::Tab | Whitespace::Newline)
+            )
+        }) else {
+            continue;
+        };
+        if is_whitespace(next) || next.token == Token::EOF {
+            continue;
+        }
+        moves.push((
+            offsets.of(token.span.start)..offsets.of(token.span.end),
+            offsets.of(next.span.start),
+        ));
+    }
+
+    let mut result = String::with_capacity(sql.len() + moves.len());
+    let mut copied = 0;
+    for (comma, insert_at) in moves {
+        result.push_str(sql[copied..comma.start].trim_end_matches([' ', '\t']));
+        result.push_str(&sql[comma.end..insert_at]);
+        result.push_str(", ");
+        copied = insert_at;
+    }
+    result.push_str(&sql[copied..]);
+    result
+}
+
+fn format_statement(
+    database: Option<Database>,
+    dialect: &dyn Dialect,
+    settings: &FormatSettings,
+    statement: &str,
+) -> Result<String, String> {
+    Parser::parse_sql(dialect, statement).map_err(|e| e.to_string())?;
+
+    let formatted = sqlformat::format(
+        statement,
+        &QueryParams::None,
+        &format_options(database, settings),
+    );
+    let formatted = formatted.trim();
+    Ok(match settings.comma_style {
+        CommaStyle::Trailing => formatted.to_owned(),
+        CommaStyle::Leading => leading_commas(dialect, formatted),
+    })
+}
+
+/// Formats the statements of `script` that can be parsed in the dialect of `database`, or in a generic one
+pub fn format_script(
+    script: &str,
+    database: Option<Database>,
+    settings: &FormatSettings,
+) -> FormattedSql {
+    let dialect = parser::sql_dialect(database);
+    let tokens = match Tokenizer::new(dialect.as_ref(), script).tokenize_with_location() {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            return FormattedSql {
+                text: script.to_owned(),
+                warnings: vec![FormatWarning {
+                    line: e.location.line,
+                    message: format!("The script was left as it is: {}", e.message),
+                }],
+            }
+        }
+    };
+    let offsets = ByteOffsets::new(script);
+
+    let mut text = String::with_capacity(script.len());
+    let mut warnings = vec![];
+    let mut copied = 0;
+    for span in statement_spans(&tokens, &offsets) {
+        text.push_str(&script[copied..span.bytes.start]);
+        let statement = &script[span.bytes.clone()];
+        match format_statement(database, dialect.as_ref(), settings, statement) {
+            Ok(formatted) => text.push_str(&formatted),
+            Err(message) => {
+                text.push_str(statement);
+                warnings.push(FormatWarning {
+                    line: span.line,
+                    message: format!("The statement was left as it is: {message}"),
+                });
+            }
+        }
+        copied = span.bytes.end;
+    }
+    text.push_str(&script[copied..]);
+
+    FormattedSql { text, warnings }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::dialect::GenericDialect;
+
+    use super::*;
+
+    #[test]
+    fn keeps_statement_boundaries_and_unparsable_statements() {
+        let script = "-- orders\nselect o.id::text from orders o;\n\n\n\
+                      SELECT * FROM a, b WHERE a.id = b.id(+);  -- legacy join\n\n\
+                      select 1 /* one */;";
+        let formatted = format_script(script, Some(Database::Postgres), &FormatSettings::default());
+
+        assert!(formatted.text.starts_with("-- orders\n"));
+        assert!(formatted.text.contains("::"));
+        assert!(formatted
+            .text
+            .contains(";\n\n\nSELECT * FROM a, b WHERE a.id = b.id(+);  -- legacy join\n\n"));
+        assert!(formatted.text.contains("/* one */"));
+        assert!(formatted.text.ends_with(';'));
+
+        let [warning] = &formatted.warnings[..] else {
+            panic!("Expected one warning, got {:?}", formatted.warnings);
+        };
+        assert_eq!(warning.line, 5);
+    }
+
+    #[test]
+    fn moves_commas_to_line_starts() {
+        let sql = "SELECT\n  a,\n  'x,\ny' AS b, -- note,\n  c,\n  d\nFROM t";
+        assert_eq!(
+            leading_commas(&GenericDialect {}, sql),
+            "SELECT\n  a\n  , 'x,\ny' AS b, -- note,\n  c\n  , d\nFROM t"
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use anyhow::{ensure, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+
+use crate::utils::numeric_value;
+
+/// Grouping by a high-cardinality column is allowed, but we stop creating groups past this point
+pub const MAX_GROUPS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregateFn {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFn {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Aggregate {
+    pub column: String,
+    #[serde(rename = "fn")]
+    pub function: AggregateFn,
+}
+
+/// The result of grouping a query, which is registered in the StatementManager as its own query
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupedQuery {
+    pub query_id: usize,
+    pub group_count: usize,
+    /// Rows whose group key showed up after `MAX_GROUPS` groups were created were left out
+    pub truncated_groups: bool,
+}
+
+pub struct GroupedRows {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    pub truncated_groups: bool,
+}
+
+/// Running state of a single aggregate within a single group
+#[derive(Default)]
+struct Accumulator {
+    count: usize,
+    sum: f64,
+    /// Sum as an integer, while every value seen so far has been an integer
+    int_sum: Option<i64>,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Accumulator {
+    fn new() -> Self {
+        Self {
+            int_sum: Some(0),
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, function: AggregateFn, value: &Value) {
+        if value.is_null() {
+            return;
+        }
+        if function == AggregateFn::Count {
+            self.count += 1;
+            return;
+        }
+
+        let Some(number) = numeric_value(value) else {
+            return;
+        };
+        self.count += 1;
+        self.sum += number;
+        self.int_sum = match (self.int_sum, value.as_i64()) {
+            (Some(sum), Some(int)) => sum.checked_add(int),
+            _ => None,
+        };
+        self.min = Some(self.min.map_or(number, |min| min.min(number)));
+        self.max = Some(self.max.map_or(number, |max| max.max(number)));
+    }
+
+    fn finish(&self, function: AggregateFn) -> Value {
+        match function {
+            AggregateFn::Count => self.count.into(),
+            _ if self.count == 0 => Value::Null,
+            AggregateFn::Sum => match self.int_sum {
+                Some(sum) => sum.into(),
+                None => float(self.sum),
+            },
+            AggregateFn::Avg => float(self.sum / self.count as f64),
+            AggregateFn::Min => self.min.map_or(Value::Null, float),
+            AggregateFn::Max => self.max.map_or(Value::Null, float),
+        }
+    }
+}
+
+fn float(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 {
+        (n as i64).into()
+    } else {
+        Number::from_f64(n).map_or(Value::Null, Value::Number)
+    }
+}
+
+fn column_index(columns: &[String], name: &str) -> anyhow::Result<usize> {
+    columns
+        .iter()
+        .position(|column| column == name)
+        .with_context(|| format!("Column {name} not found in the results"))
+}
+
+/// Groups rows by `group_columns`, in the order each group was first seen.
+///
+/// NULLs group together, and their key stays `null`, so they can't be confused with a `'NULL'` string.
+pub fn group_rows(
+    columns: &[String],
+    rows: impl IntoIterator<Item = Vec<Value>>,
+    group_columns: &[String],
+    aggregates: &[Aggregate],
+    max_groups: usize,
+) -> anyhow::Result<GroupedRows> {
+    ensure!(
+        (1..=2).contains(&group_columns.len()),
+        "Expected one or two columns to group by"
+    );
+
+    let group_idxs = group_columns
+        .iter()
+        .map(|name| column_index(columns, name))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let aggregate_idxs = aggregates
+        .iter()
+        .map(|aggregate| match aggregate.function {
+            AggregateFn::Count if aggregate.column == "*" => Ok(None),
+            _ => column_index(columns, &aggregate.column).map(Some),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // Values don't implement Hash, so groups are looked up by their keys' JSON
+    let mut group_lookup: HashMap<Vec<String>, usize> = HashMap::new();
+    let mut groups: Vec<(Vec<Value>, Vec<Accumulator>)> = Vec::new();
+    let mut truncated_groups = false;
+
+    for row in rows {
+        let key: Vec<Value> = group_idxs
+            .iter()
+            .map(|&idx| row.get(idx).cloned().unwrap_or(Value::Null))
+            .collect();
+        let lookup_key: Vec<String> = key.iter().map(Value::to_string).collect();
+
+        let group_idx = match group_lookup.get(&lookup_key) {
+            Some(&idx) => idx,
+            None if groups.len() >= max_groups => {
+                truncated_groups = true;
+                continue;
+            }
+            None => {
+                group_lookup.insert(lookup_key, groups.len());
+                groups.push((key, aggregates.iter().map(|_| Accumulator::new()).collect()));
+                groups.len() - 1
+            }
+        };
+
+        let accumulators = &mut groups[group_idx].1;
+        for ((aggregate, idx), accumulator) in aggregates
+            .iter()
+            .zip(&aggregate_idxs)
+            .zip(accumulators.iter_mut())
+        {
+            match idx {
+                Some(idx) => {
+                    accumulator.add(aggregate.function, row.get(*idx).unwrap_or(&Value::Null))
+                }
+                // count(*)
+                None => accumulator.count += 1,
+            }
+        }
+    }
+
+    let mut out_columns = group_columns.to_vec();
+    for aggregate in aggregates {
+        out_columns.push(format!(
+            "{}({})",
+            aggregate.function.name(),
+            aggregate.column
+        ));
+    }
+
+    let rows = groups
+        .into_iter()
+        .map(|(mut key, accumulators)| {
+            for (aggregate, accumulator) in aggregates.iter().zip(&accumulators) {
+                key.push(accumulator.finish(aggregate.function));
+            }
+            key
+        })
+        .collect();
+
+    Ok(GroupedRows {
+        columns: out_columns,
+        rows,
+        truncated_groups,
+    })
+}
+
+/// Parses a page produced by our row writers
+pub fn parse_page(page: &str) -> anyhow::Result<Vec<Vec<Value>>> {
+    serde_json::from_str(page).context("Expected page to be an array of rows")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn columns() -> Vec<String> {
+        ["kind", "region", "price"].map(String::from).to_vec()
+    }
+
+    fn rows() -> Vec<Vec<Value>> {
+        vec![
+            vec![json!("fruit"), json!("north"), json!(1)],
+            vec![json!("fruit"), json!("south"), json!("2.5")],
+            vec![json!(null), json!("north"), json!(4)],
+            vec![json!("NULL"), json!("north"), json!("oops")],
+            vec![json!("fruit"), json!("north"), json!(null)],
+            vec![json!(null), json!("south"), json!(6)],
+        ]
+    }
+
+    #[test]
+    fn groups_with_aggregates() {
+        let aggregates: Vec<Aggregate> = serde_json::from_value(json!([
+            {"column": "*", "fn": "count"},
+            {"column": "price", "fn": "count"},
+            {"column": "price", "fn": "sum"},
+            {"column": "price", "fn": "avg"},
+            {"column": "price", "fn": "min"},
+            {"column": "price", "fn": "max"},
+        ]))
+        .unwrap();
+
+        let grouped = group_rows(
+            &columns(),
+            rows(),
+            &["kind".to_owned()],
+            &aggregates,
+            MAX_GROUPS,
+        )
+        .unwrap();
+
+        assert_eq!(
+            grouped.columns,
+            [
+                "kind",
+                "count(*)",
+                "count(price)",
+                "sum(price)",
+                "avg(price)",
+                "min(price)",
+                "max(price)"
+            ]
+        );
+        assert_eq!(
+            grouped.rows,
+            vec![
+                vec![
+                    json!("fruit"),
+                    json!(3),
+                    json!(2),
+                    json!(3.5),
+                    json!(1.75),
+                    json!(1),
+                    json!(2.5)
+                ],
+                vec![
+                    json!(null),
+                    json!(2),
+                    json!(2),
+                    json!(10),
+                    json!(5),
+                    json!(4),
+                    json!(6)
+                ],
+                vec![
+                    json!("NULL"),
+                    json!(1),
+                    json!(1),
+                    json!(null),
+                    json!(null),
+                    json!(null),
+                    json!(null)
+                ],
+            ]
+        );
+        assert!(!grouped.truncated_groups);
+    }
+
+    #[test]
+    fn truncates_groups() {
+        let grouped = group_rows(
+            &columns(),
+            rows(),
+            &["kind".to_owned(), "region".to_owned()],
+            &[],
+            2,
+        )
+        .unwrap();
+
+        assert_eq!(
+            grouped.rows,
+            vec![
+                vec![json!("fruit"), json!("north")],
+                vec![json!("fruit"), json!("south")]
+            ]
+        );
+        assert!(grouped.truncated_groups);
+    }
+
+    #[test]
+    fn rejects_unknown_columns() {
+        assert!(group_rows(&columns(), rows(), &["nope".to_owned()], &[], MAX_GROUPS).is_err());
+        assert!(group_rows(&columns(), rows(), &[], &[], MAX_GROUPS).is_err());
+    }
+}
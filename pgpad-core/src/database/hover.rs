@@ -0,0 +1,503 @@
+//! What the editor shows when hovering a table or column name: the table it is, or the columns it can be. Names
+//! are resolved against the tables the statement reads from, with their aliases, using tokens only so that it keeps
+//! working on statements that are still being written.
+
+use std::ops::Range;
+
+use serde::Serialize;
+use sqlparser::{
+    dialect::Dialect,
+    keywords::Keyword,
+    tokenizer::{Token, Tokenizer, Word},
+};
+
+use crate::database::{
+    parser::ByteOffsets,
+    types::{DatabaseSchema, TableInfo},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HoverTarget {
+    Table {
+        schema: String,
+        name: String,
+        /// What the table is called in the statement, if it's aliased
+        alias: Option<String>,
+        column_count: usize,
+        /// The columns are yet to be loaded, so the count isn't known yet
+        columns_pending: bool,
+    },
+    Column {
+        name: String,
+        /// Every column the name can refer to, more than one if it's ambiguous
+        candidates: Vec<ColumnCandidate>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnCandidate {
+    pub schema: String,
+    pub table: String,
+    pub alias: Option<String>,
+    pub data_type: String,
+    pub is_nullable: bool,
+    pub default_value: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Hover {
+    /// The hovered name, in UTF-16 code units like editor positions
+    pub from: usize,
+    pub to: usize,
+    #[serde(flatten)]
+    pub target: HoverTarget,
+}
+
+/// A table the statement reads from or writes to, by the indices of its tokens
+pub(crate) struct TableRef<'a> {
+    /// The keyword the table follows, e.g. FROM
+    pub(crate) keyword: Keyword,
+    /// Index of the first part of its name
+    pub(crate) start: usize,
+    pub(crate) name: Vec<&'a Word>,
+    pub(crate) alias: Option<(usize, &'a Word)>,
+    /// A set-returning function like `generate_series(...)` rather than a table
+    pub(crate) function: bool,
+}
+
+impl TableRef<'_> {
+    /// The bytes of the table's name in the query
+    pub(crate) fn name_bytes(&self, tokens: &[(Token, Range<usize>)]) -> Range<usize> {
+        let end = self.start + 2 * (self.name.len() - 1);
+        tokens[self.start].1.start..tokens[end].1.end
+    }
+}
+
+pub(crate) fn word(tokens: &[(Token, Range<usize>)], idx: usize) -> Option<&Word> {
+    match tokens.get(idx) {
+        Some((Token::Word(word), _)) => Some(word),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_period(tokens: &[(Token, Range<usize>)], idx: usize) -> bool {
+    matches!(tokens.get(idx), Some((Token::Period, _)))
+}
+
+/// Unquoted names are compared like the database folds them, quoted ones as they are
+pub(crate) fn same_name(word: &Word, name: &str) -> bool {
+    if word.quote_style.is_some() {
+        word.value == name
+    } else {
+        word.value.eq_ignore_ascii_case(name)
+    }
+}
+
+/// The dotted name starting at `start`, and the index after it
+pub(crate) fn object_name(
+    tokens: &[(Token, Range<usize>)],
+    start: usize,
+) -> Option<(Vec<&Word>, usize)> {
+    let mut parts = vec![word(tokens, start)?];
+    let mut idx = start + 1;
+    while is_period(tokens, idx) {
+        let Some(part) = word(tokens, idx + 1) else {
+            break;
+        };
+        parts.push(part);
+        idx += 2;
+    }
+    Some((parts, idx))
+}
+
+/// Whether the token at `idx` is within the parentheses of a function call, like the FROM of
+/// `extract(year FROM ...)`, rather than those of a subquery
+fn in_function_call(tokens: &[(Token, Range<usize>)], idx: usize) -> bool {
+    let mut depth = 0;
+    for open in (0..idx).rev() {
+        match tokens[open].0 {
+            Token::RParen => depth += 1,
+            Token::LParen if depth > 0 => depth -= 1,
+            Token::LParen => {
+                return !word(tokens, open + 1)
+                    .is_some_and(|w| matches!(w.keyword, Keyword::SELECT | Keyword::WITH));
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+pub(crate) fn table_refs(tokens: &[(Token, Range<usize>)]) -> Vec<TableRef<'_>> {
+    let mut refs = vec![];
+
+    for (idx, (token, _)) in tokens.iter().enumerate() {
+        let Token::Word(keyword) = token else {
+            continue;
+        };
+        if !matches!(
+            keyword.keyword,
+            Keyword::FROM | Keyword::JOIN | Keyword::UPDATE | Keyword::INTO
+        ) {
+            continue;
+        }
+        // `IS DISTINCT FROM` compares values
+        if keyword.keyword == Keyword::FROM
+            && (idx > 0 && word(tokens, idx - 1).is_some_and(|w| w.keyword == Keyword::DISTINCT)
+                || in_function_call(tokens, idx))
+        {
+            continue;
+        }
+
+        let mut start = idx + 1;
+        while let Some((name, mut next)) = object_name(tokens, start) {
+            let function = keyword.keyword != Keyword::INTO
+                && matches!(tokens.get(next), Some((Token::LParen, _)));
+            if function {
+                next = skip_parens(tokens, next);
+            }
+            if word(tokens, next).is_some_and(|w| w.keyword == Keyword::AS) {
+                next += 1;
+            }
+            let alias = word(tokens, next)
+                .filter(|w| w.quote_style.is_some() || w.keyword == Keyword::NoKeyword)
+                .map(|alias| (next, alias));
+            if alias.is_some() {
+                next += 1;
+            }
+            refs.push(TableRef {
+                keyword: keyword.keyword,
+                start,
+                name,
+                alias,
+                function,
+            });
+
+            // Only FROM lists tables with commas
+            if keyword.keyword != Keyword::FROM
+                || !matches!(tokens.get(next), Some((Token::Comma, _)))
+            {
+                break;
+            }
+            start = next + 1;
+        }
+    }
+
+    refs
+}
+
+/// The index after the parenthesis closing the one at `open`
+pub(crate) fn skip_parens(tokens: &[(Token, Range<usize>)], open: usize) -> usize {
+    let mut depth = 0;
+    for (idx, (token, _)) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return idx + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+/// The table called `name` in the schema, qualified or not
+pub(crate) fn find_table<'a>(schema: &'a DatabaseSchema, name: &[&Word]) -> Option<&'a TableInfo> {
+    let (table, qualifier) = name.split_last()?;
+    schema.tables.iter().find(|t| {
+        same_name(table, &t.name) && qualifier.last().is_none_or(|s| same_name(s, &t.schema))
+    })
+}
+
+fn table_target(table: &TableInfo, alias: Option<&Word>) -> HoverTarget {
+    HoverTarget::Table {
+        schema: table.schema.clone(),
+        name: table.name.clone(),
+        alias: alias.map(|alias| alias.value.clone()),
+        column_count: table.columns.len(),
+        columns_pending: table.columns_pending,
+    }
+}
+
+/// Whether `qualifier` is how the statement refers to `table_ref`: its alias if it has one, or its name
+pub(crate) fn qualifies(table_ref: &TableRef, qualifier: &[&Word]) -> bool {
+    match (table_ref.alias, qualifier) {
+        (Some((_, alias)), [name]) => same_name(name, &alias.value),
+        (Some(_), _) => false,
+        (None, _) => {
+            qualifier.len() <= table_ref.name.len()
+                && table_ref.name[table_ref.name.len() - qualifier.len()..]
+                    .iter()
+                    .zip(qualifier)
+                    .all(|(part, name)| same_name(name, &part.value))
+        }
+    }
+}
+
+fn resolve(
+    schema: &DatabaseSchema,
+    tokens: &[(Token, Range<usize>)],
+    hovered: usize,
+) -> Option<HoverTarget> {
+    let refs = table_refs(tokens);
+
+    // Hovering an alias where it's declared
+    if let Some(table_ref) = refs
+        .iter()
+        .find(|r| r.alias.is_some_and(|(idx, _)| idx == hovered))
+    {
+        let table = find_table(schema, &table_ref.name)?;
+        return Some(table_target(table, table_ref.alias.map(|(_, alias)| alias)));
+    }
+
+    let mut start = hovered;
+    while start >= 2 && is_period(tokens, start - 1) && word(tokens, start - 2).is_some() {
+        start -= 2;
+    }
+    let (parts, _) = object_name(tokens, start)?;
+    let position = (hovered - start) / 2;
+
+    // Hovering a table where it's declared, where the other parts are its schema and database
+    if let Some(table_ref) = refs.iter().find(|r| r.start == start) {
+        if position + 1 != parts.len() {
+            return None;
+        }
+        let table = find_table(schema, &table_ref.name)?;
+        return Some(table_target(table, table_ref.alias.map(|(_, alias)| alias)));
+    }
+
+    let (column, qualifier) = parts.split_last()?;
+    if position + 1 < parts.len() {
+        // Hovering the table a column is qualified with
+        if position + 2 != parts.len() {
+            return None;
+        }
+        return match refs.iter().find(|r| qualifies(r, qualifier)) {
+            Some(table_ref) => Some(table_target(
+                find_table(schema, &table_ref.name)?,
+                table_ref.alias.map(|(_, alias)| alias),
+            )),
+            None => Some(table_target(find_table(schema, qualifier)?, None)),
+        };
+    }
+
+    let candidates: Vec<_> = refs
+        .iter()
+        .filter(|r| !r.function && (qualifier.is_empty() || qualifies(r, qualifier)))
+        .filter_map(|r| Some((r, find_table(schema, &r.name)?)))
+        .flat_map(|(r, table)| {
+            table
+                .columns
+                .iter()
+                .filter(|c| same_name(column, &c.name))
+                .map(move |c| ColumnCandidate {
+                    schema: table.schema.clone(),
+                    table: table.name.clone(),
+                    alias: r.alias.map(|(_, alias)| alias.value.clone()),
+                    data_type: c.data_type.clone(),
+                    is_nullable: c.is_nullable,
+                    default_value: c.default_value.clone(),
+                })
+        })
+        .collect();
+    if !candidates.is_empty() {
+        return Some(HoverTarget::Column {
+            name: column.value.clone(),
+            candidates,
+        });
+    }
+
+    // A table name outside of FROM and JOIN, e.g. in a statement the tokens aren't understood for
+    if qualifier.is_empty() {
+        return Some(table_target(find_table(schema, &parts)?, None));
+    }
+    None
+}
+
+pub(crate) fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+/// The tokens of a statement, without whitespace, with the bytes of the query they were read from
+pub(crate) type StatementTokens = Vec<(Token, Range<usize>)>;
+
+/// The tokens of `query` without whitespace, or nothing if it can't be tokenized
+pub(crate) fn tokens(dialect: &dyn Dialect, query: &str) -> Option<StatementTokens> {
+    let offsets = ByteOffsets::new(query);
+    let tokens = Tokenizer::new(dialect, query)
+        .tokenize_with_location()
+        .ok()?
+        .into_iter()
+        .filter(|t| !matches!(t.token, Token::Whitespace(_)))
+        .map(|t| {
+            let bytes = offsets.of(t.span.start)..offsets.of(t.span.end);
+            (t.token, bytes)
+        })
+        .collect();
+    Some(tokens)
+}
+
+/// The byte of `query` at `position`, a position in UTF-16 code units like editor positions
+pub(crate) fn byte_offset(query: &str, position: usize) -> usize {
+    let mut utf16 = 0;
+    query
+        .char_indices()
+        .find(|(_, c)| {
+            utf16 += c.len_utf16();
+            utf16 > position
+        })
+        .map_or(query.len(), |(idx, _)| idx)
+}
+
+/// The statement of the name at `position`, a position in UTF-16 code units like editor positions, and the index
+/// of the name in it
+pub(crate) fn statement_at(
+    dialect: &dyn Dialect,
+    query: &str,
+    position: usize,
+) -> Option<(StatementTokens, usize)> {
+    let cursor = byte_offset(query, position);
+
+    let mut tokens = tokens(dialect, query)?;
+
+    // The name the cursor is in, or right after
+    let hovered = tokens.iter().rposition(|(token, bytes)| {
+        matches!(token, Token::Word(_)) && bytes.start <= cursor && cursor <= bytes.end
+    })?;
+    let statement_start = tokens[..hovered]
+        .iter()
+        .rposition(|(token, _)| *token == Token::SemiColon)
+        .map_or(0, |idx| idx + 1);
+    let statement_end = tokens[hovered..]
+        .iter()
+        .position(|(token, _)| *token == Token::SemiColon)
+        .map_or(tokens.len(), |idx| hovered + idx);
+    tokens.truncate(statement_end);
+    tokens.drain(..statement_start);
+    Some((tokens, hovered - statement_start))
+}
+
+/// What's under `position` in `query`, a position in UTF-16 code units like editor positions
+pub fn hover_at(
+    dialect: &dyn Dialect,
+    schema: &DatabaseSchema,
+    query: &str,
+    position: usize,
+) -> Option<Hover> {
+    let (statement, hovered) = statement_at(dialect, query, position)?;
+    let target = resolve(schema, &statement, hovered)?;
+    let bytes = &statement[hovered].1;
+    Some(Hover {
+        from: utf16_len(&query[..bytes.start]),
+        to: utf16_len(&query[..bytes.end]),
+        target,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::dialect::PostgreSqlDialect;
+
+    use super::*;
+    use crate::database::types::ColumnInfo;
+
+    fn table(schema: &str, name: &str, columns: &[(&str, &str)]) -> TableInfo {
+        TableInfo {
+            name: name.to_owned(),
+            schema: schema.to_owned(),
+            columns: columns
+                .iter()
+                .map(|(name, data_type)| ColumnInfo {
+                    name: (*name).to_owned(),
+                    data_type: (*data_type).to_owned(),
+                    is_nullable: *name != "id",
+                    default_value: None,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn hover(query: &str, at: &str) -> Option<HoverTarget> {
+        let schema = DatabaseSchema::new(
+            vec![
+                table("public", "users", &[("id", "int4"), ("name", "text")]),
+                table(
+                    "billing",
+                    "invoices",
+                    &[("id", "int4"), ("user_id", "int4")],
+                ),
+            ],
+            vec![],
+            vec![],
+        );
+        let position = query.find(at).unwrap();
+        hover_at(&PostgreSqlDialect {}, &schema, query, position).map(|hover| {
+            assert_eq!(hover.from, position);
+            hover.target
+        })
+    }
+
+    fn candidates(target: Option<HoverTarget>) -> Vec<(String, Option<String>)> {
+        match target {
+            Some(HoverTarget::Column { candidates, .. }) => candidates
+                .into_iter()
+                .map(|c| (format!("{}.{}", c.table, c.data_type), c.alias))
+                .collect(),
+            other => panic!("Expected a column, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn resolves_columns_through_aliases() {
+        let query = "SELECT 1;\nSELECT u.name, i.user_id, id FROM users AS u JOIN billing.invoices i ON i.user_id = u.id";
+
+        assert_eq!(
+            candidates(hover(query, "name")),
+            [("users.text".to_owned(), Some("u".to_owned()))]
+        );
+        assert_eq!(
+            candidates(hover(query, "id FROM")),
+            [
+                ("users.int4".to_owned(), Some("u".to_owned())),
+                ("invoices.int4".to_owned(), Some("i".to_owned()))
+            ]
+        );
+        let Some(HoverTarget::Table {
+            schema,
+            name,
+            alias,
+            column_count,
+            ..
+        }) = hover(query, "i.user_id")
+        else {
+            panic!("Expected a table");
+        };
+        assert_eq!(
+            (
+                schema.as_str(),
+                name.as_str(),
+                alias.as_deref(),
+                column_count
+            ),
+            ("billing", "invoices", Some("i"), 2)
+        );
+    }
+
+    #[test]
+    fn resolves_tables_and_unknown_names() {
+        let query = "UPDATE \"users\" SET name = 'x' WHERE missing = 1";
+        assert!(matches!(
+            hover(query, "\"users\""),
+            Some(HoverTarget::Table { alias: None, .. })
+        ));
+        assert_eq!(candidates(hover(query, "name")).len(), 1);
+        assert_eq!(hover(query, "missing"), None);
+        assert_eq!(hover(query, "SET"), None);
+        assert_eq!(hover("SELECT * FROM billing.invoices", "billing"), None);
+    }
+}
@@ -0,0 +1,432 @@
+//! Writing results as `INSERT` statements, e.g. to copy a small reference table into another environment.
+//!
+//! Values are rendered from the pages the statement manager keeps, quoted for the dialect they're meant for.
+//! Binary values, which are kept as `\x` hex when they aren't valid UTF-8, become binary literals again.
+
+use std::io::Write;
+
+use anyhow::{bail, ensure, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{database::types::Database, utils};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InsertDialect {
+    Postgres,
+    Sqlite,
+    Mysql,
+}
+
+impl From<Database> for InsertDialect {
+    fn from(database: Database) -> Self {
+        match database {
+            Database::Postgres => InsertDialect::Postgres,
+            Database::Sqlite => InsertDialect::Sqlite,
+            Database::MySql => InsertDialect::Mysql,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InsertOptions {
+    pub dialect: InsertDialect,
+    /// How many rows each `INSERT` has in its `VALUES`
+    pub rows_per_statement: usize,
+    /// Whether NULLs are written, or their columns left out so that they get their defaults
+    pub include_nulls: bool,
+    /// Statements that aren't written to a file are returned, up to this many bytes
+    pub max_inline_bytes: usize,
+}
+
+impl Default for InsertOptions {
+    fn default() -> Self {
+        Self {
+            dialect: InsertDialect::Postgres,
+            rows_per_statement: 100,
+            include_nulls: true,
+            max_inline_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl InsertOptions {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.rows_per_statement > 0,
+            "Statements need at least one row each"
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ColumnKind {
+    Plain,
+    Binary,
+    /// A Postgres array, with the type of its elements
+    Array(String),
+}
+
+impl ColumnKind {
+    fn new(dialect: InsertDialect, column_type: Option<&str>) -> Self {
+        let Some(column_type) = column_type else {
+            return ColumnKind::Plain;
+        };
+        let column_type = column_type.to_ascii_lowercase();
+
+        match dialect {
+            InsertDialect::Postgres if column_type == "bytea" => ColumnKind::Binary,
+            // Postgres names array types after their elements, e.g. `_int4`
+            InsertDialect::Postgres => match column_type.strip_prefix('_') {
+                Some(element) => ColumnKind::Array(element.to_owned()),
+                None => ColumnKind::Plain,
+            },
+            InsertDialect::Sqlite if column_type.contains("blob") => ColumnKind::Binary,
+            InsertDialect::Mysql
+                if matches!(column_type.as_str(), "binary" | "varbinary" | "blob") =>
+            {
+                ColumnKind::Binary
+            }
+            InsertDialect::Sqlite | InsertDialect::Mysql => ColumnKind::Plain,
+        }
+    }
+}
+
+/// Whether values of a column of this type are bytes, for the dialect the column comes from
+pub fn is_binary_column(dialect: InsertDialect, column_type: Option<&str>) -> bool {
+    ColumnKind::new(dialect, column_type) == ColumnKind::Binary
+}
+
+/// Writes `INSERT` statements as pages of results are given to it
+pub struct InsertWriter<W> {
+    out: W,
+    options: InsertOptions,
+    table: String,
+    columns: Vec<String>,
+    kinds: Vec<ColumnKind>,
+    /// The rendered rows of the statement being built
+    rows: Vec<String>,
+    /// Which columns the rows of the statement being built have
+    row_columns: Vec<usize>,
+    bytes_written: usize,
+}
+
+impl<W: Write> InsertWriter<W> {
+    /// `table` may be qualified with a schema, e.g. `public.users`. `column_types` are in the same order as
+    /// `columns`, and may be empty if unknown.
+    pub fn new(
+        out: W,
+        options: InsertOptions,
+        table: &str,
+        columns: &[String],
+        column_types: &[Option<String>],
+    ) -> anyhow::Result<Self> {
+        options.validate()?;
+        ensure!(!table.trim().is_empty(), "A table name is required");
+
+        let dialect = options.dialect;
+        let table = table
+            .trim()
+            .split('.')
+            .map(|part| quote_identifier(dialect, part))
+            .collect::<Vec<_>>()
+            .join(".");
+        let kinds = (0..columns.len())
+            .map(|idx| ColumnKind::new(dialect, column_types.get(idx).and_then(|t| t.as_deref())))
+            .collect();
+
+        Ok(Self {
+            out,
+            table,
+            columns: columns
+                .iter()
+                .map(|column| quote_identifier(dialect, column))
+                .collect(),
+            kinds,
+            rows: Vec::new(),
+            row_columns: Vec::new(),
+            bytes_written: 0,
+            options,
+        })
+    }
+
+    /// Appends the rows of a page, as stored by the statement manager
+    pub fn write_page(&mut self, page: &str) -> anyhow::Result<()> {
+        let rows: Vec<Vec<Value>> =
+            serde_json::from_str(page).context("Failed to read a page of results")?;
+
+        for row in rows {
+            let mut row_columns = Vec::with_capacity(row.len());
+            let mut values = Vec::with_capacity(row.len());
+            for (idx, (value, kind)) in row.iter().zip(&self.kinds).enumerate() {
+                if value.is_null() && !self.options.include_nulls {
+                    continue;
+                }
+                row_columns.push(idx);
+                values.push(render_value(self.options.dialect, kind, value)?);
+            }
+
+            // Rows can only share a statement if they have the same columns
+            if !self.rows.is_empty() && row_columns != self.row_columns {
+                self.flush_statement()?;
+            }
+            self.row_columns = row_columns;
+            self.rows.push(format!("({})", values.join(", ")));
+
+            if self.rows.len() >= self.options.rows_per_statement {
+                self.flush_statement()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// How many bytes of statements were written so far
+    pub fn bytes_written(&self) -> usize {
+        self.bytes_written
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        self.flush_statement()?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn flush_statement(&mut self) -> anyhow::Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut statement = format!("INSERT INTO {}", self.table);
+        if self.row_columns.is_empty() {
+            // Every value of the row was a left-out NULL
+            statement.push_str(match self.options.dialect {
+                InsertDialect::Mysql => " () VALUES ()",
+                InsertDialect::Postgres | InsertDialect::Sqlite => " DEFAULT VALUES",
+            });
+            statement.push_str(";\n");
+            statement = statement.repeat(self.rows.len());
+        } else {
+            let columns: Vec<_> = self
+                .row_columns
+                .iter()
+                .map(|&idx| self.columns[idx].as_str())
+                .collect();
+            statement.push_str(&format!(" ({}) VALUES", columns.join(", ")));
+            match self.rows.as_slice() {
+                [row] => statement.push_str(&format!(" {row};\n")),
+                rows => statement.push_str(&format!("\n    {};\n", rows.join(",\n    "))),
+            }
+        }
+
+        self.out.write_all(statement.as_bytes())?;
+        self.bytes_written += statement.len();
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+fn quote_identifier(dialect: InsertDialect, identifier: &str) -> String {
+    match dialect {
+        InsertDialect::Mysql => utils::quote_mysql_identifier(identifier),
+        InsertDialect::Postgres | InsertDialect::Sqlite => utils::quote_identifier(identifier),
+    }
+}
+
+fn render_value(
+    dialect: InsertDialect,
+    kind: &ColumnKind,
+    value: &Value,
+) -> anyhow::Result<String> {
+    let rendered = match value {
+        Value::Null => "NULL".to_owned(),
+        Value::Bool(value) => match (dialect, value) {
+            (InsertDialect::Sqlite, true) => "1".to_owned(),
+            (InsertDialect::Sqlite, false) => "0".to_owned(),
+            (_, true) => "TRUE".to_owned(),
+            (_, false) => "FALSE".to_owned(),
+        },
+        Value::Number(number) => number.to_string(),
+        Value::String(text) if *kind == ColumnKind::Binary => binary_literal(dialect, text)?,
+        Value::String(text) => string_literal(dialect, text),
+        Value::Array(items) => match kind {
+            ColumnKind::Array(element_type) => {
+                let items = items
+                    .iter()
+                    .map(|item| render_value(dialect, &ColumnKind::Plain, item))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                format!("ARRAY[{}]::{element_type}[]", items.join(", "))
+            }
+            _ => string_literal(dialect, &value.to_string()),
+        },
+        Value::Object(_) => string_literal(dialect, &value.to_string()),
+    };
+    Ok(rendered)
+}
+
+fn string_literal(dialect: InsertDialect, text: &str) -> String {
+    match dialect {
+        // With standard_conforming_strings, backslashes are only escapes in E'' strings, which are needed
+        // to write control characters
+        InsertDialect::Postgres if text.chars().any(|c| c == '\\' || c.is_control()) => {
+            let mut literal = String::with_capacity(text.len() + 3);
+            literal.push_str("E'");
+            for c in text.chars() {
+                match c {
+                    '\\' => literal.push_str("\\\\"),
+                    '\'' => literal.push_str("''"),
+                    '\n' => literal.push_str("\\n"),
+                    '\r' => literal.push_str("\\r"),
+                    '\t' => literal.push_str("\\t"),
+                    c if c.is_control() => literal.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => literal.push(c),
+                }
+            }
+            literal.push('\'');
+            literal
+        }
+        // Backslashes are escapes unless the NO_BACKSLASH_ESCAPES mode is on, which isn't the default
+        InsertDialect::Mysql => format!(
+            "'{}'",
+            text.replace('\\', "\\\\")
+                .replace('\'', "''")
+                .replace('\0', "\\0")
+        ),
+        InsertDialect::Postgres | InsertDialect::Sqlite => {
+            format!("'{}'", text.replace('\'', "''"))
+        }
+    }
+}
+
+fn binary_literal(dialect: InsertDialect, text: &str) -> anyhow::Result<String> {
+    if dialect == InsertDialect::Sqlite {
+        // SQLite blobs are only kept as their length, e.g. `Blob(5)`, so they can't be written back
+        if text.starts_with("Blob(") {
+            bail!("SQLite blobs aren't kept in results, so they can't be written as INSERTs");
+        }
+        return Ok(string_literal(dialect, text));
+    }
+
+    let bytes = text
+        .strip_prefix("\\x")
+        .and_then(|digits| hex::decode(digits).ok())
+        .unwrap_or_else(|| text.as_bytes().to_vec());
+    let digits = hex::encode(bytes);
+
+    Ok(match dialect {
+        InsertDialect::Postgres => format!("'\\x{digits}'"),
+        InsertDialect::Mysql | InsertDialect::Sqlite => format!("X'{digits}'"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn generate(
+        options: InsertOptions,
+        table: &str,
+        columns: &[&str],
+        types: &[&str],
+        page: Value,
+    ) -> anyhow::Result<String> {
+        let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+        let types: Vec<Option<String>> = types.iter().map(|t| Some(t.to_string())).collect();
+        let mut writer = InsertWriter::new(Vec::new(), options, table, &columns, &types)?;
+        writer.write_page(&page.to_string())?;
+        Ok(String::from_utf8(writer.finish()?).unwrap())
+    }
+
+    #[test]
+    fn writes_postgres_inserts() {
+        let options = InsertOptions {
+            rows_per_statement: 2,
+            ..Default::default()
+        };
+        let page = json!([
+            [1, "O'Brien", true, "\\x00ff", [1, 2], {"a": 1}],
+            [2, "line\nbreak \\ here", false, "hi", [], null],
+            [3, null, null, null, null, null]
+        ]);
+
+        let inserts = generate(
+            options,
+            "public.users",
+            &["id", "name", "active", "avatar", "scores", "doc"],
+            &["int4", "text", "bool", "bytea", "_int4", "jsonb"],
+            page,
+        )
+        .unwrap();
+
+        assert_eq!(
+            inserts,
+            r#"INSERT INTO "public"."users" ("id", "name", "active", "avatar", "scores", "doc") VALUES
+    (1, 'O''Brien', TRUE, '\x00ff', ARRAY[1, 2]::int4[], '{"a":1}'),
+    (2, E'line\nbreak \\ here', FALSE, '\x6869', ARRAY[]::int4[], NULL);
+INSERT INTO "public"."users" ("id", "name", "active", "avatar", "scores", "doc") VALUES (3, NULL, NULL, NULL, NULL, NULL);
+"#
+        );
+    }
+
+    #[test]
+    fn leaves_out_nulls() {
+        let options = InsertOptions {
+            dialect: InsertDialect::Mysql,
+            include_nulls: false,
+            ..Default::default()
+        };
+        let page = json!([
+            [1, "a\\b", null],
+            [2, "it's", null],
+            [3, null, "\\xff"],
+            [null, null, null]
+        ]);
+
+        let inserts = generate(
+            options,
+            "codes",
+            &["id", "code", "raw"],
+            &["int", "varchar", "varbinary"],
+            page,
+        )
+        .unwrap();
+
+        assert_eq!(
+            inserts,
+            r#"INSERT INTO `codes` (`id`, `code`) VALUES
+    (1, 'a\\b'),
+    (2, 'it''s');
+INSERT INTO `codes` (`id`, `raw`) VALUES (3, X'ff');
+INSERT INTO `codes` () VALUES ();
+"#
+        );
+    }
+
+    #[test]
+    fn refuses_sqlite_blobs() {
+        let options = InsertOptions {
+            dialect: InsertDialect::Sqlite,
+            ..Default::default()
+        };
+        let inserts = generate(
+            options.clone(),
+            "t",
+            &["flag", "note"],
+            &["BOOLEAN", "TEXT"],
+            json!([[true, "x"]]),
+        )
+        .unwrap();
+        assert_eq!(
+            inserts,
+            "INSERT INTO \"t\" (\"flag\", \"note\") VALUES (1, 'x');\n"
+        );
+
+        let err = generate(options, "t", &["data"], &["BLOB"], json!([["Blob(5)"]])).unwrap_err();
+        assert!(err.to_string().contains("SQLite blobs"));
+    }
+}
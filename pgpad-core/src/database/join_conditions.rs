@@ -0,0 +1,306 @@
+//! Conditions for a join, suggested from the foreign keys between the table being joined and the tables joined
+//! before it
+
+use std::{collections::HashSet, ops::Range};
+
+use sqlparser::{dialect::Dialect, keywords::Keyword, tokenizer::Token};
+
+use crate::{
+    database::{
+        hover::{self, TableRef},
+        types::{Database, DatabaseSchema, ForeignKey, TableInfo},
+    },
+    utils::{quote_identifier, quote_mysql_identifier},
+};
+
+/// A column as it can be written without changing its meaning, quoted only if it has to be
+fn column_name(database: Database, name: &str) -> String {
+    let plain = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        // Postgres folds unquoted names to lowercase
+        && (database != Database::Postgres || !name.chars().any(|c| c.is_ascii_uppercase()));
+
+    match (plain, database) {
+        (true, _) => name.to_owned(),
+        (false, Database::MySql) => quote_mysql_identifier(name),
+        (false, Database::Postgres | Database::Sqlite) => quote_identifier(name),
+    }
+}
+
+fn references(key: &ForeignKey, table: &TableInfo) -> bool {
+    key.referenced_schema == table.schema && key.referenced_table == table.name
+}
+
+/// A table of the statement, with how its columns are qualified: by its alias, or by its name as written
+struct Source<'a> {
+    table: &'a TableInfo,
+    qualifier: &'a str,
+}
+
+impl<'a> Source<'a> {
+    fn new(
+        schema: &'a DatabaseSchema,
+        query: &'a str,
+        tokens: &[(Token, Range<usize>)],
+        table_ref: &TableRef,
+    ) -> Option<Self> {
+        if table_ref.function {
+            return None;
+        }
+        let table = hover::find_table(schema, &table_ref.name)?;
+        let bytes = match table_ref.alias {
+            Some((idx, _)) => tokens[idx].1.clone(),
+            None => table_ref.name_bytes(tokens),
+        };
+        Some(Self {
+            table,
+            qualifier: &query[bytes],
+        })
+    }
+}
+
+/// `joined.a = other.b AND ...` for each column of `key`, a key of the joined table or of the other one
+fn condition(
+    database: Database,
+    joined: &Source,
+    other: &Source,
+    key_of_joined: bool,
+    key: &ForeignKey,
+) -> String {
+    key.columns
+        .iter()
+        .zip(&key.referenced_columns)
+        .map(|(column, referenced)| {
+            let (joined_column, other_column) = if key_of_joined {
+                (column, referenced)
+            } else {
+                (referenced, column)
+            };
+            format!(
+                "{}.{} = {}.{}",
+                joined.qualifier,
+                column_name(database, joined_column),
+                other.qualifier,
+                column_name(database, other_column)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+/// Ready to insert conditions for the join whose `ON` is right before `position`, a position in UTF-16 code units
+/// like editor positions, possibly with the start of a condition typed after it. Each foreign key between the
+/// joined table and a table before it, either way, makes one.
+///
+/// Only the tables at the same level of parentheses as the join count, so that those of subqueries don't.
+pub fn join_conditions(
+    dialect: &dyn Dialect,
+    database: Database,
+    schema: &DatabaseSchema,
+    query: &str,
+    position: usize,
+) -> Vec<String> {
+    let cursor = hover::byte_offset(query, position);
+    let Some(tokens) = hover::tokens(dialect, query) else {
+        return vec![];
+    };
+
+    let mut before = tokens
+        .iter()
+        .take_while(|(_, bytes)| bytes.start < cursor)
+        .count();
+    // The name being typed, possibly qualified
+    let mut typed = cursor;
+    while before > 0
+        && matches!(tokens[before - 1].0, Token::Word(_) | Token::Period)
+        && tokens[before - 1].1.end >= typed
+    {
+        before -= 1;
+        typed = tokens[before].1.start;
+    }
+    let Some(on) = before.checked_sub(1) else {
+        return vec![];
+    };
+    if !hover::word(&tokens, on)
+        .is_some_and(|w| w.quote_style.is_none() && w.keyword == Keyword::ON)
+    {
+        return vec![];
+    }
+
+    let start = tokens[..on]
+        .iter()
+        .rposition(|(token, _)| *token == Token::SemiColon)
+        .map_or(0, |idx| idx + 1);
+    let statement = &tokens[start..on];
+
+    // The depth of parentheses before each token
+    let mut depth = 0usize;
+    let depths: Vec<usize> = statement
+        .iter()
+        .map(|(token, _)| {
+            let before = depth;
+            match token {
+                Token::LParen => depth += 1,
+                Token::RParen => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            before
+        })
+        .collect();
+
+    let refs: Vec<TableRef> = hover::table_refs(statement)
+        .into_iter()
+        .filter(|table_ref| depths[table_ref.start] == depth)
+        .collect();
+    let Some((joined, others)) = refs.split_last() else {
+        return vec![];
+    };
+    if joined.keyword != Keyword::JOIN {
+        return vec![];
+    }
+    let Some(joined) = Source::new(schema, query, statement, joined) else {
+        return vec![];
+    };
+
+    let mut conditions = vec![];
+    for other in others {
+        let Some(other) = Source::new(schema, query, statement, other) else {
+            continue;
+        };
+        for key in &joined.table.foreign_keys {
+            if references(key, other.table) {
+                conditions.push(condition(database, &joined, &other, true, key));
+            }
+        }
+        for key in &other.table.foreign_keys {
+            if references(key, joined.table) {
+                conditions.push(condition(database, &joined, &other, false, key));
+            }
+        }
+    }
+    // A table joined twice by the same name makes the same conditions twice
+    let mut seen = HashSet::new();
+    conditions.retain(|condition| seen.insert(condition.clone()));
+    conditions
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::dialect::PostgreSqlDialect;
+
+    use super::*;
+    use crate::database::types::ColumnInfo;
+
+    fn table(schema: &str, name: &str, keys: &[(&[&str], &str, &[&str])]) -> TableInfo {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        TableInfo {
+            name: name.to_owned(),
+            schema: schema.to_owned(),
+            columns: vec![ColumnInfo {
+                name: "id".to_owned(),
+                data_type: "integer".to_owned(),
+                is_nullable: false,
+                default_value: None,
+            }],
+            foreign_keys: keys
+                .iter()
+                .map(
+                    |(columns, referenced_table, referenced_columns)| ForeignKey {
+                        columns: names(columns),
+                        referenced_schema: schema.to_owned(),
+                        referenced_table: referenced_table.to_string(),
+                        referenced_columns: names(referenced_columns),
+                        ..Default::default()
+                    },
+                )
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn schema() -> DatabaseSchema {
+        DatabaseSchema::new(
+            vec![
+                table(
+                    "public",
+                    "customers",
+                    &[(&["referrer_id"], "customers", &["id"])],
+                ),
+                table(
+                    "public",
+                    "orders",
+                    &[(&["customer_id"], "customers", &["id"])],
+                ),
+                table(
+                    "public",
+                    "lines",
+                    &[(&["order_id", "Region"], "orders", &["id", "region"])],
+                ),
+                table("public", "products", &[]),
+            ],
+            vec!["public".to_owned()],
+            vec![],
+        )
+    }
+
+    /// The conditions at `|`
+    fn conditions(query: &str) -> Vec<String> {
+        let position = query.find('|').unwrap();
+        let query = query.replace('|', "");
+        join_conditions(
+            &PostgreSqlDialect {},
+            Database::Postgres,
+            &schema(),
+            &query,
+            position,
+        )
+    }
+
+    #[test]
+    fn suggests_foreign_keys_either_way() {
+        assert_eq!(
+            conditions("SELECT * FROM customers JOIN orders ON |"),
+            ["orders.customer_id = customers.id"]
+        );
+        assert_eq!(
+            conditions("SELECT * FROM orders o JOIN customers AS c ON c|"),
+            ["c.id = o.customer_id"]
+        );
+        // With the start of the condition typed, and aliases on both sides
+        assert_eq!(
+            conditions("SELECT * FROM public.orders o\nJOIN lines l ON l.|"),
+            [r#"l.order_id = o.id AND l."Region" = o.region"#]
+        );
+        // From every table before the join, and the key of the table to itself both ways
+        assert_eq!(
+            conditions(
+                "SELECT * FROM orders o JOIN customers c ON c.id = o.customer_id JOIN customers r ON |"
+            ),
+            [
+                "r.id = o.customer_id",
+                "r.referrer_id = c.id",
+                "r.id = c.referrer_id"
+            ]
+        );
+    }
+
+    #[test]
+    fn suggests_nothing_elsewhere() {
+        assert!(conditions("SELECT * FROM customers JOIN orders ON|").is_empty());
+        assert!(conditions("SELECT * FROM customers JOIN orders |").is_empty());
+        assert!(conditions("SELECT * FROM customers JOIN products ON |").is_empty());
+        assert!(conditions("SELECT * FROM customers JOIN missing ON |").is_empty());
+        // The tables of a subquery aren't in scope
+        assert!(
+            conditions("SELECT * FROM (SELECT * FROM customers) c JOIN orders ON |").is_empty()
+        );
+        // Another statement's tables neither
+        assert!(
+            conditions("SELECT * FROM customers; SELECT * FROM products JOIN orders ON |")
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Exporting results as JSON: either a single array of objects, or NDJSON with one object per line, for
+//! line-oriented tools like `jq`.
+//!
+//! Rows become objects keyed by column name. Values are written the way they're already kept in pages, so JSON
+//! columns stay nested JSON rather than strings. Binary columns become `{"$base64": "..."}`.
+
+use std::{collections::HashSet, io};
+
+use anyhow::{bail, Context};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::database::insert_export::{is_binary_column, InsertDialect};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonFormat {
+    /// A single array of objects
+    Json,
+    /// One object per line
+    Ndjson,
+}
+
+/// Keys for columns, with repeated names (e.g. the `id` of each table of a join) suffixed with `_2`, `_3`, etc.
+pub fn unique_keys(columns: &[String]) -> Vec<String> {
+    let mut taken: HashSet<&str> = HashSet::new();
+    let mut keys = Vec::with_capacity(columns.len());
+
+    for column in columns {
+        let mut key = column.clone();
+        let mut n = 1;
+        // Another column may already be named like a suffixed one, e.g. `id_2`
+        while taken.contains(key.as_str()) || keys.contains(&key) {
+            n += 1;
+            key = format!("{column}_{n}");
+        }
+        taken.insert(column.as_str());
+        keys.push(key);
+    }
+
+    keys
+}
+
+/// Drivers send bytes that aren't valid UTF-8 as `\x` followed by hex, and the others as text
+fn binary_value(dialect: InsertDialect, value: Value) -> anyhow::Result<Value> {
+    let Value::String(text) = value else {
+        return Ok(value);
+    };
+    // SQLite blobs are only kept as their length, e.g. `Blob(5)`
+    if dialect == InsertDialect::Sqlite && text.starts_with("Blob(") {
+        bail!("SQLite blobs aren't kept in results, so they can't be exported as JSON");
+    }
+    let bytes = text
+        .strip_prefix("\\x")
+        .and_then(|digits| hex::decode(digits).ok())
+        .unwrap_or_else(|| text.into_bytes());
+
+    let mut marker = Map::new();
+    marker.insert(
+        "$base64".to_owned(),
+        Value::String(base64::engine::general_purpose::STANDARD.encode(bytes)),
+    );
+    Ok(Value::Object(marker))
+}
+
+/// Writes rows as JSON objects to `out`
+pub struct JsonWriter<W> {
+    out: W,
+    format: JsonFormat,
+    /// Already quoted, and written in this order rather than sorted like in a `Map`
+    keys: Vec<String>,
+    /// The dialect of binary columns, None if there are none
+    binary: Vec<Option<InsertDialect>>,
+    rows: usize,
+}
+
+impl<W: io::Write> JsonWriter<W> {
+    /// `column_types` are in the same order as `columns`, and may be empty if unknown, as may the dialect they
+    /// come from
+    pub fn new(
+        out: W,
+        format: JsonFormat,
+        dialect: Option<InsertDialect>,
+        columns: &[String],
+        column_types: &[Option<String>],
+    ) -> Self {
+        let binary = (0..columns.len())
+            .map(|idx| {
+                dialect.filter(|&dialect| {
+                    is_binary_column(dialect, column_types.get(idx).and_then(|t| t.as_deref()))
+                })
+            })
+            .collect();
+
+        Self {
+            out,
+            format,
+            keys: unique_keys(columns)
+                .into_iter()
+                .map(|key| Value::String(key).to_string())
+                .collect(),
+            binary,
+            rows: 0,
+        }
+    }
+
+    /// Appends the rows of a page, as stored by the statement manager
+    pub fn write_page(&mut self, page: &str) -> anyhow::Result<()> {
+        let rows: Vec<Vec<Value>> =
+            serde_json::from_str(page).context("Failed to read a page of results")?;
+
+        for row in rows {
+            let mut object = String::from("{");
+            for (idx, ((key, binary), value)) in
+                self.keys.iter().zip(&self.binary).zip(row).enumerate()
+            {
+                if idx > 0 {
+                    object.push(',');
+                }
+                let value = match binary {
+                    Some(dialect) => binary_value(*dialect, value)?,
+                    None => value,
+                };
+                object.push_str(key);
+                object.push(':');
+                object.push_str(&serde_json::to_string(&value)?);
+            }
+            object.push('}');
+
+            match self.format {
+                JsonFormat::Json => {
+                    self.out
+                        .write_all(if self.rows == 0 { b"[\n" } else { b",\n" })?;
+                }
+                JsonFormat::Ndjson => {}
+            }
+            self.out.write_all(object.as_bytes())?;
+            if self.format == JsonFormat::Ndjson {
+                self.out.write_all(b"\n")?;
+            }
+            self.rows += 1;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        if self.format == JsonFormat::Json {
+            self.out
+                .write_all(if self.rows == 0 { b"[]\n" } else { b"\n]\n" })?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn text(writer: JsonWriter<Vec<u8>>) -> String {
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn suffixes_repeated_columns() {
+        let columns: Vec<String> = ["id", "name", "id", "id_2", "id"]
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        assert_eq!(
+            unique_keys(&columns),
+            ["id", "name", "id_2", "id_2_2", "id_3"]
+        );
+    }
+
+    #[test]
+    fn writes_json_and_ndjson() {
+        let columns = vec![
+            "id".to_owned(),
+            "doc".to_owned(),
+            "id".to_owned(),
+            "raw".to_owned(),
+        ];
+        let types = vec![
+            Some("int4".to_owned()),
+            Some("jsonb".to_owned()),
+            Some("int8".to_owned()),
+            Some("bytea".to_owned()),
+        ];
+        let page = json!([
+            [1, {"tags": ["a", "b"]}, 10, "\\x00ff"],
+            [2, null, 20, "hi"],
+            [3, [], null, null]
+        ])
+        .to_string();
+
+        let mut ndjson = JsonWriter::new(
+            Vec::new(),
+            JsonFormat::Ndjson,
+            Some(InsertDialect::Postgres),
+            &columns,
+            &types,
+        );
+        ndjson.write_page(&page).unwrap();
+        // Keys are in the order of the columns, not sorted
+        let lines = concat!(
+            r#"{"id":1,"doc":{"tags":["a","b"]},"id_2":10,"raw":{"$base64":"AP8="}}"#,
+            "\n",
+            r#"{"id":2,"doc":null,"id_2":20,"raw":{"$base64":"aGk="}}"#,
+            "\n",
+            r#"{"id":3,"doc":[],"id_2":null,"raw":null}"#,
+            "\n",
+        );
+        assert_eq!(text(ndjson), lines);
+
+        let mut array = JsonWriter::new(
+            Vec::new(),
+            JsonFormat::Json,
+            Some(InsertDialect::Postgres),
+            &columns,
+            &types,
+        );
+        array.write_page(&page).unwrap();
+        array.write_page("[]").unwrap();
+        assert_eq!(
+            text(array),
+            format!("[\n{}\n]\n", lines.trim_end().replace('\n', ",\n"))
+        );
+
+        let empty = text(JsonWriter::new(
+            Vec::new(),
+            JsonFormat::Json,
+            None,
+            &columns,
+            &[],
+        ));
+        assert_eq!(empty, "[]\n");
+    }
+
+    #[test]
+    fn encodes_binary_columns_of_each_dialect() {
+        let columns = vec!["name".to_owned(), "raw".to_owned()];
+        let page = json!([["a", "\\x00ff"]]).to_string();
+
+        for column_type in ["varbinary", "blob", "binary"] {
+            let types = vec![Some("varchar".to_owned()), Some(column_type.to_owned())];
+            let mut writer = JsonWriter::new(
+                Vec::new(),
+                JsonFormat::Ndjson,
+                Some(InsertDialect::Mysql),
+                &columns,
+                &types,
+            );
+            writer.write_page(&page).unwrap();
+            assert_eq!(
+                text(writer),
+                "{\"name\":\"a\",\"raw\":{\"$base64\":\"AP8=\"}}\n",
+                "{column_type}"
+            );
+        }
+
+        // SQLite only keeps the length of blobs
+        let types = vec![Some("TEXT".to_owned()), Some("BLOB".to_owned())];
+        let mut writer = JsonWriter::new(
+            Vec::new(),
+            JsonFormat::Ndjson,
+            Some(InsertDialect::Sqlite),
+            &columns,
+            &types,
+        );
+        writer
+            .write_page(&json!([["a", "hi"]]).to_string())
+            .unwrap();
+        assert!(writer
+            .write_page(&json!([["b", "Blob(5)"]]).to_string())
+            .is_err());
+        assert_eq!(
+            text(writer),
+            "{\"name\":\"a\",\"raw\":{\"$base64\":\"aGk=\"}}\n"
+        );
+    }
+}
@@ -0,0 +1,429 @@
+//! Column-level lineage: which base table column each output column of a SELECT came from
+
+use std::ops::ControlFlow;
+
+use serde::Serialize;
+use sqlparser::ast::{
+    visit_expressions, Expr, Ident, ObjectName, Query, Select, SelectItem, SetExpr, Statement,
+    TableAlias, TableFactor,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceColumn {
+    /// The table as written in the query (e.g. `public.users`), if it could be resolved
+    pub table: Option<String>,
+    pub column: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ColumnLineage {
+    /// A direct reference to a base table column, possibly through aliases, a subquery or a CTE
+    Column(SourceColumn),
+    /// An expression, along with the columns it references
+    Computed {
+        columns: Vec<SourceColumn>,
+    },
+    Unknown,
+}
+
+impl ColumnLineage {
+    /// Whether editing a cell of this column can be mapped back onto its base table
+    pub fn is_editable(&self) -> bool {
+        matches!(
+            self,
+            ColumnLineage::Column(SourceColumn { table: Some(_), .. })
+        )
+    }
+}
+
+/// Resolves the lineage of every output column of `statement`.
+///
+/// Returns None if the statement isn't a SELECT, or if its output columns can't be known from the query alone
+/// (e.g. `SELECT *` or set operations), in which case the driver's own column metadata should be used instead.
+pub fn resolve_lineage(statement: &Statement) -> Option<Vec<ColumnLineage>> {
+    let Statement::Query(query) = statement else {
+        return None;
+    };
+
+    let ctes = match &query.with {
+        Some(with) => with
+            .cte_tables
+            .iter()
+            .map(|cte| {
+                let columns = resolve_query(&cte.query, &[], 1)
+                    .map(|columns| rename_columns(columns, &cte.alias));
+                (cte.alias.name.value.clone(), columns)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let columns = resolve_query(query, &ctes, 0)?;
+    Some(columns.into_iter().map(|(_, lineage)| lineage).collect())
+}
+
+/// Output columns of a CTE or subquery, or None if they couldn't be resolved
+type DerivedColumns = Option<Vec<(String, ColumnLineage)>>;
+
+enum Source {
+    Table(String),
+    Derived(DerivedColumns),
+}
+
+struct ScopeEntry {
+    /// Alias, or the table's unqualified name
+    name: String,
+    /// Schema-qualified name, for references like `public.users.id`
+    qualified_name: Option<String>,
+    source: Source,
+}
+
+/// Resolves a query's output columns. Subqueries and CTEs are only followed while `depth` is zero.
+fn resolve_query(
+    query: &Query,
+    ctes: &[(String, DerivedColumns)],
+    depth: usize,
+) -> Option<Vec<(String, ColumnLineage)>> {
+    let SetExpr::Select(select) = query.body.as_ref() else {
+        return None;
+    };
+
+    let scope = build_scope(select, ctes, depth);
+    let mut columns = Vec::with_capacity(select.projection.len());
+
+    for item in &select.projection {
+        let (name, lineage) = match item {
+            SelectItem::UnnamedExpr(expr) => (output_name(expr), expr_lineage(expr, &scope)),
+            SelectItem::ExprWithAlias { expr, alias } => {
+                (alias.value.clone(), expr_lineage(expr, &scope))
+            }
+            SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => return None,
+        };
+        columns.push((name, lineage));
+    }
+
+    Some(columns)
+}
+
+fn build_scope(
+    select: &Select,
+    ctes: &[(String, DerivedColumns)],
+    depth: usize,
+) -> Vec<ScopeEntry> {
+    let relations = select.from.iter().flat_map(|table| {
+        std::iter::once(&table.relation).chain(table.joins.iter().map(|join| &join.relation))
+    });
+
+    let mut scope = Vec::new();
+    for relation in relations {
+        match relation {
+            TableFactor::Table { name, alias, .. } => {
+                let qualified_name = object_name(name);
+                let unqualified_name = name
+                    .0
+                    .last()
+                    .and_then(|part| part.as_ident())
+                    .map(|ident| ident.value.clone())
+                    .unwrap_or_else(|| qualified_name.clone());
+
+                let cte = (name.0.len() == 1)
+                    .then(|| {
+                        ctes.iter()
+                            .find(|(cte, _)| cte.eq_ignore_ascii_case(&unqualified_name))
+                    })
+                    .flatten();
+
+                let source = match cte {
+                    Some((_, columns)) => Source::Derived(columns.clone()),
+                    None => Source::Table(qualified_name.clone()),
+                };
+                let entry_name = alias
+                    .as_ref()
+                    .map(|alias| alias.name.value.clone())
+                    .unwrap_or(unqualified_name);
+
+                scope.push(ScopeEntry {
+                    name: entry_name,
+                    qualified_name: (alias.is_none() && name.0.len() > 1).then_some(qualified_name),
+                    source,
+                });
+            }
+            TableFactor::Derived {
+                subquery, alias, ..
+            } => {
+                let columns = match depth {
+                    0 => resolve_query(subquery, &[], depth + 1),
+                    _ => None,
+                };
+                let columns = match alias {
+                    Some(alias) => columns.map(|columns| rename_columns(columns, alias)),
+                    None => columns,
+                };
+
+                scope.push(ScopeEntry {
+                    name: alias
+                        .as_ref()
+                        .map(|alias| alias.name.value.clone())
+                        .unwrap_or_default(),
+                    qualified_name: None,
+                    source: Source::Derived(columns),
+                });
+            }
+            // Table functions, UNNEST, nested joins, ... are opaque
+            _ => scope.push(ScopeEntry {
+                name: String::new(),
+                qualified_name: None,
+                source: Source::Derived(None),
+            }),
+        }
+    }
+
+    scope
+}
+
+/// Applies the column list of an alias like `AS t(a, b)`
+fn rename_columns(
+    mut columns: Vec<(String, ColumnLineage)>,
+    alias: &TableAlias,
+) -> Vec<(String, ColumnLineage)> {
+    for ((name, _), alias) in columns.iter_mut().zip(&alias.columns) {
+        *name = alias.name.value.clone();
+    }
+    columns
+}
+
+fn object_name(name: &ObjectName) -> String {
+    name.0
+        .iter()
+        .map(|part| match part.as_ident() {
+            Some(ident) => ident.value.clone(),
+            None => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// The name Postgres gives an output column without an alias
+fn output_name(expr: &Expr) -> String {
+    match expr {
+        Expr::Identifier(ident) => ident.value.clone(),
+        Expr::CompoundIdentifier(idents) => idents
+            .last()
+            .map(|ident| ident.value.clone())
+            .unwrap_or_default(),
+        Expr::Nested(expr) => output_name(expr),
+        _ => "?column?".to_owned(),
+    }
+}
+
+fn expr_lineage(expr: &Expr, scope: &[ScopeEntry]) -> ColumnLineage {
+    match expr {
+        Expr::Nested(expr) => expr_lineage(expr, scope),
+        Expr::Identifier(ident) => resolve_column(&[], ident, scope),
+        Expr::CompoundIdentifier(idents) => match idents.split_last() {
+            Some((column, qualifier)) => resolve_column(qualifier, column, scope),
+            None => ColumnLineage::Unknown,
+        },
+        _ => {
+            let mut columns = Vec::new();
+            let _ = visit_expressions(expr, |expr| {
+                let lineage = match expr {
+                    Expr::Identifier(_) | Expr::CompoundIdentifier(_) => expr_lineage(expr, scope),
+                    _ => return ControlFlow::<()>::Continue(()),
+                };
+                match lineage {
+                    ColumnLineage::Column(column) => columns.push(column),
+                    ColumnLineage::Computed { columns: sources } => columns.extend(sources),
+                    ColumnLineage::Unknown => columns.push(SourceColumn {
+                        table: None,
+                        column: output_name(expr),
+                    }),
+                }
+                ControlFlow::Continue(())
+            });
+            columns.dedup();
+
+            ColumnLineage::Computed { columns }
+        }
+    }
+}
+
+fn resolve_column(qualifier: &[Ident], column: &Ident, scope: &[ScopeEntry]) -> ColumnLineage {
+    let entry = if qualifier.is_empty() {
+        match scope {
+            [entry] => Some(entry),
+            // Among several tables, only derived ones tell us which columns they have
+            _ => {
+                let mut candidates = scope.iter().filter(|entry| match &entry.source {
+                    Source::Derived(Some(columns)) => columns
+                        .iter()
+                        .any(|(name, _)| name.eq_ignore_ascii_case(&column.value)),
+                    _ => false,
+                });
+                match (candidates.next(), candidates.next()) {
+                    (Some(entry), None) => Some(entry),
+                    _ => None,
+                }
+            }
+        }
+    } else {
+        let qualifier = qualifier
+            .iter()
+            .map(|ident| ident.value.as_str())
+            .collect::<Vec<_>>()
+            .join(".");
+        scope.iter().find(|entry| {
+            entry.name.eq_ignore_ascii_case(&qualifier)
+                || entry
+                    .qualified_name
+                    .as_ref()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(&qualifier))
+        })
+    };
+
+    match entry.map(|entry| &entry.source) {
+        Some(Source::Table(table)) => ColumnLineage::Column(SourceColumn {
+            table: Some(table.clone()),
+            column: column.value.clone(),
+        }),
+        Some(Source::Derived(Some(columns))) => columns
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&column.value))
+            .map(|(_, lineage)| lineage.clone())
+            .unwrap_or(ColumnLineage::Unknown),
+        Some(Source::Derived(None)) | None => ColumnLineage::Unknown,
+    }
+}
+
+/// Fills the columns the AST couldn't resolve with the origin reported by the driver, if any
+pub fn merge_driver_lineage(
+    lineage: Option<Vec<ColumnLineage>>,
+    driver_lineage: Vec<Option<SourceColumn>>,
+) -> Vec<ColumnLineage> {
+    match lineage {
+        Some(lineage) if lineage.len() == driver_lineage.len() => lineage
+            .into_iter()
+            .zip(driver_lineage)
+            .map(|(lineage, origin)| match (lineage, origin) {
+                (ColumnLineage::Unknown, Some(origin)) => ColumnLineage::Column(origin),
+                (lineage, _) => lineage,
+            })
+            .collect(),
+        _ => driver_lineage
+            .into_iter()
+            .map(|origin| origin.map_or(ColumnLineage::Unknown, ColumnLineage::Column))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+
+    use super::*;
+
+    fn lineage(query: &str) -> Option<Vec<ColumnLineage>> {
+        let statements = Parser::parse_sql(&PostgreSqlDialect {}, query).unwrap();
+        resolve_lineage(&statements[0])
+    }
+
+    fn column(table: &str, column: &str) -> ColumnLineage {
+        ColumnLineage::Column(SourceColumn {
+            table: Some(table.to_owned()),
+            column: column.to_owned(),
+        })
+    }
+
+    fn source(table: &str, column: &str) -> SourceColumn {
+        SourceColumn {
+            table: Some(table.to_owned()),
+            column: column.to_owned(),
+        }
+    }
+
+    #[test]
+    fn resolves_direct_references_and_aliases() {
+        assert_eq!(
+            lineage("SELECT id, u.name AS username, public.users.email FROM public.users u")
+                .unwrap(),
+            [
+                column("public.users", "id"),
+                column("public.users", "name"),
+                ColumnLineage::Unknown,
+            ]
+        );
+
+        assert_eq!(
+            lineage("SELECT o.id, u.name FROM orders o JOIN users u ON u.id = o.user_id").unwrap(),
+            [column("orders", "id"), column("users", "name")]
+        );
+
+        // Ambiguous without a qualifier
+        assert_eq!(
+            lineage("SELECT name FROM orders, users").unwrap(),
+            [ColumnLineage::Unknown]
+        );
+    }
+
+    #[test]
+    fn resolves_expressions_as_computed() {
+        assert_eq!(
+            lineage("SELECT price * quantity AS total, 1, upper(name) FROM items").unwrap(),
+            [
+                ColumnLineage::Computed {
+                    columns: vec![source("items", "price"), source("items", "quantity")]
+                },
+                ColumnLineage::Computed { columns: vec![] },
+                ColumnLineage::Computed {
+                    columns: vec![source("items", "name")]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_through_subqueries_and_ctes() {
+        assert_eq!(
+            lineage(
+                "WITH recent AS (SELECT id AS order_id, total FROM orders)
+                 SELECT r.order_id, s.n, s.label FROM recent r, (SELECT name AS label, total AS n FROM stats) s"
+            )
+            .unwrap(),
+            [
+                column("orders", "id"),
+                column("stats", "total"),
+                column("stats", "name"),
+            ]
+        );
+
+        // Only one level deep
+        assert_eq!(
+            lineage("SELECT x FROM (SELECT x FROM (SELECT x FROM t) a) b").unwrap(),
+            [ColumnLineage::Unknown]
+        );
+    }
+
+    #[test]
+    fn gives_up_on_wildcards_and_set_operations() {
+        assert!(lineage("SELECT * FROM users").is_none());
+        assert!(lineage("SELECT u.* FROM users u").is_none());
+        assert!(lineage("SELECT id FROM a UNION SELECT id FROM b").is_none());
+        assert!(lineage("DELETE FROM users").is_none());
+    }
+
+    #[test]
+    fn merges_driver_lineage() {
+        let merged = merge_driver_lineage(
+            Some(vec![ColumnLineage::Unknown, column("users", "id")]),
+            vec![Some(source("public.users", "name")), None],
+        );
+        assert_eq!(
+            merged,
+            [column("public.users", "name"), column("users", "id")]
+        );
+
+        let merged = merge_driver_lineage(None, vec![None, Some(source("users", "id"))]);
+        assert_eq!(merged, [ColumnLineage::Unknown, column("users", "id")]);
+    }
+}
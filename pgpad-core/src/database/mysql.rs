@@ -0,0 +1,8 @@
+pub mod client;
+pub mod connect;
+pub mod execute;
+pub mod parser;
+mod row_writer;
+pub mod schema;
+#[cfg(test)]
+pub mod test_db;
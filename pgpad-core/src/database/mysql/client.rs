@@ -0,0 +1,186 @@
+//! MySQL connections keep a session connection, which user queries run on one after the other, and a small pool
+//! of connections for metadata queries, so that those don't queue behind a long-running user query. Running
+//! queries are killed from a metadata connection, since the session is busy with them.
+
+use std::{
+    sync::{Arc, Weak},
+    time::Instant,
+};
+
+use anyhow::Context;
+use mysql_async::{prelude::Queryable, Conn, Opts, OptsBuilder, Pool, PoolConstraints, PoolOpts};
+use tokio::{sync::Mutex, time::MissedTickBehavior};
+
+use crate::{
+    database::{mysql::execute::DbError, ConnectionDropNotifier, PING_INTERVAL, PING_TIMEOUT},
+    Error,
+};
+
+/// Metadata connections are opened as they're needed, up to this many
+const METADATA_POOL_SIZE: usize = 2;
+
+#[derive(Debug)]
+pub struct MySqlClient {
+    session: Arc<Mutex<Conn>>,
+    /// Of the session connection, which its queries are killed by
+    session_id: u32,
+    metadata: Pool,
+    opts: Opts,
+}
+
+impl MySqlClient {
+    /// Opens the session connection. If a drop notifier is given, it's told how the session's pings go, and
+    /// notified once the session drops.
+    pub async fn connect(
+        opts: Opts,
+        drop_notifier: Option<ConnectionDropNotifier>,
+    ) -> Result<Self, Error> {
+        let session = open(&opts).await?;
+        let client = Self::with_session(session, &opts, Pool::new(metadata_opts(&opts)));
+
+        if let Some(drop_notifier) = drop_notifier {
+            tokio::spawn(monitor_session(
+                Arc::downgrade(&client.session),
+                drop_notifier,
+            ));
+        }
+        Ok(client)
+    }
+
+    fn with_session(session: Conn, opts: &Opts, metadata: Pool) -> Self {
+        Self {
+            session_id: session.id(),
+            session: Arc::new(Mutex::new(session)),
+            metadata,
+            opts: opts.clone(),
+        }
+    }
+
+    /// The connection user queries run on
+    pub fn session(&self) -> &Arc<Mutex<Conn>> {
+        &self.session
+    }
+
+    /// A connection for metadata queries, which doesn't wait on user queries. It goes back to the pool once
+    /// dropped.
+    pub async fn metadata(&self) -> Result<Conn, Error> {
+        Ok(self
+            .metadata
+            .get_conn()
+            .await
+            .context("Failed to open a MySQL connection for metadata queries")?)
+    }
+
+    /// A client whose session is a connection of its own, e.g. for a transaction that mustn't see the statements
+    /// run on this client's session. Its metadata connections are this client's.
+    pub async fn reserve_session(&self) -> Result<MySqlClient, Error> {
+        let session = open(&self.opts).await?;
+        Ok(Self::with_session(
+            session,
+            &self.opts,
+            self.metadata.clone(),
+        ))
+    }
+
+    /// Kills the user query running on the session connection, if any, leaving the connection open
+    pub async fn cancel_session_query(&self) -> Result<(), Error> {
+        self.metadata()
+            .await?
+            .query_drop(format!("KILL QUERY {}", self.session_id))
+            .await
+            .context("Failed to cancel the running query")?;
+        Ok(())
+    }
+}
+
+async fn open(opts: &Opts) -> Result<Conn, Error> {
+    Conn::new(opts.clone())
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to connect to MySQL: {}", DbError(&e)).into())
+}
+
+fn metadata_opts(opts: &Opts) -> OptsBuilder {
+    let constraints = PoolConstraints::new(0, METADATA_POOL_SIZE).expect("valid pool constraints");
+    OptsBuilder::from_opts(opts.clone())
+        .pool_opts(PoolOpts::default().with_constraints(constraints))
+}
+
+/// Pings the session while it's idle, until the client is dropped or the session is found to have dropped
+async fn monitor_session(session: Weak<Mutex<Conn>>, drop_notifier: ConnectionDropNotifier) {
+    let mut interval = tokio::time::interval(PING_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        let Some(session) = session.upgrade() else {
+            return;
+        };
+        // A busy session is alive, or will fail the query running on it and be pinged next time
+        let Ok(mut conn) = session.try_lock() else {
+            continue;
+        };
+        let started_at = Instant::now();
+        match tokio::time::timeout(PING_TIMEOUT, conn.ping()).await {
+            Ok(Ok(())) => drop_notifier.record_ping(Ok(started_at.elapsed())),
+            Ok(Err(e)) => {
+                log::warn!("The MySQL session dropped: {}", DbError(&e));
+                drop_notifier.notify();
+                return;
+            }
+            Err(_) => drop_notifier.record_ping(Err("The ping timed out".to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::database::mysql::test_db::TestDb;
+
+    #[tokio::test]
+    async fn kills_session_queries_from_a_metadata_connection() {
+        let Some(db) = TestDb::new().await else {
+            return;
+        };
+        let client = Arc::new(db.client().await);
+
+        let session = client.session().clone();
+        let sleeping = tokio::spawn(async move {
+            let slept: Option<i64> = session
+                .lock()
+                .await
+                .query_first("SELECT SLEEP(30)")
+                .await
+                .unwrap();
+            slept
+        });
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        client.cancel_session_query().await.unwrap();
+
+        // SLEEP returns 1 when it's interrupted
+        let slept = tokio::time::timeout(Duration::from_secs(10), sleeping)
+            .await
+            .expect("the query should be killed long before SLEEP is over")
+            .unwrap();
+        assert_eq!(slept, Some(1));
+
+        // Only the query is killed, and the session is kept
+        let one: Option<i64> = client
+            .session()
+            .lock()
+            .await
+            .query_first("SELECT 1")
+            .await
+            .unwrap();
+        assert_eq!(one, Some(1));
+
+        // A reserved session is a connection of its own, killed on its own
+        let reserved = client.reserve_session().await.unwrap();
+        assert_ne!(reserved.session_id, client.session_id);
+
+        drop((client, reserved));
+        db.close().await;
+    }
+}
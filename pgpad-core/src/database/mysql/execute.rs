@@ -0,0 +1,387 @@
+use std::{
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use mysql_async::{
+    prelude::{Protocol, Queryable},
+    Column, Conn, Params, QueryResult, ServerError, Value,
+};
+use serde_json::Value as Json;
+
+use crate::{
+    database::{
+        lineage::{self, ColumnLineage, SourceColumn},
+        mysql::row_writer::{self, RowWriter},
+        params::{self, QueryParams},
+        parser::ParsedStatement,
+        types::{ExecSender, SavepointOutcome, STATEMENT_SAVEPOINT},
+        QueryExecEvent,
+    },
+    utils::serialize_as_json_array,
+    Error,
+};
+
+/// `ER_QUERY_TIMEOUT`, MySQL's
+const QUERY_TIMEOUT: u16 = 3024;
+/// `ER_STATEMENT_TIMEOUT`, MariaDB's
+const STATEMENT_TIMEOUT: u16 = 1969;
+/// `ER_SP_DOES_NOT_EXIST`
+const SAVEPOINT_DOES_NOT_EXIST: u16 = 1305;
+
+pub struct DbError<'a>(pub &'a mysql_async::Error);
+
+impl Display for DbError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            mysql_async::Error::Server(err) if is_timeout(err) => f.write_str(
+                "Query timed out: the statement ran for longer than the connection's query timeout",
+            ),
+            mysql_async::Error::Server(err) => f.write_str(&err.message),
+            other => write!(f, "{other}"),
+        }
+    }
+}
+
+fn is_timeout(err: &ServerError) -> bool {
+    err.code == QUERY_TIMEOUT || err.code == STATEMENT_TIMEOUT
+}
+
+/// Sets how long the statements of a session may run before the server stops them. None restores the server's
+/// default. MySQL only limits `SELECT`s, with `max_execution_time`, while MariaDB limits every statement, with
+/// `max_statement_time`.
+pub async fn set_statement_timeout(
+    conn: &mut Conn,
+    timeout_ms: Option<u64>,
+) -> Result<(), mysql_async::Error> {
+    let (mysql, mariadb) = match timeout_ms {
+        Some(timeout_ms) => (
+            format!("SET SESSION max_execution_time = {timeout_ms}"),
+            format!(
+                "SET SESSION max_statement_time = {}",
+                timeout_ms as f64 / 1000.0
+            ),
+        ),
+        None => (
+            "SET SESSION max_execution_time = DEFAULT".to_owned(),
+            "SET SESSION max_statement_time = DEFAULT".to_owned(),
+        ),
+    };
+
+    match conn.query_drop(mysql).await {
+        Ok(()) => Ok(()),
+        // MariaDB doesn't have max_execution_time
+        Err(mysql_async::Error::Server(_)) => conn.query_drop(mariadb).await,
+        Err(e) => Err(e),
+    }
+}
+
+/// Runs a statement with the text protocol, which every statement can be run with, unlike prepared ones. Only
+/// statements with placeholders are prepared, to bind their values.
+pub async fn execute_query(
+    conn: &mut Conn,
+    stmt: ParsedStatement,
+    sender: &ExecSender,
+) -> Result<(), Error> {
+    let started_at = Instant::now();
+    log::info!("Executing MySQL query: {}", stmt.statement);
+
+    if stmt.placeholders.is_empty() {
+        return match conn.query_iter(stmt.statement.clone()).await {
+            Ok(result) => read_results(result, stmt, started_at, sender).await,
+            Err(e) => fail(&e, started_at, sender),
+        };
+    }
+
+    let prepared = match conn.prep(stmt.statement.as_str()).await {
+        Ok(prepared) => prepared,
+        Err(e) => return fail(&e, started_at, sender),
+    };
+    let values = match bind(prepared.num_params(), &stmt.params) {
+        Ok(values) => values,
+        Err(e) => {
+            sender.send(QueryExecEvent::Finished {
+                elapsed_ms: started_at.elapsed().as_millis() as u64,
+                affected_rows: 0,
+                error: Some(e.to_string()),
+            })?;
+            return Err(e);
+        }
+    };
+    match conn.exec_iter(&prepared, Params::Positional(values)).await {
+        Ok(result) => read_results(result, stmt, started_at, sender).await,
+        Err(e) => fail(&e, started_at, sender),
+    }
+}
+
+/// Like [`execute_query`], but in a savepoint, so that the statement's changes are undone if it fails and the
+/// rest of the transaction is kept.
+///
+/// The session's transaction status isn't exposed by the driver, so the savepoint is always taken: outside of a
+/// transaction it's gone as soon as the statement autocommits, and settling it then fails with no outcome to report.
+pub async fn execute_query_in_savepoint(
+    conn: &mut Conn,
+    stmt: ParsedStatement,
+    sender: &ExecSender,
+) -> Result<(), Error> {
+    // BEGIN, COMMIT and the like end the transaction the savepoint would be in
+    if stmt.controls_transaction
+        || conn
+            .query_drop(format!("SAVEPOINT {STATEMENT_SAVEPOINT}"))
+            .await
+            .is_err()
+    {
+        return execute_query(conn, stmt, sender).await;
+    }
+
+    let result = execute_query(conn, stmt, sender).await;
+
+    let (settle, outcome) = match result {
+        Ok(()) => (
+            format!("RELEASE SAVEPOINT {STATEMENT_SAVEPOINT}"),
+            SavepointOutcome::Released,
+        ),
+        Err(_) => (
+            format!(
+                "ROLLBACK TO SAVEPOINT {STATEMENT_SAVEPOINT}; RELEASE SAVEPOINT {STATEMENT_SAVEPOINT}"
+            ),
+            SavepointOutcome::RolledBack,
+        ),
+    };
+    match conn.query_drop(settle).await {
+        Ok(()) => sender.send(QueryExecEvent::Savepoint(outcome))?,
+        // Not in a transaction, or one the server rolled back on its own, e.g. after a deadlock
+        Err(mysql_async::Error::Server(err)) if err.code == SAVEPOINT_DOES_NOT_EXIST => {}
+        Err(e) => log::error!(
+            "Failed to settle the statement's savepoint: {}",
+            DbError(&e)
+        ),
+    }
+
+    result
+}
+
+/// The values of a prepared statement's placeholders, from `?1` on. MySQL converts them to the types the
+/// statement expects.
+fn bind(placeholders: u16, values: &QueryParams) -> Result<Vec<Value>, Error> {
+    (1..=placeholders)
+        .map(|idx| {
+            Ok(match params::value(values, &format!("?{idx}"))? {
+                Json::Null => Value::NULL,
+                Json::Bool(value) => Value::Int(*value as i64),
+                Json::Number(number) => match (number.as_i64(), number.as_u64()) {
+                    (Some(value), _) => Value::Int(value),
+                    (None, Some(value)) => Value::UInt(value),
+                    (None, None) => Value::Double(number.as_f64().unwrap_or_default()),
+                },
+                Json::String(text) => Value::Bytes(text.clone().into_bytes()),
+                other => Value::Bytes(other.to_string().into_bytes()),
+            })
+        })
+        .collect()
+}
+
+async fn read_results<P: Protocol>(
+    mut result: QueryResult<'_, '_, P>,
+    stmt: ParsedStatement,
+    started_at: Instant,
+    sender: &ExecSender,
+) -> Result<(), Error> {
+    if !stmt.returns_values {
+        let affected_rows = result.affected_rows();
+        if let Err(e) = result.drop_result().await {
+            return fail(&e, started_at, sender);
+        }
+        sender.send(QueryExecEvent::Finished {
+            elapsed_ms: started_at.elapsed().as_millis() as u64,
+            affected_rows: affected_rows as usize,
+            error: None,
+        })?;
+        return Ok(());
+    }
+
+    let columns = result.columns().unwrap_or_else(|| Arc::from(Vec::new()));
+    let names: Vec<_> = columns.iter().map(|col| col.name_str()).collect();
+    sender.send(QueryExecEvent::TypesResolved {
+        columns: serialize_as_json_array(names.iter().map(|name| name.as_ref()))?,
+        column_types: columns
+            .iter()
+            .map(|col| row_writer::type_name(col).map(str::to_owned))
+            .collect(),
+    })?;
+
+    let needs_driver_lineage = stmt.lineage.as_ref().is_none_or(|lineage| {
+        lineage
+            .iter()
+            .any(|column| matches!(column, ColumnLineage::Unknown))
+    });
+    if needs_driver_lineage && columns.iter().any(|col| !col.org_table_ref().is_empty()) {
+        sender.send(QueryExecEvent::LineageResolved(
+            lineage::merge_driver_lineage(stmt.lineage, driver_lineage(&columns)),
+        ))?;
+    }
+
+    let batch_size = 50;
+    let mut total_rows = 0;
+    let mut writer = RowWriter::new(&columns);
+    let mut serialize_time = Duration::ZERO;
+
+    loop {
+        match result.next().await {
+            Ok(Some(row)) => {
+                let serialize_start = Instant::now();
+                writer.add_row(&row)?;
+                serialize_time += serialize_start.elapsed();
+
+                total_rows += 1;
+
+                if writer.len() >= batch_size {
+                    sender.send(QueryExecEvent::Page {
+                        page_amount: writer.len(),
+                        page: writer.finish(),
+                    })?;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                sender.send(QueryExecEvent::Serialized(serialize_time))?;
+                return fail(&e, started_at, sender);
+            }
+        }
+    }
+
+    if !writer.is_empty() {
+        sender.send(QueryExecEvent::Page {
+            page_amount: writer.len(),
+            page: writer.finish(),
+        })?;
+    }
+
+    // e.g. the status result set that follows the rows of a CALL
+    if let Err(e) = result.drop_result().await {
+        log::warn!("Failed to read the rest of the results: {}", DbError(&e));
+    }
+
+    sender.send(QueryExecEvent::Serialized(serialize_time))?;
+    sender.send(QueryExecEvent::Finished {
+        elapsed_ms: started_at.elapsed().as_millis() as u64,
+        affected_rows: 0,
+        error: None,
+    })?;
+
+    log::info!(
+        "Streaming query completed: {} rows in {}ms",
+        total_rows,
+        started_at.elapsed().as_millis()
+    );
+
+    Ok(())
+}
+
+fn fail(e: &mysql_async::Error, started_at: Instant, sender: &ExecSender) -> Result<(), Error> {
+    log::error!("MySQL query failed: {:?}", e);
+    let error_msg = DbError(e).to_string();
+
+    sender.send(QueryExecEvent::Finished {
+        elapsed_ms: started_at.elapsed().as_millis() as u64,
+        affected_rows: 0,
+        error: Some(error_msg.clone()),
+    })?;
+
+    Err(Error::Any(anyhow::anyhow!(error_msg)))
+}
+
+/// The table column each result column came from, as MySQL sends along with the columns
+fn driver_lineage(columns: &[Column]) -> Vec<Option<SourceColumn>> {
+    columns
+        .iter()
+        .map(|col| {
+            (!col.org_table_ref().is_empty()).then(|| SourceColumn {
+                table: Some(col.org_table_str().into_owned()),
+                column: col.org_name_str().into_owned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use mysql_async::consts::ColumnType;
+
+    use super::*;
+
+    #[test]
+    fn reads_lineage_from_columns() {
+        let columns = [
+            Column::new(ColumnType::MYSQL_TYPE_LONG)
+                .with_name(b"user_id")
+                .with_org_table(b"users")
+                .with_org_name(b"id"),
+            Column::new(ColumnType::MYSQL_TYPE_LONGLONG).with_name(b"count(*)"),
+        ];
+
+        assert_eq!(
+            driver_lineage(&columns),
+            [
+                Some(SourceColumn {
+                    table: Some("users".to_owned()),
+                    column: "id".to_owned(),
+                }),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_timeouts() {
+        let timeout = mysql_async::Error::Server(ServerError {
+            code: QUERY_TIMEOUT,
+            message: "Query execution was interrupted, maximum statement execution time exceeded"
+                .to_owned(),
+            state: "HY000".to_owned(),
+        });
+        assert_eq!(
+            DbError(&timeout).to_string(),
+            "Query timed out: the statement ran for longer than the connection's query timeout"
+        );
+
+        let missing = mysql_async::Error::Server(ServerError {
+            code: 1146,
+            message: "Table 'shop.missing' doesn't exist".to_owned(),
+            state: "42S02".to_owned(),
+        });
+        assert_eq!(
+            DbError(&missing).to_string(),
+            "Table 'shop.missing' doesn't exist"
+        );
+    }
+
+    #[test]
+    fn binds_values_by_position() {
+        let values = QueryParams::from([
+            ("?1".to_owned(), Json::from(true)),
+            ("?2".to_owned(), Json::from(u64::MAX)),
+            ("?3".to_owned(), Json::from(-2.5)),
+            ("?4".to_owned(), Json::from("2024-02-29 12:00:00")),
+            ("?5".to_owned(), Json::Null),
+            ("?6".to_owned(), serde_json::json!({ "tags": ["a"] })),
+        ]);
+        assert_eq!(
+            bind(6, &values).unwrap(),
+            [
+                Value::Int(1),
+                Value::UInt(u64::MAX),
+                Value::Double(-2.5),
+                Value::Bytes(b"2024-02-29 12:00:00".to_vec()),
+                Value::NULL,
+                Value::Bytes(br#"{"tags":["a"]}"#.to_vec()),
+            ]
+        );
+
+        assert!(bind(7, &values)
+            .unwrap_err()
+            .to_string()
+            .contains("No value given for parameter ?7"));
+    }
+}
@@ -0,0 +1,71 @@
+use sqlparser::{ast::Statement, dialect::MySqlDialect};
+
+use crate::database::{
+    self,
+    parser::{ParsedStatement, SqlDialectExt},
+};
+
+pub fn parse_statements(query: &str) -> anyhow::Result<Vec<ParsedStatement>> {
+    database::parser::parse_statements(&MySqlDialect {}, query)
+}
+
+impl SqlDialectExt for MySqlDialect {
+    fn returns_values(stmt: &Statement) -> bool {
+        match stmt {
+            Statement::Query { .. } => true,
+            // MariaDB supports RETURNING, MySQL doesn't
+            Statement::Insert(insert) if insert.returning.is_some() => true,
+            Statement::Delete(delete) if delete.returning.is_some() => true,
+            Statement::Explain { .. } => true,
+            Statement::ExplainTable { .. } => true,
+            Statement::Call(_) => true,
+            Statement::ShowVariable { .. }
+            | Statement::ShowStatus { .. }
+            | Statement::ShowVariables { .. }
+            | Statement::ShowCreate { .. }
+            | Statement::ShowColumns { .. }
+            | Statement::ShowDatabases { .. }
+            | Statement::ShowSchemas { .. }
+            | Statement::ShowTables { .. }
+            | Statement::ShowViews { .. }
+            | Statement::ShowFunctions { .. }
+            | Statement::ShowCollation { .. }
+            | Statement::ShowCharset(_) => true,
+            _ => false,
+        }
+    }
+
+    /// MySQL only has positional placeholders
+    fn binds_placeholder(name: &str) -> bool {
+        name == "?"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_statements() {
+        let query = r#"
+            CREATE TABLE users (id INT AUTO_INCREMENT PRIMARY KEY, name VARCHAR(50));
+            INSERT INTO users (name) VALUES ('Alice'), ('Bob');
+            SELECT `id`, name FROM users WHERE name LIKE 'A%';
+            SHOW TABLES;
+            SHOW CREATE TABLE users;
+            DESCRIBE users;
+            EXPLAIN SELECT * FROM users;
+            UPDATE users SET name = 'Carol' WHERE id = 2;
+            DELETE FROM users WHERE id = 1 RETURNING id;
+            DROP TABLE users;
+        "#;
+
+        let results = parse_statements(query).unwrap();
+        let returns_values: Vec<_> = results.iter().map(|r| r.returns_values).collect();
+        assert_eq!(
+            returns_values,
+            [false, false, true, true, true, true, true, false, true, false]
+        );
+        assert!(results[2].statement.contains("`id`"));
+    }
+}
@@ -0,0 +1,346 @@
+use std::fmt::Write;
+
+use mysql_async::{
+    consts::{ColumnFlags, ColumnType},
+    Column, Row, Value,
+};
+use serde_json::value::RawValue;
+
+use crate::utils;
+
+/// The character set of binary strings and of the columns that aren't strings
+const BINARY_CHARSET: u16 = 63;
+
+/// How the values of a column are written, since the text protocol sends them all as bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    /// `TINYINT(1)`, which is what `BOOLEAN` is
+    Bool,
+    /// Integers and floats, written as they're sent. `DECIMAL`s are written as strings instead, like Postgres'
+    /// `NUMERIC`s, so that they keep their precision.
+    Number,
+    /// `BIT(n)`, sent big-endian
+    Bit {
+        width: u32,
+    },
+    Json,
+    Text,
+}
+
+impl ValueKind {
+    fn of(column: &Column) -> Self {
+        use ColumnType::*;
+
+        match column.column_type() {
+            MYSQL_TYPE_TINY if column.column_length() == 1 => Self::Bool,
+            MYSQL_TYPE_TINY | MYSQL_TYPE_SHORT | MYSQL_TYPE_INT24 | MYSQL_TYPE_LONG
+            | MYSQL_TYPE_LONGLONG | MYSQL_TYPE_YEAR | MYSQL_TYPE_FLOAT | MYSQL_TYPE_DOUBLE => {
+                Self::Number
+            }
+            MYSQL_TYPE_BIT => Self::Bit {
+                width: column.column_length(),
+            },
+            MYSQL_TYPE_JSON => Self::Json,
+            _ => Self::Text,
+        }
+    }
+}
+
+/// The name of a column's type, as MySQL spells it
+pub fn type_name(column: &Column) -> Option<&'static str> {
+    use ColumnType::*;
+
+    let binary = column.character_set() == BINARY_CHARSET;
+    let flags = column.flags();
+    let name = match column.column_type() {
+        MYSQL_TYPE_TINY if column.column_length() == 1 => "boolean",
+        MYSQL_TYPE_TINY => "tinyint",
+        MYSQL_TYPE_SHORT => "smallint",
+        MYSQL_TYPE_INT24 => "mediumint",
+        MYSQL_TYPE_LONG => "int",
+        MYSQL_TYPE_LONGLONG => "bigint",
+        MYSQL_TYPE_FLOAT => "float",
+        MYSQL_TYPE_DOUBLE => "double",
+        MYSQL_TYPE_DECIMAL | MYSQL_TYPE_NEWDECIMAL => "decimal",
+        MYSQL_TYPE_BIT => "bit",
+        MYSQL_TYPE_YEAR => "year",
+        MYSQL_TYPE_DATE | MYSQL_TYPE_NEWDATE => "date",
+        MYSQL_TYPE_TIME | MYSQL_TYPE_TIME2 => "time",
+        MYSQL_TYPE_DATETIME | MYSQL_TYPE_DATETIME2 => "datetime",
+        MYSQL_TYPE_TIMESTAMP | MYSQL_TYPE_TIMESTAMP2 => "timestamp",
+        MYSQL_TYPE_JSON => "json",
+        MYSQL_TYPE_GEOMETRY => "geometry",
+        MYSQL_TYPE_ENUM => "enum",
+        MYSQL_TYPE_SET => "set",
+        // Enums and sets are sent as strings, with a flag
+        MYSQL_TYPE_STRING if flags.contains(ColumnFlags::ENUM_FLAG) => "enum",
+        MYSQL_TYPE_STRING if flags.contains(ColumnFlags::SET_FLAG) => "set",
+        MYSQL_TYPE_STRING if binary => "binary",
+        MYSQL_TYPE_STRING => "char",
+        MYSQL_TYPE_VAR_STRING | MYSQL_TYPE_VARCHAR if binary => "varbinary",
+        MYSQL_TYPE_VAR_STRING | MYSQL_TYPE_VARCHAR => "varchar",
+        MYSQL_TYPE_TINY_BLOB | MYSQL_TYPE_MEDIUM_BLOB | MYSQL_TYPE_LONG_BLOB | MYSQL_TYPE_BLOB
+            if binary =>
+        {
+            "blob"
+        }
+        MYSQL_TYPE_TINY_BLOB | MYSQL_TYPE_MEDIUM_BLOB | MYSQL_TYPE_LONG_BLOB | MYSQL_TYPE_BLOB => {
+            "text"
+        }
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// A somewhat efficient way of converting the raw MySQL query results into a JSON string.
+/// Think of this as a writer of Vec<Vec<Json>>.
+pub struct RowWriter {
+    buf: String,
+    row_count: usize,
+    kinds: Vec<ValueKind>,
+}
+
+impl RowWriter {
+    pub fn new(columns: &[Column]) -> Self {
+        Self {
+            buf: String::new(),
+            row_count: 0,
+            kinds: columns.iter().map(ValueKind::of).collect(),
+        }
+    }
+
+    pub fn add_row(&mut self, row: &Row) -> Result<(), anyhow::Error> {
+        self.add_values((0..row.len()).map(|idx| row.as_ref(idx)))
+    }
+
+    fn add_values<'a>(
+        &mut self,
+        values: impl Iterator<Item = Option<&'a Value>>,
+    ) -> Result<(), anyhow::Error> {
+        if self.row_count == 0 {
+            self.buf.reserve(2);
+            self.buf.push('[');
+        }
+
+        if self.row_count > 0 {
+            self.buf.push(',');
+        }
+
+        self.buf.push('[');
+        for (idx, value) in values.enumerate() {
+            if idx > 0 {
+                self.buf.push(',');
+            }
+            let kind = self.kinds.get(idx).copied().unwrap_or(ValueKind::Text);
+            self.write_value(kind, value)?;
+        }
+        self.buf.push(']');
+        self.row_count += 1;
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.row_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.row_count == 0
+    }
+
+    pub fn finish(&mut self) -> Box<RawValue> {
+        let page = utils::finish_page(&mut self.buf, self.row_count);
+        self.row_count = 0;
+        page
+    }
+
+    fn write_value(&mut self, kind: ValueKind, value: Option<&Value>) -> Result<(), anyhow::Error> {
+        let bytes = match value {
+            None | Some(Value::NULL) => {
+                self.buf.push_str("null");
+                return Ok(());
+            }
+            Some(Value::Bytes(bytes)) => bytes,
+            // Only sent by the binary protocol, for prepared statements
+            Some(Value::Int(value @ (0 | 1))) if kind == ValueKind::Bool => {
+                return Ok(write!(&mut self.buf, "{}", *value == 1)?)
+            }
+            Some(Value::Int(value)) => {
+                self.buf.push_str(itoa::Buffer::new().format(*value));
+                return Ok(());
+            }
+            Some(Value::UInt(value)) => {
+                self.buf.push_str(itoa::Buffer::new().format(*value));
+                return Ok(());
+            }
+            Some(Value::Float(value)) if value.is_finite() => {
+                return Ok(write!(&mut self.buf, "{value}")?)
+            }
+            Some(Value::Double(value)) if value.is_finite() => {
+                return Ok(write!(&mut self.buf, "{value}")?)
+            }
+            Some(other) => {
+                self.write_json_string(other.as_sql(true).trim_matches('\''));
+                return Ok(());
+            }
+        };
+
+        match kind {
+            ValueKind::Bool if bytes.as_slice() == b"0" => self.buf.push_str("false"),
+            ValueKind::Bool if bytes.as_slice() == b"1" => self.buf.push_str("true"),
+            ValueKind::Bool | ValueKind::Number if is_number(bytes) => {
+                // Checked to be an ASCII number
+                self.buf.push_str(std::str::from_utf8(bytes)?);
+            }
+            ValueKind::Bit { width } if bytes.len() <= 8 => {
+                let value = bytes
+                    .iter()
+                    .fold(0u64, |value, byte| (value << 8) | *byte as u64);
+                if width == 1 {
+                    write!(&mut self.buf, "{}", value == 1)?;
+                } else {
+                    write!(&mut self.buf, "{value}")?;
+                }
+            }
+            ValueKind::Json if utils::is_json(bytes) => {
+                self.buf.push_str(std::str::from_utf8(bytes)?);
+            }
+            _ => match std::str::from_utf8(bytes) {
+                Ok(text) => self.write_json_string(text),
+                Err(_) => self.write_json_string(&format!("\\x{}", hex::encode(bytes))),
+            },
+        }
+
+        Ok(())
+    }
+
+    fn write_json_string(&mut self, s: &str) {
+        utils::write_json_string(&mut self.buf, s);
+    }
+}
+
+/// Whether a value is a number JSON can hold as is, rather than e.g. `inf`
+fn is_number(bytes: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Number>(bytes).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value as Json};
+
+    use super::*;
+
+    fn column(column_type: ColumnType) -> Column {
+        Column::new(column_type).with_character_set(BINARY_CHARSET)
+    }
+
+    fn bytes(value: &[u8]) -> Value {
+        Value::Bytes(value.to_vec())
+    }
+
+    #[test]
+    fn writes_values_by_column_type() {
+        let columns = [
+            column(ColumnType::MYSQL_TYPE_TINY).with_column_length(1),
+            column(ColumnType::MYSQL_TYPE_TINY).with_column_length(4),
+            column(ColumnType::MYSQL_TYPE_LONGLONG),
+            column(ColumnType::MYSQL_TYPE_DOUBLE),
+            column(ColumnType::MYSQL_TYPE_NEWDECIMAL),
+            column(ColumnType::MYSQL_TYPE_BIT).with_column_length(1),
+            column(ColumnType::MYSQL_TYPE_BIT).with_column_length(16),
+            column(ColumnType::MYSQL_TYPE_JSON),
+            Column::new(ColumnType::MYSQL_TYPE_VAR_STRING).with_character_set(255),
+            column(ColumnType::MYSQL_TYPE_BLOB),
+            column(ColumnType::MYSQL_TYPE_DATETIME),
+        ];
+        let mut writer = RowWriter::new(&columns);
+
+        let row = [
+            bytes(b"1"),
+            bytes(b"-12"),
+            bytes(b"18446744073709551615"),
+            bytes(b"1.5e-7"),
+            bytes(b"12345678901234567890.123"),
+            bytes(&[1]),
+            bytes(&[1, 2]),
+            bytes(br#"{"tags": ["a"]}"#),
+            bytes("quote \" and ünïcode\n".as_bytes()),
+            bytes(&[0, 255]),
+            bytes(b"2024-02-29 12:00:00"),
+        ];
+        writer.add_values(row.iter().map(Some)).unwrap();
+
+        let row = [
+            bytes(b"0"),
+            Value::NULL,
+            Value::Int(-1),
+            bytes(b"inf"),
+            Value::NULL,
+            bytes(&[0]),
+            bytes(&[0]),
+            bytes(b"not json"),
+            bytes(b""),
+            bytes(b"plain"),
+            Value::NULL,
+        ];
+        writer.add_values(row.iter().map(Some)).unwrap();
+        assert_eq!(writer.len(), 2);
+
+        let page: Json = serde_json::from_str(writer.finish().get()).unwrap();
+        assert_eq!(
+            page,
+            json!([
+                [
+                    true,
+                    -12,
+                    18446744073709551615u64,
+                    1.5e-7,
+                    "12345678901234567890.123",
+                    true,
+                    258,
+                    {"tags": ["a"]},
+                    "quote \" and ünïcode\n",
+                    "\\x00ff",
+                    "2024-02-29 12:00:00"
+                ],
+                [false, null, -1, "inf", null, false, 0, "not json", "", "plain", null]
+            ])
+        );
+
+        assert!(writer.is_empty());
+        assert_eq!(writer.finish().get(), "[]");
+    }
+
+    #[test]
+    fn names_column_types() {
+        let names: Vec<_> = [
+            column(ColumnType::MYSQL_TYPE_TINY).with_column_length(1),
+            column(ColumnType::MYSQL_TYPE_LONG),
+            Column::new(ColumnType::MYSQL_TYPE_VAR_STRING).with_character_set(255),
+            column(ColumnType::MYSQL_TYPE_VAR_STRING),
+            Column::new(ColumnType::MYSQL_TYPE_STRING)
+                .with_character_set(255)
+                .with_flags(ColumnFlags::ENUM_FLAG),
+            column(ColumnType::MYSQL_TYPE_BLOB),
+            Column::new(ColumnType::MYSQL_TYPE_BLOB).with_character_set(255),
+            column(ColumnType::MYSQL_TYPE_NULL),
+        ]
+        .iter()
+        .map(type_name)
+        .collect();
+
+        assert_eq!(
+            names,
+            [
+                Some("boolean"),
+                Some("int"),
+                Some("varchar"),
+                Some("varbinary"),
+                Some("enum"),
+                Some("blob"),
+                Some("text"),
+                None
+            ]
+        );
+    }
+}
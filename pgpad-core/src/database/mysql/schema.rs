@@ -0,0 +1,450 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::Context;
+use mysql_async::{prelude::Queryable, Conn};
+
+use crate::{
+    database::types::{ColumnInfo, DatabaseSchema, ForeignKey, ReferentialAction, TableInfo},
+    utils::quote_mysql_identifier,
+    Error,
+};
+
+/// The tables of every database the user can see, the server's own aside. MySQL's databases are what the other
+/// databases call schemas.
+const SCHEMA_QUERY: &str = r#"
+    SELECT
+        c.TABLE_SCHEMA,
+        c.TABLE_NAME,
+        c.COLUMN_NAME,
+        c.COLUMN_TYPE,
+        c.IS_NULLABLE = 'YES',
+        c.COLUMN_DEFAULT
+    FROM
+        information_schema.TABLES t
+    JOIN
+        information_schema.COLUMNS c
+        ON t.TABLE_NAME = c.TABLE_NAME
+        AND t.TABLE_SCHEMA = c.TABLE_SCHEMA
+    WHERE
+        t.TABLE_TYPE = 'BASE TABLE'
+        AND t.TABLE_SCHEMA NOT IN ('information_schema', 'mysql', 'performance_schema', 'sys')
+    ORDER BY
+        c.TABLE_SCHEMA, c.TABLE_NAME, c.ORDINAL_POSITION
+"#;
+
+/// The columns of every foreign key, in the order of the key
+const FOREIGN_KEYS_QUERY: &str = r#"
+    SELECT
+        k.TABLE_SCHEMA,
+        k.TABLE_NAME,
+        k.CONSTRAINT_NAME,
+        k.COLUMN_NAME,
+        k.REFERENCED_TABLE_SCHEMA,
+        k.REFERENCED_TABLE_NAME,
+        k.REFERENCED_COLUMN_NAME,
+        r.DELETE_RULE,
+        r.UPDATE_RULE
+    FROM
+        information_schema.KEY_COLUMN_USAGE k
+    JOIN
+        information_schema.REFERENTIAL_CONSTRAINTS r
+        ON r.CONSTRAINT_SCHEMA = k.CONSTRAINT_SCHEMA
+        AND r.TABLE_NAME = k.TABLE_NAME
+        AND r.CONSTRAINT_NAME = k.CONSTRAINT_NAME
+    WHERE
+        k.REFERENCED_TABLE_NAME IS NOT NULL
+        AND k.TABLE_SCHEMA NOT IN ('information_schema', 'mysql', 'performance_schema', 'sys')
+    ORDER BY
+        k.TABLE_SCHEMA, k.TABLE_NAME, k.CONSTRAINT_NAME, k.ORDINAL_POSITION
+"#;
+
+/// The columns of every primary key, in the order of the key
+const PRIMARY_KEYS_QUERY: &str = r#"
+    SELECT
+        TABLE_SCHEMA,
+        TABLE_NAME,
+        COLUMN_NAME
+    FROM
+        information_schema.KEY_COLUMN_USAGE
+    WHERE
+        CONSTRAINT_NAME = 'PRIMARY'
+        AND TABLE_SCHEMA NOT IN ('information_schema', 'mysql', 'performance_schema', 'sys')
+    ORDER BY
+        TABLE_SCHEMA, TABLE_NAME, ORDINAL_POSITION
+"#;
+
+/// The rows of every table as InnoDB estimates them, which can be off by half
+const ROW_ESTIMATES_QUERY: &str = r#"
+    SELECT
+        TABLE_SCHEMA,
+        TABLE_NAME,
+        TABLE_ROWS
+    FROM
+        information_schema.TABLES
+    WHERE
+        TABLE_TYPE = 'BASE TABLE'
+        AND TABLE_SCHEMA NOT IN ('information_schema', 'mysql', 'performance_schema', 'sys')
+"#;
+
+/// Schema, table, column, type, whether it's nullable and its default
+type ColumnRow = (String, String, String, String, bool, Option<String>);
+
+/// Schema, table, constraint name, column, the schema, table and column it references, and the delete and update
+/// rules
+type ForeignKeyRow = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+);
+
+/// Schema, table and column
+type PrimaryKeyRow = (String, String, String);
+
+pub async fn get_database_schema(conn: &mut Conn) -> Result<DatabaseSchema, Error> {
+    let rows: Vec<ColumnRow> = conn
+        .query(SCHEMA_QUERY)
+        .await
+        .context("Failed to query database schema")?;
+    // Tables are still of use without their foreign keys
+    let foreign_keys: Vec<ForeignKeyRow> =
+        conn.query(FOREIGN_KEYS_QUERY).await.unwrap_or_else(|err| {
+            log::warn!("Failed to read foreign keys: {err}");
+            Vec::new()
+        });
+
+    let primary_keys: Vec<PrimaryKeyRow> =
+        conn.query(PRIMARY_KEYS_QUERY).await.unwrap_or_else(|err| {
+            log::warn!("Failed to read primary keys: {err}");
+            Vec::new()
+        });
+
+    let estimates: Vec<(String, String, Option<i64>)> =
+        conn.query(ROW_ESTIMATES_QUERY).await.unwrap_or_else(|err| {
+            log::warn!("Failed to read row estimates: {err}");
+            Vec::new()
+        });
+
+    let mut schema = schema_from_rows(rows, foreign_keys, primary_keys);
+    for (table_schema, table_name, rows) in estimates {
+        if let Some(table) = schema
+            .tables
+            .iter_mut()
+            .find(|table| table.schema == table_schema && table.name == table_name)
+        {
+            table.row_estimate = rows;
+        }
+    }
+    Ok(schema)
+}
+
+/// The columns of a single table, in the table's own order
+pub async fn get_table_columns(
+    conn: &mut Conn,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<ColumnInfo>, Error> {
+    let rows: Vec<(String, String, bool, Option<String>)> = conn
+        .exec(
+            "SELECT COLUMN_NAME, COLUMN_TYPE, IS_NULLABLE = 'YES', COLUMN_DEFAULT
+             FROM information_schema.COLUMNS
+             WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ?
+             ORDER BY ORDINAL_POSITION",
+            (schema, table),
+        )
+        .await
+        .with_context(|| format!("Failed to read the columns of {schema}.{table}"))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(name, data_type, is_nullable, default_value)| ColumnInfo {
+            name,
+            data_type,
+            is_nullable,
+            default_value,
+        })
+        .collect())
+}
+
+/// Rows in a table, counted however many there are
+pub async fn count_rows(conn: &mut Conn, schema: &str, table: &str) -> Result<u64, Error> {
+    let count: Option<u64> = conn
+        .query_first(format!(
+            "SELECT count(*) FROM {}.{}",
+            quote_mysql_identifier(schema),
+            quote_mysql_identifier(table)
+        ))
+        .await
+        .with_context(|| format!("Failed to count the rows of {schema}.{table}"))?;
+    Ok(count.unwrap_or_default())
+}
+
+fn schema_from_rows(
+    rows: Vec<ColumnRow>,
+    foreign_keys: Vec<ForeignKeyRow>,
+    primary_keys: Vec<PrimaryKeyRow>,
+) -> DatabaseSchema {
+    // Key is (schema, table_name)
+    let mut tables_map: BTreeMap<(String, String), TableInfo> = BTreeMap::new();
+    let mut unique_columns_set = HashSet::new();
+
+    for (schema, table_name, column_name, data_type, is_nullable, default_value) in rows {
+        unique_columns_set.insert(column_name.clone());
+
+        let table_info = tables_map
+            .entry((schema.clone(), table_name.clone()))
+            .or_insert_with(|| TableInfo {
+                name: table_name,
+                schema,
+                columns: Vec::new(),
+                ..Default::default()
+            });
+
+        table_info.columns.push(ColumnInfo {
+            name: column_name,
+            data_type,
+            is_nullable,
+            default_value,
+        });
+    }
+
+    let mut constraint = None;
+    for (
+        schema,
+        table,
+        name,
+        column,
+        referenced_schema,
+        referenced_table,
+        referenced_column,
+        delete_rule,
+        update_rule,
+    ) in foreign_keys
+    {
+        let Some(table_info) = tables_map.get_mut(&(schema.clone(), table.clone())) else {
+            continue;
+        };
+        // Rows are ordered by constraint, so a new one starts a new key
+        let key = (schema, table, name);
+        if constraint.as_ref() != Some(&key) {
+            table_info.foreign_keys.push(ForeignKey {
+                columns: Vec::new(),
+                referenced_schema,
+                referenced_table,
+                referenced_columns: Vec::new(),
+                name: Some(key.2.clone()),
+                on_delete: ReferentialAction::from_rule(&delete_rule),
+                on_update: ReferentialAction::from_rule(&update_rule),
+            });
+            constraint = Some(key);
+        }
+        let foreign_key = table_info.foreign_keys.last_mut().unwrap();
+        foreign_key.columns.push(column);
+        foreign_key.referenced_columns.push(referenced_column);
+    }
+
+    // Rows are ordered by position in the key
+    for (schema, table, column) in primary_keys {
+        if let Some(table_info) = tables_map.get_mut(&(schema, table)) {
+            table_info.primary_key.push(column);
+        }
+    }
+
+    let mut schemas: Vec<String> = tables_map
+        .keys()
+        .map(|(schema, _)| schema.clone())
+        .collect();
+    schemas.dedup();
+    let tables = tables_map.into_values().collect();
+
+    DatabaseSchema::new(tables, schemas, unique_columns_set.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(schema: &str, table: &str, column: &str, data_type: &str) -> ColumnRow {
+        (
+            schema.to_owned(),
+            table.to_owned(),
+            column.to_owned(),
+            data_type.to_owned(),
+            false,
+            None,
+        )
+    }
+
+    #[test]
+    fn groups_columns_by_table() {
+        let mut email = row("shop", "users", "email", "varchar(255)");
+        email.4 = true;
+        email.5 = Some("''".to_owned());
+
+        let schema = schema_from_rows(
+            vec![
+                row("shop", "users", "id", "int unsigned"),
+                email,
+                row("analytics", "events", "id", "bigint"),
+                row("shop", "orders", "user_id", "int unsigned"),
+            ],
+            vec![],
+            vec![],
+        );
+
+        assert_eq!(schema.schemas, ["analytics", "shop"]);
+        let tables: Vec<_> = schema
+            .tables
+            .iter()
+            .map(|table| {
+                let columns: Vec<_> = table.columns.iter().map(|c| c.name.as_str()).collect();
+                (table.schema.as_str(), table.name.as_str(), columns)
+            })
+            .collect();
+        assert_eq!(
+            tables,
+            [
+                ("analytics", "events", vec!["id"]),
+                ("shop", "orders", vec!["user_id"]),
+                ("shop", "users", vec!["id", "email"]),
+            ]
+        );
+
+        let email = &schema.tables[2].columns[1];
+        assert_eq!(email.data_type, "varchar(255)");
+        assert!(email.is_nullable);
+        assert_eq!(email.default_value.as_deref(), Some("''"));
+        assert_eq!(schema.unique_columns, ["email", "id", "user_id"]);
+    }
+
+    #[test]
+    fn groups_foreign_keys_by_constraint() {
+        let key = |table: &str, name: &str, column: &str, referenced: &str| -> ForeignKeyRow {
+            let (referenced_table, referenced_column) = referenced.split_once('.').unwrap();
+            (
+                "shop".to_owned(),
+                table.to_owned(),
+                name.to_owned(),
+                column.to_owned(),
+                "shop".to_owned(),
+                referenced_table.to_owned(),
+                referenced_column.to_owned(),
+                "CASCADE".to_owned(),
+                "NO ACTION".to_owned(),
+            )
+        };
+
+        let schema = schema_from_rows(
+            vec![
+                row("shop", "orders", "region", "char(2)"),
+                row("shop", "orders", "number", "int"),
+                row("shop", "lines", "order_region", "char(2)"),
+                row("shop", "lines", "order_number", "int"),
+                row("shop", "lines", "product_id", "int"),
+            ],
+            vec![
+                key("lines", "lines_order", "order_region", "orders.region"),
+                key("lines", "lines_order", "order_number", "orders.number"),
+                key("lines", "lines_product", "product_id", "products.id"),
+                key("missing", "missing_order", "order_number", "orders.number"),
+            ],
+            vec![
+                ("shop".to_owned(), "orders".to_owned(), "region".to_owned()),
+                ("shop".to_owned(), "orders".to_owned(), "number".to_owned()),
+            ],
+        );
+
+        let lines = schema.table("shop", "lines").unwrap();
+        let keys: Vec<_> = lines
+            .foreign_keys
+            .iter()
+            .map(|key| {
+                (
+                    key.columns.join(", "),
+                    key.referenced_table.as_str(),
+                    key.referenced_columns.join(", "),
+                )
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                (
+                    "order_region, order_number".to_owned(),
+                    "orders",
+                    "region, number".to_owned()
+                ),
+                ("product_id".to_owned(), "products", "id".to_owned()),
+            ]
+        );
+        assert!(schema
+            .table("shop", "orders")
+            .unwrap()
+            .foreign_keys
+            .is_empty());
+
+        assert_eq!(lines.foreign_keys[0].name.as_deref(), Some("lines_order"));
+        assert_eq!(lines.foreign_keys[0].on_delete, ReferentialAction::Cascade);
+        assert_eq!(lines.foreign_keys[0].on_update, ReferentialAction::NoAction);
+        assert_eq!(
+            schema.table("shop", "orders").unwrap().primary_key,
+            ["region", "number"]
+        );
+        assert!(lines.primary_key.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reads_schemas_from_the_server() {
+        let Some(db) = crate::database::mysql::test_db::TestDb::new().await else {
+            return;
+        };
+        let mut conn = db.conn().await;
+        conn.query_drop(
+            "CREATE TABLE customers (id int PRIMARY KEY, email varchar(255) NOT NULL DEFAULT '');
+            CREATE TABLE orders (
+                id bigint PRIMARY KEY,
+                customer_id int,
+                CONSTRAINT orders_customer FOREIGN KEY (customer_id) REFERENCES customers (id)
+            );
+            INSERT INTO customers (id) VALUES (1), (2);",
+        )
+        .await
+        .unwrap();
+
+        let schema = get_database_schema(&mut conn).await.unwrap();
+        let orders = schema.table(&db.name, "orders").unwrap();
+        assert_eq!(orders.primary_key, ["id"]);
+        assert_eq!(orders.foreign_keys[0].referenced_table, "customers");
+
+        let columns = get_table_columns(&mut conn, &db.name, "customers")
+            .await
+            .unwrap();
+        let names: Vec<_> = columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["id", "email"]);
+        // `int(11)` before MySQL 8.0.19 and on MariaDB
+        assert!(columns[0].data_type.starts_with("int"));
+        assert_eq!(columns[1].data_type, "varchar(255)");
+        assert!(!columns[1].is_nullable);
+        // MariaDB quotes string defaults
+        assert!(matches!(
+            columns[1].default_value.as_deref(),
+            Some("" | "''")
+        ));
+        assert!(get_table_columns(&mut conn, &db.name, "missing")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            count_rows(&mut conn, &db.name, "customers").await.unwrap(),
+            2
+        );
+
+        drop(conn);
+        db.close().await;
+    }
+}
@@ -0,0 +1,132 @@
+//! Values bound to the placeholders of statements, e.g. Postgres' `$1` or SQLite's `:name`.
+//!
+//! Values are given as JSON, by placeholder name, and each backend converts them to the types the statement
+//! expects. A bare `?` is named after the index the database gives it, so the first one of a statement is `?1`.
+//! Every statement of a run is given the run's values, the way `$1` means the same in each of them.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+use sqlparser::{
+    dialect::Dialect,
+    tokenizer::{Token, Tokenizer},
+};
+
+use crate::{database::parser::SqlDialectExt, Error};
+
+/// Values by placeholder name
+pub type QueryParams = BTreeMap<String, Value>;
+
+/// The placeholders of a statement that the database binds values to, in the order they first appear
+pub fn placeholders<T>(dialect: &T, statement: &str) -> Vec<String>
+where
+    T: Dialect + SqlDialectExt,
+{
+    let Ok(tokens) = Tokenizer::new(dialect, statement).tokenize() else {
+        return Vec::new();
+    };
+
+    let mut names: Vec<String> = Vec::new();
+    // Indexes are given the way SQLite does: a bare `?` and each new named placeholder come after the highest
+    // index so far
+    let mut last_index = 0;
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        let name = match token {
+            Token::Placeholder(name) => name,
+            // `:name` and `@name` are two tokens
+            Token::Colon | Token::AtSign => match tokens.peek() {
+                Some(Token::Word(word)) if word.quote_style.is_none() => {
+                    format!("{token}{}", word.value)
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        if !T::binds_placeholder(&name) || names.contains(&name) {
+            continue;
+        }
+
+        if name == "?" {
+            last_index += 1;
+            names.push(format!("?{last_index}"));
+            continue;
+        }
+        match name.strip_prefix('?').map(str::parse::<usize>) {
+            Some(Ok(index)) => last_index = last_index.max(index),
+            _ => last_index += 1,
+        }
+        names.push(name);
+    }
+
+    names
+}
+
+/// The value given for a placeholder
+pub fn value<'a>(params: &'a QueryParams, name: &str) -> Result<&'a Value, Error> {
+    params
+        .get(name)
+        .ok_or_else(|| anyhow::anyhow!("No value given for parameter {name}").into())
+}
+
+/// A value as text, for the databases that convert text to the placeholder's type. None for null.
+pub fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(text) => Some(text.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::dialect::{MySqlDialect, PostgreSqlDialect, SQLiteDialect};
+
+    use super::*;
+
+    #[test]
+    fn finds_each_backends_placeholders() {
+        assert_eq!(
+            placeholders(
+                &PostgreSqlDialect {},
+                "SELECT $2::date, $1, $2 WHERE tags ? 'a' AND items[1:n] IS NOT NULL"
+            ),
+            ["$2", "$1"]
+        );
+
+        // Named placeholders take an index too, so the bare `?` after `:name` is the second
+        assert_eq!(
+            placeholders(
+                &SQLiteDialect {},
+                "SELECT :name, ?, ?5, ?, @other, $third, :name FROM t WHERE ':quoted' <> ''"
+            ),
+            [":name", "?2", "?5", "?6", "@other", "$third"]
+        );
+
+        assert_eq!(
+            placeholders(&MySqlDialect {}, "SELECT ?, @session_var, ? FROM t"),
+            ["?1", "?2"]
+        );
+        assert!(placeholders(&PostgreSqlDialect {}, "SELECT 1").is_empty());
+    }
+
+    #[test]
+    fn converts_values_to_text() {
+        let params = QueryParams::from([("$1".to_owned(), Value::from(42))]);
+        assert_eq!(value(&params, "$1").unwrap(), &Value::from(42));
+        assert!(value(&params, "$2")
+            .unwrap_err()
+            .to_string()
+            .contains("No value given for parameter $2"));
+
+        assert_eq!(text(&Value::Null), None);
+        assert_eq!(text(&Value::from("2024-02-29")).unwrap(), "2024-02-29");
+        assert_eq!(text(&Value::from(1.5)).unwrap(), "1.5");
+        assert_eq!(text(&Value::from(true)).unwrap(), "true");
+        assert_eq!(
+            text(&serde_json::json!({ "a": [1] })).unwrap(),
+            r#"{"a":[1]}"#
+        );
+    }
+}
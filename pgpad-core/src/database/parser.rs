@@ -1,22 +1,99 @@
-use std::ops::ControlFlow;
+use std::ops::{ControlFlow, Range};
 
+use serde::Serialize;
+
+use crate::database::{
+    lineage::{self, ColumnLineage},
+    params::{self, QueryParams},
+    types::Database,
+};
 use sqlparser::{
     ast::{self, Statement, VisitMut, VisitorMut},
-    dialect::Dialect,
+    dialect::{Dialect, GenericDialect, MySqlDialect, PostgreSqlDialect, SQLiteDialect},
     keywords::Keyword,
     parser::Parser,
-    tokenizer::Token,
+    tokenizer::{Location, Token, Tokenizer},
 };
 
+/// What a statement does, broadly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementKind {
+    Select,
+    /// Changes rows: INSERT, UPDATE, DELETE, MERGE, COPY
+    Dml,
+    /// Changes the schema: CREATE, ALTER, DROP, TRUNCATE and the like
+    Ddl,
+    /// Anything else, e.g. transaction control, SET or EXPLAIN
+    Other,
+}
+
+impl StatementKind {
+    pub fn of(stmt: &Statement) -> Self {
+        use Statement::*;
+
+        match stmt {
+            Query(_) => Self::Select,
+            Insert(_) | Update { .. } | Delete(_) | Merge { .. } | Copy { .. } => Self::Dml,
+            CreateView { .. }
+            | CreateTable(_)
+            | CreateVirtualTable { .. }
+            | CreateIndex(_)
+            | CreateSchema { .. }
+            | CreateDatabase { .. }
+            | CreateFunction(_)
+            | CreateTrigger { .. }
+            | CreateProcedure { .. }
+            | CreateType { .. }
+            | CreateDomain(_)
+            | CreateSequence { .. }
+            | CreateExtension { .. }
+            | CreatePolicy { .. }
+            | AlterTable { .. }
+            | AlterIndex { .. }
+            | AlterView { .. }
+            | AlterType(_)
+            | AlterPolicy { .. }
+            | AlterSchema(_)
+            | Drop { .. }
+            | DropFunction { .. }
+            | DropDomain(_)
+            | DropProcedure { .. }
+            | DropPolicy { .. }
+            | DropExtension { .. }
+            | DropTrigger { .. }
+            | RenameTable(_)
+            | Truncate { .. }
+            | Comment { .. } => Self::Ddl,
+            _ => Self::Other,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ParsedStatement {
     pub statement: String,
     pub returns_values: bool,
     pub is_read_only: bool,
+    /// Lineage of the statement's output columns, if it could be resolved from the query alone
+    pub lineage: Option<Vec<ColumnLineage>>,
+    /// True for BEGIN, COMMIT, SAVEPOINT and the like, which can't run in a savepoint of their own
+    pub controls_transaction: bool,
+    pub kind: StatementKind,
+    /// True for UPDATE and DELETE statements without a WHERE clause or LIMIT, which change every row of their table
+    pub unfiltered_write: bool,
+    /// Byte offsets of the statement in the parsed script, without its semicolon
+    pub span: Range<usize>,
+    /// The statement's placeholders, see [`params::placeholders`]
+    pub placeholders: Vec<String>,
+    /// The values bound to the placeholders, given when the statement is submitted
+    pub params: QueryParams,
 }
 
 pub trait SqlDialectExt {
     fn returns_values(stmt: &Statement) -> bool;
+    /// Whether the database binds values to a placeholder, e.g. `$1` or `:name`
+    fn binds_placeholder(name: &str) -> bool;
     fn is_read_only(stmt: &Statement) -> bool {
         use Statement::*;
 
@@ -136,29 +213,143 @@ where
     T: Dialect + SqlDialectExt,
 {
     let mut parser = Parser::new(dialect).try_with_sql(query)?;
+    let offsets = ByteOffsets::new(query);
 
     let mut statements = vec![];
 
     loop {
         while parser.consume_token(&Token::SemiColon) {}
 
-        match parser.peek_token().token {
+        let first = parser.peek_token();
+        match first.token {
             Token::EOF => break,
             Token::Word(word) if word.keyword == Keyword::END => break,
             _ => {}
         }
 
         let statement = parser.parse_statement()?;
+        let span = offsets.of(first.span.start)..offsets.of(parser.get_current_token().span.end);
+        let text = statement.to_string();
         statements.push(ParsedStatement {
-            statement: statement.to_string(),
+            placeholders: params::placeholders(dialect, &text),
+            params: QueryParams::new(),
+            statement: text,
             returns_values: T::returns_values(&statement),
             is_read_only: T::is_read_only(&statement),
+            lineage: lineage::resolve_lineage(&statement),
+            controls_transaction: matches!(
+                statement,
+                Statement::StartTransaction { .. }
+                    | Statement::Commit { .. }
+                    | Statement::Rollback { .. }
+                    | Statement::Savepoint { .. }
+                    | Statement::ReleaseSavepoint { .. }
+            ),
+            kind: StatementKind::of(&statement),
+            unfiltered_write: match &statement {
+                Statement::Update {
+                    selection, limit, ..
+                } => selection.is_none() && limit.is_none(),
+                Statement::Delete(delete) => delete.selection.is_none() && delete.limit.is_none(),
+                _ => false,
+            },
+            span,
         });
     }
 
     Ok(statements)
 }
 
+/// The dialect statements of `database` are parsed in, or a generic one
+pub(crate) fn sql_dialect(database: Option<Database>) -> Box<dyn Dialect> {
+    match database {
+        Some(Database::Postgres) => Box::new(PostgreSqlDialect {}),
+        Some(Database::Sqlite) => Box::new(SQLiteDialect {}),
+        Some(Database::MySql) => Box::new(MySqlDialect {}),
+        None => Box::new(GenericDialect {}),
+    }
+}
+
+/// Turns the tokenizer's locations, in lines and characters, into byte offsets
+pub(crate) struct ByteOffsets<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> ByteOffsets<'a> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    pub(crate) fn of(&self, location: Location) -> usize {
+        let line = (location.line as usize).saturating_sub(1);
+        let Some(&line_start) = self.line_starts.get(line) else {
+            return self.text.len();
+        };
+        self.text[line_start..]
+            .char_indices()
+            .nth((location.column as usize).saturating_sub(1))
+            .map_or(self.text.len(), |(idx, _)| line_start + idx)
+    }
+}
+
+/// Statement kinds that can destroy data, in the order they're reported
+const DESTRUCTIVE_KEYWORDS: [Keyword; 5] = [
+    Keyword::DROP,
+    Keyword::TRUNCATE,
+    Keyword::DELETE,
+    Keyword::UPDATE,
+    Keyword::ALTER,
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DestructiveStatements {
+    /// e.g. "DROP"
+    pub kind: String,
+    pub count: usize,
+}
+
+/// Counts the statements that can destroy data (DROP, TRUNCATE, DELETE, UPDATE, ALTER) in a script.
+///
+/// This only looks at the leading keyword of each statement, so that it works for scripts we can't fully parse,
+/// e.g. ones written for another database.
+pub fn find_destructive_statements(query: &str) -> anyhow::Result<Vec<DestructiveStatements>> {
+    let tokens = Tokenizer::new(&GenericDialect {}, query).tokenize()?;
+
+    let mut counts = [0; DESTRUCTIVE_KEYWORDS.len()];
+    let mut at_statement_start = true;
+
+    for token in tokens {
+        match token {
+            Token::Whitespace(_) => {}
+            Token::SemiColon => at_statement_start = true,
+            Token::Word(word) if at_statement_start => {
+                if let Some(idx) = DESTRUCTIVE_KEYWORDS
+                    .iter()
+                    .position(|kw| *kw == word.keyword)
+                {
+                    counts[idx] += 1;
+                }
+                at_statement_start = false;
+            }
+            _ => at_statement_start = false,
+        }
+    }
+
+    Ok(DESTRUCTIVE_KEYWORDS
+        .iter()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(keyword, count)| DestructiveStatements {
+            kind: format!("{keyword:?}"),
+            count,
+        })
+        .collect())
+}
+
 #[allow(unused)]
 pub fn fingerprint_statements<T>(dialect: &T, query: &str) -> anyhow::Result<Vec<Statement>>
 where
@@ -217,3 +408,91 @@ impl VisitorMut for FingerprintVisitor {
         ControlFlow::Continue(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::postgres::parser::parse_statements;
+
+    #[test]
+    fn locates_and_classifies_statements() {
+        let script = "SELECT 'é' AS accent;\n\n  UPDATE t SET a = 1\n  WHERE b;;CREATE TABLE u (id int);\nBEGIN";
+        let statements = parse_statements(script).unwrap();
+
+        let spans: Vec<_> = statements
+            .iter()
+            .map(|stmt| &script[stmt.span.clone()])
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                "SELECT 'é' AS accent",
+                "UPDATE t SET a = 1\n  WHERE b",
+                "CREATE TABLE u (id int)",
+                "BEGIN"
+            ]
+        );
+
+        let kinds: Vec<_> = statements.iter().map(|stmt| stmt.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                StatementKind::Select,
+                StatementKind::Dml,
+                StatementKind::Ddl,
+                StatementKind::Other
+            ]
+        );
+    }
+
+    #[test]
+    fn flags_writes_to_every_row() {
+        let script = "UPDATE t SET a = 1; UPDATE t SET a = 1 WHERE b; DELETE FROM t; \
+                      DELETE FROM t USING u WHERE t.id = u.id; INSERT INTO t VALUES (1)";
+        let unfiltered: Vec<_> = parse_statements(script)
+            .unwrap()
+            .iter()
+            .map(|stmt| stmt.unfiltered_write)
+            .collect();
+        assert_eq!(unfiltered, [true, false, true, false, false]);
+    }
+
+    #[test]
+    fn finds_destructive_statements() {
+        let script = r#"
+            -- DROP TABLE in a comment doesn't count
+            DROP TABLE users;
+            drop table orders;
+            SELECT 'DELETE FROM users';
+            DELETE FROM sessions WHERE expired;
+            /* TRUNCATE */ TRUNCATE audit_log;
+            UPDATE users SET name = 'x'
+        "#;
+
+        assert_eq!(
+            find_destructive_statements(script).unwrap(),
+            vec![
+                DestructiveStatements {
+                    kind: "DROP".into(),
+                    count: 2
+                },
+                DestructiveStatements {
+                    kind: "TRUNCATE".into(),
+                    count: 1
+                },
+                DestructiveStatements {
+                    kind: "DELETE".into(),
+                    count: 1
+                },
+                DestructiveStatements {
+                    kind: "UPDATE".into(),
+                    count: 1
+                },
+            ]
+        );
+
+        assert!(find_destructive_statements("SELECT 1; SELECT 2")
+            .unwrap()
+            .is_empty());
+    }
+}
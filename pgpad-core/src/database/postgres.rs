@@ -1,6 +1,17 @@
+pub mod activity;
 pub mod connect;
+pub mod copy;
 pub mod execute;
+pub mod explain;
+pub mod flavor;
+pub mod messages;
+pub mod notifications;
+pub mod params;
 pub mod parser;
+pub mod pool;
+pub mod replication;
 pub mod row_writer;
 pub mod schema;
+pub mod ssh_tunnel;
+pub mod stats;
 pub mod tls;
@@ -0,0 +1,199 @@
+//! The sessions of a Postgres server, from `pg_stat_activity`, to see what's running and stop it
+
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Client;
+
+/// Sessions per page when no limit is given
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Narrows down the sessions of `get_server_activity`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ActivityFilters {
+    /// e.g. `active` or `idle in transaction`
+    pub state: Option<String>,
+    pub offset: i64,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerSession {
+    pub pid: i32,
+    pub user: Option<String>,
+    pub database: Option<String>,
+    pub application_name: Option<String>,
+    /// None for sessions of other users, unless we can see them
+    pub state: Option<String>,
+    /// The running query, or the last one of idle sessions
+    pub query: Option<String>,
+    pub wait_event_type: Option<String>,
+    pub wait_event: Option<String>,
+    pub xact_start: Option<String>,
+    pub query_start: Option<String>,
+    /// Whether it's one of the connection's own clients, which `terminate_session` leaves alone unless forced
+    pub own: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerActivity {
+    pub sessions: Vec<ServerSession>,
+    /// Sessions matching the filters, across all pages
+    pub total: i64,
+}
+
+/// Client sessions, longest running first. Background workers and such are left out.
+const ACTIVITY_QUERY: &str = "
+SELECT
+    pid,
+    usename::text,
+    datname::text,
+    application_name,
+    state,
+    query,
+    wait_event_type,
+    wait_event,
+    xact_start::text,
+    query_start::text,
+    count(*) OVER ()
+FROM pg_catalog.pg_stat_activity
+WHERE backend_type = 'client backend' AND ($1::text IS NULL OR state = $1)
+ORDER BY query_start NULLS LAST, pid
+LIMIT $2 OFFSET $3";
+
+/// A page of the server's sessions, `own_pids` being the backend pids of the connection's clients
+pub async fn get_server_activity(
+    client: &Client,
+    filters: &ActivityFilters,
+    own_pids: &[i32],
+) -> Result<ServerActivity, tokio_postgres::Error> {
+    let limit = filters
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = filters.offset.max(0);
+    let rows = client
+        .query(ACTIVITY_QUERY, &[&filters.state, &limit, &offset])
+        .await?;
+
+    let total = rows.first().map_or(0, |row| row.get(10));
+    let sessions = rows
+        .iter()
+        .map(|row| {
+            let pid = row.get(0);
+            ServerSession {
+                pid,
+                user: row.get(1),
+                database: row.get(2),
+                application_name: row.get(3),
+                state: row.get(4),
+                query: row.get(5),
+                wait_event_type: row.get(6),
+                wait_event: row.get(7),
+                xact_start: row.get(8),
+                query_start: row.get(9),
+                own: own_pids.contains(&pid),
+            }
+        })
+        .collect();
+
+    Ok(ServerActivity { sessions, total })
+}
+
+/// Cancels the query of a session, or closes the session with `terminate`. False if there's no such session.
+pub async fn signal_session(
+    client: &Client,
+    pid: i32,
+    terminate: bool,
+) -> Result<bool, tokio_postgres::Error> {
+    let query = if terminate {
+        "SELECT pg_catalog.pg_terminate_backend($1)"
+    } else {
+        "SELECT pg_catalog.pg_cancel_backend($1)"
+    };
+    Ok(client.query_one(query, &[&pid]).await?.get(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    async fn connect(db: &pgtemp::PgTempDB) -> Client {
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        client
+    }
+
+    #[tokio::test]
+    async fn lists_and_stops_sessions() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let monitor = connect(&db).await;
+        let other = connect(&db).await;
+        let monitor_pid: i32 = monitor
+            .query_one("SELECT pg_backend_pid()", &[])
+            .await
+            .unwrap()
+            .get(0);
+        let other_pid: i32 = other
+            .query_one("SELECT pg_backend_pid()", &[])
+            .await
+            .unwrap()
+            .get(0);
+
+        let activity = get_server_activity(&monitor, &ActivityFilters::default(), &[monitor_pid])
+            .await
+            .unwrap();
+        assert_eq!(activity.total, 2);
+        let own = activity
+            .sessions
+            .iter()
+            .find(|session| session.pid == monitor_pid)
+            .unwrap();
+        assert!(own.own);
+        assert_eq!(own.state.as_deref(), Some("active"));
+
+        let idle = ActivityFilters {
+            state: Some("idle".to_owned()),
+            ..Default::default()
+        };
+        let activity = get_server_activity(&monitor, &idle, &[monitor_pid])
+            .await
+            .unwrap();
+        assert_eq!(activity.total, 1);
+        assert_eq!(activity.sessions[0].pid, other_pid);
+        assert!(!activity.sessions[0].own);
+
+        let paged = ActivityFilters {
+            offset: 1,
+            limit: Some(1),
+            ..Default::default()
+        };
+        let activity = get_server_activity(&monitor, &paged, &[]).await.unwrap();
+        assert_eq!((activity.total, activity.sessions.len()), (2, 1));
+
+        let sleeping = tokio::spawn(async move {
+            other
+                .batch_execute("SELECT pg_sleep(30)")
+                .await
+                .unwrap_err();
+            other
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(signal_session(&monitor, other_pid, false).await.unwrap());
+        let other = tokio::time::timeout(Duration::from_secs(5), sleeping)
+            .await
+            .unwrap()
+            .unwrap();
+        // Only the query was cancelled
+        other.batch_execute("SELECT 1").await.unwrap();
+
+        assert!(signal_session(&monitor, other_pid, true).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(other.batch_execute("SELECT 1").await.is_err());
+        assert!(!signal_session(&monitor, other_pid, true).await.unwrap());
+    }
+}
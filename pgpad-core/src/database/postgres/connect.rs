@@ -1,10 +1,13 @@
 use crate::{
-    database::{Certificates, ConnectionDropNotifier},
+    database::{
+        postgres::{messages::MessageForwarder, notifications::NotificationForwarder},
+        Certificates, ConnectionDropNotifier,
+    },
     error::Error,
 };
 
 use anyhow::Context;
-use tokio_postgres::{tls::MakeTlsConnect, Client, Connection, NoTls, Socket};
+use tokio_postgres::{tls::MakeTlsConnect, AsyncMessage, Client, Connection, NoTls, Socket};
 use tokio_postgres_rustls::MakeRustlsConnect;
 
 pub async fn connect(
@@ -12,12 +15,14 @@ pub async fn connect(
     certificates: &Certificates,
     ca_cert_path: Option<&str>,
     drop_notifier: ConnectionDropNotifier,
+    notifications: NotificationForwarder,
+    messages: MessageForwarder,
 ) -> Result<Client, Error> {
     connect_inner(
         config,
         certificates,
         ca_cert_path,
-        ConnectionMode::Monitored(drop_notifier),
+        ConnectionMode::Monitored(drop_notifier, notifications, messages),
     )
     .await
 }
@@ -37,8 +42,55 @@ pub async fn test_connection(
     Ok(())
 }
 
+/// How a connection is encrypted, which its cancel requests have to match
+#[derive(Clone)]
+pub enum Tls {
+    Plain,
+    Rustls(MakeRustlsConnect),
+}
+
+impl std::fmt::Debug for Tls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tls::Plain => f.write_str("Plain"),
+            Tls::Rustls(_) => f.write_str("Rustls"),
+        }
+    }
+}
+
+impl Tls {
+    pub async fn new(
+        config: &tokio_postgres::Config,
+        certificates: &Certificates,
+        ca_cert_path: Option<&str>,
+    ) -> Result<Self, Error> {
+        use tokio_postgres::config::SslMode;
+
+        match config.get_ssl_mode() {
+            SslMode::Require | SslMode::Prefer => {
+                let certificate_store = if let Some(cert_path) = ca_cert_path {
+                    certificates.with_custom_cert(cert_path).await?
+                } else {
+                    certificates.read().await?
+                };
+
+                let rustls_config = rustls::ClientConfig::builder()
+                    .with_root_certificates(certificate_store)
+                    .with_no_client_auth();
+                Ok(Tls::Rustls(MakeRustlsConnect::new(rustls_config)))
+            }
+            // Mostly SslMode::Disable, but the enum was marked as non_exhaustive
+            _other => Ok(Tls::Plain),
+        }
+    }
+}
+
 enum ConnectionMode {
-    Monitored(ConnectionDropNotifier),
+    Monitored(
+        ConnectionDropNotifier,
+        NotificationForwarder,
+        MessageForwarder,
+    ),
     Unmonitored,
 }
 
@@ -48,28 +100,21 @@ async fn connect_inner(
     ca_cert_path: Option<&str>,
     mode: ConnectionMode,
 ) -> Result<Client, Error> {
-    use tokio_postgres::config::SslMode;
-
-    let client = match config.get_ssl_mode() {
-        SslMode::Require | SslMode::Prefer => {
-            let certificate_store = if let Some(cert_path) = ca_cert_path {
-                certificates.with_custom_cert(cert_path).await?
-            } else {
-                certificates.read().await?
-            };
-
-            let rustls_config = rustls::ClientConfig::builder()
-                .with_root_certificates(certificate_store)
-                .with_no_client_auth();
-            let tls = tokio_postgres_rustls::MakeRustlsConnect::new(rustls_config);
+    let client = match Tls::new(config, certificates, ca_cert_path).await? {
+        Tls::Rustls(tls) => {
             let (client, conn) = config
                 .connect(tls)
                 .await
                 .map_err(|e| anyhow::anyhow!("Failed to connect to Postgres: {}", e))?;
 
             match mode {
-                ConnectionMode::Monitored(drop_notifier) => {
-                    tokio::spawn(check_connection::<MakeRustlsConnect>(conn, drop_notifier));
+                ConnectionMode::Monitored(drop_notifier, notifications, messages) => {
+                    tokio::spawn(check_connection::<MakeRustlsConnect>(
+                        conn,
+                        drop_notifier,
+                        notifications,
+                        messages,
+                    ));
                 }
                 ConnectionMode::Unmonitored => {
                     tokio::spawn(log_connection::<MakeRustlsConnect>(conn));
@@ -78,16 +123,20 @@ async fn connect_inner(
 
             client
         }
-        // Mostly SslMode::Disable, but the enum was marked as non_exhaustive
-        _other => {
+        Tls::Plain => {
             let (client, conn) = config
                 .connect(NoTls)
                 .await
                 .with_context(|| format!("Failed to connect to Postgres '{config:?}'",))?;
 
             match mode {
-                ConnectionMode::Monitored(drop_notifier) => {
-                    tokio::spawn(check_connection::<NoTls>(conn, drop_notifier));
+                ConnectionMode::Monitored(drop_notifier, notifications, messages) => {
+                    tokio::spawn(check_connection::<NoTls>(
+                        conn,
+                        drop_notifier,
+                        notifications,
+                        messages,
+                    ));
                 }
                 ConnectionMode::Unmonitored => {
                     tokio::spawn(log_connection::<NoTls>(conn));
@@ -101,13 +150,27 @@ async fn connect_inner(
     Ok(client)
 }
 
+/// Drives the connection until it ends, forwarding the notifications and notices it receives
 async fn check_connection<T>(
-    conn: Connection<Socket, T::Stream>,
+    mut conn: Connection<Socket, T::Stream>,
     drop_notifier: ConnectionDropNotifier,
+    notifications: NotificationForwarder,
+    messages: MessageForwarder,
 ) where
     T: MakeTlsConnect<Socket>,
 {
-    log_connection::<T>(conn).await;
+    let res = loop {
+        match std::future::poll_fn(|cx| conn.poll_message(cx)).await {
+            Some(Ok(AsyncMessage::Notification(notification))) => {
+                notifications.forward(&notification)
+            }
+            Some(Ok(AsyncMessage::Notice(notice))) => messages.forward(&notice),
+            Some(Ok(_)) => {}
+            Some(Err(err)) => break Err(err),
+            None => break Ok(()),
+        }
+    };
+    log_result(res);
     drop_notifier.notify();
 }
 
@@ -115,7 +178,10 @@ async fn log_connection<T>(conn: Connection<Socket, T::Stream>)
 where
     T: MakeTlsConnect<Socket>,
 {
-    let res = conn.await;
+    log_result(conn.await);
+}
+
+fn log_result(res: Result<(), tokio_postgres::Error>) {
     log::info!("Connection finished");
     match res {
         Ok(()) => println!("Connected successfully"),
@@ -0,0 +1,384 @@
+//! `COPY ... TO STDOUT` and `COPY ... FROM STDIN`, which exchange their data with the client through the copy
+//! protocol rather than as rows, so the usual query path can't run them.
+
+use std::{fmt::Display, io::Read, time::Instant};
+
+use anyhow::Context;
+use bytes::Bytes;
+use futures_util::{pin_mut, SinkExt, TryStreamExt};
+use serde::Serialize;
+use serde_json::value::RawValue;
+use sqlparser::{
+    dialect::PostgreSqlDialect,
+    keywords::Keyword,
+    tokenizer::{Token, Tokenizer},
+};
+use tokio_postgres::Client;
+
+use crate::{
+    database::{
+        postgres::execute::DbError,
+        types::{ExecSender, FetchBudget},
+        QueryExecEvent,
+    },
+    utils::serialize_as_json_array,
+    Error,
+};
+
+/// How much of a file is sent at once by [`copy_in`]
+const COPY_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Rows of output per page, when a copy is shown in the editor
+const COPY_PAGE_ROWS: usize = 50;
+
+/// Which way a `COPY` exchanges its data with the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyDirection {
+    /// `COPY ... TO STDOUT`
+    Out,
+    /// `COPY ... FROM STDIN`
+    In,
+}
+
+/// What a copy to or from a file moved
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CopyReport {
+    pub bytes: u64,
+    /// As counted by the server, which only tells how many rows were copied in
+    pub rows: Option<u64>,
+}
+
+/// A failed copy's error, along with the server's context, which tells the line of the data it failed on, e.g.
+/// `COPY users, line 3, column age: "x"`
+pub struct CopyError<'a>(pub &'a tokio_postgres::Error);
+
+impl Display for CopyError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", DbError(self.0))?;
+        if let Some(context) = self.0.as_db_error().and_then(|db| db.where_()) {
+            write!(f, "\nCONTEXT: {context}")?;
+        }
+        Ok(())
+    }
+}
+
+fn copy_error(e: tokio_postgres::Error) -> Error {
+    anyhow::anyhow!("{}", CopyError(&e)).into()
+}
+
+/// The significant tokens of a `COPY` statement, None for other statements
+fn copy_tokens(statement: &str) -> Option<Vec<Token>> {
+    let tokens: Vec<Token> = Tokenizer::new(&PostgreSqlDialect {}, statement)
+        .tokenize()
+        .ok()?
+        .into_iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_)))
+        .collect();
+
+    match tokens.first() {
+        Some(Token::Word(word)) if word.keyword == Keyword::COPY => Some(tokens),
+        _ => None,
+    }
+}
+
+fn is_word(token: &Token, value: &str) -> bool {
+    matches!(token, Token::Word(word) if word.quote_style.is_none() && word.value.eq_ignore_ascii_case(value))
+}
+
+/// Which way a `COPY` exchanges its data with the client. None for other statements, including copies to and from
+/// files on the server, which run like any other statement.
+pub fn copy_direction(statement: &str) -> Option<CopyDirection> {
+    let tokens = copy_tokens(statement)?;
+
+    // `TO` and `FROM` of the statement itself, not of the query of `COPY (SELECT ... FROM t) TO STDOUT`
+    let mut depth = 0usize;
+    for pair in tokens.windows(2) {
+        match &pair[0] {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            token if depth == 0 && is_word(token, "to") && is_word(&pair[1], "stdout") => {
+                return Some(CopyDirection::Out)
+            }
+            token if depth == 0 && is_word(token, "from") && is_word(&pair[1], "stdin") => {
+                return Some(CopyDirection::In)
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether a `COPY` is in the binary format, as opposed to text or CSV
+fn is_binary(statement: &str) -> bool {
+    copy_tokens(statement).is_some_and(|tokens| tokens.iter().any(|token| is_word(token, "binary")))
+}
+
+/// Streams the output of a `COPY ... TO STDOUT` to `write`, which is called with each piece the server sends: a row
+/// for text and CSV copies. It stops the copy if it fails.
+pub async fn copy_out(
+    client: &Client,
+    statement: &str,
+    mut write: impl FnMut(&[u8]) -> Result<(), Error>,
+) -> Result<CopyReport, Error> {
+    let stream = client.copy_out(statement).await.map_err(copy_error)?;
+    pin_mut!(stream);
+
+    let mut bytes = 0;
+    while let Some(data) = stream.try_next().await.map_err(copy_error)? {
+        write(&data)?;
+        bytes += data.len() as u64;
+    }
+
+    Ok(CopyReport { bytes, rows: None })
+}
+
+/// Sends the data of a `COPY ... FROM STDIN` from `read`, calling `progress` with the bytes sent so far after each
+/// chunk. It stops the copy if it fails, and the server then drops the rows copied so far.
+pub async fn copy_in(
+    client: &Client,
+    statement: &str,
+    mut read: impl Read,
+    mut progress: impl FnMut(u64) -> Result<(), Error>,
+) -> Result<CopyReport, Error> {
+    let sink = client
+        .copy_in::<_, Bytes>(statement)
+        .await
+        .map_err(copy_error)?;
+    pin_mut!(sink);
+
+    let mut buf = vec![0; COPY_CHUNK_BYTES];
+    let mut bytes = 0;
+    loop {
+        let read = read
+            .read(&mut buf)
+            .context("Failed to read the data to copy")?;
+        if read == 0 {
+            break;
+        }
+        sink.send(Bytes::copy_from_slice(&buf[..read]))
+            .await
+            .map_err(copy_error)?;
+        bytes += read as u64;
+        progress(bytes)?;
+    }
+
+    let rows = sink.finish().await.map_err(copy_error)?;
+    Ok(CopyReport {
+        bytes,
+        rows: Some(rows),
+    })
+}
+
+/// Runs a `COPY ... TO STDOUT` from the editor, showing each row of its output as a row of a single `copy` column.
+/// Copies that need a file, binary ones and `COPY ... FROM STDIN`, fail with a message saying so.
+pub async fn execute_copy(
+    client: &Client,
+    statement: &str,
+    direction: CopyDirection,
+    sender: &ExecSender,
+    fetch: Option<&FetchBudget>,
+) -> Result<(), Error> {
+    log::info!("Executing copy: {}", statement);
+    let started_at = Instant::now();
+
+    let result = async {
+        if direction == CopyDirection::In {
+            return Err(anyhow::anyhow!(
+                "COPY ... FROM STDIN reads its data from the client, copy from a file to run it"
+            )
+            .into());
+        }
+        if is_binary(statement) {
+            return Err(anyhow::anyhow!(
+                "Binary copies can't be shown as rows, copy to a file to run them"
+            )
+            .into());
+        }
+
+        let stream = client.copy_out(statement).await.map_err(copy_error)?;
+        pin_mut!(stream);
+
+        sender.send(QueryExecEvent::TypesResolved {
+            columns: serialize_as_json_array(["copy"].into_iter())?,
+            column_types: vec![Some("text".to_owned())],
+        })?;
+
+        let mut page: Vec<[String; 1]> = Vec::with_capacity(COPY_PAGE_ROWS);
+        let mut pages_sent = 0;
+        loop {
+            // Not reading the stream leaves the rest of the output on the server, like the rows of a query
+            if page.is_empty() {
+                if let Some(fetch) = fetch {
+                    if !fetch.wait_for_page(pages_sent).await {
+                        return Err(anyhow::anyhow!("Query cancelled").into());
+                    }
+                }
+            }
+
+            let data = stream.try_next().await.map_err(copy_error)?;
+            if let Some(data) = &data {
+                let line = String::from_utf8_lossy(data);
+                let line = line.strip_suffix('\n').unwrap_or(&line);
+                page.push([line.strip_suffix('\r').unwrap_or(line).to_owned()]);
+            }
+
+            if !page.is_empty() && (page.len() >= COPY_PAGE_ROWS || data.is_none()) {
+                sender.send(QueryExecEvent::Page {
+                    page_amount: page.len(),
+                    page: RawValue::from_string(serde_json::to_string(&page)?)?,
+                })?;
+                page.clear();
+                pages_sent += 1;
+            }
+            if data.is_none() {
+                return Ok::<_, Error>(());
+            }
+        }
+    };
+
+    let error = result.await.err();
+    if let Some(error) = &error {
+        log::error!("Copy failed: {}", error);
+    }
+    sender.send(QueryExecEvent::Finished {
+        elapsed_ms: started_at.elapsed().as_millis() as u64,
+        affected_rows: 0,
+        error: error.as_ref().map(|error| error.to_string()),
+    })?;
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pgtemp::PgTempDB;
+
+    use super::*;
+    use crate::database::types::channel;
+
+    #[test]
+    fn tells_copies_through_the_client() {
+        assert_eq!(
+            copy_direction("COPY users TO STDOUT WITH (FORMAT csv)"),
+            Some(CopyDirection::Out)
+        );
+        assert_eq!(
+            copy_direction("copy (SELECT * FROM a JOIN b ON true) to stdout"),
+            Some(CopyDirection::Out)
+        );
+        assert_eq!(
+            copy_direction("/* load */ COPY users (id, name) FROM STDIN CSV HEADER"),
+            Some(CopyDirection::In)
+        );
+        // Files on the server are read and written by the server
+        assert_eq!(copy_direction("COPY users TO '/tmp/users.csv'"), None);
+        assert_eq!(copy_direction("COPY users FROM '/tmp/users.csv'"), None);
+        assert_eq!(copy_direction("SELECT 'COPY t TO STDOUT'"), None);
+
+        assert!(is_binary("COPY users TO STDOUT (FORMAT binary)"));
+        assert!(is_binary("COPY users TO STDOUT BINARY"));
+        assert!(!is_binary("COPY users TO STDOUT CSV"));
+    }
+
+    #[tokio::test]
+    async fn copies_to_and_from_the_client() {
+        let db = PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        client
+            .batch_execute("CREATE TABLE users (id int, name text)")
+            .await
+            .unwrap();
+
+        let data = "1,Alice\n2,\"Bob, Jr.\"\n";
+        let mut sent = vec![];
+        let report = copy_in(
+            &client,
+            "COPY users FROM STDIN CSV",
+            data.as_bytes(),
+            |bytes| {
+                sent.push(bytes);
+                Ok(())
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            report,
+            CopyReport {
+                bytes: data.len() as u64,
+                rows: Some(2)
+            }
+        );
+        assert_eq!(sent, [data.len() as u64]);
+
+        // The server's context tells which line of the data failed, and nothing is copied
+        let error = copy_in(
+            &client,
+            "COPY users FROM STDIN CSV",
+            "3,Carol\nfour,Dan\n".as_bytes(),
+            |_| Ok(()),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("invalid input syntax"), "{error}");
+        assert!(error.contains("CONTEXT: COPY users, line 2"), "{error}");
+
+        let mut output = vec![];
+        let report = copy_out(&client, "COPY users TO STDOUT CSV", |data| {
+            output.extend_from_slice(data);
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), data);
+        assert_eq!(report.bytes, data.len() as u64);
+
+        // In the editor, each row of the output is a row of the result
+        let (sender, mut recv) = channel();
+        execute_copy(
+            &client,
+            "COPY (SELECT * FROM users ORDER BY id) TO STDOUT WITH (FORMAT csv, HEADER)",
+            CopyDirection::Out,
+            &sender,
+            None,
+        )
+        .await
+        .unwrap();
+        drop(sender);
+        let mut events = vec![];
+        while let Some(event) = recv.recv().await {
+            events.push(event);
+        }
+        match &events[..] {
+            [QueryExecEvent::TypesResolved { columns, .. }, QueryExecEvent::Page { page_amount, page }, QueryExecEvent::Finished { error: None, .. }] =>
+            {
+                assert_eq!(columns.get(), r#"["copy"]"#);
+                assert_eq!(*page_amount, 3);
+                assert_eq!(
+                    page.get(),
+                    r#"[["id,name"],["1,Alice"],["2,\"Bob, Jr.\""]]"#
+                );
+            }
+            other => panic!("Unexpected events: {other:?}"),
+        }
+
+        let (sender, _recv) = channel();
+        let error = execute_copy(
+            &client,
+            "COPY users FROM STDIN",
+            CopyDirection::In,
+            &sender,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("copy from a file"));
+    }
+}
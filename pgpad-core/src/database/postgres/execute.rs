@@ -1,20 +1,38 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
 
 use futures_util::{pin_mut, TryStreamExt};
-use tokio_postgres::{types::ToSql, Client};
+use sqlparser::{
+    dialect::PostgreSqlDialect,
+    keywords::Keyword,
+    tokenizer::{Token, Tokenizer},
+};
+use tokio_postgres::{error::SqlState, types::ToSql, Client, Column};
 
 use crate::{
     database::{
-        parser::ParsedStatement, postgres::row_writer::RowWriter, types::ExecSender, QueryExecEvent,
+        lineage::{self, ColumnLineage, SourceColumn},
+        params::QueryParams,
+        parser::ParsedStatement,
+        postgres::{copy, params, row_writer::RowWriter},
+        types::{ExecSender, FetchBudget, SavepointOutcome, STATEMENT_SAVEPOINT},
+        QueryExecEvent,
     },
     utils::serialize_as_json_array,
     Error,
 };
 
-pub struct DbError<'a>(&'a tokio_postgres::Error);
+pub struct DbError<'a>(pub &'a tokio_postgres::Error);
 
 impl Display for DbError<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if is_timeout(self.0) {
+            return f.write_str(
+                "Query timed out: the statement ran for longer than the connection's statement_timeout",
+            );
+        }
         if let Some(db) = self.0.as_db_error() {
             f.write_str(db.message())?;
             if let Some(detail) = db.detail() {
@@ -30,24 +48,170 @@ impl Display for DbError<'_> {
     }
 }
 
+/// Whether the statement was cancelled by `statement_timeout`, rather than by the user or an administrator
+fn is_timeout(err: &tokio_postgres::Error) -> bool {
+    err.as_db_error().is_some_and(|db| {
+        *db.code() == SqlState::QUERY_CANCELED && db.message().contains("statement timeout")
+    })
+}
+
+/// Sets how long the statements of a session may run before the server cancels them. None restores the
+/// server's default, e.g. one set for the role.
+pub async fn set_statement_timeout(
+    client: &Client,
+    timeout_ms: Option<u64>,
+) -> Result<(), tokio_postgres::Error> {
+    let statement = match timeout_ms {
+        Some(timeout_ms) => format!("SET statement_timeout = {timeout_ms}"),
+        None => "RESET statement_timeout".to_owned(),
+    };
+    client.batch_execute(&statement).await
+}
+
+/// Puts `schema` first in the search path of a session, ahead of the path it started with, e.g. the role's, so that
+/// setting it again replaces it. None restores the path the session started with.
+pub async fn set_search_path(
+    client: &Client,
+    schema: Option<&str>,
+) -> Result<(), tokio_postgres::Error> {
+    match schema {
+        Some(schema) => client
+            .query_one(
+                "SELECT set_config('search_path', concat_ws(', ', quote_ident($1), NULLIF(reset_val, '')), false)
+                 FROM pg_settings
+                 WHERE name = 'search_path'",
+                &[&schema],
+            )
+            .await
+            .map(drop),
+        None => client.batch_execute("RESET search_path").await,
+    }
+}
+
+/// Runs a statement, streaming its rows in pages. With a `fetch` budget, rows stop being fetched once it's spent.
+/// The command tag Postgres ends a statement with, e.g. `UPDATE 42`. tokio-postgres only passes on the tag's row
+/// count, so the rest of it comes from the statement's leading keyword, or from its main statement's for those
+/// starting with `WITH`. None for the statements whose tag has no row count, e.g. `CREATE TABLE`.
+pub fn command_tag(statement: &str, rows: u64) -> Option<String> {
+    let tokens = Tokenizer::new(&PostgreSqlDialect {}, statement)
+        .tokenize()
+        .ok()?;
+
+    let mut depth = 0usize;
+    let mut in_with = false;
+    for token in tokens {
+        let keyword = match token {
+            Token::LParen => {
+                depth += 1;
+                continue;
+            }
+            Token::RParen => {
+                depth = depth.saturating_sub(1);
+                continue;
+            }
+            Token::Word(word) if depth == 0 => word.keyword,
+            Token::Whitespace(_) => continue,
+            _ if in_with => continue,
+            _ => return None,
+        };
+
+        let tag = match keyword {
+            Keyword::WITH => {
+                in_with = true;
+                continue;
+            }
+            Keyword::SELECT | Keyword::VALUES | Keyword::TABLE => format!("SELECT {rows}"),
+            Keyword::INSERT => format!("INSERT 0 {rows}"),
+            Keyword::UPDATE | Keyword::DELETE | Keyword::MERGE | Keyword::COPY | Keyword::FETCH => {
+                format!("{keyword:?} {rows}")
+            }
+            _ if in_with => continue,
+            _ => return None,
+        };
+        return Some(tag);
+    }
+
+    None
+}
+
 pub async fn execute_query(
     client: &Client,
     stmt: ParsedStatement,
     sender: &ExecSender,
+    fetch: Option<&FetchBudget>,
 ) -> Result<(), Error> {
+    if let Some(direction) = copy::copy_direction(&stmt.statement) {
+        return copy::execute_copy(client, &stmt.statement, direction, sender, fetch).await;
+    }
+
     if stmt.returns_values {
-        execute_query_with_results(client, &stmt.statement, sender).await?;
+        execute_query_with_results(
+            client,
+            &stmt.statement,
+            &stmt.params,
+            stmt.lineage,
+            sender,
+            fetch,
+        )
+        .await?;
     } else {
-        execute_modification_query(client, &stmt.statement, sender).await?;
+        execute_modification_query(client, &stmt.statement, &stmt.params, sender).await?;
     }
 
     Ok(())
 }
 
+/// Like [`execute_query`], but in a savepoint if the connection is in a transaction block, so that the
+/// transaction stays usable if the statement fails
+pub async fn execute_query_in_savepoint(
+    client: &Client,
+    stmt: ParsedStatement,
+    sender: &ExecSender,
+    fetch: Option<&FetchBudget>,
+) -> Result<(), Error> {
+    // Outside of a transaction block (or in one that's already aborted) taking the savepoint fails,
+    // and there's nothing to protect anyway
+    if stmt.controls_transaction
+        || client
+            .batch_execute(&format!("SAVEPOINT {STATEMENT_SAVEPOINT}"))
+            .await
+            .is_err()
+    {
+        return execute_query(client, stmt, sender, fetch).await;
+    }
+
+    let result = execute_query(client, stmt, sender, fetch).await;
+
+    let (settle, outcome) = match result {
+        Ok(()) => (
+            format!("RELEASE SAVEPOINT {STATEMENT_SAVEPOINT}"),
+            SavepointOutcome::Released,
+        ),
+        Err(_) => (
+            format!(
+                "ROLLBACK TO SAVEPOINT {STATEMENT_SAVEPOINT}; RELEASE SAVEPOINT {STATEMENT_SAVEPOINT}"
+            ),
+            SavepointOutcome::RolledBack,
+        ),
+    };
+    match client.batch_execute(&settle).await {
+        Ok(()) => sender.send(QueryExecEvent::Savepoint(outcome))?,
+        Err(e) => log::error!(
+            "Failed to settle the statement's savepoint: {}",
+            DbError(&e)
+        ),
+    }
+
+    result
+}
+
 async fn execute_query_with_results(
     client: &Client,
     query: &str,
+    values: &QueryParams,
+    lineage: Option<Vec<ColumnLineage>>,
     sender: &ExecSender,
+    fetch: Option<&FetchBudget>,
 ) -> Result<(), Error> {
     let started_at = std::time::Instant::now();
     log::info!("Starting streaming query: {}", query);
@@ -73,25 +237,89 @@ async fn execute_query_with_results(
             return Err(Error::Any(anyhow::anyhow!(error_msg)));
         }
     };
+    let values = match params::bind(&prepared_stmt, values) {
+        Ok(values) => values,
+        Err(e) => {
+            sender.send(QueryExecEvent::Finished {
+                elapsed_ms: started_at.elapsed().as_millis() as u64,
+                affected_rows: 0,
+                error: Some(e.to_string()),
+            })?;
+
+            return Err(e);
+        }
+    };
+    let values: Vec<&(dyn ToSql + Sync)> = values.iter().map(|value| value as _).collect();
 
+    let column_types = prepared_stmt
+        .columns()
+        .iter()
+        .map(|col| Some(col.type_().name().to_owned()))
+        .collect();
     let columns = prepared_stmt.columns().iter().map(|col| col.name());
     let columns = serialize_as_json_array(columns)?;
 
-    sender.send(QueryExecEvent::TypesResolved { columns })?;
+    sender.send(QueryExecEvent::TypesResolved {
+        columns,
+        column_types,
+    })?;
+
+    let needs_driver_lineage = lineage.as_ref().is_none_or(|lineage| {
+        lineage
+            .iter()
+            .any(|column| matches!(column, ColumnLineage::Unknown))
+    });
+    if needs_driver_lineage
+        && prepared_stmt
+            .columns()
+            .iter()
+            .any(|col| col.table_oid().is_some())
+    {
+        match driver_lineage(client, prepared_stmt.columns()).await {
+            Ok(driver_lineage) => sender.send(QueryExecEvent::LineageResolved(
+                lineage::merge_driver_lineage(lineage, driver_lineage),
+            ))?,
+            Err(e) => log::warn!("Failed to resolve column origins: {}", DbError(&e)),
+        }
+    }
 
-    match client.query_raw(&prepared_stmt, slice_iter(&[])).await {
+    match client.query_raw(&prepared_stmt, slice_iter(&values)).await {
         Ok(stream) => {
             pin_mut!(stream);
 
             let batch_size = 50;
             let mut total_rows = 0;
+            let mut pages_sent = 0;
 
             let mut writer = RowWriter::new();
+            let mut serialize_time = Duration::ZERO;
+            // Whether the statement waited at its high-water mark, during which it still runs on the server
+            let mut paused = false;
 
             loop {
+                // Not polling the stream leaves the rows on the server, so only the pages allowed are held here
+                if writer.is_empty() {
+                    if let Some(fetch) = fetch {
+                        paused |= !fetch.allows(pages_sent);
+                        if !fetch.wait_for_page(pages_sent).await {
+                            log::info!("Streaming query cancelled after {total_rows} rows");
+                            sender.send(QueryExecEvent::Serialized(serialize_time))?;
+                            sender.send(QueryExecEvent::Finished {
+                                elapsed_ms: started_at.elapsed().as_millis() as u64,
+                                affected_rows: 0,
+                                error: Some("Query cancelled".to_owned()),
+                            })?;
+
+                            return Err(Error::Any(anyhow::anyhow!("Query cancelled")));
+                        }
+                    }
+                }
+
                 match stream.try_next().await {
                     Ok(Some(row)) => {
+                        let serialize_start = Instant::now();
                         writer.add_row(&row)?;
+                        serialize_time += serialize_start.elapsed();
 
                         total_rows += 1;
 
@@ -100,6 +328,7 @@ async fn execute_query_with_results(
                                 page_amount: writer.len(),
                                 page: writer.finish(),
                             })?;
+                            pages_sent += 1;
                         }
                     }
                     Ok(None) => {
@@ -108,8 +337,15 @@ async fn execute_query_with_results(
                     }
                     Err(e) => {
                         log::error!("Error processing row: {}", e);
-                        let error_msg = DbError(&e).to_string();
+                        let mut error_msg = DbError(&e).to_string();
+                        if paused && is_timeout(&e) {
+                            error_msg.push_str(
+                                ". It kept running while its rows weren't being read, so read them sooner or raise \
+                                 the timeout. The rows fetched before are kept.",
+                            );
+                        }
 
+                        sender.send(QueryExecEvent::Serialized(serialize_time))?;
                         sender.send(QueryExecEvent::Finished {
                             elapsed_ms: started_at.elapsed().as_millis() as u64,
                             affected_rows: 0,
@@ -130,6 +366,11 @@ async fn execute_query_with_results(
 
             let duration = started_at.elapsed().as_millis() as u64;
 
+            let rows = stream.rows_affected().unwrap_or(total_rows);
+            if let Some(tag) = command_tag(query, rows) {
+                sender.send(QueryExecEvent::CommandTag(tag))?;
+            }
+            sender.send(QueryExecEvent::Serialized(serialize_time))?;
             sender.send(QueryExecEvent::Finished {
                 elapsed_ms: started_at.elapsed().as_millis() as u64,
                 affected_rows: 0,
@@ -159,16 +400,72 @@ async fn execute_query_with_results(
     }
 }
 
+/// The table column each result column came from, as reported by Postgres
+async fn driver_lineage(
+    client: &Client,
+    columns: &[Column],
+) -> Result<Vec<Option<SourceColumn>>, tokio_postgres::Error> {
+    let table_oids: Vec<u32> = columns
+        .iter()
+        .map(|col| col.table_oid().unwrap_or(0))
+        .collect();
+    let column_ids: Vec<i16> = columns
+        .iter()
+        .map(|col| col.column_id().unwrap_or(0))
+        .collect();
+
+    let rows = client
+        .query(
+            r#"
+            SELECT u.idx, a.attrelid::regclass::text, a.attname::text
+            FROM unnest($1::oid[], $2::int2[]) WITH ORDINALITY AS u(relid, attnum, idx)
+            JOIN pg_attribute a ON a.attrelid = u.relid AND a.attnum = u.attnum
+            "#,
+            &[&table_oids, &column_ids],
+        )
+        .await?;
+
+    let mut lineage = vec![None; columns.len()];
+    for row in rows {
+        let idx: i64 = row.get(0);
+        if let Some(origin) = lineage.get_mut(idx as usize - 1) {
+            *origin = Some(SourceColumn {
+                table: Some(row.get(1)),
+                column: row.get(2),
+            });
+        }
+    }
+
+    Ok(lineage)
+}
+
 async fn execute_modification_query(
     client: &Client,
     query: &str,
+    values: &QueryParams,
     sender: &ExecSender,
 ) -> Result<(), Error> {
     log::info!("Executing modification query: {}", query);
     let started_at = std::time::Instant::now();
 
-    match client.execute(query, &[]).await {
+    let result = async {
+        let statement = client
+            .prepare(query)
+            .await
+            .map_err(|e| DbError(&e).to_string())?;
+        let values = params::bind(&statement, values).map_err(|e| e.to_string())?;
+        let values: Vec<&(dyn ToSql + Sync)> = values.iter().map(|value| value as _).collect();
+        client
+            .execute(&statement, &values)
+            .await
+            .map_err(|e| DbError(&e).to_string())
+    };
+
+    match result.await {
         Ok(rows_affected) => {
+            if let Some(tag) = command_tag(query, rows_affected) {
+                sender.send(QueryExecEvent::CommandTag(tag))?;
+            }
             sender.send(QueryExecEvent::Finished {
                 elapsed_ms: started_at.elapsed().as_millis() as u64,
                 affected_rows: rows_affected as usize,
@@ -177,9 +474,8 @@ async fn execute_modification_query(
 
             Ok(())
         }
-        Err(e) => {
-            log::error!("Modification query failed: {:?}", e);
-            let error_msg = DbError(&e).to_string();
+        Err(error_msg) => {
+            log::error!("Modification query failed: {}", error_msg);
 
             sender.send(QueryExecEvent::Finished {
                 elapsed_ms: started_at.elapsed().as_millis() as u64,
@@ -198,8 +494,13 @@ mod tests {
 
     use pgtemp::PgTempDB;
 
-    use super::execute_query;
-    use crate::database::{postgres::parser::parse_statements, types::channel, QueryExecEvent};
+    use super::{command_tag, execute_query};
+    use crate::database::{
+        lineage::{ColumnLineage, SourceColumn},
+        postgres::parser::parse_statements,
+        types::channel,
+        QueryExecEvent,
+    };
 
     async fn run_query(
         conn: Arc<tokio_postgres::Client>,
@@ -213,7 +514,7 @@ mod tests {
         let (sender, mut recv) = channel();
 
         tokio::task::spawn(async move {
-            execute_query(&conn, stmt, &sender).await.unwrap();
+            execute_query(&conn, stmt, &sender, None).await.unwrap();
         });
 
         let mut events = Vec::new();
@@ -238,13 +539,19 @@ mod tests {
         let (sender, mut recv) = channel();
 
         tokio::task::spawn(async move {
-            execute_query(&conn, stmt, &sender).await.unwrap();
+            execute_query(&conn, stmt, &sender, None).await.unwrap();
         });
 
-        let event = recv
+        let mut event = recv
             .recv()
             .await
             .ok_or(anyhow::anyhow!("Channel unexpectedly closed"))?;
+        if let QueryExecEvent::CommandTag(_) = event {
+            event = recv
+                .recv()
+                .await
+                .ok_or(anyhow::anyhow!("Channel unexpectedly closed"))?;
+        }
         assert!(matches!(event, QueryExecEvent::Finished { .. }));
         match event {
             QueryExecEvent::Finished {
@@ -259,6 +566,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn rebuilds_command_tags() {
+        assert_eq!(command_tag("UPDATE t SET a = 1", 42).unwrap(), "UPDATE 42");
+        assert_eq!(
+            command_tag("insert into t values (1)", 1).unwrap(),
+            "INSERT 0 1"
+        );
+        assert_eq!(
+            command_tag("/* pgpad */ SELECT * FROM t", 3).unwrap(),
+            "SELECT 3"
+        );
+        assert_eq!(
+            command_tag(
+                "WITH gone AS (DELETE FROM t RETURNING *) INSERT INTO archive SELECT * FROM gone",
+                5
+            )
+            .unwrap(),
+            "INSERT 0 5"
+        );
+        assert_eq!(command_tag("CREATE TABLE t (id int)", 0), None);
+        assert_eq!(command_tag("(SELECT 1)", 1), None);
+    }
+
     #[tokio::test]
     async fn test_queries() -> anyhow::Result<()> {
         let db = PgTempDB::async_new().await;
@@ -302,7 +632,7 @@ mod tests {
         let mut events = run_query(client.clone(), query).await?.into_iter();
         let types_resolved = events.next().unwrap();
         match types_resolved {
-            QueryExecEvent::TypesResolved { columns } => {
+            QueryExecEvent::TypesResolved { columns, .. } => {
                 assert_eq!(
                     serde_json::to_string(&columns).unwrap(),
                     r#"["id","name","age"]"#
@@ -311,6 +641,31 @@ mod tests {
             other => panic!("Expected TypesResolved event, got {:?}", other),
         }
 
+        // `SELECT *` can't be resolved from the query alone, so the lineage comes from Postgres
+        let lineage = events.next().unwrap();
+        match lineage {
+            QueryExecEvent::LineageResolved(lineage) => {
+                let origins: Vec<_> = lineage
+                    .iter()
+                    .map(|lineage| match lineage {
+                        ColumnLineage::Column(SourceColumn { table, column }) => {
+                            (table.as_deref(), column.as_str())
+                        }
+                        other => panic!("Expected a column origin, got {:?}", other),
+                    })
+                    .collect();
+                assert_eq!(
+                    origins,
+                    [
+                        (Some("users"), "id"),
+                        (Some("users"), "name"),
+                        (Some("users"), "age")
+                    ]
+                );
+            }
+            other => panic!("Expected LineageResolved event, got {:?}", other),
+        }
+
         let page = events.next().unwrap();
         match page {
             QueryExecEvent::Page { page_amount, page } => {
@@ -335,6 +690,12 @@ mod tests {
             other => panic!("Expected Page event, got {:?}", other),
         }
 
+        assert!(matches!(
+            events.next(),
+            Some(QueryExecEvent::CommandTag(tag)) if tag == "SELECT 3"
+        ));
+        assert!(matches!(events.next(), Some(QueryExecEvent::Serialized(_))));
+
         let finished = events.next().unwrap();
         match finished {
             QueryExecEvent::Finished {
@@ -350,4 +711,185 @@ mod tests {
         }
         Ok(())
     }
+
+    #[tokio::test]
+    async fn reports_timeouts() {
+        let db = PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        super::set_statement_timeout(&client, Some(50))
+            .await
+            .unwrap();
+        let stmt = parse_statements("SELECT pg_sleep(5)")
+            .unwrap()
+            .pop()
+            .unwrap();
+        let (sender, mut recv) = channel();
+        assert!(execute_query(&client, stmt, &sender, None).await.is_err());
+        drop(sender);
+
+        let mut error = None;
+        while let Some(event) = recv.recv().await {
+            if let QueryExecEvent::Finished { error: e, .. } = event {
+                error = e;
+            }
+        }
+        assert_eq!(
+            error.as_deref(),
+            Some("Query timed out: the statement ran for longer than the connection's statement_timeout")
+        );
+
+        super::set_statement_timeout(&client, None).await.unwrap();
+        client.batch_execute("SELECT pg_sleep(0.1)").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn binds_parameters() {
+        let db = PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        async fn run(
+            client: &tokio_postgres::Client,
+            query: &str,
+            params: serde_json::Value,
+        ) -> Vec<QueryExecEvent> {
+            let mut stmt = parse_statements(query).unwrap().pop().unwrap();
+            stmt.params = serde_json::from_value(params).unwrap();
+
+            let (sender, mut recv) = channel();
+            let _ = execute_query(client, stmt, &sender, None).await;
+            drop(sender);
+
+            let mut events = Vec::new();
+            while let Some(event) = recv.recv().await {
+                events.push(event);
+            }
+            events
+        }
+
+        client
+            .batch_execute(
+                "CREATE TABLE events (id INT, at DATE, amount NUMERIC(10, 2), tags TEXT[], note TEXT)",
+            )
+            .await
+            .unwrap();
+
+        // Values are converted to the types Postgres inferred, like literals would be
+        let events = run(
+            &client,
+            "INSERT INTO events VALUES ($1, $2, $3, $4, $5)",
+            serde_json::json!({
+                "$1": "7",
+                "$2": "2024-02-29",
+                "$3": 12.5,
+                "$4": ["a", "b \"c\""],
+                "$5": null,
+            }),
+        )
+        .await;
+        assert!(matches!(
+            events.as_slice(),
+            [
+                QueryExecEvent::CommandTag(tag),
+                QueryExecEvent::Finished {
+                    affected_rows: 1,
+                    error: None,
+                    ..
+                }
+            ] if tag == "INSERT 0 1"
+        ));
+
+        let events = run(
+            &client,
+            "SELECT id, at, amount, tags, note IS NULL FROM events WHERE id = $1",
+            serde_json::json!({ "$1": 7 }),
+        )
+        .await;
+        let page = events.iter().find_map(|event| match event {
+            QueryExecEvent::Page { page, .. } => Some(page.get()),
+            _ => None,
+        });
+        assert_eq!(
+            page,
+            Some(r#"[[7,"2024-02-29","12.50",["a","b \"c\""],true]]"#)
+        );
+
+        let events = run(
+            &client,
+            "SELECT $1::time, $2::date",
+            serde_json::json!({ "$1": "08:15:00", "$2": "1850-01-01" }),
+        )
+        .await;
+        let page = events.iter().find_map(|event| match event {
+            QueryExecEvent::Page { page, .. } => Some(page.get()),
+            _ => None,
+        });
+        assert_eq!(page, Some(r#"[["08:15:00","1850-01-01"]]"#));
+
+        let events = run(
+            &client,
+            "SELECT $1::int + $2",
+            serde_json::json!({ "$1": 1 }),
+        )
+        .await;
+        match events.last() {
+            Some(QueryExecEvent::Finished { error, .. }) => {
+                assert_eq!(error.as_deref(), Some("No value given for parameter $2"))
+            }
+            other => panic!("Expected Finished event, got {:?}", other),
+        }
+
+        let events = run(
+            &client,
+            "SELECT $1::date",
+            serde_json::json!({ "$1": "yesterday-ish" }),
+        )
+        .await;
+        match events.last() {
+            Some(QueryExecEvent::Finished { error, .. }) => {
+                assert!(error
+                    .as_deref()
+                    .unwrap()
+                    .contains("invalid input syntax for type date"))
+            }
+            other => panic!("Expected Finished event, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn puts_the_active_schema_first_in_the_search_path() {
+        let db = PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let search_path = || async {
+            client
+                .query_one("SHOW search_path", &[])
+                .await
+                .unwrap()
+                .get::<_, String>(0)
+        };
+        let started_with = search_path().await;
+
+        super::set_search_path(&client, Some("Sales"))
+            .await
+            .unwrap();
+        assert_eq!(search_path().await, format!(r#""Sales", {started_with}"#));
+
+        // Replaced rather than stacked
+        super::set_search_path(&client, Some("billing"))
+            .await
+            .unwrap();
+        assert_eq!(search_path().await, format!("billing, {started_with}"));
+
+        super::set_search_path(&client, None).await.unwrap();
+        assert_eq!(search_path().await, started_with);
+    }
 }
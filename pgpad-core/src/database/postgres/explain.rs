@@ -0,0 +1,420 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlparser::{ast::Statement, dialect::PostgreSqlDialect, parser::Parser};
+use tokio_postgres::Client;
+
+use crate::{
+    database::{
+        parser::{ParsedStatement, StatementKind},
+        postgres::execute::DbError,
+        preflight::CostEstimate,
+    },
+    Error,
+};
+
+/// Estimates the cost of each statement in `query` with `EXPLAIN (FORMAT JSON)`, without running them.
+///
+/// Statements `EXPLAIN` can't handle (DDL, transaction control, ...) are skipped, as are the ones that fail
+/// to plan (e.g. because they depend on a table created earlier in the script) or take longer than `timeout`.
+pub async fn estimate_costs(client: &Client, query: &str, timeout: Duration) -> Vec<CostEstimate> {
+    let statements = match Parser::parse_sql(&PostgreSqlDialect {}, query) {
+        Ok(statements) => statements,
+        Err(err) => {
+            log::info!("Skipping cost estimation for a query we couldn't parse: {err}");
+            return Vec::new();
+        }
+    };
+
+    let mut estimates = Vec::new();
+    for statement in statements.iter().filter(|stmt| is_explainable(stmt)) {
+        let statement = statement.to_string();
+        let explain = format!("EXPLAIN (FORMAT JSON) {statement}");
+
+        let plan: Value = match tokio::time::timeout(timeout, client.query_one(&explain, &[])).await
+        {
+            Ok(Ok(row)) => match row.try_get(0) {
+                Ok(plan) => plan,
+                Err(err) => {
+                    log::warn!("Unexpected EXPLAIN output: {err}");
+                    continue;
+                }
+            },
+            Ok(Err(err)) => {
+                log::info!("Skipping cost estimation of a statement: {err}");
+                continue;
+            }
+            Err(_) => {
+                log::info!("Cost estimation timed out after {}ms", timeout.as_millis());
+                continue;
+            }
+        };
+
+        if let Some((total_cost, estimated_rows)) = plan_estimates(&plan) {
+            estimates.push(CostEstimate {
+                statement,
+                total_cost,
+                estimated_rows,
+            });
+        }
+    }
+
+    estimates
+}
+
+/// Statements `EXPLAIN` accepts
+fn is_explainable(stmt: &Statement) -> bool {
+    matches!(
+        stmt,
+        Statement::Query(_)
+            | Statement::Insert(_)
+            | Statement::Update { .. }
+            | Statement::Delete(_)
+            | Statement::Merge { .. }
+    )
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ExplainOptions {
+    /// Runs the statement, to report actual timings and row counts besides the planner's estimates
+    pub analyze: bool,
+    /// Lets ANALYZE run statements that modify data, in a transaction that's rolled back
+    #[serde(default)]
+    pub analyze_writes: bool,
+}
+
+/// A node of a plan, with the fields every node has pulled out, and the rest left in `details`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlanNode {
+    /// e.g. "Seq Scan" or "Hash Join"
+    pub node_type: String,
+    pub relation_name: Option<String>,
+    pub startup_cost: f64,
+    pub total_cost: f64,
+    /// The planner's estimate
+    pub plan_rows: f64,
+    pub plan_width: f64,
+    /// Only with ANALYZE. Per loop, like the times.
+    pub actual_rows: Option<f64>,
+    pub actual_loops: Option<f64>,
+    pub actual_startup_time_ms: Option<f64>,
+    pub actual_total_time_ms: Option<f64>,
+    pub details: Map<String, Value>,
+    pub children: Vec<PlanNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplainedPlan {
+    pub statement: String,
+    pub analyzed: bool,
+    /// Whether the statement ran in a transaction that was rolled back
+    pub rolled_back: bool,
+    pub planning_time_ms: Option<f64>,
+    pub execution_time_ms: Option<f64>,
+    pub plan: PlanNode,
+}
+
+impl PlanNode {
+    fn from_json(mut node: Map<String, Value>) -> anyhow::Result<Self> {
+        let mut take_f64 = |key: &str| node.remove(key).and_then(|value| value.as_f64());
+
+        let startup_cost = take_f64("Startup Cost").context("Plan node without a startup cost")?;
+        let total_cost = take_f64("Total Cost").context("Plan node without a total cost")?;
+        let plan_rows = take_f64("Plan Rows").context("Plan node without estimated rows")?;
+        let plan_width = take_f64("Plan Width").unwrap_or_default();
+        let actual_rows = take_f64("Actual Rows");
+        let actual_loops = take_f64("Actual Loops");
+        let actual_startup_time_ms = take_f64("Actual Startup Time");
+        let actual_total_time_ms = take_f64("Actual Total Time");
+
+        let node_type = match node.remove("Node Type") {
+            Some(Value::String(node_type)) => node_type,
+            _ => anyhow::bail!("Plan node without a type"),
+        };
+        let relation_name = match node.remove("Relation Name") {
+            Some(Value::String(relation)) => Some(relation),
+            _ => None,
+        };
+        let children = match node.remove("Plans") {
+            Some(Value::Array(children)) => children
+                .into_iter()
+                .map(|child| match child {
+                    Value::Object(child) => Self::from_json(child),
+                    _ => anyhow::bail!("Plan node that isn't an object"),
+                })
+                .collect::<anyhow::Result<_>>()?,
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            node_type,
+            relation_name,
+            startup_cost,
+            total_cost,
+            plan_rows,
+            plan_width,
+            actual_rows,
+            actual_loops,
+            actual_startup_time_ms,
+            actual_total_time_ms,
+            details: node,
+            children,
+        })
+    }
+}
+
+/// Reads the output of `EXPLAIN (FORMAT JSON)`, i.e. `[{"Plan": {..}, "Planning Time": ..}]`
+fn read_plan(output: Value) -> anyhow::Result<(PlanNode, Option<f64>, Option<f64>)> {
+    let Some(Value::Object(mut explained)) = output.as_array().and_then(|a| a.first()).cloned()
+    else {
+        anyhow::bail!("Unexpected EXPLAIN output");
+    };
+    let plan = match explained.remove("Plan") {
+        Some(Value::Object(plan)) => PlanNode::from_json(plan)?,
+        _ => anyhow::bail!("EXPLAIN output without a plan"),
+    };
+    let planning = explained.get("Planning Time").and_then(Value::as_f64);
+    let execution = explained.get("Execution Time").and_then(Value::as_f64);
+
+    Ok((plan, planning, execution))
+}
+
+/// The statement of a script under the cursor, a byte offset: the one it's in, or else the last one before it.
+/// Without a cursor, the script has to be a single statement.
+pub fn statement_at(
+    statements: &[ParsedStatement],
+    cursor: Option<usize>,
+) -> Result<&ParsedStatement, Error> {
+    let statement = match cursor {
+        Some(cursor) => statements
+            .iter()
+            .take_while(|stmt| stmt.span.start <= cursor)
+            .last()
+            .or(statements.first()),
+        None if statements.len() > 1 => {
+            return Err(anyhow::anyhow!(
+                "The query has {} statements, so it's the one under the cursor that's explained",
+                statements.len()
+            )
+            .into())
+        }
+        None => statements.first(),
+    };
+
+    statement
+        .context("There's no statement to explain")
+        .map_err(Into::into)
+}
+
+/// Explains a statement with `EXPLAIN (FORMAT JSON, BUFFERS)`, and ANALYZE if asked to.
+///
+/// ANALYZE runs the statement, so it's refused for statements that modify data, unless `analyze_writes` is set,
+/// in which case the statement runs in a transaction that's rolled back. The client mustn't already be in a
+/// transaction, which the rollback would end.
+pub async fn explain_statement(
+    client: &Client,
+    query: &str,
+    statement: &ParsedStatement,
+    options: ExplainOptions,
+) -> Result<ExplainedPlan, Error> {
+    if !matches!(statement.kind, StatementKind::Select | StatementKind::Dml) {
+        return Err(anyhow::anyhow!(
+            "Only queries and INSERT, UPDATE, DELETE or MERGE statements can be explained"
+        )
+        .into());
+    }
+
+    let rolled_back = options.analyze && !statement.is_read_only;
+    if rolled_back && !options.analyze_writes {
+        return Err(anyhow::anyhow!(
+            "EXPLAIN ANALYZE runs the statement, which modifies data. Allow it to run in a transaction that's rolled back to explain it."
+        )
+        .into());
+    }
+
+    let text = &query[statement.span.clone()];
+    let explain = match options.analyze {
+        true => format!("EXPLAIN (FORMAT JSON, ANALYZE, BUFFERS) {text}"),
+        false => format!("EXPLAIN (FORMAT JSON, BUFFERS) {text}"),
+    };
+
+    let db_error = |err: tokio_postgres::Error| Error::from(anyhow::anyhow!("{}", DbError(&err)));
+    let output = match rolled_back {
+        true => {
+            client.batch_execute("BEGIN").await.map_err(db_error)?;
+            let output = client.query_one(&explain, &[]).await;
+            client.batch_execute("ROLLBACK").await.map_err(db_error)?;
+            output
+        }
+        false => client.query_one(&explain, &[]).await,
+    };
+    let output: Value = output
+        .map_err(db_error)?
+        .try_get(0)
+        .context("Unexpected EXPLAIN output")?;
+    let (plan, planning_time_ms, execution_time_ms) = read_plan(output)?;
+
+    Ok(ExplainedPlan {
+        statement: text.to_owned(),
+        analyzed: options.analyze,
+        rolled_back,
+        planning_time_ms,
+        execution_time_ms,
+        plan,
+    })
+}
+
+/// Reads the total cost and estimated rows of the top-level plan node, i.e. `[{"Plan": {"Total Cost": .., "Plan Rows": ..}}]`
+fn plan_estimates(plan: &Value) -> Option<(f64, f64)> {
+    let plan = plan.get(0)?.get("Plan")?;
+
+    Some((
+        plan.get("Total Cost")?.as_f64()?,
+        plan.get("Plan Rows")?.as_f64()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_plan_estimates() {
+        let plan = serde_json::json!([{
+            "Plan": {
+                "Node Type": "Seq Scan",
+                "Relation Name": "users",
+                "Startup Cost": 0.00,
+                "Total Cost": 22.70,
+                "Plan Rows": 1270,
+                "Plan Width": 36
+            }
+        }]);
+        assert_eq!(plan_estimates(&plan), Some((22.70, 1270.0)));
+        assert_eq!(plan_estimates(&serde_json::json!([])), None);
+    }
+
+    #[test]
+    fn reads_plan_trees() {
+        let output = serde_json::json!([{
+            "Plan": {
+                "Node Type": "Hash Join",
+                "Join Type": "Inner",
+                "Startup Cost": 1.5,
+                "Total Cost": 40.25,
+                "Plan Rows": 10,
+                "Plan Width": 8,
+                "Actual Rows": 3,
+                "Actual Loops": 1,
+                "Actual Startup Time": 0.1,
+                "Actual Total Time": 0.4,
+                "Plans": [
+                    {"Node Type": "Seq Scan", "Relation Name": "users", "Startup Cost": 0.0,
+                     "Total Cost": 22.7, "Plan Rows": 1270, "Plan Width": 4},
+                    {"Node Type": "Hash", "Startup Cost": 1.0, "Total Cost": 1.0, "Plan Rows": 5,
+                     "Plan Width": 4, "Plans": [{"Node Type": "Seq Scan", "Relation Name": "teams",
+                     "Startup Cost": 0.0, "Total Cost": 1.0, "Plan Rows": 5, "Plan Width": 4}]}
+                ]
+            },
+            "Planning Time": 0.2,
+            "Execution Time": 0.5
+        }]);
+
+        let (plan, planning, execution) = read_plan(output).unwrap();
+        assert_eq!((planning, execution), (Some(0.2), Some(0.5)));
+        assert_eq!(plan.node_type, "Hash Join");
+        assert_eq!(plan.actual_rows, Some(3.0));
+        assert_eq!(plan.details.get("Join Type"), Some(&Value::from("Inner")));
+        assert!(!plan.details.contains_key("Plans"));
+
+        let scans: Vec<_> = [&plan.children[0], &plan.children[1].children[0]]
+            .iter()
+            .map(|node| node.relation_name.as_deref())
+            .collect();
+        assert_eq!(scans, [Some("users"), Some("teams")]);
+        assert_eq!(plan.children[0].actual_rows, None);
+
+        assert!(read_plan(serde_json::json!([{"Plan": {"Node Type": "Result"}}])).is_err());
+    }
+
+    #[test]
+    fn explains_the_statement_under_the_cursor() {
+        let query = "SELECT 1;\nSELECT 2;  \nSELECT 3";
+        let statements = crate::database::postgres::parser::parse_statements(query).unwrap();
+        let at = |cursor| &query[statement_at(&statements, cursor).unwrap().span.clone()];
+
+        assert_eq!(at(Some(0)), "SELECT 1");
+        assert_eq!(at(Some(12)), "SELECT 2");
+        // Past the end of a statement, but before the next one
+        assert_eq!(at(Some(20)), "SELECT 2");
+        assert_eq!(at(Some(query.len())), "SELECT 3");
+        assert!(statement_at(&statements, None).is_err());
+        assert!(statement_at(&[], Some(0)).is_err());
+    }
+
+    #[tokio::test]
+    async fn analyzes_writes_in_a_rolled_back_transaction() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        client
+            .batch_execute("CREATE TABLE t (id int); INSERT INTO t VALUES (1), (2)")
+            .await
+            .unwrap();
+
+        let query = "SELECT * FROM t; DELETE FROM t WHERE id = 1";
+        let statements = crate::database::postgres::parser::parse_statements(query).unwrap();
+        let analyze = ExplainOptions {
+            analyze: true,
+            analyze_writes: false,
+        };
+
+        let select = explain_statement(&client, query, &statements[0], analyze)
+            .await
+            .unwrap();
+        assert!(select.analyzed && !select.rolled_back);
+        assert_eq!(select.plan.node_type, "Seq Scan");
+        assert_eq!(select.plan.actual_rows, Some(2.0));
+        assert!(select.execution_time_ms.is_some());
+
+        assert!(explain_statement(&client, query, &statements[1], analyze)
+            .await
+            .is_err());
+
+        let delete = explain_statement(
+            &client,
+            query,
+            &statements[1],
+            ExplainOptions {
+                analyze_writes: true,
+                ..analyze
+            },
+        )
+        .await
+        .unwrap();
+        assert!(delete.rolled_back);
+        assert_eq!(delete.plan.node_type, "ModifyTable");
+        let count: i64 = client
+            .query_one("SELECT count(*) FROM t", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn skips_statements_explain_cannot_handle() {
+        let statements = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT 1; CREATE TABLE t (id int); BEGIN; UPDATE t SET id = 2; VACUUM t",
+        )
+        .unwrap();
+
+        let explainable: Vec<_> = statements.iter().map(is_explainable).collect();
+        assert_eq!(explainable, [true, false, false, true, false]);
+    }
+}
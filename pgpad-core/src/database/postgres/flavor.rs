@@ -0,0 +1,202 @@
+//! Servers that speak the Postgres protocol without being Postgres, or without being only Postgres, and what they
+//! can't do that pgpad otherwise relies on.
+//!
+//! The flavor is detected once per connection, from `version()` and from the catalogs and settings that are
+//! specific to each flavor, and kept with the connection as [`ServerCapabilities`].
+
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Client;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerFlavor {
+    #[default]
+    Postgres,
+    CockroachDb,
+    Neon,
+    AlloyDb,
+}
+
+impl ServerFlavor {
+    pub fn name(self) -> &'static str {
+        match self {
+            ServerFlavor::Postgres => "Postgres",
+            ServerFlavor::CockroachDb => "CockroachDB",
+            ServerFlavor::Neon => "Neon",
+            ServerFlavor::AlloyDb => "AlloyDB",
+        }
+    }
+}
+
+/// What a connected server supports, for the UI to disable what it doesn't
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerCapabilities {
+    pub flavor: ServerFlavor,
+    /// As reported by `version()`
+    pub version: String,
+    pub listen_notify: bool,
+    pub copy_to_stdout: bool,
+    /// Replication slots, publications and subscriptions
+    pub replication: bool,
+    /// Table inheritance and declarative partitioning, i.e. whether `pg_inherits` means anything
+    pub table_inheritance: bool,
+}
+
+/// The settings that give away a flavor whose `version()` is Postgres'
+const FLAVOR_SETTINGS_QUERY: &str = "
+    SELECT name FROM pg_settings
+    WHERE name LIKE 'neon.%' OR name LIKE 'alloydb.%' OR name LIKE 'google_columnar_engine.%'
+";
+
+/// Detects the flavor from the server's `version()`, whether it has a `crdb_internal` schema, and the names of its
+/// flavor-specific settings
+pub fn detect_flavor(version: &str, has_crdb_internal: bool, settings: &[String]) -> ServerFlavor {
+    let has_setting = |prefix: &str| settings.iter().any(|name| name.starts_with(prefix));
+
+    if version.starts_with("CockroachDB") || has_crdb_internal {
+        ServerFlavor::CockroachDb
+    } else if has_setting("neon.") {
+        ServerFlavor::Neon
+    } else if has_setting("alloydb.") || has_setting("google_columnar_engine.") {
+        ServerFlavor::AlloyDb
+    } else {
+        ServerFlavor::Postgres
+    }
+}
+
+impl ServerCapabilities {
+    /// `pooled` is for connections through a transaction-mode pooler, e.g. Neon's `-pooler` endpoints, where
+    /// sessions don't outlive transactions
+    pub fn new(flavor: ServerFlavor, version: String, pooled: bool) -> Self {
+        let is_cockroach = flavor == ServerFlavor::CockroachDb;
+        Self {
+            flavor,
+            version,
+            listen_notify: !is_cockroach && !pooled,
+            copy_to_stdout: !is_cockroach,
+            replication: !is_cockroach,
+            table_inheritance: !is_cockroach,
+        }
+    }
+}
+
+/// Whether the connection goes through a connection pooler rather than to the server itself
+fn is_pooled(config: &tokio_postgres::Config) -> bool {
+    config.get_hosts().iter().any(|host| match host {
+        tokio_postgres::config::Host::Tcp(host) => host
+            .split('.')
+            .next()
+            .is_some_and(|label| label.ends_with("-pooler")),
+        #[cfg(unix)]
+        tokio_postgres::config::Host::Unix(_) => false,
+    })
+}
+
+/// Detects what the server behind `client` supports. Falls back to plain Postgres if it can't be told, since
+/// that's what most servers are.
+pub async fn detect_capabilities(
+    client: &Client,
+    config: &tokio_postgres::Config,
+) -> ServerCapabilities {
+    let pooled = is_pooled(config);
+
+    let row = match client
+        .query_one(
+            "SELECT version(), EXISTS (SELECT 1 FROM pg_namespace WHERE nspname = 'crdb_internal')",
+            &[],
+        )
+        .await
+    {
+        Ok(row) => row,
+        Err(err) => {
+            log::warn!("Failed to detect the server's flavor: {err}");
+            return ServerCapabilities::new(ServerFlavor::Postgres, String::new(), pooled);
+        }
+    };
+    let version: String = row.get(0);
+    let has_crdb_internal: bool = row.get(1);
+
+    // Not every flavor has pg_settings, and those that don't aren't told apart by it anyway
+    let settings: Vec<String> = match has_crdb_internal {
+        true => Vec::new(),
+        false => match client.query(FLAVOR_SETTINGS_QUERY, &[]).await {
+            Ok(rows) => rows.iter().map(|row| row.get(0)).collect(),
+            Err(err) => {
+                log::debug!("Failed to read the server's settings: {err}");
+                Vec::new()
+            }
+        },
+    };
+
+    let flavor = detect_flavor(&version, has_crdb_internal, &settings);
+    ServerCapabilities::new(flavor, version, pooled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `version()`, `crdb_internal` and flavor-specific settings as returned by each flavor
+    fn recorded_responses() -> Vec<(ServerFlavor, &'static str, bool, Vec<String>)> {
+        vec![
+            (
+                ServerFlavor::Postgres,
+                "PostgreSQL 16.2 (Debian 16.2-1.pgdg120+2) on x86_64-pc-linux-gnu, compiled by gcc (Debian 12.2.0-14) 12.2.0, 64-bit",
+                false,
+                vec![],
+            ),
+            (
+                ServerFlavor::CockroachDb,
+                "CockroachDB CCL v23.2.4 (x86_64-pc-linux-gnu, built 2024/04/08 18:06:36, go1.21.8 X:nocoverageredesign)",
+                true,
+                vec![],
+            ),
+            (
+                ServerFlavor::Neon,
+                "PostgreSQL 16.3 on x86_64-pc-linux-gnu, compiled by gcc (Debian 10.2.1-6) 10.2.1 20210110, 64-bit",
+                false,
+                vec!["neon.tenant_id".into(), "neon.timeline_id".into()],
+            ),
+            (
+                ServerFlavor::AlloyDb,
+                "PostgreSQL 15.5 on x86_64-pc-linux-gnu, compiled by Debian clang version 12.0.1, 64-bit",
+                false,
+                vec!["alloydb.iam_authentication".into(), "google_columnar_engine.enabled".into()],
+            ),
+        ]
+    }
+
+    #[test]
+    fn detects_flavors() {
+        for (flavor, version, has_crdb_internal, settings) in recorded_responses() {
+            assert_eq!(
+                detect_flavor(version, has_crdb_internal, &settings),
+                flavor,
+                "{version}"
+            );
+        }
+
+        // Even if `version()` were to pass for Postgres'
+        assert_eq!(
+            detect_flavor("PostgreSQL 13.0", true, &[]),
+            ServerFlavor::CockroachDb
+        );
+    }
+
+    #[test]
+    fn marks_unsupported_features() {
+        let cockroach = ServerCapabilities::new(ServerFlavor::CockroachDb, String::new(), false);
+        assert!(!cockroach.listen_notify && !cockroach.replication && !cockroach.table_inheritance);
+
+        let neon = ServerCapabilities::new(ServerFlavor::Neon, String::new(), false);
+        assert!(neon.listen_notify && neon.copy_to_stdout && neon.table_inheritance);
+
+        let config: tokio_postgres::Config =
+            "postgres://me@ep-cool-darkness-123456-pooler.us-east-2.aws.neon.tech/neondb"
+                .parse()
+                .unwrap();
+        assert!(is_pooled(&config));
+        let config: tokio_postgres::Config = "postgres://me@db.example.com/app".parse().unwrap();
+        assert!(!is_pooled(&config));
+    }
+}
@@ -0,0 +1,176 @@
+//! `NOTICE`, `WARNING` and `INFO` messages, e.g. from `RAISE NOTICE` or "table does not exist, skipping". Like
+//! notifications they arrive on the connection task, which hands them to the statement running on its client, if
+//! any. Those that arrive between statements go to a log kept per connection, which outlives reconnects.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use dashmap::DashMap;
+use tokio_postgres::error::DbError;
+use uuid::Uuid;
+
+use crate::database::types::{ExecSender, QueryExecEvent, ServerMessage};
+
+/// Messages kept in a connection's log, the oldest going first
+pub const MAX_LOGGED_MESSAGES: usize = 500;
+
+type MessageLog = Arc<Mutex<VecDeque<ServerMessage>>>;
+
+/// Hands the messages of one client over, from its connection task
+#[derive(Debug, Clone)]
+pub struct MessageForwarder {
+    log: MessageLog,
+    /// Of the statement running on the client
+    statement: Arc<Mutex<Option<ExecSender>>>,
+}
+
+impl MessageForwarder {
+    pub fn forward(&self, notice: &DbError) {
+        let message = ServerMessage {
+            severity: notice.severity().to_owned(),
+            text: notice.message().to_owned(),
+        };
+
+        if let Some(sender) = self.statement.lock().unwrap().as_ref() {
+            let sent = sender.send(QueryExecEvent::Message {
+                severity: message.severity.clone(),
+                text: message.text.clone(),
+            });
+            if sent.is_ok() {
+                return;
+            }
+        }
+
+        let mut log = self.log.lock().unwrap();
+        if log.len() == MAX_LOGGED_MESSAGES {
+            log.pop_front();
+        }
+        log.push_back(message);
+    }
+
+    /// Hands the client's messages to a statement until the returned guard is dropped
+    pub fn route_to(&self, sender: ExecSender) -> StatementRoute {
+        *self.statement.lock().unwrap() = Some(sender);
+        StatementRoute {
+            statement: self.statement.clone(),
+        }
+    }
+}
+
+/// Routes a client's messages to its statement while alive
+#[derive(Debug)]
+pub struct StatementRoute {
+    statement: Arc<Mutex<Option<ExecSender>>>,
+}
+
+impl Drop for StatementRoute {
+    fn drop(&mut self) {
+        self.statement.lock().unwrap().take();
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ServerMessages {
+    logs: DashMap<Uuid, MessageLog>,
+}
+
+impl ServerMessages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A forwarder for a new client of the connection
+    pub fn forwarder(&self, connection_id: Uuid) -> MessageForwarder {
+        MessageForwarder {
+            log: self.logs.entry(connection_id).or_default().clone(),
+            statement: Arc::default(),
+        }
+    }
+
+    /// The messages that arrived outside of statements, oldest first
+    pub fn messages(&self, connection_id: Uuid) -> Vec<ServerMessage> {
+        self.logs
+            .get(&connection_id)
+            .map(|log| log.lock().unwrap().iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forgets the messages of a connection, e.g. when the user removes it
+    pub fn clear(&self, connection_id: Uuid) {
+        if let Some(log) = self.logs.get(&connection_id) {
+            log.lock().unwrap().clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::database::{
+        postgres::{connect::connect, notifications::Notifications},
+        types::channel,
+        Certificates, ConnectionMonitor,
+    };
+
+    #[tokio::test]
+    async fn routes_messages_to_the_running_statement() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let config: tokio_postgres::Config = db.connection_uri().parse().unwrap();
+        let (monitor, _dropped) = ConnectionMonitor::new();
+        let connection_id = Uuid::new_v4();
+
+        let messages = ServerMessages::new();
+        let forwarder = messages.forwarder(connection_id);
+        let client = connect(
+            &config,
+            &Certificates::new(),
+            None,
+            monitor.notifier(connection_id),
+            Notifications::new().forwarder(connection_id),
+            forwarder.clone(),
+        )
+        .await
+        .unwrap();
+
+        let (sender, mut receiver) = channel();
+        let route = forwarder.route_to(sender);
+        client
+            .batch_execute("DO $$ BEGIN RAISE NOTICE 'in the statement'; END $$")
+            .await
+            .unwrap();
+        let event = timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let QueryExecEvent::Message { severity, text } = event else {
+            panic!("Expected a message, got {event:?}");
+        };
+        assert_eq!(
+            (severity.as_str(), text.as_str()),
+            ("NOTICE", "in the statement")
+        );
+        drop(route);
+
+        client
+            .batch_execute("DROP TABLE IF EXISTS missing")
+            .await
+            .unwrap();
+        assert!(receiver.recv().await.is_none());
+        assert_eq!(
+            messages.messages(connection_id),
+            [ServerMessage {
+                severity: "NOTICE".to_owned(),
+                text: "table \"missing\" does not exist, skipping".to_owned(),
+            }]
+        );
+
+        messages.clear(connection_id);
+        assert!(messages.messages(connection_id).is_empty());
+    }
+}
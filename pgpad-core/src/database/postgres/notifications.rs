@@ -0,0 +1,230 @@
+//! `LISTEN`/`NOTIFY` support. Notifications arrive on the connection task rather than on the client, so the task
+//! forwards them, and they're reported through the receiver given out by [`Notifications::subscribe`].
+//!
+//! The channels listened on are kept per connection, so that they can be listened on again once the connection
+//! is re-established after dropping.
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio_postgres::Client;
+use uuid::Uuid;
+
+use crate::{utils::quote_identifier, Error};
+
+type LiveSender = Arc<Mutex<Option<UnboundedSender<PostgresNotification>>>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PostgresNotification {
+    pub connection_id: Uuid,
+    pub channel: String,
+    pub payload: String,
+    /// Of the server process that sent the notification
+    pub pid: i32,
+}
+
+/// Hands the notifications of one connection over, from its connection task
+#[derive(Debug, Clone)]
+pub struct NotificationForwarder {
+    connection_id: Uuid,
+    live: LiveSender,
+}
+
+impl NotificationForwarder {
+    pub fn forward(&self, notification: &tokio_postgres::Notification) {
+        let notification = PostgresNotification {
+            connection_id: self.connection_id,
+            channel: notification.channel().to_owned(),
+            payload: notification.payload().to_owned(),
+            pid: notification.process_id(),
+        };
+
+        if let Some(sender) = self.live.lock().unwrap().as_ref() {
+            let _ = sender.send(notification);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Notifications {
+    /// Channels listened on, per connection
+    listens: DashMap<Uuid, BTreeSet<String>>,
+    live: LiveSender,
+}
+
+fn listen_statement(channel: &str) -> String {
+    format!("LISTEN {}", quote_identifier(channel))
+}
+
+fn unlisten_statement(channel: &str) -> String {
+    format!("UNLISTEN {}", quote_identifier(channel))
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> UnboundedReceiver<PostgresNotification> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.live.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    pub fn forwarder(&self, connection_id: Uuid) -> NotificationForwarder {
+        NotificationForwarder {
+            connection_id,
+            live: self.live.clone(),
+        }
+    }
+
+    pub async fn listen(
+        &self,
+        connection_id: Uuid,
+        client: &Client,
+        channel: &str,
+    ) -> Result<(), Error> {
+        if channel.is_empty() {
+            return Err(anyhow::anyhow!("Channel names can't be empty").into());
+        }
+
+        client
+            .batch_execute(&listen_statement(channel))
+            .await
+            .with_context(|| format!("Failed to listen on {channel}"))?;
+        self.listens
+            .entry(connection_id)
+            .or_default()
+            .insert(channel.to_owned());
+        Ok(())
+    }
+
+    pub async fn unlisten(
+        &self,
+        connection_id: Uuid,
+        client: &Client,
+        channel: &str,
+    ) -> Result<(), Error> {
+        client
+            .batch_execute(&unlisten_statement(channel))
+            .await
+            .with_context(|| format!("Failed to stop listening on {channel}"))?;
+        if let Some(mut channels) = self.listens.get_mut(&connection_id) {
+            channels.remove(channel);
+        }
+        Ok(())
+    }
+
+    /// Listens again on the channels of a connection, once it's re-established
+    pub async fn relisten(&self, connection_id: Uuid, client: &Client) {
+        for channel in self.active_listens(connection_id) {
+            if let Err(err) = client.batch_execute(&listen_statement(&channel)).await {
+                log::warn!("Failed to listen on {channel} again: {err}");
+            }
+        }
+    }
+
+    /// Channels a connection listens on, by name
+    pub fn active_listens(&self, connection_id: Uuid) -> Vec<String> {
+        self.listens
+            .get(&connection_id)
+            .map(|channels| channels.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forgets the channels of a connection, e.g. when the user disconnects it
+    pub fn clear(&self, connection_id: Uuid) {
+        self.listens.remove(&connection_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::database::{
+        postgres::{connect::connect, messages::ServerMessages},
+        Certificates, ConnectionMonitor,
+    };
+
+    #[tokio::test]
+    async fn forwards_notifications_across_reconnects() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let config: tokio_postgres::Config = db.connection_uri().parse().unwrap();
+        let certificates = Certificates::new();
+        let (monitor, _dropped) = ConnectionMonitor::new();
+        let connection_id = Uuid::new_v4();
+
+        let notifications = Notifications::new();
+        let mut received = notifications.subscribe();
+        let connect = || {
+            connect(
+                &config,
+                &certificates,
+                None,
+                monitor.notifier(connection_id),
+                notifications.forwarder(connection_id),
+                ServerMessages::new().forwarder(connection_id),
+            )
+        };
+
+        let client = connect().await.unwrap();
+        notifications
+            .listen(connection_id, &client, "Jobs Done")
+            .await
+            .unwrap();
+        notifications
+            .listen(connection_id, &client, "audit")
+            .await
+            .unwrap();
+        assert_eq!(
+            notifications.active_listens(connection_id),
+            ["Jobs Done", "audit"]
+        );
+
+        client
+            .batch_execute("NOTIFY \"Jobs Done\", '42'")
+            .await
+            .unwrap();
+        let notification = timeout(Duration::from_secs(5), received.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(notification.connection_id, connection_id);
+        assert_eq!(notification.channel, "Jobs Done");
+        assert_eq!(notification.payload, "42");
+        assert!(notification.pid > 0);
+
+        // A new session doesn't listen on anything until told to again
+        drop(client);
+        let client = connect().await.unwrap();
+        notifications.relisten(connection_id, &client).await;
+        notifications
+            .unlisten(connection_id, &client, "Jobs Done")
+            .await
+            .unwrap();
+        assert_eq!(notifications.active_listens(connection_id), ["audit"]);
+
+        client
+            .batch_execute("NOTIFY \"Jobs Done\", 'ignored'; NOTIFY audit, 'again'")
+            .await
+            .unwrap();
+        let notification = timeout(Duration::from_secs(5), received.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (notification.channel.as_str(), notification.payload.as_str()),
+            ("audit", "again")
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Values of placeholders are sent as text, which Postgres converts to the types it inferred for the placeholders
+//! the way it converts literals. Dates, numerics and the like then take whatever Postgres accepts, not only what
+//! JSON can hold.
+
+use std::error::Error as StdError;
+
+use bytes::BytesMut;
+use serde_json::Value;
+use tokio_postgres::{
+    types::{to_sql_checked, Format, IsNull, Kind, ToSql, Type},
+    Statement,
+};
+
+use crate::{
+    database::params::{self, QueryParams},
+    Error,
+};
+
+/// The value of a placeholder, as text
+#[derive(Debug)]
+pub struct TextParam(Option<String>);
+
+impl TextParam {
+    fn new(value: &Value, ty: &Type) -> Self {
+        match (value, ty.kind()) {
+            (Value::Array(elements), Kind::Array(_)) => Self(Some(array_literal(elements))),
+            _ => Self(params::text(value)),
+        }
+    }
+}
+
+impl ToSql for TextParam {
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn StdError + Sync + Send>> {
+        match &self.0 {
+            Some(text) => {
+                out.extend_from_slice(text.as_bytes());
+                Ok(IsNull::No)
+            }
+            None => Ok(IsNull::Yes),
+        }
+    }
+
+    /// Postgres checks the text itself
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    fn encode_format(&self, _ty: &Type) -> Format {
+        Format::Text
+    }
+
+    to_sql_checked!();
+}
+
+/// The values of a prepared statement's placeholders, from `$1` on
+pub fn bind(statement: &Statement, values: &QueryParams) -> Result<Vec<TextParam>, Error> {
+    statement
+        .params()
+        .iter()
+        .enumerate()
+        .map(|(idx, ty)| {
+            let value = params::value(values, &format!("${}", idx + 1))?;
+            Ok(TextParam::new(value, ty))
+        })
+        .collect()
+}
+
+/// e.g. `{1,NULL,"a \"b\""}`, and `{{1,2},{3,4}}` for nested arrays
+fn array_literal(elements: &[Value]) -> String {
+    let mut literal = String::from("{");
+    for (idx, element) in elements.iter().enumerate() {
+        if idx > 0 {
+            literal.push(',');
+        }
+        match element {
+            Value::Null => literal.push_str("NULL"),
+            Value::Array(inner) => literal.push_str(&array_literal(inner)),
+            other => {
+                literal.push('"');
+                for ch in params::text(other).unwrap_or_default().chars() {
+                    if matches!(ch, '"' | '\\') {
+                        literal.push('\\');
+                    }
+                    literal.push(ch);
+                }
+                literal.push('"');
+            }
+        }
+    }
+    literal.push('}');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn writes_array_literals() {
+        let array = json!([1, null, "a \"b\" \\c", [true, false]]);
+        assert_eq!(
+            TextParam::new(&array, &Type::TEXT_ARRAY).0.unwrap(),
+            r#"{"1",NULL,"a \"b\" \\c",{"true","false"}}"#
+        );
+
+        // Arrays are JSON for the other types
+        assert_eq!(
+            TextParam::new(&array, &Type::JSONB).0.unwrap(),
+            r#"[1,null,"a \"b\" \\c",[true,false]]"#
+        );
+        assert_eq!(TextParam::new(&Value::Null, &Type::INT4).0, None);
+    }
+}
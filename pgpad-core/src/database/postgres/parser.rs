@@ -25,6 +25,11 @@ impl SqlDialectExt for PostgreSqlDialect {
             _ => false,
         }
     }
+
+    fn binds_placeholder(name: &str) -> bool {
+        name.strip_prefix('$')
+            .is_some_and(|index| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+    }
 }
 
 #[cfg(test)]
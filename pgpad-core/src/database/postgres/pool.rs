@@ -0,0 +1,351 @@
+//! Postgres connections keep a few clients rather than a single one, so that schema refreshes and other metadata
+//! queries don't queue behind a long-running user query.
+//!
+//! User queries all run on the same session client, since they can depend on its session state: transactions,
+//! `SET`s, temporary tables, channels listened on. Metadata queries take turns on the other clients.
+//!
+//! A metadata client can also be reserved for a transaction of its own, see [`PostgresPool::reserve_session`].
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::Instant,
+};
+
+use anyhow::Context;
+use futures_util::future::try_join_all;
+use tokio::time::MissedTickBehavior;
+use tokio_postgres::{Client, NoTls};
+
+use crate::{
+    database::{
+        postgres::{
+            connect::Tls,
+            messages::{MessageForwarder, StatementRoute},
+        },
+        types::ExecSender,
+        ConnectionDropNotifier, PING_INTERVAL, PING_TIMEOUT,
+    },
+    Error,
+};
+
+/// Clients per connection, unless set otherwise: the session client, and one for metadata queries
+pub const DEFAULT_POOL_SIZE: usize = 2;
+pub const MIN_POOL_SIZE: usize = 2;
+pub const MAX_POOL_SIZE: usize = 4;
+
+#[derive(Debug, Clone)]
+struct PooledClient {
+    client: Arc<Client>,
+    /// Of the server process serving the client, which queries are cancelled by
+    backend_pid: i32,
+    /// Set while the client is the session client of a pool from [`PostgresPool::reserve_session`]
+    reserved: Arc<AtomicBool>,
+    /// Where the notices the client gets go. None if they aren't forwarded.
+    messages: Option<MessageForwarder>,
+}
+
+impl PooledClient {
+    async fn new(client: Client, messages: Option<MessageForwarder>) -> Result<Self, Error> {
+        let backend_pid = client
+            .query_one("SELECT pg_backend_pid()", &[])
+            .await
+            .context("Failed to read the backend pid of a client")?
+            .get(0);
+
+        Ok(Self {
+            client: Arc::new(client),
+            backend_pid,
+            reserved: Arc::new(AtomicBool::new(false)),
+            messages,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PostgresPool {
+    session: PooledClient,
+    metadata: Vec<PooledClient>,
+    next: AtomicUsize,
+    /// Whether the session client is a metadata client reserved by this pool, given back once it's dropped
+    reserved_session: bool,
+    /// How the clients are encrypted, which cancel requests are sent with
+    tls: Tls,
+}
+
+impl PostgresPool {
+    /// Without metadata clients, metadata queries share the session client
+    pub async fn new(session: Client, metadata: Vec<Client>) -> Result<Self, Error> {
+        Self::from_clients(
+            (session, None),
+            metadata.into_iter().map(|client| (client, None)).collect(),
+        )
+        .await
+    }
+
+    async fn from_clients(
+        (session, messages): (Client, Option<MessageForwarder>),
+        metadata: Vec<(Client, Option<MessageForwarder>)>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            session: PooledClient::new(session, messages).await?,
+            metadata: try_join_all(
+                metadata
+                    .into_iter()
+                    .map(|(client, messages)| PooledClient::new(client, messages)),
+            )
+            .await?,
+            next: AtomicUsize::new(0),
+            reserved_session: false,
+            tls: Tls::Plain,
+        })
+    }
+
+    /// Sends cancel requests with `tls`, as the clients were connected. Unencrypted otherwise.
+    pub fn with_tls(mut self, tls: Tls) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Opens `size` clients with `connect`, all at once. Each comes with where its notices go, if they're forwarded.
+    pub async fn connect<F, Fut>(size: usize, connect: F) -> Result<Self, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<(Client, Option<MessageForwarder>), Error>>,
+    {
+        let size = size.clamp(MIN_POOL_SIZE, MAX_POOL_SIZE);
+        let mut clients = try_join_all((0..size).map(|_| connect())).await?;
+        let session = clients.remove(0);
+        Self::from_clients(session, clients).await
+    }
+
+    /// The client user queries run on
+    pub fn session(&self) -> &Arc<Client> {
+        &self.session.client
+    }
+
+    /// A client for metadata queries, which doesn't wait on user queries. Reserved clients are skipped, and the
+    /// session client is used if they're all reserved.
+    pub fn metadata(&self) -> &Arc<Client> {
+        let clients = &self.metadata;
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..clients.len())
+            .map(|offset| &clients[(start + offset) % clients.len()])
+            .find(|client| !client.reserved.load(Ordering::Relaxed))
+            .map_or(&self.session.client, |client| &client.client)
+    }
+
+    /// A pool whose session client is one of this pool's metadata clients, kept to itself until it's dropped,
+    /// e.g. for a transaction that mustn't see the statements run on this pool's session client. Its metadata
+    /// clients are this pool's. None if there's no metadata client left to reserve.
+    pub fn reserve_session(&self) -> Option<PostgresPool> {
+        let session = self.metadata.iter().find(|client| {
+            client
+                .reserved
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        })?;
+
+        Some(Self {
+            session: session.clone(),
+            metadata: self.metadata.clone(),
+            next: AtomicUsize::new(0),
+            reserved_session: true,
+            tls: self.tls.clone(),
+        })
+    }
+
+    /// Hands the notices of the session client to a statement, until the returned route is dropped
+    pub fn route_session_messages(&self, sender: &ExecSender) -> Option<StatementRoute> {
+        self.session
+            .messages
+            .as_ref()
+            .map(|messages| messages.route_to(sender.clone()))
+    }
+
+    /// The session client, then the metadata ones, e.g. to change a setting of them all
+    pub fn clients(&self) -> impl Iterator<Item = &Arc<Client>> {
+        std::iter::once(&self.session)
+            .chain(&self.metadata)
+            .map(|client| &client.client)
+    }
+
+    /// Backend pids of the session client, then of the metadata ones
+    pub fn backend_pids(&self) -> Vec<i32> {
+        std::iter::once(&self.session)
+            .chain(&self.metadata)
+            .map(|client| client.backend_pid)
+            .collect()
+    }
+
+    /// Cancels the user query running on the session client, if any. The cancel request goes over a connection of
+    /// its own, since the session client is busy with the query and every metadata client may be reserved.
+    pub async fn cancel_session_query(&self) -> Result<(), Error> {
+        let token = self.session.client.cancel_token();
+        match &self.tls {
+            Tls::Plain => token.cancel_query(NoTls).await,
+            Tls::Rustls(tls) => token.cancel_query(tls.clone()).await,
+        }
+        .context("Failed to cancel the running query")?;
+        Ok(())
+    }
+}
+
+impl Drop for PostgresPool {
+    fn drop(&mut self) {
+        if self.reserved_session {
+            self.session.reserved.store(false, Ordering::Release);
+        }
+    }
+}
+
+/// Pings the server with `SELECT 1` on a metadata client until the pool is dropped, telling `health_notifier` how
+/// long each ping took. A dropped connection is noticed as its connection future ends, so failures are only recorded.
+pub async fn monitor_pool(pool: Weak<PostgresPool>, health_notifier: ConnectionDropNotifier) {
+    let mut interval = tokio::time::interval(PING_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        let Some(pool) = pool.upgrade() else {
+            return;
+        };
+        let client = pool.metadata().clone();
+        // The pool isn't kept alive while waiting on the ping
+        drop(pool);
+
+        let started_at = Instant::now();
+        let ping = match tokio::time::timeout(PING_TIMEOUT, client.simple_query("SELECT 1")).await {
+            Ok(Ok(_)) => Ok(started_at.elapsed()),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err("The ping timed out".to_owned()),
+        };
+        health_notifier.record_ping(ping);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_metadata_queries_beside_user_queries() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let uri = db.connection_uri();
+        let pool = PostgresPool::connect(3, || async {
+            let (client, conn) = tokio_postgres::connect(&uri, tokio_postgres::NoTls)
+                .await
+                .map_err(anyhow::Error::from)?;
+            tokio::spawn(conn);
+            Ok((client, None))
+        })
+        .await
+        .unwrap();
+
+        let pids = pool.backend_pids();
+        assert_eq!(pids.len(), 3);
+        assert!(pids.iter().all(|pid| *pid > 0));
+
+        let session = pool.session().clone();
+        let slow = tokio::spawn(async move { session.batch_execute("SELECT pg_sleep(30)").await });
+
+        // Answered while the session client is busy
+        let one: i32 = timeout(
+            Duration::from_secs(5),
+            pool.metadata().query_one("SELECT 1", &[]),
+        )
+        .await
+        .unwrap()
+        .unwrap()
+        .get(0);
+        assert_eq!(one, 1);
+
+        // The sleep may not have started yet, so cancel until it's over
+        let slow = async {
+            loop {
+                pool.cancel_session_query().await.unwrap();
+                if slow.is_finished() {
+                    break slow.await.unwrap();
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+        let err = timeout(Duration::from_secs(10), slow)
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.code(),
+            Some(&tokio_postgres::error::SqlState::QUERY_CANCELED)
+        );
+
+        // Reserved clients are left out of the metadata rotation until they're given back
+        let reserved = pool.reserve_session().unwrap();
+        let other = pool.reserve_session().unwrap();
+        assert!(pool.reserve_session().is_none());
+        let reserved_pid = reserved.backend_pids()[0];
+        for _ in 0..3 {
+            assert_eq!(pid_of(pool.metadata()).await, pids[0]);
+        }
+
+        drop(other);
+        for _ in 0..3 {
+            let pid = pid_of(pool.metadata()).await;
+            assert!(pid != pids[0] && pid != reserved_pid);
+            assert_eq!(pid_of(reserved.metadata()).await, pid);
+        }
+        drop(reserved);
+        assert!(pool.reserve_session().is_some());
+    }
+
+    #[tokio::test]
+    async fn cancels_while_every_metadata_client_is_reserved() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let uri = db.connection_uri();
+        let pool = PostgresPool::connect(2, || async {
+            let (client, conn) = tokio_postgres::connect(&uri, tokio_postgres::NoTls)
+                .await
+                .map_err(anyhow::Error::from)?;
+            tokio::spawn(conn);
+            Ok((client, None))
+        })
+        .await
+        .unwrap();
+        let _reserved = pool.reserve_session().unwrap();
+
+        let session = pool.session().clone();
+        let slow = tokio::spawn(async move { session.batch_execute("SELECT pg_sleep(30)").await });
+        let slow = async {
+            loop {
+                pool.cancel_session_query().await.unwrap();
+                if slow.is_finished() {
+                    break slow.await.unwrap();
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+        let err = timeout(Duration::from_secs(10), slow)
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.code(),
+            Some(&tokio_postgres::error::SqlState::QUERY_CANCELED)
+        );
+    }
+
+    async fn pid_of(client: &Client) -> i32 {
+        client
+            .query_one("SELECT pg_backend_pid()", &[])
+            .await
+            .unwrap()
+            .get(0)
+    }
+}
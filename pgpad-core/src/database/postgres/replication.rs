@@ -0,0 +1,239 @@
+use serde::Serialize;
+use tokio_postgres::{Client, Row};
+
+/// Slots holding back more WAL than this get flagged, since they're likely to fill up the server's disk
+const EXCESSIVE_RETAINED_WAL_BYTES: i64 = 1024 * 1024 * 1024;
+
+/// Replication state of a Postgres server.
+///
+/// Each section is None if it couldn't be loaded (missing permissions, or a server that predates the view),
+/// in which case the reason is in `warnings`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicationInfo {
+    pub slots: Option<Vec<ReplicationSlot>>,
+    pub publications: Option<Vec<Publication>>,
+    pub subscriptions: Option<Vec<Subscription>>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicationSlot {
+    pub name: String,
+    /// Output plugin of logical slots, None for physical ones
+    pub plugin: Option<String>,
+    pub slot_type: String,
+    pub active: bool,
+    pub restart_lsn: Option<String>,
+    pub confirmed_flush_lsn: Option<String>,
+    /// WAL between `pg_current_wal_lsn()` and the slot's `restart_lsn`.
+    /// None on standbys, or if the slot never reserved WAL.
+    pub retained_wal_bytes: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Publication {
+    pub name: String,
+    pub all_tables: bool,
+    /// Tables as `schema.table`
+    pub tables: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Subscription {
+    pub name: String,
+    pub enabled: bool,
+    pub publications: Vec<String>,
+    pub received_lsn: Option<String>,
+    pub latest_end_lsn: Option<String>,
+    pub last_msg_receipt_time: Option<String>,
+}
+
+pub async fn get_replication_info(client: &Client) -> ReplicationInfo {
+    let mut warnings = Vec::new();
+
+    let slots = query_section(client, SLOTS_QUERY, "replication slots", &mut warnings)
+        .await
+        .map(|rows| rows.iter().map(slot_from_row).collect::<Vec<_>>());
+
+    for slot in slots.iter().flatten() {
+        if let Some(bytes) = slot
+            .retained_wal_bytes
+            .filter(|&bytes| bytes > EXCESSIVE_RETAINED_WAL_BYTES)
+        {
+            warnings.push(format!(
+                "Slot {} is retaining {:.1} GiB of WAL{}",
+                slot.name,
+                bytes as f64 / EXCESSIVE_RETAINED_WAL_BYTES as f64,
+                if slot.active { "" } else { " and is inactive" }
+            ));
+        }
+    }
+
+    let publications = query_section(client, PUBLICATIONS_QUERY, "publications", &mut warnings)
+        .await
+        .map(|rows| {
+            rows.iter()
+                .map(|row| Publication {
+                    name: row.get(0),
+                    all_tables: row.get(1),
+                    tables: row.get(2),
+                })
+                .collect()
+        });
+
+    let subscriptions = query_section(client, SUBSCRIPTIONS_QUERY, "subscriptions", &mut warnings)
+        .await
+        .map(|rows| {
+            rows.iter()
+                .map(|row| Subscription {
+                    name: row.get(0),
+                    enabled: row.get(1),
+                    publications: row.get(2),
+                    received_lsn: row.get(3),
+                    latest_end_lsn: row.get(4),
+                    last_msg_receipt_time: row.get(5),
+                })
+                .collect()
+        });
+
+    ReplicationInfo {
+        slots,
+        publications,
+        subscriptions,
+        warnings,
+    }
+}
+
+const SLOTS_QUERY: &str = r#"
+    SELECT
+        slot_name::text,
+        plugin::text,
+        slot_type,
+        active,
+        restart_lsn::text,
+        confirmed_flush_lsn::text,
+        CASE
+            WHEN pg_is_in_recovery() THEN NULL
+            ELSE pg_wal_lsn_diff(pg_current_wal_lsn(), restart_lsn)::bigint
+        END
+    FROM pg_replication_slots
+    ORDER BY slot_name
+"#;
+
+const PUBLICATIONS_QUERY: &str = r#"
+    SELECT
+        p.pubname::text,
+        p.puballtables,
+        COALESCE(
+            array_agg(format('%s.%s', pt.schemaname, pt.tablename) ORDER BY pt.schemaname, pt.tablename)
+                FILTER (WHERE pt.tablename IS NOT NULL),
+            '{}'
+        )
+    FROM pg_publication p
+    LEFT JOIN pg_publication_tables pt ON pt.pubname = p.pubname
+    GROUP BY p.pubname, p.puballtables
+    ORDER BY p.pubname
+"#;
+
+// `relid IS NULL` keeps the apply worker, and leaves out table synchronization workers
+const SUBSCRIPTIONS_QUERY: &str = r#"
+    SELECT
+        s.subname::text,
+        s.subenabled,
+        s.subpublications::text[],
+        st.received_lsn::text,
+        st.latest_end_lsn::text,
+        st.last_msg_receipt_time::text
+    FROM pg_subscription s
+    LEFT JOIN pg_stat_subscription st ON st.subid = s.oid AND st.relid IS NULL
+    WHERE s.subdbid = (SELECT oid FROM pg_database WHERE datname = current_database())
+    ORDER BY s.subname
+"#;
+
+fn slot_from_row(row: &Row) -> ReplicationSlot {
+    ReplicationSlot {
+        name: row.get(0),
+        plugin: row.get(1),
+        slot_type: row.get(2),
+        active: row.get(3),
+        restart_lsn: row.get(4),
+        confirmed_flush_lsn: row.get(5),
+        retained_wal_bytes: row.get(6),
+    }
+}
+
+/// Runs one of the queries of a report, turning failures into a warning instead of failing the whole request
+pub(crate) async fn query_section(
+    client: &Client,
+    query: &str,
+    section: &str,
+    warnings: &mut Vec<String>,
+) -> Option<Vec<Row>> {
+    match client.query(query, &[]).await {
+        Ok(rows) => Some(rows),
+        Err(err) => {
+            let reason = err
+                .as_db_error()
+                .map(|db_error| db_error.message().to_owned())
+                .unwrap_or_else(|| err.to_string());
+            log::warn!("Failed to load {section}: {reason}");
+            warnings.push(format!("Could not load {section}: {reason}"));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+    use pgtemp::PgTempDB;
+
+    use super::get_replication_info;
+
+    #[tokio::test]
+    async fn lists_slots_and_publications() -> anyhow::Result<()> {
+        let db = PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .context("Failed to connect to temporary postgres")?;
+
+        tokio::task::spawn(async move {
+            if let Err(e) = conn.await {
+                eprintln!("Connection error: {}", e);
+            }
+        });
+
+        client
+            .batch_execute(
+                "
+                CREATE TABLE orders (id int PRIMARY KEY);
+                CREATE PUBLICATION orders_pub FOR TABLE orders;
+                SELECT pg_create_physical_replication_slot('standby_slot', true);
+                ",
+            )
+            .await
+            .context("Failed to set up replication objects")?;
+
+        let info = get_replication_info(&client).await;
+        assert!(info.warnings.is_empty(), "{:?}", info.warnings);
+
+        let slots = info.slots.unwrap();
+        assert_eq!(slots.len(), 1);
+        assert_eq!(slots[0].name, "standby_slot");
+        assert_eq!(slots[0].slot_type, "physical");
+        assert_eq!(slots[0].plugin, None);
+        assert!(!slots[0].active);
+        assert!(slots[0].restart_lsn.is_some());
+        assert!(slots[0].retained_wal_bytes.is_some_and(|bytes| bytes >= 0));
+
+        let publications = info.publications.unwrap();
+        assert_eq!(publications.len(), 1);
+        assert_eq!(publications[0].name, "orders_pub");
+        assert!(!publications[0].all_tables);
+        assert_eq!(publications[0].tables, ["public.orders"]);
+
+        assert!(info.subscriptions.unwrap().is_empty());
+
+        Ok(())
+    }
+}
@@ -16,15 +16,79 @@ mod record;
 use null::NullChecker;
 use record::PgRecord;
 
-use crate::database::postgres::row_writer::{
-    bytes::PgBytes, interval::PgInterval, numeric::PostgresNumeric,
+use crate::{
+    database::postgres::row_writer::{
+        bytes::PgBytes, interval::PgInterval, numeric::PostgresNumeric,
+    },
+    utils,
 };
 
+/// How a column's values are written, resolved once per statement from the column's type rather than for every value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnKind {
+    Bool,
+    Int2,
+    Int4,
+    Int8,
+    Float4,
+    Float8,
+    Numeric,
+    Json,
+    Uuid,
+    TextArray,
+    Int4Array,
+    Int8Array,
+    Date,
+    Time,
+    Timestamp,
+    Timestamptz,
+    Interval,
+    Inet,
+    Record,
+    Text,
+    /// Written as text if it's valid UTF-8, as hex otherwise
+    Other,
+}
+
+impl ColumnKind {
+    fn of(pg_type: &Type) -> Self {
+        match *pg_type {
+            Type::BOOL => Self::Bool,
+            Type::INT2 => Self::Int2,
+            Type::INT4 => Self::Int4,
+            Type::INT8 => Self::Int8,
+            Type::FLOAT4 => Self::Float4,
+            Type::FLOAT8 => Self::Float8,
+            Type::NUMERIC => Self::Numeric,
+            Type::JSON | Type::JSONB => Self::Json,
+            Type::UUID => Self::Uuid,
+            Type::TEXT_ARRAY => Self::TextArray,
+            Type::INT4_ARRAY => Self::Int4Array,
+            Type::INT8_ARRAY => Self::Int8Array,
+            Type::DATE => Self::Date,
+            Type::TIME => Self::Time,
+            Type::TIMESTAMP => Self::Timestamp,
+            Type::TIMESTAMPTZ => Self::Timestamptz,
+            Type::INTERVAL => Self::Interval,
+            Type::INET => Self::Inet,
+            Type::RECORD => Self::Record,
+            // TODO(vini): BPCHAR and NAME are correct here?
+            Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => Self::Text,
+            _ => Self::Other,
+        }
+    }
+}
+
 /// A somewhat efficient way of converting the raw Postgres query results into a JSON string.
 /// Think of this as a writer of Vec<Vec<Json>>.
+///
+/// Values are written straight into a buffer that's kept between pages, so that a page of wide rows doesn't have
+/// to grow it from scratch.
 pub struct RowWriter {
     buf: String,
     row_count: usize,
+    /// The kind of each column, taken from the first row written
+    kinds: Vec<ColumnKind>,
 }
 
 impl Default for RowWriter {
@@ -38,10 +102,19 @@ impl RowWriter {
         Self {
             buf: String::new(),
             row_count: 0,
+            kinds: Vec::new(),
         }
     }
 
     pub fn add_row(&mut self, row: &Row) -> Result<(), anyhow::Error> {
+        if self.kinds.len() != row.len() {
+            self.kinds = row
+                .columns()
+                .iter()
+                .map(|column| ColumnKind::of(column.type_()))
+                .collect();
+        }
+
         if self.row_count == 0 {
             self.buf.reserve(2);
             self.buf.push('[');
@@ -73,15 +146,9 @@ impl RowWriter {
     }
 
     pub fn finish(&mut self) -> Box<RawValue> {
-        if self.row_count == 0 {
-            self.buf.push('[');
-        }
-        self.buf.push(']');
-
-        let json = std::mem::take(&mut self.buf);
+        let page = utils::finish_page(&mut self.buf, self.row_count);
         self.row_count = 0;
-
-        RawValue::from_string(json).unwrap()
+        page
     }
 
     fn write_pg_value_as_json(
@@ -89,17 +156,14 @@ impl RowWriter {
         row: &Row,
         column_index: usize,
     ) -> Result<(), anyhow::Error> {
-        let column = &row.columns()[column_index];
-        let pg_type = column.type_();
-
         // Is this row null?
         if row.try_get::<_, NullChecker>(column_index)?.0 {
             self.buf.push_str("null");
             return Ok(());
         }
 
-        match *pg_type {
-            Type::BOOL => {
+        match self.kinds[column_index] {
+            ColumnKind::Bool => {
                 let value: bool = row.try_get(column_index)?;
                 if value {
                     self.buf.push_str("true");
@@ -108,20 +172,20 @@ impl RowWriter {
                 }
             }
 
-            Type::INT2 => {
+            ColumnKind::Int2 => {
                 let value: i16 = row.try_get(column_index)?;
-                write!(&mut self.buf, "{}", value)?;
+                self.write_integer(value);
             }
-            Type::INT4 => {
+            ColumnKind::Int4 => {
                 let value: i32 = row.try_get(column_index)?;
-                write!(&mut self.buf, "{}", value)?;
+                self.write_integer(value);
             }
-            Type::INT8 => {
+            ColumnKind::Int8 => {
                 let value: i64 = row.try_get(column_index)?;
-                write!(&mut self.buf, "{}", value)?;
+                self.write_integer(value);
             }
 
-            Type::FLOAT4 => {
+            ColumnKind::Float4 => {
                 let value: f32 = row.try_get(column_index)?;
                 if value.is_finite() {
                     write!(&mut self.buf, "{}", value)?;
@@ -130,7 +194,7 @@ impl RowWriter {
                     write!(&mut self.buf, "\"{}\"", value)?;
                 }
             }
-            Type::FLOAT8 => {
+            ColumnKind::Float8 => {
                 let value: f64 = row.try_get(column_index)?;
                 if value.is_finite() {
                     write!(&mut self.buf, "{}", value)?;
@@ -139,100 +203,99 @@ impl RowWriter {
                 }
             }
 
-            Type::NUMERIC => {
+            ColumnKind::Numeric => {
                 // Decode NUMERIC into Decimal (full precision)
                 let value: PostgresNumeric = row.try_get(column_index)?;
                 // Send as string to the frontend to avoid precision loss
-                self.write_json_string(&value.to_string());
+                self.write_plain_string(&value)?;
             }
 
-            Type::JSON | Type::JSONB => {
+            ColumnKind::Json => {
                 let value: serde_json::Value = row.try_get(column_index)?;
-                self.buf.push_str(&value.to_string());
+                write!(&mut self.buf, "{value}")?;
             }
 
-            Type::UUID => {
+            ColumnKind::Uuid => {
                 let value: uuid::Uuid = row.try_get(column_index)?;
-                self.write_json_string(&value.to_string());
+                self.write_plain_string(&value)?;
             }
 
-            Type::TEXT_ARRAY => {
-                let value: Vec<String> = row.try_get(column_index)?;
+            ColumnKind::TextArray => {
+                let value: Vec<Option<&str>> = row.try_get(column_index)?;
                 self.buf.push('[');
                 for (i, item) in value.iter().enumerate() {
                     if i > 0 {
                         self.buf.push(',');
                     }
-                    self.write_json_string(item);
-                }
-                self.buf.push(']');
-            }
-            Type::INT4_ARRAY => {
-                let value: Vec<i32> = row.try_get(column_index)?;
-                self.buf.push('[');
-                for (i, item) in value.iter().enumerate() {
-                    if i > 0 {
-                        self.buf.push(',');
+                    match item {
+                        Some(item) => self.write_json_string(item),
+                        None => self.buf.push_str("null"),
                     }
-                    write!(&mut self.buf, "{}", item)?;
                 }
                 self.buf.push(']');
             }
-            Type::INT8_ARRAY => {
-                let value: Vec<i64> = row.try_get(column_index)?;
-                self.buf.push('[');
-                for (i, item) in value.iter().enumerate() {
-                    if i > 0 {
-                        self.buf.push(',');
-                    }
-                    write!(&mut self.buf, "{}", item)?;
-                }
-                self.buf.push(']');
+            ColumnKind::Int4Array => {
+                let value: Vec<Option<i32>> = row.try_get(column_index)?;
+                self.write_integer_array(&value);
+            }
+            ColumnKind::Int8Array => {
+                let value: Vec<Option<i64>> = row.try_get(column_index)?;
+                self.write_integer_array(&value);
             }
 
-            Type::TIMESTAMP => {
+            ColumnKind::Date => {
+                let value: chrono::NaiveDate = row.try_get(column_index)?;
+                self.write_plain_string(&value)?;
+            }
+
+            ColumnKind::Time => {
+                let value: chrono::NaiveTime = row.try_get(column_index)?;
+                self.write_plain_string(&value)?;
+            }
+
+            ColumnKind::Timestamp => {
                 let value: chrono::NaiveDateTime = row.try_get(column_index)?;
-                self.write_json_string(&value.to_string());
+                self.write_plain_string(&value)?;
             }
 
-            Type::TIMESTAMPTZ => {
+            ColumnKind::Timestamptz => {
                 let value: chrono::DateTime<chrono::Utc> = row.try_get(column_index)?;
-                self.write_json_string(&value.to_string());
+                self.write_plain_string(&value)?;
             }
 
-            Type::INTERVAL => {
+            ColumnKind::Interval => {
                 let value: PgInterval = row.try_get(column_index)?;
-                self.write_json_string(&value.to_string());
+                self.write_plain_string(&value)?;
             }
 
-            Type::INET => {
+            ColumnKind::Inet => {
                 let value: std::net::IpAddr = row.try_get(column_index)?;
-                self.write_json_string(&value.to_string());
+                self.write_plain_string(&value)?;
             }
 
-            Type::RECORD => {
+            ColumnKind::Record => {
                 let value: PgRecord = row.try_get(column_index)?;
                 self.buf.push('[');
                 for (i, field) in value.fields.iter().enumerate() {
                     if i > 0 {
                         self.buf.push(',');
                     }
-                    self.buf.push_str(&field.to_string());
+                    write!(&mut self.buf, "{field}")?;
                 }
                 self.buf.push(']');
             }
 
-            // TODO(vini): BPCHAR and NAME are correct here?
-            Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => {
+            ColumnKind::Text => {
                 let value: &str = row.try_get(column_index)?;
                 self.write_json_string(value);
             }
 
-            _ => {
+            ColumnKind::Other => {
                 let bytes = row.try_get::<_, PgBytes>(column_index)?;
                 if let Ok(value) = std::str::from_utf8(bytes.bytes) {
                     self.write_json_string(value);
                 } else {
+                    let pg_type = row.columns()[column_index].type_();
                     log::error!("Unknown type `{:?}`, kind: {:?}", pg_type, pg_type.kind());
                     self.write_json_string(&format!("\\x{}", hex::encode(bytes.bytes)));
                 }
@@ -243,21 +306,34 @@ impl RowWriter {
     }
 
     fn write_json_string(&mut self, s: &str) {
-        self.buf.push('"');
-        for ch in s.chars() {
-            match ch {
-                '"' => self.buf.push_str("\\\""),
-                '\\' => self.buf.push_str("\\\\"),
-                '\n' => self.buf.push_str("\\n"),
-                '\r' => self.buf.push_str("\\r"),
-                '\t' => self.buf.push_str("\\t"),
-                c if c.is_control() => {
-                    write!(&mut self.buf, "\\u{:04x}", c as u32).unwrap();
-                }
-                c => self.buf.push(c),
+        utils::write_json_string(&mut self.buf, s);
+    }
+
+    fn write_integer(&mut self, value: impl itoa::Integer) {
+        self.buf.push_str(itoa::Buffer::new().format(value));
+    }
+
+    fn write_integer_array<I: itoa::Integer + Copy>(&mut self, values: &[Option<I>]) {
+        self.buf.push('[');
+        for (i, item) in values.iter().enumerate() {
+            if i > 0 {
+                self.buf.push(',');
+            }
+            match item {
+                Some(item) => self.write_integer(*item),
+                None => self.buf.push_str("null"),
             }
         }
+        self.buf.push(']');
+    }
+
+    /// Writes a value as a JSON string without escaping it, for values whose text never needs it (e.g. dates),
+    /// saving the allocation of their text
+    fn write_plain_string(&mut self, value: &impl std::fmt::Display) -> std::fmt::Result {
+        self.buf.push('"');
+        write!(&mut self.buf, "{value}")?;
         self.buf.push('"');
+        Ok(())
     }
 }
 
@@ -298,6 +374,8 @@ mod tests {
                 3.14::float AS float_col,
                 'Hello'::text AS text_col,
                 true AS bool_col,
+                '2025-08-07'::date AS date_col,
+                '12:30:05'::time AS time_col,
                 '2025-08-07 12:00:00'::timestamp AS ts_col,
                 '2025-08-07 12:00:00+00'::timestamptz AS ts_tz_col,
                 '1 day'::interval AS interval_col,
@@ -326,6 +404,8 @@ mod tests {
                     3.14,
                     "Hello",
                     true,
+                    "2025-08-07",
+                    "12:30:05",
                     "2025-08-07 12:00:00",
                     "2025-08-07 12:00:00 UTC",
                     "1 day",
@@ -356,7 +436,8 @@ mod tests {
                 NULL::text AS null_text,
                 NULL::boolean AS null_bool,
                 NULL::timestamp AS null_timestamp,
-                NULL::json AS null_json
+                NULL::json AS null_json,
+                ARRAY['a', NULL] AS null_element_array
         "#;
 
         let null_rows = client.query(null_sql, &[]).await.unwrap();
@@ -368,7 +449,7 @@ mod tests {
         let null_result: Value = serde_json::from_str(null_result.get()).unwrap();
         assert_eq!(
             null_result,
-            serde_json::json!([[null, null, null, null, null]])
+            serde_json::json!([[null, null, null, null, null, ["a", null]]])
         );
 
         let multi_sql = r#"
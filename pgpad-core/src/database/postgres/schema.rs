@@ -1,21 +1,54 @@
 use std::collections::{HashMap, HashSet};
 
 use anyhow::Context;
-use tokio_postgres::Client;
+use tokio_postgres::{error::SqlState, Client, Row};
 
 use crate::{
-    database::types::{ColumnInfo, DatabaseSchema, TableInfo},
+    database::{
+        postgres::flavor::ServerFlavor,
+        types::{ColumnInfo, DatabaseSchema, ForeignKey, ReferentialAction, TableInfo},
+    },
     Error,
 };
 
-pub async fn get_database_schema(client: &Client) -> Result<DatabaseSchema, Error> {
-    let schema_query = r#"
+/// Databases with more tables than this only have their table names loaded at first, and the columns of each
+/// table once asked for, since loading every column can take long enough to hold everything else up
+pub const LAZY_COLUMNS_THRESHOLD: usize = 2_000;
+
+/// Schemas of the server itself rather than of the database
+fn system_schemas(flavor: ServerFlavor) -> &'static str {
+    match flavor {
+        ServerFlavor::CockroachDb => {
+            "'information_schema', 'pg_catalog', 'pg_toast', 'crdb_internal', 'pg_extension'"
+        }
+        _ => "'information_schema', 'pg_catalog', 'pg_toast'",
+    }
+}
+
+fn schema_query(flavor: ServerFlavor) -> String {
+    // Exclude child partitions: keep only regular tables and partition roots
+    let partition_filter = match flavor {
+        ServerFlavor::CockroachDb => "",
+        _ => {
+            "AND NOT EXISTS (
+                SELECT 1
+                FROM pg_inherits i
+                JOIN pg_class child ON child.oid = i.inhrelid
+                JOIN pg_namespace child_ns ON child_ns.oid = child.relnamespace
+                WHERE child.relname = t.table_name
+                AND child_ns.nspname = t.table_schema
+            )"
+        }
+    };
+
+    format!(
+        r#"
         SELECT 
             t.table_schema,
             t.table_name,
             c.column_name,
             c.data_type,
-            c.is_nullable::boolean,
+            c.is_nullable = 'YES',
             c.column_default
         FROM 
             information_schema.tables t
@@ -25,24 +58,346 @@ pub async fn get_database_schema(client: &Client) -> Result<DatabaseSchema, Erro
             AND t.table_schema = c.table_schema
         WHERE 
             t.table_type = 'BASE TABLE'
-            AND t.table_schema NOT IN ('information_schema', 'pg_catalog', 'pg_toast')
-            -- Exclude child partitions: keep only regular tables and partition roots
-            AND NOT EXISTS (
-                SELECT 1
-                FROM pg_inherits i
-                JOIN pg_class child ON child.oid = i.inhrelid
-                JOIN pg_namespace child_ns ON child_ns.oid = child.relnamespace
-                WHERE child.relname = t.table_name
-                AND child_ns.nspname = t.table_schema
-            )
+            AND t.table_schema NOT IN ({})
+            {partition_filter}
         ORDER BY 
             t.table_schema, t.table_name, c.ordinal_position
-    "#;
+    "#,
+        system_schemas(flavor)
+    )
+}
+
+/// Only relies on the columns of `information_schema` every flavor has, for servers that lack something
+/// [`schema_query`] needs. Partitions show up as tables of their own, and defaults are left out.
+fn fallback_schema_query(flavor: ServerFlavor) -> String {
+    format!(
+        r#"
+        SELECT
+            t.table_schema,
+            t.table_name,
+            c.column_name,
+            c.data_type,
+            c.is_nullable = 'YES',
+            NULL::text
+        FROM
+            information_schema.tables t
+        JOIN
+            information_schema.columns c
+            ON t.table_name = c.table_name
+            AND t.table_schema = c.table_schema
+        WHERE
+            t.table_type = 'BASE TABLE'
+            AND t.table_schema NOT IN ({})
+        ORDER BY
+            t.table_schema, t.table_name, c.ordinal_position
+    "#,
+        system_schemas(flavor)
+    )
+}
+
+/// Tables by schema and name, straight from the catalog rather than `information_schema`, whose views get slow
+/// with many tables
+fn table_names_query(flavor: ServerFlavor) -> String {
+    let partition_filter = match flavor {
+        ServerFlavor::CockroachDb => "",
+        _ => "AND NOT c.relispartition",
+    };
+
+    format!(
+        r#"
+        SELECT
+            n.nspname::text,
+            c.relname::text
+        FROM
+            pg_class c
+        JOIN
+            pg_namespace n ON n.oid = c.relnamespace
+        WHERE
+            c.relkind IN ('r', 'p')
+            AND n.nspname NOT IN ({})
+            AND n.nspname NOT LIKE 'pg\_temp\_%'
+            {partition_filter}
+        ORDER BY
+            1, 2
+    "#,
+        system_schemas(flavor)
+    )
+}
+
+/// The foreign keys of every table, with their columns in the order of the key
+fn foreign_keys_query(flavor: ServerFlavor) -> String {
+    format!(
+        r#"
+        SELECT
+            n.nspname::text,
+            c.relname::text,
+            ARRAY(
+                SELECT a.attname::text
+                FROM unnest(con.conkey) WITH ORDINALITY AS k(attnum, position)
+                JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+                ORDER BY k.position
+            ),
+            fn.nspname::text,
+            f.relname::text,
+            ARRAY(
+                SELECT a.attname::text
+                FROM unnest(con.confkey) WITH ORDINALITY AS k(attnum, position)
+                JOIN pg_attribute a ON a.attrelid = con.confrelid AND a.attnum = k.attnum
+                ORDER BY k.position
+            ),
+            con.conname::text,
+            con.confdeltype::text,
+            con.confupdtype::text
+        FROM
+            pg_constraint con
+        JOIN
+            pg_class c ON c.oid = con.conrelid
+        JOIN
+            pg_namespace n ON n.oid = c.relnamespace
+        JOIN
+            pg_class f ON f.oid = con.confrelid
+        JOIN
+            pg_namespace fn ON fn.oid = f.relnamespace
+        WHERE
+            con.contype = 'f'
+            AND n.nspname NOT IN ({})
+    "#,
+        system_schemas(flavor)
+    )
+}
+
+/// The primary key of every table, with its columns in the order of the key
+fn primary_keys_query(flavor: ServerFlavor) -> String {
+    format!(
+        r#"
+        SELECT
+            n.nspname::text,
+            c.relname::text,
+            ARRAY(
+                SELECT a.attname::text
+                FROM unnest(con.conkey) WITH ORDINALITY AS k(attnum, position)
+                JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+                ORDER BY k.position
+            )
+        FROM
+            pg_constraint con
+        JOIN
+            pg_class c ON c.oid = con.conrelid
+        JOIN
+            pg_namespace n ON n.oid = c.relnamespace
+        WHERE
+            con.contype = 'p'
+            AND n.nspname NOT IN ({})
+    "#,
+        system_schemas(flavor)
+    )
+}
+
+/// The planner's estimate of the rows of every table, from the last time it was vacuumed or analyzed. Tables never
+/// analyzed have -1, and partitioned tables are estimated from their partitions.
+const ROW_ESTIMATES_QUERY: &str = r#"
+    SELECT
+        n.nspname::text,
+        c.relname::text,
+        CASE c.relkind
+            WHEN 'p' THEN (
+                SELECT sum(p.reltuples)::int8
+                FROM pg_inherits i
+                JOIN pg_class p ON p.oid = i.inhrelid
+                WHERE i.inhparent = c.oid AND p.reltuples >= 0
+            )
+            ELSE NULLIF(c.reltuples, -1)::int8
+        END
+    FROM
+        pg_class c
+    JOIN
+        pg_namespace n ON n.oid = c.relnamespace
+    WHERE
+        c.relkind IN ('r', 'p')
+"#;
+
+/// Adds their row estimates to `tables`, if the server can tell
+async fn add_row_estimates(client: &Client, tables: &mut [TableInfo]) {
+    let rows = match client.query(ROW_ESTIMATES_QUERY, &[]).await {
+        Ok(rows) => rows,
+        Err(err) => {
+            log::warn!("Failed to read row estimates: {err}");
+            return;
+        }
+    };
+
+    let mut estimates: HashMap<(String, String), Option<i64>> = rows
+        .iter()
+        .map(|row| ((row.get(0), row.get(1)), row.get(2)))
+        .collect();
+    for table in tables {
+        table.row_estimate = estimates
+            .remove(&(table.schema.clone(), table.name.clone()))
+            .flatten();
+    }
+}
+
+/// Adds their foreign keys to `tables`. Keys to tables that aren't listed, like the partitions of a partitioned
+/// table, are left out. So are all of them if the server can't tell, since tables are still of use without.
+async fn add_foreign_keys(client: &Client, flavor: ServerFlavor, tables: &mut [TableInfo]) {
+    let rows = match client.query(&foreign_keys_query(flavor), &[]).await {
+        Ok(rows) => rows,
+        Err(err) => {
+            log::warn!("Failed to read foreign keys: {err}");
+            return;
+        }
+    };
+
+    let listed: HashSet<(String, String)> = tables
+        .iter()
+        .map(|table| (table.schema.clone(), table.name.clone()))
+        .collect();
+    let mut keys: HashMap<(String, String), Vec<ForeignKey>> = HashMap::new();
+    for row in &rows {
+        let key = ForeignKey {
+            columns: row.get(2),
+            referenced_schema: row.get(3),
+            referenced_table: row.get(4),
+            referenced_columns: row.get(5),
+            name: row.get(6),
+            on_delete: ReferentialAction::from_pg_code(row.get(7)),
+            on_update: ReferentialAction::from_pg_code(row.get(8)),
+        };
+        if listed.contains(&(key.referenced_schema.clone(), key.referenced_table.clone())) {
+            keys.entry((row.get(0), row.get(1))).or_default().push(key);
+        }
+    }
+
+    for table in tables {
+        if let Some(keys) = keys.remove(&(table.schema.clone(), table.name.clone())) {
+            table.foreign_keys = keys;
+        }
+    }
+}
+
+/// Adds their primary keys to `tables`, if the server can tell
+async fn add_primary_keys(client: &Client, flavor: ServerFlavor, tables: &mut [TableInfo]) {
+    let rows = match client.query(&primary_keys_query(flavor), &[]).await {
+        Ok(rows) => rows,
+        Err(err) => {
+            log::warn!("Failed to read primary keys: {err}");
+            return;
+        }
+    };
 
+    let mut keys: HashMap<(String, String), Vec<String>> = rows
+        .iter()
+        .map(|row| ((row.get(0), row.get(1)), row.get(2)))
+        .collect();
+    for table in tables {
+        if let Some(key) = keys.remove(&(table.schema.clone(), table.name.clone())) {
+            table.primary_key = key;
+        }
+    }
+}
+
+async fn query_schema(client: &Client, flavor: ServerFlavor) -> Result<Vec<Row>, Error> {
+    let err = match client.query(&schema_query(flavor), &[]).await {
+        Ok(rows) => return Ok(rows),
+        Err(err) => err,
+    };
+
+    let missing_catalog = [
+        SqlState::UNDEFINED_TABLE,
+        SqlState::UNDEFINED_COLUMN,
+        SqlState::UNDEFINED_FUNCTION,
+        SqlState::FEATURE_NOT_SUPPORTED,
+    ]
+    .iter()
+    .any(|state| err.code() == Some(state));
+    if !missing_catalog {
+        return Err(anyhow::Error::new(err)
+            .context("Failed to query database schema")
+            .into());
+    }
+
+    log::warn!("Falling back to a simpler schema query: {err}");
+    Ok(client
+        .query(&fallback_schema_query(flavor), &[])
+        .await
+        .context("Failed to query database schema")?)
+}
+
+pub async fn get_database_schema(
+    client: &Client,
+    flavor: ServerFlavor,
+) -> Result<DatabaseSchema, Error> {
+    load_schema(client, flavor, LAZY_COLUMNS_THRESHOLD).await
+}
+
+/// Loads only the table names if there are more than `max_eager_tables` tables, with their columns left pending
+async fn load_schema(
+    client: &Client,
+    flavor: ServerFlavor,
+    max_eager_tables: usize,
+) -> Result<DatabaseSchema, Error> {
+    let names = match client.query(&table_names_query(flavor), &[]).await {
+        Ok(rows) => rows,
+        Err(err) => {
+            log::warn!("Failed to count tables, loading every column: {err}");
+            return load_full_schema(client, flavor).await;
+        }
+    };
+    if names.len() <= max_eager_tables {
+        return load_full_schema(client, flavor).await;
+    }
+
+    let mut schemas = Vec::new();
+    let mut tables: Vec<TableInfo> = names
+        .iter()
+        .map(|row| {
+            let schema: String = row.get(0);
+            schemas.push(schema.clone());
+            TableInfo {
+                name: row.get(1),
+                schema,
+                columns_pending: true,
+                ..Default::default()
+            }
+        })
+        .collect();
+    add_foreign_keys(client, flavor, &mut tables).await;
+    add_primary_keys(client, flavor, &mut tables).await;
+    add_row_estimates(client, &mut tables).await;
+
+    Ok(DatabaseSchema::new(tables, schemas, Vec::new()))
+}
+
+/// The columns of a single table, in the table's own order
+pub async fn get_table_columns(
+    client: &Client,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<ColumnInfo>, Error> {
     let rows = client
-        .query(schema_query, &[])
+        .query(
+            "SELECT column_name::text, data_type::text, is_nullable = 'YES', column_default::text
+             FROM information_schema.columns
+             WHERE table_schema = $1 AND table_name = $2
+             ORDER BY ordinal_position",
+            &[&schema, &table],
+        )
         .await
-        .context("Failed to query database schema")?;
+        .with_context(|| format!("Failed to read the columns of {schema}.{table}"))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| ColumnInfo {
+            name: row.get(0),
+            data_type: row.get(1),
+            is_nullable: row.get(2),
+            default_value: row.get(3),
+        })
+        .collect())
+}
+
+async fn load_full_schema(client: &Client, flavor: ServerFlavor) -> Result<DatabaseSchema, Error> {
+    let rows = query_schema(client, flavor).await?;
 
     // Key is (schema, table_name)
     let mut tables_map = HashMap::new();
@@ -66,6 +421,7 @@ pub async fn get_database_schema(client: &Client) -> Result<DatabaseSchema, Erro
             name: table_name.to_owned(),
             schema: schema.to_owned(),
             columns: Vec::new(),
+            ..Default::default()
         });
 
         table_info.columns.push(ColumnInfo {
@@ -76,18 +432,17 @@ pub async fn get_database_schema(client: &Client) -> Result<DatabaseSchema, Erro
         });
     }
 
-    let tables = tables_map.into_values().collect();
+    let mut tables: Vec<TableInfo> = tables_map.into_values().collect();
+    add_foreign_keys(client, flavor, &mut tables).await;
+    add_primary_keys(client, flavor, &mut tables).await;
+    add_row_estimates(client, &mut tables).await;
     let schemas = schemas_set.into_iter().map(ToOwned::to_owned).collect();
     let unique_columns = unique_columns_set
         .into_iter()
         .map(ToOwned::to_owned)
         .collect();
 
-    Ok(DatabaseSchema {
-        tables,
-        schemas,
-        unique_columns,
-    })
+    Ok(DatabaseSchema::new(tables, schemas, unique_columns))
 }
 
 #[cfg(test)]
@@ -97,7 +452,11 @@ mod tests {
     use anyhow::Context;
     use pgtemp::PgTempDB;
 
-    use super::get_database_schema;
+    use super::{
+        fallback_schema_query, get_database_schema, get_table_columns, load_schema, schema_query,
+        table_names_query, DatabaseSchema,
+    };
+    use crate::database::{postgres::flavor::ServerFlavor, types::ReferentialAction};
 
     #[tokio::test]
     async fn excludes_partition_children_from_schema_listing() -> anyhow::Result<()> {
@@ -132,7 +491,15 @@ mod tests {
             .await
             .context("Failed to create partitioned test tables")?;
 
-        let schema = get_database_schema(&client).await?;
+        let schema = get_database_schema(&client, ServerFlavor::Postgres).await?;
+
+        // Whatever order the catalog returns rows in, an unchanged schema serializes the same
+        let mut again = get_database_schema(&client, ServerFlavor::Postgres).await?;
+        again.generated_at = schema.generated_at;
+        assert_eq!(
+            serde_json::to_string(&again)?,
+            serde_json::to_string(&schema)?
+        );
 
         let measurement_tables: HashSet<String> = schema
             .tables
@@ -146,6 +513,234 @@ mod tests {
             HashSet::from([String::from("measurements")])
         );
 
+        // The queries of other flavors have to run on Postgres too, since they're still mostly Postgres
+        for flavor in [ServerFlavor::CockroachDb, ServerFlavor::Neon] {
+            for query in [schema_query(flavor), fallback_schema_query(flavor)] {
+                let rows = client.query(&query, &[]).await?;
+                assert!(rows
+                    .iter()
+                    .any(|row| row.get::<_, &str>(1) == "measurements"));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn defers_columns_of_large_databases() -> anyhow::Result<()> {
+        let db = PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .context("Failed to connect to temporary postgres")?;
+        tokio::spawn(conn);
+
+        client
+            .batch_execute(
+                r#"
+                CREATE SCHEMA "Sales";
+                CREATE TABLE "Sales".orders (id int PRIMARY KEY, note text DEFAULT 'none');
+                CREATE TABLE events (at timestamptz NOT NULL) PARTITION BY RANGE (at);
+                CREATE TABLE events_2025 PARTITION OF events
+                    FOR VALUES FROM ('2025-01-01') TO ('2026-01-01');
+                CREATE TEMPORARY TABLE scratch (x int);
+                "#,
+            )
+            .await?;
+
+        let full = get_database_schema(&client, ServerFlavor::Postgres).await?;
+        assert!(full.tables.iter().all(|table| !table.columns_pending));
+
+        let lazy = load_schema(&client, ServerFlavor::Postgres, 1).await?;
+        let names: Vec<_> = lazy
+            .tables
+            .iter()
+            .map(|table| (table.schema.as_str(), table.name.as_str()))
+            .collect();
+        assert_eq!(names, [("Sales", "orders"), ("public", "events")]);
+        assert_eq!(lazy.schemas, ["Sales", "public"]);
+        assert!(lazy
+            .tables
+            .iter()
+            .all(|table| table.columns_pending && table.columns.is_empty()));
+        assert!(lazy.unique_columns.is_empty());
+
+        // Once every table has its columns, it's the same as loading them all at once
+        let mut loaded = lazy.clone();
+        for table in &lazy.tables {
+            let columns = get_table_columns(&client, &table.schema, &table.name).await?;
+            loaded = loaded
+                .with_table_columns(&table.schema, &table.name, columns)
+                .unwrap();
+        }
+        assert_eq!(loaded.generated_at, lazy.generated_at);
+        loaded.generated_at = full.generated_at;
+        assert_eq!(
+            serde_json::to_string(&loaded)?,
+            serde_json::to_string(&full)?
+        );
+
+        let orders = loaded.table("Sales", "orders").unwrap();
+        assert_eq!(
+            orders.columns[1].default_value.as_deref(),
+            Some("'none'::text")
+        );
+        assert!(loaded
+            .with_table_columns("Sales", "missing", Vec::new())
+            .is_none());
+
+        // Cockroach has no partitions to leave out
+        assert!(!table_names_query(ServerFlavor::CockroachDb).contains("relispartition"));
+
         Ok(())
     }
+
+    #[tokio::test]
+    async fn estimates_rows_from_the_planner() -> anyhow::Result<()> {
+        let db = PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .context("Failed to connect to temporary postgres")?;
+        tokio::spawn(conn);
+
+        client
+            .batch_execute(
+                "
+                CREATE TABLE items (id int);
+                INSERT INTO items SELECT generate_series(1, 500);
+                CREATE TABLE events (at date NOT NULL) PARTITION BY RANGE (at);
+                CREATE TABLE events_2024 PARTITION OF events
+                    FOR VALUES FROM ('2024-01-01') TO ('2025-01-01');
+                CREATE TABLE events_2025 PARTITION OF events
+                    FOR VALUES FROM ('2025-01-01') TO ('2026-01-01');
+                INSERT INTO events SELECT date '2024-12-20' + i FROM generate_series(1, 30) i;
+                ANALYZE;
+                ",
+            )
+            .await?;
+
+        for schema in [
+            get_database_schema(&client, ServerFlavor::Postgres).await?,
+            load_schema(&client, ServerFlavor::Postgres, 1).await?,
+        ] {
+            let estimate = |table| schema.table("public", table).unwrap().row_estimate;
+            assert_eq!(estimate("items"), Some(500));
+            assert_eq!(estimate("events"), Some(30));
+
+            // Estimates don't count as a change of the table
+            let counted = schema.with_row_estimate("public", "items", 501).unwrap();
+            assert_eq!(
+                counted.table("public", "items").unwrap().content_hash,
+                schema.table("public", "items").unwrap().content_hash
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_foreign_keys() -> anyhow::Result<()> {
+        let db = PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .context("Failed to connect to temporary postgres")?;
+        tokio::spawn(conn);
+
+        client
+            .batch_execute(
+                r#"
+                CREATE SCHEMA "Sales";
+                CREATE TABLE customers (id int PRIMARY KEY, referrer_id int REFERENCES customers);
+                CREATE TABLE "Sales".orders (
+                    region text,
+                    number int,
+                    customer_id int REFERENCES customers (id),
+                    PRIMARY KEY (region, number)
+                );
+                CREATE TABLE lines (
+                    order_number int,
+                    order_region text,
+                    FOREIGN KEY (order_region, order_number) REFERENCES "Sales".orders (region, number)
+                );
+                CREATE TABLE events (at timestamptz PRIMARY KEY) PARTITION BY RANGE (at);
+                CREATE TABLE events_2025 PARTITION OF events
+                    FOR VALUES FROM ('2025-01-01') TO ('2026-01-01');
+                CREATE TABLE alerts (
+                    event_at timestamptz CONSTRAINT alerts_event REFERENCES events
+                        ON DELETE CASCADE ON UPDATE SET NULL
+                );
+                "#,
+            )
+            .await?;
+
+        let keys = |schema: &DatabaseSchema, table: &str| -> Vec<String> {
+            let table = schema.tables.iter().find(|t| t.name == table).unwrap();
+            table
+                .foreign_keys
+                .iter()
+                .map(|key| {
+                    format!(
+                        "({}) -> {}.{} ({})",
+                        key.columns.join(", "),
+                        key.referenced_schema,
+                        key.referenced_table,
+                        key.referenced_columns.join(", ")
+                    )
+                })
+                .collect()
+        };
+
+        for schema in [
+            get_database_schema(&client, ServerFlavor::Postgres).await?,
+            load_schema(&client, ServerFlavor::Postgres, 1).await?,
+        ] {
+            assert_eq!(
+                keys(&schema, "customers"),
+                ["(referrer_id) -> public.customers (id)"]
+            );
+            assert_eq!(
+                keys(&schema, "orders"),
+                ["(customer_id) -> public.customers (id)"]
+            );
+            assert_eq!(
+                keys(&schema, "lines"),
+                ["(order_region, order_number) -> Sales.orders (region, number)"]
+            );
+            // Not to each partition as well
+            assert_eq!(
+                keys(&schema, "alerts"),
+                ["(event_at) -> public.events (at)"]
+            );
+            assert!(keys(&schema, "events").is_empty());
+
+            let orders = schema.table("Sales", "orders").unwrap();
+            assert_eq!(orders.primary_key, ["region", "number"]);
+            let graph = schema.relationship_graph();
+            // Partitioned tables are a single node
+            assert!(!graph.nodes.iter().any(|node| node.table == "events_2025"));
+            let alerts = graph
+                .edges
+                .iter()
+                .find(|edge| edge.source.table == "alerts")
+                .unwrap();
+            assert_eq!(alerts.name.as_deref(), Some("alerts_event"));
+            assert_eq!(alerts.target.table, "events");
+            assert_eq!(alerts.on_delete, ReferentialAction::Cascade);
+            assert_eq!(alerts.on_update, ReferentialAction::SetNull);
+            assert_eq!(graph.edges.len(), 4);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn adapts_schema_queries_to_flavors() {
+        let cockroach = schema_query(ServerFlavor::CockroachDb);
+        assert!(cockroach.contains("'crdb_internal'") && !cockroach.contains("pg_inherits"));
+
+        let postgres = schema_query(ServerFlavor::Postgres);
+        assert!(postgres.contains("pg_inherits") && !postgres.contains("crdb_internal"));
+        assert_eq!(schema_query(ServerFlavor::AlloyDb), postgres);
+
+        assert!(!fallback_schema_query(ServerFlavor::Postgres).contains("pg_inherits"));
+    }
 }
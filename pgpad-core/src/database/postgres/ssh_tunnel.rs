@@ -0,0 +1,523 @@
+//! SSH tunnels to Postgres and MySQL servers that are only reachable through a bastion host.
+//!
+//! Tunnels are opened with the system's `ssh` client, which forwards a local port to the server, so that they
+//! honor the user's `~/.ssh/config`, known hosts and agent. Connections then go to the local port, while TLS
+//! certificates are still verified against the server's own name.
+//!
+//! A tunnel lasts until its [`SshTunnel`] is dropped, and reports itself through the
+//! [`ConnectionDropNotifier`] if `ssh` exits before that.
+
+use std::{
+    net::Ipv4Addr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context;
+use futures_util::future::{select, Either};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::TcpStream,
+    process::{Child, Command},
+    time::Instant,
+};
+use tokio_util::sync::{CancellationToken, DropGuard};
+use url::Url;
+
+use crate::{database::ConnectionDropNotifier, Error};
+
+/// How long `ssh` gets to authenticate and start forwarding
+const OPEN_TIMEOUT: Duration = Duration::from_secs(20);
+
+const DEFAULT_SSH_PORT: u16 = 22;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SshTunnelConfig {
+    /// The bastion host
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: String,
+    pub auth: SshAuth,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SshAuth {
+    /// Keys from the SSH agent, or the default identity files
+    Agent,
+    KeyFile {
+        path: String,
+    },
+    /// Kept in the OS keyring like connection passwords, so `password` is only set when it's first given or
+    /// changed
+    Password {
+        password: Option<String>,
+    },
+}
+
+impl SshTunnelConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_SSH_PORT)
+    }
+
+    /// Checks that the host and user can't be taken by `ssh` as anything else, e.g. a host of
+    /// `-oProxyCommand=...` as an option that runs a command
+    pub fn validate(&self) -> Result<(), Error> {
+        for (what, value) in [("host", &self.host), ("user", &self.username)] {
+            if value.is_empty() {
+                return Err(anyhow::anyhow!("The SSH {what} can't be empty").into());
+            }
+            if value.starts_with('-') {
+                return Err(anyhow::anyhow!("The SSH {what} can't start with '-'").into());
+            }
+            if value.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                return Err(anyhow::anyhow!(
+                    "The SSH {what} can't contain spaces or control characters"
+                )
+                .into());
+            }
+        }
+        if self.port == Some(0) {
+            return Err(anyhow::anyhow!("The SSH port can't be 0").into());
+        }
+        Ok(())
+    }
+}
+
+/// The server a tunnel forwards to, from the connection string
+fn forward_target(connection_string: &str) -> Result<(String, u16), Error> {
+    let url = Url::parse(connection_string).context("Failed to parse connection string")?;
+    let host = url
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .context("Tunneled connection strings need a host")?;
+    Ok((host.to_owned(), url.port().unwrap_or(5432)))
+}
+
+/// The connection string going through a tunnel listening on `local_port`. The host is kept, for TLS to verify
+/// the server's certificate against it, but only `hostaddr` is connected to.
+pub fn tunneled_connection_string(
+    connection_string: &str,
+    local_port: u16,
+) -> Result<String, Error> {
+    let mut url = Url::parse(connection_string).context("Failed to parse connection string")?;
+    url.set_port(Some(local_port))
+        .map_err(|_| anyhow::anyhow!("Failed to set the port of the connection string"))?;
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "hostaddr" && key != "port")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair("hostaddr", &Ipv4Addr::LOCALHOST.to_string());
+
+    Ok(url.to_string())
+}
+
+fn ssh_args(
+    config: &SshTunnelConfig,
+    local_port: u16,
+    target: &(String, u16),
+    with_password: bool,
+) -> Vec<String> {
+    // Hosts from URLs keep IPv6 addresses bracketed, which is also how -L takes them
+    let (target_host, target_port) = target;
+    let mut args = vec![
+        "-N".to_owned(),
+        "-o".to_owned(),
+        "ExitOnForwardFailure=yes".to_owned(),
+        "-o".to_owned(),
+        "ServerAliveInterval=15".to_owned(),
+        "-o".to_owned(),
+        "ServerAliveCountMax=3".to_owned(),
+        "-o".to_owned(),
+        "ConnectTimeout=10".to_owned(),
+        "-o".to_owned(),
+        format!("BatchMode={}", if with_password { "no" } else { "yes" }),
+        "-L".to_owned(),
+        format!(
+            "{}:{local_port}:{target_host}:{target_port}",
+            Ipv4Addr::LOCALHOST
+        ),
+        "-p".to_owned(),
+        config.port().to_string(),
+        "-l".to_owned(),
+        config.username.clone(),
+    ];
+
+    match &config.auth {
+        SshAuth::KeyFile { path } => {
+            args.extend(["-i".to_owned(), path.clone()]);
+            args.extend(["-o".to_owned(), "IdentitiesOnly=yes".to_owned()]);
+        }
+        SshAuth::Password { .. } => {
+            args.extend([
+                "-o".to_owned(),
+                "PreferredAuthentications=password,keyboard-interactive".to_owned(),
+            ]);
+        }
+        SshAuth::Agent => {}
+    }
+
+    // Whatever the host is, it's not an option
+    args.push("--".to_owned());
+    args.push(config.host.clone());
+    args
+}
+
+/// A script for `ssh` to read the password from, as it won't take one any other way. The password itself stays
+/// in the environment of `ssh`, and is only read by the script's interpreter, so that no character in it is taken
+/// as syntax. Removed when dropped.
+struct Askpass {
+    path: std::path::PathBuf,
+}
+
+impl Askpass {
+    fn create() -> Result<Self, Error> {
+        let (name, script) = if cfg!(windows) {
+            // cmd would expand the password in place, where `&`, `|` or `%` in it would be run as commands
+            (
+                "cmd",
+                "@echo off\r\npowershell.exe -NoProfile -NonInteractive -Command \"Write-Output $env:PGPAD_SSH_PASSWORD\"\r\n",
+            )
+        } else {
+            ("sh", "#!/bin/sh\nprintf '%s\\n' \"$PGPAD_SSH_PASSWORD\"\n")
+        };
+        let path =
+            std::env::temp_dir().join(format!("pgpad-askpass-{}.{name}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, script).context("Failed to create the SSH askpass script")?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700))
+                .context("Failed to make the SSH askpass script executable")?;
+        }
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for Askpass {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug)]
+pub struct SshTunnel {
+    pub local_port: u16,
+    /// Kills `ssh` when dropped
+    _stop: DropGuard,
+}
+
+impl SshTunnel {
+    /// Opens a tunnel to the server of a Postgres `connection_string`, returning it along with the connection
+    /// string going through it.
+    ///
+    /// `drop_notifier` is told if the tunnel closes before it's dropped.
+    pub async fn open(
+        config: &SshTunnelConfig,
+        connection_string: &str,
+        password: Option<String>,
+        drop_notifier: Option<ConnectionDropNotifier>,
+    ) -> Result<(Self, String), Error> {
+        let target = forward_target(connection_string)?;
+        let tunnel = Self::open_to(config, target, password, drop_notifier).await?;
+        let tunneled = tunneled_connection_string(connection_string, tunnel.local_port)?;
+        Ok((tunnel, tunneled))
+    }
+
+    /// Opens a tunnel to `target`, a host and port as seen from the bastion host. IPv6 addresses have to be
+    /// bracketed.
+    pub async fn open_to(
+        config: &SshTunnelConfig,
+        target: (String, u16),
+        password: Option<String>,
+        drop_notifier: Option<ConnectionDropNotifier>,
+    ) -> Result<Self, Error> {
+        config.validate()?;
+        if matches!(config.auth, SshAuth::Password { .. }) && password.is_none() {
+            return Err(anyhow::anyhow!("No SSH password found for {}", config.host).into());
+        }
+
+        let local_port = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .and_then(|listener| listener.local_addr())
+            .context("Failed to find a free local port for the SSH tunnel")?
+            .port();
+
+        let mut command = Command::new("ssh");
+        command
+            .args(ssh_args(config, local_port, &target, password.is_some()))
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+
+        let askpass = match &password {
+            Some(password) => {
+                let askpass = Askpass::create()?;
+                command
+                    .env("SSH_ASKPASS", &askpass.path)
+                    .env("SSH_ASKPASS_REQUIRE", "force")
+                    .env("PGPAD_SSH_PASSWORD", password);
+                // Older versions of ssh only use the askpass program when there's a display
+                if std::env::var_os("DISPLAY").is_none() {
+                    command.env("DISPLAY", ":0");
+                }
+                Some(askpass)
+            }
+            None => None,
+        };
+
+        let mut child = command
+            .spawn()
+            .context("Failed to run ssh, is an SSH client installed?")?;
+        let stderr = capture_stderr(&mut child, config.host.clone());
+
+        let opened = wait_until_forwarding(&mut child, local_port).await;
+        drop(askpass);
+        if let Err(err) = opened {
+            let _ = child.kill().await;
+            let output = stderr.lock().unwrap().trim().to_owned();
+            return Err(match output.is_empty() {
+                true => anyhow::anyhow!("SSH tunnel to {} failed: {err}", config.host),
+                false => anyhow::anyhow!("SSH tunnel to {} failed: {output}", config.host),
+            }
+            .into());
+        }
+        log::info!(
+            "Opened SSH tunnel through {} to {}:{} on port {local_port}",
+            config.host,
+            target.0,
+            target.1
+        );
+
+        let stop = CancellationToken::new();
+        tokio::spawn(watch(
+            child,
+            stop.clone(),
+            config.host.clone(),
+            drop_notifier,
+        ));
+
+        Ok(Self {
+            local_port,
+            _stop: stop.drop_guard(),
+        })
+    }
+}
+
+/// Logs what `ssh` says, keeping it for when opening the tunnel fails
+fn capture_stderr(child: &mut Child, host: String) -> Arc<Mutex<String>> {
+    let output = Arc::new(Mutex::new(String::new()));
+    let Some(stderr) = child.stderr.take() else {
+        return output;
+    };
+
+    let captured = output.clone();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            log::info!("ssh {host}: {line}");
+            let mut output = captured.lock().unwrap();
+            output.push_str(&line);
+            output.push('\n');
+        }
+    });
+    output
+}
+
+/// Waits until the local end of the tunnel accepts connections, which `ssh` only does once it's authenticated
+async fn wait_until_forwarding(child: &mut Child, local_port: u16) -> anyhow::Result<()> {
+    let deadline = Instant::now() + OPEN_TIMEOUT;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Err(anyhow::anyhow!("ssh exited with {status}"));
+        }
+        if TcpStream::connect((Ipv4Addr::LOCALHOST, local_port))
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(anyhow::anyhow!(
+                "timed out after {}s",
+                OPEN_TIMEOUT.as_secs()
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Kills `ssh` once the tunnel is dropped, or reports the connection as dropped if `ssh` exits first
+async fn watch(
+    mut child: Child,
+    stop: CancellationToken,
+    host: String,
+    drop_notifier: Option<ConnectionDropNotifier>,
+) {
+    let exited = {
+        let wait = std::pin::pin!(child.wait());
+        let stopped = std::pin::pin!(stop.cancelled());
+        match select(wait, stopped).await {
+            Either::Left((status, _)) => Some(status),
+            Either::Right(_) => None,
+        }
+    };
+
+    match exited {
+        Some(status) => {
+            log::warn!("SSH tunnel through {host} closed: {status:?}");
+            if let Some(drop_notifier) = drop_notifier {
+                drop_notifier.notify();
+            }
+        }
+        None => {
+            if let Err(err) = child.kill().await {
+                log::warn!("Failed to close SSH tunnel through {host}: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_connection_strings_at_the_tunnel() {
+        let original = "postgres://app@db.internal:6432/orders?sslmode=require&hostaddr=10.0.0.5";
+        assert_eq!(
+            forward_target(original).unwrap(),
+            ("db.internal".to_owned(), 6432)
+        );
+
+        let tunneled = tunneled_connection_string(original, 50123).unwrap();
+        assert_eq!(
+            tunneled,
+            "postgres://app@db.internal:50123/orders?sslmode=require&hostaddr=127.0.0.1"
+        );
+        let config: tokio_postgres::Config = tunneled.parse().unwrap();
+        assert_eq!(config.get_ports(), [50123]);
+        assert_eq!(
+            config.get_hostaddrs(),
+            [std::net::IpAddr::from(Ipv4Addr::LOCALHOST)]
+        );
+
+        assert_eq!(
+            forward_target("postgres://app@db.internal/orders").unwrap(),
+            ("db.internal".to_owned(), 5432)
+        );
+        assert!(forward_target("postgres:///orders").is_err());
+    }
+
+    #[test]
+    fn builds_ssh_arguments() {
+        let config = SshTunnelConfig {
+            host: "bastion.example.com".into(),
+            port: Some(2222),
+            username: "deploy".into(),
+            auth: SshAuth::KeyFile {
+                path: "/home/me/.ssh/bastion".into(),
+            },
+        };
+        let args = ssh_args(&config, 50123, &("db.internal".into(), 5432), false);
+        let args = args.join(" ");
+
+        assert!(args.starts_with("-N "));
+        assert!(args.contains("-o BatchMode=yes"));
+        assert!(args.contains("-L 127.0.0.1:50123:db.internal:5432"));
+        assert!(args.contains("-p 2222 -l deploy"));
+        assert!(args.contains("-i /home/me/.ssh/bastion -o IdentitiesOnly=yes"));
+        assert!(args.ends_with(" -- bastion.example.com"));
+
+        let config = SshTunnelConfig {
+            port: None,
+            auth: SshAuth::Password { password: None },
+            ..config
+        };
+        let args = ssh_args(&config, 50123, &("db.internal".into(), 5432), true).join(" ");
+        assert!(args.contains("-o BatchMode=no"));
+        assert!(args.contains("-p 22 "));
+    }
+
+    #[test]
+    fn rejects_hosts_and_users_taken_as_options() {
+        let config = SshTunnelConfig {
+            host: "bastion.example.com".into(),
+            port: None,
+            username: "deploy".into(),
+            auth: SshAuth::Agent,
+        };
+        assert!(config.validate().is_ok());
+
+        for host in [
+            "-oProxyCommand=touch /tmp/pwned",
+            "bastion -p 1",
+            "bastion\n",
+            "",
+        ] {
+            let config = SshTunnelConfig {
+                host: host.into(),
+                ..config.clone()
+            };
+            assert!(config.validate().is_err(), "{host:?}");
+        }
+        for username in ["-oProxyCommand=id", "de ploy", "deploy\t"] {
+            let config = SshTunnelConfig {
+                username: username.into(),
+                ..config.clone()
+            };
+            assert!(config.validate().is_err(), "{username:?}");
+        }
+        let config = SshTunnelConfig {
+            port: Some(0),
+            ..config
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn reports_why_tunnels_fail() {
+        if std::process::Command::new("ssh")
+            .arg("-V")
+            .output()
+            .is_err()
+        {
+            eprintln!("ssh isn't installed, skipping the test");
+            return;
+        }
+        // Nothing listens on port 1
+        let config = SshTunnelConfig {
+            host: "127.0.0.1".into(),
+            port: Some(1),
+            username: "me".into(),
+            auth: SshAuth::Agent,
+        };
+        let err = SshTunnel::open(&config, "postgres://app@db.internal/app", None, None)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("SSH tunnel to 127.0.0.1 failed"),
+            "{err}"
+        );
+
+        let config = SshTunnelConfig {
+            auth: SshAuth::Password { password: None },
+            ..config
+        };
+        assert!(
+            SshTunnel::open(&config, "postgres://app@db.internal/app", None, None)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,379 @@
+//! A snapshot of how a Postgres server is doing, for a monitoring tab that polls it
+
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio_postgres::Client;
+use uuid::Uuid;
+
+use super::replication::query_section;
+
+/// How long a snapshot is served again rather than taken anew, so that polling doesn't hammer the server
+const SNAPSHOT_TTL: Duration = Duration::from_secs(5);
+
+/// Tables in each of the top lists
+const TOP_TABLES: i64 = 10;
+
+/// Server statistics. Like [`super::replication::ReplicationInfo`], each section is None if it couldn't be loaded,
+/// e.g. with a restricted role or on a server that predates a view, with the reason in `warnings`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PostgresStats {
+    /// In milliseconds since the epoch
+    pub taken_at: i64,
+    pub database_size_bytes: Option<i64>,
+    /// Share of the current database's block reads served from shared buffers, None before any read
+    pub cache_hit_ratio: Option<f64>,
+    pub largest_tables: Option<Vec<TableStats>>,
+    pub most_seq_scanned_tables: Option<Vec<TableStats>>,
+    /// B-tree indexes much larger than their rows need, a rough estimate that `REINDEX` may help
+    pub index_bloat_candidates: Option<Vec<IndexBloat>>,
+    pub connections: Option<ConnectionUsage>,
+    /// Replicas of a primary, or how far behind a standby is
+    pub replication_lag: Option<ReplicationLag>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableStats {
+    pub schema: String,
+    pub name: String,
+    /// With its indexes and TOAST data
+    pub total_bytes: i64,
+    pub seq_scan: i64,
+    pub idx_scan: Option<i64>,
+    pub live_rows: i64,
+    pub dead_rows: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexBloat {
+    pub schema: String,
+    pub index: String,
+    pub table: String,
+    pub bytes: i64,
+    /// About the size the index would have if rebuilt
+    pub expected_bytes: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionUsage {
+    pub count: i64,
+    pub max_connections: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicationLag {
+    pub in_recovery: bool,
+    /// Since the last transaction replayed, on standbys
+    pub replay_delay_seconds: Option<f64>,
+    /// Standbys streaming from this server
+    pub replicas: Vec<ReplicaLag>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicaLag {
+    pub application_name: String,
+    pub client_addr: Option<String>,
+    pub state: Option<String>,
+    /// WAL the replica has yet to replay. None on cascading standbys.
+    pub replay_lag_bytes: Option<i64>,
+}
+
+const DATABASE_QUERY: &str = "
+    SELECT
+        pg_database_size(d.datid),
+        d.blks_hit::float8 / NULLIF(d.blks_hit + d.blks_read, 0)
+    FROM pg_stat_database d
+    WHERE d.datname = current_database()
+";
+
+/// `{order}` is the column tables are ranked by
+const TABLES_QUERY: &str = "
+    SELECT
+        schemaname::text,
+        relname::text,
+        pg_total_relation_size(relid),
+        COALESCE(seq_scan, 0),
+        idx_scan,
+        n_live_tup,
+        n_dead_tup
+    FROM pg_stat_user_tables
+    ORDER BY {order} DESC NULLS LAST, schemaname, relname
+    LIMIT {limit}
+";
+
+/// The expected size adds the per-tuple overhead to the width of the indexed columns, then leaves room for the
+/// default fill factor. Indexes of under a megabyte aren't worth rebuilding either way.
+const INDEX_BLOAT_QUERY: &str = "
+    WITH widths AS (
+        SELECT x.indexrelid, sum(COALESCE(s.avg_width, 8)) AS width
+        FROM pg_index x
+        JOIN pg_class t ON t.oid = x.indrelid
+        JOIN pg_namespace n ON n.oid = t.relnamespace
+        JOIN pg_attribute a ON a.attrelid = x.indrelid AND a.attnum = ANY (x.indkey)
+        LEFT JOIN pg_stats s ON s.schemaname = n.nspname AND s.tablename = t.relname AND s.attname = a.attname
+        WHERE n.nspname NOT IN ('pg_catalog', 'information_schema') AND n.nspname NOT LIKE 'pg_toast%'
+        GROUP BY x.indexrelid
+    ), sizes AS (
+        SELECT
+            n.nspname::text AS schema,
+            c.relname::text AS index_name,
+            t.relname::text AS table_name,
+            pg_relation_size(c.oid) AS bytes,
+            (c.reltuples * (w.width + 16) / 0.9)::bigint AS expected_bytes
+        FROM widths w
+        JOIN pg_class c ON c.oid = w.indexrelid
+        JOIN pg_am am ON am.oid = c.relam AND am.amname = 'btree'
+        JOIN pg_index x ON x.indexrelid = c.oid
+        JOIN pg_class t ON t.oid = x.indrelid
+        JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE c.reltuples > 0
+    )
+    SELECT schema, index_name, table_name, bytes, expected_bytes
+    FROM sizes
+    WHERE bytes > 1024 * 1024 AND bytes > 2 * expected_bytes
+    ORDER BY bytes - expected_bytes DESC
+    LIMIT 10
+";
+
+const CONNECTIONS_QUERY: &str = "
+    SELECT count(*), current_setting('max_connections')::bigint
+    FROM pg_stat_activity
+";
+
+const RECOVERY_QUERY: &str = "
+    SELECT
+        pg_is_in_recovery(),
+        CASE WHEN pg_is_in_recovery()
+            THEN extract(epoch FROM now() - pg_last_xact_replay_timestamp())::float8
+        END
+";
+
+const REPLICAS_QUERY: &str = "
+    SELECT
+        application_name,
+        client_addr::text,
+        state,
+        CASE
+            WHEN pg_is_in_recovery() THEN NULL
+            ELSE pg_wal_lsn_diff(pg_current_wal_lsn(), replay_lsn)::bigint
+        END
+    FROM pg_stat_replication
+    ORDER BY application_name
+";
+
+pub async fn get_postgres_stats(client: &Client) -> PostgresStats {
+    let mut warnings = Vec::new();
+
+    let database = query_section(client, DATABASE_QUERY, "database statistics", &mut warnings)
+        .await
+        .and_then(|rows| rows.into_iter().next());
+    let database_size_bytes = database.as_ref().map(|row| row.get(0));
+    let cache_hit_ratio = database.and_then(|row| row.get(1));
+
+    let largest_tables = top_tables(
+        client,
+        "pg_total_relation_size(relid)",
+        "tables by size",
+        &mut warnings,
+    )
+    .await;
+    let most_seq_scanned_tables = top_tables(
+        client,
+        "seq_scan",
+        "tables by sequential scans",
+        &mut warnings,
+    )
+    .await;
+
+    let index_bloat_candidates = query_section(
+        client,
+        INDEX_BLOAT_QUERY,
+        "index bloat estimates",
+        &mut warnings,
+    )
+    .await
+    .map(|rows| {
+        rows.iter()
+            .map(|row| IndexBloat {
+                schema: row.get(0),
+                index: row.get(1),
+                table: row.get(2),
+                bytes: row.get(3),
+                expected_bytes: row.get(4),
+            })
+            .collect()
+    });
+
+    let connections = query_section(client, CONNECTIONS_QUERY, "connections", &mut warnings)
+        .await
+        .and_then(|rows| rows.into_iter().next())
+        .map(|row| ConnectionUsage {
+            count: row.get(0),
+            max_connections: row.get(1),
+        });
+
+    let replication_lag = replication_lag(client, &mut warnings).await;
+
+    PostgresStats {
+        taken_at: chrono::Utc::now().timestamp_millis(),
+        database_size_bytes,
+        cache_hit_ratio,
+        largest_tables,
+        most_seq_scanned_tables,
+        index_bloat_candidates,
+        connections,
+        replication_lag,
+        warnings,
+    }
+}
+
+async fn top_tables(
+    client: &Client,
+    order: &str,
+    section: &str,
+    warnings: &mut Vec<String>,
+) -> Option<Vec<TableStats>> {
+    let query = TABLES_QUERY
+        .replace("{order}", order)
+        .replace("{limit}", &TOP_TABLES.to_string());
+
+    let rows = query_section(client, &query, section, warnings).await?;
+    Some(
+        rows.iter()
+            .map(|row| TableStats {
+                schema: row.get(0),
+                name: row.get(1),
+                total_bytes: row.get(2),
+                seq_scan: row.get(3),
+                idx_scan: row.get(4),
+                live_rows: row.get(5),
+                dead_rows: row.get(6),
+            })
+            .collect(),
+    )
+}
+
+async fn replication_lag(client: &Client, warnings: &mut Vec<String>) -> Option<ReplicationLag> {
+    let recovery = query_section(client, RECOVERY_QUERY, "recovery status", warnings)
+        .await?
+        .into_iter()
+        .next()?;
+    let replicas = query_section(client, REPLICAS_QUERY, "replicas", warnings)
+        .await?
+        .iter()
+        .map(|row| ReplicaLag {
+            application_name: row.get(0),
+            client_addr: row.get(1),
+            state: row.get(2),
+            replay_lag_bytes: row.get(3),
+        })
+        .collect();
+
+    Some(ReplicationLag {
+        in_recovery: recovery.get(0),
+        replay_delay_seconds: recovery.get(1),
+        replicas,
+    })
+}
+
+/// The latest snapshot of each connection, served again while it's recent
+#[derive(Debug, Default)]
+pub struct StatsCache {
+    snapshots: DashMap<Uuid, (Instant, PostgresStats)>,
+}
+
+impl StatsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The snapshot of a connection, unless it's stale
+    pub fn get(&self, connection_id: Uuid) -> Option<PostgresStats> {
+        self.snapshots
+            .get(&connection_id)
+            .filter(|entry| entry.0.elapsed() < SNAPSHOT_TTL)
+            .map(|entry| entry.1.clone())
+    }
+
+    pub fn insert(&self, connection_id: Uuid, stats: PostgresStats) {
+        self.snapshots
+            .insert(connection_id, (Instant::now(), stats));
+    }
+
+    pub fn forget(&self, connection_id: Uuid) {
+        self.snapshots.remove(&connection_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn takes_a_snapshot_of_the_server() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        client
+            .batch_execute(
+                "CREATE TABLE small (id int);
+                 CREATE TABLE big AS SELECT g AS id FROM generate_series(1, 10000) g;
+                 ANALYZE;",
+            )
+            .await
+            .unwrap();
+
+        let stats = get_postgres_stats(&client).await;
+        assert!(stats.warnings.is_empty(), "{:?}", stats.warnings);
+        assert!(stats.database_size_bytes.is_some_and(|bytes| bytes > 0));
+        // None until the server has counted a read
+        assert!(stats
+            .cache_hit_ratio
+            .is_none_or(|ratio| (0.0..=1.0).contains(&ratio)));
+
+        let names = |tables: Option<Vec<TableStats>>| {
+            tables
+                .unwrap()
+                .into_iter()
+                .map(|table| table.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(stats.largest_tables), ["big", "small"]);
+        // Scans are counted with a delay, so their order isn't known yet
+        let mut scanned = names(stats.most_seq_scanned_tables);
+        scanned.sort();
+        assert_eq!(scanned, ["big", "small"]);
+
+        assert!(stats.index_bloat_candidates.unwrap().is_empty());
+        let connections = stats.connections.unwrap();
+        assert!(connections.count >= 1 && connections.count <= connections.max_connections);
+        let lag = stats.replication_lag.unwrap();
+        assert!(!lag.in_recovery);
+        assert!(lag.replicas.is_empty());
+    }
+
+    #[tokio::test]
+    async fn serves_recent_snapshots_again() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let cache = StatsCache::new();
+        let connection_id = Uuid::new_v4();
+        assert!(cache.get(connection_id).is_none());
+
+        let stats = get_postgres_stats(&client).await;
+        cache.insert(connection_id, stats.clone());
+        assert_eq!(cache.get(connection_id).unwrap().taken_at, stats.taken_at);
+
+        cache.forget(connection_id);
+        assert!(cache.get(connection_id).is_none());
+    }
+}
@@ -1,5 +1,6 @@
 use std::{io::Cursor, sync::Arc};
 
+use rustls::pki_types::CertificateDer;
 use tokio::sync::OnceCell;
 
 pub const RDS_CERTIFICATES: &str = include_str!("../../../../certs/aws-rds-global-bundle.pem");
@@ -8,6 +9,8 @@ pub const AZURE_CERTIFICATES: &str = include_str!("../../../../certs/azure-balti
 #[derive(Clone)]
 pub struct Certificates {
     pub certs: Arc<OnceCell<Arc<rustls::RootCertStore>>>,
+    /// The roots trusted on top of the webpki ones, for drivers that are given certificates rather than a store
+    extra_roots: Arc<OnceCell<Arc<Vec<CertificateDer<'static>>>>>,
 }
 
 impl Default for Certificates {
@@ -20,17 +23,30 @@ impl Certificates {
     pub fn new() -> Self {
         Self {
             certs: Arc::new(OnceCell::new()),
+            extra_roots: Arc::new(OnceCell::new()),
         }
     }
 
     pub async fn read(&self) -> anyhow::Result<Arc<rustls::RootCertStore>> {
         Ok(self
             .certs
-            .get_or_init(|| async { Arc::new(load_certificates().await) })
+            .get_or_init(|| async {
+                let mut cert_store = webpki_certificates();
+                cert_store.add_parsable_certificates(self.extra_roots().await.iter().cloned());
+                Arc::new(cert_store)
+            })
             .await
             .clone())
     }
 
+    /// The RDS, Azure and native roots, leaving out those rustls can't parse
+    pub async fn extra_roots(&self) -> Arc<Vec<CertificateDer<'static>>> {
+        self.extra_roots
+            .get_or_init(|| async { Arc::new(load_extra_certificates().await) })
+            .await
+            .clone()
+    }
+
     pub async fn with_custom_cert(
         &self,
         cert_path: &str,
@@ -78,37 +94,38 @@ impl Certificates {
     }
 }
 
-async fn load_certificates() -> rustls::RootCertStore {
-    let mut cert_store = webpki_certificates();
+async fn load_extra_certificates() -> Vec<CertificateDer<'static>> {
+    let mut certs = Vec::new();
 
     let native_certs_handle = tokio::task::spawn_blocking(rustls_native_certs::load_native_certs);
 
-    if let Err(e) = load_pem_certificates(RDS_CERTIFICATES, &mut cert_store) {
+    if let Err(e) = load_pem_certificates(RDS_CERTIFICATES, &mut certs) {
         eprintln!("Error loading RDS certificates: {}", e);
     }
 
-    if let Err(e) = load_pem_certificates(AZURE_CERTIFICATES, &mut cert_store) {
+    if let Err(e) = load_pem_certificates(AZURE_CERTIFICATES, &mut certs) {
         eprintln!("Error loading Azure certificates: {}", e);
     }
 
     if let Ok(native_certs) = native_certs_handle.await {
-        cert_store.add_parsable_certificates(native_certs.certs);
+        certs.extend(native_certs.certs);
     } else {
         eprintln!("Error loading native certificates");
     }
 
-    cert_store
+    let mut parsable = rustls::RootCertStore::empty();
+    certs.retain(|cert| parsable.add(cert.clone()).is_ok());
+    certs
 }
 
 fn load_pem_certificates(
     pem_data: &str,
-    cert_store: &mut rustls::RootCertStore,
+    certs: &mut Vec<CertificateDer<'static>>,
 ) -> anyhow::Result<()> {
     let mut cursor = Cursor::new(pem_data.as_bytes());
-    let certs = rustls_pemfile::certs(&mut cursor);
 
-    for cert in certs {
-        cert_store.add_parsable_certificates(cert);
+    for cert in rustls_pemfile::certs(&mut cursor) {
+        certs.extend(cert);
     }
 
     Ok(())
@@ -0,0 +1,317 @@
+//! Pre-flight checks for queries submitted to production connections.
+//!
+//! Before running on a connection tagged as production, Postgres statements are `EXPLAIN`ed and the run
+//! is held back for confirmation if their estimated cost or rows exceed the connection's thresholds.
+//! Other databases only get the parser-based lint for destructive statements.
+//!
+//! On every connection, runs are also held back if their SQL has smart quotes or other characters picked up
+//! when pasting, outside of string literals.
+//!
+//! Connections tagged with an environment are also held back according to the [`EnvironmentPolicies`], e.g. for
+//! schema changes or writes to every row of a table on prod.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::database::{
+    parser::{find_destructive_statements, DestructiveStatements, ParsedStatement, StatementKind},
+    sanitize::{find_suspicious_characters, CharacterContext, SuspiciousCharacter},
+    types::{Environment, RunOptions},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreflightSettings {
+    /// Whether the connection is tagged as production. Only pasted characters are checked otherwise.
+    pub production: bool,
+    /// Whether Postgres statements are `EXPLAIN`ed before running
+    pub estimate_cost: bool,
+    /// Planner cost above which the run must be confirmed
+    pub max_total_cost: f64,
+    /// Estimated rows above which the run must be confirmed
+    pub max_estimated_rows: f64,
+    /// How long the pre-flight `EXPLAIN` may take before it's skipped
+    pub timeout_ms: u64,
+    /// Whether runs with smart quotes, non-breaking spaces or zero-width characters outside string literals
+    /// must be confirmed
+    pub warn_suspicious_characters: bool,
+}
+
+impl Default for PreflightSettings {
+    fn default() -> Self {
+        Self {
+            production: false,
+            estimate_cost: true,
+            max_total_cost: 100_000.0,
+            max_estimated_rows: 1_000_000.0,
+            timeout_ms: 2_000,
+            warn_suspicious_characters: true,
+        }
+    }
+}
+
+/// What runs on connections of an environment must be confirmed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentPolicy {
+    /// Whether schema changes, and UPDATE or DELETE statements without a WHERE clause, must be confirmed
+    pub confirm_destructive: bool,
+}
+
+/// Connections without an environment aren't held back by any policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentPolicies {
+    pub dev: EnvironmentPolicy,
+    pub staging: EnvironmentPolicy,
+    pub prod: EnvironmentPolicy,
+    pub custom: EnvironmentPolicy,
+}
+
+impl Default for EnvironmentPolicies {
+    fn default() -> Self {
+        Self {
+            dev: EnvironmentPolicy::default(),
+            staging: EnvironmentPolicy::default(),
+            prod: EnvironmentPolicy {
+                confirm_destructive: true,
+            },
+            custom: EnvironmentPolicy::default(),
+        }
+    }
+}
+
+impl EnvironmentPolicies {
+    pub fn of(&self, environment: Environment) -> EnvironmentPolicy {
+        match environment {
+            Environment::Dev => self.dev,
+            Environment::Staging => self.staging,
+            Environment::Prod => self.prod,
+            Environment::Custom => self.custom,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardReason {
+    /// UPDATE or DELETE without a WHERE clause
+    UnfilteredWrite,
+    /// CREATE, ALTER, DROP, TRUNCATE and the like
+    SchemaChange,
+}
+
+/// A statement held back by the policy of its connection's environment
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GuardedStatement {
+    /// As written in the script
+    pub statement: String,
+    pub reason: GuardReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CostEstimate {
+    pub statement: String,
+    /// The planner's `Total Cost` for the statement
+    pub total_cost: f64,
+    /// The planner's `Plan Rows` for the statement
+    pub estimated_rows: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PreflightReport {
+    /// Statements whose estimates exceeded the thresholds
+    pub expensive_statements: Vec<CostEstimate>,
+    pub destructive_statements: Vec<DestructiveStatements>,
+    /// Characters that were likely picked up when pasting, outside of literals and comments
+    pub suspicious_characters: Vec<SuspiciousCharacter>,
+    /// Statements the policy of the connection's environment holds back
+    pub guarded_statements: Vec<GuardedStatement>,
+}
+
+impl PreflightReport {
+    /// Postgres statements are only held back when their estimates exceed the thresholds
+    pub fn from_estimates(estimates: Vec<CostEstimate>, settings: &PreflightSettings) -> Self {
+        let expensive_statements = estimates
+            .into_iter()
+            .filter(|estimate| {
+                estimate.total_cost > settings.max_total_cost
+                    || estimate.estimated_rows > settings.max_estimated_rows
+            })
+            .collect();
+
+        Self {
+            expensive_statements,
+            ..Default::default()
+        }
+    }
+
+    /// Fallback for databases we can't estimate costs on
+    pub fn from_lint(query: &str) -> Self {
+        let destructive_statements = find_destructive_statements(query).unwrap_or_else(|err| {
+            log::warn!("Could not scan query for destructive statements: {err}");
+            Vec::new()
+        });
+
+        Self {
+            destructive_statements,
+            ..Default::default()
+        }
+    }
+
+    pub fn check_suspicious_characters(&mut self, query: &str) {
+        self.suspicious_characters = find_suspicious_characters(query)
+            .into_iter()
+            .filter(|c| c.context == CharacterContext::Code)
+            .collect();
+    }
+
+    /// Holds back the statements the policy asks to confirm. `statements` were parsed from `query`.
+    pub fn check_policy(
+        &mut self,
+        policy: EnvironmentPolicy,
+        query: &str,
+        statements: &[ParsedStatement],
+    ) {
+        if !policy.confirm_destructive {
+            return;
+        }
+        self.guarded_statements = statements
+            .iter()
+            .filter_map(|stmt| {
+                let reason = if stmt.kind == StatementKind::Ddl {
+                    GuardReason::SchemaChange
+                } else if stmt.unfiltered_write {
+                    GuardReason::UnfilteredWrite
+                } else {
+                    return None;
+                };
+                Some(GuardedStatement {
+                    statement: query[stmt.span.clone()].to_owned(),
+                    reason,
+                })
+            })
+            .collect();
+    }
+
+    pub fn requires_confirmation(&self) -> bool {
+        !self.expensive_statements.is_empty()
+            || !self.destructive_statements.is_empty()
+            || !self.suspicious_characters.is_empty()
+            || !self.guarded_statements.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SubmitOutcome {
+    Submitted {
+        query_ids: Vec<usize>,
+    },
+    /// The run is held until it's confirmed through `confirm_run`, or dropped through `discard_run`
+    ConfirmationRequired {
+        run_id: Uuid,
+        report: PreflightReport,
+    },
+}
+
+/// A run waiting for confirmation
+#[derive(Debug, Clone)]
+pub struct PendingRun {
+    pub connection_id: Uuid,
+    /// With its variables substituted
+    pub query: String,
+    /// As written in the editor, which its statements are marked by
+    pub written_query: String,
+    /// Including the query tag, rendered when the run was submitted
+    pub options: RunOptions,
+    /// The editor tab the query comes from, to mark its statements once it runs
+    pub tab_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(total_cost: f64, estimated_rows: f64) -> CostEstimate {
+        CostEstimate {
+            statement: "SELECT * FROM t".into(),
+            total_cost,
+            estimated_rows,
+        }
+    }
+
+    #[test]
+    fn holds_back_statements_over_thresholds() {
+        let settings = PreflightSettings {
+            production: true,
+            max_total_cost: 1_000.0,
+            max_estimated_rows: 500.0,
+            ..Default::default()
+        };
+
+        let report = PreflightReport::from_estimates(vec![estimate(10.0, 5.0)], &settings);
+        assert!(!report.requires_confirmation());
+
+        let report = PreflightReport::from_estimates(
+            vec![
+                estimate(10.0, 5.0),
+                estimate(5_000.0, 5.0),
+                estimate(10.0, 50_000.0),
+            ],
+            &settings,
+        );
+        assert!(report.requires_confirmation());
+        assert_eq!(
+            report.expensive_statements,
+            [estimate(5_000.0, 5.0), estimate(10.0, 50_000.0)]
+        );
+    }
+
+    #[test]
+    fn lints_destructive_statements() {
+        assert!(!PreflightReport::from_lint("SELECT * FROM t").requires_confirmation());
+
+        let report = PreflightReport::from_lint("SELECT 1; DELETE FROM t");
+        assert!(report.requires_confirmation());
+        assert_eq!(report.destructive_statements[0].kind, "DELETE");
+    }
+
+    #[test]
+    fn holds_back_statements_by_environment() {
+        let query = "SELECT 1; DELETE FROM t WHERE id = 1; DELETE FROM t; ALTER TABLE t ADD c int";
+        let statements = crate::database::postgres::parser::parse_statements(query).unwrap();
+        let policies = EnvironmentPolicies::default();
+
+        let mut report = PreflightReport::default();
+        report.check_policy(policies.of(Environment::Dev), query, &statements);
+        assert!(!report.requires_confirmation());
+
+        report.check_policy(policies.of(Environment::Prod), query, &statements);
+        assert!(report.requires_confirmation());
+        assert_eq!(
+            report.guarded_statements,
+            [
+                GuardedStatement {
+                    statement: "DELETE FROM t".into(),
+                    reason: GuardReason::UnfilteredWrite,
+                },
+                GuardedStatement {
+                    statement: "ALTER TABLE t ADD c int".into(),
+                    reason: GuardReason::SchemaChange,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn holds_back_pasted_characters_outside_literals() {
+        let mut report = PreflightReport::default();
+        report.check_suspicious_characters("SELECT 'it’s fine' -- it’s fine");
+        assert!(!report.requires_confirmation());
+
+        report.check_suspicious_characters("SELECT * FROM users WHERE name = ’bob’");
+        assert!(report.requires_confirmation());
+        assert_eq!(report.suspicious_characters.len(), 2);
+    }
+}
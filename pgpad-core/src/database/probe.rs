@@ -0,0 +1,344 @@
+//! Lightweight reachability probes of saved connections, e.g. to show which ones are up when the app starts.
+//!
+//! Probes never authenticate: for Postgres they stop at the server's first answer to the startup message, which
+//! says whether it wants credentials, and for MySQL at the greeting the server sends first, so that probing can't
+//! count towards failed login attempts. Credentials aren't looked up either, which keeps probes away from the
+//! keyring.
+
+use std::{path::Path, time::Duration};
+
+use serde::Serialize;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::Instant,
+};
+use tokio_postgres::config::Host;
+use uuid::Uuid;
+
+use crate::database::{mysql, sqlite::paths, types::ConnectionConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeStatus {
+    Reachable,
+    /// The server is up, and wants credentials to go any further
+    AuthRequired,
+    Unreachable,
+    Timeout,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionProbe {
+    pub connection_id: Uuid,
+    pub status: ProbeStatus,
+    /// How long the probe took, None if it timed out
+    pub latency_ms: Option<u64>,
+    /// Why the connection is unreachable
+    pub error: Option<String>,
+}
+
+/// What the server answered to the startup message
+#[derive(Debug, PartialEq, Eq)]
+enum StartupResponse {
+    /// An authentication request, with its code (0 being "no authentication needed")
+    Authentication(i32),
+    /// e.g. a missing `pg_hba.conf` entry, or too many connections. The server is still up.
+    Error,
+}
+
+/// Probes the given connection, giving up after `timeout`
+pub async fn probe(
+    connection_id: Uuid,
+    config: &ConnectionConfig,
+    timeout: Duration,
+) -> ConnectionProbe {
+    let started = Instant::now();
+
+    let result = match tokio::time::timeout(timeout, probe_config(config)).await {
+        Ok(result) => result,
+        Err(_) => {
+            return ConnectionProbe {
+                connection_id,
+                status: ProbeStatus::Timeout,
+                latency_ms: None,
+                error: None,
+            }
+        }
+    };
+
+    let (status, error) = match result {
+        Ok(status) => (status, None),
+        Err(err) => (ProbeStatus::Unreachable, Some(err.to_string())),
+    };
+
+    ConnectionProbe {
+        connection_id,
+        status,
+        latency_ms: Some(started.elapsed().as_millis() as u64),
+        error,
+    }
+}
+
+async fn probe_config(config: &ConnectionConfig) -> anyhow::Result<ProbeStatus> {
+    match config {
+        // The server is only reachable through the tunnel, so it's the bastion that's probed
+        ConnectionConfig::Postgres {
+            ssh_tunnel: Some(tunnel),
+            ..
+        }
+        | ConnectionConfig::MySQL {
+            ssh_tunnel: Some(tunnel),
+            ..
+        } => {
+            tokio::net::TcpStream::connect((tunnel.host.as_str(), tunnel.port()))
+                .await
+                .map_err(|err| anyhow::anyhow!("Failed to reach {}: {err}", tunnel.host))?;
+            Ok(ProbeStatus::Reachable)
+        }
+        ConnectionConfig::Postgres {
+            connection_string, ..
+        } => {
+            let config: tokio_postgres::Config = connection_string
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid connection string"))?;
+            probe_postgres(&config).await
+        }
+        ConnectionConfig::SQLite {
+            db_path,
+            home_relative_path,
+        } => {
+            let db_path = paths::resolve(
+                db_path,
+                home_relative_path.as_deref(),
+                paths::home_dir().as_deref(),
+            )
+            .map_or_else(
+                || db_path.clone(),
+                |path| path.to_string_lossy().into_owned(),
+            );
+            tokio::task::spawn_blocking(move || probe_sqlite(&db_path)).await?
+        }
+        ConnectionConfig::MySQL {
+            connection_string, ..
+        } => {
+            let opts = mysql::connect::parse_connection_string(connection_string)
+                .map_err(|_| anyhow::anyhow!("Invalid connection string"))?;
+            let stream =
+                tokio::net::TcpStream::connect((opts.ip_or_hostname(), opts.tcp_port())).await?;
+            mysql_greeting(stream).await
+        }
+    }
+}
+
+/// Reads the first packet the server sends, then hangs up: a handshake, which always asks for credentials, or an
+/// error, e.g. when the client's host isn't allowed to connect. The server is up either way.
+async fn mysql_greeting<S>(mut stream: S) -> anyhow::Result<ProbeStatus>
+where
+    S: AsyncRead + Unpin,
+{
+    // A 3-byte length and a sequence number come before the payload
+    let mut header = [0; 4];
+    stream.read_exact(&mut header).await?;
+
+    match stream.read_u8().await? {
+        // The protocol version of the handshake
+        10 => Ok(ProbeStatus::AuthRequired),
+        0xff => Ok(ProbeStatus::Reachable),
+        _ => Err(anyhow::anyhow!(
+            "The server doesn't speak the MySQL protocol"
+        )),
+    }
+}
+
+/// Tries every host of `config` in order, like connecting would
+async fn probe_postgres(config: &tokio_postgres::Config) -> anyhow::Result<ProbeStatus> {
+    let hosts = config.get_hosts();
+    if hosts.is_empty() {
+        return Err(anyhow::anyhow!("The connection string has no host"));
+    }
+
+    let user = config.get_user().unwrap_or("postgres");
+    let dbname = config.get_dbname().unwrap_or(user);
+
+    let mut last_error = None;
+    for (idx, host) in hosts.iter().enumerate() {
+        let port = match config.get_ports() {
+            [] => 5432,
+            [port] => *port,
+            ports => ports.get(idx).copied().unwrap_or(5432),
+        };
+
+        match probe_postgres_host(host, port, user, dbname).await {
+            Ok(status) => return Ok(status),
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    Err(last_error.expect("there's at least one host"))
+}
+
+async fn probe_postgres_host(
+    host: &Host,
+    port: u16,
+    user: &str,
+    dbname: &str,
+) -> anyhow::Result<ProbeStatus> {
+    let response = match host {
+        Host::Tcp(host) => {
+            let stream = tokio::net::TcpStream::connect((host.as_str(), port)).await?;
+            startup(stream, user, dbname).await?
+        }
+        #[cfg(unix)]
+        Host::Unix(dir) => {
+            let socket = dir.join(format!(".s.PGSQL.{port}"));
+            let stream = tokio::net::UnixStream::connect(socket).await?;
+            startup(stream, user, dbname).await?
+        }
+        #[cfg(not(unix))]
+        Host::Unix(_) => return Err(anyhow::anyhow!("Unix sockets aren't supported here")),
+    };
+
+    Ok(match response {
+        StartupResponse::Authentication(0) | StartupResponse::Error => ProbeStatus::Reachable,
+        StartupResponse::Authentication(_) => ProbeStatus::AuthRequired,
+    })
+}
+
+/// Sends a plain-text startup message and reads the first message of the answer, then hangs up.
+///
+/// Servers that only accept TLS connections answer with an error, which is enough to tell they're up.
+async fn startup<S>(mut stream: S, user: &str, dbname: &str) -> anyhow::Result<StartupResponse>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(&startup_message(user, dbname)).await?;
+
+    let tag = stream.read_u8().await?;
+    let len = stream.read_i32().await?;
+    match tag {
+        b'R' if len >= 8 => Ok(StartupResponse::Authentication(stream.read_i32().await?)),
+        b'E' => Ok(StartupResponse::Error),
+        _ => Err(anyhow::anyhow!(
+            "The server doesn't speak the Postgres protocol"
+        )),
+    }
+}
+
+/// A protocol 3.0 StartupMessage
+fn startup_message(user: &str, dbname: &str) -> Vec<u8> {
+    let mut body = 196608i32.to_be_bytes().to_vec();
+    for (key, value) in [("user", user), ("database", dbname)] {
+        body.extend_from_slice(key.as_bytes());
+        body.push(0);
+        body.extend_from_slice(value.as_bytes());
+        body.push(0);
+    }
+    body.push(0);
+
+    let mut message = ((body.len() + 4) as i32).to_be_bytes().to_vec();
+    message.extend(body);
+    message
+}
+
+/// Opens the file read-only, so that probing never creates it, and reads its header
+fn probe_sqlite(db_path: &str) -> anyhow::Result<ProbeStatus> {
+    if db_path != ":memory:" && !Path::new(db_path).is_file() {
+        return Err(anyhow::anyhow!("{db_path} does not exist"));
+    }
+
+    let conn = rusqlite::Connection::open_with_flags(
+        db_path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    conn.query_row("PRAGMA schema_version", [], |row| row.get::<_, i64>(0))?;
+
+    Ok(ProbeStatus::Reachable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_startup_messages() {
+        let message = startup_message("me", "db");
+        assert_eq!(&message[..4], &(message.len() as i32).to_be_bytes());
+        assert_eq!(&message[4..8], &[0, 3, 0, 0]);
+        assert_eq!(&message[8..], b"user\0me\0database\0db\0\0");
+    }
+
+    #[tokio::test]
+    async fn probes_sqlite_files() {
+        let path = std::env::temp_dir().join(format!("pgpad-{}.db", Uuid::new_v4()));
+        let config = ConnectionConfig::SQLite {
+            db_path: path.to_string_lossy().into_owned(),
+            home_relative_path: None,
+        };
+        let id = Uuid::new_v4();
+
+        let missing = probe(id, &config, Duration::from_secs(5)).await;
+        assert_eq!(missing.status, ProbeStatus::Unreachable);
+        assert!(!path.exists());
+
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE t (id INTEGER)")
+            .unwrap();
+        let found = probe(id, &config, Duration::from_secs(5)).await;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(found.status, ProbeStatus::Reachable);
+        assert!(found.latency_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn probes_postgres_without_authenticating() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let config = ConnectionConfig::Postgres {
+            connection_string: db.connection_uri(),
+            ca_cert_path: None,
+            ssh_tunnel: None,
+            default_schema: None,
+        };
+
+        let found = probe(Uuid::new_v4(), &config, Duration::from_secs(5)).await;
+        assert!(matches!(
+            found.status,
+            ProbeStatus::Reachable | ProbeStatus::AuthRequired
+        ));
+
+        // Nothing listens on port 1
+        let closed = ConnectionConfig::Postgres {
+            connection_string: "postgres://me@127.0.0.1:1/db".into(),
+            ca_cert_path: None,
+            ssh_tunnel: None,
+            default_schema: None,
+        };
+        let closed = probe(Uuid::new_v4(), &closed, Duration::from_secs(5)).await;
+        assert_eq!(closed.status, ProbeStatus::Unreachable);
+    }
+
+    #[tokio::test]
+    async fn reads_mysql_greetings() {
+        let handshake: &[u8] = b"\x4a\x00\x00\x00\x0a8.4.0\x00";
+        assert_eq!(
+            mysql_greeting(handshake).await.unwrap(),
+            ProbeStatus::AuthRequired
+        );
+        let refused: &[u8] = b"\x1a\x00\x00\x00\xff\x6a\x04Host is not allowed";
+        assert_eq!(
+            mysql_greeting(refused).await.unwrap(),
+            ProbeStatus::Reachable
+        );
+        // A server that hangs up, or that speaks something else
+        assert!(mysql_greeting(&b""[..]).await.is_err());
+        assert!(mysql_greeting(&b"HTTP/1.1 400"[..]).await.is_err());
+
+        let closed = ConnectionConfig::MySQL {
+            connection_string: "mysql://me@127.0.0.1:1/db".into(),
+            ca_cert_path: None,
+            ssh_tunnel: None,
+        };
+        let closed = probe(Uuid::new_v4(), &closed, Duration::from_secs(5)).await;
+        assert_eq!(closed.status, ProbeStatus::Unreachable);
+    }
+}
@@ -0,0 +1,214 @@
+//! Query tags: a comment prepended to every statement sent to a connection, e.g.
+//! `/* pgpad user=alice conn=staging script=q3-report.sql run=… */`, so that DBAs can attribute load to pgpad
+//! and its users from `pg_stat_activity` and the like.
+//!
+//! Tags are added by the [`StatementManager`](crate::database::stmt_manager::StatementManager) after parsing, so
+//! they never affect how statements are classified, and they're stripped before queries are saved to the history.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Every tag starts with this, which is how they're told apart from the user's own comments
+const TAG_PREFIX: &str = "/* pgpad ";
+
+pub const DEFAULT_TEMPLATE: &str =
+    "user={user} conn={connection} script={script} run={run_id} version={app_version}";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryTagSettings {
+    pub enabled: bool,
+    /// The text of the tag. `{user}`, `{connection}`, `{script}`, `{run_id}` and `{app_version}` are replaced
+    /// with their values, and other text is kept as is.
+    pub template: String,
+}
+
+impl Default for QueryTagSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            template: DEFAULT_TEMPLATE.to_owned(),
+        }
+    }
+}
+
+/// What the variables of a template are replaced with
+#[derive(Debug, Clone)]
+pub struct TagContext<'a> {
+    pub connection: &'a str,
+    /// The name of the script the query comes from, if any
+    pub script: Option<&'a str>,
+    pub run_id: Uuid,
+}
+
+/// The name of the user running pgpad, as the OS knows them
+fn os_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_owned())
+}
+
+/// Keeps values from ending the comment early, or from opening a nested one, which Postgres would never close
+fn sanitize(value: &str) -> String {
+    value
+        .replace("*/", "* /")
+        .replace("/*", "/ *")
+        .replace(|c: char| c.is_control(), " ")
+}
+
+/// Renders `template` into a tag, comment delimiters included
+pub fn render_tag(template: &str, context: &TagContext) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let value = match &rest[1..end] {
+            "user" => os_user(),
+            "connection" => context.connection.to_owned(),
+            "script" => context.script.unwrap_or("-").to_owned(),
+            "run_id" => context.run_id.to_string(),
+            "app_version" => env!("CARGO_PKG_VERSION").to_owned(),
+            _ => {
+                // Not a variable, keep the brace and look for the next one
+                text.push('{');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        text.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+
+    format!("{TAG_PREFIX}{} */", sanitize(text.trim()))
+}
+
+/// Length of the optimizer hint comment (e.g. `/*+ SeqScan(t) */`) the statement starts with, if any.
+/// Hints have to come first to be picked up, so tags go after them.
+fn leading_hint_len(statement: &str) -> Option<usize> {
+    if !statement.starts_with("/*+") {
+        return None;
+    }
+    statement.find("*/").map(|end| end + 2)
+}
+
+/// Prepends `tag` to the statement, after its optimizer hints if it has any.
+/// Tags left over from an earlier run (e.g. in a query copied from `pg_stat_activity`) are replaced.
+pub fn tag_statement(statement: &str, tag: &str) -> String {
+    let statement = &strip_tag(statement);
+    match leading_hint_len(statement) {
+        Some(hint_len) => {
+            let (hint, rest) = statement.split_at(hint_len);
+            format!("{hint} {tag}{rest}")
+        }
+        // An unterminated hint is left alone rather than risking turning the tag into a part of it
+        None if statement.starts_with("/*+") => statement.to_owned(),
+        None => format!("{tag} {statement}"),
+    }
+}
+
+/// Removes the tag added by [`tag_statement`], if any
+pub fn strip_tag(statement: &str) -> String {
+    let hint_len = leading_hint_len(statement).unwrap_or(0);
+    let (hint, rest) = statement.split_at(hint_len);
+    let rest = if hint_len > 0 {
+        rest.strip_prefix(' ').unwrap_or(rest)
+    } else {
+        rest
+    };
+
+    let Some(tag_end) = rest
+        .starts_with(TAG_PREFIX)
+        .then(|| rest.find("*/"))
+        .flatten()
+    else {
+        return statement.to_owned();
+    };
+
+    let untagged = &rest[tag_end + 2..];
+    if hint_len > 0 {
+        format!("{hint}{untagged}")
+    } else {
+        untagged.strip_prefix(' ').unwrap_or(untagged).to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> TagContext<'static> {
+        TagContext {
+            connection: "staging",
+            script: Some("q3-report.sql"),
+            run_id: Uuid::nil(),
+        }
+    }
+
+    #[test]
+    fn renders_templates() {
+        let tag = render_tag(
+            "conn={connection} script={script} run={run_id} {other}",
+            &context(),
+        );
+        assert_eq!(
+            tag,
+            format!(
+                "/* pgpad conn=staging script=q3-report.sql run={} {{other}} */",
+                Uuid::nil()
+            )
+        );
+
+        let sneaky = TagContext {
+            connection: "a */ DROP TABLE users; /* b\n",
+            script: None,
+            run_id: Uuid::nil(),
+        };
+        assert_eq!(
+            render_tag("conn={connection} script={script}", &sneaky),
+            "/* pgpad conn=a * / DROP TABLE users; / * b  script=- */"
+        );
+    }
+
+    #[test]
+    fn tags_round_trip() {
+        let tag = render_tag(DEFAULT_TEMPLATE, &context());
+
+        for statement in [
+            "SELECT 1",
+            "-- a comment\nSELECT 1",
+            "/* pgpad-ish, but the user's own */ SELECT 1",
+            "DO $$ BEGIN PERFORM 1; END $$",
+        ] {
+            let tagged = tag_statement(statement, &tag);
+            assert!(tagged.starts_with(TAG_PREFIX));
+            assert_eq!(strip_tag(&tagged), statement);
+            assert_eq!(strip_tag(statement), statement);
+        }
+
+        let retagged = tag_statement(&tag_statement("SELECT 1", &tag), &tag);
+        assert_eq!(retagged, format!("{tag} SELECT 1"));
+    }
+
+    #[test]
+    fn keeps_hints_first() {
+        let tag = render_tag("run={run_id}", &context());
+
+        let statement = "/*+ SeqScan(users) */ SELECT * FROM users";
+        let tagged = tag_statement(statement, &tag);
+        assert_eq!(
+            tagged,
+            format!("/*+ SeqScan(users) */ {tag} SELECT * FROM users")
+        );
+        assert_eq!(strip_tag(&tagged), statement);
+
+        let unterminated = "/*+ SeqScan(users) SELECT 1";
+        assert_eq!(tag_statement(unterminated, &tag), unterminated);
+    }
+}
@@ -0,0 +1,218 @@
+//! Reconnecting connections that dropped, waiting twice as long after each failed attempt.
+//!
+//! A connection has at most one reconnect loop. Disconnecting it by hand stops the loop, and so does connecting
+//! it by hand, which waits for the attempt in progress rather than opening a second client alongside it.
+
+use std::{sync::Arc, time::Duration};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectSettings {
+    pub enabled: bool,
+    /// How many attempts are made before giving up
+    pub max_retries: u32,
+    /// How long to wait before the first attempt. Each later attempt waits twice as long as the one before.
+    pub backoff_ms: u64,
+    /// The longest wait between attempts
+    pub max_backoff_ms: u64,
+}
+
+impl Default for ReconnectSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_retries: 5,
+            backoff_ms: 1000,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+impl ReconnectSettings {
+    /// How long to wait before an attempt, counting from 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        Duration::from_millis(
+            self.backoff_ms
+                .saturating_mul(factor)
+                .min(self.max_backoff_ms.max(self.backoff_ms)),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ReconnectStatus {
+    /// Waiting to make an attempt
+    Reconnecting {
+        attempt: u32,
+        max_retries: u32,
+        delay_ms: u64,
+    },
+    Reconnected,
+    /// Every attempt failed. The error is that of the last one, if it failed with one.
+    GaveUp {
+        error: Option<String>,
+    },
+}
+
+/// Sent as the connection-status event
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionStatusEvent {
+    pub connection_id: Uuid,
+    #[serde(flatten)]
+    pub status: ReconnectStatus,
+}
+
+#[derive(Debug, Default)]
+pub struct Reconnects {
+    /// Stops the loop of each connection being reconnected
+    running: DashMap<Uuid, CancellationToken>,
+    /// Held while a connection is being connected, by hand or by its reconnect loop
+    connecting: DashMap<Uuid, Arc<Mutex<()>>>,
+}
+
+impl Reconnects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a reconnect loop for a connection, unless it already has one. The loop is unregistered once the
+    /// guard is dropped.
+    pub fn start(&self, connection_id: Uuid) -> Option<ReconnectGuard<'_>> {
+        let stop = CancellationToken::new();
+        match self.running.entry(connection_id) {
+            Entry::Occupied(_) => None,
+            Entry::Vacant(entry) => {
+                entry.insert(stop.clone());
+                Some(ReconnectGuard {
+                    reconnects: self,
+                    connection_id,
+                    stop,
+                })
+            }
+        }
+    }
+
+    /// Stops the reconnect loop of a connection. Returns whether it had one.
+    pub fn stop(&self, connection_id: Uuid) -> bool {
+        match self.running.remove(&connection_id) {
+            Some((_, stop)) => {
+                stop.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_reconnecting(&self, connection_id: Uuid) -> bool {
+        self.running.contains_key(&connection_id)
+    }
+
+    /// Waits until nothing else is connecting the connection, and keeps others from doing so until the guard is
+    /// dropped
+    pub async fn lock(&self, connection_id: Uuid) -> OwnedMutexGuard<()> {
+        let lock = self.connecting.entry(connection_id).or_default().clone();
+        lock.lock_owned().await
+    }
+}
+
+pub struct ReconnectGuard<'a> {
+    reconnects: &'a Reconnects,
+    connection_id: Uuid,
+    stop: CancellationToken,
+}
+
+impl ReconnectGuard<'_> {
+    pub fn is_stopped(&self) -> bool {
+        self.stop.is_cancelled()
+    }
+
+    /// Waits for `delay`, returning false if the loop was stopped in the meantime
+    pub async fn wait(&self, delay: Duration) -> bool {
+        tokio::select! {
+            _ = self.stop.cancelled() => false,
+            _ = tokio::time::sleep(delay) => true,
+        }
+    }
+}
+
+impl Drop for ReconnectGuard<'_> {
+    fn drop(&mut self) {
+        // Stopping unregisters the loop, and a loop started after that is another's to unregister
+        if !self.stop.is_cancelled() {
+            self.reconnects.running.remove(&self.connection_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_delays_up_to_the_maximum() {
+        let settings = ReconnectSettings {
+            backoff_ms: 500,
+            max_backoff_ms: 3000,
+            ..Default::default()
+        };
+        let delays: Vec<_> = (1..=5)
+            .map(|attempt| settings.delay(attempt).as_millis())
+            .collect();
+        assert_eq!(delays, [500, 1000, 2000, 3000, 3000]);
+
+        // No overflow after many attempts
+        assert_eq!(settings.delay(100), Duration::from_millis(3000));
+    }
+
+    #[tokio::test]
+    async fn runs_one_loop_per_connection() {
+        let reconnects = Reconnects::new();
+        let connection_id = Uuid::new_v4();
+
+        let guard = reconnects.start(connection_id).unwrap();
+        assert!(reconnects.start(connection_id).is_none());
+        assert!(reconnects.is_reconnecting(connection_id));
+
+        assert!(reconnects.stop(connection_id));
+        assert!(guard.is_stopped());
+        assert!(!guard.wait(Duration::from_secs(60)).await);
+
+        // A loop started before the stopped one is dropped stays registered
+        let second = reconnects.start(connection_id).unwrap();
+        drop(guard);
+        assert!(reconnects.is_reconnecting(connection_id));
+        drop(second);
+        assert!(!reconnects.is_reconnecting(connection_id));
+        assert!(!reconnects.stop(connection_id));
+    }
+
+    #[test]
+    fn serializes_status_events() {
+        let event = ConnectionStatusEvent {
+            connection_id: Uuid::nil(),
+            status: ReconnectStatus::Reconnecting {
+                attempt: 2,
+                max_retries: 5,
+                delay_ms: 2000,
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "connection_id": Uuid::nil(),
+                "status": "reconnecting",
+                "attempt": 2,
+                "max_retries": 5,
+                "delay_ms": 2000,
+            })
+        );
+    }
+}
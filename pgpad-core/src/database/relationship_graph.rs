@@ -0,0 +1,185 @@
+//! The tables of a schema and the foreign keys between them, as a graph the frontend draws as an
+//! entity-relationship diagram. Built from the cached [`DatabaseSchema`], so it's as fresh as the schema is.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::database::types::{DatabaseSchema, ReferentialAction};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RelationshipGraph {
+    /// In the order of the schema's tables, by schema then by name
+    pub nodes: Vec<GraphNode>,
+    /// By the table they're from, then in the order of its foreign keys
+    pub edges: Vec<GraphEdge>,
+    /// The hash of the schema the graph was built from, see [`DatabaseSchema::hash`]
+    pub schema_hash: String,
+}
+
+/// A table. Partitioned tables are a single node, since their partitions aren't part of the schema.
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    /// The id of the table, see [`TableInfo::id`](crate::database::types::TableInfo::id)
+    pub id: String,
+    pub schema: String,
+    pub table: String,
+    pub primary_key: Vec<String>,
+    pub row_estimate: Option<i64>,
+}
+
+/// A foreign key, from the table that has it to the table it references
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    pub name: Option<String>,
+    pub source: EdgeEnd,
+    pub target: EdgeEnd,
+    pub on_delete: ReferentialAction,
+    pub on_update: ReferentialAction,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EdgeEnd {
+    /// The id of the table's node
+    pub node: String,
+    pub schema: String,
+    pub table: String,
+    /// Paired with the columns of the other end by position
+    pub columns: Vec<String>,
+}
+
+impl RelationshipGraph {
+    /// Foreign keys to tables the schema doesn't have are left out, since there's no node for them to point to
+    pub fn new(schema: &DatabaseSchema) -> Self {
+        let ids: HashMap<(&str, &str), &str> = schema
+            .tables
+            .iter()
+            .map(|table| {
+                (
+                    (table.schema.as_str(), table.name.as_str()),
+                    table.id.as_str(),
+                )
+            })
+            .collect();
+
+        let nodes = schema
+            .tables
+            .iter()
+            .map(|table| GraphNode {
+                id: table.id.clone(),
+                schema: table.schema.clone(),
+                table: table.name.clone(),
+                primary_key: table.primary_key.clone(),
+                row_estimate: table.row_estimate,
+            })
+            .collect();
+
+        let edges = schema
+            .tables
+            .iter()
+            .flat_map(|table| {
+                table.foreign_keys.iter().filter_map(|key| {
+                    let target = ids.get(&(
+                        key.referenced_schema.as_str(),
+                        key.referenced_table.as_str(),
+                    ))?;
+                    Some(GraphEdge {
+                        name: key.name.clone(),
+                        source: EdgeEnd {
+                            node: table.id.clone(),
+                            schema: table.schema.clone(),
+                            table: table.name.clone(),
+                            columns: key.columns.clone(),
+                        },
+                        target: EdgeEnd {
+                            node: (*target).to_owned(),
+                            schema: key.referenced_schema.clone(),
+                            table: key.referenced_table.clone(),
+                            columns: key.referenced_columns.clone(),
+                        },
+                        on_delete: key.on_delete,
+                        on_update: key.on_update,
+                    })
+                })
+            })
+            .collect();
+
+        Self {
+            nodes,
+            edges,
+            schema_hash: schema.hash.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::types::{ForeignKey, TableInfo};
+
+    fn table(name: &str, primary_key: &[&str], foreign_keys: Vec<ForeignKey>) -> TableInfo {
+        TableInfo {
+            name: name.to_owned(),
+            schema: "public".to_owned(),
+            primary_key: primary_key.iter().map(|c| c.to_string()).collect(),
+            foreign_keys,
+            row_estimate: Some(10),
+            ..Default::default()
+        }
+    }
+
+    fn key(name: &str, column: &str, referenced_table: &str) -> ForeignKey {
+        ForeignKey {
+            columns: vec![column.to_owned()],
+            referenced_schema: "public".to_owned(),
+            referenced_table: referenced_table.to_owned(),
+            referenced_columns: vec!["id".to_owned()],
+            name: Some(name.to_owned()),
+            on_delete: ReferentialAction::Cascade,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn links_tables_by_their_foreign_keys() {
+        let schema = DatabaseSchema::new(
+            vec![
+                table("customers", &["id"], Vec::new()),
+                table(
+                    "orders",
+                    &["id"],
+                    vec![
+                        key("orders_customer_fkey", "customer_id", "customers"),
+                        // Dropped since, with no such table in the schema
+                        key("orders_coupon_fkey", "coupon_id", "coupons"),
+                    ],
+                ),
+            ],
+            vec!["public".to_owned()],
+            Vec::new(),
+        );
+
+        let graph = schema.relationship_graph();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.nodes[1].table, "orders");
+        assert_eq!(graph.nodes[1].primary_key, ["id"]);
+        assert_eq!(graph.nodes[1].row_estimate, Some(10));
+        assert_eq!(graph.schema_hash, schema.hash);
+
+        let [edge] = graph.edges.as_slice() else {
+            panic!("Expected a single edge, got {:?}", graph.edges);
+        };
+        assert_eq!(edge.name.as_deref(), Some("orders_customer_fkey"));
+        assert_eq!(edge.source.node, graph.nodes[1].id);
+        assert_eq!(edge.source.columns, ["customer_id"]);
+        assert_eq!(edge.target.node, graph.nodes[0].id);
+        assert_eq!(edge.target.columns, ["id"]);
+        assert_eq!(edge.on_delete, ReferentialAction::Cascade);
+        assert_eq!(edge.on_update, ReferentialAction::NoAction);
+
+        // Built once, and rebuilt for an updated schema
+        assert!(std::sync::Arc::ptr_eq(&graph, &schema.relationship_graph()));
+        let updated = schema.with_row_estimate("public", "orders", 42).unwrap();
+        assert_eq!(updated.relationship_graph().nodes[1].row_estimate, Some(42));
+    }
+}
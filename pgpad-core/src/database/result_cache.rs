@@ -0,0 +1,321 @@
+//! The pages of a statement's result. Once they take more memory than the [`ResultCacheSettings`] allow, the oldest
+//! ones are moved to a file of the statement's, in the data directory, and read back from it when fetched. The file
+//! goes when the statement does, and files left behind by a crash are removed on startup.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use uuid::Uuid;
+
+use crate::{
+    database::types::{Page, QueryId},
+    Error,
+};
+
+/// Where spilled pages go, in the data directory
+pub const SPILL_DIR: &str = "result-spill";
+const SPILL_EXTENSION: &str = "pages";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResultCacheSettings {
+    /// How many bytes of pages a result keeps in memory before moving the oldest ones to disk. None keeps them all
+    /// in memory.
+    pub spill_threshold_bytes: Option<usize>,
+}
+
+/// Where and past which size the pages of a result are spilled
+#[derive(Debug, Clone)]
+pub struct SpillConfig {
+    pub dir: PathBuf,
+    pub threshold_bytes: usize,
+}
+
+#[derive(Debug)]
+struct SpillFile {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// Offset and length of each page moved to the file, which are always the first pages of the result
+    extents: Vec<(u64, usize)>,
+    len: u64,
+}
+
+impl SpillFile {
+    fn create(dir: &Path, query_id: QueryId) -> Result<Self, Error> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(format!("{query_id}-{}.{SPILL_EXTENSION}", Uuid::new_v4()));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            extents: Vec::new(),
+            len: 0,
+        })
+    }
+
+    fn append(&mut self, page: &Page) -> Result<(), Error> {
+        let file = self.file.get_mut().unwrap();
+        file.seek(SeekFrom::Start(self.len))
+            .and_then(|_| file.write_all(page.get().as_bytes()))
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+
+        self.extents.push((self.len, page.get().len()));
+        self.len += page.get().len() as u64;
+        Ok(())
+    }
+
+    fn read(&self, idx: usize) -> Result<Page, Error> {
+        let (offset, len) = self.extents[idx];
+        let mut json = vec![0; len];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(offset))
+                .and_then(|_| file.read_exact(&mut json))
+                .with_context(|| format!("Failed to read {}", self.path.display()))?;
+        }
+
+        let json = String::from_utf8(json).context("A spilled page isn't UTF-8")?;
+        Ok(RawValue::from_string(json)?)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!("Failed to remove {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// The pages of a result, in order
+#[derive(Debug, Default)]
+pub struct PageStore {
+    query_id: QueryId,
+    /// The pages after those spilled
+    in_memory: VecDeque<Page>,
+    /// Bytes of the pages in memory
+    memory_bytes: usize,
+    spill: Option<SpillConfig>,
+    spilled: Option<SpillFile>,
+    /// How many rows there are up to the end of each page, to find the page of a row without reading the others
+    row_ends: Vec<usize>,
+}
+
+impl PageStore {
+    /// Without a `spill` config every page stays in memory
+    pub fn new(query_id: QueryId, spill: Option<SpillConfig>) -> Self {
+        Self {
+            query_id,
+            spill,
+            ..Default::default()
+        }
+    }
+
+    /// Pages that stay in memory, e.g. those of derived results
+    pub fn from_pages(pages: Vec<Page>) -> Result<Self, Error> {
+        let mut store = Self::default();
+        for page in pages {
+            let rows = count_rows(&page)?;
+            store.memory_bytes += page.get().len();
+            store.in_memory.push_back(page);
+            store.end_page(rows);
+        }
+        Ok(store)
+    }
+
+    pub fn len(&self) -> usize {
+        self.spilled_count() + self.in_memory.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether some of the pages were moved to disk
+    pub fn is_spilled(&self) -> bool {
+        self.spilled_count() > 0
+    }
+
+    fn spilled_count(&self) -> usize {
+        self.spilled.as_ref().map_or(0, |file| file.extents.len())
+    }
+
+    /// Rows in all of the pages
+    pub fn row_count(&self) -> usize {
+        self.row_ends.last().copied().unwrap_or(0)
+    }
+
+    /// The page a row is on, and its index within the page. None if there's no such row yet.
+    pub fn locate_row(&self, row_index: usize) -> Option<(usize, usize)> {
+        let page_idx = self.row_ends.partition_point(|&end| end <= row_index);
+        self.row_ends.get(page_idx)?;
+        let page_start = page_idx
+            .checked_sub(1)
+            .map_or(0, |prev| self.row_ends[prev]);
+        Some((page_idx, row_index - page_start))
+    }
+
+    fn end_page(&mut self, rows: usize) {
+        let row_count = self.row_count();
+        self.row_ends.push(row_count + rows);
+    }
+
+    /// Adds a page of `rows` rows after the others, then spills the oldest pages in memory if they're over the
+    /// threshold. The newest page always stays in memory. If spilling fails, pages are kept in memory from then on.
+    pub fn push(&mut self, page: Page, rows: usize) {
+        self.memory_bytes += page.get().len();
+        self.in_memory.push_back(page);
+        self.end_page(rows);
+
+        let Some(threshold_bytes) = self.spill.as_ref().map(|spill| spill.threshold_bytes) else {
+            return;
+        };
+        while self.memory_bytes > threshold_bytes && self.in_memory.len() > 1 {
+            if let Err(err) = self.spill_oldest() {
+                log::error!("Failed to move a page of results to disk: {err}");
+                self.spill = None;
+                return;
+            }
+        }
+    }
+
+    fn spill_oldest(&mut self) -> Result<(), Error> {
+        let file = match &mut self.spilled {
+            Some(file) => file,
+            None => {
+                let dir = &self.spill.as_ref().expect("spilling is enabled").dir;
+                self.spilled.insert(SpillFile::create(dir, self.query_id)?)
+            }
+        };
+
+        let page = self.in_memory.front().expect("a page is in memory");
+        file.append(page)?;
+        self.memory_bytes -= page.get().len();
+        self.in_memory.pop_front();
+        Ok(())
+    }
+
+    /// A page, read back from disk if it was spilled. None if there's no such page yet.
+    pub fn get(&self, idx: usize) -> Result<Option<Page>, Error> {
+        let spilled = self.spilled_count();
+        if idx < spilled {
+            let file = self.spilled.as_ref().expect("pages were spilled");
+            return file.read(idx).map(Some);
+        }
+        Ok(self.in_memory.get(idx - spilled).cloned())
+    }
+}
+
+/// Rows in a page, for pages that don't come with their count
+pub fn count_rows(page: &Page) -> Result<usize, Error> {
+    let rows: Vec<serde::de::IgnoredAny> = serde_json::from_str(page.get())?;
+    Ok(rows.len())
+}
+
+/// Removes the pages spilled by earlier runs of the app, which a crash may have left behind
+pub fn remove_orphans(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == SPILL_EXTENSION) {
+            if let Err(err) = fs::remove_file(&path) {
+                log::warn!("Failed to remove {}: {err}", path.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(rows: &str) -> Page {
+        RawValue::from_string(rows.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn spills_the_oldest_pages() {
+        let dir = std::env::temp_dir().join(format!("pgpad-spill-{}", Uuid::new_v4()));
+        let mut store = PageStore::new(
+            7,
+            Some(SpillConfig {
+                dir: dir.clone(),
+                threshold_bytes: 20,
+            }),
+        );
+
+        store.push(page("[[1,\"one\"]]"), 1);
+        assert!(!store.is_spilled());
+        store.push(page("[[2,\"two\"]]"), 1);
+        store.push(page("[[3,\"three\"]]"), 1);
+        assert!(store.is_spilled());
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.in_memory.len(), 1);
+
+        let pages: Vec<_> = (0..4)
+            .map(|idx| store.get(idx).unwrap().map(|page| page.get().to_owned()))
+            .collect();
+        assert_eq!(
+            pages,
+            [
+                Some("[[1,\"one\"]]".to_owned()),
+                Some("[[2,\"two\"]]".to_owned()),
+                Some("[[3,\"three\"]]".to_owned()),
+                None
+            ]
+        );
+
+        // The file goes with the store
+        let files = || fs::read_dir(&dir).unwrap().count();
+        assert_eq!(files(), 1);
+        drop(store);
+        assert_eq!(files(), 0);
+
+        fs::write(dir.join("0-orphan.pages"), "[]").unwrap();
+        fs::write(dir.join("unrelated.txt"), "").unwrap();
+        remove_orphans(&dir);
+        assert_eq!(files(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn locates_rows_across_pages() {
+        let store =
+            PageStore::from_pages(vec![page("[[1],[2]]"), page("[]"), page("[[3],[4],[5]]")])
+                .unwrap();
+        assert_eq!(store.row_count(), 5);
+        assert_eq!(store.locate_row(0), Some((0, 0)));
+        assert_eq!(store.locate_row(1), Some((0, 1)));
+        assert_eq!(store.locate_row(2), Some((2, 0)));
+        assert_eq!(store.locate_row(4), Some((2, 2)));
+        assert_eq!(store.locate_row(5), None);
+        assert_eq!(PageStore::default().locate_row(0), None);
+    }
+
+    #[test]
+    fn keeps_pages_in_memory_without_a_threshold() {
+        let mut store = PageStore::new(0, None);
+        for idx in 0..100 {
+            store.push(page(&format!("[[{idx}]]")), 1);
+        }
+        assert!(!store.is_spilled());
+        assert_eq!(store.get(99).unwrap().unwrap().get(), "[[99]]");
+    }
+}
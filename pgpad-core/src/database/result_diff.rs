@@ -0,0 +1,426 @@
+//! Comparing the cached results of two queries, e.g. the same SELECT before and after a data fix, by pairing their
+//! rows on key columns.
+//!
+//! Rows are indexed by a hash of their key, so only the indexes come on top of the results themselves. Keys are
+//! compared like `IS NOT DISTINCT FROM`, NULL matching NULL. Keys found more than once in either result can't be
+//! paired reliably, so their rows are listed as duplicates rather than compared.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use anyhow::{bail, ensure};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::database::sqlite::join::CachedResult;
+
+/// The first column of the diff, telling what happened to each row
+pub const CHANGE_COLUMN: &str = "_change";
+
+/// The result of diffing two queries
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResultDiff {
+    /// The rows that differ, registered in the StatementManager as their own query. None if the results couldn't
+    /// be compared.
+    pub query_id: Option<usize>,
+    pub added: usize,
+    pub removed: usize,
+    /// Changed columns are `{"old": ..., "new": ...}` in the diff
+    pub changed: usize,
+    pub unchanged: usize,
+    /// Keys found more than once in either result
+    pub duplicate_keys: usize,
+    /// Set if the results don't have the same columns
+    pub incomparable: Option<IncomparableColumns>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IncomparableColumns {
+    pub only_in_a: Vec<String>,
+    pub only_in_b: Vec<String>,
+}
+
+pub struct DiffedRows {
+    /// [`CHANGE_COLUMN`], then the columns of the first result
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// Without a query id, which is up to the caller
+    pub summary: ResultDiff,
+}
+
+/// Rows with the same key, by the hash of their key. Distinct keys with the same hash get a group each.
+struct KeyIndex {
+    groups: HashMap<u64, Vec<Vec<usize>>>,
+}
+
+impl KeyIndex {
+    fn new(rows: &[Vec<Value>], key: &[usize]) -> Self {
+        let mut groups: HashMap<u64, Vec<Vec<usize>>> = HashMap::new();
+        for (idx, row) in rows.iter().enumerate() {
+            let bucket = groups.entry(hash_key(row, key)).or_default();
+            match bucket
+                .iter()
+                .position(|group| same_key(&rows[group[0]], key, row, key))
+            {
+                Some(group) => bucket[group].push(idx),
+                None => bucket.push(vec![idx]),
+            }
+        }
+        Self { groups }
+    }
+
+    /// The rows of `rows` whose key (at `key`) matches that of `other` (at `other_key`)
+    fn find(
+        &self,
+        rows: &[Vec<Value>],
+        key: &[usize],
+        other: &[Value],
+        other_key: &[usize],
+    ) -> Option<&[usize]> {
+        self.groups
+            .get(&hash_key(other, other_key))?
+            .iter()
+            .find(|group| same_key(&rows[group[0]], key, other, other_key))
+            .map(Vec::as_slice)
+    }
+}
+
+fn hash_key(row: &[Value], key: &[usize]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for &idx in key {
+        hash_value(&row[idx], &mut hasher);
+    }
+    hasher.finish()
+}
+
+fn hash_value(value: &Value, hasher: &mut DefaultHasher) {
+    match value {
+        Value::Null => 0u8.hash(hasher),
+        Value::Bool(b) => (1u8, b).hash(hasher),
+        // As written, since equal numbers are equal as JSON only when written the same way
+        Value::Number(n) => (2u8, n.to_string()).hash(hasher),
+        Value::String(s) => (3u8, s).hash(hasher),
+        Value::Array(values) => {
+            (4u8, values.len()).hash(hasher);
+            for value in values {
+                hash_value(value, hasher);
+            }
+        }
+        // Objects are equal whatever the order of their fields, which they may keep
+        Value::Object(fields) => (5u8, fields.len()).hash(hasher),
+    }
+}
+
+fn same_key(a: &[Value], a_key: &[usize], b: &[Value], b_key: &[usize]) -> bool {
+    a_key
+        .iter()
+        .zip(b_key)
+        .all(|(&a_idx, &b_idx)| a[a_idx] == b[b_idx])
+}
+
+/// Where each column of `a` is in `b`, columns being matched by name and in order when a name is repeated. The
+/// columns that don't match if some don't.
+fn match_columns(a: &[String], b: &[String]) -> Result<Vec<usize>, IncomparableColumns> {
+    let mut taken = vec![false; b.len()];
+    let mut positions = Vec::with_capacity(a.len());
+    let mut only_in_a = Vec::new();
+
+    for column in a {
+        match (0..b.len()).find(|&idx| !taken[idx] && b[idx] == *column) {
+            Some(idx) => {
+                taken[idx] = true;
+                positions.push(idx);
+            }
+            None => only_in_a.push(column.clone()),
+        }
+    }
+
+    let only_in_b: Vec<String> = b
+        .iter()
+        .zip(&taken)
+        .filter(|(_, taken)| !**taken)
+        .map(|(column, _)| column.clone())
+        .collect();
+    if only_in_a.is_empty() && only_in_b.is_empty() {
+        Ok(positions)
+    } else {
+        Err(IncomparableColumns {
+            only_in_a,
+            only_in_b,
+        })
+    }
+}
+
+/// Pairs the rows of `a` and `b` on `key_columns`, listing the rows of `a` that were removed or changed, in their
+/// order, then the rows added in `b`. Rows of duplicate keys are listed as `duplicate_in_a` and `duplicate_in_b`.
+pub fn diff_rows(
+    a: CachedResult,
+    b: CachedResult,
+    key_columns: &[String],
+) -> anyhow::Result<DiffedRows> {
+    ensure!(
+        !key_columns.is_empty(),
+        "Pick the columns rows are paired on"
+    );
+
+    let mut columns = vec![CHANGE_COLUMN.to_owned()];
+    let b_positions = match match_columns(&a.columns, &b.columns) {
+        Ok(positions) => positions,
+        Err(incomparable) => {
+            columns.extend(a.columns);
+            return Ok(DiffedRows {
+                columns,
+                rows: Vec::new(),
+                summary: ResultDiff {
+                    incomparable: Some(incomparable),
+                    ..Default::default()
+                },
+            });
+        }
+    };
+
+    let mut a_key = Vec::with_capacity(key_columns.len());
+    for column in key_columns {
+        let mut matching = a.columns.iter().enumerate().filter(|(_, c)| *c == column);
+        match (matching.next(), matching.next()) {
+            (Some((idx, _)), None) => a_key.push(idx),
+            (Some(_), Some(_)) => {
+                bail!("Column {column} is ambiguous, since there's more than one")
+            }
+            (None, _) => bail!("Column {column} not found"),
+        }
+    }
+    let b_key: Vec<usize> = a_key.iter().map(|&idx| b_positions[idx]).collect();
+
+    let a_index = KeyIndex::new(&a.rows, &a_key);
+    let b_index = KeyIndex::new(&b.rows, &b_key);
+    let mut summary = ResultDiff::default();
+    let mut rows = Vec::new();
+
+    let labelled = |change: &str, values: Vec<Value>| {
+        let mut row = Vec::with_capacity(values.len() + 1);
+        row.push(Value::from(change));
+        row.extend(values);
+        row
+    };
+    // In the order of `a`'s columns
+    let b_values =
+        |row: &[Value]| -> Vec<Value> { b_positions.iter().map(|&idx| row[idx].clone()).collect() };
+
+    for (idx, row) in a.rows.iter().enumerate() {
+        let a_group = a_index
+            .find(&a.rows, &a_key, row, &a_key)
+            .expect("rows are in their own index");
+        let b_group = b_index.find(&b.rows, &b_key, row, &a_key);
+
+        if a_group.len() > 1 || b_group.is_some_and(|group| group.len() > 1) {
+            if a_group[0] == idx {
+                summary.duplicate_keys += 1;
+            }
+            rows.push(labelled("duplicate_in_a", row.clone()));
+            continue;
+        }
+        let Some(&[b_idx]) = b_group else {
+            summary.removed += 1;
+            rows.push(labelled("removed", row.clone()));
+            continue;
+        };
+
+        let new = b_values(&b.rows[b_idx]);
+        if *row == new {
+            summary.unchanged += 1;
+            continue;
+        }
+        summary.changed += 1;
+        let values = row
+            .iter()
+            .zip(new)
+            .map(|(old, new)| {
+                if *old == new {
+                    new
+                } else {
+                    json!({ "old": old, "new": new })
+                }
+            })
+            .collect();
+        rows.push(labelled("changed", values));
+    }
+
+    for (idx, row) in b.rows.iter().enumerate() {
+        let b_group = b_index
+            .find(&b.rows, &b_key, row, &b_key)
+            .expect("rows are in their own index");
+        let a_group = a_index.find(&a.rows, &a_key, row, &b_key);
+
+        match a_group {
+            // Paired, or listed with the duplicates of `a`
+            Some(a_group) if a_group.len() == 1 && b_group.len() == 1 => {}
+            Some(_) => rows.push(labelled("duplicate_in_b", b_values(row))),
+            None if b_group.len() > 1 => {
+                if b_group[0] == idx {
+                    summary.duplicate_keys += 1;
+                }
+                rows.push(labelled("duplicate_in_b", b_values(row)));
+            }
+            None => {
+                summary.added += 1;
+                rows.push(labelled("added", b_values(row)));
+            }
+        }
+    }
+
+    columns.extend(a.columns);
+    Ok(DiffedRows {
+        columns,
+        rows,
+        summary,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(columns: &[&str], rows: Vec<Value>) -> CachedResult {
+        CachedResult {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            column_types: Vec::new(),
+            rows: rows
+                .into_iter()
+                .map(|row| serde_json::from_value(row).unwrap())
+                .collect(),
+        }
+    }
+
+    fn key(columns: &[&str]) -> Vec<String> {
+        columns.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn lists_added_removed_and_changed_rows() {
+        let before = result(
+            &["id", "name", "total"],
+            vec![
+                json!([1, "ada", 10]),
+                json!([2, "bob", 20]),
+                json!([3, "cy", 30]),
+                json!([null, "nobody", 0]),
+            ],
+        );
+        // Same columns, in another order
+        let after = result(
+            &["total", "id", "name"],
+            vec![
+                json!([10, 1, "ada"]),
+                json!([25, 2, "bob"]),
+                json!([40, 4, "di"]),
+                json!([1, null, "nobody"]),
+            ],
+        );
+
+        let diff = diff_rows(before, after, &key(&["id"])).unwrap();
+        assert_eq!(diff.columns, ["_change", "id", "name", "total"]);
+        assert_eq!(
+            diff.rows,
+            [
+                json!(["changed", 2, "bob", {"old": 20, "new": 25}]),
+                json!(["removed", 3, "cy", 30]),
+                // NULL keys pair up
+                json!(["changed", null, "nobody", {"old": 0, "new": 1}]),
+                json!(["added", 4, "di", 40]),
+            ]
+            .map(|row| serde_json::from_value::<Vec<Value>>(row).unwrap())
+        );
+        let summary = diff.summary;
+        assert_eq!(
+            (
+                summary.added,
+                summary.removed,
+                summary.changed,
+                summary.unchanged
+            ),
+            (1, 1, 2, 1)
+        );
+        assert_eq!(summary.duplicate_keys, 0);
+        assert!(summary.incomparable.is_none());
+    }
+
+    #[test]
+    fn flags_duplicate_keys_instead_of_pairing_them() {
+        let before = result(
+            &["id", "sku", "qty"],
+            vec![
+                json!([1, "a", 1]),
+                json!([1, "a", 2]),
+                json!([2, "b", 1]),
+                json!([3, "c", 1]),
+            ],
+        );
+        let after = result(
+            &["id", "sku", "qty"],
+            vec![
+                json!([1, "a", 3]),
+                json!([2, "b", 1]),
+                json!([3, "c", 1]),
+                json!([3, "c", 2]),
+                json!([4, "d", 1]),
+                json!([4, "d", 1]),
+            ],
+        );
+
+        let diff = diff_rows(before, after, &key(&["id", "sku"])).unwrap();
+        let changes: Vec<&str> = diff
+            .rows
+            .iter()
+            .map(|row| row[0].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "duplicate_in_a",
+                "duplicate_in_a",
+                "duplicate_in_a",
+                "duplicate_in_b",
+                "duplicate_in_b",
+                "duplicate_in_b",
+                "duplicate_in_b",
+                "duplicate_in_b",
+            ]
+        );
+        assert_eq!(diff.summary.duplicate_keys, 3);
+        assert_eq!(diff.summary.unchanged, 1);
+        assert_eq!(
+            (
+                diff.summary.added,
+                diff.summary.removed,
+                diff.summary.changed
+            ),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn reports_different_columns_as_incomparable() {
+        let before = result(&["id", "name"], vec![json!([1, "ada"])]);
+        let after = result(&["id", "full_name"], vec![json!([1, "ada"])]);
+
+        let diff = diff_rows(before, after, &key(&["id"])).unwrap();
+        assert!(diff.rows.is_empty());
+        assert_eq!(
+            diff.summary.incomparable,
+            Some(IncomparableColumns {
+                only_in_a: vec!["name".to_owned()],
+                only_in_b: vec!["full_name".to_owned()],
+            })
+        );
+
+        let before = result(&["id", "id"], vec![json!([1, 2])]);
+        let after = result(&["id", "id"], vec![json!([1, 2])]);
+        assert!(diff_rows(before, after, &key(&["id"])).is_err());
+        let before = result(&["id"], vec![]);
+        let after = result(&["id"], vec![]);
+        assert!(diff_rows(before, after, &key(&["missing"])).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+//! Detection and normalization of the typographic characters SQL picks up when it's pasted from word processors,
+//! chat apps or web pages: smart quotes, non-breaking spaces and zero-width characters. Databases reject them
+//! with baffling errors like `syntax error at or near "’"`.
+//!
+//! Only characters in the SQL itself are normalized. The ones in string literals, quoted identifiers and comments
+//! may be intentional, so they're reported but left alone.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharacterContext {
+    Code,
+    /// A string literal or quoted identifier
+    Literal,
+    Comment,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SuspiciousCharacter {
+    /// Position in the original text, in UTF-16 code units like editor positions
+    pub offset: usize,
+    pub found: char,
+    /// e.g. "U+2019"
+    pub code_point: String,
+    /// What the character is replaced with, empty for zero-width characters
+    pub replacement: &'static str,
+    pub context: CharacterContext,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SanitizedSql {
+    pub text: String,
+    /// The characters that were replaced, with offsets into the original text so that the editor can apply
+    /// the replacements as edits of its buffer
+    pub replacements: Vec<SuspiciousCharacter>,
+    /// Characters left alone because they're in literals or comments
+    pub kept: Vec<SuspiciousCharacter>,
+}
+
+fn replacement_for(c: char) -> Option<&'static str> {
+    let replacement = match c {
+        '\u{2018}' | '\u{2019}' => "'",
+        '\u{201C}' | '\u{201D}' => "\"",
+        '\u{00A0}' | '\u{2007}' | '\u{202F}' => " ",
+        '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}' => "",
+        _ => return None,
+    };
+    Some(replacement)
+}
+
+enum ScanState {
+    Code,
+    SingleQuoted,
+    DoubleQuoted,
+    LineComment,
+    BlockComment {
+        depth: usize,
+    },
+    /// Postgres' `$tag$ ... $tag$` strings
+    DollarQuoted {
+        tag: String,
+    },
+}
+
+/// The `$tag$` starting at `s`, if any. `$1` placeholders aren't tags.
+pub(crate) fn dollar_tag(s: &str) -> Option<&str> {
+    let rest = s.strip_prefix('$')?;
+    let end = rest.find('$')?;
+    let tag = &rest[..end];
+
+    let valid = tag
+        .chars()
+        .enumerate()
+        .all(|(i, c)| c == '_' || c.is_alphabetic() || (i > 0 && c.is_ascii_digit()));
+    valid.then_some(&s[..end + 2])
+}
+
+/// Finds the suspicious characters in `sql`, and where they are
+pub fn find_suspicious_characters(sql: &str) -> Vec<SuspiciousCharacter> {
+    let mut found = Vec::new();
+    let mut state = ScanState::Code;
+    let mut offset = 0;
+    // Bytes of a multi-character token (e.g. `--` or `$tag$`) left to skip
+    let mut skip_until = 0;
+
+    for (i, c) in sql.char_indices() {
+        let char_offset = offset;
+        offset += c.len_utf16();
+
+        if i < skip_until {
+            continue;
+        }
+        let rest = &sql[i..];
+
+        let context = match &state {
+            ScanState::Code => CharacterContext::Code,
+            ScanState::SingleQuoted | ScanState::DoubleQuoted | ScanState::DollarQuoted { .. } => {
+                CharacterContext::Literal
+            }
+            ScanState::LineComment | ScanState::BlockComment { .. } => CharacterContext::Comment,
+        };
+
+        if let Some(replacement) = replacement_for(c) {
+            found.push(SuspiciousCharacter {
+                offset: char_offset,
+                found: c,
+                code_point: format!("U+{:04X}", c as u32),
+                replacement,
+                context,
+            });
+            continue;
+        }
+
+        state = match state {
+            ScanState::Code => match c {
+                '\'' => ScanState::SingleQuoted,
+                '"' => ScanState::DoubleQuoted,
+                '-' if rest.starts_with("--") => {
+                    skip_until = i + 2;
+                    ScanState::LineComment
+                }
+                '/' if rest.starts_with("/*") => {
+                    skip_until = i + 2;
+                    ScanState::BlockComment { depth: 1 }
+                }
+                '$' => match dollar_tag(rest) {
+                    Some(tag) => {
+                        skip_until = i + tag.len();
+                        ScanState::DollarQuoted {
+                            tag: tag.to_owned(),
+                        }
+                    }
+                    None => ScanState::Code,
+                },
+                _ => ScanState::Code,
+            },
+            // Doubled quotes are escaped quotes, which the next iteration skips
+            ScanState::SingleQuoted if c == '\'' && rest.starts_with("''") => {
+                skip_until = i + 2;
+                ScanState::SingleQuoted
+            }
+            ScanState::SingleQuoted if c == '\'' => ScanState::Code,
+            ScanState::DoubleQuoted if c == '"' && rest.starts_with("\"\"") => {
+                skip_until = i + 2;
+                ScanState::DoubleQuoted
+            }
+            ScanState::DoubleQuoted if c == '"' => ScanState::Code,
+            ScanState::LineComment if c == '\n' => ScanState::Code,
+            ScanState::BlockComment { depth } if rest.starts_with("/*") => {
+                skip_until = i + 2;
+                ScanState::BlockComment { depth: depth + 1 }
+            }
+            ScanState::BlockComment { depth } if rest.starts_with("*/") => {
+                skip_until = i + 2;
+                match depth {
+                    1 => ScanState::Code,
+                    depth => ScanState::BlockComment { depth: depth - 1 },
+                }
+            }
+            ScanState::DollarQuoted { tag } if rest.starts_with(&tag) => {
+                skip_until = i + tag.len();
+                ScanState::Code
+            }
+            state => state,
+        };
+    }
+
+    found
+}
+
+/// Replaces the suspicious characters found outside literals and comments
+pub fn sanitize_sql(sql: &str) -> SanitizedSql {
+    let (replacements, kept): (Vec<_>, Vec<_>) = find_suspicious_characters(sql)
+        .into_iter()
+        .partition(|c| c.context == CharacterContext::Code);
+
+    let mut text = String::with_capacity(sql.len());
+    let mut offset = 0;
+    let mut pending = replacements.iter().peekable();
+
+    for c in sql.chars() {
+        match pending.peek() {
+            Some(replacement) if replacement.offset == offset => {
+                text.push_str(replacement.replacement);
+                pending.next();
+            }
+            _ => text.push(c),
+        }
+        offset += c.len_utf16();
+    }
+
+    SanitizedSql {
+        text,
+        replacements,
+        kept,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_characters_outside_literals() {
+        let sql = "SELECT\u{00A0}* FROM “users” WHERE name = ’it’’s’ AND note = 'don’t'\u{200B}; -- it’s fine";
+        let sanitized = sanitize_sql(sql);
+
+        assert_eq!(
+            sanitized.text,
+            "SELECT * FROM \"users\" WHERE name = 'it''s' AND note = 'don’t'; -- it’s fine"
+        );
+
+        let replaced: Vec<_> = sanitized.replacements.iter().map(|c| c.found).collect();
+        assert_eq!(
+            replaced,
+            ['\u{00A0}', '“', '”', '’', '’', '’', '’', '\u{200B}']
+        );
+        assert_eq!(sanitized.replacements[0].offset, 6);
+        assert_eq!(sanitized.replacements[0].code_point, "U+00A0");
+
+        let kept: Vec<_> = sanitized.kept.iter().map(|c| c.context).collect();
+        assert_eq!(kept, [CharacterContext::Literal, CharacterContext::Comment]);
+    }
+
+    #[test]
+    fn offsets_match_editor_positions() {
+        // The emoji takes two UTF-16 code units, like in the editor's buffer
+        let sql = "SELECT '😀' AS a,\u{00A0}’b’";
+        let found = find_suspicious_characters(sql);
+        let offsets: Vec<_> = found.iter().map(|c| c.offset).collect();
+
+        let utf16: Vec<u16> = sql.encode_utf16().collect();
+        for c in &found {
+            assert_eq!(
+                char::decode_utf16([utf16[c.offset]])
+                    .next()
+                    .unwrap()
+                    .unwrap(),
+                c.found
+            );
+        }
+        assert_eq!(offsets, [17, 18, 20]);
+    }
+
+    #[test]
+    fn skips_dollar_quoted_strings_and_block_comments() {
+        let sql = "SELECT $1, $body$ it’s $body$, /* a /* nested ’ */ comment’ */ $$’$$";
+        let found = find_suspicious_characters(sql);
+
+        assert!(found.iter().all(|c| c.context != CharacterContext::Code));
+        assert_eq!(found.len(), 4);
+        assert_eq!(sanitize_sql(sql).text, sql);
+    }
+}
@@ -0,0 +1,416 @@
+//! Queries run on a schedule in the background, e.g. to check every few minutes that a table has no orphaned rows.
+//!
+//! The frontends tick every [`SCHEDULER_TICK`], running the schedules that are due through their own
+//! [`StatementManager`], so that they don't replace the results shown in the editor. Runs on connections that
+//! aren't connected are skipped rather than connecting without being asked to.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Datelike, TimeZone, Timelike};
+use dashmap::DashSet;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+use crate::{
+    database::{stmt_manager::StatementManager, types::QueryId},
+    Error,
+};
+
+/// How often the frontends look for schedules that are due. Cron schedules are due at most once a minute, so this
+/// has to be shorter than that.
+pub const SCHEDULER_TICK: Duration = Duration::from_secs(15);
+
+/// Shortest period of `Schedule::Every`
+pub const MIN_PERIOD_SECS: u64 = 10;
+
+/// How often a run checks whether its statements are over
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// When a scheduled query runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schedule {
+    /// Every `seconds` since the last run, or right away if it never ran
+    Every { seconds: u64 },
+    /// When the local time matches a `minute hour day month weekday` expression, e.g. `0 9 * * 1-5`
+    Cron { expression: String },
+}
+
+impl Schedule {
+    pub fn validate(&self) -> Result<(), Error> {
+        match self {
+            Schedule::Every { seconds } if *seconds < MIN_PERIOD_SECS => Err(anyhow::anyhow!(
+                "Scheduled queries can't run more often than every {MIN_PERIOD_SECS} seconds"
+            )
+            .into()),
+            Schedule::Every { .. } => Ok(()),
+            Schedule::Cron { expression } => CronExpression::parse(expression).map(|_| ()),
+        }
+    }
+
+    /// Whether a schedule last run at `last_run_at` (in seconds since the epoch) is due at `now`. Cron schedules
+    /// that were missed, e.g. while the app was closed, wait for their next time rather than catching up.
+    pub fn is_due<Tz: TimeZone>(&self, last_run_at: Option<i64>, now: &DateTime<Tz>) -> bool {
+        match self {
+            Schedule::Every { seconds } => {
+                last_run_at.is_none_or(|last| now.timestamp() - last >= *seconds as i64)
+            }
+            Schedule::Cron { expression } => {
+                let Ok(cron) = CronExpression::parse(expression) else {
+                    return false;
+                };
+                let minute_start = now.timestamp() - now.timestamp().rem_euclid(60);
+                cron.matches(now) && last_run_at.is_none_or(|last| last < minute_start)
+            }
+        }
+    }
+}
+
+/// A parsed cron expression, each field being the set of values it matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpression {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    /// 0 being Sunday
+    weekdays: u64,
+    /// As in cron, a day matches if either its day of the month or its weekday does when both are restricted
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CronExpression {
+    /// Fields are `*`, values, ranges like `1-5` and steps like `*/15` or `0-30/10`, separated by commas
+    pub fn parse(expression: &str) -> Result<Self, Error> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(anyhow::anyhow!(
+                "Cron expressions have 5 fields (minute hour day month weekday), not {}",
+                fields.len()
+            )
+            .into());
+        };
+
+        let mut weekday_bits = parse_field(weekdays, 0, 7, "weekday")?;
+        // Both 0 and 7 are Sunday
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits = (weekday_bits | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59, "minute")?,
+            hours: parse_field(hours, 0, 23, "hour")?,
+            days: parse_field(days, 1, 31, "day")?,
+            months: parse_field(months, 1, 12, "month")?,
+            weekdays: weekday_bits,
+            days_restricted: !days.starts_with('*'),
+            weekdays_restricted: !weekdays.starts_with('*'),
+        })
+    }
+
+    pub fn matches<T: Datelike + Timelike>(&self, time: &T) -> bool {
+        let has = |bits: u64, value: u32| bits & (1 << value) != 0;
+
+        let day = has(self.days, time.day());
+        let weekday = has(self.weekdays, time.weekday().num_days_from_sunday());
+        let day_matches = if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        };
+
+        has(self.minutes, time.minute())
+            && has(self.hours, time.hour())
+            && has(self.months, time.month())
+            && day_matches
+    }
+}
+
+/// The values a field matches, as bits
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, Error> {
+    let invalid = || anyhow::anyhow!("Invalid {name} in cron expression: {field}");
+    let number = |s: &str| s.parse::<u32>().map_err(|_| invalid());
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(number(step)?).filter(|step| *step > 0)),
+            None => (part, None),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // `5/10` is every 10 from 5 on
+            None if step.is_some() => (number(range)?, max),
+            None => {
+                let value = number(range)?;
+                (value, value)
+            }
+        };
+        if (part.contains('/') && step.is_none()) || start < min || end > max || start > end {
+            return Err(invalid().into());
+        }
+
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduledRunStatus {
+    Success,
+    Error,
+    /// The connection wasn't connected
+    Skipped,
+}
+
+impl ScheduledRunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduledRunStatus::Success => "success",
+            ScheduledRunStatus::Error => "error",
+            ScheduledRunStatus::Skipped => "skipped",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "success" => Some(ScheduledRunStatus::Success),
+            "error" => Some(ScheduledRunStatus::Error),
+            "skipped" => Some(ScheduledRunStatus::Skipped),
+            _ => None,
+        }
+    }
+}
+
+/// How a scheduled run went
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledRun {
+    pub schedule_id: i64,
+    pub name: String,
+    pub connection_id: Uuid,
+    pub status: ScheduledRunStatus,
+    /// Rows returned by the statements that return rows, or affected by the others
+    pub row_count: u64,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+    /// Whether the schedule alerts on rows and the run returned some
+    pub alert: bool,
+}
+
+/// What the statements of a run amount to once they're over
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub row_count: u64,
+    /// Only counting the statements that return rows
+    pub returned_rows: u64,
+    /// Of the first statement that failed
+    pub error: Option<String>,
+}
+
+/// Keeps track of the schedules being run, so that a run doesn't start while the previous one is going, and
+/// reports failed and alerting runs through the receiver given out by [`Scheduler::subscribe`]
+#[derive(Default)]
+pub struct Scheduler {
+    running: DashSet<i64>,
+    sender: Mutex<Option<UnboundedSender<ScheduledRun>>>,
+}
+
+impl std::fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Scheduler")
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> UnboundedReceiver<ScheduledRun> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.sender.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Marks a schedule as running until the returned claim is dropped. None if it's already running.
+    pub fn claim(&self, schedule_id: i64) -> Option<RunClaim<'_>> {
+        self.running.insert(schedule_id).then_some(RunClaim {
+            scheduler: self,
+            schedule_id,
+        })
+    }
+
+    pub fn is_running(&self, schedule_id: i64) -> bool {
+        self.running.contains(&schedule_id)
+    }
+
+    /// Reports a run that failed or raised an alert. Other runs are only recorded.
+    pub fn report(&self, run: &ScheduledRun) {
+        if run.status != ScheduledRunStatus::Error && !run.alert {
+            return;
+        }
+        if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+            let _ = sender.send(run.clone());
+        }
+    }
+}
+
+/// A schedule being run, see [`Scheduler::claim`]
+pub struct RunClaim<'a> {
+    scheduler: &'a Scheduler,
+    schedule_id: i64,
+}
+
+impl Drop for RunClaim<'_> {
+    fn drop(&mut self) {
+        self.scheduler.running.remove(&self.schedule_id);
+    }
+}
+
+/// Waits for the statements of a run to be over, letting them fetch all their rows, and sums up their results
+pub async fn wait_for_run(
+    manager: &StatementManager,
+    query_ids: &[QueryId],
+) -> Result<(RunSummary, Duration), Error> {
+    let started = Instant::now();
+    let mut summary = RunSummary::default();
+
+    for &query_id in query_ids {
+        while !manager.is_query_over(query_id)? {
+            manager.request_more_rows(query_id)?;
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        let snapshot = manager.fetch_initial_renderable_state(query_id).await?;
+        if let Some(error) = snapshot.error {
+            summary.error.get_or_insert(error);
+            continue;
+        }
+        if snapshot.returns_values {
+            let rows = snapshot
+                .statement
+                .map_or(0, |statement| statement.metrics.rows_fetched as u64);
+            summary.returned_rows += rows;
+            summary.row_count += rows;
+        } else {
+            summary.row_count += snapshot.affected_rows.unwrap_or(0) as u64;
+        }
+    }
+
+    Ok((summary, started.elapsed()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, Utc};
+
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        // 2024-07-01 is a Monday
+        NaiveDate::from_ymd_opt(2024, 7, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, second)
+            .unwrap()
+            .and_utc()
+    }
+
+    #[test]
+    fn matches_cron_expressions() {
+        let cron = |expression| CronExpression::parse(expression).unwrap();
+
+        let every_quarter = cron("*/15 * * * *");
+        assert!(every_quarter.matches(&at(1, 10, 45, 0)));
+        assert!(!every_quarter.matches(&at(1, 10, 46, 0)));
+
+        let weekday_mornings = cron("0 9 * * 1-5");
+        assert!(weekday_mornings.matches(&at(1, 9, 0, 30)));
+        // Sunday
+        assert!(!weekday_mornings.matches(&at(7, 9, 0, 0)));
+        assert!(cron("0 9 * * 7").matches(&at(7, 9, 0, 0)));
+
+        // Either the day of the month or the weekday, when both are given
+        let firsts_and_fridays = cron("30 6 1,15 * 5");
+        assert!(firsts_and_fridays.matches(&at(1, 6, 30, 0)));
+        assert!(firsts_and_fridays.matches(&at(5, 6, 30, 0)));
+        assert!(!firsts_and_fridays.matches(&at(2, 6, 30, 0)));
+
+        assert!(cron("5/20 * * 7 *").matches(&at(3, 0, 45, 0)));
+        assert!(!cron("* * * 1-6 *").matches(&at(3, 0, 45, 0)));
+
+        for invalid in [
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(CronExpression::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn tells_when_schedules_are_due() {
+        let now = at(1, 10, 45, 20);
+
+        let every_minute = Schedule::Every { seconds: 60 };
+        assert!(every_minute.is_due(None, &now));
+        assert!(every_minute.is_due(Some(now.timestamp() - 60), &now));
+        assert!(!every_minute.is_due(Some(now.timestamp() - 59), &now));
+
+        let quarterly = Schedule::Cron {
+            expression: "*/15 * * * *".to_owned(),
+        };
+        assert!(quarterly.is_due(None, &now));
+        assert!(quarterly.is_due(Some(at(1, 10, 30, 0).timestamp()), &now));
+        // Already run this minute
+        assert!(!quarterly.is_due(Some(at(1, 10, 45, 5).timestamp()), &now));
+        assert!(!quarterly.is_due(None, &at(1, 10, 46, 0)));
+
+        assert!(Schedule::Every { seconds: 5 }.validate().is_err());
+        assert!(Schedule::Cron {
+            expression: "every day".to_owned()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn runs_each_schedule_once_at_a_time() {
+        let scheduler = Scheduler::new();
+        let mut runs = scheduler.subscribe();
+
+        let claim = scheduler.claim(1).unwrap();
+        assert!(scheduler.claim(1).is_none());
+        assert!(scheduler.claim(2).is_some());
+        drop(claim);
+        assert!(!scheduler.is_running(1));
+        assert!(scheduler.claim(1).is_some());
+
+        let run = ScheduledRun {
+            schedule_id: 1,
+            name: "orphans".to_owned(),
+            connection_id: Uuid::new_v4(),
+            status: ScheduledRunStatus::Success,
+            row_count: 0,
+            duration_ms: 1,
+            error: None,
+            alert: false,
+        };
+        scheduler.report(&run);
+        assert!(runs.try_recv().is_err());
+
+        scheduler.report(&ScheduledRun { alert: true, ..run });
+        assert!(runs.try_recv().unwrap().alert);
+    }
+}
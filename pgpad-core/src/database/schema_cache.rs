@@ -0,0 +1,184 @@
+//! Per-connection cache of database schemas, with single-flight fetching: while a schema is being introspected,
+//! everyone else asking for it waits for that same introspection instead of starting their own.
+
+use std::{sync::Arc, time::Duration};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use uuid::Uuid;
+
+use crate::{database::types::DatabaseSchema, Error};
+
+/// Longest an introspection may take before every caller waiting on it gets an error
+pub const SCHEMA_FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An in-flight introspection. Errors are strings since they're handed out to every waiter.
+pub type SchemaFetch = Shared<BoxFuture<'static, Result<Arc<DatabaseSchema>, String>>>;
+
+#[derive(Clone)]
+pub enum CachedSchema {
+    Ready(Arc<DatabaseSchema>),
+    Pending(SchemaFetch),
+}
+
+impl std::fmt::Debug for CachedSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CachedSchema::Ready(_) => write!(f, "CachedSchema::Ready"),
+            CachedSchema::Pending(_) => write!(f, "CachedSchema::Pending"),
+        }
+    }
+}
+
+pub type SchemaCache = DashMap<Uuid, CachedSchema>;
+
+/// The cached schema of a connection, if it was already fetched
+pub fn ready_schema(schemas: &SchemaCache, connection_id: Uuid) -> Option<Arc<DatabaseSchema>> {
+    match schemas.get(&connection_id).as_deref() {
+        Some(CachedSchema::Ready(schema)) => Some(schema.clone()),
+        _ => None,
+    }
+}
+
+/// Replaces the cached schema of a connection with what `update` makes of it, if it was already fetched and
+/// `update` makes something of it
+pub fn update_ready_schema(
+    schemas: &SchemaCache,
+    connection_id: Uuid,
+    update: impl FnOnce(&DatabaseSchema) -> Option<DatabaseSchema>,
+) {
+    if let Some(mut entry) = schemas.get_mut(&connection_id) {
+        if let CachedSchema::Ready(schema) = entry.value() {
+            if let Some(updated) = update(schema) {
+                *entry = CachedSchema::Ready(Arc::new(updated));
+            }
+        }
+    }
+}
+
+/// Gets the schema of a connection from the cache, or from `fetch` if nobody else is fetching it already.
+///
+/// `fetch` is only called by the first caller, and failures aren't cached, so the next call tries again.
+pub async fn get_or_fetch_schema<F>(
+    schemas: &SchemaCache,
+    connection_id: Uuid,
+    fetch: F,
+) -> Result<Arc<DatabaseSchema>, Error>
+where
+    F: FnOnce() -> Result<BoxFuture<'static, Result<DatabaseSchema, Error>>, Error>,
+{
+    let pending = match schemas.entry(connection_id) {
+        Entry::Occupied(entry) => match entry.get() {
+            CachedSchema::Ready(schema) => return Ok(schema.clone()),
+            CachedSchema::Pending(pending) => pending.clone(),
+        },
+        Entry::Vacant(entry) => {
+            let introspection = fetch()?;
+            let pending = async move {
+                match tokio::time::timeout(SCHEMA_FETCH_TIMEOUT, introspection).await {
+                    Ok(Ok(schema)) => Ok(Arc::new(schema)),
+                    Ok(Err(err)) => Err(err.to_string()),
+                    Err(_) => Err(format!(
+                        "Loading the schema timed out after {}s",
+                        SCHEMA_FETCH_TIMEOUT.as_secs()
+                    )),
+                }
+            }
+            .boxed()
+            .shared();
+
+            entry.insert(CachedSchema::Pending(pending.clone()));
+            pending
+        }
+    };
+
+    let result = pending.clone().await;
+
+    // The first waiter to get here settles the entry, unless it was invalidated in the meantime
+    if let Entry::Occupied(mut entry) = schemas.entry(connection_id) {
+        let is_ours = matches!(entry.get(), CachedSchema::Pending(fetch) if fetch.ptr_eq(&pending));
+        if is_ours {
+            match &result {
+                Ok(schema) => {
+                    entry.insert(CachedSchema::Ready(schema.clone()));
+                }
+                Err(_) => {
+                    entry.remove();
+                }
+            }
+        }
+    }
+
+    result.map_err(|err| anyhow::anyhow!(err).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn slow_introspection(
+        calls: &Arc<AtomicUsize>,
+        fail: bool,
+    ) -> impl FnOnce() -> Result<BoxFuture<'static, Result<DatabaseSchema, Error>>, Error> {
+        let calls = calls.clone();
+        move || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                if fail {
+                    return Err(anyhow::anyhow!("connection reset").into());
+                }
+                Ok(DatabaseSchema::new(
+                    Vec::new(),
+                    vec!["public".into()],
+                    Vec::new(),
+                ))
+            }
+            .boxed())
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_introspection() {
+        let schemas = SchemaCache::new();
+        let connection_id = Uuid::new_v4();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let results = futures_util::future::join_all((0..10).map(|_| {
+            get_or_fetch_schema(&schemas, connection_id, slow_introspection(&calls, false))
+        }))
+        .await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let first = results[0].as_ref().unwrap();
+        assert!(results
+            .iter()
+            .all(|schema| Arc::ptr_eq(schema.as_ref().unwrap(), first)));
+        assert!(ready_schema(&schemas, connection_id).is_some());
+    }
+
+    #[tokio::test]
+    async fn errors_reach_every_waiter_and_are_not_cached() {
+        let schemas = SchemaCache::new();
+        let connection_id = Uuid::new_v4();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let results = futures_util::future::join_all((0..3).map(|_| {
+            get_or_fetch_schema(&schemas, connection_id, slow_introspection(&calls, true))
+        }))
+        .await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(results
+            .iter()
+            .all(|result| result.as_ref().unwrap_err().to_string() == "connection reset"));
+        assert!(schemas.get(&connection_id).is_none());
+
+        get_or_fetch_schema(&schemas, connection_id, slow_introspection(&calls, false))
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,354 @@
+//! Search over the tables of a connection, for schemas too large to browse.
+//!
+//! With the `semantic-search` feature and a model configured, tables are ranked by how close their meaning is to
+//! the query, so that "customer churn facts" finds `analytics.f_customer_retention`. The model is a file of word
+//! vectors read from disk, so nothing leaves the machine. Each table's vector is computed from the words of its
+//! name, schema and columns, kept in [`Storage`](crate::storage::Storage), and only computed again when the table
+//! changes.
+//!
+//! Without the feature or a model, or for queries the model knows none of the words of, tables are matched by
+//! name instead, so that there's a single way to search either way.
+
+#[cfg(feature = "semantic-search")]
+mod index;
+
+#[cfg(feature = "semantic-search")]
+pub use index::SemanticIndex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::{
+    error_hints::edit_distance,
+    types::{DatabaseSchema, TableInfo},
+};
+
+/// Most tables returned by a search, whatever the caller asks for
+pub const MAX_RESULTS: usize = 200;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SemanticSearchSettings {
+    pub enabled: bool,
+    /// Word vectors in GloVe's text format (`word 0.12 -0.5 …` on each line) or fastText's `.vec` one
+    pub model_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMethod {
+    Semantic,
+    /// By name, when semantic search isn't available
+    Fuzzy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchedField {
+    Name,
+    Schema,
+    Column,
+}
+
+/// A name of a table that matched the query
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldMatch {
+    pub field: MatchedField,
+    pub value: String,
+    /// Byte ranges of the matching words in `value`, to highlight them
+    pub ranges: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaSearchHit {
+    pub schema: String,
+    pub name: String,
+    /// Higher is better. Only comparable with the scores of the same search.
+    pub score: f32,
+    pub matches: Vec<FieldMatch>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaSearchResults {
+    pub method: SearchMethod,
+    pub hits: Vec<SchemaSearchHit>,
+}
+
+/// A lowercased word of a name or of a query, e.g. `customer` in `f_customerRetention`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Word {
+    pub text: String,
+    /// Byte range of the word in the original text
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Splits names into words at anything that isn't a letter or a digit, at camelCase humps, and where letters
+/// meet digits
+pub(crate) fn words(text: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut previous: Option<char> = None;
+
+    let mut push = |start: usize, end: usize| {
+        words.push(Word {
+            text: text[start..end].to_lowercase(),
+            start,
+            end,
+        })
+    };
+
+    for (idx, c) in text.char_indices() {
+        let hump = previous.is_some_and(|previous| {
+            (previous.is_lowercase() && c.is_uppercase())
+                || (previous.is_alphabetic() != c.is_alphabetic())
+        });
+        if !c.is_alphanumeric() || hump {
+            if let Some(start) = start.take() {
+                push(start, idx);
+            }
+        }
+        if c.is_alphanumeric() && start.is_none() {
+            start = Some(idx);
+        }
+        previous = Some(c);
+    }
+    if let Some(start) = start {
+        push(start, text.len());
+    }
+
+    words
+}
+
+/// The names of a table that are searched, by how much a match in them counts
+pub(crate) fn fields(table: &TableInfo) -> Vec<(MatchedField, &str, f32)> {
+    let mut fields = vec![
+        (MatchedField::Name, table.name.as_str(), 1.0),
+        (MatchedField::Schema, table.schema.as_str(), 0.4),
+    ];
+    fields.extend(
+        table
+            .columns
+            .iter()
+            .map(|column| (MatchedField::Column, column.name.as_str(), 0.6)),
+    );
+    fields
+}
+
+/// The names of a table with words for which `is_match` holds, and where those words are
+pub(crate) fn highlights(table: &TableInfo, is_match: impl Fn(&Word) -> bool) -> Vec<FieldMatch> {
+    fields(table)
+        .into_iter()
+        .filter_map(|(field, value, _)| {
+            let ranges: Vec<_> = words(value)
+                .iter()
+                .filter(|word| is_match(word))
+                .map(|word| (word.start, word.end))
+                .collect();
+            (!ranges.is_empty()).then(|| FieldMatch {
+                field,
+                value: value.to_owned(),
+                ranges,
+            })
+        })
+        .collect()
+}
+
+/// How well a word of the query matches a word of a name, from 0 to 1
+fn word_match(query: &str, word: &str) -> f32 {
+    if query == word {
+        1.0
+    } else if query.len() >= 2 && word.starts_with(query) {
+        // Still being typed
+        0.8
+    } else if query.chars().count() >= 4 && edit_distance(query, word) <= query.chars().count() / 4
+    {
+        0.6
+    } else {
+        0.0
+    }
+}
+
+/// Ranks tables by how many words of the query their names have, allowing for prefixes and typos
+pub fn fuzzy_search(schema: &DatabaseSchema, query: &str, limit: usize) -> Vec<SchemaSearchHit> {
+    let query_words = words(query);
+    if query_words.is_empty() {
+        return Vec::new();
+    }
+
+    let mut hits: Vec<_> = schema
+        .tables
+        .iter()
+        .filter_map(|table| {
+            let fields: Vec<_> = fields(table)
+                .into_iter()
+                .map(|(_, value, weight)| (words(value), weight))
+                .collect();
+
+            let total: f32 = query_words
+                .iter()
+                .map(|query_word| {
+                    fields
+                        .iter()
+                        .flat_map(|(words, weight)| {
+                            words
+                                .iter()
+                                .map(move |word| weight * word_match(&query_word.text, &word.text))
+                        })
+                        .fold(0.0, f32::max)
+                })
+                .sum();
+            // Among equal matches, tables whose name is mostly the query come first, e.g. `customers` for "cust"
+            let name_words = &fields[0].0;
+            let covered = name_words
+                .iter()
+                .filter(|word| {
+                    query_words
+                        .iter()
+                        .any(|query_word| word_match(&query_word.text, &word.text) > 0.0)
+                })
+                .count();
+            let coverage = covered as f32 / name_words.len().max(1) as f32;
+            let score = total / query_words.len() as f32 + 0.1 * coverage;
+
+            (score > 0.0).then(|| SchemaSearchHit {
+                schema: table.schema.clone(),
+                name: table.name.clone(),
+                score,
+                matches: highlights(table, |word| {
+                    query_words
+                        .iter()
+                        .any(|query_word| word_match(&query_word.text, &word.text) > 0.0)
+                }),
+            })
+        })
+        .collect();
+
+    sort_hits(&mut hits, limit);
+    hits
+}
+
+/// Best first, then by name so that ties don't shuffle around between searches
+pub(crate) fn sort_hits(hits: &mut Vec<SchemaSearchHit>, limit: usize) {
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.schema.cmp(&b.schema))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    hits.truncate(limit);
+}
+
+/// Stand-in for the index when the `semantic-search` feature is off, so that searches fall back to names
+#[cfg(not(feature = "semantic-search"))]
+#[derive(Debug, Default)]
+pub struct SemanticIndex;
+
+#[cfg(not(feature = "semantic-search"))]
+impl SemanticIndex {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn refresh(
+        &self,
+        _connection_id: uuid::Uuid,
+        _schema: &std::sync::Arc<DatabaseSchema>,
+        _storage: &crate::storage::Storage,
+    ) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    pub async fn search(
+        &self,
+        _connection_id: uuid::Uuid,
+        _schema: &std::sync::Arc<DatabaseSchema>,
+        _query: &str,
+        _limit: usize,
+        _settings: &SemanticSearchSettings,
+        _storage: &crate::storage::Storage,
+    ) -> Result<Option<Vec<SchemaSearchHit>>, crate::Error> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::database::types::ColumnInfo;
+
+    pub(crate) fn table(schema: &str, name: &str, columns: &[&str]) -> TableInfo {
+        TableInfo {
+            name: name.into(),
+            schema: schema.into(),
+            columns: columns
+                .iter()
+                .map(|column| ColumnInfo {
+                    name: (*column).into(),
+                    data_type: "text".into(),
+                    is_nullable: true,
+                    default_value: None,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn splits_names_into_words() {
+        let texts: Vec<_> = words("f_customerRetention2024 «Ünïcode» HTTPServer")
+            .into_iter()
+            .map(|word| word.text)
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "f",
+                "customer",
+                "retention",
+                "2024",
+                "ünïcode",
+                "httpserver"
+            ]
+        );
+
+        let word = &words("analytics.f_customer_retention")[2];
+        assert_eq!((word.start, word.end), (12, 20));
+    }
+
+    #[test]
+    fn matches_names_fuzzily() {
+        let schema = DatabaseSchema::new(
+            vec![
+                table(
+                    "analytics",
+                    "f_customer_retention",
+                    &["customer_id", "churned_at"],
+                ),
+                table("public", "customers", &["id", "name"]),
+                table("public", "orders", &["id", "customer_id"]),
+            ],
+            vec!["analytics".into(), "public".into()],
+            Vec::new(),
+        );
+
+        let hits = fuzzy_search(&schema, "custmer retention", 10);
+        assert_eq!(hits[0].name, "f_customer_retention");
+        assert_eq!(
+            hits[0].matches[0],
+            FieldMatch {
+                field: MatchedField::Name,
+                value: "f_customer_retention".into(),
+                ranges: vec![(2, 10), (11, 20)],
+            }
+        );
+
+        let names: Vec<_> = fuzzy_search(&schema, "cust", 2)
+            .into_iter()
+            .map(|hit| hit.name)
+            .collect();
+        assert_eq!(names, ["customers", "f_customer_retention"]);
+
+        assert!(fuzzy_search(&schema, "invoices", 10).is_empty());
+        assert!(fuzzy_search(&schema, " ", 10).is_empty());
+    }
+}
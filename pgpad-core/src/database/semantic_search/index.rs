@@ -0,0 +1,521 @@
+//! The semantic index: a vector per table, averaged from the word vectors of its names, compared to the query's
+//! with a cosine similarity scan. Even tens of thousands of tables only take a few milliseconds to scan, so
+//! there's no need for an approximate index.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    sync::{Arc, Mutex, Weak},
+};
+
+use anyhow::Context;
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use super::{highlights, sort_hits, words, SchemaSearchHit, SemanticSearchSettings};
+use crate::{
+    database::types::{DatabaseSchema, TableInfo},
+    storage::{SchemaVector, Storage},
+    utils::{fnv1a, FNV_OFFSET_BASIS},
+    Error,
+};
+
+/// Tables less similar to the query than this aren't returned
+const MIN_TABLE_SIMILARITY: f32 = 0.3;
+
+/// Words of a table at least this similar to a word of the query are highlighted
+const MIN_WORD_SIMILARITY: f32 = 0.5;
+
+/// How much each name counts towards a table's vector
+const NAME_WEIGHT: f32 = 2.0;
+const COLUMNS_WEIGHT: f32 = 1.0;
+const SCHEMA_WEIGHT: f32 = 0.5;
+
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+/// Cosine similarity of two normalized vectors. Vectors of another model (or empty ones) aren't similar to anything.
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// A word embedding model, e.g. GloVe's
+#[derive(Debug)]
+pub struct WordVectors {
+    dimensions: usize,
+    /// Normalized, by lowercased word
+    vectors: HashMap<String, Vec<f32>>,
+}
+
+impl WordVectors {
+    /// Reads vectors in GloVe's text format, one `word 0.12 -0.5 …` per line, or in fastText's `.vec` format,
+    /// which adds a `word_count dimensions` header
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open the word vectors at {}", path.display()))?;
+
+        let mut dimensions = 0;
+        let mut vectors = HashMap::new();
+        for (idx, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.context("Failed to read the word vectors")?;
+            let mut parts = line.split_whitespace();
+            let Some(word) = parts.next() else {
+                continue;
+            };
+            let vector = parts
+                .map(str::parse)
+                .collect::<Result<Vec<f32>, _>>()
+                .with_context(|| format!("Invalid word vector on line {}", idx + 1))?;
+            if idx == 0 && vector.len() == 1 {
+                // fastText's header
+                continue;
+            }
+
+            if dimensions == 0 {
+                dimensions = vector.len();
+            }
+            if vector.len() != dimensions {
+                return Err(anyhow::anyhow!(
+                    "The word vector on line {} has {} dimensions instead of {dimensions}",
+                    idx + 1,
+                    vector.len()
+                )
+                .into());
+            }
+            // Files list frequent words first, which is the spelling to keep when a word comes in several cases
+            vectors
+                .entry(word.to_lowercase())
+                .or_insert_with(|| normalize(vector));
+        }
+
+        if vectors.is_empty() {
+            return Err(anyhow::anyhow!("{} has no word vectors", path.display()).into());
+        }
+
+        Ok(Self {
+            dimensions,
+            vectors,
+        })
+    }
+
+    /// The normalized mean of the vectors of the words the model knows, or None if it knows none of them
+    pub fn embed<'a>(&self, words: impl IntoIterator<Item = &'a str>) -> Option<Vec<f32>> {
+        let mut sum = vec![0.0; self.dimensions];
+        let mut known = false;
+        for vector in words.into_iter().filter_map(|word| self.vectors.get(word)) {
+            sum.iter_mut().zip(vector).for_each(|(sum, v)| *sum += v);
+            known = true;
+        }
+        known.then(|| normalize(sum))
+    }
+
+    fn word_similarity(&self, a: &str, b: &str) -> f32 {
+        if a == b {
+            return 1.0;
+        }
+        match (self.vectors.get(a), self.vectors.get(b)) {
+            (Some(a), Some(b)) => similarity(a, b),
+            _ => 0.0,
+        }
+    }
+
+    /// The vector of a table, from its name first, then its columns, and a little from its schema
+    fn embed_table(&self, table: &TableInfo) -> Vec<f32> {
+        let embed = |text: &str| {
+            let words = words(text);
+            self.embed(words.iter().map(|word| word.text.as_str()))
+        };
+        let columns: Vec<_> = table
+            .columns
+            .iter()
+            .flat_map(|column| words(&column.name))
+            .collect();
+
+        let parts = [
+            (embed(&table.name), NAME_WEIGHT),
+            (
+                self.embed(columns.iter().map(|word| word.text.as_str())),
+                COLUMNS_WEIGHT,
+            ),
+            (embed(&table.schema), SCHEMA_WEIGHT),
+        ];
+        let mut sum = vec![0.0; self.dimensions];
+        let mut known = false;
+        for (vector, weight) in parts {
+            if let Some(vector) = vector {
+                sum.iter_mut()
+                    .zip(vector)
+                    .for_each(|(sum, v)| *sum += weight * v);
+                known = true;
+            }
+        }
+
+        // Tables with no known words get no vector, and are never found
+        if known {
+            normalize(sum)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+fn object_key(table: &TableInfo) -> String {
+    format!("{}.{}", table.schema, table.name)
+}
+
+/// Tells whether a stored vector is stale: it changes with the table's names, or with the model
+fn content_hash(table: &TableInfo, model_path: &str) -> String {
+    let mut hash = fnv1a(FNV_OFFSET_BASIS, model_path.as_bytes());
+    for name in [&table.schema, &table.name]
+        .into_iter()
+        .chain(table.columns.iter().map(|column| &column.name))
+    {
+        hash = fnv1a(fnv1a(hash, name.as_bytes()), b"\0");
+    }
+    format!("{hash:016x}")
+}
+
+#[derive(Debug)]
+struct ConnectionIndex {
+    /// The schema the vectors were computed from. Weak, so that its address can't be reused by another schema.
+    schema: Weak<DatabaseSchema>,
+    model_path: String,
+    vectors: HashMap<String, SchemaVector>,
+}
+
+/// The vectors of every connection's tables, kept in sync with the schema cache
+#[derive(Debug, Default)]
+pub struct SemanticIndex {
+    /// The last model loaded, by path, or None if it couldn't be
+    model: Mutex<Option<(String, Option<Arc<WordVectors>>)>>,
+    connections: DashMap<Uuid, ConnectionIndex>,
+}
+
+impl SemanticIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The configured model, loading it the first time. None if semantic search is disabled, or if the model
+    /// can't be loaded, in which case searches fall back to names.
+    async fn model(&self, settings: &SemanticSearchSettings) -> Option<(String, Arc<WordVectors>)> {
+        if !settings.enabled {
+            return None;
+        }
+        let path = settings.model_path.as_deref()?.trim().to_owned();
+
+        if let Some((loaded_path, model)) = self.model.lock().unwrap().as_ref() {
+            if *loaded_path == path {
+                return model.clone().map(|model| (path, model));
+            }
+        }
+
+        let model_path = path.clone();
+        let model =
+            match tokio::task::spawn_blocking(move || WordVectors::load(Path::new(&model_path)))
+                .await
+            {
+                Ok(Ok(model)) => Some(Arc::new(model)),
+                Ok(Err(err)) => {
+                    log::warn!(
+                        "Semantic search falls back to names, the model couldn't be loaded: {err}"
+                    );
+                    None
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Semantic search falls back to names, loading the model panicked: {err}"
+                    );
+                    None
+                }
+            };
+        *self.model.lock().unwrap() = Some((path.clone(), model.clone()));
+        model.map(|model| (path, model))
+    }
+
+    /// Brings the vectors of a connection up to date with its schema, if a model is loaded already.
+    /// Only tables that changed are embedded again.
+    pub fn refresh(
+        &self,
+        connection_id: Uuid,
+        schema: &Arc<DatabaseSchema>,
+        storage: &Storage,
+    ) -> Result<(), Error> {
+        let loaded = self.model.lock().unwrap().clone();
+        match loaded {
+            Some((path, Some(model))) => self.update(connection_id, schema, &path, &model, storage),
+            _ => Ok(()),
+        }
+    }
+
+    fn update(
+        &self,
+        connection_id: Uuid,
+        schema: &Arc<DatabaseSchema>,
+        model_path: &str,
+        model: &WordVectors,
+        storage: &Storage,
+    ) -> Result<(), Error> {
+        let schema_ref = Arc::downgrade(schema);
+        if let Some(index) = self.connections.get(&connection_id) {
+            if index.model_path == model_path && Weak::ptr_eq(&index.schema, &schema_ref) {
+                return Ok(());
+            }
+        }
+
+        let mut previous: HashMap<String, SchemaVector> =
+            match self.connections.remove(&connection_id) {
+                Some((_, index)) => index.vectors,
+                None => storage
+                    .get_schema_vectors(&connection_id)?
+                    .into_iter()
+                    .map(|vector| (vector.object_key.clone(), vector))
+                    .collect(),
+            };
+
+        let mut vectors = HashMap::with_capacity(schema.tables.len());
+        let mut changed = Vec::new();
+        for table in &schema.tables {
+            let object_key = object_key(table);
+            let content_hash = content_hash(table, model_path);
+            let vector = match previous.remove(&object_key) {
+                Some(vector) if vector.content_hash == content_hash => vector,
+                _ => {
+                    let vector = SchemaVector {
+                        object_key: object_key.clone(),
+                        content_hash,
+                        vector: model.embed_table(table),
+                    };
+                    changed.push(vector.clone());
+                    vector
+                }
+            };
+            vectors.insert(object_key, vector);
+        }
+
+        let removed: Vec<_> = previous.into_keys().collect();
+        if !changed.is_empty() || !removed.is_empty() {
+            storage.update_schema_vectors(&connection_id, &changed, &removed)?;
+        }
+
+        self.connections.insert(
+            connection_id,
+            ConnectionIndex {
+                schema: schema_ref,
+                model_path: model_path.to_owned(),
+                vectors,
+            },
+        );
+        Ok(())
+    }
+
+    /// The tables closest in meaning to the query, or None if semantic search isn't available for it
+    pub async fn search(
+        &self,
+        connection_id: Uuid,
+        schema: &Arc<DatabaseSchema>,
+        query: &str,
+        limit: usize,
+        settings: &SemanticSearchSettings,
+        storage: &Storage,
+    ) -> Result<Option<Vec<SchemaSearchHit>>, Error> {
+        let Some((model_path, model)) = self.model(settings).await else {
+            return Ok(None);
+        };
+        let query_words = words(query);
+        let Some(query_vector) = model.embed(query_words.iter().map(|word| word.text.as_str()))
+        else {
+            return Ok(None);
+        };
+
+        self.update(connection_id, schema, &model_path, &model, storage)?;
+        let Some(index) = self.connections.get(&connection_id) else {
+            return Ok(None);
+        };
+
+        let mut hits: Vec<_> = schema
+            .tables
+            .iter()
+            .filter_map(|table| {
+                let vector = index.vectors.get(&object_key(table))?;
+                let score = similarity(&query_vector, &vector.vector);
+                (score >= MIN_TABLE_SIMILARITY).then(|| SchemaSearchHit {
+                    schema: table.schema.clone(),
+                    name: table.name.clone(),
+                    score,
+                    matches: highlights(table, |word| {
+                        query_words.iter().any(|query_word| {
+                            model.word_similarity(&query_word.text, &word.text)
+                                >= MIN_WORD_SIMILARITY
+                        })
+                    }),
+                })
+            })
+            .collect();
+
+        sort_hits(&mut hits, limit);
+        Ok(Some(hits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::database::{
+        semantic_search::tests::table,
+        types::{ConnectionConfig, ConnectionInfo, Permissions},
+    };
+
+    const VECTORS: &str = "\
+9 4
+customer 1 0 0 0
+client 0.9 0.1 0 0
+churn 0 1 0.2 0
+retention 0 0.9 0.3 0
+facts 0 0 1 0
+f 0 0 0.8 0.2
+analytics 0 0.2 0.8 0
+orders 0 0 0 1
+id 0.1 0.1 0.1 0.1
+";
+
+    fn storage_with_connection() -> (Storage, Uuid) {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "warehouse".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: "/tmp/warehouse.db".into(),
+                    home_relative_path: None,
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+        (storage, connection_id)
+    }
+
+    #[tokio::test]
+    async fn finds_tables_by_meaning() {
+        let model_path = std::env::temp_dir().join(format!("pgpad-vectors-{}.vec", Uuid::new_v4()));
+        std::fs::write(&model_path, VECTORS).unwrap();
+        let settings = SemanticSearchSettings {
+            enabled: true,
+            model_path: Some(model_path.to_string_lossy().into_owned()),
+        };
+        let (storage, connection_id) = storage_with_connection();
+        let index = SemanticIndex::new();
+
+        let schema = Arc::new(DatabaseSchema::new(
+            vec![
+                table("analytics", "f_customer_retention", &["customer_id"]),
+                table("public", "orders", &["id", "customer_id"]),
+                table("public", "xyzzy", &["plugh"]),
+            ],
+            vec!["analytics".into(), "public".into()],
+            Vec::new(),
+        ));
+
+        let hits = index
+            .search(
+                connection_id,
+                &schema,
+                "client churn facts",
+                10,
+                &settings,
+                &storage,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(hits[0].name, "f_customer_retention");
+        assert_eq!(hits[0].matches[0].ranges, [(0, 1), (2, 10), (11, 20)]);
+        assert!(hits.iter().all(|hit| hit.name != "xyzzy"));
+        assert_eq!(storage.get_schema_vectors(&connection_id).unwrap().len(), 3);
+
+        // Unknown words can't be searched by meaning
+        assert!(index
+            .search(connection_id, &schema, "plugh", 10, &settings, &storage)
+            .await
+            .unwrap()
+            .is_none());
+
+        // A refreshed schema only has its changed tables embedded again
+        let stored = storage.get_schema_vectors(&connection_id).unwrap();
+        let refreshed = Arc::new(DatabaseSchema {
+            tables: vec![
+                schema.tables[0].clone(),
+                table("public", "orders", &["id", "client_id"]),
+            ],
+            ..(*schema).clone()
+        });
+        index.refresh(connection_id, &refreshed, &storage).unwrap();
+        let restored = storage.get_schema_vectors(&connection_id).unwrap();
+        assert_eq!(restored.len(), 2);
+        let vector_of = |vectors: &[SchemaVector], key: &str| {
+            vectors
+                .iter()
+                .find(|vector| vector.object_key == key)
+                .cloned()
+                .unwrap()
+        };
+        assert_eq!(
+            vector_of(&restored, "analytics.f_customer_retention"),
+            vector_of(&stored, "analytics.f_customer_retention")
+        );
+        assert_ne!(
+            vector_of(&restored, "public.orders").content_hash,
+            vector_of(&stored, "public.orders").content_hash
+        );
+
+        let disabled = SemanticSearchSettings {
+            enabled: false,
+            ..settings
+        };
+        assert!(index
+            .search(
+                connection_id,
+                &schema,
+                "client churn",
+                10,
+                &disabled,
+                &storage
+            )
+            .await
+            .unwrap()
+            .is_none());
+
+        std::fs::remove_file(model_path).unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_vectors() {
+        let path = std::env::temp_dir().join(format!("pgpad-vectors-{}.txt", Uuid::new_v4()));
+        for contents in ["", "customer 1 0\nchurn 1 0 0\n", "customer 1 zero\n"] {
+            std::fs::write(&path, contents).unwrap();
+            assert!(WordVectors::load(&path).is_err(), "{contents:?}");
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,46 +1,126 @@
 use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use anyhow::Context;
+use futures_util::FutureExt;
 use serde_json::value::RawValue;
 use uuid::Uuid;
 
 use crate::{
-    credentials,
+    connection_file::{self, ConnectionSecrets, ImportOutcome, ImportedConnection},
+    credentials::{self, Seal},
     database::{
         self,
-        postgres::{self, connect::connect},
-        sqlite,
+        browse::{self, BrowsePage},
+        column_stats::{self, ColumnKind, ColumnStats, ColumnStatsBuilder},
+        csv_import::{self, CsvBatches, CsvImportOptions, CsvImportReport, TargetTable},
+        definition::{self, Definition, DefinitionTarget},
+        delimited::{DelimitedWriter, ExportOptions},
+        diagnostics::{self, Diagnostic},
+        error_hints::{self, ErrorSuggestion},
+        execution_marks::StatementMarker,
+        filtering::{CompiledFilter, FilteredQuery, RowFilter},
+        formatter::{self, FormatSettings, FormattedSql},
+        grouping::{self, Aggregate, GroupedQuery},
+        hover::{self, Hover},
+        insert_export::{InsertDialect, InsertOptions, InsertWriter},
+        join_conditions,
+        json_export::{JsonFormat, JsonWriter},
+        mysql::{self, client::MySqlClient},
+        parser,
+        postgres::{
+            self,
+            activity::{ActivityFilters, ServerActivity},
+            connect::connect,
+            copy::{self as pg_copy, CopyDirection, CopyReport},
+            explain::{self, ExplainOptions, ExplainedPlan},
+            flavor,
+            pool::{self, PostgresPool},
+            replication::ReplicationInfo,
+            ssh_tunnel::{SshAuth, SshTunnel, SshTunnelConfig},
+            stats::PostgresStats,
+        },
+        preflight::{
+            EnvironmentPolicies, PendingRun, PreflightReport, PreflightSettings, SubmitOutcome,
+        },
+        probe::{self, ConnectionProbe},
+        query_tags::{self, QueryTagSettings, TagContext},
+        reconnect::{ReconnectSettings, ReconnectStatus},
+        relationship_graph::RelationshipGraph,
+        result_cache::ResultCacheSettings,
+        result_diff::{self, ResultDiff},
+        sanitize::{self, SanitizedSql},
+        scheduler::{self, RunSummary, ScheduledRun, ScheduledRunStatus},
+        schema_cache,
+        semantic_search::{self, SchemaSearchResults, SearchMethod, SemanticSearchSettings},
+        sorting::{self, Collation, NullsOrder, SortKey, SortedQuery},
+        sqlite::{
+            self,
+            explain::QueryPlan,
+            join::{CachedResult, JoinedQuery},
+            paths,
+            snapshot::{self, SnapshotInfo, SnapshotRefresh},
+        },
+        stmt_manager::{PageRewrite, StatementManager},
+        table_ddl::{self, TableDdl},
+        trace::TracedStatement,
+        transactions::TransactionInfo,
         types::{
-            Connection, ConnectionConfig, ConnectionInfo, ConnectionRuntime, Database,
-            DatabaseSchema, QuerySnapshot, QueryStatus, RuntimeClient,
+            normalize_color, normalize_folder, rename_folder, ColumnInfo, Connection,
+            ConnectionConfig, ConnectionInfo, ConnectionRuntime, Database, DatabaseSchema,
+            Environment, Permissions, QuerySnapshot, QueryStatus, ResourceLimits, RowDetailField,
+            RunOptions, RuntimeClient, ServerMessage, StatementInfo, SubmitOptions,
+            TimingBreakdown,
         },
-        Certificates, ConnectionMonitor,
+        variables, Certificates, ConnectionHealth, ConnectionMonitor,
     },
     error::Error,
-    storage::{QueryHistoryEntry, SavedQuery},
-    AppState,
+    external_edit::{ConflictResolution, ExternalEditSession, ExternalEditorSettings},
+    linked_scripts::{self, IndexedScriptDirectory},
+    operations::{Operation, OperationInfo, OperationKind},
+    script_file::{self, ScriptFile},
+    script_templates::{self, ScriptTemplate},
+    storage::{
+        DraftBuffer, ExportTemplate, HistoryCursor, HistoryFilters, HistoryPruneReport,
+        HistorySettings, LinkedScriptDirectory, QueryHistoryEntry, QueryHistoryPage, QueryVariable,
+        SavedQuery, ScheduledQuery, ScriptFilters, SessionInfo, SessionState, Snippet,
+    },
+    tree_state::{self, TreeState},
+    AppState, CredentialBackendStatus, SecretBackend,
 };
 
+#[allow(clippy::too_many_arguments)]
 pub async fn add_connection(
     name: String,
+    folder: Option<String>,
+    environment: Option<Environment>,
+    color: Option<String>,
     config: ConnectionConfig,
     permissions: crate::database::types::Permissions,
     state: &AppState,
 ) -> Result<ConnectionInfo, Error> {
     let id = Uuid::new_v4();
+    let color = normalize_color(color.as_deref())?;
 
-    let (config, password) = credentials::extract_sensitive_data(config)?;
+    let (mut config, password) = credentials::extract_sensitive_data(config)?;
+    let ssh_password = credentials::extract_ssh_password(&mut config);
+    let config = normalize_config(config)?;
 
     // It's expected that add_connection receives config with the password included,
     // as checked by the form in the UI. This call saves it in the keyring.
     if let Some(password) = password {
-        credentials::store_sensitive_data(&id, &password)?;
+        credentials::store_sensitive_data(&id, &password, &state.credential_file)?;
+    }
+    if let Some(ssh_password) = ssh_password {
+        credentials::store_ssh_password(&id, &ssh_password, &state.credential_file)?;
     }
 
-    let connection = Connection::new(id, name, config, permissions);
+    let folder = normalize_folder(folder.as_deref());
+    let connection = Connection::new(id, name, folder, environment, color, config, permissions);
     let info = connection.to_connection_info();
 
     state.storage.save_connection(&info)?;
@@ -49,16 +129,26 @@ pub async fn add_connection(
     Ok(info)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn update_connection(
     conn_id: Uuid,
     name: String,
+    folder: Option<String>,
+    environment: Option<Environment>,
+    color: Option<String>,
     config: ConnectionConfig,
     permissions: crate::database::types::Permissions,
     state: &AppState,
 ) -> Result<ConnectionInfo, Error> {
-    let (config, password) = credentials::extract_sensitive_data(config)?;
+    let color = normalize_color(color.as_deref())?;
+    let (mut config, password) = credentials::extract_sensitive_data(config)?;
+    let ssh_password = credentials::extract_ssh_password(&mut config);
+    let config = normalize_config(config)?;
     if let Some(password) = password {
-        credentials::store_sensitive_data(&conn_id, &password)?;
+        credentials::store_sensitive_data(&conn_id, &password, &state.credential_file)?;
+    }
+    if let Some(ssh_password) = ssh_password {
+        credentials::store_ssh_password(&conn_id, &ssh_password, &state.credential_file)?;
     }
 
     if let Some(mut connection_entry) = state.connections.get_mut(&conn_id) {
@@ -69,24 +159,47 @@ pub async fn update_connection(
                 ConnectionConfig::Postgres {
                     connection_string: old,
                     ca_cert_path: old_cert,
+                    ssh_tunnel: old_tunnel,
+                    ..
                 },
                 ConnectionConfig::Postgres {
                     connection_string: new,
                     ca_cert_path: new_cert,
+                    ssh_tunnel: new_tunnel,
+                    ..
                 },
-            ) => old != new || old_cert != new_cert,
+            ) => old != new || old_cert != new_cert || old_tunnel != new_tunnel,
             (
-                ConnectionConfig::SQLite { db_path: old },
-                ConnectionConfig::SQLite { db_path: new },
+                ConnectionConfig::SQLite { db_path: old, .. },
+                ConnectionConfig::SQLite { db_path: new, .. },
             ) => old != new,
+            (
+                ConnectionConfig::MySQL {
+                    connection_string: old,
+                    ca_cert_path: old_cert,
+                    ssh_tunnel: old_tunnel,
+                },
+                ConnectionConfig::MySQL {
+                    connection_string: new,
+                    ca_cert_path: new_cert,
+                    ssh_tunnel: new_tunnel,
+                },
+            ) => old != new || old_cert != new_cert || old_tunnel != new_tunnel,
             _ => true,
         };
 
         if config_changed {
             connection.runtime = ConnectionRuntime::Disconnected;
+            connection.snapshot = None;
+            connection.capabilities = None;
+            connection.tunnel = None;
+            connection.active_schema = None;
         }
 
         connection.name = name;
+        connection.folder = normalize_folder(folder.as_deref());
+        connection.environment = environment;
+        connection.color = color;
         connection.permissions = permissions;
         connection.config = config;
     }
@@ -102,11 +215,183 @@ pub async fn update_connection(
     Ok(updated_info)
 }
 
+/// The password of a connection's SSH tunnel, if it authenticates with one, from where it was saved
+fn saved_ssh_password(
+    connection_id: Uuid,
+    ssh_tunnel: &SshTunnelConfig,
+    state: &AppState,
+) -> Result<Option<String>, Error> {
+    match ssh_tunnel.auth {
+        SshAuth::Password { .. } => {
+            credentials::get_ssh_password(&connection_id, &state.credential_file)
+        }
+        SshAuth::Agent | SshAuth::KeyFile { .. } => Ok(None),
+    }
+}
+
+/// Makes the path of a SQLite connection absolute, and records where it is relative to the home directory. SSH
+/// tunnels are checked to be safe to pass to `ssh`.
+fn normalize_config(config: ConnectionConfig) -> Result<ConnectionConfig, Error> {
+    match config {
+        ConnectionConfig::SQLite { db_path, .. } => {
+            let path = paths::normalize(&db_path, paths::home_dir().as_deref())?;
+            Ok(ConnectionConfig::SQLite {
+                db_path: path.absolute,
+                home_relative_path: path.home_relative,
+            })
+        }
+        ConnectionConfig::Postgres {
+            ssh_tunnel: Some(ref tunnel),
+            ..
+        }
+        | ConnectionConfig::MySQL {
+            ssh_tunnel: Some(ref tunnel),
+            ..
+        } => {
+            tunnel.validate()?;
+            Ok(config)
+        }
+        config => Ok(config),
+    }
+}
+
+/// Where the file of a SQLite connection is on this machine.
+///
+/// None if it can't be found even though the connection was opened before, rather than letting SQLite create an
+/// empty database in its place. The files of connections that were never opened are yet to be created.
+fn locate_database_file(
+    connection_id: Uuid,
+    db_path: &str,
+    home_relative_path: Option<&str>,
+    state: &AppState,
+) -> Result<Option<PathBuf>, Error> {
+    if let Some(path) = paths::resolve(db_path, home_relative_path, paths::home_dir().as_deref()) {
+        return Ok(Some(path));
+    }
+
+    if state.storage.get_last_connected(&connection_id)?.is_some() {
+        return Ok(None);
+    }
+    Ok(Some(PathBuf::from(db_path)))
+}
+
 pub async fn connect_to_database(
     connection_id: Uuid,
     state: &AppState,
     monitor: &ConnectionMonitor,
     certificates: &Certificates,
+) -> Result<bool, Error> {
+    // The attempt a reconnect loop may be making goes first, and if it connects, its client is the one kept
+    let was_reconnecting = state.reconnects.stop(connection_id);
+    let _connecting = state.reconnects.lock(connection_id).await;
+    if was_reconnecting && is_connected(connection_id, state) {
+        return Ok(true);
+    }
+
+    open_connection(connection_id, state, monitor, certificates).await
+}
+
+fn is_connected(connection_id: Uuid, state: &AppState) -> bool {
+    state
+        .connections
+        .get(&connection_id)
+        .is_some_and(|connection| connection.is_client_connected())
+}
+
+fn reconnect_settings_key(connection_id: Uuid) -> String {
+    format!("reconnect.{connection_id}")
+}
+
+/// How a connection is reconnected after it drops
+pub async fn get_reconnect_settings(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<ReconnectSettings, Error> {
+    match state
+        .storage
+        .get_setting(&reconnect_settings_key(connection_id))?
+    {
+        Some(settings) => Ok(serde_json::from_str(&settings)?),
+        None => Ok(ReconnectSettings::default()),
+    }
+}
+
+pub async fn set_reconnect_settings(
+    connection_id: Uuid,
+    settings: ReconnectSettings,
+    state: &AppState,
+) -> Result<(), Error> {
+    state.storage.set_setting(
+        &reconnect_settings_key(connection_id),
+        &serde_json::to_string(&settings)?,
+    )?;
+    Ok(())
+}
+
+/// Reconnects a connection that dropped, with its reconnect settings. Gives up once the attempts run out, or
+/// when the connection is disconnected or connected by hand in the meantime. Returns whether it reconnected.
+pub async fn reconnect_to_database(
+    connection_id: Uuid,
+    state: &AppState,
+    monitor: &ConnectionMonitor,
+    certificates: &Certificates,
+    on_status: impl Fn(ReconnectStatus),
+) -> Result<bool, Error> {
+    let settings = get_reconnect_settings(connection_id, state).await?;
+    if !settings.enabled || settings.max_retries == 0 {
+        return Ok(false);
+    }
+    let Some(reconnect) = state.reconnects.start(connection_id) else {
+        log::info!("{connection_id} is already being reconnected");
+        return Ok(false);
+    };
+
+    let mut last_error = None;
+    for attempt in 1..=settings.max_retries {
+        let delay = settings.delay(attempt);
+        on_status(ReconnectStatus::Reconnecting {
+            attempt,
+            max_retries: settings.max_retries,
+            delay_ms: delay.as_millis() as u64,
+        });
+        if !reconnect.wait(delay).await {
+            return Ok(false);
+        }
+
+        let _connecting = state.reconnects.lock(connection_id).await;
+        if reconnect.is_stopped() {
+            return Ok(false);
+        }
+        log::info!(
+            "Reconnecting {connection_id}, attempt {attempt} of {}",
+            settings.max_retries
+        );
+        match open_connection(connection_id, state, monitor, certificates).await {
+            Ok(true) => {
+                on_status(ReconnectStatus::Reconnected);
+                return Ok(true);
+            }
+            Ok(false) => last_error = None,
+            Err(e) => {
+                log::warn!("Failed to reconnect {connection_id}: {e}");
+                last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    log::warn!(
+        "Gave up reconnecting {connection_id} after {} attempts",
+        settings.max_retries
+    );
+    on_status(ReconnectStatus::GaveUp { error: last_error });
+    Ok(false)
+}
+
+async fn open_connection(
+    connection_id: Uuid,
+    state: &AppState,
+    monitor: &ConnectionMonitor,
+    certificates: &Certificates,
 ) -> Result<bool, Error> {
     if !state.connections.contains_key(&connection_id) {
         let stored_connections = state.storage.get_connections()?;
@@ -114,6 +399,9 @@ pub async fn connect_to_database(
             let connection = Connection::new(
                 stored_connection.id,
                 stored_connection.name.clone(),
+                stored_connection.folder.clone(),
+                stored_connection.environment,
+                stored_connection.color.clone(),
                 stored_connection.config.clone(),
                 stored_connection.permissions,
             );
@@ -121,38 +409,128 @@ pub async fn connect_to_database(
         }
     }
 
-    let mut connection_entry = state
+    // Connecting may take a while (tunnels, secret commands, copying snapshots), so it's done without holding the
+    // connection's entry, which is only locked again to store the outcome
+    let (config, active_schema) = state
         .connections
-        .get_mut(&connection_id)
+        .get(&connection_id)
+        .map(|connection| (connection.config.clone(), connection.active_schema.clone()))
         .with_context(|| format!("Connection not found: {}", connection_id))?;
+    let database_file = match &config {
+        ConnectionConfig::SQLite {
+            db_path,
+            home_relative_path,
+        } => locate_database_file(connection_id, db_path, home_relative_path.as_deref(), state)?,
+        _ => None,
+    };
+    let snapshot = match &database_file {
+        Some(path) if snapshot_mode(connection_id, state)? => {
+            Some(open_snapshot(connection_id, path, state).await?)
+        }
+        _ => None,
+    };
 
-    let connection = connection_entry.value_mut();
+    let connection_entry = || {
+        state
+            .connections
+            .get_mut(&connection_id)
+            .with_context(|| format!("Connection not found: {}", connection_id))
+    };
 
-    match &connection.config {
+    match &config {
         ConnectionConfig::Postgres {
             connection_string,
             ca_cert_path,
+            ssh_tunnel,
+            default_schema,
         } => {
+            // The schema picked since connecting is kept when reconnecting
+            let active_schema = active_schema.or_else(|| default_schema.clone());
+            let (tunnel, connection_string) = match ssh_tunnel {
+                Some(ssh_tunnel) => {
+                    let (tunnel, tunneled) = SshTunnel::open(
+                        ssh_tunnel,
+                        connection_string,
+                        saved_ssh_password(connection_id, ssh_tunnel, state)?,
+                        Some(monitor.notifier(connection_id)),
+                    )
+                    .await?;
+                    (Some(tunnel), tunneled)
+                }
+                None => (None, connection_string.clone()),
+            };
+
             let mut config: tokio_postgres::Config =
                 connection_string.parse().with_context(|| {
                     format!("Failed to parse connection string: {}", connection_string)
                 })?;
             if config.get_password().is_none() {
-                credentials::get_password(&connection_id)?.map(|pw| config.password(pw));
+                let backends = secret_backends(connection_id, state)?;
+                credentials::resolve_password(
+                    &connection_id,
+                    &backends,
+                    &state.secret_cache,
+                    &state.credential_file,
+                )
+                .await?
+                .map(|pw| config.password(pw));
             }
 
-            match connect(
-                &config,
-                certificates,
-                ca_cert_path.as_deref(),
-                monitor.notifier(connection_id),
-            )
+            let pool_size = get_postgres_pool_size(connection_id, state).await?;
+            let tls =
+                postgres::connect::Tls::new(&config, certificates, ca_cert_path.as_deref()).await?;
+            let pool = PostgresPool::connect(pool_size, || async {
+                let messages = state.server_messages.forwarder(connection_id);
+                let client = connect(
+                    &config,
+                    certificates,
+                    ca_cert_path.as_deref(),
+                    monitor.notifier(connection_id),
+                    state.notifications.forwarder(connection_id),
+                    messages.clone(),
+                )
+                .await?;
+                if let Some(schema) = &active_schema {
+                    postgres::execute::set_search_path(&client, Some(schema))
+                        .await
+                        .with_context(|| format!("Failed to put {schema} in the search path"))?;
+                }
+                Ok((client, Some(messages)))
+            })
             .await
-            {
-                Ok(pg_client) => {
-                    connection.runtime = ConnectionRuntime::Connected(RuntimeClient::Postgres {
-                        client: Arc::new(pg_client),
-                    });
+            .map(|pool| pool.with_tls(tls));
+
+            match pool {
+                Ok(pool) => {
+                    let pg_client = pool.session();
+                    state.notifications.relisten(connection_id, pg_client).await;
+                    let limits = get_connection_resource_limits(connection_id, state).await?;
+                    if let Some(timeout_ms) = limits.query_timeout_ms {
+                        if let Err(e) =
+                            postgres::execute::set_statement_timeout(pg_client, Some(timeout_ms))
+                                .await
+                        {
+                            log::warn!("Failed to set the query timeout: {e}");
+                        }
+                    }
+                    let capabilities = flavor::detect_capabilities(pg_client, &config).await;
+                    log::info!(
+                        "Connected to a {} server: {}",
+                        capabilities.flavor.name(),
+                        capabilities.version
+                    );
+                    let mut connection = connection_entry()?;
+                    connection.capabilities = Some(capabilities);
+                    connection.tunnel = tunnel;
+                    connection.active_schema = active_schema;
+                    let pool = Arc::new(pool);
+                    tokio::spawn(pool::monitor_pool(
+                        Arc::downgrade(&pool),
+                        monitor.notifier(connection_id),
+                    ));
+                    connection.runtime =
+                        ConnectionRuntime::Connected(RuntimeClient::Postgres { pool });
+                    drop(connection);
 
                     if let Err(e) = state.storage.update_last_connected(&connection_id) {
                         log::warn!("Failed to update last connected timestamp: {}", e);
@@ -162,369 +540,4174 @@ pub async fn connect_to_database(
                 }
                 Err(e) => {
                     log::error!("Failed to connect to Postgres: {}", e);
-                    connection.runtime = ConnectionRuntime::Disconnected;
+                    connection_entry()?.runtime = ConnectionRuntime::Disconnected;
                     Ok(false)
                 }
             }
         }
-        ConnectionConfig::SQLite { db_path } => match rusqlite::Connection::open(db_path) {
-            Ok(conn) => {
-                connection.runtime = ConnectionRuntime::Connected(RuntimeClient::SQLite {
-                    connection: Arc::new(Mutex::new(conn)),
-                });
+        ConnectionConfig::SQLite { db_path, .. } => {
+            let Some(database_file) = database_file else {
+                log::warn!("The SQLite database {} is missing", db_path);
+                connection_entry()?.runtime = ConnectionRuntime::Disconnected;
+                return Ok(false);
+            };
 
-                if let Err(e) = state.storage.update_last_connected(&connection_id) {
-                    log::warn!("Failed to update last connected timestamp: {}", e);
-                }
+            let opened = match snapshot {
+                Some((conn, snapshot)) => Ok((conn, Some(snapshot))),
+                None => rusqlite::Connection::open(&database_file).map(|conn| (conn, None)),
+            };
+
+            match opened {
+                Ok((conn, snapshot)) => {
+                    let mut connection = connection_entry()?;
+                    connection.runtime = ConnectionRuntime::Connected(RuntimeClient::SQLite {
+                        connection: Arc::new(Mutex::new(conn)),
+                        trace: state.statement_traces.get(connection_id),
+                    });
+
+                    if let Err(e) = state.storage.update_last_connected(&connection_id) {
+                        log::warn!("Failed to update last connected timestamp: {}", e);
+                    }
 
-                log::info!("Successfully connected to SQLite database: {}", db_path);
-                Ok(true)
+                    match &snapshot {
+                        Some(snapshot) => log::info!(
+                            "Opened a snapshot of SQLite database {}: {}",
+                            database_file.display(),
+                            snapshot.local_path
+                        ),
+                        None => log::info!(
+                            "Successfully connected to SQLite database: {}",
+                            database_file.display()
+                        ),
+                    }
+                    connection.snapshot = snapshot;
+                    Ok(true)
+                }
+                Err(e) => {
+                    log::error!(
+                        "Failed to connect to SQLite database {}: {}",
+                        database_file.display(),
+                        e
+                    );
+                    connection_entry()?.runtime = ConnectionRuntime::Disconnected;
+                    Ok(false)
+                }
             }
-            Err(e) => {
-                log::error!("Failed to connect to SQLite database {}: {}", db_path, e);
-                connection.runtime = ConnectionRuntime::Disconnected;
-                Ok(false)
+        }
+        ConnectionConfig::MySQL {
+            connection_string,
+            ca_cert_path,
+            ssh_tunnel,
+        } => {
+            let opts = mysql::connect::parse_connection_string(connection_string)?;
+            let password = match opts.pass() {
+                Some(_) => None,
+                None => {
+                    let backends = secret_backends(connection_id, state)?;
+                    credentials::resolve_password(
+                        &connection_id,
+                        &backends,
+                        &state.secret_cache,
+                        &state.credential_file,
+                    )
+                    .await?
+                }
+            };
+            let opts = mysql::connect::complete_opts(
+                opts,
+                password,
+                certificates,
+                ca_cert_path.as_deref(),
+            )
+            .await;
+            let (tunnel, opts) = match ssh_tunnel {
+                Some(ssh_tunnel) => {
+                    let tunnel = SshTunnel::open_to(
+                        ssh_tunnel,
+                        mysql::connect::forward_target(&opts),
+                        saved_ssh_password(connection_id, ssh_tunnel, state)?,
+                        Some(monitor.notifier(connection_id)),
+                    )
+                    .await?;
+                    let opts = mysql::connect::tunneled_opts(opts, tunnel.local_port);
+                    (Some(tunnel), opts)
+                }
+                None => (None, opts),
+            };
+
+            match MySqlClient::connect(opts, Some(monitor.notifier(connection_id))).await {
+                Ok(client) => {
+                    let limits = get_connection_resource_limits(connection_id, state).await?;
+                    if let Some(timeout_ms) = limits.query_timeout_ms {
+                        let mut session = client.session().lock().await;
+                        if let Err(e) =
+                            mysql::execute::set_statement_timeout(&mut session, Some(timeout_ms))
+                                .await
+                        {
+                            log::warn!(
+                                "Failed to set the query timeout: {}",
+                                mysql::execute::DbError(&e)
+                            );
+                        }
+                    }
+                    let mut connection = connection_entry()?;
+                    connection.tunnel = tunnel;
+                    connection.runtime = ConnectionRuntime::Connected(RuntimeClient::MySQL {
+                        client: Arc::new(client),
+                    });
+                    drop(connection);
+
+                    if let Err(e) = state.storage.update_last_connected(&connection_id) {
+                        log::warn!("Failed to update last connected timestamp: {}", e);
+                    }
+
+                    log::info!("Connected to MySQL: {connection_string}");
+                    Ok(true)
+                }
+                Err(e) => {
+                    log::error!("Failed to connect to MySQL: {}", e);
+                    connection_entry()?.runtime = ConnectionRuntime::Disconnected;
+                    Ok(false)
+                }
             }
-        },
+        }
     }
 }
 
-pub async fn disconnect_from_database(connection_id: Uuid, state: &AppState) -> Result<(), Error> {
-    let mut connection_entry = state
-        .connections
-        .get_mut(&connection_id)
-        .with_context(|| format!("Connection not found: {}", connection_id))?;
-    let connection = connection_entry.value_mut();
+fn snapshot_mode_key(connection_id: Uuid) -> String {
+    format!("snapshot_mode.{connection_id}")
+}
 
-    connection.runtime = ConnectionRuntime::Disconnected;
+fn snapshot_mode(connection_id: Uuid, state: &AppState) -> Result<bool, Error> {
+    match state
+        .storage
+        .get_setting(&snapshot_mode_key(connection_id))?
+    {
+        Some(enabled) => Ok(serde_json::from_str(&enabled)?),
+        None => Ok(false),
+    }
+}
+
+/// Whether a SQLite connection opens a local, read-only copy of its file rather than the file itself
+pub async fn get_snapshot_mode(connection_id: Uuid, state: &AppState) -> Result<bool, Error> {
+    snapshot_mode(connection_id, state)
+}
+
+/// Takes effect the next time the connection is opened
+pub async fn set_snapshot_mode(
+    connection_id: Uuid,
+    enabled: bool,
+    state: &AppState,
+) -> Result<(), Error> {
+    state.storage.set_setting(
+        &snapshot_mode_key(connection_id),
+        &serde_json::to_string(&enabled)?,
+    )?;
     Ok(())
 }
 
-pub async fn submit_query(
+/// Opens a snapshot of `source`, copying it first unless the cached copy is still up to date. The cache is read
+/// and written on blocking threads, the copy sending its progress back here.
+async fn open_snapshot(
     connection_id: Uuid,
-    query: &str,
+    source: &Path,
     state: &AppState,
-) -> Result<Vec<usize>, Error> {
-    let connection_entry = state
-        .connections
-        .get(&connection_id)
-        .with_context(|| format!("Connection not found: {}", connection_id))?;
+) -> Result<(rusqlite::Connection, SnapshotInfo), Error> {
+    let snapshots = state.snapshots.clone();
+    let path = source.to_owned();
+    let cached =
+        tokio::task::spawn_blocking(move || snapshots.cached(connection_id, &path)).await??;
+
+    let snapshot = match cached {
+        Some(snapshot) => snapshot,
+        None => {
+            let operation = state.operations.start(
+                OperationKind::Snapshot,
+                format!("Copying {}", source.display()),
+                true,
+            );
+            let token = operation.token().clone();
+            let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+            let snapshots = state.snapshots.clone();
+            let path = source.to_owned();
+            let copy = tokio::task::spawn_blocking(move || {
+                snapshots.copy(connection_id, &path, |copied, total| {
+                    if token.is_cancelled() {
+                        return Err(anyhow::anyhow!("Operation cancelled").into());
+                    }
+                    let _ = sender.send((copied, total));
+                    Ok(())
+                })
+            });
+            while let Some((copied, total)) = receiver.recv().await {
+                operation.set_progress(copied as usize, total as usize);
+            }
+            let snapshot = operation.complete(copy.await?)?;
 
-    let connection = connection_entry.value();
+            let mut in_use: Vec<Uuid> = state
+                .connections
+                .iter()
+                .filter(|connection| {
+                    connection.snapshot.is_some() && connection.is_client_connected()
+                })
+                .map(|connection| connection.id)
+                .collect();
+            in_use.push(connection_id);
+            let snapshots = state.snapshots.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = snapshots.evict(&in_use) {
+                    log::warn!("Failed to evict snapshots: {}", e);
+                }
+            })
+            .await?;
 
-    let client = connection.get_client()?;
-    let query_ids = state.stmt_manager.submit_query(client, query)?;
+            snapshot
+        }
+    };
 
-    Ok(query_ids)
+    let conn = rusqlite::Connection::open_with_flags(
+        &snapshot.local_path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    Ok((conn, snapshot))
 }
 
-pub async fn wait_until_renderable(
-    query_id: usize,
+/// Copies the source of a snapshot again if it changed since, and switches the connection over to the new copy
+pub async fn refresh_snapshot(
+    connection_id: Uuid,
     state: &AppState,
-) -> Result<QuerySnapshot, Error> {
-    let now = Instant::now();
+) -> Result<SnapshotRefresh, Error> {
+    let (config, current) = state
+        .connections
+        .get(&connection_id)
+        .map(|connection| (connection.config.clone(), connection.snapshot.clone()))
+        .with_context(|| format!("Connection not found: {}", connection_id))?;
 
-    let info = state
-        .stmt_manager
-        .fetch_initial_renderable_state(query_id)
-        .await?;
-    let elapsed = now.elapsed();
-    log::info!("wait_until_renderable took {}ms", elapsed.as_millis());
-    Ok(info)
-}
+    let (
+        ConnectionConfig::SQLite {
+            db_path,
+            home_relative_path,
+        },
+        Some(current),
+    ) = (config, current)
+    else {
+        return Err(anyhow::anyhow!("This connection isn't opened as a snapshot").into());
+    };
 
-pub async fn fetch_page(
-    query_id: usize,
-    page_index: usize,
-    state: &AppState,
-) -> Result<Option<Box<RawValue>>, Error> {
-    let now = Instant::now();
-    let page = state.stmt_manager.fetch_page(query_id, page_index)?;
-    let elapsed = now.elapsed();
-    log::info!("Took {}us to get page {page_index}", elapsed.as_micros());
+    let source = locate_database_file(
+        connection_id,
+        &db_path,
+        home_relative_path.as_deref(),
+        state,
+    )?
+    .with_context(|| format!("The SQLite database {db_path} is missing"))?;
+    let (conn, snapshot) = open_snapshot(connection_id, &source, state).await?;
+    let refreshed = snapshot.local_path != current.local_path;
+    if refreshed {
+        let mut connection = state
+            .connections
+            .get_mut(&connection_id)
+            .with_context(|| format!("Connection not found: {}", connection_id))?;
+        if connection.is_client_connected() {
+            connection.runtime = ConnectionRuntime::Connected(RuntimeClient::SQLite {
+                connection: Arc::new(Mutex::new(conn)),
+                trace: state.statement_traces.get(connection_id),
+            });
+        }
+        connection.snapshot = Some(snapshot.clone());
+        drop(connection);
 
-    Ok(page)
+        state.schemas.remove(&connection_id);
+    }
+
+    Ok(SnapshotRefresh {
+        refreshed,
+        snapshot,
+    })
 }
 
-pub async fn get_query_status(query_id: usize, state: &AppState) -> Result<QueryStatus, Error> {
-    state.stmt_manager.get_query_status(query_id)
+fn connection_environment(connection_id: Uuid, state: &AppState) -> Option<Environment> {
+    state
+        .connections
+        .get(&connection_id)
+        .and_then(|connection| connection.environment)
 }
 
-pub async fn get_page_count(query_id: usize, state: &AppState) -> Result<usize, Error> {
-    state.stmt_manager.get_page_count(query_id)
+fn connection_snapshot(connection_id: Uuid, state: &AppState) -> Option<SnapshotInfo> {
+    state
+        .connections
+        .get(&connection_id)
+        .and_then(|connection| connection.snapshot.clone())
 }
 
-pub async fn get_connections(state: &AppState) -> Result<Vec<ConnectionInfo>, Error> {
-    let mut stored_connections = state.storage.get_connections()?;
+pub async fn disconnect_from_database(connection_id: Uuid, state: &AppState) -> Result<(), Error> {
+    state.reconnects.stop(connection_id);
+    let mut connection_entry = state
+        .connections
+        .get_mut(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?;
+    let connection = connection_entry.value_mut();
 
-    for connection in &mut stored_connections {
-        if let Some(runtime_connection) = state.connections.get(&connection.id) {
-            connection.connected = runtime_connection.is_client_connected();
-        } else {
-            connection.connected = false;
-        }
+    // The clients are dropped along with the runtime, which would end their transactions without saying so
+    let runtime = std::mem::replace(&mut connection.runtime, ConnectionRuntime::Disconnected);
+    connection.capabilities = None;
+    connection.active_schema = None;
+    let tunnel = connection.tunnel.take();
+    drop(connection_entry);
+
+    let rolled_back = state.transactions.rollback_all(connection_id).await;
+    if !rolled_back.is_empty() {
+        log::warn!(
+            "Rolled back the open transactions of {} tab(s) while disconnecting {connection_id}",
+            rolled_back.len()
+        );
     }
+    drop((runtime, tunnel));
+    state.notifications.clear(connection_id);
+    state.postgres_stats.forget(connection_id);
+    Ok(())
+}
 
-    Ok(stored_connections)
+/// How the pings of a connected connection went. None if it's disconnected, or wasn't pinged yet: SQLite
+/// connections never are, being local files.
+pub async fn get_connection_health(
+    connection_id: Uuid,
+    state: &AppState,
+    monitor: &ConnectionMonitor,
+) -> Result<Option<ConnectionHealth>, Error> {
+    if !state.connections.contains_key(&connection_id) {
+        return Err(anyhow::anyhow!("Connection not found: {}", connection_id).into());
+    }
+    Ok(is_connected(connection_id, state)
+        .then(|| monitor.health(connection_id))
+        .flatten())
 }
 
-pub async fn remove_connection(connection_id: Uuid, state: &AppState) -> Result<(), Error> {
-    if let Err(e) = credentials::delete_password(&connection_id) {
-        log::debug!(
-            "Could not delete password from keyring (may not exist): {}",
-            e
-        );
+/// The client a tab's statements run on: that of its transaction if it has one open, else the connection's
+fn run_client(
+    connection_id: Uuid,
+    tab_id: Option<&str>,
+    state: &AppState,
+) -> Result<RuntimeClient, Error> {
+    match state.transactions.client_for(connection_id, tab_id)? {
+        Some(client) => Ok(client),
+        None => connection_client(connection_id, state),
     }
+}
 
-    state.storage.remove_connection(&connection_id)?;
-    state.connections.remove(&connection_id);
+/// Opens a transaction for an editor tab, which the tab's statements then run in until it's committed or rolled
+/// back
+pub async fn begin_transaction(
+    connection_id: Uuid,
+    tab_id: &str,
+    state: &AppState,
+) -> Result<(), Error> {
+    let client = connection_client(connection_id, state)?;
+    let limits = get_connection_resource_limits(connection_id, state).await?;
+    state
+        .transactions
+        .begin(connection_id, tab_id, client, limits.query_timeout_ms)
+        .await
+}
 
-    Ok(())
+pub async fn commit_transaction(
+    connection_id: Uuid,
+    tab_id: &str,
+    state: &AppState,
+) -> Result<(), Error> {
+    state.transactions.end(connection_id, tab_id, true).await
 }
 
-pub async fn test_connection(
-    // It's expected that test_connection receives config with the password included
-    config: ConnectionConfig,
-    certificates: &Certificates,
+pub async fn rollback_transaction(
+    connection_id: Uuid,
+    tab_id: &str,
+    state: &AppState,
+) -> Result<(), Error> {
+    state.transactions.end(connection_id, tab_id, false).await
+}
+
+/// The transactions open on a connection, oldest first
+pub async fn get_transaction_state(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<Vec<TransactionInfo>, Error> {
+    Ok(state.transactions.open_on(connection_id))
+}
+
+pub async fn submit_query(
+    connection_id: Uuid,
+    query: &str,
+    options: SubmitOptions,
+    state: &AppState,
+) -> Result<SubmitOutcome, Error> {
+    // Statements are marked as they're written in the editor
+    let written = query;
+    let query = &substitute_variables(query, Some(connection_id), state)?;
+    let limits = options
+        .resource_limits
+        .or(get_connection_resource_limits(connection_id, state).await?);
+    check_resource_limits(connection_id, &limits, state)?;
+    let settings = get_preflight_settings(connection_id, state).await?;
+
+    let client = run_client(connection_id, options.tab_id.as_deref(), state)?;
+    if let Some(snapshot) = connection_snapshot(connection_id, state) {
+        snapshot::reject_writes(query, &snapshot)?;
+    }
+
+    let mut report = match &client {
+        _ if !settings.production => PreflightReport::default(),
+        // On the session client, since the query may use its temporary tables
+        RuntimeClient::Postgres { pool } if settings.estimate_cost => {
+            let timeout = Duration::from_millis(settings.timeout_ms);
+            let estimates = postgres::explain::estimate_costs(pool.session(), query, timeout).await;
+            PreflightReport::from_estimates(estimates, &settings)
+        }
+        RuntimeClient::Postgres { .. } => PreflightReport::default(),
+        RuntimeClient::SQLite { .. } | RuntimeClient::MySQL { .. } => {
+            PreflightReport::from_lint(query)
+        }
+    };
+    if settings.warn_suspicious_characters {
+        report.check_suspicious_characters(query);
+    }
+    if let Some(environment) = connection_environment(connection_id, state) {
+        let policy = get_environment_policies(state).await?.of(environment);
+        // Scripts that don't parse fail once submitted anyway
+        if let Ok(statements) = client.parse_statements(query) {
+            report.check_policy(policy, query, &statements);
+        }
+    }
+
+    let run_options = RunOptions {
+        limits,
+        tag: render_query_tag(
+            connection_id,
+            &client,
+            options.script_name.as_deref(),
+            state,
+        )?,
+        savepoints: statement_savepoints(
+            connection_id,
+            matches!(client, RuntimeClient::Postgres { .. }),
+            state,
+        )?,
+        params: options.params,
+    };
+
+    // A new run from the same tab supersedes the one held back for it, which won't be confirmed anymore
+    state
+        .pending_runs
+        .retain(|_, run| run.connection_id != connection_id || run.tab_id != options.tab_id);
+
+    if report.requires_confirmation() {
+        let run_id = Uuid::new_v4();
+        state.pending_runs.insert(
+            run_id,
+            PendingRun {
+                connection_id,
+                query: query.to_owned(),
+                written_query: written.to_owned(),
+                options: run_options,
+                tab_id: options.tab_id,
+            },
+        );
+        return Ok(SubmitOutcome::ConfirmationRequired { run_id, report });
+    }
+
+    settle_execution_marks(state)?;
+    let query_ids = state
+        .stmt_manager
+        .submit_query(connection_id, client, query, &run_options)?;
+    state
+        .execution_marks
+        .start_run(options.tab_id.as_deref(), written, &query_ids);
+
+    Ok(SubmitOutcome::Submitted { query_ids })
+}
+
+/// Runs a query that `submit_query` held back for confirmation
+pub async fn confirm_run(run_id: Uuid, state: &AppState) -> Result<Vec<usize>, Error> {
+    let (_, run) = state
+        .pending_runs
+        .remove(&run_id)
+        .with_context(|| format!("Run not found or already confirmed: {run_id}"))?;
+
+    let client = run_client(run.connection_id, run.tab_id.as_deref(), state)?;
+    settle_execution_marks(state)?;
+    let query_ids =
+        state
+            .stmt_manager
+            .submit_query(run.connection_id, client, &run.query, &run.options)?;
+    state
+        .execution_marks
+        .start_run(run.tab_id.as_deref(), &run.written_query, &query_ids);
+
+    Ok(query_ids)
+}
+
+/// Forgets a run that `submit_query` held back, once it's declined
+pub async fn discard_run(run_id: Uuid, state: &AppState) -> Result<(), Error> {
+    state.pending_runs.remove(&run_id);
+    Ok(())
+}
+
+/// Runs the query fetching a page of a table, ordered by its primary key, see [`browse`]. Pages are 0-based.
+pub async fn browse_table(
+    connection_id: Uuid,
+    schema: &str,
+    table: &str,
+    page_index: usize,
+    page_size: usize,
+    state: &AppState,
+) -> Result<BrowsePage, Error> {
+    let client = connection_client(connection_id, state)?;
+    let count = page_index == 0;
+
+    let (database, order_by, total) = match &client {
+        RuntimeClient::Postgres { pool } => {
+            let client = pool.metadata();
+            let order_by = browse::postgres_order_columns(client, schema, table).await?;
+            let total = match count {
+                true => browse::postgres_row_count(client, schema, table).await?,
+                false => None,
+            };
+            (Database::Postgres, order_by, total)
+        }
+        RuntimeClient::SQLite { connection, .. } => {
+            let connection = connection.clone();
+            let (schema, table) = (schema.to_owned(), table.to_owned());
+            tokio::task::spawn_blocking(move || {
+                let conn = connection.lock().unwrap();
+                let order_by = browse::sqlite_order_columns(&conn, &schema, &table)?;
+                let total = match count {
+                    true => Some((browse::sqlite_row_count(&conn, &schema, &table)?, false)),
+                    false => None,
+                };
+                Ok::<_, Error>((Database::Sqlite, order_by, total))
+            })
+            .await??
+        }
+        RuntimeClient::MySQL { client } => {
+            let mut conn = client.metadata().await?;
+            let order_by = browse::mysql_order_columns(&mut conn, schema, table).await?;
+            let total = match count {
+                true => Some(browse::mysql_row_count(&mut conn, schema, table).await?),
+                false => None,
+            };
+            (Database::MySql, order_by, total)
+        }
+    };
+
+    let query = browse::page_query(database, schema, table, &order_by, page_index, page_size)?;
+    let run_options = RunOptions {
+        limits: get_connection_resource_limits(connection_id, state).await?,
+        ..Default::default()
+    };
+    settle_execution_marks(state)?;
+    let query_ids = state
+        .stmt_manager
+        .submit_query(connection_id, client, &query, &run_options)?;
+    state.execution_marks.start_run(None, &query, &query_ids);
+    let query_id = *query_ids
+        .first()
+        .context("The page query has no statements")?;
+
+    Ok(BrowsePage {
+        query_id,
+        query,
+        order_by,
+        total_rows: total.map(|(rows, _)| rows),
+        total_is_estimate: total.is_some_and(|(_, estimated)| estimated),
+    })
+}
+
+fn connection_client(connection_id: Uuid, state: &AppState) -> Result<RuntimeClient, Error> {
+    let connection_entry = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?;
+
+    connection_entry.value().get_client()
+}
+
+/// Starts or stops recording the statements sent to a SQLite connection
+pub async fn set_statement_tracing(
+    connection_id: Uuid,
+    enabled: bool,
+    state: &AppState,
+) -> Result<(), Error> {
+    state.statement_traces.set_enabled(connection_id, enabled);
+    Ok(())
+}
+
+/// The latest `limit` statements sent to a connection while tracing was enabled, oldest first
+pub async fn get_statement_trace(
+    connection_id: Uuid,
+    limit: usize,
+    state: &AppState,
+) -> Result<Vec<TracedStatement>, Error> {
+    Ok(state.statement_traces.latest(connection_id, limit))
+}
+
+pub async fn clear_statement_trace(connection_id: Uuid, state: &AppState) -> Result<(), Error> {
+    state.statement_traces.clear(connection_id);
+    Ok(())
+}
+
+fn preflight_settings_key(connection_id: Uuid) -> String {
+    format!("preflight.{connection_id}")
+}
+
+/// Whether a connection is tagged as production, and the thresholds its queries are checked against
+pub async fn get_preflight_settings(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<PreflightSettings, Error> {
+    match state
+        .storage
+        .get_setting(&preflight_settings_key(connection_id))?
+    {
+        Some(settings) => Ok(serde_json::from_str(&settings)?),
+        None => Ok(PreflightSettings::default()),
+    }
+}
+
+pub async fn set_preflight_settings(
+    connection_id: Uuid,
+    settings: PreflightSettings,
+    state: &AppState,
+) -> Result<(), Error> {
+    state.storage.set_setting(
+        &preflight_settings_key(connection_id),
+        &serde_json::to_string(&settings)?,
+    )?;
+    Ok(())
+}
+
+const ENVIRONMENT_POLICIES_KEY: &str = "environment_policies";
+
+/// What runs must be confirmed on the connections of each environment
+pub async fn get_environment_policies(state: &AppState) -> Result<EnvironmentPolicies, Error> {
+    match state.storage.get_setting(ENVIRONMENT_POLICIES_KEY)? {
+        Some(policies) => Ok(serde_json::from_str(&policies)?),
+        None => Ok(EnvironmentPolicies::default()),
+    }
+}
+
+pub async fn set_environment_policies(
+    policies: EnvironmentPolicies,
+    state: &AppState,
+) -> Result<(), Error> {
+    state
+        .storage
+        .set_setting(ENVIRONMENT_POLICIES_KEY, &serde_json::to_string(&policies)?)?;
+    Ok(())
+}
+
+fn statement_savepoints_key(connection_id: Uuid) -> String {
+    format!("statement_savepoints.{connection_id}")
+}
+
+/// Whether statements run in a transaction block get a savepoint each, so that one failing doesn't abort the
+/// transaction. On by default for Postgres, where a failing statement aborts the whole transaction.
+fn statement_savepoints(
+    connection_id: Uuid,
+    is_postgres: bool,
+    state: &AppState,
 ) -> Result<bool, Error> {
-    match config {
-        ConnectionConfig::Postgres {
-            connection_string,
-            ca_cert_path,
-        } => {
-            let config: tokio_postgres::Config = connection_string.parse().with_context(|| {
-                format!("Failed to parse connection string: {}", connection_string)
-            })?;
-            log::info!("Testing Postgres connection: {config:?}");
-            match postgres::connect::test_connection(&config, certificates, ca_cert_path.as_deref())
+    match state
+        .storage
+        .get_setting(&statement_savepoints_key(connection_id))?
+    {
+        Some(enabled) => Ok(serde_json::from_str(&enabled)?),
+        None => Ok(is_postgres),
+    }
+}
+
+pub async fn get_statement_savepoints(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<bool, Error> {
+    let is_postgres = matches!(
+        state
+            .connections
+            .get(&connection_id)
+            .with_context(|| format!("Connection not found: {}", connection_id))?
+            .config,
+        ConnectionConfig::Postgres { .. }
+    );
+    statement_savepoints(connection_id, is_postgres, state)
+}
+
+pub async fn set_statement_savepoints(
+    connection_id: Uuid,
+    enabled: bool,
+    state: &AppState,
+) -> Result<(), Error> {
+    state.storage.set_setting(
+        &statement_savepoints_key(connection_id),
+        &serde_json::to_string(&enabled)?,
+    )?;
+    Ok(())
+}
+
+fn query_tag_settings_key(connection_id: Uuid) -> String {
+    format!("query_tags.{connection_id}")
+}
+
+/// Whether the statements sent to a connection are tagged with a comment, and what the comment says
+pub async fn get_query_tag_settings(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<QueryTagSettings, Error> {
+    match state
+        .storage
+        .get_setting(&query_tag_settings_key(connection_id))?
+    {
+        Some(settings) => Ok(serde_json::from_str(&settings)?),
+        None => Ok(QueryTagSettings::default()),
+    }
+}
+
+pub async fn set_query_tag_settings(
+    connection_id: Uuid,
+    settings: QueryTagSettings,
+    state: &AppState,
+) -> Result<(), Error> {
+    state.storage.set_setting(
+        &query_tag_settings_key(connection_id),
+        &serde_json::to_string(&settings)?,
+    )?;
+    Ok(())
+}
+
+/// The tag for a new run on a connection, if tagging is enabled for it.
+/// Local database files have nobody to attribute load to, so they're never tagged.
+fn render_query_tag(
+    connection_id: Uuid,
+    client: &RuntimeClient,
+    script: Option<&str>,
+    state: &AppState,
+) -> Result<Option<String>, Error> {
+    if matches!(client, RuntimeClient::SQLite { .. }) {
+        return Ok(None);
+    }
+
+    let settings = match state
+        .storage
+        .get_setting(&query_tag_settings_key(connection_id))?
+    {
+        Some(settings) => serde_json::from_str::<QueryTagSettings>(&settings)?,
+        None => return Ok(None),
+    };
+    if !settings.enabled {
+        return Ok(None);
+    }
+
+    let connection = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?;
+    let context = TagContext {
+        connection: &connection.name,
+        script,
+        run_id: Uuid::new_v4(),
+    };
+
+    Ok(Some(query_tags::render_tag(&settings.template, &context)))
+}
+
+fn secret_backends_key(connection_id: Uuid) -> String {
+    format!("secret_backends.{connection_id}")
+}
+
+fn secret_backends(connection_id: Uuid, state: &AppState) -> Result<Vec<SecretBackend>, Error> {
+    match state
+        .storage
+        .get_setting(&secret_backends_key(connection_id))?
+    {
+        Some(backends) => Ok(serde_json::from_str(&backends)?),
+        None => Ok(vec![SecretBackend::Keyring]),
+    }
+}
+
+/// The secret backends a connection's password is looked up in, in order
+pub async fn get_secret_backends(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<Vec<SecretBackend>, Error> {
+    secret_backends(connection_id, state)
+}
+
+pub async fn set_secret_backends(
+    connection_id: Uuid,
+    backends: Vec<SecretBackend>,
+    state: &AppState,
+) -> Result<(), Error> {
+    state.storage.set_setting(
+        &secret_backends_key(connection_id),
+        &serde_json::to_string(&backends)?,
+    )?;
+    // The cached password might have come from a backend that's no longer used
+    state.secret_cache.forget(&connection_id);
+    Ok(())
+}
+
+/// Whether secrets go to the keyring or to the credential file, and whether the file can be used
+pub async fn get_credential_backend_status(
+    state: &AppState,
+) -> Result<CredentialBackendStatus, Error> {
+    Ok(credentials::backend_status(&state.credential_file))
+}
+
+/// Unlocks the credential file for this session, creating it with this passphrase if there's none yet
+pub async fn unlock_credential_file(
+    passphrase: String,
+    state: &AppState,
+) -> Result<CredentialBackendStatus, Error> {
+    state.credential_file.unlock(&passphrase)?;
+    Ok(credentials::backend_status(&state.credential_file))
+}
+
+fn postgres_pool_size_key(connection_id: Uuid) -> String {
+    format!("postgres_pool_size.{connection_id}")
+}
+
+/// How many clients a Postgres connection opens, see [`pool`]. Takes effect on the next connect.
+pub async fn get_postgres_pool_size(connection_id: Uuid, state: &AppState) -> Result<usize, Error> {
+    match state
+        .storage
+        .get_setting(&postgres_pool_size_key(connection_id))?
+    {
+        Some(size) => Ok(serde_json::from_str(&size)?),
+        None => Ok(pool::DEFAULT_POOL_SIZE),
+    }
+}
+
+pub async fn set_postgres_pool_size(
+    connection_id: Uuid,
+    size: usize,
+    state: &AppState,
+) -> Result<(), Error> {
+    if !(pool::MIN_POOL_SIZE..=pool::MAX_POOL_SIZE).contains(&size) {
+        return Err(anyhow::anyhow!(
+            "Postgres connections have between {} and {} clients, not {size}",
+            pool::MIN_POOL_SIZE,
+            pool::MAX_POOL_SIZE
+        )
+        .into());
+    }
+
+    state.storage.set_setting(
+        &postgres_pool_size_key(connection_id),
+        &serde_json::to_string(&size)?,
+    )?;
+    Ok(())
+}
+
+/// Cancels the query running on a Postgres or MySQL connection, if any, from another of its clients
+pub async fn cancel_postgres(connection_id: Uuid, state: &AppState) -> Result<(), Error> {
+    cancel_session_query(&connection_client(connection_id, state)?).await
+}
+
+async fn cancel_session_query(client: &RuntimeClient) -> Result<(), Error> {
+    match client {
+        RuntimeClient::Postgres { pool } => pool.cancel_session_query().await,
+        RuntimeClient::MySQL { client } => client.cancel_session_query().await,
+        RuntimeClient::SQLite { .. } => {
+            Err(anyhow::anyhow!("Only Postgres and MySQL queries can be cancelled this way").into())
+        }
+    }
+}
+
+fn resource_limits_key(connection_id: Uuid) -> String {
+    format!("resource_limits.{connection_id}")
+}
+
+/// The resource limits applied to every run on a connection, unless overridden when submitting a query
+pub async fn get_connection_resource_limits(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<ResourceLimits, Error> {
+    match state
+        .storage
+        .get_setting(&resource_limits_key(connection_id))?
+    {
+        Some(limits) => Ok(serde_json::from_str(&limits)?),
+        None => Ok(ResourceLimits::default()),
+    }
+}
+
+/// Fails for limits the connection's engine can't honor, rather than running without them
+fn check_resource_limits(
+    connection_id: Uuid,
+    limits: &ResourceLimits,
+    state: &AppState,
+) -> Result<(), Error> {
+    let db = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?
+        .config
+        .kind();
+    if db == Database::MySql && limits.soft_heap_limit_bytes.is_some() {
+        return Err(anyhow::anyhow!(
+            "MySQL connections can't have a soft heap limit, only SQLite ones can"
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub async fn set_connection_resource_limits(
+    connection_id: Uuid,
+    limits: ResourceLimits,
+    state: &AppState,
+) -> Result<(), Error> {
+    check_resource_limits(connection_id, &limits, state)?;
+    state.storage.set_setting(
+        &resource_limits_key(connection_id),
+        &serde_json::to_string(&limits)?,
+    )?;
+
+    match connection_client(connection_id, state) {
+        Ok(RuntimeClient::Postgres { pool }) => {
+            postgres::execute::set_statement_timeout(pool.session(), limits.query_timeout_ms)
                 .await
-            {
-                Ok(()) => Ok(true),
-                Err(e) => {
-                    log::error!("Postgres connection test failed: {}", e);
-                    Ok(false)
-                }
-            }
+                .context("Failed to set the connection's statement_timeout")?;
         }
-        ConnectionConfig::SQLite { db_path } => match rusqlite::Connection::open(db_path) {
-            Ok(_) => Ok(true),
-            Err(e) => {
-                log::error!("SQLite connection test failed: {}", e);
-                Ok(false)
-            }
-        },
+        Ok(RuntimeClient::MySQL { client }) => {
+            let mut session = client.session().lock().await;
+            mysql::execute::set_statement_timeout(&mut session, limits.query_timeout_ms)
+                .await
+                .context("Failed to set the connection's query timeout")?;
+        }
+        Ok(RuntimeClient::SQLite { .. }) | Err(_) => {}
     }
+    Ok(())
+}
+
+pub async fn wait_until_renderable(
+    query_id: usize,
+    state: &AppState,
+) -> Result<QuerySnapshot, Error> {
+    let now = Instant::now();
+
+    let info = state
+        .stmt_manager
+        .fetch_initial_renderable_state(query_id)
+        .await?;
+    let elapsed = now.elapsed();
+    log::info!("wait_until_renderable took {}ms", elapsed.as_millis());
+    Ok(info)
+}
+
+/// How long [`cancel_all_queries`] keeps cancelling the Postgres and MySQL statements of a connection
+const CANCEL_ALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Cancels the statements of a connection that aren't over yet. Returns how many were cancelled.
+pub async fn cancel_all_queries(connection_id: Uuid, state: &AppState) -> Result<usize, Error> {
+    let cancelled = state.stmt_manager.cancel_connection_queries(connection_id);
+
+    // The statements may be running in transactions, on clients of their own
+    let clients: Vec<_> = connection_client(connection_id, state)
+        .into_iter()
+        .chain(state.transactions.clients_on(connection_id))
+        .filter(|client| !matches!(client, RuntimeClient::SQLite { .. }))
+        .collect();
+
+    if !cancelled.is_empty() && !clients.is_empty() {
+        // Statements sent together queue up on the server, which only cancels the one running, so cancel until
+        // they're all over
+        let deadline = Instant::now() + CANCEL_ALL_TIMEOUT;
+        while Instant::now() < deadline {
+            let mut running = false;
+            for query_id in &cancelled {
+                running |= !state.stmt_manager.is_query_over(*query_id)?;
+            }
+            if !running {
+                break;
+            }
+            for client in &clients {
+                cancel_session_query(client).await?;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    Ok(cancelled.len())
+}
+
+/// Which statement of the submitted script each query is, in the order given. None for derived results.
+pub async fn get_statement_infos(
+    query_ids: &[usize],
+    state: &AppState,
+) -> Result<Vec<Option<StatementInfo>>, Error> {
+    query_ids
+        .iter()
+        .map(|query_id| state.stmt_manager.get_statement_info(*query_id))
+        .collect()
+}
+
+pub async fn fetch_page(
+    query_id: usize,
+    page_index: usize,
+    state: &AppState,
+) -> Result<Option<Box<RawValue>>, Error> {
+    let now = Instant::now();
+    let page = state.stmt_manager.fetch_page(query_id, page_index)?;
+    let elapsed = now.elapsed();
+    log::info!("Took {}us to get page {page_index}", elapsed.as_micros());
+
+    Ok(page)
+}
+
+pub async fn get_query_status(query_id: usize, state: &AppState) -> Result<QueryStatus, Error> {
+    state.stmt_manager.get_query_status(query_id)
+}
+
+pub async fn get_page_count(query_id: usize, state: &AppState) -> Result<usize, Error> {
+    state.stmt_manager.get_page_count(query_id)
+}
+
+/// Lets a Postgres query that stopped fetching rows ahead of those read fetch more, see
+/// [`FetchBudget`](super::types::FetchBudget)
+pub async fn request_more_rows(query_id: usize, state: &AppState) -> Result<(), Error> {
+    state.stmt_manager.request_more_rows(query_id)
+}
+
+/// Where the time of a query went: parsing, queueing, execution, fetching and serialization.
+/// None while the query is still running.
+pub async fn get_timing_breakdown(
+    query_id: usize,
+    state: &AppState,
+) -> Result<Option<TimingBreakdown>, Error> {
+    state.stmt_manager.get_timing_breakdown(query_id)
+}
+
+pub async fn get_row_detail(
+    query_id: usize,
+    row_index: usize,
+    state: &AppState,
+) -> Result<Vec<RowDetailField>, Error> {
+    state.stmt_manager.get_row_detail(query_id, row_index)
+}
+
+/// Statistics of a column of a query's results, over the pages fetched so far. Big results take a while, so
+/// this is an operation that can be cancelled.
+pub async fn get_column_stats(
+    query_id: usize,
+    column_index: usize,
+    state: &AppState,
+) -> Result<ColumnStats, Error> {
+    let columns: Vec<String> = match state.stmt_manager.get_columns(query_id)? {
+        Some(columns) => serde_json::from_str(columns.get())?,
+        None => Vec::new(),
+    };
+    let column = columns
+        .get(column_index)
+        .with_context(|| format!("The results have no column {column_index}"))?;
+    let column_type = state
+        .stmt_manager
+        .get_column_types(query_id)?
+        .get(column_index)
+        .cloned()
+        .flatten();
+
+    let operation = state.operations.start(
+        OperationKind::ColumnStats,
+        format!("Computing the statistics of {column}"),
+        true,
+    );
+    let builder = ColumnStatsBuilder::new(column.clone(), column_type);
+    let result = compute_column_stats(query_id, column_index, builder, &operation, state).await;
+    operation.complete(result)
+}
+
+async fn compute_column_stats(
+    query_id: usize,
+    column_index: usize,
+    mut builder: ColumnStatsBuilder,
+    operation: &Operation<'_>,
+    state: &AppState,
+) -> Result<ColumnStats, Error> {
+    // Pages fetched from now on aren't counted
+    let over = state.stmt_manager.is_query_over(query_id)?;
+    let page_count = state.stmt_manager.get_page_count(query_id)?;
+
+    for page_index in 0..page_count {
+        operation.check_cancelled()?;
+        if let Some(page) = state.stmt_manager.fetch_page(query_id, page_index)? {
+            builder.add_page(page.get(), column_index)?;
+        }
+        operation.set_progress(page_index + 1, page_count);
+        // Gives the cancellation a chance to come in between pages
+        tokio::task::yield_now().await;
+    }
+
+    let mut stats = builder.finish();
+    stats.partial = !over || state.stmt_manager.is_truncated(query_id)?;
+    Ok(stats)
+}
+
+pub async fn get_connections(state: &AppState) -> Result<Vec<ConnectionInfo>, Error> {
+    let mut stored_connections = state.storage.get_connections()?;
+
+    for connection in &mut stored_connections {
+        if let Some(runtime_connection) = state.connections.get(&connection.id) {
+            connection.connected = runtime_connection.is_client_connected();
+            connection.snapshot = runtime_connection.snapshot.clone();
+            connection.capabilities = runtime_connection.capabilities.clone();
+        } else {
+            connection.connected = false;
+        }
+
+        if let ConnectionConfig::SQLite {
+            db_path,
+            home_relative_path,
+        } = &connection.config
+        {
+            connection.file_missing =
+                locate_database_file(connection.id, db_path, home_relative_path.as_deref(), state)?
+                    .is_none();
+        }
+    }
+
+    Ok(stored_connections)
+}
+
+/// Renames a folder of connections, moving its subfolders along. Returns how many connections moved.
+pub async fn rename_connection_folder(
+    old: &str,
+    new: &str,
+    state: &AppState,
+) -> Result<usize, Error> {
+    let old = normalize_folder(Some(old)).context("The folder to rename can't be empty")?;
+    let new = normalize_folder(Some(new)).context("Folders can't be renamed to nothing")?;
+
+    let moved = state.storage.rename_connection_folder(&old, &new)?;
+    for connection_id in &moved {
+        if let Some(mut connection) = state.connections.get_mut(connection_id) {
+            connection.folder = connection
+                .folder
+                .as_deref()
+                .and_then(|folder| rename_folder(folder, &old, &new));
+        }
+    }
+    Ok(moved.len())
+}
+
+/// Writes saved connections to a file to share them, with the snippets of no connection in particular and theirs.
+/// Their passwords are only included if there's a passphrase to seal them with. Returns how many connections were
+/// exported.
+pub async fn export_connections(
+    path: &str,
+    connection_ids: &[Uuid],
+    passphrase: Option<&str>,
+    state: &AppState,
+) -> Result<usize, Error> {
+    let saved = state.storage.get_connections()?;
+    let seal = passphrase.map(Seal::new).transpose()?;
+
+    let mut exported = Vec::with_capacity(connection_ids.len());
+    let mut infos = Vec::with_capacity(connection_ids.len());
+    for connection_id in connection_ids {
+        let info = saved
+            .iter()
+            .find(|info| info.id == *connection_id)
+            .with_context(|| format!("Connection {connection_id} not found"))?;
+        infos.push(info);
+
+        let secrets = match &seal {
+            Some((_, key)) => {
+                let secrets = ConnectionSecrets {
+                    password: credentials::get_password(connection_id, &state.credential_file)?,
+                    ssh_password: credentials::get_ssh_password(
+                        connection_id,
+                        &state.credential_file,
+                    )?,
+                };
+                Some((key, secrets))
+            }
+            None => None,
+        };
+        exported.push(connection_file::export_connection(info, secrets)?);
+    }
+
+    let snippets = connection_file::export_snippets(state.storage.get_snippets(None)?, &infos);
+    let json = connection_file::write_connection_file(
+        &exported,
+        &snippets,
+        seal.as_ref().map(|(seal, _)| seal),
+    )?;
+    std::fs::write(path, json).with_context(|| format!("Failed to write {path}"))?;
+    Ok(exported.len())
+}
+
+/// Creates the connections of an exported file, with new ids. Those already saved, with the same name and target,
+/// are skipped. Each entry gets its own outcome, so that a broken one doesn't keep the others from being imported.
+///
+/// Its snippets are saved too, on the connection of the same name if they have one, unless the same snippet is
+/// already saved.
+pub async fn import_connections(
+    path: &str,
+    passphrase: Option<&str>,
+    state: &AppState,
+) -> Result<Vec<ImportedConnection>, Error> {
+    let json = std::fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+    let file = connection_file::parse_connection_file(&json)?;
+    // Without the passphrase, the connections are still imported, just without their passwords
+    let key = file
+        .secrets
+        .as_ref()
+        .zip(passphrase)
+        .map(|(seal, passphrase)| seal.unlock(passphrase))
+        .transpose()?;
+
+    let connections = state.storage.get_connections()?;
+    let mut saved: std::collections::HashSet<_> = connections
+        .iter()
+        .map(|info| connection_file::duplicate_key(&info.name, &info.config))
+        .collect();
+    let mut ids_by_name: std::collections::HashMap<_, _> = connections
+        .into_iter()
+        .map(|info| (info.name, info.id))
+        .collect();
+
+    let mut imported = Vec::with_capacity(file.entries.len());
+    for (name, entry) in file.entries {
+        let outcome = match entry {
+            Ok(mut entry) => {
+                // The file might have been exported on another machine, where the home directory is elsewhere
+                if let ConnectionConfig::SQLite {
+                    db_path,
+                    home_relative_path,
+                } = &mut entry.config
+                {
+                    if let Some(path) = paths::resolve(
+                        db_path,
+                        home_relative_path.as_deref(),
+                        paths::home_dir().as_deref(),
+                    ) {
+                        *db_path = path.to_string_lossy().into_owned();
+                    }
+                }
+
+                if saved.insert(connection_file::duplicate_key(&entry.name, &entry.config)) {
+                    let name = entry.name.clone();
+                    match import_connection(entry, key.as_ref(), state).await {
+                        Ok(connection_id) => {
+                            ids_by_name.insert(name, connection_id);
+                            ImportOutcome::Created { connection_id }
+                        }
+                        Err(e) => ImportOutcome::Error {
+                            message: e.to_string(),
+                        },
+                    }
+                } else {
+                    ImportOutcome::Skipped {
+                        reason: "A connection with the same name and target is already saved"
+                            .to_owned(),
+                    }
+                }
+            }
+            Err(message) => ImportOutcome::Error { message },
+        };
+        imported.push(ImportedConnection { name, outcome });
+    }
+
+    let mut snippets = state.storage.get_snippets(None)?;
+    for snippet in file.snippets {
+        let connection_id = match &snippet.connection {
+            Some(name) => match ids_by_name.get(name) {
+                Some(id) => Some(*id),
+                // Rather than offering it everywhere
+                None => continue,
+            },
+            None => None,
+        };
+        let snippet = Snippet {
+            id: 0,
+            name: snippet.name,
+            prefix: snippet.prefix,
+            body: snippet.body,
+            connection_id,
+            db_type: snippet.db_type,
+        };
+        let duplicate = snippets.iter().any(|saved| {
+            (
+                &saved.prefix,
+                &saved.body,
+                saved.connection_id,
+                saved.db_type,
+            ) == (
+                &snippet.prefix,
+                &snippet.body,
+                connection_id,
+                snippet.db_type,
+            )
+        });
+        if !duplicate {
+            snippets.push(state.storage.save_snippet(&snippet)?);
+        }
+    }
+
+    Ok(imported)
+}
+
+async fn import_connection(
+    entry: connection_file::ExportedConnection,
+    key: Option<&credentials::SealKey>,
+    state: &AppState,
+) -> Result<Uuid, Error> {
+    let secrets = key
+        .map(|key| entry.open_secrets(key))
+        .transpose()?
+        .unwrap_or_default();
+
+    let info = add_connection(
+        entry.name,
+        entry.folder,
+        entry.environment,
+        entry.color,
+        entry.config,
+        entry.permissions,
+        state,
+    )
+    .await?;
+    if let Some(password) = secrets.password {
+        credentials::store_sensitive_data(&info.id, &password, &state.credential_file)?;
+    }
+    if let Some(ssh_password) = secrets.ssh_password {
+        credentials::store_ssh_password(&info.id, &ssh_password, &state.credential_file)?;
+    }
+    Ok(info.id)
+}
+
+/// Points a SQLite connection at the new location of its file, e.g. after it was moved or synced from another
+/// machine
+pub async fn relocate_database_file(
+    connection_id: Uuid,
+    db_path: &str,
+    state: &AppState,
+) -> Result<ConnectionInfo, Error> {
+    let path = paths::normalize(db_path, paths::home_dir().as_deref())?;
+    if !Path::new(&path.absolute).is_file() {
+        return Err(anyhow::anyhow!("{} does not exist", path.absolute).into());
+    }
+
+    let mut connection = state
+        .connections
+        .get_mut(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?;
+    if !matches!(connection.config, ConnectionConfig::SQLite { .. }) {
+        return Err(anyhow::anyhow!("Only SQLite connections have a database file").into());
+    }
+
+    connection.config = ConnectionConfig::SQLite {
+        db_path: path.absolute,
+        home_relative_path: path.home_relative,
+    };
+    connection.runtime = ConnectionRuntime::Disconnected;
+    connection.snapshot = None;
+    let info = connection.to_connection_info();
+    drop(connection);
+
+    state.storage.update_connection(&info)?;
+    Ok(info)
+}
+
+pub async fn remove_connection(connection_id: Uuid, state: &AppState) -> Result<(), Error> {
+    if let Err(e) = credentials::delete_password(&connection_id, &state.credential_file) {
+        log::debug!("Could not delete password (may not exist): {}", e);
+    }
+    if let Err(e) = credentials::delete_ssh_password(&connection_id, &state.credential_file) {
+        log::debug!("Could not delete SSH password: {}", e);
+    }
+
+    state.storage.remove_connection(&connection_id)?;
+    state.reconnects.stop(connection_id);
+    state.connections.remove(&connection_id);
+    state.secret_cache.forget(&connection_id);
+    state.statement_traces.remove(connection_id);
+    state.server_messages.clear(connection_id);
+    if let Err(e) = state.snapshots.remove(connection_id) {
+        log::warn!("Failed to remove the snapshot of {}: {}", connection_id, e);
+    }
+
+    Ok(())
+}
+
+pub async fn test_connection(
+    // It's expected that test_connection receives config with the password included
+    config: ConnectionConfig,
+    certificates: &Certificates,
+) -> Result<bool, Error> {
+    match config {
+        ConnectionConfig::Postgres {
+            connection_string,
+            ca_cert_path,
+            ssh_tunnel,
+            ..
+        } => {
+            // Closed once the test is over
+            let (_tunnel, connection_string) = match ssh_tunnel {
+                Some(ssh_tunnel) => {
+                    let password = match &ssh_tunnel.auth {
+                        SshAuth::Password { password } => password.clone(),
+                        SshAuth::Agent | SshAuth::KeyFile { .. } => None,
+                    };
+                    match SshTunnel::open(&ssh_tunnel, &connection_string, password, None).await {
+                        Ok((tunnel, tunneled)) => (Some(tunnel), tunneled),
+                        Err(e) => {
+                            log::error!("Postgres connection test failed: {}", e);
+                            return Ok(false);
+                        }
+                    }
+                }
+                None => (None, connection_string),
+            };
+
+            let config: tokio_postgres::Config = connection_string.parse().with_context(|| {
+                format!("Failed to parse connection string: {}", connection_string)
+            })?;
+            log::info!("Testing Postgres connection: {config:?}");
+            match postgres::connect::test_connection(&config, certificates, ca_cert_path.as_deref())
+                .await
+            {
+                Ok(()) => Ok(true),
+                Err(e) => {
+                    log::error!("Postgres connection test failed: {}", e);
+                    Ok(false)
+                }
+            }
+        }
+        ConnectionConfig::SQLite { db_path, .. } => match rusqlite::Connection::open(db_path) {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                log::error!("SQLite connection test failed: {}", e);
+                Ok(false)
+            }
+        },
+        ConnectionConfig::MySQL {
+            connection_string,
+            ca_cert_path,
+            ssh_tunnel,
+        } => {
+            let opts = mysql::connect::parse_connection_string(&connection_string)?;
+            let opts =
+                mysql::connect::complete_opts(opts, None, certificates, ca_cert_path.as_deref())
+                    .await;
+            // Closed once the test is over
+            let (_tunnel, opts) = match ssh_tunnel {
+                Some(ssh_tunnel) => {
+                    let password = match &ssh_tunnel.auth {
+                        SshAuth::Password { password } => password.clone(),
+                        SshAuth::Agent | SshAuth::KeyFile { .. } => None,
+                    };
+                    let target = mysql::connect::forward_target(&opts);
+                    match SshTunnel::open_to(&ssh_tunnel, target, password, None).await {
+                        Ok(tunnel) => {
+                            let opts = mysql::connect::tunneled_opts(opts, tunnel.local_port);
+                            (Some(tunnel), opts)
+                        }
+                        Err(e) => {
+                            log::error!("MySQL connection test failed: {}", e);
+                            return Ok(false);
+                        }
+                    }
+                }
+                None => (None, opts),
+            };
+            match mysql::connect::test_connection(opts).await {
+                Ok(()) => Ok(true),
+                Err(e) => {
+                    log::error!("MySQL connection test failed: {}", e);
+                    Ok(false)
+                }
+            }
+        }
+    }
+}
+
+/// Longest a single connection probe may take
+const MAX_PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Checks which of the given saved connections are reachable, probing them concurrently without authenticating.
+///
+/// Runs as a cancellable operation, so that it can be stopped when its results aren't wanted anymore, in which
+/// case it fails.
+pub async fn probe_connections(
+    connection_ids: Vec<Uuid>,
+    timeout_ms: u64,
+    state: &AppState,
+) -> Result<Vec<ConnectionProbe>, Error> {
+    let stored_connections = state.storage.get_connections()?;
+    let connections = connection_ids
+        .iter()
+        .map(|id| {
+            stored_connections
+                .iter()
+                .find(|c| c.id == *id)
+                .with_context(|| format!("Connection not found: {id}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let timeout = Duration::from_millis(timeout_ms).min(MAX_PROBE_TIMEOUT);
+
+    let operation = state.operations.start(
+        OperationKind::ConnectionProbe,
+        format!("Checking {} connections", connections.len()),
+        true,
+    );
+    let probes = futures_util::future::join_all(
+        connections
+            .iter()
+            .map(|connection| probe::probe(connection.id, &connection.config, timeout)),
+    );
+
+    let result = match operation.token().run_until_cancelled(probes).await {
+        Some(probes) => Ok(probes),
+        None => operation.check_cancelled().map(|()| Vec::new()),
+    };
+    operation.complete(result)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn save_query_to_history(
+    connection_id: String,
+    query: String,
+    duration_ms: Option<u64>,
+    status: String,
+    row_count: u64,
+    error_message: Option<String>,
+    timings: Option<Vec<TimingBreakdown>>,
+    plan_json: Option<String>,
+    state: &AppState,
+) -> Result<(), Error> {
+    let entry = QueryHistoryEntry {
+        id: 0, // Sqlite will assign,
+        connection_id,
+        // In case the query was copied from somewhere that shows tagged statements, e.g. `pg_stat_activity`
+        query_text: query_tags::strip_tag(&query),
+        executed_at: chrono::Utc::now().timestamp(),
+        duration_ms: duration_ms.map(|d| d as i64),
+        status,
+        row_count: row_count as i64,
+        error_message,
+        timings,
+        run_count: 1,
+        has_plan: plan_json.is_some(),
+        plan_json,
+        scheduled: false,
+    };
+
+    let dedupe = state.storage.get_history_settings()?.dedupe;
+    state.storage.save_query_history(&entry, dedupe)?;
+    Ok(())
+}
+
+/// A history entry with its plan, if it has one
+pub async fn get_query_history_entry(
+    id: i64,
+    state: &AppState,
+) -> Result<QueryHistoryEntry, Error> {
+    state
+        .storage
+        .get_query_history_entry(id)?
+        .with_context(|| format!("History entry not found: {id}"))
+        .map_err(Into::into)
+}
+
+pub async fn get_history_settings(state: &AppState) -> Result<HistorySettings, Error> {
+    state.storage.get_history_settings()
+}
+
+pub async fn set_history_settings(
+    settings: HistorySettings,
+    state: &AppState,
+) -> Result<(), Error> {
+    if settings.max_entries == Some(0) || settings.max_age_days == Some(0) {
+        return Err(anyhow::anyhow!("The history has to keep at least some entries").into());
+    }
+    state.storage.set_history_settings(&settings)
+}
+
+pub async fn get_result_cache_settings(state: &AppState) -> Result<ResultCacheSettings, Error> {
+    state.storage.get_result_cache_settings()
+}
+
+/// Applies to the queries submitted from now on
+pub async fn set_result_cache_settings(
+    settings: ResultCacheSettings,
+    state: &AppState,
+) -> Result<(), Error> {
+    if settings.spill_threshold_bytes == Some(0) {
+        return Err(anyhow::anyhow!("Results have to keep at least some bytes in memory").into());
+    }
+    state.storage.set_result_cache_settings(&settings)?;
+    state
+        .stmt_manager
+        .set_spill_threshold(settings.spill_threshold_bytes);
+    Ok(())
+}
+
+/// Deletes the history entries the history settings don't keep
+pub async fn prune_query_history(state: &AppState) -> Result<HistoryPruneReport, Error> {
+    let settings = state.storage.get_history_settings()?;
+    state
+        .storage
+        .prune_query_history(&settings, chrono::Utc::now().timestamp())
+}
+
+pub async fn get_query_history(
+    connection_id: String,
+    limit: Option<u32>,
+    state: &AppState,
+) -> Result<Vec<QueryHistoryEntry>, Error> {
+    state
+        .storage
+        .get_query_history(&connection_id, limit.map(|l| l as i64))
+}
+
+/// Entries older than `before` (or the newest ones), for scrolling through a long history
+pub async fn get_query_history_page(
+    connection_id: String,
+    before: Option<HistoryCursor>,
+    page_size: u32,
+    filters: HistoryFilters,
+    state: &AppState,
+) -> Result<QueryHistoryPage, Error> {
+    state
+        .storage
+        .get_query_history_page(&connection_id, before, page_size as i64, &filters)
+}
+
+pub async fn get_query_history_count(
+    connection_id: String,
+    filters: HistoryFilters,
+    state: &AppState,
+) -> Result<i64, Error> {
+    state
+        .storage
+        .get_query_history_count(&connection_id, &filters)
+}
+
+pub async fn initialize_connections(state: &AppState) -> Result<(), Error> {
+    let stored_connections = state.storage.get_connections()?;
+
+    for stored_connection in stored_connections {
+        let connection = Connection::new(
+            stored_connection.id,
+            stored_connection.name,
+            stored_connection.folder,
+            stored_connection.environment,
+            stored_connection.color,
+            stored_connection.config,
+            stored_connection.permissions,
+        );
+        state.connections.insert(connection.id, connection);
+    }
+
+    log::info!(
+        "Initialized {} connections from storage",
+        state.connections.len()
+    );
+    Ok(())
+}
+
+/// Loads the stored connections, returning the drafts of editor buffers left by the last run so that the frontend
+/// can offer to recover them
+pub async fn initialize_workspace(state: &AppState) -> Result<Vec<DraftBuffer>, Error> {
+    initialize_connections(state).await?;
+    state.storage.get_draft_buffers()
+}
+
+/// Formats the statements of a script one by one, leaving the ones that can't be parsed as they are
+pub async fn format_sql(
+    query: &str,
+    dialect: Option<Database>,
+    options: FormatSettings,
+) -> Result<FormattedSql, Error> {
+    Ok(formatter::format_script(query, dialect, &options))
+}
+
+/// Replaces the smart quotes, non-breaking spaces and zero-width characters outside literals and comments
+pub async fn sanitize_sql(text: &str) -> Result<SanitizedSql, Error> {
+    Ok(sanitize::sanitize_sql(text))
+}
+
+/// What's under `position` of the query, resolved against the cached schema of the connection. Nothing is shown
+/// until the schema was fetched, so that hovering never waits on an introspection.
+pub async fn lsp_hover(
+    connection_id: Uuid,
+    query: &str,
+    position: usize,
+    state: &AppState,
+) -> Result<Option<Hover>, Error> {
+    let db = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?
+        .config
+        .kind();
+    let Some(schema) = schema_cache::ready_schema(&state.schemas, connection_id) else {
+        return Ok(None);
+    };
+
+    let dialect = parser::sql_dialect(Some(db));
+    Ok(hover::hover_at(dialect.as_ref(), &schema, query, position))
+}
+
+/// Conditions for the join whose `ON` is right before `position`, from the foreign keys in the cached schema of the
+/// connection. There are none until the schema was fetched.
+pub async fn lsp_join_conditions(
+    connection_id: Uuid,
+    query: &str,
+    position: usize,
+    state: &AppState,
+) -> Result<Vec<String>, Error> {
+    let db = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?
+        .config
+        .kind();
+    let Some(schema) = schema_cache::ready_schema(&state.schemas, connection_id) else {
+        return Ok(vec![]);
+    };
+
+    let dialect = parser::sql_dialect(Some(db));
+    Ok(join_conditions::join_conditions(
+        dialect.as_ref(),
+        db,
+        &schema,
+        query,
+        position,
+    ))
+}
+
+/// Unknown tables and columns of the query, and ambiguous column names, checked against the cached schema of the
+/// connection. There are none until the schema was fetched.
+pub async fn lsp_diagnostics(
+    connection_id: Uuid,
+    query: &str,
+    state: &AppState,
+) -> Result<Vec<Diagnostic>, Error> {
+    let db = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?
+        .config
+        .kind();
+    let Some(schema) = schema_cache::ready_schema(&state.schemas, connection_id) else {
+        return Ok(vec![]);
+    };
+
+    let dialect = parser::sql_dialect(Some(db));
+    Ok(diagnostics::diagnostics(
+        dialect.as_ref(),
+        db,
+        &schema,
+        query,
+    ))
+}
+
+/// Where the name at `position` of the query is defined: a CTE of the same statement, or a view or routine whose
+/// definition is fetched from the database
+pub async fn lsp_definition(
+    connection_id: Uuid,
+    query: &str,
+    position: usize,
+    state: &AppState,
+) -> Result<Option<Definition>, Error> {
+    let db = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?
+        .config
+        .kind();
+
+    let target =
+        definition::definition_at(parser::sql_dialect(Some(db)).as_ref(), db, query, position);
+    let (schema, name) = match target {
+        None => return Ok(None),
+        Some(DefinitionTarget::Local { bytes }) => {
+            return Ok(Some(Definition::Local {
+                from: hover::utf16_len(&query[..bytes.start]),
+                to: hover::utf16_len(&query[..bytes.end]),
+            }))
+        }
+        Some(DefinitionTarget::Object { schema, name }) => (schema, name),
+    };
+
+    let object = match connection_client(connection_id, state)? {
+        RuntimeClient::Postgres { pool } => {
+            definition::postgres_definition(pool.metadata(), schema.as_deref(), &name).await?
+        }
+        RuntimeClient::SQLite { connection, .. } => {
+            tokio::task::spawn_blocking(move || {
+                let conn = connection.lock().unwrap();
+                definition::sqlite_definition(&conn, schema.as_deref(), &name)
+            })
+            .await??
+        }
+        RuntimeClient::MySQL { client } => {
+            let mut conn = client.metadata().await?;
+            definition::mysql_definition(&mut conn, schema.as_deref(), &name).await?
+        }
+    };
+    Ok(object.map(Definition::Object))
+}
+
+/// The statements creating a table, with its constraints and indexes, for the database of the connection
+pub async fn get_table_ddl(
+    connection_id: Uuid,
+    schema: &str,
+    table: &str,
+    state: &AppState,
+) -> Result<TableDdl, Error> {
+    let client = connection_client(connection_id, state)?;
+    let (dialect, ddl) = match client {
+        RuntimeClient::Postgres { pool } => (
+            Database::Postgres,
+            table_ddl::postgres_table_ddl(pool.metadata(), schema, table).await?,
+        ),
+        RuntimeClient::SQLite { connection, .. } => {
+            let schema = match schema {
+                "" => "main".to_owned(),
+                schema => schema.to_owned(),
+            };
+            let table = table.to_owned();
+            let ddl = tokio::task::spawn_blocking(move || {
+                let conn = connection.lock().unwrap();
+                table_ddl::sqlite_table_ddl(&conn, &schema, &table)
+            })
+            .await??;
+            (Database::Sqlite, ddl)
+        }
+        RuntimeClient::MySQL { client } => {
+            let mut conn = client.metadata().await?;
+            (
+                Database::MySql,
+                table_ddl::mysql_table_ddl(&mut conn, schema, table).await?,
+            )
+        }
+    };
+
+    Ok(TableDdl {
+        schema: schema.to_owned(),
+        table: table.to_owned(),
+        dialect,
+        ddl,
+    })
+}
+
+pub async fn is_query_read_only(
+    connection_id: Uuid,
+    query: &str,
+    state: &AppState,
+) -> Result<bool, Error> {
+    log::info!("Checking if {query} is read-only");
+    let db = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?
+        .config
+        .kind();
+
+    let stmts = match db {
+        Database::Postgres => database::postgres::parser::parse_statements(query)?,
+        Database::Sqlite => database::sqlite::parser::parse_statements(query)?,
+        Database::MySql => database::mysql::parser::parse_statements(query)?,
+    };
+
+    Ok(stmts.into_iter().all(|stmt| stmt.is_read_only))
+}
+
+/// The placeholders of a query's statements, so that values can be asked for before it's run, see
+/// [`params`](database::params). Placeholders that several statements have are only listed once.
+pub async fn detect_query_parameters(
+    connection_id: Uuid,
+    query: &str,
+    state: &AppState,
+) -> Result<Vec<String>, Error> {
+    let db = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?
+        .config
+        .kind();
+
+    let stmts = match db {
+        Database::Postgres => database::postgres::parser::parse_statements(query)?,
+        Database::Sqlite => database::sqlite::parser::parse_statements(query)?,
+        Database::MySql => database::mysql::parser::parse_statements(query)?,
+    };
+
+    let mut names: Vec<String> = Vec::new();
+    for name in stmts.into_iter().flat_map(|stmt| stmt.placeholders) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+/// Explains the statement of `query` under the cursor, a byte offset, see [`postgres::explain`].
+///
+/// Runs on a metadata client, so that it doesn't wait on the user's queries nor end a transaction they're in. The
+/// session's temporary tables can't be explained, then. Statements explained with ANALYZE, having run, are saved
+/// to the history with their plan.
+pub async fn explain_query(
+    connection_id: Uuid,
+    query: &str,
+    cursor: Option<usize>,
+    options: ExplainOptions,
+    state: &AppState,
+) -> Result<ExplainedPlan, Error> {
+    let RuntimeClient::Postgres { pool } = connection_client(connection_id, state)? else {
+        return Err(anyhow::anyhow!("Only Postgres queries can be explained").into());
+    };
+
+    let statements = postgres::parser::parse_statements(query)?;
+    let statement = explain::statement_at(&statements, cursor)?;
+    let explained = explain::explain_statement(pool.metadata(), query, statement, options).await?;
+
+    // The statement ran, so it goes in the history with its plan
+    if explained.analyzed {
+        let saved = save_query_to_history(
+            connection_id.to_string(),
+            explained.statement.clone(),
+            explained.execution_time_ms.map(|ms| ms.round() as u64),
+            "success".to_owned(),
+            explained.plan.actual_rows.unwrap_or_default() as u64,
+            None,
+            None,
+            Some(serde_json::to_string(&explained)?),
+            state,
+        )
+        .await;
+        if let Err(err) = saved {
+            log::warn!("Failed to save the explained query to the history: {err}");
+        }
+    }
+    Ok(explained)
+}
+
+/// Explains the statement of `query` under the cursor, a byte offset, with `EXPLAIN QUERY PLAN`, see
+/// [`sqlite::explain`]
+pub async fn explain_sqlite_query(
+    connection_id: Uuid,
+    query: &str,
+    cursor: Option<usize>,
+    state: &AppState,
+) -> Result<QueryPlan, Error> {
+    let RuntimeClient::SQLite { connection, .. } = connection_client(connection_id, state)? else {
+        return Err(anyhow::anyhow!("Only SQLite query plans can be explained this way").into());
+    };
+
+    let query = query.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let statements = sqlite::parser::parse_statements(&query)?;
+        let statement = explain::statement_at(&statements, cursor)?;
+        let conn = connection.lock().unwrap();
+        sqlite::explain::explain_statement(&conn, &query, statement)
+    })
+    .await?
+}
+
+pub async fn get_database_schema(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<Arc<DatabaseSchema>, Error> {
+    let schema = schema_cache::get_or_fetch_schema(&state.schemas, connection_id, || {
+        let connection_entry = state
+            .connections
+            .get(&connection_id)
+            .with_context(|| format!("Connection not found: {}", connection_id))?;
+
+        let flavor = connection_entry
+            .capabilities
+            .as_ref()
+            .map(|capabilities| capabilities.flavor)
+            .unwrap_or_default();
+        let active_schema = connection_entry.active_schema.clone();
+        let introspection = match connection_entry.value().runtime.clone() {
+            ConnectionRuntime::Connected(RuntimeClient::Postgres { pool }) => async move {
+                let mut schema =
+                    postgres::schema::get_database_schema(pool.metadata(), flavor).await?;
+                schema.active_schema = active_schema;
+                Ok(schema)
+            }
+            .boxed(),
+            ConnectionRuntime::Connected(RuntimeClient::SQLite { connection, trace }) => {
+                sqlite::schema::get_database_schema(connection, trace).boxed()
+            }
+            ConnectionRuntime::Connected(RuntimeClient::MySQL { client }) => async move {
+                mysql::schema::get_database_schema(&mut client.metadata().await?).await
+            }
+            .boxed(),
+            ConnectionRuntime::Disconnected => {
+                return Err(Error::Any(anyhow::anyhow!("Connection not active")))
+            }
+        };
+        Ok(introspection)
+    })
+    .await?;
+
+    if let Err(err) = state
+        .semantic_index
+        .refresh(connection_id, &schema, &state.storage)
+    {
+        log::warn!("Failed to update the semantic index of {connection_id}: {err}");
+    }
+
+    Ok(schema)
+}
+
+/// The tables of a connection and the foreign keys between them, for drawing an entity-relationship diagram. Built
+/// from the cached schema and kept with it, so it's refreshed along with it.
+pub async fn get_relationship_graph(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<Arc<RelationshipGraph>, Error> {
+    let schema = get_database_schema(connection_id, state).await?;
+    Ok(schema.relationship_graph())
+}
+
+fn sqlite_connection(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<Arc<Mutex<rusqlite::Connection>>, Error> {
+    match connection_client(connection_id, state)? {
+        RuntimeClient::SQLite { connection, .. } => Ok(connection),
+        _ => Err(anyhow::anyhow!("Only SQLite connections have attached databases").into()),
+    }
+}
+
+/// Attaches the SQLite database at `path` to a SQLite connection as `alias`, and returns the schema with its
+/// tables. It stays attached until the connection is closed.
+pub async fn attach_sqlite_database(
+    connection_id: Uuid,
+    path: &str,
+    alias: &str,
+    state: &AppState,
+) -> Result<Arc<DatabaseSchema>, Error> {
+    let connection = sqlite_connection(connection_id, state)?;
+    let (path, alias) = (path.to_owned(), alias.to_owned());
+    tokio::task::spawn_blocking(move || {
+        let conn = connection.lock().unwrap();
+        sqlite::schema::attach_database(&conn, &path, &alias)
+    })
+    .await??;
+
+    state.schemas.remove(&connection_id);
+    get_database_schema(connection_id, state).await
+}
+
+/// Detaches a database attached to a SQLite connection, and returns the schema without its tables
+pub async fn detach_sqlite_database(
+    connection_id: Uuid,
+    alias: &str,
+    state: &AppState,
+) -> Result<Arc<DatabaseSchema>, Error> {
+    let connection = sqlite_connection(connection_id, state)?;
+    let alias = alias.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let conn = connection.lock().unwrap();
+        sqlite::schema::detach_database(&conn, &alias)
+    })
+    .await??;
+
+    state.schemas.remove(&connection_id);
+    get_database_schema(connection_id, state).await
+}
+
+/// Statistics of a column over a whole table, computed by the server with a single query, so that the table
+/// doesn't need to be fetched
+pub async fn get_table_column_stats(
+    connection_id: Uuid,
+    schema: &str,
+    table: &str,
+    column: &str,
+    state: &AppState,
+) -> Result<ColumnStats, Error> {
+    let columns = get_table_columns(connection_id, schema, table, state).await?;
+    let column_type = columns
+        .into_iter()
+        .find(|info| info.name == column)
+        .with_context(|| format!("Column not found: {column}"))?
+        .data_type;
+
+    match connection_client(connection_id, state)? {
+        RuntimeClient::Postgres { pool } => {
+            column_stats::postgres_table_stats(pool.metadata(), schema, table, column, &column_type)
+                .await
+        }
+        RuntimeClient::SQLite { connection, .. } => {
+            let (schema, table, column) = (schema.to_owned(), table.to_owned(), column.to_owned());
+            tokio::task::spawn_blocking(move || {
+                let conn = connection.lock().unwrap();
+                column_stats::sqlite_table_stats(&conn, &schema, &table, &column, &column_type)
+            })
+            .await?
+        }
+        RuntimeClient::MySQL { client } => {
+            let mut conn = client.metadata().await?;
+            column_stats::mysql_table_stats(&mut conn, schema, table, column, &column_type).await
+        }
+    }
+}
+
+/// The columns of a table, loading them if they were left pending by [`get_database_schema`]. The cached schema
+/// is updated with them, so they're only loaded once.
+pub async fn get_table_columns(
+    connection_id: Uuid,
+    schema: &str,
+    table: &str,
+    state: &AppState,
+) -> Result<Vec<ColumnInfo>, Error> {
+    let cached = get_database_schema(connection_id, state).await?;
+    let info = cached
+        .table(schema, table)
+        .with_context(|| format!("Table not found: {schema}.{table}"))?;
+    if !info.columns_pending {
+        return Ok(info.columns.clone());
+    }
+
+    let columns = match connection_client(connection_id, state)? {
+        RuntimeClient::Postgres { pool } => {
+            postgres::schema::get_table_columns(pool.metadata(), schema, table).await?
+        }
+        RuntimeClient::MySQL { client } => {
+            let mut conn = client.metadata().await?;
+            mysql::schema::get_table_columns(&mut conn, schema, table).await?
+        }
+        RuntimeClient::SQLite { .. } => {
+            return Err(anyhow::anyhow!("SQLite tables have their columns loaded upfront").into());
+        }
+    };
+
+    schema_cache::update_ready_schema(&state.schemas, connection_id, |cached| {
+        cached.with_table_columns(schema, table, columns.clone())
+    });
+    Ok(columns)
+}
+
+/// Puts a schema first in the search path of a connected Postgres connection, for as long as it stays connected,
+/// without changing its default schema. None restores the default schema, or the server's search path without one.
+pub async fn set_active_schema(
+    connection_id: Uuid,
+    schema: Option<String>,
+    state: &AppState,
+) -> Result<(), Error> {
+    let (pool, default_schema) = {
+        let connection = state
+            .connections
+            .get(&connection_id)
+            .with_context(|| format!("Connection not found: {}", connection_id))?;
+        let default_schema = match &connection.config {
+            ConnectionConfig::Postgres { default_schema, .. } => default_schema.clone(),
+            _ => {
+                return Err(anyhow::anyhow!("Only Postgres connections have a search path").into())
+            }
+        };
+        let RuntimeClient::Postgres { pool } = connection.get_client()? else {
+            return Err(anyhow::anyhow!("Only Postgres connections have a search path").into());
+        };
+        (pool, default_schema)
+    };
+    let schema = schema
+        .filter(|schema| !schema.trim().is_empty())
+        .or(default_schema);
+
+    if let Some(schema) = &schema {
+        let exists: bool = pool
+            .metadata()
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM pg_namespace WHERE nspname = $1)",
+                &[schema],
+            )
+            .await
+            .context("Failed to look up the schema")?
+            .get(0);
+        if !exists {
+            return Err(anyhow::anyhow!("Schema not found: {schema}").into());
+        }
+    }
+    for client in pool.clients() {
+        postgres::execute::set_search_path(client, schema.as_deref())
+            .await
+            .context("Failed to change the search path")?;
+    }
+
+    if let Some(mut connection) = state.connections.get_mut(&connection_id) {
+        connection.active_schema = schema.clone();
+    }
+    schema_cache::update_ready_schema(&state.schemas, connection_id, |cached| {
+        Some(cached.with_active_schema(schema.clone()))
+    });
+    Ok(())
+}
+
+/// Counts the rows of a table exactly, and keeps the count as the table's estimate in the cached schema
+pub async fn refresh_table_row_count(
+    connection_id: Uuid,
+    schema: &str,
+    table: &str,
+    state: &AppState,
+) -> Result<i64, Error> {
+    let count = match connection_client(connection_id, state)? {
+        RuntimeClient::Postgres { pool } => {
+            browse::postgres_exact_row_count(pool.metadata(), schema, table).await?
+        }
+        RuntimeClient::SQLite { connection, .. } => {
+            let (schema, table) = (schema.to_owned(), table.to_owned());
+            tokio::task::spawn_blocking(move || {
+                let conn = connection.lock().unwrap();
+                browse::sqlite_row_count(&conn, &schema, &table)
+            })
+            .await??
+        }
+        RuntimeClient::MySQL { client } => {
+            let mut conn = client.metadata().await?;
+            mysql::schema::count_rows(&mut conn, schema, table).await?
+        }
+    };
+    let count = count as i64;
+
+    schema_cache::update_ready_schema(&state.schemas, connection_id, |cached| {
+        cached.with_row_estimate(schema, table, count)
+    });
+    Ok(count)
+}
+
+const SEMANTIC_SEARCH_KEY: &str = "semantic_search";
+
+pub async fn get_semantic_search_settings(
+    state: &AppState,
+) -> Result<SemanticSearchSettings, Error> {
+    match state.storage.get_setting(SEMANTIC_SEARCH_KEY)? {
+        Some(settings) => Ok(serde_json::from_str(&settings)?),
+        None => Ok(SemanticSearchSettings::default()),
+    }
+}
+
+pub async fn set_semantic_search_settings(
+    settings: SemanticSearchSettings,
+    state: &AppState,
+) -> Result<(), Error> {
+    state
+        .storage
+        .set_setting(SEMANTIC_SEARCH_KEY, &serde_json::to_string(&settings)?)?;
+    Ok(())
+}
+
+/// Searches the tables of a connection by meaning if semantic search is available, or else by name
+pub async fn semantic_search_schema(
+    connection_id: Uuid,
+    query: &str,
+    limit: usize,
+    state: &AppState,
+) -> Result<SchemaSearchResults, Error> {
+    let schema = get_database_schema(connection_id, state).await?;
+    let settings = get_semantic_search_settings(state).await?;
+    let limit = limit.clamp(1, semantic_search::MAX_RESULTS);
+
+    let semantic_hits = state
+        .semantic_index
+        .search(
+            connection_id,
+            &schema,
+            query,
+            limit,
+            &settings,
+            &state.storage,
+        )
+        .await?;
+
+    Ok(match semantic_hits {
+        Some(hits) => SchemaSearchResults {
+            method: SearchMethod::Semantic,
+            hits,
+        },
+        None => SchemaSearchResults {
+            method: SearchMethod::Fuzzy,
+            hits: semantic_search::fuzzy_search(&schema, query, limit),
+        },
+    })
+}
+
+const ERROR_SUGGESTIONS_KEY: &str = "error_suggestions";
+
+/// Schema-aware suggestions for an error `query` failed with, e.g. similarly named columns when a column doesn't
+/// exist. Only the cached schema is used, so there are none until the schema has been loaded.
+pub async fn suggest_error_fixes(
+    connection_id: Uuid,
+    query: &str,
+    error: &str,
+    state: &AppState,
+) -> Result<Vec<ErrorSuggestion>, Error> {
+    if !get_error_suggestions_enabled(state).await? {
+        return Ok(Vec::new());
+    }
+
+    let Some(schema) = schema_cache::ready_schema(&state.schemas, connection_id) else {
+        return Ok(Vec::new());
+    };
+
+    Ok(error_hints::suggest(error, query, &schema))
+}
+
+pub async fn get_error_suggestions_enabled(state: &AppState) -> Result<bool, Error> {
+    match state.storage.get_setting(ERROR_SUGGESTIONS_KEY)? {
+        Some(enabled) => Ok(serde_json::from_str(&enabled)?),
+        None => Ok(true),
+    }
+}
+
+pub async fn set_error_suggestions_enabled(enabled: bool, state: &AppState) -> Result<(), Error> {
+    state
+        .storage
+        .set_setting(ERROR_SUGGESTIONS_KEY, &serde_json::to_string(&enabled)?)?;
+    Ok(())
+}
+
+/// Replication slots, publications and subscriptions of a Postgres connection
+pub async fn get_postgres_replication_info(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<ReplicationInfo, Error> {
+    let client = {
+        let connection_entry = state
+            .connections
+            .get(&connection_id)
+            .with_context(|| format!("Connection not found: {}", connection_id))?;
+
+        if let Some(capabilities) = &connection_entry.capabilities {
+            if !capabilities.replication {
+                return Err(anyhow::anyhow!(
+                    "{} servers don't have Postgres replication slots or publications",
+                    capabilities.flavor.name()
+                )
+                .into());
+            }
+        }
+
+        match &connection_entry.value().runtime {
+            ConnectionRuntime::Connected(RuntimeClient::Postgres { pool }) => {
+                pool.metadata().clone()
+            }
+            ConnectionRuntime::Connected(_) => {
+                return Err(Error::Any(anyhow::anyhow!(
+                    "Replication info is only available for Postgres connections"
+                )))
+            }
+            ConnectionRuntime::Disconnected => {
+                return Err(Error::Any(anyhow::anyhow!("Connection not active")))
+            }
+        }
+    };
+
+    Ok(postgres::replication::get_replication_info(&client).await)
+}
+
+/// A snapshot of the statistics of a Postgres connection's server, taken at most every few seconds
+pub async fn get_postgres_stats(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<PostgresStats, Error> {
+    if let Some(stats) = state.postgres_stats.get(connection_id) {
+        return Ok(stats);
+    }
+
+    let pool = activity_pool(connection_id, state)?;
+    let stats = postgres::stats::get_postgres_stats(pool.metadata()).await;
+    state.postgres_stats.insert(connection_id, stats.clone());
+    Ok(stats)
+}
+
+fn activity_pool(connection_id: Uuid, state: &AppState) -> Result<Arc<PostgresPool>, Error> {
+    match connection_client(connection_id, state)? {
+        RuntimeClient::Postgres { pool } => Ok(pool),
+        _ => Err(anyhow::anyhow!("Only Postgres servers can be monitored").into()),
+    }
+}
+
+/// A page of the sessions of a Postgres connection's server, from `pg_stat_activity`
+pub async fn get_server_activity(
+    connection_id: Uuid,
+    filters: ActivityFilters,
+    state: &AppState,
+) -> Result<ServerActivity, Error> {
+    let pool = activity_pool(connection_id, state)?;
+    Ok(
+        postgres::activity::get_server_activity(pool.metadata(), &filters, &pool.backend_pids())
+            .await
+            .context("Failed to load the server's activity")?,
+    )
+}
+
+/// Cancels the query of a session of a Postgres connection's server, or with `force` closes the session. Sessions of
+/// the connection's own clients are only closed with `force`. False if there's no such session.
+pub async fn terminate_session(
+    connection_id: Uuid,
+    pid: i32,
+    force: bool,
+    state: &AppState,
+) -> Result<bool, Error> {
+    let pool = activity_pool(connection_id, state)?;
+    if !force && pool.backend_pids().contains(&pid) {
+        return Err(anyhow::anyhow!(
+            "Session {pid} is one of this connection's own, and is only closed when forced"
+        )
+        .into());
+    }
+
+    Ok(
+        postgres::activity::signal_session(pool.metadata(), pid, force)
+            .await
+            .with_context(|| format!("Failed to stop session {pid}"))?,
+    )
+}
+
+/// The session client of a Postgres connection whose server can deliver notifications
+fn notifying_client(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<Arc<tokio_postgres::Client>, Error> {
+    let connection_entry = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {}", connection_id))?;
+
+    if let Some(capabilities) = &connection_entry.capabilities {
+        if !capabilities.listen_notify {
+            return Err(anyhow::anyhow!(
+                "This {} connection can't LISTEN, either because the server doesn't support it or because it goes through a transaction pooler",
+                capabilities.flavor.name()
+            )
+            .into());
+        }
+    }
+
+    match &connection_entry.value().runtime {
+        ConnectionRuntime::Connected(RuntimeClient::Postgres { pool }) => {
+            Ok(pool.session().clone())
+        }
+        ConnectionRuntime::Connected(_) => Err(Error::Any(anyhow::anyhow!(
+            "Notifications are only available for Postgres connections"
+        ))),
+        ConnectionRuntime::Disconnected => {
+            Err(Error::Any(anyhow::anyhow!("Connection not active")))
+        }
+    }
+}
+
+/// Listens on a channel, reporting its notifications as `postgres-notification` events. The channel is listened
+/// on again whenever the connection is re-established, until the user disconnects.
+pub async fn listen_postgres(
+    connection_id: Uuid,
+    channel: &str,
+    state: &AppState,
+) -> Result<(), Error> {
+    let client = notifying_client(connection_id, state)?;
+    state
+        .notifications
+        .listen(connection_id, &client, channel)
+        .await
+}
+
+pub async fn unlisten_postgres(
+    connection_id: Uuid,
+    channel: &str,
+    state: &AppState,
+) -> Result<(), Error> {
+    let client = notifying_client(connection_id, state)?;
+    state
+        .notifications
+        .unlisten(connection_id, &client, channel)
+        .await
+}
+
+/// Channels a connection listens on
+pub async fn get_active_listens(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<Vec<String>, Error> {
+    Ok(state.notifications.active_listens(connection_id))
+}
+
+/// Notices and warnings a Postgres connection got outside of statements, oldest first. Those sent while a statement
+/// ran are in its [`StatementInfo`].
+pub async fn get_connection_messages(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<Vec<ServerMessage>, Error> {
+    Ok(state.server_messages.messages(connection_id))
+}
+
+// Script management commands
+
+/// Trimmed, without blanks or repeats, in their original order
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|t| t == tag) {
+            normalized.push(tag.to_owned());
+        }
+    }
+    normalized
+}
+
+/// Saves a new script. If `template_id` is given, the script is seeded with that template instead of `content`.
+#[allow(clippy::too_many_arguments)]
+pub async fn save_script(
+    name: String,
+    content: String,
+    connection_id: Option<Uuid>,
+    description: Option<String>,
+    template_id: Option<String>,
+    tags: Vec<String>,
+    favorite: bool,
+    state: &AppState,
+) -> Result<i64, Error> {
+    let query_text = match template_id {
+        Some(id) => script_templates::find_script_template(&id, &state.storage)?.body,
+        None => content,
+    };
+
+    let script = SavedQuery {
+        id: 0, // New script
+        name,
+        description,
+        query_text,
+        connection_id,
+        tags: normalize_tags(tags),
+        created_at: 0, // Will be set by storage
+        updated_at: 0, // Will be set by storage
+        favorite,
+    };
+
+    let script_id = state.storage.save_query(&script)?;
+    Ok(script_id)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn update_script(
+    id: i64,
+    name: String,
+    content: String,
+    connection_id: Option<Uuid>,
+    description: Option<String>,
+    tags: Vec<String>,
+    favorite: bool,
+    state: &AppState,
+) -> Result<(), Error> {
+    let script = SavedQuery {
+        id,
+        name,
+        description,
+        query_text: content,
+        connection_id,
+        tags: normalize_tags(tags),
+        created_at: 0, // Will be ignored for updates
+        updated_at: 0, // Will be set by storage
+        favorite,
+    };
+
+    state.storage.save_query(&script)?;
+    Ok(())
+}
+
+/// Built-in and user-defined templates for new scripts on this type of database
+pub async fn get_script_templates(
+    db_type: Database,
+    state: &AppState,
+) -> Result<Vec<ScriptTemplate>, Error> {
+    script_templates::get_script_templates(db_type, &state.storage)
+}
+
+/// Saves a user-defined template, replacing any template of the same name
+pub async fn save_script_template(
+    db_type: Database,
+    name: &str,
+    description: Option<String>,
+    body: &str,
+    state: &AppState,
+) -> Result<ScriptTemplate, Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Script templates need a name").into());
+    }
+
+    let description = description
+        .as_deref()
+        .map(str::trim)
+        .filter(|d| !d.is_empty());
+    state
+        .storage
+        .save_script_template(db_type, name, description, body)
+}
+
+/// The snippets offered on a connection, or all of them if None
+pub async fn get_snippets(
+    connection_id: Option<Uuid>,
+    state: &AppState,
+) -> Result<Vec<Snippet>, Error> {
+    let scope = match connection_id {
+        Some(connection_id) => {
+            let db_type = state
+                .connections
+                .get(&connection_id)
+                .with_context(|| format!("Connection not found: {}", connection_id))?
+                .config
+                .kind();
+            Some((connection_id, db_type))
+        }
+        None => None,
+    };
+    state
+        .storage
+        .get_snippets(scope.as_ref().map(|(id, db_type)| (id, *db_type)))
+}
+
+/// Creates a snippet if its id is 0, or updates the one with its id
+pub async fn save_snippet(mut snippet: Snippet, state: &AppState) -> Result<Snippet, Error> {
+    snippet.name = snippet.name.trim().to_owned();
+    snippet.prefix = snippet.prefix.trim().to_owned();
+    if snippet.name.is_empty() {
+        return Err(anyhow::anyhow!("Snippets need a name").into());
+    }
+    // Typed as a single word to expand it
+    if snippet.prefix.is_empty() || snippet.prefix.contains(char::is_whitespace) {
+        return Err(anyhow::anyhow!("Snippet prefixes need to be a single word").into());
+    }
+
+    state.storage.save_snippet(&snippet)
+}
+
+pub async fn delete_snippet(id: i64, state: &AppState) -> Result<(), Error> {
+    state.storage.delete_snippet(id)
+}
+
+/// The variables defined on a connection, those of no connection first, or all of them if None
+pub async fn get_query_variables(
+    connection_id: Option<Uuid>,
+    state: &AppState,
+) -> Result<Vec<QueryVariable>, Error> {
+    state.storage.get_query_variables(connection_id.as_ref())
+}
+
+/// Creates a variable if its id is 0, or updates the one with its id
+pub async fn save_query_variable(
+    mut variable: QueryVariable,
+    state: &AppState,
+) -> Result<QueryVariable, Error> {
+    variable.name = variable.name.trim().to_owned();
+    if !variables::is_valid_name(&variable.name) {
+        return Err(anyhow::anyhow!(
+            "Variable names are letters, digits and underscores, not starting with a digit"
+        )
+        .into());
+    }
+
+    state.storage.save_query_variable(&variable)
+}
+
+pub async fn delete_query_variable(id: i64, state: &AppState) -> Result<(), Error> {
+    state.storage.delete_query_variable(id)
+}
+
+/// `query` with its variables substituted, as it would run on the connection
+pub async fn preview_substitution(
+    query: &str,
+    connection_id: Option<Uuid>,
+    state: &AppState,
+) -> Result<String, Error> {
+    substitute_variables(query, connection_id, state)
+}
+
+/// Those of the connection take precedence over those of no connection of the same name
+fn substitute_variables(
+    query: &str,
+    connection_id: Option<Uuid>,
+    state: &AppState,
+) -> Result<String, Error> {
+    if !query.contains("${") {
+        return Ok(query.to_owned());
+    }
+
+    let mut values = HashMap::new();
+    for variable in state.storage.get_query_variables(connection_id.as_ref())? {
+        if variable.connection_id.is_some() || !values.contains_key(&variable.name) {
+            values.insert(variable.name, variable.value);
+        }
+    }
+    variables::substitute(query, &values)
+}
+
+pub async fn get_scheduled_queries(state: &AppState) -> Result<Vec<ScheduledQuery>, Error> {
+    state.storage.get_scheduled_queries()
+}
+
+/// Creates a scheduled query if its id is 0, or updates the one with its id
+pub async fn save_scheduled_query(
+    mut scheduled: ScheduledQuery,
+    state: &AppState,
+) -> Result<ScheduledQuery, Error> {
+    scheduled.name = scheduled.name.trim().to_owned();
+    if scheduled.name.is_empty() {
+        return Err(anyhow::anyhow!("Scheduled queries need a name").into());
+    }
+    if scheduled.query_text.trim().is_empty() {
+        return Err(anyhow::anyhow!("There's no query to schedule").into());
+    }
+    scheduled.schedule.validate()?;
+    check_scheduled_policy(&scheduled, state).await?;
+
+    state.storage.save_scheduled_query(&scheduled)
+}
+
+/// Scheduled queries run unattended, so those with statements the policy of their connection's environment holds
+/// back must have been saved as confirmed. Checked again before each run, as the environment may have changed since.
+async fn check_scheduled_policy(scheduled: &ScheduledQuery, state: &AppState) -> Result<(), Error> {
+    if scheduled.confirmed_destructive {
+        return Ok(());
+    }
+    let connection = state
+        .storage
+        .get_connections()?
+        .into_iter()
+        .find(|connection| connection.id == scheduled.connection_id)
+        .with_context(|| format!("Connection not found: {}", scheduled.connection_id))?;
+    let Some(environment) = connection.environment else {
+        return Ok(());
+    };
+    let policy = get_environment_policies(state).await?.of(environment);
+
+    let query = substitute_variables(&scheduled.query_text, Some(scheduled.connection_id), state)?;
+    let statements = match connection.config.kind() {
+        Database::Postgres => database::postgres::parser::parse_statements(&query),
+        Database::Sqlite => database::sqlite::parser::parse_statements(&query),
+        Database::MySql => database::mysql::parser::parse_statements(&query),
+    };
+    // Scripts that don't parse fail once run anyway
+    let Ok(statements) = statements else {
+        return Ok(());
+    };
+    let mut report = PreflightReport::default();
+    report.check_policy(policy, &query, &statements);
+
+    match report.guarded_statements.first() {
+        Some(guarded) => Err(anyhow::anyhow!(
+            "Statements like `{}` must be confirmed to run on a schedule on {} connections",
+            guarded.statement.trim(),
+            environment.as_str()
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+pub async fn delete_scheduled_query(id: i64, state: &AppState) -> Result<(), Error> {
+    state.storage.delete_scheduled_query(id)
+}
+
+/// The enabled scheduled queries that are due, leaving out those whose previous run is still going
+pub async fn due_scheduled_queries(state: &AppState) -> Result<Vec<ScheduledQuery>, Error> {
+    let now = chrono::Local::now();
+    Ok(state
+        .storage
+        .get_scheduled_queries()?
+        .into_iter()
+        .filter(|scheduled| {
+            scheduled.enabled
+                && !state.scheduler.is_running(scheduled.id)
+                && scheduled.schedule.is_due(scheduled.last_run_at, &now)
+        })
+        .collect())
+}
+
+/// Runs a scheduled query, records how it went with the query and in the history, and reports it if it failed or
+/// raised an alert. None if its previous run is still going.
+///
+/// Connections that aren't connected are skipped rather than connected to, and so are Postgres connections without a
+/// client to spare, until the next time the query is due.
+pub async fn run_scheduled_query(
+    scheduled: ScheduledQuery,
+    state: &AppState,
+) -> Result<Option<ScheduledRun>, Error> {
+    let Some(_claim) = state.scheduler.claim(scheduled.id) else {
+        return Ok(None);
+    };
+    let ran_at = chrono::Utc::now().timestamp();
+
+    let outcome = match scheduled_client(scheduled.connection_id, state) {
+        Some(client) => Some(execute_scheduled_query(&scheduled, client, state).await),
+        None => None,
+    };
+    let (status, summary, duration) = match outcome {
+        None => (
+            ScheduledRunStatus::Skipped,
+            RunSummary::default(),
+            Duration::ZERO,
+        ),
+        Some(Ok((summary, duration))) if summary.error.is_some() => {
+            (ScheduledRunStatus::Error, summary, duration)
+        }
+        Some(Ok((summary, duration))) => (ScheduledRunStatus::Success, summary, duration),
+        Some(Err(err)) => (
+            ScheduledRunStatus::Error,
+            RunSummary {
+                error: Some(err.to_string()),
+                ..Default::default()
+            },
+            Duration::ZERO,
+        ),
+    };
+
+    let run = ScheduledRun {
+        schedule_id: scheduled.id,
+        name: scheduled.name,
+        connection_id: scheduled.connection_id,
+        status,
+        row_count: summary.row_count,
+        duration_ms: duration.as_millis() as u64,
+        error: summary.error,
+        alert: scheduled.alert_if_rows
+            && status == ScheduledRunStatus::Success
+            && summary.returned_rows > 0,
+    };
+
+    state
+        .storage
+        .record_scheduled_run(scheduled.id, ran_at, status, run.error.as_deref())?;
+    if status != ScheduledRunStatus::Skipped {
+        let entry = QueryHistoryEntry {
+            id: 0,
+            connection_id: scheduled.connection_id.to_string(),
+            query_text: scheduled.query_text,
+            executed_at: ran_at,
+            duration_ms: Some(run.duration_ms as i64),
+            status: status.as_str().to_owned(),
+            row_count: run.row_count as i64,
+            error_message: run.error.clone(),
+            timings: None,
+            run_count: 1,
+            plan_json: None,
+            has_plan: false,
+            scheduled: true,
+        };
+        let dedupe = state.storage.get_history_settings()?.dedupe;
+        state.storage.save_query_history(&entry, dedupe)?;
+    }
+
+    state.scheduler.report(&run);
+    Ok(Some(run))
+}
+
+/// The client a scheduled run uses. For Postgres that's a client reserved as the session for the run, never the
+/// session client itself, so that the run doesn't end up in a transaction opened from the editor or queue behind its
+/// statements.
+fn scheduled_client(connection_id: Uuid, state: &AppState) -> Option<RuntimeClient> {
+    match connection_client(connection_id, state).ok()? {
+        RuntimeClient::Postgres { pool } => Some(RuntimeClient::Postgres {
+            pool: Arc::new(pool.reserve_session()?),
+        }),
+        client => Some(client),
+    }
+}
+
+/// Runs on a statement manager of its own, so that the results shown in the editor stay
+async fn execute_scheduled_query(
+    scheduled: &ScheduledQuery,
+    client: RuntimeClient,
+    state: &AppState,
+) -> Result<(RunSummary, Duration), Error> {
+    let connection_id = scheduled.connection_id;
+    check_scheduled_policy(scheduled, state).await?;
+    let query = substitute_variables(&scheduled.query_text, Some(connection_id), state)?;
+
+    let options = RunOptions {
+        limits: get_connection_resource_limits(connection_id, state).await?,
+        tag: render_query_tag(connection_id, &client, Some(scheduled.name.as_str()), state)?,
+        ..Default::default()
+    };
+
+    let manager = StatementManager::new();
+    let query_ids = manager.submit_query(connection_id, client, &query, &options)?;
+    scheduler::wait_for_run(&manager, &query_ids).await
+}
+
+pub async fn get_scripts(
+    connection_id: Option<Uuid>,
+    state: &AppState,
+) -> Result<Vec<SavedQuery>, Error> {
+    let scripts = state
+        .storage
+        .get_saved_queries(connection_id.as_ref(), &ScriptFilters::default())?;
+    Ok(scripts)
+}
+
+/// Scripts with a tag, favorites only, or matching some text, as any combination of those
+pub async fn get_scripts_filtered(
+    connection_id: Option<Uuid>,
+    tag: Option<String>,
+    favorites_only: bool,
+    search_text: Option<String>,
+    state: &AppState,
+) -> Result<Vec<SavedQuery>, Error> {
+    let filters = ScriptFilters {
+        tag: tag.map(|tag| tag.trim().to_owned()),
+        favorites_only,
+        search_text,
+    };
+    state
+        .storage
+        .get_saved_queries(connection_id.as_ref(), &filters)
+}
+
+/// Returns whether the script is now a favorite
+pub async fn toggle_script_favorite(id: i64, state: &AppState) -> Result<bool, Error> {
+    state.storage.toggle_saved_query_favorite(id)
+}
+
+pub async fn delete_script(id: i64, state: &AppState) -> Result<(), Error> {
+    state.storage.delete_saved_query(id)?;
+    Ok(())
+}
+
+/// The saved sessions of every window, the most recently saved first
+pub async fn list_sessions(state: &AppState) -> Result<Vec<SessionInfo>, Error> {
+    state.storage.list_sessions()
+}
+
+/// Saves the session of a window, `version` being that of the frontend's format of `data`
+pub async fn save_session(
+    session_id: &str,
+    version: u32,
+    data: &str,
+    state: &AppState,
+) -> Result<(), Error> {
+    if session_id.is_empty() {
+        return Err(anyhow::anyhow!("Sessions need an id").into());
+    }
+    state.storage.save_session(session_id, version, data)
+}
+
+pub async fn get_session(
+    session_id: &str,
+    state: &AppState,
+) -> Result<Option<SessionState>, Error> {
+    state.storage.get_session(session_id)
+}
+
+pub async fn delete_session(session_id: &str, state: &AppState) -> Result<(), Error> {
+    state.storage.delete_session(session_id)
+}
+
+/// Saves the latest contents of an editor buffer with unsaved changes. Called often, as the buffer is edited.
+pub async fn autosave_buffer(
+    buffer_id: &str,
+    connection_id: Option<Uuid>,
+    content: &str,
+    cursor: u32,
+    state: &AppState,
+) -> Result<(), Error> {
+    if buffer_id.is_empty() {
+        return Err(anyhow::anyhow!("Draft buffers need an id").into());
+    }
+    // The connection may have been removed since the buffer was opened
+    let connection_id = connection_id.filter(|id| state.connections.contains_key(id));
+    state
+        .storage
+        .save_draft_buffer(buffer_id, connection_id.as_ref(), content, cursor)
+}
+
+/// The drafts of every editor buffer, the most recently saved first
+pub async fn get_draft_buffers(state: &AppState) -> Result<Vec<DraftBuffer>, Error> {
+    state.storage.get_draft_buffers()
+}
+
+/// Drops the draft of a buffer that was saved or closed
+pub async fn discard_draft_buffer(buffer_id: &str, state: &AppState) -> Result<(), Error> {
+    state.storage.discard_draft_buffer(buffer_id)
+}
+
+fn execution_marks_key(tab_id: &str) -> String {
+    format!("execution_marks.{tab_id}")
+}
+
+fn save_execution_marks(tab_id: &str, state: &AppState) -> Result<(), Error> {
+    let marks = state.execution_marks.to_json(tab_id)?;
+    state
+        .storage
+        .set_setting(&execution_marks_key(tab_id), &marks)?;
+    Ok(())
+}
+
+/// Records how the statements of the last run went, before the statement manager drops them for a new run
+fn settle_execution_marks(state: &AppState) -> Result<(), Error> {
+    if let Some(tab_id) = state.execution_marks.refresh(&state.stmt_manager, true) {
+        save_execution_marks(&tab_id, state)?;
+    }
+    Ok(())
+}
+
+/// The statements of a tab that were run, with the outcome of their latest run, located in the tab's current
+/// text. Marks of statements that were changed since are dropped.
+pub async fn get_buffer_execution_state(
+    tab_id: &str,
+    text: &str,
+    state: &AppState,
+) -> Result<Vec<StatementMarker>, Error> {
+    if !state.execution_marks.is_loaded(tab_id) {
+        let saved = state.storage.get_setting(&execution_marks_key(tab_id))?;
+        if let Err(err) = state.execution_marks.load(tab_id, saved.as_deref()) {
+            log::warn!("Discarding the execution marks of tab {tab_id}: {err}");
+            state.execution_marks.load(tab_id, None)?;
+        }
+    }
+
+    let refreshed = state.execution_marks.refresh(&state.stmt_manager, false);
+    let (markers, pruned) = state.execution_marks.markers(tab_id, text);
+    if pruned && refreshed.as_deref() != Some(tab_id) {
+        save_execution_marks(tab_id, state)?;
+    }
+    if let Some(refreshed) = refreshed {
+        save_execution_marks(&refreshed, state)?;
+    }
+
+    Ok(markers)
+}
+
+/// Saves the state of a connection's Items panel, once it's validated
+pub async fn save_tree_state(
+    connection_id: Uuid,
+    state_json: &str,
+    state: &AppState,
+) -> Result<(), Error> {
+    let tree_state = tree_state::parse_tree_state(state_json)?;
+    state
+        .storage
+        .save_tree_state(&connection_id, &serde_json::to_string(&tree_state)?)?;
+    Ok(())
+}
+
+/// The saved state of a connection's Items panel, upgraded to the current version.
+/// States that can't be read anymore are discarded, so that they don't keep the panel from opening.
+pub async fn get_tree_state(
+    connection_id: Uuid,
+    state: &AppState,
+) -> Result<Option<TreeState>, Error> {
+    let Some(saved) = state.storage.get_tree_state(&connection_id)? else {
+        return Ok(None);
+    };
+
+    match tree_state::parse_tree_state(&saved) {
+        Ok(tree_state) => Ok(Some(tree_state)),
+        Err(err) => {
+            log::warn!("Discarding the tree state of {connection_id}: {err}");
+            Ok(None)
+        }
+    }
+}
+
+/// Export page to CSV
+pub async fn export_page(
+    query_id: usize,
+    page_index: usize,
+    state: &AppState,
+) -> Result<String, Error> {
+    let now = Instant::now();
+    let columns = state
+        .stmt_manager
+        .get_columns(query_id)?
+        .ok_or_else(|| anyhow::anyhow!("No columns found yet"))?;
+
+    let page = state
+        .stmt_manager
+        .fetch_page(query_id, page_index)?
+        .ok_or_else(|| anyhow::anyhow!("No page {page_index} found yet"))?;
+
+    let csv_export = database::export::export_to_csv(columns.get(), page.get())?;
+
+    let elapsed = now.elapsed();
+
+    log::info!(
+        "Took {}us to export page {page_index} to CSV",
+        elapsed.as_micros()
+    );
+
+    Ok(csv_export)
+}
+
+/// Loads a CSV file into a table, which is created if it doesn't exist and `options` say so. Rows the database
+/// rejects are left out and reported, unless `options` say to stop at the first one.
+pub async fn import_csv(
+    connection_id: Uuid,
+    file_path: &str,
+    options: CsvImportOptions,
+    state: &AppState,
+) -> Result<CsvImportReport, Error> {
+    options.validate()?;
+    let client = {
+        let connection = state
+            .connections
+            .get(&connection_id)
+            .with_context(|| format!("Connection not found: {connection_id}"))?;
+        if connection.permissions == Permissions::ReadOnly {
+            return Err(anyhow::anyhow!("The connection is read-only").into());
+        }
+        connection.get_client()?
+    };
+
+    let table = TargetTable {
+        schema: options
+            .schema
+            .clone()
+            .filter(|schema| !schema.trim().is_empty()),
+        name: match options.table.trim() {
+            "" => Path::new(file_path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .context("Pick a table to import into")?,
+            table => table.to_owned(),
+        },
+    };
+    let create_table = options.create_table;
+    let path = file_path.to_owned();
+    // Reading the header and the rows to infer types from blocks
+    let (batches, size) = tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&path).with_context(|| format!("Failed to open {path}"))?;
+        let size = file.metadata().map_or(0, |metadata| metadata.len());
+        let batches = CsvBatches::new(std::io::BufReader::new(file), &options)?;
+        Ok::<_, Error>((batches, size))
+    })
+    .await??;
+
+    let operation = state.operations.start(
+        OperationKind::Import,
+        format!("Importing {file_path}"),
+        true,
+    );
+    let show_progress = |bytes_read: u64, rows_loaded: u64| {
+        operation.set_progress(bytes_read as usize, size as usize);
+        operation.set_detail(format!("{rows_loaded} rows loaded"));
+    };
+    let result = match client {
+        RuntimeClient::Postgres { pool } => {
+            let progress = |report: &CsvImportReport| {
+                operation.check_cancelled()?;
+                show_progress(report.bytes_read, report.rows_loaded);
+                Ok(())
+            };
+            csv_import::load_postgres(&pool, batches, &table, create_table, progress).await
+        }
+        RuntimeClient::SQLite { connection, .. } => {
+            // The load holds the connection's lock and blocks, so it runs on a blocking thread, sending its
+            // progress back here
+            let token = operation.token().clone();
+            let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+            let load = tokio::task::spawn_blocking(move || {
+                let progress = |report: &CsvImportReport| {
+                    if token.is_cancelled() {
+                        return Err(anyhow::anyhow!("Operation cancelled").into());
+                    }
+                    let _ = sender.send((report.bytes_read, report.rows_loaded));
+                    Ok(())
+                };
+                csv_import::load_sqlite(&connection, batches, &table, create_table, progress)
+            });
+            while let Some((bytes_read, rows_loaded)) = receiver.recv().await {
+                show_progress(bytes_read, rows_loaded);
+            }
+            load.await?
+        }
+        RuntimeClient::MySQL { .. } => Err(anyhow::anyhow!(
+            "CSV files can only be imported into Postgres and SQLite connections"
+        )
+        .into()),
+    };
+    let report = operation.complete(result)?;
+
+    if report.created_table {
+        state.schemas.remove(&connection_id);
+    }
+    Ok(report)
+}
+
+/// The Postgres pool of a connection, for copies, which only Postgres has. Copies into a table need a connection
+/// that isn't read-only.
+fn copy_pool(
+    connection_id: Uuid,
+    writes: bool,
+    state: &AppState,
+) -> Result<Arc<PostgresPool>, Error> {
+    let connection = state
+        .connections
+        .get(&connection_id)
+        .with_context(|| format!("Connection not found: {connection_id}"))?;
+    if writes && connection.permissions == Permissions::ReadOnly {
+        return Err(anyhow::anyhow!("The connection is read-only").into());
+    }
+    match connection.get_client()? {
+        RuntimeClient::Postgres { pool } => Ok(pool),
+        _ => Err(anyhow::anyhow!("Only Postgres connections can run COPY").into()),
+    }
+}
+
+/// A client of its own for a copy, which keeps the client busy until it's over
+fn reserve_copy_session(pool: &PostgresPool) -> Result<PostgresPool, Error> {
+    Ok(pool.reserve_session().context(
+        "Every client of the connection is busy with a transaction, commit or roll one back to copy",
+    )?)
+}
+
+/// Runs a `COPY ... TO STDOUT`, writing its output to a file as it arrives
+pub async fn copy_out_to_file(
+    connection_id: Uuid,
+    statement: &str,
+    path: &str,
+    state: &AppState,
+) -> Result<CopyReport, Error> {
+    if pg_copy::copy_direction(statement) != Some(CopyDirection::Out) {
+        return Err(anyhow::anyhow!("Only COPY ... TO STDOUT can be copied to a file").into());
+    }
+    let pool = copy_pool(connection_id, false, state)?;
+
+    let operation =
+        state
+            .operations
+            .start(OperationKind::Export, format!("Copying to {path}"), true);
+    let result = async {
+        use std::io::Write;
+
+        let mut file = std::io::BufWriter::new(
+            std::fs::File::create(path).with_context(|| format!("Failed to create {path}"))?,
+        );
+        let pool = reserve_copy_session(&pool)?;
+        let mut written = 0u64;
+        let report = tokio::select! {
+            report = pg_copy::copy_out(pool.session(), statement, |data| {
+                operation.check_cancelled()?;
+                file.write_all(data).with_context(|| format!("Failed to write to {path}"))?;
+                written += data.len() as u64;
+                operation.set_detail(format!("{written} bytes copied"));
+                Ok(())
+            }) => report?,
+            // The output may stall for a while, e.g. behind a slow query
+            _ = operation.token().cancelled() => return Err(anyhow::anyhow!("Operation cancelled").into()),
+        };
+        file.flush().with_context(|| format!("Failed to write to {path}"))?;
+        Ok::<_, Error>(report)
+    }
+    .await;
+    if result.is_err() {
+        let _ = std::fs::remove_file(path);
+    }
+    operation.complete(result)
+}
+
+/// Runs a `COPY ... FROM STDIN`, sending it the contents of a file. The server keeps none of the rows if it fails or
+/// is cancelled.
+pub async fn copy_in_from_file(
+    connection_id: Uuid,
+    statement: &str,
+    path: &str,
+    state: &AppState,
+) -> Result<CopyReport, Error> {
+    if pg_copy::copy_direction(statement) != Some(CopyDirection::In) {
+        return Err(anyhow::anyhow!("Only COPY ... FROM STDIN can be copied from a file").into());
+    }
+    let pool = copy_pool(connection_id, true, state)?;
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {path}"))?;
+    let size = file.metadata().map_or(0, |metadata| metadata.len());
+    let reader = std::io::BufReader::new(file);
+
+    let operation = state
+        .operations
+        .start(OperationKind::Import, format!("Copying {path}"), true);
+    let result = async {
+        let pool = reserve_copy_session(&pool)?;
+        tokio::select! {
+            report = pg_copy::copy_in(pool.session(), statement, reader, |bytes| {
+                operation.check_cancelled()?;
+                operation.set_progress(bytes as usize, size as usize);
+                operation.set_detail(format!("{bytes} bytes copied"));
+                Ok(())
+            }) => report,
+            _ = operation.token().cancelled() => Err(anyhow::anyhow!("Operation cancelled").into()),
+        }
+    }
+    .await;
+    operation.complete(result)
+}
+
+/// Export every page of a query's results to an Excel file
+pub async fn export_to_xlsx(query_id: usize, path: &str, state: &AppState) -> Result<(), Error> {
+    let operation =
+        state
+            .operations
+            .start(OperationKind::Export, format!("Exporting to {path}"), true);
+    let result = write_xlsx(query_id, path, &operation, state).await;
+    operation.complete(result)
+}
+
+async fn write_xlsx(
+    query_id: usize,
+    path: &str,
+    operation: &Operation<'_>,
+    state: &AppState,
+) -> Result<(), Error> {
+    let now = Instant::now();
+    let columns = state
+        .stmt_manager
+        .get_columns(query_id)?
+        .ok_or_else(|| anyhow::anyhow!("No columns found yet"))?;
+
+    let column_types = state.stmt_manager.get_column_types(query_id)?;
+
+    let mut exporter = database::export::XlsxExporter::new(columns.get(), &column_types)?;
+
+    let page_count = state.stmt_manager.get_page_count(query_id)?;
+    for page_index in 0..page_count {
+        operation.check_cancelled()?;
+        if let Some(page) = state.stmt_manager.fetch_page(query_id, page_index)? {
+            exporter.write_page(page.get())?;
+        }
+        operation.set_progress(page_index + 1, page_count);
+        // Lets other commands, e.g. cancelling this export, run between pages
+        tokio::task::yield_now().await;
+    }
+
+    exporter.save(Path::new(path))?;
+
+    log::info!(
+        "Took {}ms to export {page_count} pages to {path}",
+        now.elapsed().as_millis()
+    );
+
+    Ok(())
+}
+
+/// Exports every page of a query as delimited text, using either a saved template or inline options
+pub async fn export_query_results(
+    query_id: usize,
+    path: &str,
+    template_id: Option<i64>,
+    options: Option<ExportOptions>,
+    state: &AppState,
+) -> Result<(), Error> {
+    let operation =
+        state
+            .operations
+            .start(OperationKind::Export, format!("Exporting to {path}"), true);
+    let result = write_delimited(query_id, path, template_id, options, &operation, state).await;
+    operation.complete(result)
+}
+
+async fn write_delimited(
+    query_id: usize,
+    path: &str,
+    template_id: Option<i64>,
+    options: Option<ExportOptions>,
+    operation: &Operation<'_>,
+    state: &AppState,
+) -> Result<(), Error> {
+    let now = Instant::now();
+    let options = match (template_id, options) {
+        (Some(_), Some(_)) => {
+            return Err(
+                anyhow::anyhow!("Expected either a template or export options, not both").into(),
+            )
+        }
+        (Some(id), None) => {
+            state
+                .storage
+                .get_export_template(id)?
+                .with_context(|| format!("Export template {id} not found"))?
+                .options
+        }
+        (None, options) => options.unwrap_or_default(),
+    };
+
+    let columns = state
+        .stmt_manager
+        .get_columns(query_id)?
+        .ok_or_else(|| anyhow::anyhow!("No columns found yet"))?;
+    let columns: Vec<String> = serde_json::from_str(columns.get())?;
+
+    let file = std::fs::File::create(path).with_context(|| format!("Failed to create {path}"))?;
+    let mut writer = DelimitedWriter::new(std::io::BufWriter::new(file), &options, &columns)?;
+
+    let page_count = state.stmt_manager.get_page_count(query_id)?;
+    for page_index in 0..page_count {
+        operation.check_cancelled()?;
+        if let Some(page) = state.stmt_manager.fetch_page(query_id, page_index)? {
+            writer.write_page(page.get())?;
+        }
+        operation.set_progress(page_index + 1, page_count);
+        tokio::task::yield_now().await;
+    }
+
+    writer
+        .finish()
+        .with_context(|| format!("Failed to write {path}"))?;
+
+    log::info!(
+        "Took {}ms to export {page_count} pages to {path}",
+        now.elapsed().as_millis()
+    );
+
+    Ok(())
+}
+
+/// Exports every page of a query as JSON, either as an array of objects or as NDJSON
+pub async fn export_query_results_json(
+    query_id: usize,
+    path: &str,
+    format: JsonFormat,
+    state: &AppState,
+) -> Result<(), Error> {
+    let operation =
+        state
+            .operations
+            .start(OperationKind::Export, format!("Exporting to {path}"), true);
+    let result = write_json(query_id, path, format, &operation, state).await;
+    operation.complete(result)
+}
+
+async fn write_json(
+    query_id: usize,
+    path: &str,
+    format: JsonFormat,
+    operation: &Operation<'_>,
+    state: &AppState,
+) -> Result<(), Error> {
+    let now = Instant::now();
+
+    let columns = state
+        .stmt_manager
+        .get_columns(query_id)?
+        .ok_or_else(|| anyhow::anyhow!("No columns found yet"))?;
+    let columns: Vec<String> = serde_json::from_str(columns.get())?;
+    let column_types = state.stmt_manager.get_column_types(query_id)?;
+    // Which column types are binary depends on the database they come from
+    let dialect = state
+        .stmt_manager
+        .get_connection_id(query_id)?
+        .and_then(|connection_id| state.connections.get(&connection_id))
+        .map(|connection| InsertDialect::from(connection.config.kind()));
+
+    let file = std::fs::File::create(path).with_context(|| format!("Failed to create {path}"))?;
+    let mut writer = JsonWriter::new(
+        std::io::BufWriter::new(file),
+        format,
+        dialect,
+        &columns,
+        &column_types,
+    );
+
+    let page_count = state.stmt_manager.get_page_count(query_id)?;
+    for page_index in 0..page_count {
+        operation.check_cancelled()?;
+        if let Some(page) = state.stmt_manager.fetch_page(query_id, page_index)? {
+            writer.write_page(page.get())?;
+        }
+        operation.set_progress(page_index + 1, page_count);
+        tokio::task::yield_now().await;
+    }
+
+    writer
+        .finish()
+        .with_context(|| format!("Failed to write {path}"))?;
+
+    log::info!(
+        "Took {}ms to export {page_count} pages to {path}",
+        now.elapsed().as_millis()
+    );
+
+    Ok(())
+}
+
+/// Writes a query's results as INSERT statements into `table_name`. They're written to `path` if it's given,
+/// and returned otherwise, as long as they fit in the options' `max_inline_bytes`.
+pub async fn generate_inserts(
+    query_id: usize,
+    table_name: &str,
+    options: InsertOptions,
+    path: Option<&str>,
+    state: &AppState,
+) -> Result<Option<String>, Error> {
+    let Some(path) = path else {
+        let max_inline_bytes = options.max_inline_bytes;
+        let inserts = write_inserts(query_id, table_name, options, Vec::new(), None, state)?;
+        if inserts.len() > max_inline_bytes {
+            return Err(too_large_for_inline(max_inline_bytes));
+        }
+        return Ok(Some(
+            String::from_utf8(inserts).context("Generated invalid UTF-8")?,
+        ));
+    };
+
+    let operation =
+        state
+            .operations
+            .start(OperationKind::Export, format!("Exporting to {path}"), true);
+    let result = std::fs::File::create(path)
+        .with_context(|| format!("Failed to create {path}"))
+        .map_err(Error::from)
+        .and_then(|file| {
+            let out = std::io::BufWriter::new(file);
+            write_inserts(query_id, table_name, options, out, Some(&operation), state)
+        })
+        .map(|_| None);
+    operation.complete(result)
+}
+
+fn write_inserts<W: std::io::Write>(
+    query_id: usize,
+    table_name: &str,
+    options: InsertOptions,
+    out: W,
+    operation: Option<&Operation>,
+    state: &AppState,
+) -> Result<W, Error> {
+    let now = Instant::now();
+    // Only output that's returned is limited
+    let max_bytes = operation.is_none().then_some(options.max_inline_bytes);
+
+    let columns = state
+        .stmt_manager
+        .get_columns(query_id)?
+        .ok_or_else(|| anyhow::anyhow!("No columns found yet"))?;
+    let columns: Vec<String> = serde_json::from_str(columns.get())?;
+    let column_types = state.stmt_manager.get_column_types(query_id)?;
+
+    let mut writer = InsertWriter::new(out, options, table_name, &columns, &column_types)?;
+
+    let page_count = state.stmt_manager.get_page_count(query_id)?;
+    for page_index in 0..page_count {
+        if let Some(operation) = operation {
+            operation.check_cancelled()?;
+        }
+        if let Some(page) = state.stmt_manager.fetch_page(query_id, page_index)? {
+            writer.write_page(page.get())?;
+        }
+        if let Some(max_bytes) = max_bytes.filter(|&max| writer.bytes_written() > max) {
+            return Err(too_large_for_inline(max_bytes));
+        }
+        if let Some(operation) = operation {
+            operation.set_progress(page_index + 1, page_count);
+        }
+    }
+    let out = writer.finish()?;
+
+    log::info!(
+        "Took {}ms to generate INSERTs for {page_count} pages",
+        now.elapsed().as_millis()
+    );
+
+    Ok(out)
 }
 
-pub async fn save_query_to_history(
-    connection_id: String,
-    query: String,
-    duration_ms: Option<u64>,
-    status: String,
-    row_count: u64,
-    error_message: Option<String>,
-    state: &AppState,
-) -> Result<(), Error> {
-    let entry = QueryHistoryEntry {
-        id: 0, // Sqlite will assign,
-        connection_id,
-        query_text: query,
-        executed_at: chrono::Utc::now().timestamp(),
-        duration_ms: duration_ms.map(|d| d as i64),
-        status,
-        row_count: row_count as i64,
-        error_message,
-    };
+fn too_large_for_inline(max_bytes: usize) -> Error {
+    anyhow::anyhow!(
+        "The INSERT statements are larger than {max_bytes} bytes, write them to a file instead"
+    )
+    .into()
+}
 
-    state.storage.save_query_history(&entry)?;
-    Ok(())
+pub async fn list_export_templates(state: &AppState) -> Result<Vec<ExportTemplate>, Error> {
+    state.storage.get_export_templates()
 }
 
-pub async fn get_query_history(
-    connection_id: String,
-    limit: Option<u32>,
+/// Creates an export template, or updates an existing one if `id` is given
+pub async fn save_export_template(
+    id: Option<i64>,
+    name: &str,
+    options: ExportOptions,
     state: &AppState,
-) -> Result<Vec<QueryHistoryEntry>, Error> {
+) -> Result<ExportTemplate, Error> {
+    if name.trim().is_empty() {
+        return Err(anyhow::anyhow!("Export templates need a name").into());
+    }
+    options.validate()?;
+
     state
         .storage
-        .get_query_history(&connection_id, limit.map(|l| l as i64))
+        .save_export_template(id, name.trim(), &options)
 }
 
-pub async fn initialize_connections(state: &AppState) -> Result<(), Error> {
-    let stored_connections = state.storage.get_connections()?;
+/// Long-running operations that haven't finished yet
+pub async fn list_operations(state: &AppState) -> Result<Vec<OperationInfo>, Error> {
+    Ok(state.operations.list())
+}
 
-    for stored_connection in stored_connections {
-        let connection = Connection::new(
-            stored_connection.id,
-            stored_connection.name,
-            stored_connection.config,
-            stored_connection.permissions,
-        );
-        state.connections.insert(connection.id, connection);
+pub async fn cancel_operation(id: Uuid, state: &AppState) -> Result<(), Error> {
+    state.operations.cancel(id)
+}
+
+const RECENT_SCRIPT_FILES_KEY: &str = "recent_script_files";
+const MAX_RECENT_SCRIPT_FILES: usize = 20;
+
+/// Reads a `.sql` file from outside pgpad (drag-and-drop, file association, ...),
+/// along with a summary of any destructive statements it contains
+pub async fn open_script_file(path: &str, state: &AppState) -> Result<ScriptFile, Error> {
+    let script = read_script_file(PathBuf::from(path), state).await?;
+
+    let mut recent = get_recent_script_files(state).await?;
+    recent.retain(|recent_path| recent_path != &script.path);
+    recent.insert(0, script.path.clone());
+    recent.truncate(MAX_RECENT_SCRIPT_FILES);
+    state
+        .storage
+        .set_setting(RECENT_SCRIPT_FILES_KEY, &serde_json::to_string(&recent)?)?;
+
+    Ok(script)
+}
+
+pub async fn get_recent_script_files(state: &AppState) -> Result<Vec<String>, Error> {
+    match state.storage.get_setting(RECENT_SCRIPT_FILES_KEY)? {
+        Some(value) => Ok(serde_json::from_str(&value)?),
+        None => Ok(Vec::new()),
     }
+}
 
-    log::info!(
-        "Initialized {} connections from storage",
-        state.connections.len()
+/// Same as the amount of rows our row writers put in a page
+const DERIVED_PAGE_SIZE: usize = 50;
+
+/// Groups the cached results of a query, registering the grouped rows as a new query
+pub async fn group_query_results(
+    query_id: usize,
+    group_columns: Vec<String>,
+    aggregates: Vec<Aggregate>,
+    state: &AppState,
+) -> Result<GroupedQuery, Error> {
+    let CachedResult { columns, rows, .. } = cached_result(query_id, state)?;
+
+    let grouped = grouping::group_rows(
+        &columns,
+        rows,
+        &group_columns,
+        &aggregates,
+        grouping::MAX_GROUPS,
+    )?;
+
+    let group_count = grouped.rows.len();
+    let columns = RawValue::from_string(serde_json::to_string(&grouped.columns)?)?;
+    let pages = grouped
+        .rows
+        .chunks(DERIVED_PAGE_SIZE)
+        .map(|chunk| Ok(RawValue::from_string(serde_json::to_string(chunk)?)?))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let query_id =
+        state
+            .stmt_manager
+            .register_derived_result(columns, pages, grouped.truncated_groups)?;
+
+    Ok(GroupedQuery {
+        query_id,
+        group_count,
+        truncated_groups: grouped.truncated_groups,
+    })
+}
+
+/// Sorts the cached results of a query with the given collation, registering the sorted rows as a new query
+pub async fn sort_query_results(
+    query_id: usize,
+    keys: Vec<SortKey>,
+    collation: Collation,
+    state: &AppState,
+) -> Result<SortedQuery, Error> {
+    let CachedResult { columns, rows, .. } = cached_result(query_id, state)?;
+
+    let rows =
+        tokio::task::spawn_blocking(move || sorting::sort_rows(&columns, rows, &keys, collation))
+            .await??;
+
+    let row_count = rows.len();
+    let columns = state
+        .stmt_manager
+        .get_columns(query_id)?
+        .ok_or_else(|| anyhow::anyhow!("No columns found yet for query {query_id}"))?;
+    let pages = rows
+        .chunks(DERIVED_PAGE_SIZE)
+        .map(|chunk| Ok(RawValue::from_string(serde_json::to_string(chunk)?)?))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let truncated = state.stmt_manager.is_truncated(query_id)?;
+    let query_id = state
+        .stmt_manager
+        .register_derived_result(columns, pages, truncated)?;
+
+    Ok(SortedQuery {
+        query_id,
+        row_count,
+    })
+}
+
+/// Results of more rows than this are sorted or filtered as an operation, whose progress is shown
+const LARGE_RESULT_ROWS: usize = 100_000;
+
+/// Sorts the rows of a completed query in place by one of its columns, spilled pages included, so that its pages
+/// are fetched sorted from then on. Each page keeps its number of rows. Values compare the way the type of the
+/// column orders them. Sorting the query again supersedes a sort of it still in progress, which then fails.
+pub async fn sort_result(
+    query_id: usize,
+    column_index: usize,
+    descending: bool,
+    nulls: NullsOrder,
+    state: &AppState,
+) -> Result<SortedQuery, Error> {
+    let columns: Vec<String> = match state.stmt_manager.get_columns(query_id)? {
+        Some(columns) => serde_json::from_str(columns.get())?,
+        None => Vec::new(),
+    };
+    let column = columns
+        .get(column_index)
+        .with_context(|| format!("The results have no column {column_index}"))?;
+    let kind = state
+        .stmt_manager
+        .get_column_types(query_id)?
+        .get(column_index)
+        .cloned()
+        .flatten()
+        .map(|column_type| ColumnKind::from_type(&column_type));
+
+    let rewrite = state.stmt_manager.start_rewrite(query_id)?;
+    let row_count = state
+        .stmt_manager
+        .get_statement_info(query_id)?
+        .map_or(0, |info| info.metrics.rows_fetched);
+    let sort = SortColumn {
+        index: column_index,
+        kind,
+        descending,
+        nulls,
+    };
+    if row_count <= LARGE_RESULT_ROWS {
+        return sort_pages(query_id, sort, rewrite, None, state).await;
+    }
+
+    let operation = state.operations.start(
+        OperationKind::Sort,
+        format!("Sorting {row_count} rows by {column}"),
+        true,
     );
-    Ok(())
+    let result = sort_pages(query_id, sort, rewrite, Some(&operation), state).await;
+    operation.complete(result)
 }
 
-pub async fn format_sql(query: &str) -> Result<String, Error> {
-    let formatted = sqlformat::format(query, &Default::default(), &Default::default());
-    Ok(formatted)
+struct SortColumn {
+    index: usize,
+    kind: Option<ColumnKind>,
+    descending: bool,
+    nulls: NullsOrder,
 }
 
-pub async fn is_query_read_only(
-    connection_id: Uuid,
-    query: &str,
+async fn sort_pages(
+    query_id: usize,
+    sort: SortColumn,
+    rewrite: PageRewrite,
+    operation: Option<&Operation<'_>>,
     state: &AppState,
-) -> Result<bool, Error> {
-    log::info!("Checking if {query} is read-only");
-    let db = state
-        .connections
-        .get(&connection_id)
-        .with_context(|| format!("Connection not found: {}", connection_id))?
-        .config
-        .kind();
+) -> Result<SortedQuery, Error> {
+    let check = |rewrite: &PageRewrite| -> Result<(), Error> {
+        rewrite.check_superseded()?;
+        operation.map_or(Ok(()), |operation| operation.check_cancelled())
+    };
+    // Reading the pages is the first half of the work, writing them back the second
+    let progress = |done: usize, page_count: usize| {
+        if let Some(operation) = operation {
+            operation.set_progress(done, page_count * 2);
+        }
+    };
 
-    let stmts = match db {
-        Database::Postgres => database::postgres::parser::parse_statements(query)?,
-        Database::Sqlite => database::sqlite::parser::parse_statements(query)?,
+    let page_count = state.stmt_manager.get_page_count(query_id)?;
+    let mut rows = Vec::new();
+    let mut page_lengths = Vec::with_capacity(page_count);
+    for page_index in 0..page_count {
+        check(&rewrite)?;
+        if let Some(page) = state.stmt_manager.fetch_page(query_id, page_index)? {
+            let page = grouping::parse_page(page.get())?;
+            page_lengths.push(page.len());
+            rows.extend(page);
+        }
+        progress(page_index + 1, page_count);
+    }
+
+    let rows = tokio::task::spawn_blocking(move || {
+        sorting::sort_rows_by_column(
+            &mut rows,
+            sort.index,
+            sort.kind,
+            sort.descending,
+            sort.nulls,
+        );
+        rows
+    })
+    .await?;
+
+    let mut pages = Vec::with_capacity(page_lengths.len());
+    let mut remaining = rows.as_slice();
+    for (page_index, len) in page_lengths.into_iter().enumerate() {
+        check(&rewrite)?;
+        let (page, rest) = remaining.split_at(len);
+        pages.push(RawValue::from_string(serde_json::to_string(page)?)?);
+        remaining = rest;
+        progress(page_count + page_index + 1, page_count);
+    }
+
+    state.stmt_manager.finish_rewrite(rewrite, pages)?;
+    Ok(SortedQuery {
+        query_id,
+        row_count: rows.len(),
+    })
+}
+
+/// Registers the rows of a completed query that match `filter` as a query of their own. Its pages are made from
+/// those of the filtered query when they're fetched, so only the positions of the matching rows are kept.
+pub async fn filter_result(
+    query_id: usize,
+    filter: RowFilter,
+    state: &AppState,
+) -> Result<FilteredQuery, Error> {
+    if state.stmt_manager.get_query_status(query_id)? != QueryStatus::Completed {
+        return Err(anyhow::anyhow!("Query {query_id} has to complete first").into());
+    }
+    let column_count = match state.stmt_manager.get_columns(query_id)? {
+        Some(columns) => serde_json::from_str::<Vec<String>>(columns.get())?.len(),
+        None => 0,
     };
+    let filter = CompiledFilter::new(&filter, column_count)?;
 
-    Ok(stmts.into_iter().all(|stmt| stmt.is_read_only))
+    let row_count = state
+        .stmt_manager
+        .get_statement_info(query_id)?
+        .map_or(0, |info| info.metrics.rows_fetched);
+    if row_count <= LARGE_RESULT_ROWS {
+        return filter_pages(query_id, &filter, None, state);
+    }
+
+    let operation = state.operations.start(
+        OperationKind::Filter,
+        format!("Filtering {row_count} rows"),
+        true,
+    );
+    let result = filter_pages(query_id, &filter, Some(&operation), state);
+    operation.complete(result)
 }
 
-pub async fn get_database_schema(
-    connection_id: Uuid,
+fn filter_pages(
+    query_id: usize,
+    filter: &CompiledFilter,
+    operation: Option<&Operation<'_>>,
     state: &AppState,
-) -> Result<Arc<DatabaseSchema>, Error> {
-    if let Some(schema) = state.schemas.get(&connection_id) {
-        return Ok(schema.clone());
+) -> Result<FilteredQuery, Error> {
+    let rewrites = state.stmt_manager.get_rewrite_count(query_id)?;
+    let page_count = state.stmt_manager.get_page_count(query_id)?;
+
+    let mut matching = Vec::new();
+    let mut source_row_count = 0;
+    for page_index in 0..page_count {
+        if let Some(operation) = operation {
+            operation.check_cancelled()?;
+        }
+        if let Some(page) = state.stmt_manager.fetch_page(query_id, page_index)? {
+            let rows = grouping::parse_page(page.get())?;
+            source_row_count += rows.len();
+            matching.extend(
+                rows.iter()
+                    .enumerate()
+                    .filter(|(_, row)| filter.matches(row))
+                    .map(|(row_index, _)| (page_index, row_index)),
+            );
+        }
+        if let Some(operation) = operation {
+            operation.set_progress(page_index + 1, page_count);
+        }
     }
 
-    let connection_entry = state
-        .connections
-        .get(&connection_id)
-        .with_context(|| format!("Connection not found: {}", connection_id))?;
+    let row_count = matching.len();
+    let query_id = state
+        .stmt_manager
+        .register_row_view(query_id, matching, rewrites)?;
+    Ok(FilteredQuery {
+        query_id,
+        row_count,
+        source_row_count,
+    })
+}
+
+/// Drops a filtered result, see [`filter_result`]. The query it filtered is left as it was.
+pub fn clear_result_filter(query_id: usize, state: &AppState) -> Result<(), Error> {
+    state.stmt_manager.remove_derived_result(query_id)
+}
+
+/// Keeps a finished result when the next query is submitted, so that it can be joined or diffed with the results
+/// of later runs
+pub fn pin_result(query_id: usize, state: &AppState) -> Result<(), Error> {
+    state.stmt_manager.pin_result(query_id)
+}
+
+/// Lets a result be dropped again, see [`pin_result`]
+pub fn unpin_result(query_id: usize, state: &AppState) -> Result<(), Error> {
+    state.stmt_manager.unpin_result(query_id)
+}
 
-    let connection = connection_entry.value();
+fn cached_result(query_id: usize, state: &AppState) -> Result<CachedResult, Error> {
+    let columns = state
+        .stmt_manager
+        .get_columns(query_id)?
+        .ok_or_else(|| anyhow::anyhow!("No columns found yet for query {query_id}"))?;
+    let columns: Vec<String> = serde_json::from_str(columns.get())?;
+    let column_types = state.stmt_manager.get_column_types(query_id)?;
 
-    let schema = match &connection.runtime {
-        ConnectionRuntime::Connected(RuntimeClient::Postgres { client }) => {
-            postgres::schema::get_database_schema(client).await?
+    let page_count = state.stmt_manager.get_page_count(query_id)?;
+    let mut rows = Vec::new();
+    for page_index in 0..page_count {
+        if let Some(page) = state.stmt_manager.fetch_page(query_id, page_index)? {
+            rows.extend(grouping::parse_page(page.get())?);
         }
-        ConnectionRuntime::Connected(RuntimeClient::SQLite { connection }) => {
-            sqlite::schema::get_database_schema(Arc::clone(connection)).await?
+    }
+
+    Ok(CachedResult {
+        columns,
+        column_types,
+        rows,
+    })
+}
+
+/// Joins the cached results of two queries (possibly from different connections) with SQLite SQL over tables
+/// `a` and `b`, registering the output as a new query.
+///
+/// Joining a truncated result would silently give partial output, so that needs `acknowledge_partial`.
+pub async fn join_results(
+    query_id_a: usize,
+    query_id_b: usize,
+    sql: String,
+    acknowledge_partial: bool,
+    state: &AppState,
+) -> Result<JoinedQuery, Error> {
+    for query_id in [query_id_a, query_id_b] {
+        if state.stmt_manager.get_query_status(query_id)? != QueryStatus::Completed {
+            return Err(anyhow::anyhow!("Query {query_id} hasn't completed yet").into());
         }
-        ConnectionRuntime::Disconnected => {
-            return Err(Error::Any(anyhow::anyhow!("Connection not active")))
+        if state.stmt_manager.is_truncated(query_id)? && !acknowledge_partial {
+            return Err(anyhow::anyhow!(
+                "The result of query {query_id} is truncated, so the join would be over partial data"
+            )
+            .into());
         }
-    };
+    }
 
-    let schema = Arc::new(schema);
-    state.schemas.insert(connection_id, schema.clone());
+    let a = cached_result(query_id_a, state)?;
+    let b = cached_result(query_id_b, state)?;
 
-    Ok(schema)
+    let joined =
+        tokio::task::spawn_blocking(move || sqlite::join::join_results(&a, &b, &sql)).await??;
+
+    let columns = RawValue::from_string(serde_json::to_string(&joined.columns)?)?;
+    let query_id = state
+        .stmt_manager
+        .register_derived_result(columns, joined.pages, false)?;
+
+    Ok(JoinedQuery {
+        query_id,
+        row_count: joined.row_count,
+    })
 }
 
-// Script management commands
-pub async fn save_script(
-    name: String,
-    content: String,
-    connection_id: Option<Uuid>,
-    description: Option<String>,
+/// Pairs the rows of two cached results on `key_columns`, registering the rows that differ as a new query
+pub async fn diff_results(
+    query_id_a: usize,
+    query_id_b: usize,
+    key_columns: Vec<String>,
     state: &AppState,
-) -> Result<i64, Error> {
-    let script = SavedQuery {
-        id: 0, // New script
-        name,
-        description,
-        query_text: content,
-        connection_id,
-        tags: None,
-        created_at: 0, // Will be set by storage
-        updated_at: 0, // Will be set by storage
-        favorite: false,
+) -> Result<ResultDiff, Error> {
+    for query_id in [query_id_a, query_id_b] {
+        if state.stmt_manager.get_query_status(query_id)? != QueryStatus::Completed {
+            return Err(anyhow::anyhow!("Query {query_id} hasn't completed yet").into());
+        }
+        if state.stmt_manager.is_truncated(query_id)? {
+            return Err(anyhow::anyhow!(
+                "The result of query {query_id} is truncated, so rows missing from it would show up as changes"
+            )
+            .into());
+        }
+    }
+
+    let a = cached_result(query_id_a, state)?;
+    let b = cached_result(query_id_b, state)?;
+
+    let diffed =
+        tokio::task::spawn_blocking(move || result_diff::diff_rows(a, b, &key_columns)).await??;
+    if diffed.summary.incomparable.is_some() {
+        return Ok(diffed.summary);
+    }
+
+    let columns = RawValue::from_string(serde_json::to_string(&diffed.columns)?)?;
+    let pages = diffed
+        .rows
+        .chunks(DERIVED_PAGE_SIZE)
+        .map(|chunk| Ok(RawValue::from_string(serde_json::to_string(chunk)?)?))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let query_id = state
+        .stmt_manager
+        .register_derived_result(columns, pages, false)?;
+
+    Ok(ResultDiff {
+        query_id: Some(query_id),
+        ..diffed.summary
+    })
+}
+
+async fn read_script_file(path: PathBuf, state: &AppState) -> Result<ScriptFile, Error> {
+    let max_bytes = match state.storage.get_setting("max_script_file_bytes")? {
+        Some(value) => value
+            .parse()
+            .context("Invalid value for max_script_file_bytes")?,
+        None => script_file::DEFAULT_MAX_SCRIPT_FILE_BYTES,
     };
 
-    let script_id = state.storage.save_query(&script)?;
-    Ok(script_id)
+    tokio::task::spawn_blocking(move || script_file::read_script_file(&path, max_bytes)).await?
 }
 
-pub async fn update_script(
-    id: i64,
-    name: String,
-    content: String,
+/// Links a directory of `.sql` files, listing them alongside saved scripts
+pub async fn link_script_directory(
+    path: &str,
     connection_id: Option<Uuid>,
-    description: Option<String>,
     state: &AppState,
-) -> Result<(), Error> {
-    let script = SavedQuery {
-        id,
-        name,
-        description,
-        query_text: content,
-        connection_id,
-        tags: None,
-        created_at: 0, // Will be ignored for updates
-        updated_at: 0, // Will be set by storage
-        favorite: false,
-    };
+) -> Result<IndexedScriptDirectory, Error> {
+    let path = Path::new(path)
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {path}"))?;
+    if !path.is_dir() {
+        return Err(anyhow::anyhow!("{} is not a directory", path.display()).into());
+    }
 
-    state.storage.save_query(&script)?;
+    let directory = state
+        .storage
+        .save_linked_script_directory(&path.to_string_lossy(), connection_id.as_ref())?;
+
+    index_linked_directory(directory, state).await
+}
+
+pub async fn unlink_script_directory(id: i64, state: &AppState) -> Result<(), Error> {
+    state.script_watchers.unwatch(id);
+    state.storage.delete_linked_script_directory(id)?;
     Ok(())
 }
 
-pub async fn get_scripts(
-    connection_id: Option<Uuid>,
+/// Lists every linked directory along with its scripts, re-indexing them from disk
+pub async fn get_linked_script_directories(
     state: &AppState,
-) -> Result<Vec<SavedQuery>, Error> {
-    let scripts = state.storage.get_saved_queries(connection_id.as_ref())?;
-    Ok(scripts)
+) -> Result<Vec<IndexedScriptDirectory>, Error> {
+    let mut indexed = Vec::new();
+    for directory in state.storage.get_linked_script_directories()? {
+        indexed.push(index_linked_directory(directory, state).await?);
+    }
+
+    Ok(indexed)
 }
 
-pub async fn delete_script(id: i64, state: &AppState) -> Result<(), Error> {
-    state.storage.delete_saved_query(id)?;
-    Ok(())
+/// Reads a linked script from disk, e.g. right before running it
+pub async fn read_linked_script(path: &str, state: &AppState) -> Result<ScriptFile, Error> {
+    let directories = state.storage.get_linked_script_directories()?;
+    let path = linked_scripts::resolve_linked_script(Path::new(path), &directories)?;
+
+    read_script_file(path, state).await
+}
+
+const EXTERNAL_EDITOR_KEY: &str = "external_editor";
+
+/// Which editor scripts are opened in, and how conflicting edits are handled
+pub async fn get_external_editor_settings(
+    state: &AppState,
+) -> Result<ExternalEditorSettings, Error> {
+    match state.storage.get_setting(EXTERNAL_EDITOR_KEY)? {
+        Some(settings) => Ok(serde_json::from_str(&settings)?),
+        None => Ok(ExternalEditorSettings::default()),
+    }
 }
 
-pub async fn save_session_state(session_data: &str, state: &AppState) -> Result<(), Error> {
-    state.storage.set_setting("session_state", session_data)?;
+pub async fn set_external_editor_settings(
+    settings: ExternalEditorSettings,
+    state: &AppState,
+) -> Result<(), Error> {
+    state
+        .storage
+        .set_setting(EXTERNAL_EDITOR_KEY, &serde_json::to_string(&settings)?)?;
     Ok(())
 }
 
-pub async fn get_session_state(state: &AppState) -> Result<Option<String>, Error> {
-    let session_data = state.storage.get_setting("session_state")?;
-    Ok(session_data)
+/// Opens a tab's contents in an external editor, reporting changes to the file until the session ends
+pub async fn edit_externally(
+    tab_id: String,
+    title: &str,
+    contents: &str,
+    state: &AppState,
+) -> Result<ExternalEditSession, Error> {
+    let settings = get_external_editor_settings(state).await?;
+    state
+        .external_edits
+        .start(tab_id, title, contents, &settings)
 }
 
-/// Export page to CSV
-pub async fn export_page(
-    query_id: usize,
-    page_index: usize,
+/// Called as the tab is edited in pgpad, so that conflicting external edits can be detected
+pub async fn sync_external_edit(
+    session_id: Uuid,
+    contents: String,
+    state: &AppState,
+) -> Result<(), Error> {
+    state.external_edits.local_change(session_id, contents)
+}
+
+/// Returns the tab's contents after resolving a conflict
+pub async fn resolve_external_edit(
+    session_id: Uuid,
+    resolution: ConflictResolution,
     state: &AppState,
 ) -> Result<String, Error> {
-    let now = Instant::now();
-    let columns = state
-        .stmt_manager
-        .get_columns(query_id)?
-        .ok_or_else(|| anyhow::anyhow!("No columns found yet"))?;
+    state.external_edits.resolve(session_id, resolution)
+}
 
-    let page = state
-        .stmt_manager
-        .fetch_page(query_id, page_index)?
-        .ok_or_else(|| anyhow::anyhow!("No page {page_index} found yet"))?;
+pub async fn end_external_edit(session_id: Uuid, state: &AppState) -> Result<(), Error> {
+    state.external_edits.end(session_id);
+    Ok(())
+}
 
-    let csv_export = database::export::export_to_csv(columns.get(), page.get())?;
+async fn index_linked_directory(
+    directory: LinkedScriptDirectory,
+    state: &AppState,
+) -> Result<IndexedScriptDirectory, Error> {
+    let path = PathBuf::from(&directory.path);
 
-    let elapsed = now.elapsed();
+    if !path.is_dir() {
+        state.script_watchers.unwatch(directory.id);
+        return Ok(IndexedScriptDirectory {
+            directory,
+            missing: true,
+            watched: false,
+            scripts: Vec::new(),
+        });
+    }
 
-    log::info!(
-        "Took {}us to export page {page_index} to CSV",
-        elapsed.as_micros()
-    );
+    let scripts = {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || linked_scripts::index_directory(&path)).await??
+    };
+    let watched = state.script_watchers.watch(directory.id, &path);
 
-    Ok(csv_export)
+    Ok(IndexedScriptDirectory {
+        directory,
+        missing: false,
+        watched,
+        scripts,
+    })
 }
@@ -0,0 +1,490 @@
+//! Collation-aware sorting of cached results, so that text sorts the way people read it ("apple" before "Zebra",
+//! "é" next to "e") rather than in byte order.
+
+use std::cmp::Ordering;
+
+use anyhow::Context;
+use feruca::{Collator, Locale, Tailoring};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{database::column_stats::ColumnKind, utils::numeric_value};
+
+/// How text is compared. The default is the Unicode Collation Algorithm (with CLDR root locale tailoring),
+/// ignoring case.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Collation {
+    /// Compare the raw bytes instead, like a `C` collation. Overrides the other options.
+    pub binary: bool,
+    /// Whether "apple" and "Apple" are different. If they are, lowercase sorts first.
+    pub case_sensitive: bool,
+    /// Compare runs of digits by their value, so that "file10" sorts after "file2"
+    pub numeric: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NullsOrder {
+    /// Like Postgres: last when ascending, first when descending
+    #[default]
+    Default,
+    First,
+    Last,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SortKey {
+    pub column: String,
+    #[serde(default)]
+    pub descending: bool,
+    #[serde(default)]
+    pub nulls: NullsOrder,
+}
+
+/// The result of sorting a query, which is registered in the StatementManager as its own query
+#[derive(Debug, Clone, Serialize)]
+pub struct SortedQuery {
+    pub query_id: usize,
+    pub row_count: usize,
+}
+
+/// Compares text according to a [`Collation`]
+pub struct TextComparator {
+    collation: Collation,
+    collator: Collator,
+}
+
+impl TextComparator {
+    pub fn new(collation: Collation) -> Self {
+        Self {
+            collation,
+            collator: Collator::new(Tailoring::Cldr(Locale::Root), true, false),
+        }
+    }
+
+    pub fn compare(&mut self, a: &str, b: &str) -> Ordering {
+        if self.collation.binary {
+            return a.as_bytes().cmp(b.as_bytes());
+        }
+
+        if self.collation.numeric {
+            let mut a_chunks = Chunks(a);
+            let mut b_chunks = Chunks(b);
+            loop {
+                let ordering = match (a_chunks.next(), b_chunks.next()) {
+                    (None, None) => return Ordering::Equal,
+                    (None, Some(_)) => return Ordering::Less,
+                    (Some(_), None) => return Ordering::Greater,
+                    (Some(Chunk::Digits(a)), Some(Chunk::Digits(b))) => compare_digits(a, b),
+                    (Some(a), Some(b)) => self.compare_text(a.as_str(), b.as_str()),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+
+        self.compare_text(a, b)
+    }
+
+    fn compare_text(&mut self, a: &str, b: &str) -> Ordering {
+        if self.collation.case_sensitive {
+            self.collator.collate(a, b)
+        } else {
+            self.collator.collate(&a.to_lowercase(), &b.to_lowercase())
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Chunk<'a> {
+    Digits(&'a str),
+    Text(&'a str),
+}
+
+impl<'a> Chunk<'a> {
+    fn as_str(&self) -> &'a str {
+        match self {
+            Chunk::Digits(s) | Chunk::Text(s) => s,
+        }
+    }
+}
+
+/// Splits text into runs of ASCII digits and runs of everything else
+struct Chunks<'a>(&'a str);
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Chunk<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.0.chars().next()?;
+        let is_digit = first.is_ascii_digit();
+        let end = self
+            .0
+            .find(|c: char| c.is_ascii_digit() != is_digit)
+            .unwrap_or(self.0.len());
+
+        let (chunk, rest) = self.0.split_at(end);
+        self.0 = rest;
+        Some(if is_digit {
+            Chunk::Digits(chunk)
+        } else {
+            Chunk::Text(chunk)
+        })
+    }
+}
+
+/// Compares runs of digits by value, without parsing them, since they may not fit in any integer type
+fn compare_digits(a: &str, b: &str) -> Ordering {
+    let a_value = a.trim_start_matches('0');
+    let b_value = b.trim_start_matches('0');
+    a_value
+        .len()
+        .cmp(&b_value.len())
+        .then_with(|| a_value.cmp(b_value))
+        // e.g. "01" after "1"
+        .then_with(|| a.len().cmp(&b.len()))
+}
+
+/// Compares two non-null values: numbers by value, everything else as text
+fn compare_values(a: &Value, b: &Value, comparator: &mut TextComparator) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => a
+                .as_f64()
+                .partial_cmp(&b.as_f64())
+                .unwrap_or(Ordering::Equal),
+        },
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::String(a), Value::String(b)) => comparator.compare(a, b),
+        (a, b) => comparator.compare(&text_of(a), &text_of(b)),
+    }
+}
+
+fn text_of(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl NullsOrder {
+    fn nulls_first(self, descending: bool) -> bool {
+        match self {
+            NullsOrder::Default => descending,
+            NullsOrder::First => true,
+            NullsOrder::Last => false,
+        }
+    }
+}
+
+/// Compares two values that may be null, with `compare` for those that aren't
+fn compare_nullable(
+    a: &Value,
+    b: &Value,
+    descending: bool,
+    nulls: NullsOrder,
+    compare: impl FnOnce(&Value, &Value) -> Ordering,
+) -> Ordering {
+    let nulls_first = nulls.nulls_first(descending);
+    match (a.is_null(), b.is_null()) {
+        (true, true) => Ordering::Equal,
+        (true, false) if nulls_first => Ordering::Less,
+        (true, false) => Ordering::Greater,
+        (false, true) if nulls_first => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => {
+            let ordering = compare(a, b);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        }
+    }
+}
+
+/// Sorts rows by the given keys, in order. The sort is stable, so rows that compare equal keep their order.
+pub fn sort_rows(
+    columns: &[String],
+    mut rows: Vec<Vec<Value>>,
+    keys: &[SortKey],
+    collation: Collation,
+) -> anyhow::Result<Vec<Vec<Value>>> {
+    let keys = keys
+        .iter()
+        .map(|key| {
+            let idx = columns
+                .iter()
+                .position(|column| *column == key.column)
+                .with_context(|| format!("Column {} not found", key.column))?;
+            Ok((idx, key))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut comparator = TextComparator::new(collation);
+    rows.sort_by(|a, b| {
+        for (idx, key) in &keys {
+            let ordering =
+                compare_nullable(&a[*idx], &b[*idx], key.descending, key.nulls, |a, b| {
+                    compare_values(a, b, &mut comparator)
+                });
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+
+    Ok(rows)
+}
+
+/// Compares two non-null values of a column of the given kind. Numeric columns compare by value even when their
+/// values are text, and temporal ones by their text, which sorts chronologically.
+fn compare_typed(
+    a: &Value,
+    b: &Value,
+    kind: ColumnKind,
+    comparator: &mut TextComparator,
+) -> Ordering {
+    match (kind, a, b) {
+        (ColumnKind::Numeric, Value::Number(_), Value::Number(_)) => {
+            compare_values(a, b, comparator)
+        }
+        (ColumnKind::Numeric, _, _) => match (numeric_value(a), numeric_value(b)) {
+            (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+            _ => compare_values(a, b, comparator),
+        },
+        (ColumnKind::Temporal, Value::String(a), Value::String(b)) => a.cmp(b),
+        _ => compare_values(a, b, comparator),
+    }
+}
+
+/// Sorts rows in place by a single column, the way values of its kind order, or by the values themselves if its
+/// kind isn't known. The sort is stable.
+pub fn sort_rows_by_column(
+    rows: &mut [Vec<Value>],
+    column: usize,
+    kind: Option<ColumnKind>,
+    descending: bool,
+    nulls: NullsOrder,
+) {
+    let mut comparator = TextComparator::new(Collation::default());
+    rows.sort_by(|a, b| {
+        let (a, b) = (&a[column], &b[column]);
+        compare_nullable(a, b, descending, nulls, |a, b| match kind {
+            Some(kind) => compare_typed(a, b, kind, &mut comparator),
+            None => compare_values(a, b, &mut comparator),
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn sorted(values: &[Value], key: SortKey, collation: Collation) -> Vec<Value> {
+        let rows = values.iter().map(|value| vec![value.clone()]).collect();
+        sort_rows(&["v".into()], rows, &[key], collation)
+            .unwrap()
+            .into_iter()
+            .map(|mut row| row.remove(0))
+            .collect()
+    }
+
+    fn ascending() -> SortKey {
+        SortKey {
+            column: "v".into(),
+            descending: false,
+            nulls: NullsOrder::Default,
+        }
+    }
+
+    #[test]
+    fn ignores_case_by_default() {
+        let values = [
+            json!("Zebra"),
+            json!("apple"),
+            json!("Apple"),
+            json!("banana"),
+        ];
+
+        assert_eq!(
+            sorted(&values, ascending(), Collation::default()),
+            [
+                json!("apple"),
+                json!("Apple"),
+                json!("banana"),
+                json!("Zebra")
+            ]
+        );
+
+        let case_sensitive = Collation {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            sorted(
+                &[json!("Apple"), json!("apple")],
+                ascending(),
+                case_sensitive
+            ),
+            [json!("apple"), json!("Apple")]
+        );
+
+        let binary = Collation {
+            binary: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            sorted(&values, ascending(), binary),
+            [
+                json!("Apple"),
+                json!("Zebra"),
+                json!("apple"),
+                json!("banana")
+            ]
+        );
+    }
+
+    #[test]
+    fn sorts_accents_next_to_their_base_letter() {
+        let values = [
+            json!("zèbre"),
+            json!("éclair"),
+            json!("eclair"),
+            json!("fig"),
+        ];
+
+        assert_eq!(
+            sorted(&values, ascending(), Collation::default()),
+            [
+                json!("eclair"),
+                json!("éclair"),
+                json!("fig"),
+                json!("zèbre")
+            ]
+        );
+    }
+
+    #[test]
+    fn compares_numbers_in_text_by_value() {
+        let values = [
+            json!("file10"),
+            json!("file2"),
+            json!("file1"),
+            json!("file02"),
+        ];
+
+        assert_eq!(
+            sorted(&values, ascending(), Collation::default()),
+            [
+                json!("file02"),
+                json!("file1"),
+                json!("file10"),
+                json!("file2")
+            ]
+        );
+
+        let numeric = Collation {
+            numeric: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            sorted(&values, ascending(), numeric),
+            [
+                json!("file1"),
+                json!("file2"),
+                json!("file02"),
+                json!("file10")
+            ]
+        );
+    }
+
+    #[test]
+    fn places_nulls() {
+        let values = [json!(2), Value::Null, json!(10), json!(1)];
+
+        assert_eq!(
+            sorted(&values, ascending(), Collation::default()),
+            [json!(1), json!(2), json!(10), Value::Null]
+        );
+
+        let descending = SortKey {
+            descending: true,
+            ..ascending()
+        };
+        assert_eq!(
+            sorted(&values, descending, Collation::default()),
+            [Value::Null, json!(10), json!(2), json!(1)]
+        );
+
+        let nulls_first = SortKey {
+            nulls: NullsOrder::First,
+            ..ascending()
+        };
+        assert_eq!(
+            sorted(&values, nulls_first, Collation::default()),
+            [Value::Null, json!(1), json!(2), json!(10)]
+        );
+    }
+
+    #[test]
+    fn sorts_by_the_kind_of_the_column() {
+        // Numbers sent as text, with a tag to check the sort is stable
+        let rows = || {
+            vec![
+                vec![json!("10.5"), json!("a")],
+                vec![Value::Null, json!("b")],
+                vec![json!("9"), json!("c")],
+                vec![json!("10.5"), json!("d")],
+                vec![json!("-2"), json!("e")],
+            ]
+        };
+        let tags = |rows: Vec<Vec<Value>>| -> Vec<Value> {
+            rows.into_iter().map(|mut row| row.remove(1)).collect()
+        };
+
+        let mut numeric = rows();
+        sort_rows_by_column(
+            &mut numeric,
+            0,
+            Some(ColumnKind::Numeric),
+            false,
+            NullsOrder::Default,
+        );
+        assert_eq!(
+            tags(numeric),
+            [json!("e"), json!("c"), json!("a"), json!("d"), json!("b")]
+        );
+
+        let mut descending = rows();
+        sort_rows_by_column(
+            &mut descending,
+            0,
+            Some(ColumnKind::Numeric),
+            true,
+            NullsOrder::Last,
+        );
+        assert_eq!(
+            tags(descending),
+            [json!("a"), json!("d"), json!("c"), json!("e"), json!("b")]
+        );
+
+        // As text, "10.5" comes before "9"
+        let mut text = rows();
+        text.retain(|row| row[1] != json!("e"));
+        sort_rows_by_column(
+            &mut text,
+            0,
+            Some(ColumnKind::Text),
+            false,
+            NullsOrder::First,
+        );
+        assert_eq!(tags(text), [json!("b"), json!("a"), json!("d"), json!("c")]);
+    }
+}
@@ -1,4 +1,9 @@
 pub mod execute;
+pub mod explain;
+pub mod join;
+mod limits;
 pub mod parser;
+pub mod paths;
 mod row_writer;
 pub mod schema;
+pub mod snapshot;
@@ -1,10 +1,16 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use rusqlite::Connection;
+use rusqlite::{params_from_iter, types::Value, Connection, Statement};
+use serde_json::value::RawValue;
 
 use crate::{
     database::{
-        parser::ParsedStatement, sqlite::row_writer::RowWriter, types::ExecSender, QueryExecEvent,
+        params::{self, QueryParams},
+        parser::ParsedStatement,
+        sqlite::{explain, limits::RunLimits, row_writer::RowWriter},
+        trace::{StatementSource, StatementTrace},
+        types::{ExecSender, ResourceLimits, SavepointOutcome, STATEMENT_SAVEPOINT},
+        QueryExecEvent,
     },
     utils::serialize_as_json_array,
     Error,
@@ -16,26 +22,166 @@ pub fn execute_query(
     stmt: ParsedStatement,
     sender: &ExecSender,
 ) -> Result<(), Error> {
-    let start = std::time::Instant::now();
+    execute_query_with_limits(
+        client,
+        stmt,
+        sender,
+        ResourceLimits::default(),
+        &StatementTrace::default(),
+    )
+}
+
+/// Like [`execute_query`], but enforcing the given resource ceilings while the statement runs,
+/// and recording it in the connection's statement trace.
+pub fn execute_query_with_limits(
+    client: &Connection,
+    stmt: ParsedStatement,
+    sender: &ExecSender,
+    limits: ResourceLimits,
+    trace: &StatementTrace,
+) -> Result<(), Error> {
+    run_traced(client, stmt, sender, limits, trace).map(|_| ())
+}
 
-    if stmt.returns_values {
-        execute_query_with_results(client, &stmt.statement, sender, start)?;
+/// Runs the statement and records it in the trace, returning how many rows it returned or affected,
+/// or None if it failed after it started returning rows
+fn run_traced(
+    client: &Connection,
+    stmt: ParsedStatement,
+    sender: &ExecSender,
+    limits: ResourceLimits,
+    trace: &StatementTrace,
+) -> Result<Option<usize>, Error> {
+    let start = std::time::Instant::now();
+    let limits = RunLimits::install(client, limits);
+
+    let rows = if stmt.returns_values {
+        execute_query_with_results(
+            client,
+            &stmt.statement,
+            &stmt.params,
+            sender,
+            &limits,
+            start,
+        )
     } else {
-        execute_modification_query(client, &stmt.statement, sender, start)?;
+        execute_modification_query(
+            client,
+            &stmt.statement,
+            &stmt.params,
+            sender,
+            &limits,
+            start,
+        )
+    };
+
+    trace.record(
+        StatementSource::User,
+        &stmt.statement,
+        start.elapsed(),
+        rows.as_ref().ok().copied().flatten(),
+    );
+
+    rows
+}
+
+/// Like [`execute_query_with_limits`], but in a savepoint if the connection is in a transaction, so that the
+/// statement's changes are undone if it fails, and the rest of the transaction is kept
+pub fn execute_query_in_savepoint(
+    client: &Connection,
+    stmt: ParsedStatement,
+    sender: &ExecSender,
+    limits: ResourceLimits,
+    trace: &StatementTrace,
+) -> Result<(), Error> {
+    // Outside of a transaction, the savepoint would start one
+    if stmt.controls_transaction || client.is_autocommit() {
+        return execute_query_with_limits(client, stmt, sender, limits, trace);
+    }
+    client.execute_batch(&format!("SAVEPOINT {STATEMENT_SAVEPOINT}"))?;
+
+    let rows = run_traced(client, stmt, sender, limits, trace);
+
+    let (settle, outcome) = match rows {
+        Ok(Some(_)) => (
+            format!("RELEASE {STATEMENT_SAVEPOINT}"),
+            SavepointOutcome::Released,
+        ),
+        Ok(None) | Err(_) => (
+            format!("ROLLBACK TO {STATEMENT_SAVEPOINT}; RELEASE {STATEMENT_SAVEPOINT}"),
+            SavepointOutcome::RolledBack,
+        ),
+    };
+    match client.execute_batch(&settle) {
+        Ok(()) => sender.send(QueryExecEvent::Savepoint(outcome))?,
+        // e.g. SQLite rolled back the whole transaction on its own
+        Err(e) => log::error!("Failed to settle the statement's savepoint: {}", e),
+    }
+
+    rows.map(|_| ())
+}
+
+/// Reports the ceilings hit during the run, and then finishes it
+fn finish(
+    sender: &ExecSender,
+    limits: &RunLimits,
+    started_at: Instant,
+    affected_rows: usize,
+    error: Option<String>,
+) -> Result<(), Error> {
+    for ceiling in limits.ceilings_hit() {
+        sender.send(QueryExecEvent::CeilingHit(ceiling))?;
     }
 
+    sender.send(QueryExecEvent::Finished {
+        elapsed_ms: started_at.elapsed().as_millis() as u64,
+        affected_rows,
+        error,
+    })?;
+
     Ok(())
 }
 
+/// The values of a prepared statement's placeholders, by index. SQLite converts them to the types of the
+/// columns they're compared with or stored in, as it would literals.
+fn bind(stmt: &Statement<'_>, values: &QueryParams) -> rusqlite::Result<Vec<Value>> {
+    (1..=stmt.parameter_count())
+        .map(|idx| {
+            let name = match stmt.parameter_name(idx) {
+                Some(name) => name.to_owned(),
+                None => format!("?{idx}"),
+            };
+            let value = params::value(values, &name)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+            Ok(match value {
+                serde_json::Value::Null => Value::Null,
+                serde_json::Value::Bool(value) => Value::Integer(*value as i64),
+                serde_json::Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+                    (Some(value), _) => Value::Integer(value),
+                    (None, Some(value)) => Value::Real(value),
+                    (None, None) => Value::Text(number.to_string()),
+                },
+                serde_json::Value::String(text) => Value::Text(text.clone()),
+                other => Value::Text(other.to_string()),
+            })
+        })
+        .collect()
+}
+
 fn execute_query_with_results(
     client: &Connection,
     query: &str,
+    values: &QueryParams,
     sender: &ExecSender,
+    limits: &RunLimits,
     started_at: Instant,
-) -> Result<(), Error> {
+) -> Result<Option<usize>, Error> {
     log::info!("Starting SQLite query: {}", query);
 
     match client.prepare(query) {
+        Ok(mut stmt) if explain::is_query_plan(&stmt) => {
+            execute_query_plan(&mut stmt, values, sender, limits, started_at)
+        }
         Ok(mut stmt) => {
             let columns = stmt.columns();
             let column_names = columns.iter().map(|c| c.name());
@@ -45,19 +191,25 @@ fn execute_query_with_results(
                 .collect();
             let columns = serialize_as_json_array(column_names)?;
 
-            match stmt.query([]) {
+            match bind(&stmt, values).and_then(|values| stmt.query(params_from_iter(values))) {
                 Ok(mut rows) => {
-                    sender.send(QueryExecEvent::TypesResolved { columns })?;
+                    sender.send(QueryExecEvent::TypesResolved {
+                        columns,
+                        column_types: column_types.clone(),
+                    })?;
 
                     let mut total_rows = 0;
                     // TODO: make this configurable
                     let batch_size = 50;
                     let mut writer = RowWriter::new(column_types);
+                    let mut serialize_time = Duration::ZERO;
 
                     loop {
                         match rows.next() {
                             Ok(Some(row)) => {
+                                let serialize_start = Instant::now();
                                 writer.add_row(row)?;
+                                serialize_time += serialize_start.elapsed();
                                 total_rows += 1;
 
                                 if writer.len() >= batch_size {
@@ -73,15 +225,13 @@ fn execute_query_with_results(
                             }
                             Err(e) => {
                                 log::error!("Error processing SQLite row: {}", e);
-                                let error_msg = format!("Query failed: {}", e);
-
-                                sender.send(QueryExecEvent::Finished {
-                                    elapsed_ms: started_at.elapsed().as_millis() as u64,
-                                    // TODO(vini): this is actually not necessarily true?
-                                    //             Might not matter, though
-                                    affected_rows: 0,
-                                    error: Some(error_msg),
-                                })?;
+                                let error_msg = limits.error_message(&e);
+
+                                // TODO(vini): affected_rows is actually not necessarily true?
+                                //             Might not matter, though
+                                sender.send(QueryExecEvent::Serialized(serialize_time))?;
+                                finish(sender, limits, started_at, 0, Some(error_msg))?;
+                                return Ok(None);
                             }
                         }
                     }
@@ -100,23 +250,15 @@ fn execute_query_with_results(
                         duration
                     );
 
-                    sender.send(QueryExecEvent::Finished {
-                        elapsed_ms: started_at.elapsed().as_millis() as u64,
-                        affected_rows: 0,
-                        error: None,
-                    })?;
-
-                    Ok(())
+                    sender.send(QueryExecEvent::Serialized(serialize_time))?;
+                    finish(sender, limits, started_at, 0, None)?;
+                    Ok(Some(total_rows))
                 }
                 Err(e) => {
                     log::error!("SQLite query execution failed: {:?}", e);
-                    let error_msg = format!("Query failed: {}", e);
+                    let error_msg = limits.error_message(&e);
 
-                    sender.send(QueryExecEvent::Finished {
-                        elapsed_ms: started_at.elapsed().as_millis() as u64,
-                        affected_rows: 0,
-                        error: Some(error_msg.clone()),
-                    })?;
+                    finish(sender, limits, started_at, 0, Some(error_msg.clone()))?;
 
                     // TODO(vini): is this necessary, if we already sent the error to the receiver thread?
                     Err(Error::Any(anyhow::anyhow!(error_msg)))
@@ -125,45 +267,75 @@ fn execute_query_with_results(
         }
         Err(e) => {
             log::error!("SQLite statement preparation failed: {:?}", e);
-            let error_msg = format!("Query failed: {}", e);
+            let error_msg = limits.error_message(&e);
 
-            sender.send(QueryExecEvent::Finished {
-                elapsed_ms: started_at.elapsed().as_millis() as u64,
-                affected_rows: 0,
-                error: Some(error_msg.clone()),
-            })?;
+            finish(sender, limits, started_at, 0, Some(error_msg.clone()))?;
 
             Err(Error::Any(anyhow::anyhow!(error_msg)))
         }
     }
 }
 
+/// Sends the plan of an `EXPLAIN QUERY PLAN` statement as a tree, in a single JSON value, see [`explain`]
+fn execute_query_plan(
+    stmt: &mut Statement<'_>,
+    values: &QueryParams,
+    sender: &ExecSender,
+    limits: &RunLimits,
+    started_at: Instant,
+) -> Result<Option<usize>, Error> {
+    let rows = bind(stmt, values).and_then(|values| {
+        let rows = stmt.query(params_from_iter(values))?;
+        explain::read_plan_rows(rows)
+    });
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            let error_msg = limits.error_message(&e);
+            finish(sender, limits, started_at, 0, Some(error_msg.clone()))?;
+            return Err(Error::Any(anyhow::anyhow!(error_msg)));
+        }
+    };
+    let node_count = rows.len();
+
+    sender.send(QueryExecEvent::TypesResolved {
+        columns: serialize_as_json_array(["QUERY PLAN"].into_iter())?,
+        column_types: vec![Some("json".to_owned())],
+    })?;
+    let page = serde_json::to_string(&[[explain::plan_tree(rows)]])?;
+    sender.send(QueryExecEvent::Page {
+        page_amount: 1,
+        page: RawValue::from_string(page)?,
+    })?;
+    finish(sender, limits, started_at, 0, None)?;
+
+    Ok(Some(node_count))
+}
+
 fn execute_modification_query(
     client: &Connection,
     query: &str,
+    values: &QueryParams,
     sender: &ExecSender,
+    limits: &RunLimits,
     started_at: Instant,
-) -> Result<(), Error> {
+) -> Result<Option<usize>, Error> {
     log::info!("Executing modification query: {}", query);
 
-    match client.execute(query, []) {
+    let result = client.prepare(query).and_then(|mut stmt| {
+        let values = bind(&stmt, values)?;
+        stmt.execute(params_from_iter(values))
+    });
+    match result {
         Ok(rows_affected) => {
-            sender.send(QueryExecEvent::Finished {
-                elapsed_ms: started_at.elapsed().as_millis() as u64,
-                affected_rows: rows_affected,
-                error: None,
-            })?;
-            Ok(())
+            finish(sender, limits, started_at, rows_affected, None)?;
+            Ok(Some(rows_affected))
         }
         Err(e) => {
             log::error!("Modification query failed: {:?}", e);
-            let error_msg = format!("Query failed: {}", e);
+            let error_msg = limits.error_message(&e);
 
-            sender.send(QueryExecEvent::Finished {
-                elapsed_ms: started_at.elapsed().as_millis() as u64,
-                affected_rows: 0,
-                error: Some(error_msg.clone()),
-            })?;
+            finish(sender, limits, started_at, 0, Some(error_msg.clone()))?;
 
             Err(Error::Any(anyhow::anyhow!(error_msg)))
         }
@@ -178,8 +350,13 @@ mod tests {
     use rusqlite::Connection;
     use std::sync::Mutex;
 
-    use super::execute_query;
-    use crate::database::{sqlite::parser::parse_statements, types::channel, QueryExecEvent};
+    use super::{execute_query, execute_query_with_limits};
+    use crate::database::{
+        sqlite::parser::parse_statements,
+        trace::StatementTrace,
+        types::{channel, ResourceCeiling, ResourceLimits},
+        QueryExecEvent,
+    };
 
     async fn run_query(
         conn: Arc<Mutex<Connection>>,
@@ -273,6 +450,9 @@ mod tests {
             other => panic!("Expected Page event, got {:?}", other),
         }
 
+        let event = recv.recv().await.unwrap();
+        assert!(matches!(event, QueryExecEvent::Serialized(_)));
+
         let event = recv.recv().await.unwrap();
         dbg!(&event);
         assert!(matches!(event, QueryExecEvent::Finished { .. }));
@@ -310,7 +490,7 @@ mod tests {
         let mut events = run_query(conn.clone(), query).await?.into_iter();
         let types_resolved = events.next().unwrap();
         match types_resolved {
-            QueryExecEvent::TypesResolved { columns } => {
+            QueryExecEvent::TypesResolved { columns, .. } => {
                 assert_eq!(
                     serde_json::to_string(&columns).unwrap(),
                     r#"["id","name","age"]"#
@@ -331,6 +511,8 @@ mod tests {
             other => panic!("Expected Page event, got {:?}", other),
         }
 
+        assert!(matches!(events.next(), Some(QueryExecEvent::Serialized(_))));
+
         let finished = events.next().unwrap();
         match finished {
             QueryExecEvent::Finished {
@@ -358,7 +540,7 @@ mod tests {
         let mut events = run_query(conn.clone(), query).await?.into_iter();
         let types_resolved = events.next().unwrap();
         match types_resolved {
-            QueryExecEvent::TypesResolved { columns } => {
+            QueryExecEvent::TypesResolved { columns, .. } => {
                 assert_eq!(serde_json::to_string(&columns).unwrap(), r#"["x"]"#);
             }
             other => panic!("Expected TypesResolved event, got {:?}", other),
@@ -409,6 +591,8 @@ mod tests {
             other => panic!("Expected Page event, got {:?}", other),
         }
 
+        assert!(matches!(events.next(), Some(QueryExecEvent::Serialized(_))));
+
         let finished = events.next().unwrap();
         match finished {
             QueryExecEvent::Finished {
@@ -423,4 +607,206 @@ mod tests {
         }
         Ok(())
     }
+
+    #[tokio::test]
+    async fn interrupts_statements_over_budget() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        // Never ends on its own
+        let query = "WITH RECURSIVE t(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM t) SELECT count(*) FROM t";
+        let stmt = parse_statements(query).unwrap().pop().unwrap();
+        let limits = ResourceLimits {
+            soft_heap_limit_bytes: None,
+            statement_cpu_budget_ms: Some(50),
+            query_timeout_ms: None,
+            fetch_ahead_pages: None,
+        };
+
+        let (sender, mut recv) = channel();
+
+        let conn = tokio::task::spawn_blocking(move || {
+            execute_query_with_limits(&conn, stmt, &sender, limits, &StatementTrace::default())
+                .unwrap();
+            conn
+        });
+
+        let mut events = Vec::new();
+        while let Some(event) = recv.recv().await {
+            events.push(event);
+        }
+
+        assert!(matches!(
+            events[..],
+            [
+                QueryExecEvent::TypesResolved { .. },
+                QueryExecEvent::Serialized(_),
+                QueryExecEvent::CeilingHit(ResourceCeiling::StatementCpuBudget),
+                QueryExecEvent::Finished { .. }
+            ]
+        ));
+        match events.pop().unwrap() {
+            QueryExecEvent::Finished { error, .. } => {
+                let error = error.unwrap();
+                assert!(error.starts_with("Resource budget exceeded"), "{error}");
+            }
+            other => panic!("Expected Finished event, got {:?}", other),
+        }
+
+        // The budget only applied to that run
+        let conn = Arc::new(Mutex::new(conn.await.unwrap()));
+        let events = run_query(conn, "SELECT 1").await.unwrap();
+        assert!(matches!(
+            events.last(),
+            Some(QueryExecEvent::Finished { error: None, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_timeouts() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        let query = "WITH RECURSIVE t(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM t) SELECT count(*) FROM t";
+        let stmt = parse_statements(query).unwrap().pop().unwrap();
+        let limits = ResourceLimits {
+            query_timeout_ms: Some(50),
+            ..Default::default()
+        };
+
+        let (sender, mut recv) = channel();
+        tokio::task::spawn_blocking(move || {
+            execute_query_with_limits(&conn, stmt, &sender, limits, &StatementTrace::default())
+                .unwrap();
+        });
+
+        let mut events = Vec::new();
+        while let Some(event) = recv.recv().await {
+            events.push(event);
+        }
+
+        // Not a ceiling, just a statement that took too long
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, QueryExecEvent::CeilingHit(_))));
+        match events.pop().unwrap() {
+            QueryExecEvent::Finished { error, .. } => {
+                let error = error.unwrap();
+                assert_eq!(
+                    error,
+                    "Query timed out: the statement ran for longer than the 50ms query timeout"
+                );
+            }
+            other => panic!("Expected Finished event, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn binds_parameters() {
+        async fn run(
+            conn: Arc<Mutex<Connection>>,
+            query: &str,
+            params: serde_json::Value,
+        ) -> Vec<QueryExecEvent> {
+            let mut stmt = parse_statements(query).unwrap().pop().unwrap();
+            stmt.params = serde_json::from_value(params).unwrap();
+
+            let (sender, mut recv) = channel();
+            tokio::task::spawn_blocking(move || {
+                let conn = conn.lock().unwrap();
+                let _ = execute_query(&conn, stmt, &sender);
+            });
+
+            let mut events = Vec::new();
+            while let Some(event) = recv.recv().await {
+                events.push(event);
+            }
+            events
+        }
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE events (id INTEGER, at DATE, score REAL, done BOOLEAN, meta TEXT)",
+        )
+        .unwrap();
+        let conn = Arc::new(Mutex::new(conn));
+
+        // Named placeholders take an index too, so `?5` is the fifth
+        let events = run(
+            conn.clone(),
+            "INSERT INTO events VALUES (?, :at, @score, $done, ?5)",
+            serde_json::json!({
+                "?1": "7",
+                ":at": "2024-02-29",
+                "@score": 2.5,
+                "$done": true,
+                "?5": { "tags": ["a"] },
+            }),
+        )
+        .await;
+        assert!(matches!(
+            events.as_slice(),
+            [QueryExecEvent::Finished {
+                affected_rows: 1,
+                error: None,
+                ..
+            }]
+        ));
+
+        let events = run(
+            conn.clone(),
+            "SELECT id, at, score, done, meta, ? IS NULL FROM events WHERE id = ?2",
+            serde_json::json!({ "?1": null, "?2": 7 }),
+        )
+        .await;
+        let page = events.iter().find_map(|event| match event {
+            QueryExecEvent::Page { page, .. } => Some(page.get()),
+            _ => None,
+        });
+        // The text given for the INTEGER column was stored as an integer
+        assert_eq!(
+            page,
+            Some(r#"[[7,"2024-02-29",2.5,true,{"tags":["a"]},1]]"#)
+        );
+
+        let events = run(conn, "SELECT :missing", serde_json::json!({})).await;
+        match events.last() {
+            Some(QueryExecEvent::Finished { error, .. }) => assert_eq!(
+                error.as_deref(),
+                Some("Query failed: No value given for parameter :missing")
+            ),
+            other => panic!("Expected Finished event, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_query_plans_as_trees() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+            .unwrap();
+        let conn = Arc::new(Mutex::new(conn));
+
+        let events = run_query(
+            conn.clone(),
+            "EXPLAIN QUERY PLAN SELECT * FROM users WHERE name IN (SELECT name FROM users)",
+        )
+        .await
+        .unwrap();
+        let Some(QueryExecEvent::TypesResolved { columns, .. }) = events.first() else {
+            panic!("Expected TypesResolved event, got {:?}", events.first());
+        };
+        assert_eq!(columns.get(), r#"["QUERY PLAN"]"#);
+        let page = events.iter().find_map(|event| match event {
+            QueryExecEvent::Page { page, .. } => Some(page.get()),
+            _ => None,
+        });
+        let page: serde_json::Value = serde_json::from_str(page.unwrap()).unwrap();
+        let nodes = page[0][0].as_array().unwrap();
+        assert!(nodes.iter().any(|node| node["children"][0]["detail"]
+            .as_str()
+            .is_some_and(|detail| detail.starts_with("SCAN users"))));
+
+        // The bytecode listing stays a table
+        let events = run_query(conn, "EXPLAIN SELECT 1").await.unwrap();
+        let Some(QueryExecEvent::TypesResolved { columns, .. }) = events.first() else {
+            panic!("Expected TypesResolved event, got {:?}", events.first());
+        };
+        assert!(columns.get().starts_with(r#"["addr","opcode""#));
+    }
 }
@@ -0,0 +1,161 @@
+//! `EXPLAIN QUERY PLAN` lists the plan's nodes one per row, each with its parent's id. They're put back together
+//! into a tree here. Plain `EXPLAIN`, the bytecode listing, is a table and stays one.
+
+use rusqlite::{Connection, Rows, Statement};
+use serde::Serialize;
+
+use crate::{
+    database::parser::{ParsedStatement, StatementKind},
+    Error,
+};
+
+/// `sqlite3_stmt_isexplain` of `EXPLAIN QUERY PLAN` statements
+const QUERY_PLAN: i32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryPlanNode {
+    pub id: i64,
+    /// e.g. "SCAN users" or "SEARCH orders USING INDEX orders_user_id (user_id=?)"
+    pub detail: String,
+    pub children: Vec<QueryPlanNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryPlan {
+    pub statement: String,
+    /// The top-level nodes, e.g. the main query and a subquery that's evaluated first
+    pub nodes: Vec<QueryPlanNode>,
+}
+
+pub fn is_query_plan(stmt: &Statement<'_>) -> bool {
+    stmt.is_explain() == QUERY_PLAN
+}
+
+/// Reads the id, parent and detail of the nodes of an `EXPLAIN QUERY PLAN` statement
+pub fn read_plan_rows(mut rows: Rows<'_>) -> rusqlite::Result<Vec<(i64, i64, String)>> {
+    let mut nodes = Vec::new();
+    while let Some(row) = rows.next()? {
+        nodes.push((
+            row.get("id")?,
+            row.get("parent")?,
+            row.get::<_, Option<String>>("detail")?.unwrap_or_default(),
+        ));
+    }
+    Ok(nodes)
+}
+
+/// Nests each node under its parent. A node whose parent isn't in the plan is a top-level one, like those whose
+/// parent is 0. Nodes keep the order SQLite gave them in.
+pub fn plan_tree(rows: Vec<(i64, i64, String)>) -> Vec<QueryPlanNode> {
+    fn children_of(parent: i64, rows: &[(i64, i64, String)]) -> Vec<QueryPlanNode> {
+        rows.iter()
+            .filter(|(id, row_parent, _)| *row_parent == parent && *id != parent)
+            .map(|(id, _, detail)| QueryPlanNode {
+                id: *id,
+                detail: detail.clone(),
+                children: children_of(*id, rows),
+            })
+            .collect()
+    }
+
+    rows.iter()
+        .filter(|(_, parent, _)| !rows.iter().any(|(id, _, _)| id == parent))
+        .map(|(id, _, detail)| QueryPlanNode {
+            id: *id,
+            detail: detail.clone(),
+            children: children_of(*id, &rows),
+        })
+        .collect()
+}
+
+/// Explains a statement with `EXPLAIN QUERY PLAN`, which doesn't run it
+pub fn explain_statement(
+    conn: &Connection,
+    query: &str,
+    statement: &ParsedStatement,
+) -> Result<QueryPlan, Error> {
+    if !matches!(statement.kind, StatementKind::Select | StatementKind::Dml) {
+        return Err(anyhow::anyhow!(
+            "Only queries and INSERT, UPDATE or DELETE statements can be explained"
+        )
+        .into());
+    }
+
+    let text = &query[statement.span.clone()];
+    let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {text}"))?;
+    // The plan doesn't depend on the values of placeholders, which are left null
+    let rows = read_plan_rows(stmt.raw_query())?;
+
+    Ok(QueryPlan {
+        statement: text.to_owned(),
+        nodes: plan_tree(rows),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::sqlite::parser::parse_statements;
+
+    #[test]
+    fn nests_nodes_under_their_parents() {
+        let rows = vec![
+            (2, 0, "CO-ROUTINE recent".to_owned()),
+            (5, 2, "SCAN orders".to_owned()),
+            (12, 0, "SCAN recent".to_owned()),
+            (
+                15,
+                0,
+                "SEARCH users USING INTEGER PRIMARY KEY (rowid=?)".to_owned(),
+            ),
+            (20, 15, "CORRELATED SCALAR SUBQUERY 1".to_owned()),
+            (24, 20, "SCAN items".to_owned()),
+            // Orphans are kept, at the top
+            (30, 99, "USE TEMP B-TREE FOR ORDER BY".to_owned()),
+        ];
+
+        let tree = plan_tree(rows);
+        let outline: Vec<_> = tree
+            .iter()
+            .map(|node| {
+                let children: Vec<_> = node.children.iter().map(|c| c.id).collect();
+                (node.id, children)
+            })
+            .collect();
+        assert_eq!(
+            outline,
+            [(2, vec![5]), (12, vec![]), (15, vec![20]), (30, vec![])]
+        );
+        assert_eq!(tree[2].children[0].children[0].detail, "SCAN items");
+    }
+
+    #[test]
+    fn explains_statements() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE orders (id INTEGER PRIMARY KEY, user_id INTEGER);",
+        )
+        .unwrap();
+
+        let query = "SELECT name FROM users WHERE id IN (SELECT user_id FROM orders)";
+        let statements = parse_statements(query).unwrap();
+        let plan = explain_statement(&conn, query, &statements[0]).unwrap();
+        assert_eq!(plan.statement, query);
+
+        let details: Vec<_> = plan.nodes.iter().map(|n| n.detail.as_str()).collect();
+        assert!(
+            details.iter().any(|d| d.starts_with("SEARCH users")),
+            "{details:?}"
+        );
+        let subquery = plan
+            .nodes
+            .iter()
+            .find(|node| node.detail.contains("LIST SUBQUERY"))
+            .unwrap();
+        assert_eq!(subquery.children[0].detail, "SCAN orders");
+
+        let statements = parse_statements("DROP TABLE users").unwrap();
+        assert!(explain_statement(&conn, "DROP TABLE users", &statements[0]).is_err());
+    }
+}
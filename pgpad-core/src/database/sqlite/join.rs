@@ -0,0 +1,432 @@
+//! Joining cached results, possibly from different connections, by loading them into an in-memory SQLite
+//! database as tables `a` and `b` and running the user's SQL over them.
+
+use anyhow::{ensure, Context};
+use rusqlite::{types::Value as SqlValue, Connection};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    database::{sqlite::row_writer::RowWriter, types::Page},
+    utils::quote_identifier,
+};
+
+/// Limit on the cells loaded from both results, and on the cells of the joined result
+pub const MAX_JOIN_CELLS: usize = 2_000_000;
+
+/// Same as the amount of rows our row writers put in a page
+const JOINED_PAGE_SIZE: usize = 50;
+
+/// A cached result to be loaded as a table
+pub struct CachedResult {
+    pub columns: Vec<String>,
+    /// The database's name for the type of each column, in the same order as `columns`. Empty if unknown.
+    pub column_types: Vec<Option<String>>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl CachedResult {
+    fn cell_count(&self) -> usize {
+        self.rows.len() * self.columns.len()
+    }
+}
+
+/// The result of a join, which is registered in the StatementManager as its own query
+#[derive(Debug, Clone, Serialize)]
+pub struct JoinedQuery {
+    pub query_id: usize,
+    pub row_count: usize,
+}
+
+pub struct JoinedRows {
+    pub columns: Vec<String>,
+    pub pages: Vec<Page>,
+    pub row_count: usize,
+}
+
+/// How a column is loaded, from the type the database reported for it, or inferred from its non-NULL values when
+/// that's unknown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+    Boolean,
+    Integer,
+    Real,
+    /// Dates and timestamps, as ISO-8601 text so that SQLite's date and time functions understand them.
+    /// Time zones are dropped, since our row writers send TIMESTAMPTZ values in UTC.
+    Timestamp,
+    /// NUMERIC values we receive as text. Kept as text so that no precision is lost: use `CAST(x AS REAL)` to
+    /// do arithmetic on them.
+    Decimal,
+    Text,
+    /// Objects and arrays, as JSON text
+    Json,
+    /// Values of different types, which are loaded as-is
+    Any,
+}
+
+impl ColumnType {
+    fn declared(self) -> &'static str {
+        match self {
+            Self::Boolean => "BOOLEAN",
+            Self::Integer => "INTEGER",
+            Self::Real => "REAL",
+            Self::Timestamp | Self::Decimal | Self::Text | Self::Json => "TEXT",
+            // No declared type means BLOB affinity, so SQLite doesn't convert values
+            Self::Any => "",
+        }
+    }
+
+    /// Maps the type names our drivers report, e.g. `int8` on Postgres, `datetime` on MySQL or `VARCHAR(20)` on
+    /// SQLite. None for types that don't map to one in particular.
+    fn of_column_type(column_type: &str) -> Option<Self> {
+        let column_type = column_type.to_ascii_lowercase();
+        // Postgres names array types after their elements, e.g. `_int4`. Their values come as JSON arrays.
+        if column_type.starts_with('_') {
+            return Some(Self::Json);
+        }
+        // e.g. `varchar(20)` or `bigint unsigned`
+        let base = column_type
+            .split(['(', ' '])
+            .next()
+            .unwrap_or_default()
+            .trim();
+
+        let ty = match base {
+            "bool" | "boolean" => Self::Boolean,
+            "int2" | "int4" | "int8" | "smallint" | "integer" | "int" | "bigint" | "tinyint"
+            | "mediumint" | "year" | "oid" => Self::Integer,
+            "float4" | "float8" | "real" | "float" | "double" => Self::Real,
+            "numeric" | "decimal" => Self::Decimal,
+            "date" | "timestamp" | "timestamptz" | "datetime" => Self::Timestamp,
+            "json" | "jsonb" => Self::Json,
+            "text" | "varchar" | "char" | "bpchar" | "character" | "name" | "citext" | "uuid"
+            | "enum" | "set" | "time" | "timetz" | "interval" | "clob" => Self::Text,
+            _ => return None,
+        };
+        Some(ty)
+    }
+
+    fn of_value(value: &Value) -> Option<Self> {
+        let ty = match value {
+            Value::Null => return None,
+            Value::Bool(_) => Self::Boolean,
+            Value::Number(n) if n.is_i64() => Self::Integer,
+            Value::Number(_) => Self::Real,
+            Value::String(s) if parse_timestamp(s).is_some() => Self::Timestamp,
+            Value::String(s) if is_decimal(s) => Self::Decimal,
+            Value::String(_) => Self::Text,
+            Value::Array(_) | Value::Object(_) => Self::Json,
+        };
+        Some(ty)
+    }
+
+    fn infer(rows: &[Vec<Value>], column: usize) -> Self {
+        let mut inferred = None;
+
+        for ty in rows
+            .iter()
+            .filter_map(|row| row.get(column).and_then(Self::of_value))
+        {
+            inferred = Some(match (inferred, ty) {
+                (None, ty) => ty,
+                (Some(current), ty) if current == ty => current,
+                (Some(Self::Integer), Self::Real) | (Some(Self::Real), Self::Integer) => Self::Real,
+                // e.g. `1.50` and `abc`
+                (Some(Self::Decimal | Self::Timestamp | Self::Text), Self::Text)
+                | (Some(Self::Text), Self::Decimal | Self::Timestamp) => Self::Text,
+                _ => return Self::Any,
+            });
+        }
+
+        inferred.unwrap_or(Self::Text)
+    }
+
+    fn to_sql(self, value: &Value) -> SqlValue {
+        match value {
+            Value::Null => SqlValue::Null,
+            Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => SqlValue::Integer(i),
+                None => SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) if self == Self::Timestamp => {
+                SqlValue::Text(parse_timestamp(s).unwrap_or_else(|| s.clone()))
+            }
+            Value::String(s) => SqlValue::Text(s.clone()),
+            Value::Array(_) | Value::Object(_) => SqlValue::Text(value.to_string()),
+        }
+    }
+}
+
+/// Normalizes the dates and timestamps our row writers produce into a format SQLite understands
+fn parse_timestamp(s: &str) -> Option<String> {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    let s = s.strip_suffix(" UTC").unwrap_or(s);
+
+    if let Ok(timestamp) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f") {
+        return Some(timestamp.format("%Y-%m-%d %H:%M:%S%.f").to_string());
+    }
+
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+/// Whether `s` is a number sent as text, e.g. `-12.50`
+fn is_decimal(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+
+    !int.is_empty()
+        && int.bytes().all(|b| b.is_ascii_digit())
+        && frac.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// The declared type of each column of `result`, or the one inferred from its values if that's unknown
+fn column_types(result: &CachedResult) -> Vec<ColumnType> {
+    (0..result.columns.len())
+        .map(|column| {
+            result
+                .column_types
+                .get(column)
+                .and_then(Option::as_deref)
+                .and_then(ColumnType::of_column_type)
+                .unwrap_or_else(|| ColumnType::infer(&result.rows, column))
+        })
+        .collect()
+}
+
+fn load_table(conn: &Connection, table: &str, result: &CachedResult) -> anyhow::Result<()> {
+    let types = column_types(result);
+
+    let definitions = result
+        .columns
+        .iter()
+        .zip(&types)
+        .map(|(column, ty)| format!("{} {}", quote_identifier(column), ty.declared()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    conn.execute(&format!("CREATE TABLE {table} ({definitions})"), [])
+        .with_context(|| format!("Failed to create table `{table}`"))?;
+
+    let placeholders = vec!["?"; result.columns.len()].join(", ");
+    let mut insert = conn.prepare(&format!("INSERT INTO {table} VALUES ({placeholders})"))?;
+
+    for row in &result.rows {
+        ensure!(
+            row.len() == result.columns.len(),
+            "Expected {} values per row in `{table}`, got {}",
+            result.columns.len(),
+            row.len()
+        );
+        let values = row.iter().zip(&types).map(|(value, ty)| ty.to_sql(value));
+        insert.execute(rusqlite::params_from_iter(values))?;
+    }
+
+    Ok(())
+}
+
+/// Loads both results as tables `a` and `b`, and runs `sql` (a single SELECT, in SQLite's dialect) over them
+pub fn join_results(a: &CachedResult, b: &CachedResult, sql: &str) -> anyhow::Result<JoinedRows> {
+    let input_cells = a.cell_count() + b.cell_count();
+    ensure!(
+        input_cells <= MAX_JOIN_CELLS,
+        "The results have {input_cells} cells between them, more than the {MAX_JOIN_CELLS} that can be joined"
+    );
+
+    let mut conn = Connection::open_in_memory()?;
+    let tx = conn.transaction()?;
+    load_table(&tx, "a", a)?;
+    load_table(&tx, "b", b)?;
+    tx.commit()?;
+
+    let mut stmt = conn.prepare(sql)?;
+    ensure!(
+        stmt.column_count() > 0,
+        "Expected a query returning rows, e.g. `SELECT * FROM a JOIN b USING (id)`"
+    );
+
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let column_decltypes = stmt
+        .columns()
+        .iter()
+        .map(|column| column.decl_type().map(String::from))
+        .collect();
+
+    let mut writer = RowWriter::new(column_decltypes);
+    let mut pages = Vec::new();
+    let mut row_count = 0;
+
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        writer.add_row(row)?;
+        row_count += 1;
+
+        ensure!(
+            row_count * columns.len() <= MAX_JOIN_CELLS,
+            "The joined result has more than {MAX_JOIN_CELLS} cells"
+        );
+
+        if writer.len() >= JOINED_PAGE_SIZE {
+            pages.push(writer.finish());
+        }
+    }
+    if !writer.is_empty() {
+        pages.push(writer.finish());
+    }
+
+    Ok(JoinedRows {
+        columns,
+        pages,
+        row_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn result(columns: &[&str], rows: Value) -> CachedResult {
+        CachedResult {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            column_types: Vec::new(),
+            rows: serde_json::from_value(rows).unwrap(),
+        }
+    }
+
+    fn rows(joined: &JoinedRows) -> Vec<Value> {
+        joined
+            .pages
+            .iter()
+            .flat_map(|page| serde_json::from_str::<Vec<Value>>(page.get()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn joins_results() {
+        let users = result(
+            &["id", "name", "active"],
+            json!([[1, "alice", true], [2, "bob", false], [3, "carol", null]]),
+        );
+        let orders = result(
+            &["user_id", "total", "placed_at"],
+            json!([
+                [1, "10.50", "2024-01-31 13:45:00 UTC"],
+                [1, "2.25", "2024-02-01 09:00:00.5 UTC"],
+                [2, "7", "2024-02-02 00:00:00 UTC"]
+            ]),
+        );
+
+        let joined = join_results(
+            &users,
+            &orders,
+            "SELECT name, active, total, date(placed_at) AS day
+             FROM a JOIN b ON b.user_id = a.id
+             ORDER BY placed_at",
+        )
+        .unwrap();
+
+        assert_eq!(joined.columns, ["name", "active", "total", "day"]);
+        assert_eq!(joined.row_count, 3);
+        assert_eq!(
+            rows(&joined),
+            [
+                json!(["alice", true, "10.50", "2024-01-31"]),
+                json!(["alice", true, "2.25", "2024-02-01"]),
+                json!(["bob", false, "7", "2024-02-02"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn infers_column_types() {
+        let rows: Vec<Vec<Value>> = serde_json::from_value(json!([
+            [1, 1.5, "1.50", "2024-01-31", {"k": 1}, 1, null],
+            [2, 2, "-3", "2024-01-31 13:45:00", [1], "x", null]
+        ]))
+        .unwrap();
+
+        let types: Vec<_> = (0..7).map(|i| ColumnType::infer(&rows, i)).collect();
+        assert_eq!(
+            types,
+            [
+                ColumnType::Integer,
+                ColumnType::Real,
+                ColumnType::Decimal,
+                ColumnType::Timestamp,
+                ColumnType::Json,
+                ColumnType::Any,
+                ColumnType::Text,
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_declared_column_types() {
+        let types: Vec<_> = [
+            "int8",
+            "VARCHAR(20)",
+            "double precision",
+            "_int4",
+            "datetime",
+            "bigint unsigned",
+            "geometry",
+        ]
+        .into_iter()
+        .map(ColumnType::of_column_type)
+        .collect();
+        assert_eq!(
+            types,
+            [
+                Some(ColumnType::Integer),
+                Some(ColumnType::Text),
+                Some(ColumnType::Real),
+                Some(ColumnType::Json),
+                Some(ColumnType::Timestamp),
+                Some(ColumnType::Integer),
+                None,
+            ]
+        );
+
+        // MySQL sends booleans as numbers, which only their type tells apart
+        let users = CachedResult {
+            column_types: vec![
+                Some("int".into()),
+                Some("boolean".into()),
+                Some("varchar".into()),
+            ],
+            ..result(
+                &["id", "active", "zip"],
+                json!([[1, 1, "02134"], [2, 0, "10001"]]),
+            )
+        };
+        let orders = result(&["user_id"], json!([[1], [2]]));
+
+        let joined = join_results(
+            &users,
+            &orders,
+            "SELECT active, zip, typeof(zip) FROM a JOIN b ON b.user_id = a.id ORDER BY id",
+        )
+        .unwrap();
+        assert_eq!(
+            rows(&joined),
+            [
+                json!([true, "02134", "text"]),
+                json!([false, "10001", "text"])
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_statements_without_rows() {
+        let a = result(&["id"], json!([[1]]));
+        let b = result(&["id"], json!([[1]]));
+
+        let err = join_results(&a, &b, "DELETE FROM a").err().unwrap();
+        assert!(err.to_string().contains("Expected a query"), "{err}");
+        assert!(join_results(&a, &b, "SELECT * FROM missing").is_err());
+    }
+}
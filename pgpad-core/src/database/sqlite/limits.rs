@@ -0,0 +1,122 @@
+//! Per-run resource ceilings for SQLite, see [`ResourceLimits`]
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rusqlite::{ffi, Connection};
+
+use crate::database::types::{ResourceCeiling, ResourceLimits};
+
+/// Number of virtual machine instructions between calls to the progress handler
+const PROGRESS_HANDLER_OPS: i32 = 10_000;
+
+/// Applies `ResourceLimits` to a connection for the duration of a run, undoing them when dropped
+pub struct RunLimits<'a> {
+    conn: &'a Connection,
+    limits: ResourceLimits,
+    budget_exceeded: Arc<AtomicBool>,
+    timed_out: Arc<AtomicBool>,
+    /// The soft heap limit before this run, if we changed it
+    previous_soft_heap_limit: Option<i64>,
+}
+
+impl<'a> RunLimits<'a> {
+    pub fn install(conn: &'a Connection, limits: ResourceLimits) -> Self {
+        // Note that the soft heap limit is process-wide, so it applies to every SQLite connection while this run lasts
+        let previous_soft_heap_limit = limits.soft_heap_limit_bytes.map(|bytes| {
+            // SAFETY: both calls only read or update SQLite's global memory accounting
+            unsafe {
+                ffi::sqlite3_memory_highwater(1);
+                ffi::sqlite3_soft_heap_limit64(bytes)
+            }
+        });
+
+        let budget_exceeded = Arc::new(AtomicBool::new(false));
+        let timed_out = Arc::new(AtomicBool::new(false));
+        if limits.statement_cpu_budget_ms.is_some() || limits.query_timeout_ms.is_some() {
+            let deadline = |ms: u64| Instant::now() + Duration::from_millis(ms);
+            let budget_deadline = limits.statement_cpu_budget_ms.map(deadline);
+            let timeout_deadline = limits.query_timeout_ms.map(deadline);
+            let (budget_exceeded, timed_out) = (budget_exceeded.clone(), timed_out.clone());
+
+            // Returning true interrupts the statement, failing it with SQLITE_INTERRUPT
+            conn.progress_handler(
+                PROGRESS_HANDLER_OPS,
+                Some(move || {
+                    let now = Instant::now();
+                    if budget_deadline.is_some_and(|deadline| now >= deadline) {
+                        budget_exceeded.store(true, Ordering::Relaxed);
+                        true
+                    } else if timeout_deadline.is_some_and(|deadline| now >= deadline) {
+                        timed_out.store(true, Ordering::Relaxed);
+                        true
+                    } else {
+                        false
+                    }
+                }),
+            );
+        }
+
+        Self {
+            conn,
+            limits,
+            budget_exceeded,
+            timed_out,
+            previous_soft_heap_limit,
+        }
+    }
+
+    /// The message to report for a failed statement.
+    ///
+    /// Running out of budget or time is reported apart from other errors, so it isn't mistaken for the user
+    /// cancelling the query.
+    pub fn error_message(&self, err: &rusqlite::Error) -> String {
+        match (self.limits.statement_cpu_budget_ms, self.limits.query_timeout_ms) {
+            (Some(budget_ms), _) if self.budget_exceeded.load(Ordering::Relaxed) => format!(
+                "Resource budget exceeded: the statement ran for longer than its {budget_ms}ms budget"
+            ),
+            (_, Some(timeout_ms)) if self.timed_out.load(Ordering::Relaxed) => format!(
+                "Query timed out: the statement ran for longer than the {timeout_ms}ms query timeout"
+            ),
+            _ => format!("Query failed: {}", err),
+        }
+    }
+
+    pub fn ceilings_hit(&self) -> Vec<ResourceCeiling> {
+        let mut ceilings = Vec::new();
+
+        if self.budget_exceeded.load(Ordering::Relaxed) {
+            ceilings.push(ResourceCeiling::StatementCpuBudget);
+        }
+
+        if let Some(limit) = self.limits.soft_heap_limit_bytes.filter(|&limit| limit > 0) {
+            // SAFETY: only reads SQLite's global memory accounting
+            let highwater = unsafe { ffi::sqlite3_memory_highwater(0) };
+            if highwater >= limit {
+                ceilings.push(ResourceCeiling::SoftHeapLimit);
+            }
+        }
+
+        ceilings
+    }
+}
+
+impl Drop for RunLimits<'_> {
+    fn drop(&mut self) {
+        if self.limits.statement_cpu_budget_ms.is_some() || self.limits.query_timeout_ms.is_some() {
+            self.conn.progress_handler(0, None::<fn() -> bool>);
+        }
+
+        if let Some(previous) = self.previous_soft_heap_limit {
+            // SAFETY: only updates SQLite's global memory accounting
+            unsafe {
+                ffi::sqlite3_soft_heap_limit64(previous);
+            }
+        }
+    }
+}
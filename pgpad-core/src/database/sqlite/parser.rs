@@ -29,6 +29,11 @@ impl SqlDialectExt for SQLiteDialect {
             _ => false,
         }
     }
+
+    /// `?`, `?NNN`, `:name`, `@name` and `$name`
+    fn binds_placeholder(name: &str) -> bool {
+        name.starts_with(['?', ':', '@', '$'])
+    }
 }
 
 fn pragma_returns_values(name: &ObjectName) -> bool {
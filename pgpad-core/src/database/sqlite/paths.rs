@@ -0,0 +1,285 @@
+//! Paths of database files, normalized so that connections keep working when the storage database is synced
+//! between machines. Besides the absolute path, files inside the home directory are also stored relative to it
+//! (`~/data/app.db`), which is what's tried when the absolute path doesn't exist on this machine.
+//!
+//! The functions here deal with both `/` and `\` separators whatever the platform, since a path may have been
+//! saved on another one.
+
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+/// SQLite's in-memory database, which isn't a file
+pub const MEMORY: &str = ":memory:";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedPath {
+    pub absolute: String,
+    /// e.g. `~/data/app.db`, if the file is inside the home directory
+    pub home_relative: Option<String>,
+}
+
+/// The home directory of the user running pgpad
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+/// Whether the path comes from Windows, where comparisons ignore case
+fn is_windows_style(path: &str) -> bool {
+    path.contains('\\') || path.as_bytes().get(1) == Some(&b':')
+}
+
+fn is_unc(path: &str) -> bool {
+    path.starts_with("\\\\") || path.starts_with("//")
+}
+
+fn components(path: &str) -> Vec<&str> {
+    path.split(is_separator)
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect()
+}
+
+/// The form of `path` relative to `home`, e.g. `~/data/app.db`, if it's inside it
+fn home_relative(path: &str, home: &str) -> Option<String> {
+    if is_unc(path) != is_unc(home) {
+        return None;
+    }
+
+    let path_components = components(path);
+    let home_components = components(home);
+    if home_components.is_empty() || path_components.len() <= home_components.len() {
+        return None;
+    }
+
+    let ignore_case = is_windows_style(path) || is_windows_style(home);
+    let inside_home =
+        path_components
+            .iter()
+            .zip(&home_components)
+            .all(|(a, b)| match ignore_case {
+                true => a.eq_ignore_ascii_case(b),
+                false => a == b,
+            });
+    if !inside_home || path_components.contains(&"..") {
+        return None;
+    }
+
+    Some(format!(
+        "~/{}",
+        path_components[home_components.len()..].join("/")
+    ))
+}
+
+/// Expands a path starting with `~` with the given home directory
+fn expand_home(path: &str, home: &Path) -> Option<PathBuf> {
+    let rest = match path {
+        "~" => "",
+        path => path
+            .strip_prefix("~/")
+            .or_else(|| path.strip_prefix("~\\"))?,
+    };
+
+    let mut expanded = home.to_path_buf();
+    expanded.extend(components(rest));
+    Some(expanded)
+}
+
+/// Removes the `\\?\` prefix Windows adds to canonicalized paths, which other programs (and people) don't expect
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    let text = path.to_string_lossy();
+    if let Some(unc) = text.strip_prefix(r"\\?\UNC\") {
+        PathBuf::from(format!(r"\\{unc}"))
+    } else if let Some(local) = text.strip_prefix(r"\\?\") {
+        PathBuf::from(local)
+    } else {
+        path
+    }
+}
+
+/// Makes `path` absolute, resolving symlinks and `..` if the file (or at least its directory) exists.
+///
+/// `~` is expanded, and other relative paths are taken relative to the home directory rather than to wherever
+/// pgpad happens to be started from.
+pub fn normalize(path: &str, home: Option<&Path>) -> Result<NormalizedPath, Error> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Err(anyhow::anyhow!("The database file path is empty").into());
+    }
+    if path == MEMORY {
+        return Ok(NormalizedPath {
+            absolute: path.to_owned(),
+            home_relative: None,
+        });
+    }
+
+    let mut absolute = home
+        .and_then(|home| expand_home(path, home))
+        .unwrap_or_else(|| PathBuf::from(path));
+    if absolute.is_relative() && !is_unc(path) {
+        let home = home.ok_or_else(|| {
+            anyhow::anyhow!("Can't resolve the relative path {path}: the home directory is unknown")
+        })?;
+        absolute = home.join(absolute);
+    }
+
+    if let Ok(canonical) = absolute.canonicalize() {
+        absolute = strip_verbatim_prefix(canonical);
+    } else if let (Some(dir), Some(file_name)) = (absolute.parent(), absolute.file_name()) {
+        // A database that's yet to be created
+        if let Ok(dir) = dir.canonicalize() {
+            absolute = strip_verbatim_prefix(dir).join(file_name);
+        }
+    }
+
+    let absolute = absolute.to_string_lossy().into_owned();
+    let home_relative = home.and_then(|home| home_relative(&absolute, &home.to_string_lossy()));
+
+    Ok(NormalizedPath {
+        absolute,
+        home_relative,
+    })
+}
+
+/// Where the file of a connection is on this machine: at its absolute path, or else at its home-relative one.
+/// None if it's at neither.
+pub fn resolve(
+    absolute: &str,
+    home_relative: Option<&str>,
+    home: Option<&Path>,
+) -> Option<PathBuf> {
+    if absolute == MEMORY || Path::new(absolute).is_file() {
+        return Some(PathBuf::from(absolute));
+    }
+
+    home_relative
+        .zip(home)
+        .and_then(|(path, home)| expand_home(path, home))
+        .filter(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn relates_paths_to_the_home_directory() {
+        for (path, home, expected) in [
+            ("/home/me/data/app.db", "/home/me", Some("~/data/app.db")),
+            (
+                "/home/me/my data/café ☕.db",
+                "/home/me/",
+                Some("~/my data/café ☕.db"),
+            ),
+            (
+                r"C:\Users\Me\Data\sales.db",
+                r"c:\users\me",
+                Some("~/Data/sales.db"),
+            ),
+            (
+                r"\\fileserver\home\me\q3 report.db",
+                r"\\fileserver\home\me",
+                Some("~/q3 report.db"),
+            ),
+            ("/home/meg/app.db", "/home/me", None),
+            ("/home/Me/app.db", "/home/me", None),
+            ("/fileserver/home/me/app.db", r"\\fileserver\home\me", None),
+            ("/home/me", "/home/me", None),
+            ("/home/me/../you/app.db", "/home/me", None),
+        ] {
+            assert_eq!(home_relative(path, home).as_deref(), expected, "{path}");
+        }
+
+        let mac_home = Path::new("/Users/me");
+        assert_eq!(
+            expand_home("~/Data/sales.db", mac_home),
+            Some(PathBuf::from("/Users/me/Data/sales.db"))
+        );
+        assert_eq!(
+            expand_home(r"~\q3 report.db", mac_home),
+            Some(PathBuf::from("/Users/me/q3 report.db"))
+        );
+        assert_eq!(expand_home("/Users/me/app.db", mac_home), None);
+
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from(r"\\?\UNC\fileserver\share\app.db")),
+            PathBuf::from(r"\\fileserver\share\app.db")
+        );
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from(r"\\?\C:\data\app.db")),
+            PathBuf::from(r"C:\data\app.db")
+        );
+    }
+
+    #[test]
+    fn round_trips_paths() {
+        let home = std::env::temp_dir()
+            .canonicalize()
+            .unwrap()
+            .join(format!("pgpad-home-{}", Uuid::new_v4()));
+        let dir = home.join("my data").join("café ☕");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("sales q3.db");
+        fs::write(&file, b"").unwrap();
+
+        for path in [
+            file.to_string_lossy().into_owned(),
+            "~/my data/café ☕/sales q3.db".to_owned(),
+            "my data/./café ☕/sales q3.db".to_owned(),
+            format!(
+                "  {}/my data/../my data/café ☕/sales q3.db  ",
+                home.display()
+            ),
+        ] {
+            let normalized = normalize(&path, Some(&home)).unwrap();
+            assert_eq!(normalized.absolute, file.to_string_lossy(), "{path}");
+            assert_eq!(
+                normalized.home_relative.as_deref(),
+                Some("~/my data/café ☕/sales q3.db")
+            );
+            assert_eq!(
+                resolve(
+                    &normalized.absolute,
+                    normalized.home_relative.as_deref(),
+                    Some(&home)
+                ),
+                Some(file.clone())
+            );
+        }
+
+        // Synced from another machine, where the home directory is elsewhere
+        let synced = resolve(
+            r"C:\Users\me\my data\café ☕\sales q3.db",
+            Some("~/my data/café ☕/sales q3.db"),
+            Some(&home),
+        );
+        assert_eq!(synced, Some(file.clone()));
+
+        let new_file = normalize("~/new.db", Some(&home)).unwrap();
+        assert_eq!(new_file.absolute, home.join("new.db").to_string_lossy());
+        assert_eq!(
+            resolve(
+                &new_file.absolute,
+                new_file.home_relative.as_deref(),
+                Some(&home)
+            ),
+            None
+        );
+
+        assert_eq!(normalize(MEMORY, Some(&home)).unwrap().absolute, MEMORY);
+        assert!(normalize("  ", Some(&home)).is_err());
+        assert!(normalize("relative.db", None).is_err());
+
+        fs::remove_dir_all(home).unwrap();
+    }
+}
@@ -9,15 +9,27 @@ use crate::utils;
 pub struct RowWriter {
     buf: String,
     row_count: usize,
-    column_decltypes: Vec<Option<String>>,
+    /// Whether each column's declared type looks like a boolean, so that its 0s and 1s are written as such
+    bool_columns: Vec<bool>,
 }
 
 impl RowWriter {
     pub fn new(column_decltypes: Vec<Option<String>>) -> Self {
+        let bool_columns = column_decltypes
+            .iter()
+            .map(|decltype| {
+                decltype.as_deref().is_some_and(|s| {
+                    s.eq_ignore_ascii_case("boolean")
+                        || s.eq_ignore_ascii_case("bool")
+                        || s.to_ascii_lowercase().contains("tinyint(1)")
+                })
+            })
+            .collect();
+
         Self {
             buf: String::new(),
             row_count: 0,
-            column_decltypes,
+            bool_columns,
         }
     }
 
@@ -32,30 +44,17 @@ impl RowWriter {
         }
 
         self.buf.push('[');
-        for i in 0..self.column_decltypes.len() {
+        for i in 0..self.bool_columns.len() {
             if i > 0 {
                 self.buf.push(',');
             }
 
             match row.get_ref(i)? {
-                ValueRef::Null => self.write_json_string("NULL"),
-                ValueRef::Integer(value) if value == 0 || value == 1 => {
-                    let decltype = self.column_decltypes[i].as_deref();
-                    let looks_like_bool = decltype
-                        .map(|s| {
-                            s.eq_ignore_ascii_case("boolean")
-                                || s.eq_ignore_ascii_case("bool")
-                                || s.to_ascii_lowercase().contains("tinyint(1)")
-                        })
-                        .unwrap_or(false);
-
-                    if looks_like_bool {
-                        write!(&mut self.buf, "{}", value == 1)?
-                    } else {
-                        write!(&mut self.buf, "{value}")?
-                    }
+                ValueRef::Null => self.buf.push_str("null"),
+                ValueRef::Integer(value @ (0 | 1)) if self.bool_columns[i] => {
+                    write!(&mut self.buf, "{}", value == 1)?
                 }
-                ValueRef::Integer(value) => write!(&mut self.buf, "{value}")?,
+                ValueRef::Integer(value) => self.buf.push_str(itoa::Buffer::new().format(value)),
                 ValueRef::Real(value) => write!(&mut self.buf, "{value}")?,
                 ValueRef::Text(value) => {
                     // If this is a JSON object or array, convert it so that it's picked up by JsonInspector in the front-end
@@ -93,33 +92,13 @@ impl RowWriter {
     }
 
     pub fn finish(&mut self) -> Box<RawValue> {
-        if self.row_count == 0 {
-            self.buf.push('[');
-        }
-        self.buf.push(']');
-
-        let json = std::mem::take(&mut self.buf);
+        let page = utils::finish_page(&mut self.buf, self.row_count);
         self.row_count = 0;
-
-        RawValue::from_string(json).unwrap()
+        page
     }
 
     fn write_json_string(&mut self, s: &str) {
-        self.buf.push('"');
-        for ch in s.chars() {
-            match ch {
-                '"' => self.buf.push_str("\\\""),
-                '\\' => self.buf.push_str("\\\\"),
-                '\n' => self.buf.push_str("\\n"),
-                '\r' => self.buf.push_str("\\r"),
-                '\t' => self.buf.push_str("\\t"),
-                c if c.is_control() => {
-                    write!(&mut self.buf, "\\u{:04x}", c as u32).unwrap();
-                }
-                c => self.buf.push(c),
-            }
-        }
-        self.buf.push('"');
+        utils::write_json_string(&mut self.buf, s);
     }
 }
 
@@ -264,7 +243,7 @@ mod tests {
     fn null_handling() -> Result<(), Error> {
         let conn = create_test_db()?;
         let result = execute_query_one_row(&conn, "SELECT null_col FROM test_data WHERE id = 1")?;
-        assert_eq!(result, serde_json::json!([["NULL"]]));
+        assert_eq!(result, serde_json::json!([[null]]));
         Ok(())
     }
 
@@ -1,73 +1,525 @@
+//! The tables of SQLite connections, from the main database and from every database attached to it. The tables of
+//! an attached database are in the schema named after it, those of the main database in the unnamed one.
+
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
 use anyhow::Context;
 use rusqlite::Connection;
 
 use crate::{
-    database::types::{ColumnInfo, DatabaseSchema, TableInfo},
+    database::{
+        browse::qualified_name,
+        trace::{StatementSource, StatementTrace},
+        types::{ColumnInfo, DatabaseSchema, ForeignKey, ReferentialAction, TableInfo},
+    },
+    utils::quote_identifier,
     Error,
 };
 
-pub async fn get_database_schema(conn: Arc<Mutex<Connection>>) -> Result<DatabaseSchema, Error> {
+/// The main database, then the attached ones. The temporary database only has what the session created.
+const DATABASES_QUERY: &str =
+    "SELECT name FROM pragma_database_list WHERE name <> 'temp' ORDER BY seq";
+
+/// Databases up to this size have every table counted, which is quick at that size
+const EXACT_COUNT_MAX_BYTES: i64 = 32 * 1024 * 1024;
+
+const MAIN_DATABASE: &str = "main";
+
+/// The schema the tables of a database are in
+fn schema_name(database: &str) -> &str {
+    match database {
+        MAIN_DATABASE => "",
+        database => database,
+    }
+}
+
+pub async fn get_database_schema(
+    conn: Arc<Mutex<Connection>>,
+    trace: Arc<StatementTrace>,
+) -> Result<DatabaseSchema, Error> {
     tokio::task::spawn_blocking(move || {
         let conn = conn.lock().unwrap();
 
-        let mut tables_stmt = conn.prepare(
-            "SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%'",
-        )?;
-        let table_names: Vec<String> = tables_stmt
-            .query_map([], |row| row.get::<_, String>(0))?
-            .collect::<Result<Vec<_>, _>>()?;
+        let started_at = Instant::now();
+        let databases: Vec<String> = conn
+            .prepare(DATABASES_QUERY)?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        trace.record(
+            StatementSource::Introspection,
+            DATABASES_QUERY,
+            started_at.elapsed(),
+            Some(databases.len()),
+        );
 
         let mut tables = Vec::new();
         let mut unique_columns_set = HashSet::new();
+        // The primary key of each table by schema and name, by position in the key, for the tables and for foreign
+        // keys that don't name the columns
+        let mut primary_keys: HashMap<(String, String), BTreeMap<i32, String>> = HashMap::new();
+
+        for database in &databases {
+            let schema = schema_name(database);
+            let tables_query = format!(
+                "SELECT name FROM {} WHERE type='table' AND name NOT LIKE 'sqlite_%'",
+                qualified_name(database, "sqlite_master")
+            );
+            let started_at = Instant::now();
+            let mut tables_stmt = conn.prepare(&tables_query)?;
+            let table_names: Vec<String> = tables_stmt
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            trace.record(
+                StatementSource::Introspection,
+                &tables_query,
+                started_at.elapsed(),
+                Some(table_names.len()),
+            );
 
-        for table_name in table_names {
-            let pragma_query = format!("PRAGMA table_info('{}')", table_name);
-            let mut col_stmt = conn
-                .prepare(&pragma_query)
-                .context("Failed to prepare PRAGMA table_info query")?;
+            let mut row_estimates = row_estimates(&conn, &trace, database, &table_names);
 
-            let col_rows = col_stmt.query_map([], |row| {
-                let column_name: String = row.get(1)?;
-                let data_type: String = row.get(2)?;
-                let not_null: bool = row.get::<_, i32>(3)? != 0;
-                let default_value: Option<String> = row.get(4)?;
+            for table_name in table_names {
+                let pragma_query = format!(
+                    "PRAGMA {}.table_info('{}')",
+                    quote_identifier(database),
+                    table_name.replace('\'', "''")
+                );
+                let started_at = Instant::now();
+                let mut col_stmt = conn
+                    .prepare(&pragma_query)
+                    .context("Failed to prepare PRAGMA table_info query")?;
 
-                Ok((column_name, data_type, !not_null, default_value)) // !not_null = is_nullable
-            })?;
+                let col_rows = col_stmt.query_map([], |row| {
+                    let column_name: String = row.get(1)?;
+                    let data_type: String = row.get(2)?;
+                    let not_null: bool = row.get::<_, i32>(3)? != 0;
+                    let default_value: Option<String> = row.get(4)?;
+                    let primary_key: i32 = row.get(5)?;
 
-            let mut columns = Vec::new();
-            for col_result in col_rows {
-                let (column_name, data_type, is_nullable, default_value) = col_result?;
+                    Ok((
+                        column_name,
+                        data_type,
+                        !not_null,
+                        default_value,
+                        primary_key,
+                    )) // !not_null = is_nullable
+                })?;
 
-                unique_columns_set.insert(column_name.clone());
+                let mut columns = Vec::new();
+                for col_result in col_rows {
+                    let (column_name, data_type, is_nullable, default_value, primary_key) =
+                        col_result?;
 
-                columns.push(ColumnInfo {
-                    name: column_name,
-                    data_type,
-                    is_nullable,
-                    default_value,
+                    unique_columns_set.insert(column_name.clone());
+                    if primary_key > 0 {
+                        primary_keys
+                            .entry((schema.to_owned(), table_name.clone()))
+                            .or_default()
+                            .insert(primary_key, column_name.clone());
+                    }
+
+                    columns.push(ColumnInfo {
+                        name: column_name,
+                        data_type,
+                        is_nullable,
+                        default_value,
+                    });
+                }
+                trace.record(
+                    StatementSource::Introspection,
+                    &pragma_query,
+                    started_at.elapsed(),
+                    Some(columns.len()),
+                );
+
+                let foreign_keys = foreign_keys(&conn, &trace, database, &table_name)?;
+                tables.push(TableInfo {
+                    row_estimate: row_estimates.remove(&table_name),
+                    name: table_name,
+                    schema: schema.to_owned(),
+                    columns,
+                    foreign_keys,
+                    ..Default::default()
                 });
             }
+        }
 
-            tables.push(TableInfo {
-                name: table_name,
-                schema: String::new(),
-                columns,
-            });
+        for table in &mut tables {
+            if let Some(primary_key) = primary_keys.get(&(table.schema.clone(), table.name.clone()))
+            {
+                table.primary_key = primary_key.values().cloned().collect();
+            }
+            for key in &mut table.foreign_keys {
+                if key.referenced_columns.is_empty() {
+                    key.referenced_columns = primary_keys
+                        .get(&(table.schema.clone(), key.referenced_table.clone()))
+                        .map(|columns| columns.values().cloned().collect())
+                        .unwrap_or_default();
+                }
+            }
         }
 
         let unique_columns = unique_columns_set.into_iter().collect();
+        let schemas = databases
+            .into_iter()
+            .filter(|database| database != MAIN_DATABASE)
+            .collect();
 
-        Ok(DatabaseSchema {
-            tables,
-            schemas: vec![],
-            unique_columns,
-        }) as Result<_, Error>
+        Ok(DatabaseSchema::new(tables, schemas, unique_columns)) as Result<_, Error>
     })
     .await?
 }
+
+/// The rows of each table of a database: counted if the database is small, or else as of the last `ANALYZE`.
+/// Tables neither tells about are left out.
+fn row_estimates(
+    conn: &Connection,
+    trace: &StatementTrace,
+    database: &str,
+    table_names: &[String],
+) -> HashMap<String, i64> {
+    let pragma = |name: &str| {
+        let query = format!("PRAGMA {}.{name}", quote_identifier(database));
+        conn.query_row(&query, [], |row| row.get::<_, i64>(0))
+    };
+    let size = match (pragma("page_count"), pragma("page_size")) {
+        (Ok(page_count), Ok(page_size)) => page_count * page_size,
+        _ => i64::MAX,
+    };
+
+    if size <= EXACT_COUNT_MAX_BYTES {
+        return table_names
+            .iter()
+            .filter_map(|table| {
+                let query = format!("SELECT count(*) FROM {}", qualified_name(database, table));
+                let started_at = Instant::now();
+                let count = conn.query_row(&query, [], |row| row.get(0)).ok()?;
+                trace.record(
+                    StatementSource::Introspection,
+                    &query,
+                    started_at.elapsed(),
+                    Some(1),
+                );
+                Some((table.clone(), count))
+            })
+            .collect();
+    }
+
+    // What `ANALYZE` found, the first number of `stat` being the rows of the table
+    let stat1_query = format!(
+        "SELECT tbl, stat FROM {}",
+        qualified_name(database, "sqlite_stat1")
+    );
+    let started_at = Instant::now();
+    // Without `ANALYZE` ever run, there's no `sqlite_stat1`
+    let Ok(mut stmt) = conn.prepare(&stat1_query) else {
+        return HashMap::new();
+    };
+    let Ok(rows) = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    }) else {
+        return HashMap::new();
+    };
+
+    let mut estimates = HashMap::new();
+    for (table, stat) in rows.flatten() {
+        let Some(rows) = stat
+            .split(' ')
+            .next()
+            .and_then(|rows| rows.parse::<i64>().ok())
+        else {
+            continue;
+        };
+        let estimate = estimates.entry(table).or_insert(rows);
+        *estimate = rows.max(*estimate);
+    }
+    trace.record(
+        StatementSource::Introspection,
+        &stat1_query,
+        started_at.elapsed(),
+        Some(estimates.len()),
+    );
+    estimates
+}
+
+/// The foreign keys of a table, to tables of the same database. Those to the primary key of the other table without
+/// naming its columns are left without referenced columns.
+fn foreign_keys(
+    conn: &Connection,
+    trace: &StatementTrace,
+    database: &str,
+    table_name: &str,
+) -> Result<Vec<ForeignKey>, Error> {
+    let pragma_query = format!(
+        "PRAGMA {}.foreign_key_list('{}')",
+        quote_identifier(database),
+        table_name.replace('\'', "''")
+    );
+    let started_at = Instant::now();
+    let mut stmt = conn
+        .prepare(&pragma_query)
+        .context("Failed to prepare PRAGMA foreign_key_list query")?;
+
+    // By id, and within a key by seq
+    let mut keys: BTreeMap<i64, ForeignKey> = BTreeMap::new();
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, String>(6)?,
+        ))
+    })?;
+    for row in rows {
+        let (id, referenced_table, column, referenced_column, on_update, on_delete) = row?;
+        let key = keys.entry(id).or_insert_with(|| ForeignKey {
+            columns: Vec::new(),
+            referenced_schema: schema_name(database).to_owned(),
+            referenced_table,
+            referenced_columns: Vec::new(),
+            name: None,
+            on_delete: ReferentialAction::from_rule(&on_delete),
+            on_update: ReferentialAction::from_rule(&on_update),
+        });
+        key.columns.push(column);
+        key.referenced_columns.extend(referenced_column);
+    }
+    trace.record(
+        StatementSource::Introspection,
+        &pragma_query,
+        started_at.elapsed(),
+        Some(keys.len()),
+    );
+
+    Ok(keys.into_values().collect())
+}
+
+/// Attaches the database file at `path` as `alias`. Its tables are then in the schema named `alias`.
+pub fn attach_database(conn: &Connection, path: &str, alias: &str) -> Result<(), Error> {
+    if alias.is_empty()
+        || [MAIN_DATABASE, "temp"]
+            .iter()
+            .any(|reserved| alias.eq_ignore_ascii_case(reserved))
+    {
+        return Err(anyhow::anyhow!("{alias:?} can't be the name of an attached database").into());
+    }
+    // SQLite would create an empty database rather than fail
+    if !Path::new(path).is_file() {
+        return Err(anyhow::anyhow!("There's no database file at {path}").into());
+    }
+
+    conn.execute("ATTACH DATABASE ?1 AS ?2", [path, alias])
+        .with_context(|| format!("Failed to attach {path} as {alias}"))?;
+    Ok(())
+}
+
+pub fn detach_database(conn: &Connection, alias: &str) -> Result<(), Error> {
+    conn.execute("DETACH DATABASE ?1", [alias])
+        .with_context(|| format!("Failed to detach {alias}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn introspect(statements: &str) -> DatabaseSchema {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(statements).unwrap();
+        get_database_schema(Arc::new(Mutex::new(conn)), Default::default())
+            .await
+            .unwrap()
+    }
+
+    fn to_json(mut schema: DatabaseSchema) -> String {
+        schema.generated_at = 0;
+        serde_json::to_string(&schema).unwrap()
+    }
+
+    #[tokio::test]
+    async fn serializes_unchanged_schemas_identically() {
+        let fixture = "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                       CREATE TABLE orders (id INTEGER, user_id INTEGER, total REAL DEFAULT 0);
+                       CREATE TABLE Événements (id INTEGER);";
+        let first = introspect(fixture).await;
+        let names: Vec<_> = first
+            .tables
+            .iter()
+            .map(|table| table.name.as_str())
+            .collect();
+        assert_eq!(names, ["orders", "users", "Événements"]);
+        assert_eq!(first.unique_columns, ["id", "name", "total", "user_id"]);
+
+        let json = to_json(first.clone());
+        for _ in 0..3 {
+            assert_eq!(to_json(introspect(fixture).await), json);
+        }
+
+        // Created in another order, with the same tables
+        let reordered = introspect(
+            "CREATE TABLE Événements (id INTEGER);
+             CREATE TABLE orders (id INTEGER, user_id INTEGER, total REAL DEFAULT 0);
+             CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL);",
+        )
+        .await;
+        assert_eq!(to_json(reordered), json);
+
+        // Only the changed table gets a new content hash
+        let changed = introspect(&fixture.replace("total REAL DEFAULT 0", "total NUMERIC")).await;
+        assert_ne!(changed.hash, first.hash);
+        for (before, after) in first.tables.iter().zip(&changed.tables) {
+            assert_eq!(before.id, after.id);
+            assert_eq!(
+                before.content_hash == after.content_hash,
+                before.name != "orders"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_the_rows_of_small_databases() {
+        let schema = introspect(
+            r#"CREATE TABLE empty (id INTEGER);
+               CREATE TABLE "odd""name" (id INTEGER);
+               INSERT INTO "odd""name" VALUES (1), (2), (3);"#,
+        )
+        .await;
+
+        assert_eq!(schema.table("", "empty").unwrap().row_estimate, Some(0));
+        assert_eq!(schema.table("", "odd\"name").unwrap().row_estimate, Some(3));
+    }
+
+    #[tokio::test]
+    async fn reads_foreign_keys() {
+        let schema = introspect(
+            "CREATE TABLE customers (id INTEGER PRIMARY KEY);
+             CREATE TABLE orders (
+                 region TEXT,
+                 number INTEGER,
+                 customer_id INTEGER REFERENCES customers ON DELETE SET NULL,
+                 PRIMARY KEY (number, region)
+             );
+             CREATE TABLE lines (
+                 order_region TEXT,
+                 order_number INTEGER,
+                 FOREIGN KEY (order_number, order_region) REFERENCES orders,
+                 FOREIGN KEY (order_region) REFERENCES regions (code)
+             );",
+        )
+        .await;
+
+        let keys = |table: &str| -> Vec<(Vec<String>, String, Vec<String>)> {
+            let table = schema.table("", table).unwrap();
+            table
+                .foreign_keys
+                .iter()
+                .map(|key| {
+                    (
+                        key.columns.clone(),
+                        key.referenced_table.clone(),
+                        key.referenced_columns.clone(),
+                    )
+                })
+                .collect()
+        };
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        assert!(keys("customers").is_empty());
+        // The columns of the primary key, when they aren't named
+        assert_eq!(
+            keys("orders"),
+            [(
+                names(&["customer_id"]),
+                "customers".to_owned(),
+                names(&["id"])
+            )]
+        );
+        assert_eq!(
+            keys("lines"),
+            [
+                (
+                    names(&["order_number", "order_region"]),
+                    "orders".to_owned(),
+                    names(&["number", "region"])
+                ),
+                // To a table that doesn't exist, which SQLite allows
+                (
+                    names(&["order_region"]),
+                    "regions".to_owned(),
+                    names(&["code"])
+                ),
+            ]
+        );
+
+        let orders = schema.table("", "orders").unwrap();
+        assert_eq!(orders.primary_key, ["number", "region"]);
+        assert_eq!(orders.foreign_keys[0].on_delete, ReferentialAction::SetNull);
+        assert_eq!(
+            orders.foreign_keys[0].on_update,
+            ReferentialAction::NoAction
+        );
+        assert!(schema.table("", "lines").unwrap().primary_key.is_empty());
+        // Only the keys to tables of the schema
+        assert_eq!(schema.relationship_graph().edges.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn introspects_attached_databases() {
+        let schema = introspect(
+            "CREATE TABLE items (id INTEGER PRIMARY KEY);
+             ATTACH DATABASE ':memory:' AS archive;
+             CREATE TABLE archive.items (id INTEGER PRIMARY KEY, sku TEXT);
+             CREATE TABLE archive.parts (item_id INTEGER REFERENCES items);
+             INSERT INTO archive.items VALUES (1, 'a'), (2, 'b');
+             INSERT INTO archive.parts VALUES (1), (2);",
+        )
+        .await;
+
+        assert_eq!(schema.schemas, ["archive"]);
+        assert_eq!(schema.table("", "items").unwrap().columns.len(), 1);
+        assert_eq!(schema.table("archive", "items").unwrap().columns.len(), 2);
+        let parts = schema.table("archive", "parts").unwrap();
+        assert_eq!(parts.row_estimate, Some(2));
+        // To the table of the same database, not to the main one's
+        assert_eq!(parts.foreign_keys[0].referenced_schema, "archive");
+        assert_eq!(parts.foreign_keys[0].referenced_columns, ["id"]);
+        assert!(schema.unique_columns.contains(&"sku".to_owned()));
+    }
+
+    #[test]
+    fn attaches_and_detaches_databases() {
+        let path = std::env::temp_dir().join(format!("pgpad-attached-{}.db", uuid::Uuid::new_v4()));
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE events (id INTEGER)")
+            .unwrap();
+        let path = path.to_str().unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        attach_database(&conn, path, "events_db").unwrap();
+        let count: i64 = conn
+            .query_row("SELECT count(*) FROM events_db.events", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 0);
+
+        assert!(attach_database(&conn, path, "main").is_err());
+        assert!(attach_database(&conn, &format!("{path}.missing"), "other").is_err());
+
+        detach_database(&conn, "events_db").unwrap();
+        assert!(conn
+            .execute_batch("SELECT * FROM events_db.events")
+            .is_err());
+        assert!(detach_database(&conn, "events_db").is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
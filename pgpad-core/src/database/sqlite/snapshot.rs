@@ -0,0 +1,547 @@
+//! Snapshots of SQLite files that are slow or risky to query in place, e.g. on a network share: the file is
+//! copied to a local cache together with its WAL, and the connection opens the copy read-only.
+//!
+//! Each connection has at most one snapshot, which is reused for as long as its source doesn't change. The cache
+//! is kept under a size cap by evicting the snapshots that were used least recently.
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{database::sqlite::parser::parse_statements, Error};
+
+/// Most disk space the cache may use, in bytes, unless a snapshot in use needs more
+pub const DEFAULT_MAX_CACHE_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Progress is reported after every chunk
+const COPY_CHUNK_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    /// The file the snapshot was copied from
+    pub source_path: String,
+    /// When the source (or its WAL) was last modified before the copy, in milliseconds since the epoch
+    pub source_modified_ms: i64,
+    /// Size of the source and its WAL, in bytes
+    pub source_size: u64,
+    /// When the copy was made, as a Unix timestamp
+    pub copied_at: i64,
+    /// The copy the connection opens
+    pub local_path: String,
+    /// Size of the copy, in bytes
+    pub size_bytes: u64,
+}
+
+/// The outcome of [`refresh_snapshot`](crate::database::services::refresh_snapshot)
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotRefresh {
+    /// Whether the source had changed, and so was copied again
+    pub refreshed: bool,
+    pub snapshot: SnapshotInfo,
+}
+
+/// What's kept next to each snapshot in the cache directory
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(flatten)]
+    info: SnapshotInfo,
+    /// When the snapshot was last opened, in milliseconds since the epoch
+    last_used_ms: i64,
+}
+
+#[derive(Debug)]
+pub struct SnapshotCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Copies being written, which eviction mustn't mistake for leftovers
+    copying: Mutex<HashSet<PathBuf>>,
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+fn wal_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push("-wal");
+    PathBuf::from(path)
+}
+
+/// When a database was last modified and how large it is, WAL included, since in WAL mode most writes only touch
+/// the WAL
+fn source_version(source: &Path) -> Result<(i64, u64), Error> {
+    let mut modified_ms = 0;
+    let mut size = 0;
+
+    for path in [source.to_owned(), wal_path(source)] {
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == ErrorKind::NotFound && path != source => continue,
+            Err(err) => {
+                return Err(anyhow::Error::new(err)
+                    .context(format!("Failed to read {}", path.display()))
+                    .into())
+            }
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_millis() as i64);
+
+        modified_ms = modified_ms.max(modified);
+        size += metadata.len();
+    }
+
+    Ok((modified_ms, size))
+}
+
+/// Copies `from` into `to` chunk by chunk, adding to `copied` and reporting it through `on_progress`
+fn copy_file(
+    from: &Path,
+    to: &Path,
+    copied: &mut u64,
+    total: u64,
+    on_progress: &mut impl FnMut(u64, u64) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut reader =
+        fs::File::open(from).with_context(|| format!("Failed to open {}", from.display()))?;
+    let mut writer =
+        fs::File::create(to).with_context(|| format!("Failed to create {}", to.display()))?;
+
+    let mut buf = vec![0; COPY_CHUNK_BYTES];
+    loop {
+        let read = reader
+            .read(&mut buf)
+            .with_context(|| format!("Failed to read {}", from.display()))?;
+        if read == 0 {
+            break;
+        }
+        writer
+            .write_all(&buf[..read])
+            .with_context(|| format!("Failed to write {}", to.display()))?;
+
+        *copied += read as u64;
+        on_progress(*copied, total)?;
+    }
+
+    Ok(())
+}
+
+/// Checkpoints the WAL of a copy into its main file and switches it out of WAL mode, since a database in WAL mode
+/// can't be opened read-only without its shared-memory file
+fn fold_wal(db_path: &Path) -> Result<(), Error> {
+    let conn = rusqlite::Connection::open(db_path)?;
+    conn.query_row("PRAGMA journal_mode = DELETE", [], |row| {
+        row.get::<_, String>(0)
+    })?;
+    Ok(())
+}
+
+/// Removes a copy and the files SQLite may have left next to it
+fn remove_copy(db_path: &Path) {
+    let mut paths = vec![db_path.to_owned(), wal_path(db_path)];
+    for suffix in ["-shm", "-journal"] {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(suffix);
+        paths.push(PathBuf::from(path));
+    }
+
+    for path in paths {
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            // e.g. on Windows, while a connection still has it open. Eviction will try again later.
+            Err(err) => log::warn!("Failed to remove {}: {err}", path.display()),
+        }
+    }
+}
+
+impl SnapshotCache {
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes,
+            copying: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn entry_path(&self, connection_id: Uuid) -> PathBuf {
+        self.dir.join(format!("{connection_id}.json"))
+    }
+
+    /// Unreadable entries are treated like missing ones, so that the snapshot gets copied again
+    fn read_entry(&self, connection_id: Uuid) -> Option<CacheEntry> {
+        let json = fs::read_to_string(self.entry_path(connection_id)).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    fn write_entry(&self, connection_id: Uuid, entry: &CacheEntry) -> Result<(), Error> {
+        let path = self.entry_path(connection_id);
+        fs::write(&path, serde_json::to_vec(entry)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// The snapshot of `source` for a connection, if there's one and the source hasn't changed since.
+    /// Counts as a use of the snapshot.
+    pub fn cached(
+        &self,
+        connection_id: Uuid,
+        source: &Path,
+    ) -> Result<Option<SnapshotInfo>, Error> {
+        let Some(mut entry) = self.read_entry(connection_id) else {
+            return Ok(None);
+        };
+
+        let (modified_ms, size) = source_version(source)?;
+        let up_to_date = entry.info.source_path == source.to_string_lossy()
+            && entry.info.source_modified_ms == modified_ms
+            && entry.info.source_size == size
+            && Path::new(&entry.info.local_path).is_file();
+        if !up_to_date {
+            return Ok(None);
+        }
+
+        entry.last_used_ms = now_ms();
+        self.write_entry(connection_id, &entry)?;
+        Ok(Some(entry.info))
+    }
+
+    /// Copies `source` and its WAL into the cache, replacing the connection's previous snapshot.
+    ///
+    /// `on_progress` is called with the number of bytes copied so far and the expected total after every chunk,
+    /// and stops the copy if it fails.
+    pub fn copy(
+        &self,
+        connection_id: Uuid,
+        source: &Path,
+        mut on_progress: impl FnMut(u64, u64) -> Result<(), Error>,
+    ) -> Result<SnapshotInfo, Error> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        // Taken before copying, so that changes made while copying are picked up by the next refresh
+        let (source_modified_ms, source_size) = source_version(source)?;
+        let copied_at = chrono::Utc::now();
+        // Each copy gets a new name, so that connections still reading the previous one aren't disturbed
+        let local_path = self
+            .dir
+            .join(format!("{connection_id}-{}.db", Uuid::new_v4().simple()));
+
+        self.copying.lock().unwrap().insert(local_path.clone());
+        let result = (|| {
+            let mut copied = 0;
+            copy_file(
+                source,
+                &local_path,
+                &mut copied,
+                source_size,
+                &mut on_progress,
+            )?;
+            let source_wal = wal_path(source);
+            if source_wal.is_file() {
+                copy_file(
+                    &source_wal,
+                    &wal_path(&local_path),
+                    &mut copied,
+                    source_size,
+                    &mut on_progress,
+                )?;
+            }
+            fold_wal(&local_path)?;
+            Ok::<_, Error>(
+                fs::metadata(&local_path)
+                    .context("Failed to read the copy")?
+                    .len(),
+            )
+        })();
+        self.copying.lock().unwrap().remove(&local_path);
+
+        let size_bytes = match result {
+            Ok(size_bytes) => size_bytes,
+            Err(err) => {
+                remove_copy(&local_path);
+                return Err(err);
+            }
+        };
+
+        let info = SnapshotInfo {
+            source_path: source.to_string_lossy().into_owned(),
+            source_modified_ms,
+            source_size,
+            copied_at: copied_at.timestamp(),
+            local_path: local_path.to_string_lossy().into_owned(),
+            size_bytes,
+        };
+
+        let previous = self.read_entry(connection_id);
+        self.write_entry(
+            connection_id,
+            &CacheEntry {
+                info: info.clone(),
+                last_used_ms: now_ms(),
+            },
+        )?;
+        if let Some(previous) = previous {
+            remove_copy(Path::new(&previous.info.local_path));
+        }
+
+        Ok(info)
+    }
+
+    /// Removes the snapshot of a connection, if it has one
+    pub fn remove(&self, connection_id: Uuid) -> Result<(), Error> {
+        if let Some(entry) = self.read_entry(connection_id) {
+            remove_copy(Path::new(&entry.info.local_path));
+        }
+
+        match fs::remove_file(self.entry_path(connection_id)) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(anyhow::Error::new(err)
+                .context("Failed to remove the snapshot")
+                .into()),
+        }
+    }
+
+    /// Evicts the least recently used snapshots until the cache fits under its cap, except for those of the
+    /// connections in `keep` (e.g. the ones open right now). Also removes copies no snapshot refers to anymore.
+    pub fn evict(&self, keep: &[Uuid]) -> Result<(), Error> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(anyhow::Error::new(err)
+                    .context(format!("Failed to read {}", self.dir.display()))
+                    .into())
+            }
+        };
+
+        let mut entries = Vec::new();
+        let mut copies = Vec::new();
+        for dir_entry in dir {
+            let path = dir_entry
+                .context("Failed to read the snapshot cache")?
+                .path();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("json") => {
+                    let connection_id = path
+                        .file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .and_then(|stem| Uuid::parse_str(stem).ok());
+                    if let Some(connection_id) = connection_id {
+                        if let Some(entry) = self.read_entry(connection_id) {
+                            entries.push((connection_id, entry));
+                        }
+                    }
+                }
+                Some("db") => copies.push(path),
+                _ => {}
+            }
+        }
+
+        // Left over from interrupted copies, or from replaced snapshots that couldn't be removed at the time
+        let referenced: HashSet<_> = entries
+            .iter()
+            .map(|(_, entry)| PathBuf::from(&entry.info.local_path))
+            .collect();
+        let copying = self.copying.lock().unwrap().clone();
+        for copy in copies {
+            if !referenced.contains(&copy) && !copying.contains(&copy) {
+                remove_copy(&copy);
+            }
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, entry)| entry.info.size_bytes).sum();
+        entries.sort_by_key(|(_, entry)| entry.last_used_ms);
+        for (connection_id, entry) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if keep.contains(&connection_id) {
+                continue;
+            }
+
+            log::info!(
+                "Evicting the snapshot of {} from the cache",
+                entry.info.source_path
+            );
+            self.remove(connection_id)?;
+            total -= entry.info.size_bytes;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fails with an explanation if the query would write to the snapshot
+pub fn reject_writes(query: &str, snapshot: &SnapshotInfo) -> Result<(), Error> {
+    if parse_statements(query)?
+        .iter()
+        .all(|statement| statement.is_read_only)
+    {
+        return Ok(());
+    }
+
+    let copied_at = chrono::DateTime::from_timestamp(snapshot.copied_at, 0)
+        .map_or_else(String::new, |copied_at| {
+            format!(", copied on {}", copied_at.format("%Y-%m-%d %H:%M:%S UTC"))
+        });
+    Err(anyhow::anyhow!(
+        "This connection is a read-only snapshot of {}{copied_at}, so it can't be written to. \
+         Turn off \"Open as snapshot\" to connect to the file itself.",
+        snapshot.source_path
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pgpad-snapshots-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn row_count(db_path: &str) -> i64 {
+        let conn = rusqlite::Connection::open_with_flags(
+            db_path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .unwrap();
+        conn.query_row("SELECT count(*) FROM t", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn copies_the_wal_and_reuses_unchanged_snapshots() {
+        let dir = temp_dir();
+        let source = dir.join("source.db");
+        // Kept open, so that the WAL isn't checkpointed into the main file
+        let writer = rusqlite::Connection::open(&source).unwrap();
+        writer
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 PRAGMA wal_autocheckpoint = 0;
+                 CREATE TABLE t (id INTEGER);
+                 INSERT INTO t VALUES (1), (2), (3);",
+            )
+            .unwrap();
+        assert!(wal_path(&source).is_file());
+
+        let cache = SnapshotCache::new(dir.join("cache"), DEFAULT_MAX_CACHE_BYTES);
+        let connection_id = Uuid::new_v4();
+        assert!(cache.cached(connection_id, &source).unwrap().is_none());
+
+        let mut progress = Vec::new();
+        let snapshot = cache
+            .copy(connection_id, &source, |copied, total| {
+                progress.push((copied, total));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            progress.last().map(|(copied, _)| *copied),
+            Some(snapshot.source_size)
+        );
+        assert_eq!(row_count(&snapshot.local_path), 3);
+        assert!(!wal_path(Path::new(&snapshot.local_path)).exists());
+        assert_eq!(
+            cache.cached(connection_id, &source).unwrap(),
+            Some(snapshot.clone())
+        );
+
+        writer.execute("INSERT INTO t VALUES (4)", []).unwrap();
+        assert!(cache.cached(connection_id, &source).unwrap().is_none());
+
+        let refreshed = cache.copy(connection_id, &source, |_, _| Ok(())).unwrap();
+        assert_eq!(row_count(&refreshed.local_path), 4);
+        assert!(!Path::new(&snapshot.local_path).exists());
+
+        let cancelled = cache.copy(connection_id, &source, |_, _| {
+            Err(anyhow::anyhow!("Operation cancelled").into())
+        });
+        assert!(cancelled.is_err());
+        assert_eq!(
+            cache.cached(connection_id, &source).unwrap(),
+            Some(refreshed)
+        );
+
+        drop(writer);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_used_snapshots() {
+        let dir = temp_dir();
+        let source = dir.join("source.db");
+        rusqlite::Connection::open(&source)
+            .unwrap()
+            .execute_batch("CREATE TABLE t (id INTEGER); INSERT INTO t VALUES (1);")
+            .unwrap();
+
+        let size = {
+            let probe = SnapshotCache::new(dir.join("probe"), DEFAULT_MAX_CACHE_BYTES);
+            probe
+                .copy(Uuid::new_v4(), &source, |_, _| Ok(()))
+                .unwrap()
+                .size_bytes
+        };
+        let cache = SnapshotCache::new(dir.join("cache"), 2 * size);
+        let [a, b, c] = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        for connection_id in [a, b, c] {
+            cache.copy(connection_id, &source, |_, _| Ok(())).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        cache.cached(a, &source).unwrap().unwrap();
+
+        let leftover = dir
+            .join("cache")
+            .join(format!("{}-leftover.db", Uuid::new_v4()));
+        fs::write(&leftover, b"").unwrap();
+
+        cache.evict(&[]).unwrap();
+        assert!(cache.cached(a, &source).unwrap().is_some());
+        assert!(cache.cached(b, &source).unwrap().is_none());
+        assert!(cache.cached(c, &source).unwrap().is_some());
+        assert!(!leftover.exists());
+
+        // Snapshots in use are kept even when they don't fit
+        let cache = SnapshotCache::new(dir.join("cache"), 0);
+        cache.evict(&[c]).unwrap();
+        assert!(cache.cached(a, &source).unwrap().is_none());
+        assert!(cache.cached(c, &source).unwrap().is_some());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_writes() {
+        let snapshot = SnapshotInfo {
+            source_path: "/mnt/share/sales.db".into(),
+            source_modified_ms: 0,
+            source_size: 0,
+            copied_at: 0,
+            local_path: String::new(),
+            size_bytes: 0,
+        };
+
+        reject_writes("SELECT * FROM t; SELECT 1", &snapshot).unwrap();
+        let err = reject_writes("SELECT 1; DELETE FROM t", &snapshot).unwrap_err();
+        assert!(err.to_string().starts_with(
+            "This connection is a read-only snapshot of /mnt/share/sales.db, copied on 1970-01-01"
+        ));
+    }
+}
@@ -1,47 +1,219 @@
-use std::sync::{
-    atomic::{AtomicU8, Ordering},
-    Arc, Mutex, RwLock,
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
 };
 
 use anyhow::Context;
 use serde_json::value::RawValue;
-use tokio::task::{self, JoinHandle};
+use tokio::{
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    task::{self, JoinHandle},
+};
 
 use dashmap::DashMap;
+use futures_util::{
+    future::{self, BoxFuture, Shared},
+    FutureExt,
+};
+use uuid::Uuid;
 
 use crate::{
     database::{
+        lineage::ColumnLineage,
+        mysql,
         parser::ParsedStatement,
-        postgres, sqlite,
-        types::{channel, Page, QueryId, QuerySnapshot, QueryStatus, RuntimeClient},
+        postgres, query_tags,
+        result_cache::{self, PageStore, SpillConfig, SPILL_DIR},
+        sqlite,
+        types::{
+            channel, ExecSender, FetchBudget, Page, PageAvailable, QueryId, QuerySnapshot,
+            QueryStatus, ResourceCeiling, RowDetailField, RunOptions, RuntimeClient,
+            SavepointOutcome, ServerMessage, StatementInfo, StatementMetrics, TimingBreakdown,
+            DEFAULT_FETCH_AHEAD_PAGES,
+        },
         QueryExecEvent,
     },
     utils::Condvar,
     Error,
 };
 
+type PageSender = Arc<Mutex<Option<UnboundedSender<PageAvailable>>>>;
+
+/// Shortest time between two page events of a query. Fast streams produce pages much quicker than the UI can
+/// take in events, so the pages that come in meanwhile are reported together.
+const PAGE_EVENT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Rows per page of a view, see [`StatementManager::register_row_view`]
+const VIEW_PAGE_SIZE: usize = 50;
+
 /// The storage/state for an individual statement being executed
 struct ExecState {
+    /// The connection the statement runs on. None for derived results.
+    connection_id: Option<Uuid>,
     status: AtomicU8,
-    pages: RwLock<Vec<Page>>,
+    /// Appended to while the statement streams rows. Readers only hold the lock to clone out a single page.
+    pages: RwLock<PageStore>,
     error: RwLock<Option<String>>,
     columns: RwLock<Option<Box<RawValue>>>,
+    /// In the same order as `columns`. Empty if unknown, e.g. for derived results.
+    column_types: RwLock<Vec<Option<String>>>,
     /// True if this query is expected to return some amount of rows
     /// False if this is a query that will never return anything (e.g. an UPDATE without a RETURNING clause)
     // TODO(vini): we could refactor this into an enum with a variant with `pages`, `columns`, and one with just `rows_affected`
     returns_values: bool,
     rows_affected: RwLock<Option<usize>>,
+    ceilings_hit: RwLock<Vec<ResourceCeiling>>,
+    column_lineage: RwLock<Option<Vec<ColumnLineage>>>,
+    savepoint: RwLock<Option<SavepointOutcome>>,
+    /// None for derived results
+    statement: Option<StatementInfo>,
+    /// True for derived results that left out some of their source rows (e.g. groups past `MAX_GROUPS`)
+    truncated: bool,
+    marks: Mutex<PhaseMarks>,
+    fetched: Mutex<Fetched>,
+    messages: Mutex<Vec<ServerMessage>>,
+    /// Set once the statement is cancelled, so that it doesn't start if it hasn't yet
+    cancelled: AtomicBool,
+    /// Interrupts the statement while it runs on SQLite
+    interrupt: Mutex<Option<rusqlite::InterruptHandle>>,
+    /// How far ahead the statement fetches rows. Only set for Postgres statements that return rows.
+    fetch: Option<FetchBudget>,
+    /// Bumped whenever a rewrite of the pages starts, so that one still in progress knows it was superseded, see
+    /// [`StatementManager::start_rewrite`]
+    rewrites: AtomicUsize,
+    /// Set for results that are views of another query's rows, whose pages are served from that query's
+    view: Option<RowView>,
+    /// Kept when the next query is submitted, see [`StatementManager::pin_result`]
+    pinned: AtomicBool,
 
     /// If set, the UI can now render the results of this query,
     /// even if it's still on-going (e.g. we already have enough data to render the first page)
     renderable: Condvar,
 }
 
+/// The rows of another query that make up a result, e.g. those a filter kept, see
+/// [`StatementManager::register_row_view`]
+struct RowView {
+    source: QueryId,
+    /// The page of the source and the row within it, in order
+    rows: Vec<(usize, usize)>,
+    /// How many times the source's pages were rewritten when the view was made. The offsets are stale once that
+    /// changes.
+    source_rewrites: usize,
+}
+
+impl ExecState {
+    fn statement_info(&self) -> Option<StatementInfo> {
+        let spilled = self.pages.read().expect("RwLock poisoned").is_spilled();
+        let (first_row_ms, elapsed_ms) = self.marks.lock().unwrap().progress(Instant::now());
+        let fetched = self.fetched.lock().unwrap();
+        let metrics = StatementMetrics {
+            first_row_ms,
+            elapsed_ms,
+            rows_fetched: fetched.rows,
+            bytes_fetched: fetched.bytes,
+            command_tag: fetched.command_tag.clone(),
+        };
+        let messages = self.messages.lock().unwrap().clone();
+
+        self.statement.clone().map(|statement| StatementInfo {
+            spilled,
+            metrics,
+            messages,
+            ..statement
+        })
+    }
+}
+
+/// What a statement's results amount to so far, see [`StatementMetrics`]
+#[derive(Debug, Default)]
+struct Fetched {
+    rows: usize,
+    bytes: usize,
+    command_tag: Option<String>,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+/// Monotonic timestamps taken at the boundaries of a statement's phases, see [`TimingBreakdown`]
+#[derive(Debug, Clone, Copy)]
+struct PhaseMarks {
+    submitted: Instant,
+    parsed: Instant,
+    started: Option<Instant>,
+    first_page: Option<Instant>,
+    finished: Option<Instant>,
+    serialize: Duration,
+}
+
+impl PhaseMarks {
+    fn new(submitted: Instant, parsed: Instant) -> Self {
+        Self {
+            submitted,
+            parsed,
+            started: None,
+            first_page: None,
+            finished: None,
+            serialize: Duration::ZERO,
+        }
+    }
+
+    /// Time to the first page and time spent executing, as of `now`, see [`StatementMetrics`]
+    fn progress(&self, now: Instant) -> (Option<f64>, Option<f64>) {
+        let Some(started) = self.started else {
+            return (None, None);
+        };
+        let since_start = |to: Instant| millis(to.saturating_duration_since(started));
+
+        (
+            self.first_page.map(since_start),
+            Some(since_start(self.finished.unwrap_or(now))),
+        )
+    }
+
+    /// None until the statement is over
+    fn breakdown(&self) -> Option<TimingBreakdown> {
+        let between = |from: Instant, to: Instant| millis(to.saturating_duration_since(from));
+
+        let finished = self.finished?;
+        let started = self.started.unwrap_or(self.parsed);
+        let first_page = self.first_page.unwrap_or(finished);
+
+        Some(TimingBreakdown {
+            parse_ms: between(self.submitted, self.parsed),
+            queue_ms: between(self.parsed, started),
+            execute_ms: between(started, first_page),
+            fetch_ms: between(first_page, finished),
+            serialize_ms: millis(self.serialize),
+            total_ms: between(self.submitted, finished),
+        })
+    }
+}
+
 /// Executes and keeps track of the execution of queries.
 pub struct StatementManager {
     queries: DashMap<QueryId, Arc<ExecState>>,
-    /// Handles for tasks spawned by the current batch of queries
-    task_handles: Mutex<Vec<JoinHandle<()>>>,
+    /// Ids aren't reused, so that results pinned by an earlier submission can't be mistaken for newer ones
+    next_id: AtomicUsize,
+    /// Id of the first statement of the latest submission. Results from before it only remain if pinned.
+    run_start: AtomicUsize,
+    /// Tasks spawned by the current batch of queries
+    workers: Mutex<Vec<Worker>>,
+    /// Reports new pages, see [`StatementManager::subscribe`]
+    page_sender: PageSender,
+    /// Where results spill to disk once they're over `spill_threshold` bytes, see
+    /// [`result_cache`](super::result_cache)
+    spill_dir: PathBuf,
+    spill_threshold: RwLock<Option<usize>>,
 }
 
 impl std::fmt::Debug for StatementManager {
@@ -53,37 +225,109 @@ impl std::fmt::Debug for StatementManager {
 #[allow(clippy::new_without_default)]
 impl StatementManager {
     pub fn new() -> Self {
+        Self::with_spill_dir(std::env::temp_dir().join(SPILL_DIR))
+    }
+
+    pub fn with_spill_dir(spill_dir: PathBuf) -> Self {
         Self {
             queries: DashMap::new(),
-            task_handles: Mutex::new(Vec::new()),
+            next_id: AtomicUsize::new(0),
+            run_start: AtomicUsize::new(0),
+            workers: Mutex::new(Vec::new()),
+            page_sender: Default::default(),
+            spill_dir,
+            spill_threshold: RwLock::new(None),
         }
     }
 
-    fn stop_workers(&self) {
-        let mut handles = self.task_handles.lock().unwrap();
-        for handle in handles.drain(..) {
-            handle.abort();
+    /// Applies to the statements submitted from now on. None keeps their results in memory.
+    pub fn set_spill_threshold(&self, threshold_bytes: Option<usize>) {
+        *self.spill_threshold.write().expect("RwLock poisoned") = threshold_bytes;
+    }
+
+    /// Reports new pages of results as soon as they can be fetched, so that results can be read while they stream.
+    /// Pages that come in quick succession are reported together, see [`PAGE_EVENT_INTERVAL`].
+    pub fn subscribe(&self) -> UnboundedReceiver<PageAvailable> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.page_sender.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Stops the statements of the current batch. Postgres statements fetching rows are cancelled rather than
+    /// aborted: one paused at its high-water mark holds its session (and the transaction it's in) until it's
+    /// cancelled on the server, which its executor does as it ends. Resolves once they have.
+    fn stop_workers(&self) -> Released {
+        let mut releasing = Vec::new();
+        for worker in self.workers.lock().unwrap().drain(..) {
+            worker.receiver.abort();
+            match &worker.exec_state.fetch {
+                Some(fetch) if !worker.executor.is_finished() => {
+                    // Those yet to start mustn't once the previous statement is over
+                    worker.exec_state.cancelled.store(true, Ordering::Relaxed);
+                    fetch.cancel();
+                    releasing.push(worker.executor);
+                }
+                _ => worker.executor.abort(),
+            }
         }
+
+        future::join_all(releasing).map(|_| ()).boxed().shared()
     }
 
-    /// Submits a new query (possibly containing multiple statements) for execution
-    pub fn submit_query(&self, client: RuntimeClient, query: &str) -> Result<Vec<QueryId>, Error> {
-        self.stop_workers();
-        self.queries.clear();
+    /// Submits a new query (possibly containing multiple statements) for execution, dropping the results of the
+    /// previous one unless they were pinned.
+    ///
+    /// `options` apply to each statement, see [`RunOptions`].
+    pub fn submit_query(
+        &self,
+        connection_id: Uuid,
+        client: RuntimeClient,
+        query: &str,
+        options: &RunOptions,
+    ) -> Result<Vec<QueryId>, Error> {
+        let submitted = Instant::now();
+        let released = self.stop_workers();
+        self.queries
+            .retain(|_, exec_state| exec_state.pinned.load(Ordering::Relaxed));
 
-        let parse_statements = match &client {
-            RuntimeClient::Postgres { .. } => postgres::parser::parse_statements,
-            RuntimeClient::SQLite { .. } => sqlite::parser::parse_statements,
-        };
+        // MySQL sessions are a single connection, which statements can only take turns on
+        let sequenced = options.savepoints || matches!(client, RuntimeClient::MySQL { .. });
 
-        let statements = parse_statements(query)?;
+        let statements = client.parse_statements(query)?;
+        let parsed = Instant::now();
+        let first_id = self.next_id.fetch_add(statements.len(), Ordering::Relaxed);
+        self.run_start.store(first_id, Ordering::Relaxed);
         let mut query_ids = Vec::with_capacity(statements.len());
-        let mut handles = self.task_handles.lock().unwrap();
+        let mut workers = self.workers.lock().unwrap();
+        let statement_count = statements.len();
+        // Statements in savepoints have to run one after the other too, or their savepoints would interleave
+        let mut previous = None;
 
-        for (idx, statement) in statements.into_iter().enumerate() {
-            let new_handles = self.create_worker(idx as QueryId, client.clone(), statement);
-            handles.extend(new_handles);
-            query_ids.push(idx);
+        for (idx, mut statement) in statements.into_iter().enumerate() {
+            if let Some(tag) = &options.tag {
+                statement.statement = query_tags::tag_statement(&statement.statement, tag);
+            }
+            statement.params = options.params.clone();
+            let marks = PhaseMarks::new(submitted, parsed);
+            let (done, next) = oneshot::channel();
+            let after = std::mem::replace(&mut previous, sequenced.then_some(next));
+
+            let query_id = first_id + idx;
+            let worker = self.create_worker(
+                (connection_id, query_id, idx),
+                client.clone(),
+                statement,
+                options,
+                marks,
+                Sequencing {
+                    released: released.clone(),
+                    after,
+                    done,
+                    last: idx + 1 == statement_count,
+                },
+            );
+            workers.push(worker);
+            query_ids.push(query_id);
         }
 
         Ok(query_ids)
@@ -100,24 +344,100 @@ impl StatementManager {
         exec_state.renderable.wait().await;
 
         let returns_values = exec_state.returns_values;
+        // Taken first, since the fields below hold their locks until the snapshot is built
+        let statement = exec_state.statement_info();
 
         let info = QuerySnapshot {
             returns_values,
             status: exec_state.status.load(Ordering::Relaxed).into(),
             first_page: if returns_values {
-                let pages = exec_state.pages.read().expect("RwLock poisoned");
-                pages.first().cloned()
+                exec_state.pages.read().expect("RwLock poisoned").get(0)?
             } else {
                 None
             },
             affected_rows: *exec_state.rows_affected.read().expect("RwLock poisoned"),
             error: exec_state.error.read().expect("RwLock poisoned").clone(),
             columns: exec_state.columns.read().expect("RwLock poisoned").clone(),
+            ceilings_hit: exec_state
+                .ceilings_hit
+                .read()
+                .expect("RwLock poisoned")
+                .clone(),
+            column_lineage: exec_state
+                .column_lineage
+                .read()
+                .expect("RwLock poisoned")
+                .clone(),
+            timings: exec_state.marks.lock().unwrap().breakdown(),
+            savepoint: *exec_state.savepoint.read().expect("RwLock poisoned"),
+            statement,
         };
 
         Ok(info)
     }
 
+    /// Cancels the statements of a connection that aren't over yet: those that haven't started won't, and those
+    /// running on SQLite are interrupted. Postgres and MySQL statements that are running have to be cancelled on the
+    /// server, see [`PostgresPool::cancel_session_query`](super::postgres::pool::PostgresPool::cancel_session_query)
+    /// and [`MySqlClient::cancel_session_query`](super::mysql::client::MySqlClient::cancel_session_query).
+    ///
+    /// Returns the statements cancelled.
+    pub fn cancel_connection_queries(&self, connection_id: Uuid) -> Vec<QueryId> {
+        let mut cancelled: Vec<QueryId> = self
+            .queries
+            .iter()
+            .filter(|entry| {
+                let exec_state = entry.value();
+                exec_state.connection_id == Some(connection_id)
+                    && !is_over(exec_state.status.load(Ordering::Relaxed).into())
+                    && !exec_state.cancelled.swap(true, Ordering::Relaxed)
+            })
+            .map(|entry| {
+                if let Some(interrupt) = entry.value().interrupt.lock().unwrap().as_ref() {
+                    interrupt.interrupt();
+                }
+                if let Some(fetch) = &entry.value().fetch {
+                    fetch.cancel();
+                }
+                *entry.key()
+            })
+            .collect();
+        cancelled.sort_unstable();
+        cancelled
+    }
+
+    /// Lets a statement that stopped fetching rows at its high-water mark fetch as many again past the pages it
+    /// has, see [`FetchBudget`]. Does nothing for statements that fetch all their rows.
+    pub fn request_more_rows(&self, query_id: QueryId) -> Result<(), Error> {
+        let exec_state = self.get(query_id)?;
+        if let Some(fetch) = &exec_state.fetch {
+            fetch.request_more(exec_state.pages.read().expect("RwLock poisoned").len());
+        }
+        Ok(())
+    }
+
+    pub fn is_query_over(&self, query_id: QueryId) -> Result<bool, Error> {
+        Ok(is_over(self.get_query_status(query_id)?))
+    }
+
+    /// Which statement of the submitted script a query is, see [`StatementInfo`]. None for derived results.
+    pub fn get_statement_info(&self, query_id: QueryId) -> Result<Option<StatementInfo>, Error> {
+        Ok(self.get(query_id)?.statement_info())
+    }
+
+    /// Where the time of a query went, or None if it's still running
+    pub fn get_timing_breakdown(
+        &self,
+        query_id: QueryId,
+    ) -> Result<Option<TimingBreakdown>, Error> {
+        Ok(self.get(query_id)?.marks.lock().unwrap().breakdown())
+    }
+
+    /// The connection the query ran on, or None for derived results
+    pub fn get_connection_id(&self, query_id: QueryId) -> Result<Option<Uuid>, Error> {
+        Ok(self.get(query_id)?.connection_id)
+    }
+
     pub fn get_columns(&self, query_id: QueryId) -> Result<Option<Box<RawValue>>, Error> {
         Ok(self
             .get(query_id)?
@@ -127,11 +447,93 @@ impl StatementManager {
             .clone())
     }
 
+    /// The types of the query's columns, in the same order as its columns. Empty if unknown.
+    pub fn get_column_types(&self, query_id: QueryId) -> Result<Vec<Option<String>>, Error> {
+        Ok(self
+            .get(query_id)?
+            .column_types
+            .read()
+            .expect("RwLock poisoned")
+            .clone())
+    }
+
+    /// Whether the query's result is missing some rows, see [`StatementManager::register_derived_result`]
+    pub fn is_truncated(&self, query_id: QueryId) -> Result<bool, Error> {
+        Ok(self.get(query_id)?.truncated)
+    }
+
     /// Fetches a page of results for a given query.
     pub fn fetch_page(&self, query_id: QueryId, page_idx: usize) -> Result<Option<Page>, Error> {
         let exec_state = self.get(query_id)?;
-        let pages = exec_state.pages.read().expect("RwLock poisoned");
-        Ok(pages.get(page_idx).cloned())
+        self.page_of(&exec_state, page_idx)
+    }
+
+    /// Every column of a single row, in order, along with what's known about the column.
+    /// `row_index` counts from the first row of the result, across pages.
+    pub fn get_row_detail(
+        &self,
+        query_id: QueryId,
+        row_index: usize,
+    ) -> Result<Vec<RowDetailField>, Error> {
+        let exec_state = self.get(query_id)?;
+
+        let columns: Vec<String> = match &*exec_state.columns.read().expect("RwLock poisoned") {
+            Some(columns) => serde_json::from_str(columns.get())?,
+            None => Vec::new(),
+        };
+
+        // The page of the row is found without reading the pages before it
+        let (location, row_count) = match &exec_state.view {
+            Some(view) => (
+                (row_index < view.rows.len())
+                    .then_some((row_index / VIEW_PAGE_SIZE, row_index % VIEW_PAGE_SIZE)),
+                view.rows.len(),
+            ),
+            None => {
+                let pages = exec_state.pages.read().expect("RwLock poisoned");
+                (pages.locate_row(row_index), pages.row_count())
+            }
+        };
+        let out_of_range = Error::RowOutOfRange {
+            row_index,
+            row_count,
+        };
+        let Some((page_idx, row_in_page)) = location else {
+            return Err(out_of_range);
+        };
+        let Some(page) = self.page_of(&exec_state, page_idx)? else {
+            return Err(out_of_range);
+        };
+        let mut rows: Vec<Vec<serde_json::Value>> = serde_json::from_str(page.get())?;
+        if row_in_page >= rows.len() {
+            return Err(out_of_range);
+        }
+        let row = rows.swap_remove(row_in_page);
+
+        let column_types = exec_state.column_types.read().expect("RwLock poisoned");
+        let lineage = exec_state.column_lineage.read().expect("RwLock poisoned");
+
+        let fields = columns
+            .into_iter()
+            .zip(row)
+            .enumerate()
+            .map(|(idx, (column, value))| {
+                let origin_table = match lineage.as_ref().and_then(|lineage| lineage.get(idx)) {
+                    Some(ColumnLineage::Column(source)) => source.table.clone(),
+                    _ => None,
+                };
+
+                RowDetailField {
+                    column,
+                    column_type: column_types.get(idx).cloned().flatten(),
+                    value,
+                    truncated: false,
+                    origin_table,
+                }
+            })
+            .collect();
+
+        Ok(fields)
     }
 
     pub fn get_query_status(&self, query_id: QueryId) -> Result<QueryStatus, Error> {
@@ -142,96 +544,707 @@ impl StatementManager {
 
     pub fn get_page_count(&self, query_id: QueryId) -> Result<usize, Error> {
         let exec_state = self.get(query_id)?;
-        let page_count = exec_state.pages.read().expect("RwLock poisoned").len();
-        Ok(page_count)
+        Ok(self.page_count_of(&exec_state))
+    }
+
+    /// Starts rewriting the pages of a completed query, superseding any rewrite of it still in progress
+    pub fn start_rewrite(&self, query_id: QueryId) -> Result<PageRewrite, Error> {
+        let exec_state = self.get(query_id)?;
+        if QueryStatus::from(exec_state.status.load(Ordering::Relaxed)) != QueryStatus::Completed {
+            return Err(anyhow::anyhow!("Query {query_id} has to complete first").into());
+        }
+
+        let generation = exec_state.rewrites.fetch_add(1, Ordering::AcqRel) + 1;
+        Ok(PageRewrite {
+            query_id,
+            exec_state,
+            generation,
+        })
+    }
+
+    /// Replaces the pages of the query with `pages`, which spill to disk like those of a running statement. Fails
+    /// if the rewrite was superseded, leaving the pages of the newer one.
+    pub fn finish_rewrite(&self, rewrite: PageRewrite, pages: Vec<Page>) -> Result<(), Error> {
+        let mut store = PageStore::new(rewrite.query_id, self.spill_config());
+        for page in pages {
+            let rows = result_cache::count_rows(&page)?;
+            store.push(page, rows);
+        }
+
+        let mut current = rewrite.exec_state.pages.write().expect("RwLock poisoned");
+        rewrite.check_superseded()?;
+        *current = store;
+        Ok(())
+    }
+
+    /// Registers a result computed from other queries' results (e.g. a grouping) as an already-completed query,
+    /// so it can be paged through and exported like any other.
+    pub fn register_derived_result(
+        &self,
+        columns: Box<RawValue>,
+        pages: Vec<Page>,
+        truncated: bool,
+    ) -> Result<QueryId, Error> {
+        Ok(self.insert_derived(ExecState {
+            pages: RwLock::new(PageStore::from_pages(pages)?),
+            ..derived_state(columns, truncated)
+        }))
+    }
+
+    /// Registers some of the rows of a query as a result of their own, without copying them: its pages are made
+    /// from the source's pages whenever they're fetched. `rows` holds the page and the row within it of each row
+    /// of the view, and `source_rewrites` is what [`StatementManager::get_rewrite_count`] returned before they
+    /// were read from the source.
+    pub fn register_row_view(
+        &self,
+        source: QueryId,
+        rows: Vec<(usize, usize)>,
+        source_rewrites: usize,
+    ) -> Result<QueryId, Error> {
+        let source_state = self.get(source)?;
+        let columns = source_state
+            .columns
+            .read()
+            .expect("RwLock poisoned")
+            .clone()
+            .with_context(|| format!("Query {source} has no columns"))?;
+
+        let exec_state = ExecState {
+            column_types: RwLock::new(
+                source_state
+                    .column_types
+                    .read()
+                    .expect("RwLock poisoned")
+                    .clone(),
+            ),
+            column_lineage: RwLock::new(
+                source_state
+                    .column_lineage
+                    .read()
+                    .expect("RwLock poisoned")
+                    .clone(),
+            ),
+            view: Some(RowView {
+                source,
+                rows,
+                source_rewrites,
+            }),
+            ..derived_state(columns, source_state.truncated)
+        };
+
+        Ok(self.insert_derived(exec_state))
+    }
+
+    /// How many times the pages of the query were rewritten, see [`StatementManager::register_row_view`]
+    pub fn get_rewrite_count(&self, query_id: QueryId) -> Result<usize, Error> {
+        Ok(self.get(query_id)?.rewrites.load(Ordering::Acquire))
+    }
+
+    /// Drops a derived result (e.g. a filtered view), leaving the queries it was computed from as they are
+    pub fn remove_derived_result(&self, query_id: QueryId) -> Result<(), Error> {
+        if self.get(query_id)?.connection_id.is_some() {
+            return Err(anyhow::anyhow!("Query {query_id} isn't a derived result").into());
+        }
+        self.queries.remove(&query_id);
+        Ok(())
+    }
+
+    /// Keeps a result when the next query is submitted, e.g. to join it with that query's. Pinning a view pins
+    /// the result it was made from too.
+    pub fn pin_result(&self, query_id: QueryId) -> Result<(), Error> {
+        let exec_state = self.get(query_id)?;
+        if !is_over(exec_state.status.load(Ordering::Relaxed).into()) {
+            return Err(
+                anyhow::anyhow!("Query {query_id} has to finish before it can be pinned").into(),
+            );
+        }
+
+        if let Some(view) = &exec_state.view {
+            self.pin_result(view.source)?;
+        }
+        exec_state.pinned.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Undoes [`StatementManager::pin_result`]. Results of an earlier submission are dropped right away, those of
+    /// the latest one once the next query is submitted.
+    pub fn unpin_result(&self, query_id: QueryId) -> Result<(), Error> {
+        let exec_state = self.get(query_id)?;
+        let pinned_view = self.queries.iter().find_map(|entry| {
+            let viewed = entry
+                .view
+                .as_ref()
+                .is_some_and(|view| view.source == query_id);
+            (viewed && entry.pinned.load(Ordering::Relaxed)).then_some(*entry.key())
+        });
+        if let Some(view_id) = pinned_view {
+            return Err(anyhow::anyhow!(
+                "Query {query_id} is still needed by query {view_id}, which is pinned"
+            )
+            .into());
+        }
+
+        exec_state.pinned.store(false, Ordering::Relaxed);
+        if query_id < self.run_start.load(Ordering::Relaxed) {
+            self.queries.remove(&query_id);
+        }
+        Ok(())
+    }
+
+    fn insert_derived(&self, exec_state: ExecState) -> QueryId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.queries.insert(id, Arc::new(exec_state));
+
+        id
+    }
+
+    fn page_count_of(&self, exec_state: &ExecState) -> usize {
+        match &exec_state.view {
+            Some(view) => view.rows.len().div_ceil(VIEW_PAGE_SIZE),
+            None => exec_state.pages.read().expect("RwLock poisoned").len(),
+        }
+    }
+
+    fn page_of(&self, exec_state: &ExecState, page_idx: usize) -> Result<Option<Page>, Error> {
+        match &exec_state.view {
+            Some(view) => self.view_page(view, page_idx),
+            None => exec_state
+                .pages
+                .read()
+                .expect("RwLock poisoned")
+                .get(page_idx),
+        }
+    }
+
+    /// Puts together a page of a view from the raw rows of its source, which are copied as they are
+    fn view_page(&self, view: &RowView, page_idx: usize) -> Result<Option<Page>, Error> {
+        let source = self.get(view.source)?;
+        if source.rewrites.load(Ordering::Acquire) != view.source_rewrites {
+            return Err(anyhow::anyhow!(
+                "The results of query {} changed since they were filtered, e.g. by sorting them",
+                view.source
+            )
+            .into());
+        }
+
+        let start = page_idx * VIEW_PAGE_SIZE;
+        let Some(rows) = view.rows.get(start..).filter(|rows| !rows.is_empty()) else {
+            return Ok(None);
+        };
+        let rows = &rows[..rows.len().min(VIEW_PAGE_SIZE)];
+
+        let mut json = String::from("[");
+        // The rows are in order, so each page of the source is parsed once
+        let mut loaded: Option<(usize, Vec<Box<RawValue>>)> = None;
+        for (idx, &(page, row)) in rows.iter().enumerate() {
+            if loaded
+                .as_ref()
+                .is_none_or(|(loaded_page, _)| *loaded_page != page)
+            {
+                let source_page = self
+                    .page_of(&source, page)?
+                    .with_context(|| format!("Query {} has no page {page}", view.source))?;
+                loaded = Some((page, serde_json::from_str(source_page.get())?));
+            }
+            let (_, source_rows) = loaded.as_ref().expect("the page was just loaded");
+            let source_row = source_rows.get(row).with_context(|| {
+                format!("Page {page} of query {} has no row {row}", view.source)
+            })?;
+
+            if idx > 0 {
+                json.push(',');
+            }
+            json.push_str(source_row.get());
+        }
+        json.push(']');
+
+        Ok(Some(RawValue::from_string(json)?))
+    }
+}
+
+/// The state of a derived result, already completed and renderable, with no pages
+fn derived_state(columns: Box<RawValue>, truncated: bool) -> ExecState {
+    let renderable = Condvar::new();
+    renderable.set();
+
+    let now = Instant::now();
+    let marks = PhaseMarks {
+        finished: Some(now),
+        ..PhaseMarks::new(now, now)
+    };
+
+    ExecState {
+        connection_id: None,
+        status: AtomicU8::new(QueryStatus::Completed as u8),
+        pages: RwLock::new(PageStore::default()),
+        error: RwLock::new(None),
+        columns: RwLock::new(Some(columns)),
+        column_types: RwLock::new(Vec::new()),
+        returns_values: true,
+        rows_affected: RwLock::new(None),
+        ceilings_hit: RwLock::new(Vec::new()),
+        column_lineage: RwLock::new(None),
+        savepoint: RwLock::new(None),
+        statement: None,
+        truncated,
+        marks: Mutex::new(marks),
+        fetched: Mutex::default(),
+        messages: Mutex::default(),
+        cancelled: AtomicBool::new(false),
+        interrupt: Mutex::new(None),
+        fetch: None,
+        rewrites: AtomicUsize::new(0),
+        view: None,
+        pinned: AtomicBool::new(false),
+        renderable,
+    }
+}
+
+/// A rewrite of the pages of a completed query in progress, e.g. to sort its rows. Superseded once another one of
+/// the same query starts.
+pub struct PageRewrite {
+    query_id: QueryId,
+    exec_state: Arc<ExecState>,
+    generation: usize,
+}
+
+impl PageRewrite {
+    pub fn is_superseded(&self) -> bool {
+        self.exec_state.rewrites.load(Ordering::Acquire) != self.generation
+    }
+
+    /// Fails if another rewrite of the query started since this one did
+    pub fn check_superseded(&self) -> Result<(), Error> {
+        if self.is_superseded() {
+            return Err(anyhow::anyhow!(
+                "Rewriting the results of query {} was superseded by another rewrite",
+                self.query_id
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Coalesces the new pages of a statement into at most one event per [`PAGE_EVENT_INTERVAL`]
+#[derive(Debug)]
+struct PageNotifier {
+    query_id: QueryId,
+    /// Pages reported so far
+    reported: usize,
+    /// Pages available so far, some of which may be yet to be reported
+    available: usize,
+    last_sent: Option<Instant>,
+}
+
+impl PageNotifier {
+    fn new(query_id: QueryId) -> Self {
+        Self {
+            query_id,
+            reported: 0,
+            available: 0,
+            last_sent: None,
+        }
+    }
+
+    /// Records that `page_count` pages are available. Returns the event to send, unless one was sent too recently,
+    /// in which case the new pages wait for [`PageNotifier::deadline`].
+    fn pages_added(&mut self, page_count: usize, now: Instant) -> Option<PageAvailable> {
+        self.available = page_count;
+        let throttled = self.last_sent.is_some_and(|last_sent| {
+            now.saturating_duration_since(last_sent) < PAGE_EVENT_INTERVAL
+        });
+        if throttled {
+            return None;
+        }
+        self.flush(now)
+    }
+
+    /// When the pending pages are due to be reported, if there are any
+    fn deadline(&self) -> Option<Instant> {
+        let last_sent = self.last_sent?;
+        (self.available > self.reported).then(|| last_sent + PAGE_EVENT_INTERVAL)
+    }
+
+    /// The event reporting the pending pages, regardless of when the last one was sent
+    fn flush(&mut self, now: Instant) -> Option<PageAvailable> {
+        if self.available <= self.reported {
+            return None;
+        }
+
+        let event = PageAvailable {
+            query_id: self.query_id,
+            first_new_page: self.reported,
+            page_count: self.available,
+        };
+        self.reported = self.available;
+        self.last_sent = Some(now);
+        Some(event)
+    }
+}
+
+fn send_page_event(page_sender: &PageSender, event: Option<PageAvailable>) {
+    if let (Some(sender), Some(event)) = (page_sender.lock().unwrap().as_ref(), event) {
+        let _ = sender.send(event);
     }
 }
 
+fn is_over(status: QueryStatus) -> bool {
+    matches!(status, QueryStatus::Completed | QueryStatus::Error)
+}
+
+/// Ends a statement that was cancelled before it started
+fn send_cancelled(sender: &ExecSender) {
+    let _ = sender.send(QueryExecEvent::Finished {
+        elapsed_ms: 0,
+        affected_rows: 0,
+        error: Some("Query cancelled".to_owned()),
+    });
+}
+
+/// Resolves once the statements of the previous batch gave their Postgres session back, see
+/// [`StatementManager::stop_workers`]
+type Released = Shared<BoxFuture<'static, ()>>;
+
+/// Orders a statement's execution after the previous one's
+struct Sequencing {
+    /// Postgres statements wait on it before they start
+    released: Released,
+    /// Resolves once the previous statement is over, None to start right away
+    after: Option<oneshot::Receiver<()>>,
+    /// Dropped once this statement is over
+    done: oneshot::Sender<()>,
+    /// Whether the statement is the last of its batch. The others fetch all their rows, since the ones after
+    /// them wait behind them on the same session.
+    last: bool,
+}
+
+/// The tasks of a statement of the current batch
+struct Worker {
+    exec_state: Arc<ExecState>,
+    executor: JoinHandle<()>,
+    receiver: JoinHandle<()>,
+}
+
 /// Impl block for internal methods
 impl StatementManager {
     fn create_worker(
         &self,
-        id: QueryId,
+        (connection_id, id, ordinal): (Uuid, QueryId, usize),
         client: RuntimeClient,
         stmt: ParsedStatement,
-    ) -> [JoinHandle<()>; 2] {
+        options: &RunOptions,
+        marks: PhaseMarks,
+        sequencing: Sequencing,
+    ) -> Worker {
         let exec_storage = ExecState {
+            connection_id: Some(connection_id),
             status: AtomicU8::new(QueryStatus::Pending as u8),
-            pages: RwLock::new(vec![]),
+            pages: RwLock::new(PageStore::new(id, self.spill_config())),
             error: RwLock::new(None),
             columns: RwLock::new(None),
+            column_types: RwLock::new(Vec::new()),
             returns_values: stmt.returns_values,
             rows_affected: RwLock::new(None),
+            ceilings_hit: RwLock::new(Vec::new()),
+            column_lineage: RwLock::new(stmt.lineage.clone()),
+            savepoint: RwLock::new(None),
+            statement: Some(StatementInfo {
+                query_id: id,
+                ordinal,
+                start: stmt.span.start,
+                end: stmt.span.end,
+                kind: stmt.kind,
+                spilled: false,
+                metrics: StatementMetrics::default(),
+                messages: Vec::new(),
+            }),
+            truncated: false,
+            marks: Mutex::new(marks),
+            fetched: Mutex::default(),
+            messages: Mutex::default(),
+            cancelled: AtomicBool::new(false),
+            interrupt: Mutex::new(None),
+            fetch: (stmt.returns_values
+                && sequencing.last
+                && matches!(client, RuntimeClient::Postgres { .. }))
+            .then(|| {
+                FetchBudget::new(
+                    options
+                        .limits
+                        .fetch_ahead_pages
+                        .unwrap_or(DEFAULT_FETCH_AHEAD_PAGES),
+                )
+            }),
+            rewrites: AtomicUsize::new(0),
+            view: None,
+            pinned: AtomicBool::new(false),
             renderable: Condvar::new(),
         };
 
         let exec_storage = Arc::new(exec_storage);
         self.queries.insert(id, exec_storage.clone());
+        let worker_state = exec_storage.clone();
 
         let (sender, recv) = channel();
+        let page_sender = self.page_sender.clone();
+
+        let Sequencing {
+            released,
+            after,
+            done,
+            ..
+        } = sequencing;
+        let (limits, savepoints) = (options.limits, options.savepoints);
+        let exec_state = exec_storage.clone();
 
         let executor_handle = match client {
-            RuntimeClient::Postgres { client } => task::spawn(async move {
-                if let Err(err) = postgres::execute::execute_query(&client, stmt, &sender).await {
+            RuntimeClient::Postgres { pool } => task::spawn(async move {
+                let _done = done;
+                released.await;
+                let client = pool.session();
+                if let Some(after) = after {
+                    let _ = after.await;
+                }
+                if exec_state.cancelled.load(Ordering::Relaxed) {
+                    return send_cancelled(&sender);
+                }
+
+                let _ = sender.send(QueryExecEvent::Started(Instant::now()));
+                let _messages = pool.route_session_messages(&sender);
+                let fetch = exec_state.fetch.as_ref();
+                let result = if savepoints {
+                    postgres::execute::execute_query_in_savepoint(client, stmt, &sender, fetch)
+                        .await
+                } else {
+                    postgres::execute::execute_query(client, stmt, &sender, fetch).await
+                };
+                if let Err(err) = result {
                     log::error!("Error executing Postgres query: {}", err);
                 }
+                // Dropping the rows left doesn't stop the server from sending them
+                if fetch.is_some_and(FetchBudget::is_cancelled) {
+                    if let Err(err) = pool.cancel_session_query().await {
+                        log::error!("Failed to cancel a Postgres query on the server: {}", err);
+                    }
+                }
             }),
-            RuntimeClient::SQLite { connection } => task::spawn_blocking(move || {
-                let conn = connection.lock().unwrap();
-                if let Err(err) = sqlite::execute::execute_query(&conn, stmt, &sender) {
-                    log::error!("Error executing SQLite query: {}", err);
+            RuntimeClient::SQLite { connection, trace } => {
+                let execute = move || {
+                    let _done = done;
+                    let conn = connection.lock().unwrap();
+                    // Set before checking whether the statement was cancelled, so that cancelling it either
+                    // stops it from starting or interrupts it
+                    *exec_state.interrupt.lock().unwrap() = Some(conn.get_interrupt_handle());
+                    if exec_state.cancelled.load(Ordering::Relaxed) {
+                        return send_cancelled(&sender);
+                    }
+                    let _ = sender.send(QueryExecEvent::Started(Instant::now()));
+                    let result = if savepoints {
+                        sqlite::execute::execute_query_in_savepoint(
+                            &conn, stmt, &sender, limits, &trace,
+                        )
+                    } else {
+                        sqlite::execute::execute_query_with_limits(
+                            &conn, stmt, &sender, limits, &trace,
+                        )
+                    };
+                    exec_state.interrupt.lock().unwrap().take();
+                    if let Err(err) = result {
+                        log::error!("Error executing SQLite query: {}", err);
+                    }
+                };
+
+                match after {
+                    Some(after) => task::spawn(async move {
+                        let _ = after.await;
+                        let _ = task::spawn_blocking(execute).await;
+                    }),
+                    None => task::spawn_blocking(execute),
+                }
+            }
+            RuntimeClient::MySQL { client } => task::spawn(async move {
+                let _done = done;
+                if let Some(after) = after {
+                    let _ = after.await;
+                }
+                let mut session = client.session().lock().await;
+                if exec_state.cancelled.load(Ordering::Relaxed) {
+                    return send_cancelled(&sender);
+                }
+
+                let _ = sender.send(QueryExecEvent::Started(Instant::now()));
+                let execute = async {
+                    if savepoints {
+                        mysql::execute::execute_query_in_savepoint(&mut session, stmt, &sender)
+                            .await
+                    } else {
+                        mysql::execute::execute_query(&mut session, stmt, &sender).await
+                    }
+                };
+                let result = match limits.statement_cpu_budget_ms {
+                    Some(budget_ms) => {
+                        tokio::pin!(execute);
+                        tokio::select! {
+                            result = &mut execute => result,
+                            _ = tokio::time::sleep(Duration::from_millis(budget_ms)) => {
+                                // The server stops the statement, which then finishes with an error
+                                if let Err(err) = client.cancel_session_query().await {
+                                    log::error!("Failed to stop a MySQL query over its budget: {}", err);
+                                }
+                                let _ = sender.send(QueryExecEvent::CeilingHit(
+                                    ResourceCeiling::StatementCpuBudget,
+                                ));
+                                execute.await
+                            }
+                        }
+                    }
+                    None => execute.await,
+                };
+                if let Err(err) = result {
+                    log::error!("Error executing MySQL query: {}", err);
                 }
             }),
         };
 
         let receiver_handle = task::spawn(async move {
             let mut recv = recv;
+            let mut finished = None;
+            let mut notifier = PageNotifier::new(id);
 
             exec_storage
                 .status
                 .store(QueryStatus::Running as u8, Ordering::Relaxed);
 
-            while let Some(event) = recv.recv().await {
+            loop {
+                let event = match notifier.deadline() {
+                    Some(deadline) => {
+                        match tokio::time::timeout_at(deadline.into(), recv.recv()).await {
+                            Ok(event) => event,
+                            Err(_) => {
+                                send_page_event(&page_sender, notifier.flush(Instant::now()));
+                                continue;
+                            }
+                        }
+                    }
+                    None => recv.recv().await,
+                };
+                let Some(event) = event else {
+                    break;
+                };
+
                 match event {
-                    QueryExecEvent::TypesResolved { columns } => {
+                    QueryExecEvent::TypesResolved {
+                        columns,
+                        column_types,
+                    } => {
                         *exec_storage.columns.write().unwrap() = Some(columns);
+                        *exec_storage.column_types.write().unwrap() = column_types;
                     }
-                    QueryExecEvent::Page {
-                        page_amount: _,
-                        page,
-                    } => {
-                        exec_storage.pages.write().unwrap().push(page);
+                    QueryExecEvent::Started(at) => {
+                        exec_storage.marks.lock().unwrap().started = Some(at);
+                    }
+                    QueryExecEvent::Page { page_amount, page } => {
+                        {
+                            let mut fetched = exec_storage.fetched.lock().unwrap();
+                            fetched.rows += page_amount;
+                            fetched.bytes += page.get().len();
+                        }
+                        exec_storage
+                            .marks
+                            .lock()
+                            .unwrap()
+                            .first_page
+                            .get_or_insert_with(Instant::now);
+                        let page_count = {
+                            let mut pages = exec_storage.pages.write().unwrap();
+                            pages.push(page, page_amount);
+                            pages.len()
+                        };
+                        let _ = exec_storage.status.compare_exchange(
+                            QueryStatus::Running as u8,
+                            QueryStatus::Streaming as u8,
+                            Ordering::Relaxed,
+                            Ordering::Relaxed,
+                        );
                         exec_storage.renderable.set();
+
+                        send_page_event(
+                            &page_sender,
+                            notifier.pages_added(page_count, Instant::now()),
+                        );
+                    }
+                    QueryExecEvent::LineageResolved(lineage) => {
+                        *exec_storage.column_lineage.write().unwrap() = Some(lineage);
+                    }
+                    QueryExecEvent::CeilingHit(ceiling) => {
+                        exec_storage.ceilings_hit.write().unwrap().push(ceiling);
+                    }
+                    QueryExecEvent::Serialized(duration) => {
+                        exec_storage.marks.lock().unwrap().serialize = duration;
+                    }
+                    QueryExecEvent::Savepoint(outcome) => {
+                        *exec_storage.savepoint.write().unwrap() = Some(outcome);
+                    }
+                    QueryExecEvent::CommandTag(tag) => {
+                        exec_storage.fetched.lock().unwrap().command_tag = Some(tag);
+                    }
+                    QueryExecEvent::Message { severity, text } => {
+                        exec_storage
+                            .messages
+                            .lock()
+                            .unwrap()
+                            .push(ServerMessage { severity, text });
                     }
                     QueryExecEvent::Finished {
                         elapsed_ms: _,
                         affected_rows,
                         error,
                     } => {
-                        if let Some(err) = error {
-                            *exec_storage.error.write().unwrap() = Some(err);
-                            exec_storage
-                                .status
-                                .store(QueryStatus::Error as u8, Ordering::Relaxed);
-                        } else {
-                            exec_storage
-                                .status
-                                .store(QueryStatus::Completed as u8, Ordering::Relaxed);
-
-                            *exec_storage.rows_affected.write().unwrap() = Some(affected_rows);
-                        }
-
-                        exec_storage.renderable.set();
-
-                        // TODO(vini): fingerprint query here, and save it?
-
-                        break;
+                        exec_storage.marks.lock().unwrap().finished = Some(Instant::now());
+                        finished = Some((affected_rows, error));
+                        // So that the last pages don't wait for the interval to be over
+                        send_page_event(&page_sender, notifier.flush(Instant::now()));
                     }
                 }
             }
+            send_page_event(&page_sender, notifier.flush(Instant::now()));
+
+            // The statement is only over once its executor is, e.g. after it settled the statement's savepoint
+            let Some((affected_rows, error)) = finished else {
+                return;
+            };
+            if let Some(err) = error {
+                *exec_storage.error.write().unwrap() = Some(err);
+                exec_storage
+                    .status
+                    .store(QueryStatus::Error as u8, Ordering::Relaxed);
+            } else {
+                exec_storage
+                    .status
+                    .store(QueryStatus::Completed as u8, Ordering::Relaxed);
+
+                *exec_storage.rows_affected.write().unwrap() = Some(affected_rows);
+            }
+
+            exec_storage.renderable.set();
+
+            // TODO(vini): fingerprint query here, and save it?
         });
 
-        [executor_handle, receiver_handle]
+        Worker {
+            exec_state: worker_state,
+            executor: executor_handle,
+            receiver: receiver_handle,
+        }
+    }
+
+    fn spill_config(&self) -> Option<SpillConfig> {
+        let threshold_bytes = (*self.spill_threshold.read().expect("RwLock poisoned"))?;
+        Some(SpillConfig {
+            dir: self.spill_dir.clone(),
+            threshold_bytes,
+        })
     }
 
     fn get(&self, query_id: QueryId) -> Result<Arc<ExecState>, Error> {
@@ -245,13 +1258,60 @@ impl StatementManager {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::{Arc, Mutex};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
 
+    use mysql_async::prelude::Queryable;
     use serde_json::{json, value::RawValue};
+    use uuid::Uuid;
 
-    use crate::database::types::RuntimeClient;
+    use crate::{
+        database::{
+            mysql::test_db::TestDb,
+            postgres::pool::PostgresPool,
+            trace::StatementTraces,
+            types::{
+                QueryStatus, ResourceCeiling, ResourceLimits, RunOptions, RuntimeClient,
+                SavepointOutcome, TimingBreakdown,
+            },
+        },
+        Error,
+    };
+
+    use super::{PageNotifier, PhaseMarks, StatementManager, PAGE_EVENT_INTERVAL};
+
+    #[test]
+    fn breaks_down_timings() {
+        let submitted = Instant::now();
+        let at = |ms| submitted + Duration::from_millis(ms);
+
+        let mut marks = PhaseMarks::new(submitted, at(2));
+        marks.started = Some(at(5));
+        marks.first_page = Some(at(15));
+        assert_eq!(marks.breakdown(), None);
 
-    use super::StatementManager;
+        marks.serialize = Duration::from_millis(3);
+        marks.finished = Some(at(40));
+        assert_eq!(
+            marks.breakdown(),
+            Some(TimingBreakdown {
+                parse_ms: 2.0,
+                queue_ms: 3.0,
+                execute_ms: 10.0,
+                fetch_ms: 25.0,
+                serialize_ms: 3.0,
+                total_ms: 40.0,
+            })
+        );
+
+        // Statements without rows spend all of their time executing
+        marks.first_page = None;
+        let breakdown = marks.breakdown().unwrap();
+        assert_eq!((breakdown.execute_ms, breakdown.fetch_ms), (35.0, 0.0));
+    }
 
     #[tokio::test]
     async fn test_basic_functionality() {
@@ -271,8 +1331,11 @@ mod tests {
 
         let client = RuntimeClient::SQLite {
             connection: Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap())),
+            trace: Default::default(),
         };
-        let query_ids = stmt_manager.submit_query(client, query).unwrap();
+        let query_ids = stmt_manager
+            .submit_query(Uuid::nil(), client, query, &RunOptions::default())
+            .unwrap();
         assert_eq!(query_ids, vec![0]);
 
         let snapshot = stmt_manager
@@ -311,4 +1374,1065 @@ mod tests {
             "id,name,price\n1,\"apple\",0.99\n2,\"banana\",1.25\n3,\"cherry\",2.5\n"
         );
     }
+
+    /// The cells of the first worksheet of an xlsx file by reference, e.g. `A1`, as their type and value. Types are
+    /// `str`, `bool`, `num`, or `date` for numbers shown as dates.
+    fn read_xlsx(path: &std::path::Path) -> HashMap<String, (&'static str, String)> {
+        use std::io::Read;
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(path).unwrap()).unwrap();
+        let mut xml = String::new();
+        archive
+            .by_name("xl/worksheets/sheet1.xml")
+            .unwrap()
+            .read_to_string(&mut xml)
+            .unwrap();
+
+        let cell = regex::Regex::new(
+            r#"<c r="([A-Z]+\d+)"( s="\d+")?(?: t="(\w+)")?>(?:<v>([^<]*)</v>|<is><t[^>]*>([^<]*)</t></is>)</c>"#,
+        )
+        .unwrap();
+        cell.captures_iter(&xml)
+            .map(|captures| {
+                let kind = match (captures.get(3).map(|t| t.as_str()), captures.get(2)) {
+                    (Some("inlineStr"), _) => "str",
+                    (Some("b"), _) => "bool",
+                    (None, Some(_)) => "date",
+                    (None, None) => "num",
+                    (Some(other), _) => panic!("Unexpected cell type {other}"),
+                };
+                let value = captures.get(4).or(captures.get(5)).unwrap().as_str();
+                (captures[1].to_owned(), (kind, value.to_owned()))
+            })
+            .collect()
+    }
+
+    fn export_xlsx(
+        columns: &RawValue,
+        column_types: &[Option<String>],
+        page: &RawValue,
+    ) -> HashMap<String, (&'static str, String)> {
+        let mut exporter =
+            crate::database::export::XlsxExporter::new(columns.get(), column_types).unwrap();
+        exporter.write_page(page.get()).unwrap();
+
+        let path = std::env::temp_dir().join(format!("pgpad-{}.xlsx", uuid::Uuid::new_v4()));
+        exporter.save(&path).unwrap();
+        let cells = read_xlsx(&path);
+        std::fs::remove_file(&path).unwrap();
+        cells
+    }
+
+    fn cell(kind: &'static str, value: &str) -> Option<(&'static str, String)> {
+        Some((kind, value.to_owned()))
+    }
+
+    #[tokio::test]
+    async fn test_xlsx_exports() {
+        let query = r"
+        SELECT column1 AS id, column2 AS name, column3 AS created_at, column4 AS note
+        FROM (
+            VALUES
+                (1, 'apple', '2024-01-31 10:00:00', NULL),
+                (2, 'banana', '2024-02-01', printf('%.*c', 40000, 'x')),
+                (9007199254740993, 'cherry', 'not a date', 'true'));";
+        let (columns, page) = run_query(query).await;
+
+        // The types of expressions are unknown, so values are written as what they look like
+        let mut cells = export_xlsx(&columns, &[], &page);
+        for (cell_ref, name) in [
+            ("A1", "id"),
+            ("B1", "name"),
+            ("C1", "created_at"),
+            ("D1", "note"),
+        ] {
+            assert_eq!(cells.remove(cell_ref), cell("str", name));
+        }
+        assert_eq!(cells.remove("A2"), cell("num", "1"));
+        assert_eq!(cells.remove("B2"), cell("str", "apple"));
+        // Excel dates count days since 1900, and times are fractions of a day
+        let (kind, serial) = cells.remove("C2").unwrap();
+        assert_eq!(kind, "date");
+        assert!((serial.parse::<f64>().unwrap() - (45322.0 + 10.0 / 24.0)).abs() < 1e-9);
+        assert_eq!(cells.remove("A3"), cell("num", "2"));
+        assert_eq!(cells.remove("B3"), cell("str", "banana"));
+        assert_eq!(cells.remove("C3"), cell("date", "45323"));
+        // Truncated to Excel's limit
+        assert_eq!(cells.remove("D3"), cell("str", &"x".repeat(32_767)));
+        // Too big for an Excel number without losing digits
+        assert_eq!(cells.remove("A4"), cell("str", "9007199254740993"));
+        assert_eq!(cells.remove("B4"), cell("str", "cherry"));
+        assert_eq!(cells.remove("C4"), cell("str", "not a date"));
+        assert_eq!(cells.remove("D4"), cell("str", "true"));
+        // Nulls are left empty
+        assert!(cells.is_empty(), "{cells:?}");
+    }
+
+    #[tokio::test]
+    async fn xlsx_exports_follow_column_types() {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE items (zip VARCHAR(10), code TEXT, released DATE, active BOOLEAN);
+                INSERT INTO items VALUES
+                    ('02134', '2024-02-01', '2024-01-31', 1),
+                    ('10001', '12', 'soon', 0);",
+            )
+            .unwrap();
+        let client = RuntimeClient::SQLite {
+            connection: Arc::new(Mutex::new(connection)),
+            trace: Default::default(),
+        };
+        let stmt_manager = StatementManager::new();
+        stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                client,
+                "SELECT * FROM items ORDER BY zip",
+                &RunOptions::default(),
+            )
+            .unwrap();
+        let snapshot = stmt_manager
+            .fetch_initial_renderable_state(0)
+            .await
+            .unwrap();
+        let column_types = stmt_manager.get_column_types(0).unwrap();
+
+        let cells = export_xlsx(
+            &snapshot.columns.unwrap(),
+            &column_types,
+            &snapshot.first_page.unwrap(),
+        );
+        let row = |row: u32| {
+            ["A", "B", "C", "D"]
+                .map(|col| cells.get(&format!("{col}{row}")).cloned())
+                .to_vec()
+        };
+
+        // Text stays text even if it looks like a date or a number
+        assert_eq!(
+            row(2),
+            [
+                cell("str", "02134"),
+                cell("str", "2024-02-01"),
+                cell("date", "45322"),
+                cell("bool", "1"),
+            ]
+        );
+        assert_eq!(
+            row(3),
+            [
+                cell("str", "10001"),
+                cell("str", "12"),
+                cell("str", "soon"),
+                cell("bool", "0"),
+            ]
+        );
+
+        // Decimals come as text, and are numbers unless Excel can't hold all of their digits
+        let cells = export_xlsx(
+            &RawValue::from_string(r#"["price"]"#.to_owned()).unwrap(),
+            &[Some("numeric".to_owned())],
+            &RawValue::from_string(
+                r#"[["12.50"],["0.1000000000000000000000"],["12345678901234567890.25"]]"#
+                    .to_owned(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(cells.get("A2").cloned(), cell("num", "12.5"));
+        assert_eq!(cells.get("A3").cloned(), cell("num", "0.1"));
+        assert_eq!(
+            cells.get("A4").cloned(),
+            cell("str", "12345678901234567890.25")
+        );
+
+        // Dates Excel doesn't have stay text
+        let cells = export_xlsx(
+            &RawValue::from_string(r#"["born","seen_at"]"#.to_owned()).unwrap(),
+            &[Some("date".to_owned()), Some("timestamp".to_owned())],
+            &RawValue::from_string(
+                r#"[["1850-03-01","1850-03-01 10:00:00"],["2024-01-31","2024-01-31 00:00:00"]]"#
+                    .to_owned(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(cells.get("A2").cloned(), cell("str", "1850-03-01"));
+        assert_eq!(cells.get("B2").cloned(), cell("str", "1850-03-01 10:00:00"));
+        assert_eq!(cells.get("A3").cloned(), cell("date", "45322"));
+        assert_eq!(cells.get("B3").cloned(), cell("date", "45322"));
+
+        // Nor do untyped ones that look like dates
+        let cells = export_xlsx(
+            &RawValue::from_string(r#"["born"]"#.to_owned()).unwrap(),
+            &[],
+            &RawValue::from_string(r#"[["1850-03-01"]]"#.to_owned()).unwrap(),
+        );
+        assert_eq!(cells.get("A2").cloned(), cell("str", "1850-03-01"));
+    }
+
+    /// SQL NULL must always be JSON null, and empty or whitespace-only strings must be kept as-is,
+    /// regardless of the driver
+    #[tokio::test]
+    async fn nulls_and_empty_strings_are_consistent_across_drivers() {
+        const FIXTURE: &str = "
+            CREATE TABLE fixture (id INTEGER, text_col TEXT, num_col NUMERIC, date_col DATE);
+            INSERT INTO fixture VALUES
+                (1, NULL, NULL, NULL),
+                (2, '', 0, '2024-01-31'),
+                (3, '   ', 1.5, NULL);";
+        // Drivers render NUMERIC and DATE differently, so only check whether they were NULL
+        const QUERY: &str = "
+            SELECT
+                id,
+                text_col,
+                CASE WHEN num_col IS NULL THEN NULL ELSE 'set' END,
+                CASE WHEN date_col IS NULL THEN NULL ELSE 'set' END
+            FROM fixture
+            ORDER BY id";
+        let expected = json!([
+            [1, null, null, null],
+            [2, "", "set", "set"],
+            [3, "   ", "set", null]
+        ]);
+
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        connection.execute_batch(FIXTURE).unwrap();
+        let sqlite = RuntimeClient::SQLite {
+            connection: Arc::new(Mutex::new(connection)),
+            trace: Default::default(),
+        };
+
+        let db = pgtemp::PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::task::spawn(async move {
+            if let Err(e) = conn.await {
+                eprintln!("Connection error: {}", e);
+            }
+        });
+        client.batch_execute(FIXTURE).await.unwrap();
+        let postgres = RuntimeClient::Postgres {
+            pool: Arc::new(PostgresPool::new(client, vec![]).await.unwrap()),
+        };
+        let mut clients = vec![sqlite, postgres];
+
+        let mysql_db = TestDb::new().await;
+        if let Some(db) = &mysql_db {
+            db.conn().await.query_drop(FIXTURE).await.unwrap();
+            clients.push(RuntimeClient::MySQL {
+                client: Arc::new(db.client().await),
+            });
+        }
+
+        for client in clients {
+            let stmt_manager = StatementManager::new();
+            stmt_manager
+                .submit_query(Uuid::nil(), client, QUERY, &RunOptions::default())
+                .unwrap();
+            let snapshot = stmt_manager
+                .fetch_initial_renderable_state(0)
+                .await
+                .unwrap();
+
+            let page = snapshot.first_page.expect("first_page returned None");
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(page.get()).unwrap(),
+                expected
+            );
+        }
+        if let Some(db) = mysql_db {
+            db.close().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn row_detail_spans_pages() {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE items (id INTEGER, name TEXT);
+                WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 60)
+                INSERT INTO items SELECT i, 'item ' || i FROM n;",
+            )
+            .unwrap();
+        let client = RuntimeClient::SQLite {
+            connection: Arc::new(Mutex::new(connection)),
+            trace: Default::default(),
+        };
+
+        let stmt_manager = StatementManager::new();
+        stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                client,
+                "SELECT id, name, id * 2 AS double FROM items ORDER BY id",
+                &RunOptions::default(),
+            )
+            .unwrap();
+        while stmt_manager.get_query_status(0).unwrap() != QueryStatus::Completed {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(stmt_manager.get_page_count(0).unwrap(), 2);
+
+        let detail = stmt_manager.get_row_detail(0, 54).unwrap();
+        let columns: Vec<_> = detail.iter().map(|field| field.column.as_str()).collect();
+        assert_eq!(columns, ["id", "name", "double"]);
+        assert_eq!(detail[1].value, json!("item 55"));
+        assert_eq!(detail[1].column_type.as_deref(), Some("TEXT"));
+        assert_eq!(detail[1].origin_table.as_deref(), Some("items"));
+        assert_eq!(detail[2].origin_table, None);
+
+        assert!(matches!(
+            stmt_manager.get_row_detail(0, 60),
+            Err(Error::RowOutOfRange {
+                row_index: 60,
+                row_count: 60
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_pages_while_streaming() {
+        let stmt_manager = StatementManager::new();
+        let mut pages = stmt_manager.subscribe();
+
+        let client = RuntimeClient::SQLite {
+            connection: Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap())),
+            trace: Default::default(),
+        };
+        stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                client,
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 120)
+                SELECT i FROM n",
+                &RunOptions::default(),
+            )
+            .unwrap();
+
+        let mut reported = 0;
+        while reported < 3 {
+            let available = pages.recv().await.unwrap();
+            assert_eq!(available.query_id, 0);
+            assert_eq!(available.first_new_page, reported);
+            reported = available.page_count;
+            // Pages are readable as soon as they're reported, whether or not the query is over
+            assert!(stmt_manager.fetch_page(0, reported - 1).unwrap().is_some());
+            assert!(matches!(
+                stmt_manager.get_query_status(0).unwrap(),
+                QueryStatus::Streaming | QueryStatus::Completed
+            ));
+        }
+        assert_eq!(reported, 3);
+    }
+
+    #[test]
+    fn coalesces_page_events() {
+        let start = Instant::now();
+        let mut notifier = PageNotifier::new(7);
+        let mut sink = Vec::new();
+
+        // A page every 10µs for 100ms, with the pending ones sent whenever their deadline passes
+        for page_count in 1..=10_000 {
+            let now = start + Duration::from_micros(10 * page_count as u64);
+            if notifier.deadline().is_some_and(|deadline| deadline <= now) {
+                sink.extend(notifier.flush(now));
+            }
+            sink.extend(notifier.pages_added(page_count, now));
+        }
+        let end = start + Duration::from_micros(100_010);
+        assert!(notifier.deadline().is_some());
+        sink.extend(notifier.flush(end));
+        assert_eq!(notifier.flush(end), None);
+
+        // The first page right away, one event per 50ms after, and the rest once the statement finished
+        assert_eq!(sink.len(), 3);
+        assert_eq!((sink[0].first_new_page, sink[0].page_count), (0, 1));
+        for pair in sink.windows(2) {
+            assert_eq!(pair[1].first_new_page, pair[0].page_count);
+        }
+        assert_eq!(sink.last().unwrap().page_count, 10_000);
+        assert!(sink.iter().all(|event| event.query_id == 7));
+    }
+
+    #[tokio::test]
+    async fn page_events_stay_bounded_while_streaming() {
+        let stmt_manager = StatementManager::new();
+        let mut pages = stmt_manager.subscribe();
+
+        let client = RuntimeClient::SQLite {
+            connection: Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap())),
+            trace: Default::default(),
+        };
+        let started = Instant::now();
+        stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                client,
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100000)
+                SELECT i FROM n",
+                &RunOptions::default(),
+            )
+            .unwrap();
+
+        let mut events = 0;
+        let mut reported = 0;
+        while reported < 2000 {
+            let available = pages.recv().await.unwrap();
+            assert_eq!(available.first_new_page, reported);
+            reported = available.page_count;
+            events += 1;
+        }
+        assert_eq!(reported, 2000);
+
+        let max_events = started.elapsed().as_millis() / PAGE_EVENT_INTERVAL.as_millis() + 2;
+        assert!(
+            events as u128 <= max_events,
+            "{events} events for {reported} pages"
+        );
+        assert!(events < 100, "{events} events for {reported} pages");
+    }
+
+    #[tokio::test]
+    async fn tags_statements_after_parsing() {
+        let traces = StatementTraces::new();
+        let connection_id = Uuid::new_v4();
+        traces.set_enabled(connection_id, true);
+
+        let client = RuntimeClient::SQLite {
+            connection: Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap())),
+            trace: traces.get(connection_id),
+        };
+        let tag = "/* pgpad run=1 */";
+
+        let stmt_manager = StatementManager::new();
+        let query_ids = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                client,
+                "SELECT 1; CREATE TABLE t (id INTEGER)",
+                &RunOptions {
+                    tag: Some(tag.into()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(query_ids, [0, 1]);
+
+        // Classification happens before tagging, so the SELECT still returns values
+        let snapshot = stmt_manager
+            .fetch_initial_renderable_state(0)
+            .await
+            .unwrap();
+        assert!(snapshot.returns_values);
+        while stmt_manager.get_query_status(1).unwrap() != QueryStatus::Completed {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let traced = traces.latest(connection_id, 10);
+        assert_eq!(traced.len(), 2);
+        assert!(traced
+            .iter()
+            .all(|statement| statement.sql.starts_with(tag)));
+    }
+
+    async fn wait_until_over(stmt_manager: &StatementManager, query_ids: &[usize]) {
+        for &query_id in query_ids {
+            while !matches!(
+                stmt_manager.get_query_status(query_id).unwrap(),
+                QueryStatus::Completed | QueryStatus::Error
+            ) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_statements_keep_postgres_transactions_usable() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::task::spawn(conn);
+        let pool = Arc::new(PostgresPool::new(client, vec![]).await.unwrap());
+        let client = pool.session();
+
+        let stmt_manager = StatementManager::new();
+        let query_ids = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                RuntimeClient::Postgres { pool: pool.clone() },
+                "BEGIN;
+                CREATE TABLE t (id int);
+                INSERT INTO t VALUES (1);
+                INSERT INTO t VALUES ('oops');
+                INSERT INTO t VALUES (2);
+                COMMIT",
+                &RunOptions {
+                    savepoints: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        wait_until_over(&stmt_manager, &query_ids).await;
+
+        let mut outcomes = Vec::new();
+        for query_id in query_ids {
+            let snapshot = stmt_manager
+                .fetch_initial_renderable_state(query_id)
+                .await
+                .unwrap();
+            outcomes.push((snapshot.status, snapshot.savepoint));
+        }
+        assert_eq!(
+            outcomes,
+            [
+                (QueryStatus::Completed, None),
+                (QueryStatus::Completed, Some(SavepointOutcome::Released)),
+                (QueryStatus::Completed, Some(SavepointOutcome::Released)),
+                (QueryStatus::Error, Some(SavepointOutcome::RolledBack)),
+                (QueryStatus::Completed, Some(SavepointOutcome::Released)),
+                (QueryStatus::Completed, None),
+            ]
+        );
+
+        let ids: Vec<i32> = client
+            .query("SELECT id FROM t ORDER BY id", &[])
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(ids, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn failing_statements_keep_mysql_transactions_usable() {
+        let Some(db) = TestDb::new().await else {
+            return;
+        };
+        let client = Arc::new(db.client().await);
+
+        let stmt_manager = StatementManager::new();
+        let query_ids = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                RuntimeClient::MySQL {
+                    client: client.clone(),
+                },
+                "CREATE TABLE t (id int PRIMARY KEY);
+                BEGIN;
+                INSERT INTO t VALUES (1);
+                INSERT INTO t VALUES (1);
+                INSERT INTO t VALUES (2);
+                COMMIT;
+                INSERT INTO t VALUES (3)",
+                &RunOptions {
+                    savepoints: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        wait_until_over(&stmt_manager, &query_ids).await;
+
+        let mut outcomes = Vec::new();
+        for query_id in query_ids {
+            let snapshot = stmt_manager
+                .fetch_initial_renderable_state(query_id)
+                .await
+                .unwrap();
+            outcomes.push((snapshot.status, snapshot.savepoint));
+        }
+        // Outside of the transaction, statements autocommit, and their savepoints with them
+        assert_eq!(
+            outcomes,
+            [
+                (QueryStatus::Completed, None),
+                (QueryStatus::Completed, None),
+                (QueryStatus::Completed, Some(SavepointOutcome::Released)),
+                (QueryStatus::Error, Some(SavepointOutcome::RolledBack)),
+                (QueryStatus::Completed, Some(SavepointOutcome::Released)),
+                (QueryStatus::Completed, None),
+                (QueryStatus::Completed, None),
+            ]
+        );
+
+        let ids: Vec<i32> = client
+            .session()
+            .lock()
+            .await
+            .query("SELECT id FROM t ORDER BY id")
+            .await
+            .unwrap();
+        assert_eq!(ids, [1, 2, 3]);
+
+        drop(client);
+        db.close().await;
+    }
+
+    #[tokio::test]
+    async fn kills_mysql_statements_over_their_budget() {
+        let Some(db) = TestDb::new().await else {
+            return;
+        };
+        let client = Arc::new(db.client().await);
+
+        let stmt_manager = StatementManager::new();
+        let query_ids = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                RuntimeClient::MySQL {
+                    client: client.clone(),
+                },
+                "SELECT SLEEP(30)",
+                &RunOptions {
+                    limits: ResourceLimits {
+                        statement_cpu_budget_ms: Some(200),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            wait_until_over(&stmt_manager, &query_ids),
+        )
+        .await
+        .expect("the statement should be killed long before SLEEP is over");
+
+        let snapshot = stmt_manager
+            .fetch_initial_renderable_state(query_ids[0])
+            .await
+            .unwrap();
+        assert_eq!(snapshot.ceilings_hit, [ResourceCeiling::StatementCpuBudget]);
+
+        // Statements within their budget run as usual
+        let query_ids = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                RuntimeClient::MySQL { client },
+                "SELECT SLEEP(0.1)",
+                &RunOptions {
+                    limits: ResourceLimits {
+                        statement_cpu_budget_ms: Some(5_000),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        wait_until_over(&stmt_manager, &query_ids).await;
+        let snapshot = stmt_manager
+            .fetch_initial_renderable_state(query_ids[0])
+            .await
+            .unwrap();
+        assert_eq!(snapshot.status, QueryStatus::Completed);
+        assert!(snapshot.ceilings_hit.is_empty());
+
+        db.close().await;
+    }
+
+    #[tokio::test]
+    async fn postgres_statements_fetch_up_to_their_high_water_mark() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::task::spawn(conn);
+        let pool = Arc::new(PostgresPool::new(client, vec![]).await.unwrap());
+
+        async fn wait_for_pages(stmt_manager: &StatementManager, page_count: usize) {
+            while stmt_manager.get_page_count(0).unwrap() < page_count {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            // Gives it the time to fetch past the mark, if it were to
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(stmt_manager.get_page_count(0).unwrap(), page_count);
+            assert_eq!(
+                stmt_manager.get_query_status(0).unwrap(),
+                QueryStatus::Streaming
+            );
+        }
+
+        let stmt_manager = StatementManager::new();
+        stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                RuntimeClient::Postgres { pool: pool.clone() },
+                "SELECT n, repeat('x', 100) FROM generate_series(1, 1000000) AS n",
+                &RunOptions {
+                    limits: ResourceLimits {
+                        fetch_ahead_pages: Some(3),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        wait_for_pages(&stmt_manager, 3).await;
+
+        // Reading the pages fetched lets it fetch as many again
+        stmt_manager.request_more_rows(0).unwrap();
+        wait_for_pages(&stmt_manager, 6).await;
+
+        // Cancelling it while it waits ends it, and the rows left aren't sent
+        assert_eq!(stmt_manager.cancel_connection_queries(Uuid::nil()), [0]);
+        wait_until_over(&stmt_manager, &[0]).await;
+        let snapshot = stmt_manager
+            .fetch_initial_renderable_state(0)
+            .await
+            .unwrap();
+        assert_eq!(snapshot.status, QueryStatus::Error);
+        assert_eq!(snapshot.error.as_deref(), Some("Query cancelled"));
+
+        let row = tokio::time::timeout(
+            Duration::from_secs(10),
+            pool.session().query_one("SELECT 1", &[]),
+        )
+        .await
+        .expect("the session is still busy with the cancelled statement")
+        .unwrap();
+        assert_eq!(row.get::<_, i32>(0), 1);
+    }
+
+    async fn postgres_pool(db: &pgtemp::PgTempDB) -> Arc<PostgresPool> {
+        let uri = db.connection_uri();
+        let pool = PostgresPool::connect(2, || async {
+            let (client, conn) = tokio_postgres::connect(&uri, tokio_postgres::NoTls)
+                .await
+                .map_err(anyhow::Error::from)?;
+            tokio::task::spawn(conn);
+            Ok((client, None))
+        })
+        .await
+        .unwrap();
+        Arc::new(pool)
+    }
+
+    fn fetching_ahead(pages: usize) -> RunOptions {
+        RunOptions {
+            limits: ResourceLimits {
+                fetch_ahead_pages: Some(pages),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn paused_postgres_statements_give_their_session_back_to_the_next_run() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let pool = postgres_pool(&db).await;
+
+        let stmt_manager = StatementManager::new();
+        // Its rows are slow to come, so the next run would wait for them all unless it's cancelled on the server
+        let paused = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                RuntimeClient::Postgres { pool: pool.clone() },
+                "SELECT n, pg_sleep(0.001) FROM generate_series(1, 1000000) AS n",
+                &fetching_ahead(1),
+            )
+            .unwrap();
+        while stmt_manager.get_page_count(paused[0]).unwrap() < 1 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let next = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                RuntimeClient::Postgres { pool },
+                "SELECT 42",
+                &RunOptions::default(),
+            )
+            .unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            wait_until_over(&stmt_manager, &next),
+        )
+        .await
+        .expect("the next run waited on the paused statement");
+        assert_eq!(
+            stmt_manager.fetch_page(next[0], 0).unwrap().unwrap().get(),
+            "[[42]]"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_last_postgres_statement_of_a_run_pauses() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let pool = postgres_pool(&db).await;
+
+        let stmt_manager = StatementManager::new();
+        let query_ids = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                RuntimeClient::Postgres { pool },
+                "SELECT n FROM generate_series(1, 1000) AS n; SELECT 42",
+                &fetching_ahead(1),
+            )
+            .unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            wait_until_over(&stmt_manager, &query_ids),
+        )
+        .await
+        .expect("the second statement waited behind the first");
+        assert_eq!(stmt_manager.get_page_count(query_ids[0]).unwrap(), 20);
+    }
+
+    #[tokio::test]
+    async fn reports_statement_metrics() {
+        let client = RuntimeClient::SQLite {
+            connection: Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap())),
+            trace: Default::default(),
+        };
+
+        let stmt_manager = StatementManager::new();
+        let query_ids = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                client,
+                "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < 120)
+                SELECT x FROM n",
+                &RunOptions::default(),
+            )
+            .unwrap();
+        wait_until_over(&stmt_manager, &query_ids).await;
+
+        let metrics = stmt_manager.get_statement_info(0).unwrap().unwrap().metrics;
+        assert_eq!(metrics.rows_fetched, 120);
+        // 120 numbers, with brackets and commas
+        assert!(metrics.bytes_fetched > 3 * 120, "{metrics:?}");
+        assert!(metrics.first_row_ms.unwrap() <= metrics.elapsed_ms.unwrap());
+        assert_eq!(metrics.command_tag, None);
+    }
+
+    #[tokio::test]
+    async fn savepoints_are_only_taken_in_sqlite_transactions() {
+        let client = RuntimeClient::SQLite {
+            connection: Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap())),
+            trace: Default::default(),
+        };
+
+        let stmt_manager = StatementManager::new();
+        let query_ids = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                client,
+                "CREATE TABLE t (id INTEGER PRIMARY KEY);
+                BEGIN;
+                INSERT INTO t VALUES (1);
+                INSERT INTO t VALUES (1);
+                COMMIT",
+                &RunOptions {
+                    savepoints: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        wait_until_over(&stmt_manager, &query_ids).await;
+
+        let mut savepoints = Vec::new();
+        for query_id in query_ids {
+            let snapshot = stmt_manager
+                .fetch_initial_renderable_state(query_id)
+                .await
+                .unwrap();
+            savepoints.push(snapshot.savepoint);
+        }
+        assert_eq!(
+            savepoints,
+            [
+                None,
+                None,
+                Some(SavepointOutcome::Released),
+                Some(SavepointOutcome::RolledBack),
+                None
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn pinned_results_outlive_later_submissions() {
+        let client = RuntimeClient::SQLite {
+            connection: Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap())),
+            trace: Default::default(),
+        };
+
+        let stmt_manager = StatementManager::new();
+        let first = stmt_manager
+            .submit_query(
+                Uuid::nil(),
+                client.clone(),
+                "SELECT 1; SELECT 2",
+                &RunOptions::default(),
+            )
+            .unwrap();
+        assert!(stmt_manager.pin_result(first[0]).is_err());
+        wait_until_over(&stmt_manager, &first).await;
+        stmt_manager.pin_result(first[0]).unwrap();
+
+        let second = stmt_manager
+            .submit_query(Uuid::nil(), client, "SELECT 3", &RunOptions::default())
+            .unwrap();
+        wait_until_over(&stmt_manager, &second).await;
+
+        // Ids aren't reused, and only the pinned result was kept
+        assert_eq!(second, [2]);
+        assert_eq!(
+            stmt_manager.fetch_page(first[0], 0).unwrap().unwrap().get(),
+            "[[1]]"
+        );
+        assert!(stmt_manager.get_query_status(first[1]).is_err());
+        let statement = stmt_manager.get_statement_info(second[0]).unwrap().unwrap();
+        assert_eq!(statement.ordinal, 0);
+
+        // Results of earlier submissions go as soon as they're unpinned
+        stmt_manager.unpin_result(first[0]).unwrap();
+        assert!(stmt_manager.get_query_status(first[0]).is_err());
+    }
+
+    #[tokio::test]
+    async fn cancels_the_statements_of_a_connection() {
+        let client = RuntimeClient::SQLite {
+            connection: Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap())),
+            trace: Default::default(),
+        };
+        let connection_id = Uuid::new_v4();
+
+        let stmt_manager = StatementManager::new();
+        let query_ids = stmt_manager
+            .submit_query(
+                connection_id,
+                client,
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n) SELECT count(*) FROM n;
+                SELECT 2",
+                &RunOptions {
+                    savepoints: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        while stmt_manager.get_query_status(0).unwrap() == QueryStatus::Pending {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert!(stmt_manager
+            .cancel_connection_queries(Uuid::new_v4())
+            .is_empty());
+        assert_eq!(
+            stmt_manager.cancel_connection_queries(connection_id),
+            [0, 1]
+        );
+        // Already cancelled
+        assert!(stmt_manager
+            .cancel_connection_queries(connection_id)
+            .is_empty());
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            wait_until_over(&stmt_manager, &query_ids),
+        )
+        .await
+        .unwrap();
+        for query_id in query_ids {
+            let snapshot = stmt_manager
+                .fetch_initial_renderable_state(query_id)
+                .await
+                .unwrap();
+            assert_eq!(snapshot.status, QueryStatus::Error);
+        }
+        let snapshot = stmt_manager
+            .fetch_initial_renderable_state(1)
+            .await
+            .unwrap();
+        assert_eq!(snapshot.error.as_deref(), Some("Query cancelled"));
+    }
+
+    #[test]
+    fn replaces_pages_unless_superseded() {
+        let manager = StatementManager::new();
+        let page = |json: &str| RawValue::from_string(json.to_owned()).unwrap();
+        let query_id = manager
+            .register_derived_result(
+                page(r#"["n"]"#),
+                vec![page("[[2],[1]]"), page("[[3]]")],
+                false,
+            )
+            .unwrap();
+
+        let superseded = manager.start_rewrite(query_id).unwrap();
+        let rewrite = manager.start_rewrite(query_id).unwrap();
+        assert!(superseded.is_superseded());
+        assert!(!rewrite.is_superseded());
+
+        manager
+            .finish_rewrite(rewrite, vec![page("[[1],[2]]"), page("[[3]]")])
+            .unwrap();
+        assert!(manager
+            .finish_rewrite(superseded, vec![page("[[3],[2]]"), page("[[1]]")])
+            .is_err());
+
+        assert_eq!(manager.get_page_count(query_id).unwrap(), 2);
+        let first = manager.fetch_page(query_id, 0).unwrap().unwrap();
+        assert_eq!(first.get(), "[[1],[2]]");
+        // Rows are found in the rewritten pages
+        let detail = manager.get_row_detail(query_id, 2).unwrap();
+        assert_eq!(detail[0].value, serde_json::json!(3));
+    }
+
+    #[test]
+    fn serves_views_from_their_source() {
+        let manager = StatementManager::new();
+        let page = |json: &str| RawValue::from_string(json.to_owned()).unwrap();
+        let source = manager
+            .register_derived_result(
+                page(r#"["n"]"#),
+                vec![page("[[1],[2],[3]]"), page(r#"[[4],["five"]]"#)],
+                false,
+            )
+            .unwrap();
+
+        let rows = (0..60).map(|n| (n % 2, 1)).collect();
+        let rewrites = manager.get_rewrite_count(source).unwrap();
+        let view = manager.register_row_view(source, rows, rewrites).unwrap();
+        assert_eq!(manager.get_page_count(view).unwrap(), 2);
+        assert_eq!(
+            manager.get_columns(view).unwrap().unwrap().get(),
+            r#"["n"]"#
+        );
+        let last = manager.fetch_page(view, 1).unwrap().unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(last.get()).unwrap(),
+            serde_json::json!([
+                [2],
+                ["five"],
+                [2],
+                ["five"],
+                [2],
+                ["five"],
+                [2],
+                ["five"],
+                [2],
+                ["five"]
+            ])
+        );
+        assert!(manager.fetch_page(view, 2).unwrap().is_none());
+        let detail = manager.get_row_detail(view, 51).unwrap();
+        assert_eq!(detail[0].value, serde_json::json!("five"));
+
+        // Sorting the source leaves the offsets of the view stale
+        let rewrite = manager.start_rewrite(source).unwrap();
+        manager
+            .finish_rewrite(rewrite, vec![page(r#"[[1],[2],[3]]"#)])
+            .unwrap();
+        assert!(manager.fetch_page(view, 0).is_err());
+
+        manager.remove_derived_result(view).unwrap();
+        assert!(manager.fetch_page(view, 0).is_err());
+        assert!(manager.fetch_page(source, 0).unwrap().is_some());
+    }
 }
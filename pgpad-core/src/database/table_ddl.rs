@@ -0,0 +1,281 @@
+//! The statements creating a table, with its constraints and indexes, e.g. to copy them into another database.
+//!
+//! SQLite and MySQL keep them (`sqlite_master.sql`, `SHOW CREATE TABLE`), while Postgres has no function returning
+//! them, so they're put together from its catalog.
+
+use anyhow::Context;
+use mysql_async::prelude::Queryable;
+use serde::Serialize;
+
+use crate::{
+    database::{browse::qualified_name, types::Database},
+    utils::quote_mysql_identifier,
+    Error,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TableDdl {
+    pub schema: String,
+    pub table: String,
+    /// The database the statements are written for, to label them
+    pub dialect: Database,
+    /// `CREATE TABLE`, then its indexes, each ending with a semicolon
+    pub ddl: String,
+}
+
+const POSTGRES_TABLE: &str = "
+SELECT c.oid, c.relpersistence = 'u', c.relkind = 'p', pg_catalog.pg_get_partkeydef(c.oid)
+FROM pg_catalog.pg_class c
+JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+WHERE n.nspname = $1 AND c.relname = $2 AND c.relkind IN ('r', 'p')";
+
+/// Identity and generated columns have their own clause rather than a default
+const POSTGRES_COLUMNS: &str = "
+SELECT
+    quote_ident(a.attname),
+    pg_catalog.format_type(a.atttypid, a.atttypmod),
+    CASE WHEN a.attcollation <> t.typcollation THEN quote_ident(co.collname) END,
+    a.attnotnull,
+    pg_catalog.pg_get_expr(d.adbin, d.adrelid),
+    a.attidentity::text,
+    a.attgenerated::text
+FROM pg_catalog.pg_attribute a
+JOIN pg_catalog.pg_type t ON t.oid = a.atttypid
+LEFT JOIN pg_catalog.pg_collation co ON co.oid = a.attcollation
+LEFT JOIN pg_catalog.pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+WHERE a.attrelid = $1 AND a.attnum > 0 AND NOT a.attisdropped
+ORDER BY a.attnum";
+
+/// Primary keys first, since foreign keys and such are usually read after them. Not null constraints are part of
+/// their column.
+const POSTGRES_CONSTRAINTS: &str = "
+SELECT quote_ident(conname), pg_catalog.pg_get_constraintdef(oid, true)
+FROM pg_catalog.pg_constraint
+WHERE conrelid = $1 AND contype IN ('p', 'u', 'c', 'f', 'x')
+ORDER BY array_position(ARRAY['p', 'u', 'c', 'f', 'x']::\"char\"[], contype), conname";
+
+/// Indexes of constraints are created with the constraint
+const POSTGRES_INDEXES: &str = "
+SELECT pg_catalog.pg_get_indexdef(i.indexrelid)
+FROM pg_catalog.pg_index i
+JOIN pg_catalog.pg_class c ON c.oid = i.indexrelid
+WHERE i.indrelid = $1
+  AND NOT EXISTS (SELECT 1 FROM pg_catalog.pg_constraint WHERE conindid = i.indexrelid AND conrelid = i.indrelid)
+ORDER BY c.relname";
+
+pub async fn postgres_table_ddl(
+    client: &tokio_postgres::Client,
+    schema: &str,
+    table: &str,
+) -> Result<String, Error> {
+    let name = qualified_name(schema, table);
+    let row = client
+        .query_opt(POSTGRES_TABLE, &[&schema, &table])
+        .await
+        .with_context(|| format!("Failed to look up {name}"))?
+        .with_context(|| format!("Table {name} not found"))?;
+    let (oid, unlogged, partitioned, partition_key): (u32, bool, bool, Option<String>) =
+        (row.get(0), row.get(1), row.get(2), row.get(3));
+
+    let mut lines = vec![];
+    for row in client
+        .query(POSTGRES_COLUMNS, &[&oid])
+        .await
+        .with_context(|| format!("Failed to read the columns of {name}"))?
+    {
+        let (column, data_type, collation, not_null, default, identity, generated): (
+            String,
+            String,
+            Option<String>,
+            bool,
+            Option<String>,
+            String,
+            String,
+        ) = (
+            row.get(0),
+            row.get(1),
+            row.get(2),
+            row.get(3),
+            row.get(4),
+            row.get(5),
+            row.get(6),
+        );
+
+        let mut line = format!("{column} {data_type}");
+        if let Some(collation) = collation {
+            line.push_str(&format!(" COLLATE {collation}"));
+        }
+        match (identity.as_str(), generated.as_str(), default) {
+            ("a", _, _) => line.push_str(" GENERATED ALWAYS AS IDENTITY"),
+            ("d", _, _) => line.push_str(" GENERATED BY DEFAULT AS IDENTITY"),
+            (_, "s", Some(expression)) => {
+                line.push_str(&format!(" GENERATED ALWAYS AS ({expression}) STORED"))
+            }
+            (_, _, Some(default)) => line.push_str(&format!(" DEFAULT {default}")),
+            _ => {}
+        }
+        if not_null {
+            line.push_str(" NOT NULL");
+        }
+        lines.push(line);
+    }
+
+    for row in client
+        .query(POSTGRES_CONSTRAINTS, &[&oid])
+        .await
+        .with_context(|| format!("Failed to read the constraints of {name}"))?
+    {
+        let (constraint, definition): (String, String) = (row.get(0), row.get(1));
+        lines.push(format!("CONSTRAINT {constraint} {definition}"));
+    }
+
+    let mut ddl = format!(
+        "CREATE {}TABLE {name} (\n    {}\n)",
+        if unlogged { "UNLOGGED " } else { "" },
+        lines.join(",\n    ")
+    );
+    if let Some(partition_key) = partition_key.filter(|_| partitioned) {
+        ddl.push_str(&format!(" PARTITION BY {partition_key}"));
+    }
+    ddl.push(';');
+
+    for row in client
+        .query(POSTGRES_INDEXES, &[&oid])
+        .await
+        .with_context(|| format!("Failed to read the indexes of {name}"))?
+    {
+        ddl.push_str(&format!("\n\n{};", row.get::<_, String>(0)));
+    }
+
+    Ok(ddl)
+}
+
+/// The table as it was created, then its indexes. Automatic indexes, of primary keys and unique constraints, have
+/// no statement and are created with the table.
+pub fn sqlite_table_ddl(
+    conn: &rusqlite::Connection,
+    schema: &str,
+    table: &str,
+) -> Result<String, Error> {
+    let query = format!(
+        "SELECT sql FROM {}
+         WHERE tbl_name = ?1 COLLATE NOCASE AND type IN ('table', 'index') AND sql IS NOT NULL
+         ORDER BY type = 'table' DESC, name",
+        qualified_name(schema, "sqlite_master")
+    );
+
+    let mut stmt = conn
+        .prepare(&query)
+        .with_context(|| format!("Failed to look up {table}"))?;
+    let statements = stmt
+        .query_map([table], |row| row.get::<_, String>(0))
+        .with_context(|| format!("Failed to look up {table}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to read the table's definition")?;
+
+    if statements.is_empty() {
+        return Err(anyhow::anyhow!("Table {table} not found").into());
+    }
+    Ok(statements
+        .iter()
+        .map(|sql| format!("{sql};"))
+        .collect::<Vec<_>>()
+        .join("\n\n"))
+}
+
+/// `SHOW CREATE TABLE`, which has the table's indexes and constraints
+pub async fn mysql_table_ddl(
+    conn: &mut mysql_async::Conn,
+    schema: &str,
+    table: &str,
+) -> Result<String, Error> {
+    let row: Option<mysql_async::Row> = conn
+        .query_first(format!(
+            "SHOW CREATE TABLE {}.{}",
+            quote_mysql_identifier(schema),
+            quote_mysql_identifier(table)
+        ))
+        .await
+        .with_context(|| format!("Failed to look up {table}"))?;
+    // `Table`, then `Create Table`
+    let ddl: String = row
+        .and_then(|row| row.get(1))
+        .with_context(|| format!("Table {table} not found"))?;
+    Ok(ddl + ";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn puts_postgres_tables_together_from_the_catalog() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let created = r#"CREATE TABLE teams (id int PRIMARY KEY);
+            CREATE TABLE "Members" (
+                id bigint GENERATED ALWAYS AS IDENTITY,
+                team_id int NOT NULL REFERENCES teams (id) ON DELETE CASCADE,
+                "Name" text COLLATE "C" NOT NULL DEFAULT 'anonymous',
+                upper_name text GENERATED ALWAYS AS (upper("Name")) STORED,
+                age numeric(3, 0) CHECK (age >= 0),
+                PRIMARY KEY (id),
+                UNIQUE (team_id, "Name")
+            );
+            CREATE INDEX members_age ON "Members" (age DESC);"#;
+        client.batch_execute(created).await.unwrap();
+
+        let ddl = postgres_table_ddl(&client, "public", "Members")
+            .await
+            .unwrap();
+        assert_eq!(
+            ddl,
+            r#"CREATE TABLE "public"."Members" (
+    id bigint GENERATED ALWAYS AS IDENTITY NOT NULL,
+    team_id integer NOT NULL,
+    "Name" text COLLATE "C" DEFAULT 'anonymous'::text NOT NULL,
+    upper_name text GENERATED ALWAYS AS (upper("Name")) STORED,
+    age numeric(3,0),
+    CONSTRAINT "Members_pkey" PRIMARY KEY (id),
+    CONSTRAINT "Members_team_id_Name_key" UNIQUE (team_id, "Name"),
+    CONSTRAINT "Members_age_check" CHECK (age >= 0::numeric),
+    CONSTRAINT "Members_team_id_fkey" FOREIGN KEY (team_id) REFERENCES teams(id) ON DELETE CASCADE
+);
+
+CREATE INDEX members_age ON public."Members" USING btree (age DESC);"#
+        );
+
+        // It runs as-is
+        client
+            .batch_execute(&format!(r#"DROP TABLE "Members"; {ddl}"#))
+            .await
+            .unwrap();
+
+        let error = postgres_table_ddl(&client, "public", "missing")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn reads_sqlite_tables_with_their_indexes() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);
+             CREATE INDEX users_email ON users (lower(email));
+             CREATE TABLE other (id INTEGER)",
+        )
+        .unwrap();
+
+        assert_eq!(
+            sqlite_table_ddl(&conn, "main", "Users").unwrap(),
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);\n\n\
+             CREATE INDEX users_email ON users (lower(email));"
+        );
+        assert!(sqlite_table_ddl(&conn, "main", "missing").is_err());
+    }
+}
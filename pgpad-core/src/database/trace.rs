@@ -0,0 +1,180 @@
+//! Per-connection trace of the statements pgpad sends to local database files, to help debug
+//! locking. Tracing is off by default, in which case recording a statement is a single atomic load.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+/// Statements kept per connection, older ones are dropped first
+pub const TRACE_CAPACITY: usize = 1_000;
+
+type LiveSender = Arc<Mutex<Option<UnboundedSender<TracedStatement>>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementSource {
+    /// Statements the user ran
+    User,
+    /// Schema introspection done by pgpad itself
+    Introspection,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TracedStatement {
+    pub connection_id: Uuid,
+    /// Unix timestamp, in milliseconds
+    pub timestamp: i64,
+    pub source: StatementSource,
+    pub sql: String,
+    pub duration_ms: u64,
+    /// Rows returned or affected, None if the statement failed
+    pub rows: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct StatementTrace {
+    connection_id: Uuid,
+    enabled: AtomicBool,
+    entries: Mutex<VecDeque<TracedStatement>>,
+    live: LiveSender,
+}
+
+impl StatementTrace {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn record(
+        &self,
+        source: StatementSource,
+        sql: &str,
+        duration: Duration,
+        rows: Option<usize>,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let statement = TracedStatement {
+            connection_id: self.connection_id,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            source,
+            sql: sql.to_owned(),
+            duration_ms: duration.as_millis() as u64,
+            rows,
+        };
+
+        if let Some(sender) = self.live.lock().unwrap().as_ref() {
+            let _ = sender.send(statement.clone());
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == TRACE_CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(statement);
+    }
+}
+
+/// The statement traces of every connection, reporting new statements through the receiver given out by
+/// [`StatementTraces::subscribe`]
+#[derive(Debug, Default)]
+pub struct StatementTraces {
+    traces: DashMap<Uuid, Arc<StatementTrace>>,
+    live: LiveSender,
+}
+
+impl StatementTraces {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> UnboundedReceiver<TracedStatement> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.live.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    pub fn get(&self, connection_id: Uuid) -> Arc<StatementTrace> {
+        self.traces
+            .entry(connection_id)
+            .or_insert_with(|| {
+                Arc::new(StatementTrace {
+                    connection_id,
+                    live: self.live.clone(),
+                    ..Default::default()
+                })
+            })
+            .clone()
+    }
+
+    pub fn set_enabled(&self, connection_id: Uuid, enabled: bool) {
+        self.get(connection_id)
+            .enabled
+            .store(enabled, Ordering::Relaxed);
+    }
+
+    /// The latest `limit` statements, oldest first
+    pub fn latest(&self, connection_id: Uuid, limit: usize) -> Vec<TracedStatement> {
+        let trace = self.get(connection_id);
+        let entries = trace.entries.lock().unwrap();
+        entries
+            .iter()
+            .skip(entries.len().saturating_sub(limit))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self, connection_id: Uuid) {
+        self.get(connection_id).entries.lock().unwrap().clear();
+    }
+
+    pub fn remove(&self, connection_id: Uuid) {
+        self.traces.remove(&connection_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_only_while_enabled() {
+        let traces = StatementTraces::new();
+        let mut live = traces.subscribe();
+        let id = Uuid::new_v4();
+        let trace = traces.get(id);
+
+        trace.record(StatementSource::User, "SELECT 1", Duration::ZERO, Some(1));
+        assert!(traces.latest(id, 10).is_empty());
+
+        traces.set_enabled(id, true);
+        for i in 0..TRACE_CAPACITY + 5 {
+            trace.record(
+                StatementSource::Introspection,
+                &format!("SELECT {i}"),
+                Duration::from_millis(2),
+                Some(1),
+            );
+        }
+
+        let latest = traces.latest(id, 2);
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[1].sql, format!("SELECT {}", TRACE_CAPACITY + 4));
+        assert_eq!(traces.latest(id, usize::MAX).len(), TRACE_CAPACITY);
+        assert_eq!(live.try_recv().unwrap().sql, "SELECT 0");
+
+        traces.clear(id);
+        assert!(traces.latest(id, 10).is_empty());
+    }
+}
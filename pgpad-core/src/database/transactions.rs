@@ -0,0 +1,410 @@
+//! Transactions opened explicitly from an editor tab, which stay open across runs until the user commits or
+//! rolls them back. Until then, the statements the tab runs go through the transaction rather than autocommitting.
+//!
+//! Postgres transactions get a client of their own, reserved from the connection's pool, so that the other tabs
+//! keep autocommitting on the session client. MySQL transactions get a session connection of their own. SQLite connections only have the one handle, so while a tab has a
+//! transaction open on one, the other tabs can't run anything on it.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use dashmap::DashMap;
+use serde::Serialize;
+use uuid::Uuid;
+
+use mysql_async::prelude::Queryable;
+
+use crate::{
+    database::{mysql, types::RuntimeClient},
+    Error,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TransactionInfo {
+    /// The editor tab the transaction was opened from
+    pub tab_id: String,
+    /// In milliseconds since the epoch
+    pub started_at: i64,
+}
+
+#[derive(Debug)]
+struct OpenTransaction {
+    client: RuntimeClient,
+    started_at: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct Transactions {
+    /// By connection, then by tab
+    open: DashMap<(Uuid, String), OpenTransaction>,
+}
+
+async fn run_on(client: &RuntimeClient, statement: &'static str) -> Result<(), Error> {
+    match client {
+        RuntimeClient::Postgres { pool } => {
+            pool.session()
+                .batch_execute(statement)
+                .await
+                .with_context(|| format!("Failed to run {statement}"))?;
+        }
+        RuntimeClient::SQLite { connection, .. } => {
+            let connection = connection.clone();
+            tokio::task::spawn_blocking(move || {
+                let conn = connection.lock().unwrap();
+                // The tab may have ended the transaction itself
+                if statement != "BEGIN" && conn.is_autocommit() {
+                    return Ok(());
+                }
+                conn.execute_batch(statement)
+                    .with_context(|| format!("Failed to run {statement}"))
+            })
+            .await
+            .context("Failed to run on the SQLite connection")??;
+        }
+        RuntimeClient::MySQL { client } => {
+            client
+                .session()
+                .lock()
+                .await
+                .query_drop(statement)
+                .await
+                .with_context(|| format!("Failed to run {statement}"))?;
+        }
+    }
+    Ok(())
+}
+
+impl Transactions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens a transaction for a tab. `statement_timeout_ms` applies to the statements run in it, since Postgres
+    /// and MySQL transactions don't run on the session the connection's timeout was set on.
+    pub async fn begin(
+        &self,
+        connection_id: Uuid,
+        tab_id: &str,
+        client: RuntimeClient,
+        statement_timeout_ms: Option<u64>,
+    ) -> Result<(), Error> {
+        let key = (connection_id, tab_id.to_owned());
+        if self.open.contains_key(&key) {
+            return Err(anyhow::anyhow!("This tab already has a transaction open").into());
+        }
+
+        let client = match client {
+            RuntimeClient::Postgres { pool } => {
+                let reserved = pool.reserve_session().context(
+                    "Every client of the connection is taken by another transaction. Commit or roll one back, or give the connection a bigger pool.",
+                )?;
+                RuntimeClient::Postgres {
+                    pool: Arc::new(reserved),
+                }
+            }
+            RuntimeClient::SQLite { .. } => {
+                if self.any_open(connection_id) {
+                    return Err(anyhow::anyhow!(
+                        "Another tab has a transaction open on this connection"
+                    )
+                    .into());
+                }
+                client
+            }
+            RuntimeClient::MySQL { client } => {
+                let reserved = client.reserve_session().await?;
+                if let Some(timeout_ms) = statement_timeout_ms {
+                    let mut session = reserved.session().lock().await;
+                    if let Err(err) =
+                        mysql::execute::set_statement_timeout(&mut session, Some(timeout_ms)).await
+                    {
+                        log::warn!(
+                            "Failed to set the query timeout of a transaction: {}",
+                            mysql::execute::DbError(&err)
+                        );
+                    }
+                }
+                RuntimeClient::MySQL {
+                    client: Arc::new(reserved),
+                }
+            }
+        };
+
+        run_on(&client, "BEGIN").await?;
+        if let (RuntimeClient::Postgres { pool }, Some(timeout_ms)) =
+            (&client, statement_timeout_ms)
+        {
+            let set_timeout = format!("SET LOCAL statement_timeout = {timeout_ms}");
+            if let Err(err) = pool.session().batch_execute(&set_timeout).await {
+                log::warn!("Failed to set the query timeout of a transaction: {err}");
+            }
+        }
+
+        self.open.insert(
+            key,
+            OpenTransaction {
+                client,
+                started_at: chrono::Utc::now().timestamp_millis(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Commits or rolls back the transaction of a tab. It's over either way, even if that fails.
+    pub async fn end(&self, connection_id: Uuid, tab_id: &str, commit: bool) -> Result<(), Error> {
+        let (_, transaction) = self
+            .open
+            .remove(&(connection_id, tab_id.to_owned()))
+            .context("This tab has no transaction open")?;
+
+        let statement = if commit { "COMMIT" } else { "ROLLBACK" };
+        run_on(&transaction.client, statement).await
+    }
+
+    /// The client the statements of a tab run on if it has a transaction open. Fails if another tab has one open
+    /// on the same SQLite connection.
+    pub fn client_for(
+        &self,
+        connection_id: Uuid,
+        tab_id: Option<&str>,
+    ) -> Result<Option<RuntimeClient>, Error> {
+        if let Some(tab_id) = tab_id {
+            if let Some(transaction) = self.open.get(&(connection_id, tab_id.to_owned())) {
+                return Ok(Some(transaction.client.clone()));
+            }
+        }
+
+        let blocked = self.open.iter().any(|entry| {
+            entry.key().0 == connection_id
+                && matches!(entry.value().client, RuntimeClient::SQLite { .. })
+        });
+        if blocked {
+            return Err(anyhow::anyhow!(
+                "Another tab has a transaction open on this connection. Commit or roll it back first."
+            )
+            .into());
+        }
+        Ok(None)
+    }
+
+    /// The transactions open on a connection, oldest first
+    pub fn open_on(&self, connection_id: Uuid) -> Vec<TransactionInfo> {
+        let mut open: Vec<_> = self
+            .open
+            .iter()
+            .filter(|entry| entry.key().0 == connection_id)
+            .map(|entry| TransactionInfo {
+                tab_id: entry.key().1.clone(),
+                started_at: entry.value().started_at,
+            })
+            .collect();
+        open.sort_by(|a, b| (a.started_at, &a.tab_id).cmp(&(b.started_at, &b.tab_id)));
+        open
+    }
+
+    /// The clients of the transactions open on a connection
+    pub fn clients_on(&self, connection_id: Uuid) -> Vec<RuntimeClient> {
+        self.open
+            .iter()
+            .filter(|entry| entry.key().0 == connection_id)
+            .map(|entry| entry.value().client.clone())
+            .collect()
+    }
+
+    fn any_open(&self, connection_id: Uuid) -> bool {
+        self.open.iter().any(|entry| entry.key().0 == connection_id)
+    }
+
+    /// Rolls back the transactions open on a connection, e.g. before disconnecting it. Returns the tabs whose
+    /// transactions were rolled back.
+    pub async fn rollback_all(&self, connection_id: Uuid) -> Vec<String> {
+        let tabs: Vec<String> = self
+            .open_on(connection_id)
+            .into_iter()
+            .map(|transaction| transaction.tab_id)
+            .collect();
+
+        for tab_id in &tabs {
+            if let Err(err) = self.end(connection_id, tab_id, false).await {
+                log::warn!("Failed to roll back the transaction of {tab_id}: {err}");
+            }
+        }
+        tabs
+    }
+
+    /// Forgets the transactions of a connection that dropped, which the server ended along with their sessions
+    pub fn forget(&self, connection_id: Uuid) {
+        self.open.retain(|(id, _), _| *id != connection_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::database::{postgres::pool::PostgresPool, trace::StatementTrace};
+
+    async fn count(pool: &PostgresPool) -> i64 {
+        pool.metadata()
+            .query_one("SELECT count(*) FROM accounts", &[])
+            .await
+            .unwrap()
+            .get(0)
+    }
+
+    #[tokio::test]
+    async fn postgres_transactions_get_a_client_of_their_own() {
+        let db = pgtemp::PgTempDB::async_new().await;
+        let uri = db.connection_uri();
+        let pool = PostgresPool::connect(3, || async {
+            let (client, conn) = tokio_postgres::connect(&uri, tokio_postgres::NoTls)
+                .await
+                .map_err(anyhow::Error::from)?;
+            tokio::spawn(conn);
+            Ok((client, None))
+        })
+        .await
+        .unwrap();
+        pool.session()
+            .batch_execute("CREATE TABLE accounts (id int)")
+            .await
+            .unwrap();
+        let pool = Arc::new(pool);
+        let connection = || RuntimeClient::Postgres { pool: pool.clone() };
+
+        let transactions = Transactions::new();
+        let connection_id = Uuid::new_v4();
+        transactions
+            .begin(connection_id, "tab 1", connection(), Some(5_000))
+            .await
+            .unwrap();
+        assert!(transactions
+            .begin(connection_id, "tab 1", connection(), None)
+            .await
+            .is_err());
+
+        let Some(RuntimeClient::Postgres { pool: tx_pool }) = transactions
+            .client_for(connection_id, Some("tab 1"))
+            .unwrap()
+        else {
+            panic!("Expected the transaction's client");
+        };
+        // Other tabs keep autocommitting
+        assert!(transactions
+            .client_for(connection_id, Some("tab 2"))
+            .unwrap()
+            .is_none());
+
+        tx_pool
+            .session()
+            .batch_execute("INSERT INTO accounts VALUES (1)")
+            .await
+            .unwrap();
+        let timeout: String = tx_pool
+            .session()
+            .query_one("SHOW statement_timeout", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(timeout, "5s");
+        assert_eq!(count(&pool).await, 0);
+        drop(tx_pool);
+
+        transactions
+            .end(connection_id, "tab 1", true)
+            .await
+            .unwrap();
+        assert_eq!(count(&pool).await, 1);
+        assert!(transactions
+            .end(connection_id, "tab 1", true)
+            .await
+            .is_err());
+
+        // Both metadata clients can be taken, but no more
+        for tab_id in ["tab 1", "tab 2"] {
+            transactions
+                .begin(connection_id, tab_id, connection(), None)
+                .await
+                .unwrap();
+        }
+        assert!(transactions
+            .begin(connection_id, "tab 3", connection(), None)
+            .await
+            .is_err());
+        let open = transactions.open_on(connection_id);
+        assert_eq!(
+            open.iter().map(|t| t.tab_id.as_str()).collect::<Vec<_>>(),
+            ["tab 1", "tab 2"]
+        );
+
+        assert_eq!(
+            transactions.rollback_all(connection_id).await,
+            ["tab 1", "tab 2"]
+        );
+        assert!(transactions.open_on(connection_id).is_empty());
+        assert!(pool.reserve_session().is_some());
+    }
+
+    #[tokio::test]
+    async fn sqlite_transactions_keep_other_tabs_out() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE accounts (id INTEGER)")
+            .unwrap();
+        let connection = Arc::new(Mutex::new(conn));
+        let client = RuntimeClient::SQLite {
+            connection: connection.clone(),
+            trace: Arc::new(StatementTrace::default()),
+        };
+
+        let transactions = Transactions::new();
+        let connection_id = Uuid::new_v4();
+        transactions
+            .begin(connection_id, "tab 1", client.clone(), None)
+            .await
+            .unwrap();
+        assert!(transactions
+            .begin(connection_id, "tab 2", client.clone(), None)
+            .await
+            .is_err());
+        assert!(transactions
+            .client_for(connection_id, Some("tab 1"))
+            .unwrap()
+            .is_some());
+        assert!(transactions
+            .client_for(connection_id, Some("tab 2"))
+            .is_err());
+        assert!(transactions.client_for(connection_id, None).is_err());
+
+        connection
+            .lock()
+            .unwrap()
+            .execute_batch("INSERT INTO accounts VALUES (1)")
+            .unwrap();
+        transactions
+            .end(connection_id, "tab 1", false)
+            .await
+            .unwrap();
+        let rows: i64 = connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT count(*) FROM accounts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 0);
+        assert!(transactions
+            .client_for(connection_id, Some("tab 2"))
+            .unwrap()
+            .is_none());
+
+        // Ending a transaction the tab already committed itself is fine
+        transactions
+            .begin(connection_id, "tab 1", client, None)
+            .await
+            .unwrap();
+        connection.lock().unwrap().execute_batch("COMMIT").unwrap();
+        transactions
+            .end(connection_id, "tab 1", true)
+            .await
+            .unwrap();
+    }
+}
@@ -1,12 +1,36 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
 
 use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
 
-use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    watch,
+};
 use uuid::Uuid;
 
-use crate::Error;
+use crate::{
+    database::{
+        lineage::ColumnLineage,
+        mysql::{self, client::MySqlClient},
+        params::QueryParams,
+        parser::{ParsedStatement, StatementKind},
+        postgres::{
+            self,
+            flavor::ServerCapabilities,
+            pool::PostgresPool,
+            ssh_tunnel::{SshTunnel, SshTunnelConfig},
+        },
+        relationship_graph::RelationshipGraph,
+        sqlite::{self, snapshot::SnapshotInfo},
+        trace::StatementTrace,
+    },
+    utils::{fnv1a, FNV_OFFSET_BASIS},
+    Error,
+};
 
 pub type QueryId = usize;
 
@@ -29,18 +53,202 @@ pub struct QuerySnapshot {
     pub affected_rows: Option<usize>,
     pub columns: Option<Box<RawValue>>,
     pub error: Option<String>,
+    /// Resource ceilings that were hit while running the query
+    pub ceilings_hit: Vec<ResourceCeiling>,
+    /// Where each column came from, in the same order as `columns`
+    pub column_lineage: Option<Vec<ColumnLineage>>,
+    /// Where the time went, once the query is over
+    pub timings: Option<TimingBreakdown>,
+    /// What became of the statement's savepoint, if it ran in one
+    pub savepoint: Option<SavepointOutcome>,
+    /// Which statement of the submitted script this is. None for derived results, e.g. groupings.
+    pub statement: Option<StatementInfo>,
+}
+
+/// Where a statement is in the script it was submitted with, so that each of a run's results can be told apart
+#[derive(Debug, Clone, Serialize)]
+pub struct StatementInfo {
+    pub query_id: QueryId,
+    /// 0-based position of the statement in the script
+    pub ordinal: usize,
+    /// Byte offsets of the statement in the script, without its semicolon
+    pub start: usize,
+    pub end: usize,
+    pub kind: StatementKind,
+    /// Whether some of the result's pages were moved to disk, which makes paging through them slower
+    pub spilled: bool,
+    pub metrics: StatementMetrics,
+    /// Notices and warnings the server sent while the statement ran
+    pub messages: Vec<ServerMessage>,
+}
+
+/// A message the server sent besides a statement's results, e.g. from Postgres' `RAISE NOTICE`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServerMessage {
+    /// e.g. `NOTICE` or `WARNING`
+    pub severity: String,
+    pub text: String,
+}
+
+/// How a statement's run is going, or went
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StatementMetrics {
+    /// From the start of execution until the first page of rows. None until there is one.
+    pub first_row_ms: Option<f64>,
+    /// From the start of execution until now, or until the end once it's over. None until it starts.
+    pub elapsed_ms: Option<f64>,
+    pub rows_fetched: usize,
+    /// Size of the rows fetched, as serialized into pages
+    pub bytes_fetched: usize,
+    /// e.g. `UPDATE 42`, once a Postgres statement is over. Only for statements whose tag has a row count.
+    pub command_tag: Option<String>,
+}
+
+/// Savepoints wrap statements run in a transaction block, so that one failing doesn't abort the transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SavepointOutcome {
+    /// The statement succeeded, and its savepoint was released
+    Released,
+    /// The statement failed, and its changes were rolled back. The transaction is still usable.
+    RolledBack,
+}
+
+/// The name of the savepoints taken before statements
+pub const STATEMENT_SAVEPOINT: &str = "pgpad_statement";
+
+/// Where the time of a statement went, in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TimingBreakdown {
+    /// Parsing and classifying the submitted query, shared by all of its statements
+    pub parse_ms: f64,
+    /// Waiting to be executed, e.g. behind earlier statements on the same SQLite connection
+    pub queue_ms: f64,
+    /// From the start of execution until the first page of rows, or until the end for statements without rows
+    pub execute_ms: f64,
+    /// Streaming the rows after the first page
+    pub fetch_ms: f64,
+    /// Serializing rows into pages. Not a phase of its own: it's part of `execute_ms` and `fetch_ms`.
+    pub serialize_ms: f64,
+    pub total_ms: f64,
+}
+
+/// One column of a result row, see [`StatementManager::get_row_detail`](super::stmt_manager::StatementManager::get_row_detail)
+#[derive(Debug, Clone, Serialize)]
+pub struct RowDetailField {
+    pub column: String,
+    /// The column's type as named by the database, if known
+    #[serde(rename = "type")]
+    pub column_type: Option<String>,
+    pub value: serde_json::Value,
+    /// Whether `value` is shortened. Values are always kept whole for now, so this is always false.
+    pub truncated: bool,
+    /// The table the column was read from, if it's a plain column reference
+    pub origin_table: Option<String>,
 }
 
+/// Per-run resource ceilings, and the query timeout, which every engine honors. SQLite honors every ceiling, MySQL
+/// only the statement budget, and Postgres none.
+///
+/// Defaults are set per connection, and can be overridden for a single run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// SQLite's `soft_heap_limit`, in bytes
+    pub soft_heap_limit_bytes: Option<i64>,
+    /// How long a single statement may run before being interrupted. MySQL statements are killed on the server.
+    pub statement_cpu_budget_ms: Option<u64>,
+    /// How long a single statement may run before being cancelled, reported as a timeout rather than as hitting a
+    /// ceiling. Postgres connections have their default set as their `statement_timeout`, so it can't be
+    /// overridden for a single run.
+    pub query_timeout_ms: Option<u64>,
+    /// How many pages a Postgres statement fetches ahead of those read before waiting to be asked for more, see
+    /// [`FetchBudget`]. [`DEFAULT_FETCH_AHEAD_PAGES`] if unset.
+    pub fetch_ahead_pages: Option<usize>,
+}
+
+impl ResourceLimits {
+    /// Fills in the limits not set in `self` from `defaults`
+    pub fn or(self, defaults: ResourceLimits) -> ResourceLimits {
+        ResourceLimits {
+            soft_heap_limit_bytes: self
+                .soft_heap_limit_bytes
+                .or(defaults.soft_heap_limit_bytes),
+            statement_cpu_budget_ms: self
+                .statement_cpu_budget_ms
+                .or(defaults.statement_cpu_budget_ms),
+            query_timeout_ms: self.query_timeout_ms.or(defaults.query_timeout_ms),
+            fetch_ahead_pages: self.fetch_ahead_pages.or(defaults.fetch_ahead_pages),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceCeiling {
+    /// The statement was interrupted for exceeding its budget
+    StatementCpuBudget,
+    /// Memory use reached the soft heap limit, so SQLite had to release caches
+    SoftHeapLimit,
+}
+
+/// How the statements of a run are executed
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Honored as far as the engine can, see [`ResourceLimits`]
+    pub limits: ResourceLimits,
+    /// Prepended to each statement once it's parsed, see [`query_tags`](crate::database::query_tags)
+    pub tag: Option<String>,
+    /// Whether statements run in a transaction block get a savepoint each. Statements then run one after
+    /// the other, rather than being pipelined.
+    pub savepoints: bool,
+    /// Bound to the placeholders of each statement
+    pub params: QueryParams,
+}
+
+/// Options given when submitting a query
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SubmitOptions {
+    /// Overrides the connection's default resource limits for this run
+    #[serde(default)]
+    pub resource_limits: ResourceLimits,
+    /// The script the query comes from, for query tags
+    #[serde(default)]
+    pub script_name: Option<String>,
+    /// The editor tab the query comes from, whose statements are then marked as run, see
+    /// [`ExecutionMarks`](super::execution_marks::ExecutionMarks)
+    #[serde(default)]
+    pub tab_id: Option<String>,
+    /// Values of the query's placeholders, see [`params`](super::params)
+    #[serde(default)]
+    pub params: QueryParams,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Database {
     Postgres,
     Sqlite,
+    MySql,
+}
+
+impl Database {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Postgres => "postgres",
+            Self::Sqlite => "sqlite",
+            Self::MySql => "mysql",
+        }
+    }
 }
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub enum QueryStatus {
     Pending = 0,
+    /// Running, with no rows received yet
     Running = 1,
+    /// Still running, with some pages of rows already readable
+    Streaming = 4,
     Completed = 2,
     Error = 3,
 }
@@ -51,11 +259,23 @@ impl From<u8> for QueryStatus {
             0 => Self::Pending,
             1 => Self::Running,
             2 => Self::Completed,
+            4 => Self::Streaming,
             _ => Self::Error,
         }
     }
 }
 
+/// Sent when new pages of a query's results can be fetched, including while it's still streaming. Pages that come
+/// in quick succession are reported together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PageAvailable {
+    pub query_id: QueryId,
+    /// Index of the first page that wasn't reported before. The new pages are the ones from there on.
+    pub first_new_page: usize,
+    /// The number of pages now available
+    pub page_count: usize,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum Permissions {
@@ -83,13 +303,107 @@ impl Permissions {
     }
 }
 
+/// What a connection is used for, which decides the statements that need confirming, see
+/// [`EnvironmentPolicies`](super::preflight::EnvironmentPolicies)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Environment {
+    Dev,
+    Staging,
+    Prod,
+    Custom,
+}
+
+impl Environment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Dev => "dev",
+            Self::Staging => "staging",
+            Self::Prod => "prod",
+            Self::Custom => "custom",
+        }
+    }
+
+    /// None for values this version doesn't know
+    pub fn from_storage_str(s: &str) -> Option<Self> {
+        match s {
+            "dev" => Some(Self::Dev),
+            "staging" => Some(Self::Staging),
+            "prod" => Some(Self::Prod),
+            "custom" => Some(Self::Custom),
+            _ => None,
+        }
+    }
+}
+
+/// Checks that a connection's color is a hex color, e.g. `#d43f3a` or `#f00`, lowercasing it. Blank colors are
+/// None.
+pub fn normalize_color(color: Option<&str>) -> Result<Option<String>, Error> {
+    let Some(color) = color.map(str::trim).filter(|color| !color.is_empty()) else {
+        return Ok(None);
+    };
+    let valid = color.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 6) && hex.bytes().all(|b| b.is_ascii_hexdigit())
+    });
+    if !valid {
+        return Err(anyhow::anyhow!("{color} isn't a hex color like #d43f3a").into());
+    }
+    Ok(Some(color.to_ascii_lowercase()))
+}
+
+/// Separates the nested folders of a connection's folder
+pub const FOLDER_SEPARATOR: char = '/';
+
+/// Trims each of the folder's segments and drops the empty ones. None if there's nothing left.
+pub fn normalize_folder(folder: Option<&str>) -> Option<String> {
+    let segments: Vec<_> = folder?
+        .split(FOLDER_SEPARATOR)
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    (!segments.is_empty()).then(|| segments.join(&FOLDER_SEPARATOR.to_string()))
+}
+
+/// Where a connection's folder goes once `old` is renamed to `new`, which moves its subfolders along. None if the
+/// folder isn't `old` or inside it.
+pub fn rename_folder(folder: &str, old: &str, new: &str) -> Option<String> {
+    let rest = folder.strip_prefix(old)?;
+    if rest.is_empty() || rest.starts_with(FOLDER_SEPARATOR) {
+        Some(format!("{new}{rest}"))
+    } else {
+        None
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionInfo {
     pub id: Uuid,
     pub name: String,
+    /// e.g. `client/staging`, nested with [`FOLDER_SEPARATOR`]. None for connections outside of folders.
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub environment: Option<Environment>,
+    /// A CSS hex color, e.g. `#d43f3a`, that the connection is marked with
+    #[serde(default)]
+    pub color: Option<String>,
     pub connected: bool,
     pub permissions: Permissions,
     pub config: ConnectionConfig,
+    /// Set while the connection is opened as a snapshot of its SQLite file
+    #[serde(default)]
+    pub snapshot: Option<SnapshotInfo>,
+    /// Whether the file of a SQLite connection that was opened before can't be found anymore, neither at its
+    /// absolute path nor relative to the home directory
+    #[serde(default)]
+    pub file_missing: bool,
+    /// What the server of a connected Postgres connection supports
+    #[serde(default)]
+    pub capabilities: Option<ServerCapabilities>,
+    /// The schema first in the search path of a connected Postgres connection, if one was picked: the connection's
+    /// default schema, or the one set since with `set_active_schema`
+    #[serde(default)]
+    pub active_schema: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,9 +411,27 @@ pub enum ConnectionConfig {
     Postgres {
         connection_string: String,
         ca_cert_path: Option<String>,
+        /// For servers only reachable through a bastion host
+        #[serde(default)]
+        ssh_tunnel: Option<SshTunnelConfig>,
+        /// Put first in the search path once connected, so that its objects are found without naming it
+        #[serde(default)]
+        default_schema: Option<String>,
     },
     SQLite {
         db_path: String,
+        /// `db_path` relative to the home directory, e.g. `~/data/app.db`, if it's inside it. Set when the
+        /// connection is saved.
+        #[serde(default)]
+        home_relative_path: Option<String>,
+    },
+    /// MySQL or MariaDB
+    MySQL {
+        connection_string: String,
+        ca_cert_path: Option<String>,
+        /// For servers only reachable through a bastion host
+        #[serde(default)]
+        ssh_tunnel: Option<SshTunnelConfig>,
     },
 }
 
@@ -107,19 +439,45 @@ pub enum ConnectionConfig {
 pub struct Connection {
     pub id: Uuid,
     pub name: String,
+    pub folder: Option<String>,
+    pub environment: Option<Environment>,
+    pub color: Option<String>,
     pub permissions: Permissions,
     pub config: ConnectionConfig,
     pub runtime: ConnectionRuntime,
+    /// The local copy the runtime reads from, in snapshot mode
+    pub snapshot: Option<SnapshotInfo>,
+    /// Detected when a Postgres connection connects
+    pub capabilities: Option<ServerCapabilities>,
+    /// Open while a tunneled Postgres connection is connected, closed when dropped
+    pub tunnel: Option<SshTunnel>,
+    /// The schema put first in the search path of a connected Postgres connection
+    pub active_schema: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum RuntimeClient {
     Postgres {
-        client: Arc<tokio_postgres::Client>,
+        pool: Arc<PostgresPool>,
     },
     SQLite {
         connection: Arc<Mutex<rusqlite::Connection>>,
+        trace: Arc<StatementTrace>,
     },
+    MySQL {
+        client: Arc<MySqlClient>,
+    },
+}
+
+impl RuntimeClient {
+    /// Splits a script into statements, with the dialect of the client's database
+    pub fn parse_statements(&self, query: &str) -> anyhow::Result<Vec<ParsedStatement>> {
+        match self {
+            Self::Postgres { .. } => postgres::parser::parse_statements(query),
+            Self::SQLite { .. } => sqlite::parser::parse_statements(query),
+            Self::MySQL { .. } => mysql::parser::parse_statements(query),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -133,6 +491,7 @@ impl ConnectionConfig {
         match self {
             ConnectionConfig::Postgres { .. } => Database::Postgres,
             ConnectionConfig::SQLite { .. } => Database::Sqlite,
+            ConnectionConfig::MySQL { .. } => Database::MySql,
         }
     }
 }
@@ -142,19 +501,41 @@ impl Connection {
         ConnectionInfo {
             id: self.id,
             name: self.name.clone(),
+            folder: self.folder.clone(),
+            environment: self.environment,
+            color: self.color.clone(),
             connected: self.is_client_connected(),
             permissions: self.permissions,
             config: self.config.clone(),
+            snapshot: self.snapshot.clone(),
+            file_missing: false,
+            capabilities: self.capabilities.clone(),
+            active_schema: self.active_schema.clone(),
         }
     }
 
-    pub fn new(id: Uuid, name: String, config: ConnectionConfig, permissions: Permissions) -> Self {
+    pub fn new(
+        id: Uuid,
+        name: String,
+        folder: Option<String>,
+        environment: Option<Environment>,
+        color: Option<String>,
+        config: ConnectionConfig,
+        permissions: Permissions,
+    ) -> Self {
         Self {
             id,
             name,
+            folder,
+            environment,
+            color,
             permissions,
             config,
             runtime: ConnectionRuntime::Disconnected,
+            snapshot: None,
+            capabilities: None,
+            active_schema: None,
+            tunnel: None,
         }
     }
 
@@ -165,16 +546,18 @@ impl Connection {
     /// Get the inner client object
     pub fn get_client(&self) -> Result<RuntimeClient, Error> {
         let client = match &self.runtime {
-            ConnectionRuntime::Connected(RuntimeClient::Postgres { client }) => {
-                RuntimeClient::Postgres {
-                    client: client.clone(),
-                }
+            ConnectionRuntime::Connected(RuntimeClient::Postgres { pool }) => {
+                RuntimeClient::Postgres { pool: pool.clone() }
             }
-            ConnectionRuntime::Connected(RuntimeClient::SQLite { connection }) => {
+            ConnectionRuntime::Connected(RuntimeClient::SQLite { connection, trace }) => {
                 RuntimeClient::SQLite {
                     connection: connection.clone(),
+                    trace: trace.clone(),
                 }
             }
+            ConnectionRuntime::Connected(RuntimeClient::MySQL { client }) => RuntimeClient::MySQL {
+                client: client.clone(),
+            },
             ConnectionRuntime::Disconnected => {
                 return Err(Error::Any(anyhow::anyhow!("Connection not active")));
             }
@@ -192,19 +575,258 @@ pub struct ColumnInfo {
     pub default_value: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TableInfo {
+    /// The same for the same table across refreshes and sessions. Set by [`DatabaseSchema::new`].
+    #[serde(default)]
+    pub id: String,
     pub name: String,
     pub schema: String,
+    /// In the table's own order
     pub columns: Vec<ColumnInfo>,
+    /// Changes whenever the table's columns do. Set by [`DatabaseSchema::new`].
+    #[serde(default)]
+    pub content_hash: String,
+    /// Whether the columns are yet to be loaded, for databases with too many tables to load them all at once.
+    /// `columns` is empty until then.
+    #[serde(default)]
+    pub columns_pending: bool,
+    /// The foreign keys of this table to other tables, or to itself
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKey>,
+    /// About how many rows the table has, as cheaply as the database can tell, if it can. Not part of the table's
+    /// content, so it doesn't change `content_hash`.
+    #[serde(default)]
+    pub row_estimate: Option<i64>,
+    /// The columns of the table's primary key, in the key's order. Empty if it has none.
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ForeignKey {
+    /// Paired with `referenced_columns` by position
+    pub columns: Vec<String>,
+    pub referenced_schema: String,
+    pub referenced_table: String,
+    pub referenced_columns: Vec<String>,
+    /// The name of the constraint. None for SQLite, which doesn't tell.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub on_delete: ReferentialAction,
+    #[serde(default)]
+    pub on_update: ReferentialAction,
+}
+
+/// What happens to the rows referencing a row when it's deleted or its key is updated
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferentialAction {
+    #[default]
+    NoAction,
+    Restrict,
+    Cascade,
+    SetNull,
+    SetDefault,
+}
+
+impl ReferentialAction {
+    /// From the way SQLite and MySQL spell it, e.g. `SET NULL`. Unknown actions are taken as `NO ACTION`, the
+    /// default.
+    pub fn from_rule(rule: &str) -> Self {
+        match rule.to_ascii_uppercase().as_str() {
+            "RESTRICT" => Self::Restrict,
+            "CASCADE" => Self::Cascade,
+            "SET NULL" => Self::SetNull,
+            "SET DEFAULT" => Self::SetDefault,
+            _ => Self::NoAction,
+        }
+    }
+
+    /// From Postgres' `pg_constraint.confdeltype` and `confupdtype`
+    pub fn from_pg_code(code: &str) -> Self {
+        match code {
+            "r" => Self::Restrict,
+            "c" => Self::Cascade,
+            "n" => Self::SetNull,
+            "d" => Self::SetDefault,
+            _ => Self::NoAction,
+        }
+    }
+
+    pub fn as_sql(self) -> &'static str {
+        match self {
+            Self::NoAction => "NO ACTION",
+            Self::Restrict => "RESTRICT",
+            Self::Cascade => "CASCADE",
+            Self::SetNull => "SET NULL",
+            Self::SetDefault => "SET DEFAULT",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseSchema {
+    /// By schema, then by name
     pub tables: Vec<TableInfo>,
     pub schemas: Vec<String>,
     // Deduplicated list of column names across all tables, for autocomplete purposes
     pub unique_columns: Vec<String>,
+    /// When the schema was introspected, in milliseconds since the epoch
+    #[serde(default)]
+    pub generated_at: i64,
+    /// Changes whenever any table does, or when tables are added or dropped
+    #[serde(default)]
+    pub hash: String,
+    /// The schema first in the search path, whose objects are found without naming it, if one was picked for the
+    /// connection. Not part of `hash`.
+    #[serde(default)]
+    pub active_schema: Option<String>,
+    /// Built the first time it's asked for, see [`DatabaseSchema::relationship_graph`]. Being part of the schema,
+    /// it's dropped whenever the cached schema is. Copies with other tables have to reset it.
+    #[serde(skip)]
+    pub(crate) relationship_graph: OnceLock<Arc<RelationshipGraph>>,
+}
+
+/// A hash of `parts` that's stable across versions, as hex since it doesn't fit in a JS number
+fn stable_hash<'a>(parts: impl IntoIterator<Item = &'a str>) -> String {
+    let hash = parts.into_iter().fold(FNV_OFFSET_BASIS, |hash, part| {
+        // Separated, so that ("ab", "c") and ("a", "bc") don't collide
+        fnv1a(fnv1a(hash, part.as_bytes()), &[0])
+    });
+    format!("{hash:016x}")
+}
+
+impl DatabaseSchema {
+    /// Orders everything the same way whatever order the catalog returned it in, so that unchanged schemas
+    /// serialize the same, and sets the ids and hashes of tables. Strings are compared byte by byte, so the order
+    /// doesn't depend on the locale either.
+    ///
+    /// Columns are left in the table's own order, which the catalogs already return them in.
+    /// Foreign keys count as part of the table's content.
+    pub fn new(
+        mut tables: Vec<TableInfo>,
+        mut schemas: Vec<String>,
+        mut unique_columns: Vec<String>,
+    ) -> Self {
+        for table in &mut tables {
+            table.id = stable_hash(["table", &table.schema, &table.name]);
+            table.foreign_keys.sort();
+            let columns = table.columns.iter().flat_map(|column| {
+                [
+                    column.name.as_str(),
+                    column.data_type.as_str(),
+                    if column.is_nullable {
+                        "null"
+                    } else {
+                        "not null"
+                    },
+                    column.default_value.as_deref().unwrap_or(""),
+                ]
+            });
+            let foreign_keys = table.foreign_keys.iter().flat_map(|key| {
+                ["foreign key", &key.referenced_schema, &key.referenced_table]
+                    .into_iter()
+                    .chain(key.columns.iter().map(String::as_str))
+                    .chain(["references"])
+                    .chain(key.referenced_columns.iter().map(String::as_str))
+                    .chain([
+                        key.name.as_deref().unwrap_or(""),
+                        key.on_delete.as_sql(),
+                        key.on_update.as_sql(),
+                    ])
+            });
+            // Left out of tables without one, whose hashes stay as they were
+            let primary_key = (!table.primary_key.is_empty())
+                .then_some("primary key")
+                .into_iter()
+                .chain(table.primary_key.iter().map(String::as_str));
+            table.content_hash = stable_hash(columns.chain(foreign_keys).chain(primary_key));
+        }
+        tables.sort_by(|a, b| (&a.schema, &a.name).cmp(&(&b.schema, &b.name)));
+        schemas.sort();
+        schemas.dedup();
+        unique_columns.sort();
+        unique_columns.dedup();
+
+        let hash = stable_hash(
+            tables
+                .iter()
+                .flat_map(|table| [table.id.as_str(), table.content_hash.as_str()]),
+        );
+
+        Self {
+            tables,
+            schemas,
+            unique_columns,
+            generated_at: chrono::Utc::now().timestamp_millis(),
+            hash,
+            active_schema: None,
+            relationship_graph: OnceLock::new(),
+        }
+    }
+
+    /// The tables and the foreign keys between them, built once per schema
+    pub fn relationship_graph(&self) -> Arc<RelationshipGraph> {
+        self.relationship_graph
+            .get_or_init(|| Arc::new(RelationshipGraph::new(self)))
+            .clone()
+    }
+
+    pub fn table(&self, schema: &str, name: &str) -> Option<&TableInfo> {
+        self.tables
+            .iter()
+            .find(|table| table.schema == schema && table.name == name)
+    }
+
+    /// A copy with the columns of a table whose columns were pending, or None if there's no such table
+    pub fn with_table_columns(
+        &self,
+        schema: &str,
+        name: &str,
+        columns: Vec<ColumnInfo>,
+    ) -> Option<Self> {
+        let mut tables = self.tables.clone();
+        let table = tables
+            .iter_mut()
+            .find(|table| table.schema == schema && table.name == name)?;
+
+        let mut unique_columns = self.unique_columns.clone();
+        unique_columns.extend(columns.iter().map(|column| column.name.clone()));
+        table.columns = columns;
+        table.columns_pending = false;
+
+        Some(Self {
+            // It's still the same introspection
+            generated_at: self.generated_at,
+            active_schema: self.active_schema.clone(),
+            ..Self::new(tables, self.schemas.clone(), unique_columns)
+        })
+    }
+
+    /// A copy with another schema first in the search path
+    pub fn with_active_schema(&self, active_schema: Option<String>) -> Self {
+        Self {
+            active_schema,
+            ..self.clone()
+        }
+    }
+
+    /// A copy with the row count of a table, or None if there's no such table
+    pub fn with_row_estimate(&self, schema: &str, name: &str, rows: i64) -> Option<Self> {
+        let mut updated = self.clone();
+        updated
+            .tables
+            .iter_mut()
+            .find(|table| table.schema == schema && table.name == name)?
+            .row_estimate = Some(rows);
+        // Its nodes have the estimates
+        updated.relationship_graph = OnceLock::new();
+        Some(updated)
+    }
 }
 
 pub fn channel() -> (
@@ -214,6 +836,74 @@ pub fn channel() -> (
     mpsc::unbounded_channel()
 }
 
+/// Pages of 50 rows, so 10k rows
+pub const DEFAULT_FETCH_AHEAD_PAGES: usize = 200;
+
+/// How many pages a statement may fetch, so that a huge result isn't pulled into memory before anyone reads it.
+/// The statement waits once it has fetched them all, until more are allowed or it's cancelled.
+///
+/// Only Postgres statements wait, by not polling their row stream: the server then stops sending rows, and the
+/// session is held by the statement until it's done. So only the last statement of a run waits, and it's
+/// cancelled once the next run is submitted.
+#[derive(Debug)]
+pub struct FetchBudget {
+    /// How many pages more to allow on each request
+    ahead: usize,
+    state: watch::Sender<FetchState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FetchState {
+    allowed: usize,
+    cancelled: bool,
+}
+
+impl FetchBudget {
+    pub fn new(ahead: usize) -> Self {
+        let ahead = ahead.max(1);
+        Self {
+            ahead,
+            state: watch::Sender::new(FetchState {
+                allowed: ahead,
+                cancelled: false,
+            }),
+        }
+    }
+
+    /// Waits until the statement may fetch another page, having fetched `fetched` pages. Returns false if the
+    /// statement was cancelled instead.
+    pub async fn wait_for_page(&self, fetched: usize) -> bool {
+        let mut state = self.state.subscribe();
+        let allowed = state
+            .wait_for(|state| state.cancelled || state.allowed > fetched)
+            .await
+            .is_ok_and(|state| !state.cancelled);
+        allowed
+    }
+
+    /// Whether the statement may fetch another page right away, having fetched `fetched` pages
+    pub fn allows(&self, fetched: usize) -> bool {
+        let state = self.state.borrow();
+        !state.cancelled && state.allowed > fetched
+    }
+
+    /// Allows fetching as many pages again past the `read` first ones
+    pub fn request_more(&self, read: usize) {
+        self.state.send_modify(|state| {
+            state.allowed = state.allowed.max(read + self.ahead);
+        });
+    }
+
+    /// Stops the statement at its next page, even if it's waiting
+    pub fn cancel(&self) {
+        self.state.send_modify(|state| state.cancelled = true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.borrow().cancelled
+    }
+}
+
 #[derive(Debug)]
 /// An event sent by a query executor to the main thread
 pub enum QueryExecEvent {
@@ -221,10 +911,12 @@ pub enum QueryExecEvent {
     TypesResolved {
         // Serialized Vec<String>, because I can't help myself
         columns: Box<RawValue>,
+        /// The type of each column as named by the database, None if the driver couldn't tell
+        column_types: Vec<Option<String>>,
     },
     /// Sent by a query executor when a page of results is available
     Page {
-        #[allow(unused)]
+        /// Rows in the page
         page_amount: usize,
         /// JSON-serialized Vec<Vec<Json>>
         page: Page,
@@ -238,4 +930,18 @@ pub enum QueryExecEvent {
         /// If the query failed, this will contain the error message
         error: Option<String>,
     },
+    /// Sent by a query executor after `TypesResolved`, if the driver could tell where columns came from
+    LineageResolved(Vec<ColumnLineage>),
+    /// Sent by a query executor, before `Finished`, when a resource ceiling was hit
+    CeilingHit(ResourceCeiling),
+    /// Sent once the statement is about to run, e.g. after acquiring the SQLite connection
+    Started(Instant),
+    /// Sent by a query executor, before `Finished`, with the total time spent serializing rows into pages
+    Serialized(Duration),
+    /// Sent by a query executor, after `Finished`, once the savepoint the statement ran in is settled
+    Savepoint(SavepointOutcome),
+    /// Sent by the Postgres executor, before `Finished`, with the statement's command tag
+    CommandTag(String),
+    /// Sent on behalf of the Postgres connection, while the statement runs, for each notice or warning
+    Message { severity: String, text: String },
 }
@@ -0,0 +1,200 @@
+//! Variables like `${start_date}`, defined for every connection or for one of them, and substituted into queries
+//! before they run.
+//!
+//! Only the `${name}` in the SQL itself are substituted, not those in string literals, quoted identifiers or
+//! comments. `$${name}` is written as `${name}` as-is.
+
+use std::collections::HashMap;
+
+use crate::{database::sanitize::dollar_tag, Error};
+
+enum ScanState {
+    Code,
+    /// A string literal or quoted identifier, ending with this quote
+    Quoted(char),
+    LineComment,
+    BlockComment {
+        depth: usize,
+    },
+    DollarQuoted {
+        tag: String,
+    },
+}
+
+/// Whether `name` can be used as `${name}`
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+/// The `${name}` starting at `s`, if any
+fn variable_at(s: &str) -> Option<&str> {
+    let rest = s.strip_prefix("${")?;
+    let end = rest.find('}')?;
+    is_valid_name(&rest[..end]).then_some(&s[..end + 3])
+}
+
+/// Replaces the variables of `query` with their values, failing if some have none
+pub fn substitute(query: &str, values: &HashMap<String, String>) -> Result<String, Error> {
+    let mut substituted = String::with_capacity(query.len());
+    let mut undefined: Vec<&str> = Vec::new();
+    let mut state = ScanState::Code;
+    let mut i = 0;
+
+    while let Some(c) = query[i..].chars().next() {
+        let rest = &query[i..];
+        // Bytes the current token takes, copied as-is unless it's a variable
+        let mut len = c.len_utf8();
+
+        state = match state {
+            ScanState::Code => {
+                if let Some(escaped) = rest.strip_prefix('$').and_then(variable_at) {
+                    substituted.push_str(escaped);
+                    i += escaped.len() + 1;
+                    continue;
+                }
+                if let Some(variable) = variable_at(rest) {
+                    let name = &variable[2..variable.len() - 1];
+                    match values.get(name) {
+                        Some(value) => substituted.push_str(value),
+                        None if !undefined.contains(&name) => undefined.push(name),
+                        None => {}
+                    }
+                    i += variable.len();
+                    continue;
+                }
+
+                match c {
+                    '\'' | '"' | '`' => ScanState::Quoted(c),
+                    '-' if rest.starts_with("--") => {
+                        len = 2;
+                        ScanState::LineComment
+                    }
+                    '/' if rest.starts_with("/*") => {
+                        len = 2;
+                        ScanState::BlockComment { depth: 1 }
+                    }
+                    '$' => match dollar_tag(rest) {
+                        Some(tag) => {
+                            len = tag.len();
+                            ScanState::DollarQuoted {
+                                tag: tag.to_owned(),
+                            }
+                        }
+                        None => ScanState::Code,
+                    },
+                    _ => ScanState::Code,
+                }
+            }
+            // Doubled quotes are escaped quotes
+            ScanState::Quoted(quote) if c == quote && rest[1..].starts_with(quote) => {
+                len = 2;
+                ScanState::Quoted(quote)
+            }
+            ScanState::Quoted(quote) if c == quote => ScanState::Code,
+            ScanState::LineComment if c == '\n' => ScanState::Code,
+            ScanState::BlockComment { depth } if rest.starts_with("/*") => {
+                len = 2;
+                ScanState::BlockComment { depth: depth + 1 }
+            }
+            ScanState::BlockComment { depth } if rest.starts_with("*/") => {
+                len = 2;
+                match depth {
+                    1 => ScanState::Code,
+                    depth => ScanState::BlockComment { depth: depth - 1 },
+                }
+            }
+            ScanState::DollarQuoted { tag } if rest.starts_with(&tag) => {
+                len = tag.len();
+                ScanState::Code
+            }
+            state => state,
+        };
+
+        substituted.push_str(&rest[..len]);
+        i += len;
+    }
+
+    match undefined.as_slice() {
+        [] => Ok(substituted),
+        [name] => Err(anyhow::anyhow!("Variable ${{{name}}} isn't defined").into()),
+        names => Err(anyhow::anyhow!(
+            "Variables {} aren't defined",
+            names
+                .iter()
+                .map(|name| format!("${{{name}}}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> HashMap<String, String> {
+        HashMap::from([
+            ("start_date".to_owned(), "'2024-01-01'".to_owned()),
+            ("table".to_owned(), "orders".to_owned()),
+        ])
+    }
+
+    #[test]
+    fn substitutes_variables_outside_literals_and_comments() {
+        let query = "SELECT '${table}', \"${table}\" -- ${table}
+            FROM ${table} /* ${table} /* nested */ ${table} */
+            WHERE created_at >= ${start_date} AND body = $body$ ${table} $body$ AND id = $1;";
+        assert_eq!(
+            substitute(query, &values()).unwrap(),
+            "SELECT '${table}', \"${table}\" -- ${table}
+            FROM orders /* ${table} /* nested */ ${table} */
+            WHERE created_at >= '2024-01-01' AND body = $body$ ${table} $body$ AND id = $1;"
+        );
+
+        // Quotes doubled within literals don't end them
+        assert_eq!(
+            substitute("SELECT 'it''s ${table}', ${table}", &values()).unwrap(),
+            "SELECT 'it''s ${table}', orders"
+        );
+        assert_eq!(
+            substitute("SELECT `${table}` FROM ${table}", &values()).unwrap(),
+            "SELECT `${table}` FROM orders"
+        );
+    }
+
+    #[test]
+    fn keeps_escaped_variables() {
+        assert_eq!(
+            substitute("SELECT '$' || $${table}, ${table}, $${nope}", &values()).unwrap(),
+            "SELECT '$' || ${table}, orders, ${nope}"
+        );
+        // Not variables, so left alone
+        assert_eq!(
+            substitute("SELECT ${1:table}, ${}, $$ ${table} $$", &values()).unwrap(),
+            "SELECT ${1:table}, ${}, $$ ${table} $$"
+        );
+    }
+
+    #[test]
+    fn names_undefined_variables() {
+        let error = substitute("SELECT ${a} FROM ${table}", &values()).unwrap_err();
+        assert_eq!(error.to_string(), "Variable ${a} isn't defined");
+
+        let error = substitute("SELECT ${a}, ${b}, ${a}", &values()).unwrap_err();
+        assert_eq!(error.to_string(), "Variables ${a}, ${b} aren't defined");
+    }
+
+    #[test]
+    fn validates_names() {
+        assert!(is_valid_name("start_date"));
+        assert!(is_valid_name("_x1"));
+        assert!(!is_valid_name("1x"));
+        assert!(!is_valid_name("start date"));
+        assert!(!is_valid_name(""));
+    }
+}
@@ -15,6 +15,8 @@ pub enum Error {
     Json(#[from] serde_json::Error),
     #[error(transparent)]
     Join(#[from] tokio::task::JoinError),
+    #[error("Row {row_index} is out of range, the result has {row_count} rows")]
+    RowOutOfRange { row_index: usize, row_count: usize },
 }
 
 impl<T: Debug> From<tokio::sync::mpsc::error::SendError<T>> for Error {
@@ -0,0 +1,545 @@
+//! Editing a script in an external editor: the tab's contents are written to a private temporary file, which
+//! is opened in the user's editor and watched for changes until the session ends.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{mpsc as std_mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context;
+use dashmap::DashMap;
+use notify::{RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+use crate::Error;
+
+/// Editors usually write a file in several steps (e.g. vim writes a new file and renames it over the old one),
+/// so we wait for the events to settle before reading the file
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExternalEditorSettings {
+    /// Command the file is opened with, e.g. `code --wait`, with the file's path as its last argument.
+    /// If unset, the file is opened with the OS' default handler for `.sql` files.
+    pub command: Option<String>,
+    pub conflict_strategy: ConflictStrategy,
+}
+
+/// What to do when both the file and the tab changed since they were last in sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// The file's contents replace the tab's
+    ExternalWins,
+    /// The tab's contents are written back to the file
+    PgpadWins,
+    /// The user picks one, see [`ExternalEdits::resolve`]
+    #[default]
+    Prompt,
+}
+
+/// Which side to keep when resolving a conflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    KeepExternal,
+    KeepPgpad,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExternalEditSession {
+    pub id: Uuid,
+    pub tab_id: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExternalEditChange {
+    /// The file changed, and its contents should replace the tab's
+    Changed { contents: String },
+    /// Both the file and the tab changed, and the user has to pick one
+    Conflict { contents: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExternalEditEvent {
+    pub session_id: Uuid,
+    pub tab_id: String,
+    #[serde(flatten)]
+    pub change: ExternalEditChange,
+}
+
+#[derive(Debug, PartialEq)]
+enum Reaction {
+    Nothing,
+    Report(ExternalEditChange),
+    WriteBack(String),
+}
+
+#[derive(Debug, Default)]
+struct SyncState {
+    /// What the file and the tab last agreed on
+    synced: String,
+    /// Edits made in the tab since then
+    local: Option<String>,
+    /// File contents waiting for the user to resolve a conflict
+    conflicting: Option<String>,
+}
+
+impl SyncState {
+    fn new(contents: &str) -> Self {
+        Self {
+            synced: contents.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn local_change(&mut self, contents: String) {
+        self.local = (contents != self.synced).then_some(contents);
+    }
+
+    fn external_change(&mut self, contents: String, strategy: ConflictStrategy) -> Reaction {
+        // Includes the events caused by our own writes
+        if contents == self.synced {
+            return Reaction::Nothing;
+        }
+
+        let Some(local) = self.local.take() else {
+            self.synced = contents.clone();
+            return Reaction::Report(ExternalEditChange::Changed { contents });
+        };
+
+        if local == contents {
+            self.synced = contents;
+            return Reaction::Nothing;
+        }
+
+        match strategy {
+            ConflictStrategy::ExternalWins => {
+                self.synced = contents.clone();
+                Reaction::Report(ExternalEditChange::Changed { contents })
+            }
+            ConflictStrategy::PgpadWins => {
+                self.synced = local.clone();
+                Reaction::WriteBack(local)
+            }
+            ConflictStrategy::Prompt => {
+                self.local = Some(local);
+                self.conflicting = Some(contents.clone());
+                Reaction::Report(ExternalEditChange::Conflict { contents })
+            }
+        }
+    }
+
+    /// Returns the contents the tab should now have, and whether they must be written to the file
+    fn resolve(&mut self, resolution: ConflictResolution) -> Option<(String, bool)> {
+        let external = self.conflicting.take()?;
+        let local = self.local.take().unwrap_or_else(|| self.synced.clone());
+
+        let resolved = match resolution {
+            ConflictResolution::KeepExternal => (external, false),
+            ConflictResolution::KeepPgpad => (local, true),
+        };
+        self.synced = resolved.0.clone();
+        Some(resolved)
+    }
+}
+
+struct Session {
+    info: ExternalEditSession,
+    dir: PathBuf,
+    state: Arc<Mutex<SyncState>>,
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.dir) {
+            log::warn!("Failed to clean up {}: {err}", self.dir.display());
+        }
+    }
+}
+
+/// The running external editing sessions, reporting changes through the receiver given out by
+/// [`ExternalEdits::subscribe`].
+///
+/// Sessions can't be started until someone subscribes (e.g. the web server never does). Their temporary files
+/// are deleted when they end, or when this is dropped.
+#[derive(Default)]
+pub struct ExternalEdits {
+    sessions: DashMap<Uuid, Session>,
+    sender: Mutex<Option<UnboundedSender<ExternalEditEvent>>>,
+}
+
+impl std::fmt::Debug for ExternalEdits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ExternalEdits")
+    }
+}
+
+impl ExternalEdits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> UnboundedReceiver<ExternalEditEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.sender.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Writes `contents` to a temporary file and opens it in the user's editor
+    pub fn start(
+        &self,
+        tab_id: String,
+        title: &str,
+        contents: &str,
+        settings: &ExternalEditorSettings,
+    ) -> Result<ExternalEditSession, Error> {
+        let Some(sender) = self.sender.lock().unwrap().clone() else {
+            return Err(
+                anyhow::anyhow!("Editing in an external editor isn't available here").into(),
+            );
+        };
+
+        let id = Uuid::new_v4();
+        let dir = std::env::temp_dir().join(format!("pgpad-edit-{id}"));
+        let path = dir.join(format!("{}.sql", file_stem(title)));
+        create_private_file(&dir, &path, contents)?;
+
+        let state = Arc::new(Mutex::new(SyncState::new(contents)));
+        let info = ExternalEditSession {
+            id,
+            tab_id,
+            path: path.to_string_lossy().into_owned(),
+        };
+
+        let (events_tx, events_rx) = std_mpsc::channel();
+        let watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(_) => {
+                    let _ = events_tx.send(());
+                }
+                Err(err) => log::warn!("Error watching externally edited file: {err}"),
+            })
+            .and_then(|mut watcher| {
+                // Watching the directory rather than the file, so that we keep seeing changes after the editor
+                // replaces the file
+                watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+                Ok(watcher)
+            });
+
+        let watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(err) => {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(anyhow::anyhow!("Failed to watch {}: {err}", path.display()).into());
+            }
+        };
+
+        let session = Session {
+            info: info.clone(),
+            dir,
+            state: state.clone(),
+            _watcher: watcher,
+        };
+        // Dropping the session from here on cleans up the file
+        self.sessions.insert(id, session);
+
+        if let Err(err) = open_in_editor(&path, settings.command.as_deref()) {
+            self.end(id);
+            return Err(err);
+        }
+
+        let watched = WatchedFile {
+            info: info.clone(),
+            path,
+            state,
+            strategy: settings.conflict_strategy,
+        };
+        // Ends once the watcher (and with it, `events_tx`) is dropped
+        std::thread::spawn(move || watched.forward_changes(events_rx, sender));
+
+        Ok(info)
+    }
+
+    /// Records the tab's contents, so that external changes made after this are detected as conflicts
+    pub fn local_change(&self, id: Uuid, contents: String) -> Result<(), Error> {
+        self.session_state(id)?
+            .lock()
+            .unwrap()
+            .local_change(contents);
+        Ok(())
+    }
+
+    /// Resolves a conflict reported through [`ExternalEditChange::Conflict`], returning the tab's new contents
+    pub fn resolve(&self, id: Uuid, resolution: ConflictResolution) -> Result<String, Error> {
+        let session = self
+            .sessions
+            .get(&id)
+            .ok_or_else(|| anyhow::anyhow!("External editing session not found: {id}"))?;
+
+        let resolved = session.state.lock().unwrap().resolve(resolution);
+        let Some((contents, write_back)) = resolved else {
+            return Err(anyhow::anyhow!("There is no conflict to resolve").into());
+        };
+
+        if write_back {
+            fs::write(&session.info.path, &contents)
+                .with_context(|| format!("Failed to write {}", session.info.path))?;
+        }
+
+        Ok(contents)
+    }
+
+    /// Stops watching the file and deletes it
+    pub fn end(&self, id: Uuid) {
+        self.sessions.remove(&id);
+    }
+
+    /// Ends every session, e.g. when the app exits
+    pub fn end_all(&self) {
+        self.sessions.clear();
+    }
+
+    fn session_state(&self, id: Uuid) -> Result<Arc<Mutex<SyncState>>, Error> {
+        self.sessions
+            .get(&id)
+            .map(|session| session.state.clone())
+            .ok_or_else(|| anyhow::anyhow!("External editing session not found: {id}").into())
+    }
+}
+
+struct WatchedFile {
+    info: ExternalEditSession,
+    path: PathBuf,
+    state: Arc<Mutex<SyncState>>,
+    strategy: ConflictStrategy,
+}
+
+impl WatchedFile {
+    fn forward_changes(
+        self,
+        events: std_mpsc::Receiver<()>,
+        sender: UnboundedSender<ExternalEditEvent>,
+    ) {
+        while events.recv().is_ok() {
+            while events.recv_timeout(DEBOUNCE).is_ok() {}
+
+            // Missing while the editor replaces it, or deleted by the user: either way there's nothing to sync
+            let Ok(contents) = fs::read_to_string(&self.path) else {
+                continue;
+            };
+
+            let reaction = self
+                .state
+                .lock()
+                .unwrap()
+                .external_change(contents, self.strategy);
+
+            match reaction {
+                Reaction::Nothing => {}
+                Reaction::Report(change) => {
+                    let event = ExternalEditEvent {
+                        session_id: self.info.id,
+                        tab_id: self.info.tab_id.clone(),
+                        change,
+                    };
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+                Reaction::WriteBack(contents) => {
+                    if let Err(err) = fs::write(&self.path, contents) {
+                        log::warn!("Failed to write {}: {err}", self.path.display());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A file name for the script, so that editors show something recognizable
+fn file_stem(title: &str) -> String {
+    let stem: String = title
+        .trim()
+        .trim_end_matches(".sql")
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | ' ') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect();
+
+    if stem.trim().is_empty() {
+        "script".into()
+    } else {
+        stem
+    }
+}
+
+/// Creates `path` in a new directory only the current user can access, since scripts may contain secrets
+fn create_private_file(dir: &Path, path: &Path, contents: &str) -> Result<(), Error> {
+    let mut dir_builder = fs::DirBuilder::new();
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+        dir_builder.mode(0o700);
+        options.mode(0o600);
+    }
+
+    dir_builder
+        .create(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let written = options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()));
+    if let Err(err) = written {
+        let _ = fs::remove_dir_all(dir);
+        return Err(anyhow::anyhow!("Failed to write {}: {err}", path.display()).into());
+    }
+
+    Ok(())
+}
+
+fn open_in_editor(path: &Path, command: Option<&str>) -> Result<(), Error> {
+    let mut parts = command.unwrap_or_default().split_whitespace();
+
+    let mut cmd = match parts.next() {
+        Some(program) => {
+            let mut cmd = Command::new(program);
+            cmd.args(parts);
+            cmd
+        }
+        None => default_opener(),
+    };
+
+    let mut child = cmd
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to open the external editor")?;
+
+    // Reaps the editor once it exits
+    std::thread::spawn(move || child.wait());
+
+    Ok(())
+}
+
+fn default_opener() -> Command {
+    if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", "start", ""]);
+        cmd
+    } else {
+        Command::new("xdg-open")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed(contents: &str) -> Reaction {
+        Reaction::Report(ExternalEditChange::Changed {
+            contents: contents.into(),
+        })
+    }
+
+    #[test]
+    fn forwards_external_changes() {
+        let mut state = SyncState::new("SELECT 1");
+
+        let strategy = ConflictStrategy::Prompt;
+        assert_eq!(
+            state.external_change("SELECT 1".into(), strategy),
+            Reaction::Nothing
+        );
+        assert_eq!(
+            state.external_change("SELECT 2".into(), strategy),
+            changed("SELECT 2")
+        );
+
+        // Edits in pgpad that were undone don't conflict
+        state.local_change("SELECT 3".into());
+        state.local_change("SELECT 2".into());
+        assert_eq!(
+            state.external_change("SELECT 4".into(), strategy),
+            changed("SELECT 4")
+        );
+    }
+
+    #[test]
+    fn handles_conflicts() {
+        let mut state = SyncState::new("SELECT 1");
+        state.local_change("SELECT 'pgpad'".into());
+        assert_eq!(
+            state.external_change("SELECT 'editor'".into(), ConflictStrategy::ExternalWins),
+            changed("SELECT 'editor'")
+        );
+
+        state.local_change("SELECT 'pgpad'".into());
+        assert_eq!(
+            state.external_change("SELECT 'editor 2'".into(), ConflictStrategy::PgpadWins),
+            Reaction::WriteBack("SELECT 'pgpad'".into())
+        );
+        // Our own write coming back
+        assert_eq!(
+            state.external_change("SELECT 'pgpad'".into(), ConflictStrategy::PgpadWins),
+            Reaction::Nothing
+        );
+
+        state.local_change("SELECT 'pgpad 2'".into());
+        assert_eq!(
+            state.external_change("SELECT 'editor 3'".into(), ConflictStrategy::Prompt),
+            Reaction::Report(ExternalEditChange::Conflict {
+                contents: "SELECT 'editor 3'".into()
+            })
+        );
+        assert_eq!(
+            state.resolve(ConflictResolution::KeepPgpad),
+            Some(("SELECT 'pgpad 2'".into(), true))
+        );
+        assert_eq!(state.resolve(ConflictResolution::KeepPgpad), None);
+    }
+
+    #[test]
+    fn creates_private_files() {
+        let dir = std::env::temp_dir().join(format!("pgpad-edit-test-{}", Uuid::new_v4()));
+        let path = dir.join(format!("{}.sql", file_stem("reports/monthly.sql")));
+        create_private_file(&dir, &path, "SELECT 1").unwrap();
+
+        assert_eq!(path.file_name().unwrap(), "reports_monthly.sql");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "SELECT 1");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            let mode = fs::metadata(&dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
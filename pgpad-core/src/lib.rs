@@ -1,21 +1,47 @@
+pub mod connection_file;
 mod credentials;
 pub mod database;
 mod error;
+pub mod external_edit;
+pub mod linked_scripts;
+pub mod operations;
+pub mod script_file;
+pub mod script_templates;
 pub mod storage;
+pub mod tree_state;
 mod utils;
 
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use dashmap::DashMap;
 use uuid::Uuid;
 
 use crate::{
+    credentials::{CredentialFile, SecretCache, CREDENTIAL_FILE_NAME},
     database::{
+        execution_marks::ExecutionMarks,
+        postgres::{messages::ServerMessages, notifications::Notifications, stats::StatsCache},
+        preflight::PendingRun,
+        reconnect::Reconnects,
+        result_cache::{self, SPILL_DIR},
+        scheduler::Scheduler,
+        schema_cache::SchemaCache,
+        semantic_search::SemanticIndex,
+        sqlite::snapshot::{SnapshotCache, DEFAULT_MAX_CACHE_BYTES},
         stmt_manager::StatementManager,
-        types::{Connection, ConnectionRuntime, DatabaseSchema},
+        trace::StatementTraces,
+        transactions::Transactions,
+        types::{Connection, ConnectionRuntime},
     },
+    external_edit::ExternalEdits,
+    linked_scripts::ScriptWatchers,
+    operations::OperationRegistry,
     storage::Storage,
 };
+pub use credentials::{CredentialBackendStatus, CredentialStore, SecretBackend};
 pub use database::{Certificates, ConnectionMonitor};
 pub use error::{Error, Result};
 pub use storage::{QueryHistoryEntry, SavedQuery};
@@ -23,30 +49,102 @@ pub use storage::{QueryHistoryEntry, SavedQuery};
 #[derive(Debug)]
 pub struct AppState {
     pub connections: DashMap<Uuid, Connection>,
-    pub schemas: DashMap<Uuid, Arc<DatabaseSchema>>,
+    pub schemas: SchemaCache,
     /// SQLite database for application data
     pub storage: Storage,
     pub stmt_manager: StatementManager,
+    pub script_watchers: ScriptWatchers,
+    pub secret_cache: SecretCache,
+    /// Where secrets go when the keyring fails, next to the storage database
+    pub credential_file: CredentialFile,
+    /// Runs held back by pre-flight checks, until they're confirmed
+    pub pending_runs: DashMap<Uuid, PendingRun>,
+    pub operations: OperationRegistry,
+    pub statement_traces: StatementTraces,
+    pub external_edits: ExternalEdits,
+    /// Local copies of the SQLite files opened as snapshots, kept next to the storage database. Shared with the
+    /// blocking threads that copy them.
+    pub snapshots: Arc<SnapshotCache>,
+    /// Which statements of each editor tab were run, and how that went
+    pub execution_marks: ExecutionMarks,
+    /// Vectors of each connection's tables, for semantic search
+    pub semantic_index: SemanticIndex,
+    /// Channels listened on by Postgres connections, and the notifications they receive
+    pub notifications: Notifications,
+    /// Notices Postgres connections got outside of statements
+    pub server_messages: ServerMessages,
+    /// Transactions opened from editor tabs, by connection and tab
+    pub transactions: Transactions,
+    /// Connections being reconnected after dropping
+    pub reconnects: Reconnects,
+    /// The latest server statistics of each Postgres connection
+    pub postgres_stats: StatsCache,
+    /// The scheduled queries being run, and where their failures and alerts are reported
+    pub scheduler: Scheduler,
 }
 
 impl AppState {
     pub fn new(db_path: impl Into<PathBuf>) -> Result<Self> {
-        let storage = Storage::new(db_path.into())?;
+        let db_path = db_path.into();
+        let data_dir = db_path
+            .parent()
+            .map_or_else(std::env::temp_dir, Path::to_path_buf);
+        let snapshot_dir = data_dir.join("snapshots");
+        let storage = Storage::new(db_path)?;
+
+        let spill_dir = data_dir.join(SPILL_DIR);
+        result_cache::remove_orphans(&spill_dir);
+        let stmt_manager = StatementManager::with_spill_dir(spill_dir);
+        match storage.get_result_cache_settings() {
+            Ok(settings) => stmt_manager.set_spill_threshold(settings.spill_threshold_bytes),
+            Err(err) => log::warn!("Failed to read the result cache settings: {err}"),
+        }
+
+        let pruned = storage.get_history_settings().and_then(|settings| {
+            storage.prune_query_history(&settings, chrono::Utc::now().timestamp())
+        });
+        match pruned {
+            Ok(report) if report.deleted_entries > 0 => log::info!(
+                "Pruned {} history entries, reclaiming {} bytes",
+                report.deleted_entries,
+                report.reclaimed_bytes
+            ),
+            Ok(_) => {}
+            Err(err) => log::warn!("Failed to prune the query history: {err}"),
+        }
 
         Ok(Self {
             connections: DashMap::new(),
-            schemas: DashMap::new(),
+            schemas: SchemaCache::new(),
             storage,
-            stmt_manager: StatementManager::new(),
+            stmt_manager,
+            script_watchers: ScriptWatchers::new(),
+            secret_cache: SecretCache::new(),
+            credential_file: CredentialFile::new(data_dir.join(CREDENTIAL_FILE_NAME)),
+            pending_runs: DashMap::new(),
+            operations: OperationRegistry::new(),
+            statement_traces: StatementTraces::new(),
+            external_edits: ExternalEdits::new(),
+            snapshots: Arc::new(SnapshotCache::new(snapshot_dir, DEFAULT_MAX_CACHE_BYTES)),
+            execution_marks: ExecutionMarks::new(),
+            semantic_index: SemanticIndex::new(),
+            notifications: Notifications::new(),
+            server_messages: ServerMessages::new(),
+            transactions: Transactions::new(),
+            reconnects: Reconnects::new(),
+            postgres_stats: StatsCache::new(),
+            scheduler: Scheduler::new(),
         })
     }
 
-    pub fn mark_disconnected(&self, connection_id: Uuid) -> bool {
-        let Some(mut connection) = self.connections.get_mut(&connection_id) else {
-            return false;
-        };
+    /// Returns whether the connection was connected until now, or None if there's no such connection
+    pub fn mark_disconnected(&self, connection_id: Uuid) -> Option<bool> {
+        let mut connection = self.connections.get_mut(&connection_id)?;
 
+        let was_connected = connection.is_client_connected();
         connection.runtime = ConnectionRuntime::Disconnected;
-        true
+        connection.tunnel = None;
+        self.transactions.forget(connection_id);
+        Some(was_connected)
     }
 }
@@ -0,0 +1,356 @@
+//! "Linked" script directories: `.sql` files that live on disk (e.g. in a git repository) and are listed
+//! in the scripts sidebar without being copied into our storage.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{mpsc as std_mpsc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use dashmap::DashMap;
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use walkdir::WalkDir;
+
+use crate::{storage::LinkedScriptDirectory, Error};
+
+/// Editors usually write a file in several steps (truncate, write, rename, ...), so we wait for
+/// the events to settle before reporting a change
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Filesystems on which change notifications are unreliable, or not delivered at all
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "afs",
+    "fuse.sshfs",
+    "fuse.rclone",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkedScript {
+    pub path: String,
+    /// Path relative to the linked directory, e.g. `reports/monthly.sql`
+    pub relative_path: String,
+    pub name: String,
+    pub modified_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedScriptDirectory {
+    #[serde(flatten)]
+    pub directory: LinkedScriptDirectory,
+    /// The directory was deleted or moved since it was linked
+    pub missing: bool,
+    /// False if external changes won't be picked up, e.g. on network filesystems
+    pub watched: bool,
+    pub scripts: Vec<LinkedScript>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkedScriptChangeKind {
+    /// Created or modified
+    Changed,
+    Removed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkedScriptChange {
+    pub directory_id: i64,
+    pub path: String,
+    pub kind: LinkedScriptChangeKind,
+}
+
+/// Lists the `.sql` files under `dir`, skipping hidden files and directories (e.g. `.git`)
+pub fn index_directory(dir: &Path) -> Result<Vec<LinkedScript>, Error> {
+    let mut scripts = Vec::new();
+
+    let entries = WalkDir::new(dir)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !is_hidden(entry.file_name()));
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                log::warn!("Skipping entry while indexing {}: {err}", dir.display());
+                continue;
+            }
+        };
+
+        if !entry.file_type().is_file() || !is_sql_file(entry.path()) {
+            continue;
+        }
+
+        let path = entry.path();
+        let relative_path = path.strip_prefix(dir).unwrap_or(path);
+        let modified_at = entry
+            .metadata()
+            .ok()
+            .and_then(|metadata| metadata.modified().ok())
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|elapsed| elapsed.as_secs() as i64);
+
+        scripts.push(LinkedScript {
+            path: path.to_string_lossy().into_owned(),
+            relative_path: relative_path.to_string_lossy().into_owned(),
+            name: path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            modified_at,
+        });
+    }
+
+    Ok(scripts)
+}
+
+/// Resolves `path`, making sure it's a `.sql` file inside one of `directories`
+pub fn resolve_linked_script(
+    path: &Path,
+    directories: &[LinkedScriptDirectory],
+) -> Result<PathBuf, Error> {
+    if !path.exists() {
+        return Err(anyhow::anyhow!(
+            "{} was deleted or moved. Re-index its directory to refresh the list of scripts.",
+            path.display()
+        )
+        .into());
+    }
+
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", path.display()))?;
+
+    let in_linked_directory = directories.iter().any(|directory| {
+        Path::new(&directory.path)
+            .canonicalize()
+            .is_ok_and(|dir| path.starts_with(dir))
+    });
+
+    if !in_linked_directory || !is_sql_file(&path) {
+        return Err(
+            anyhow::anyhow!("{} is not a script in a linked directory", path.display()).into(),
+        );
+    }
+
+    Ok(path)
+}
+
+fn is_hidden(file_name: &std::ffi::OsStr) -> bool {
+    file_name.to_string_lossy().starts_with('.')
+}
+
+fn is_sql_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("sql"))
+}
+
+/// Best-effort check for whether `path` is on a network filesystem
+pub fn is_network_filesystem(path: &Path) -> bool {
+    if cfg!(windows) {
+        // UNC paths, e.g. `\\server\share`
+        return path.to_string_lossy().starts_with(r"\\");
+    }
+
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    let Ok(mounts) = std::fs::read_to_string("/proc/mounts") else {
+        return false;
+    };
+
+    // The filesystem of the longest mount point containing `path`
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let mount_point = fields.next()?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            Some((mount_point, fs_type))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .is_some_and(|(_, fs_type)| NETWORK_FILESYSTEMS.contains(&fs_type))
+}
+
+/// Watches linked directories, reporting changes to `.sql` files through the receiver
+/// given out by [`ScriptWatchers::subscribe`].
+///
+/// Nothing is watched until someone subscribes (e.g. the web server never does).
+#[derive(Default)]
+pub struct ScriptWatchers {
+    watchers: DashMap<i64, notify::RecommendedWatcher>,
+    sender: Mutex<Option<UnboundedSender<LinkedScriptChange>>>,
+}
+
+impl std::fmt::Debug for ScriptWatchers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ScriptWatchers")
+    }
+}
+
+impl ScriptWatchers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> UnboundedReceiver<LinkedScriptChange> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.sender.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Starts watching a directory, if it isn't already being watched.
+    /// Returns whether the directory is being watched.
+    pub fn watch(&self, directory_id: i64, dir: &Path) -> bool {
+        if self.watchers.contains_key(&directory_id) {
+            return true;
+        }
+
+        let Some(sender) = self.sender.lock().unwrap().clone() else {
+            return false;
+        };
+
+        if is_network_filesystem(dir) {
+            log::info!(
+                "Not watching {}, since it's on a network filesystem",
+                dir.display()
+            );
+            return false;
+        }
+
+        let (events_tx, events_rx) = std_mpsc::channel();
+        let watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) => {
+                    for path in event.paths {
+                        let _ = events_tx.send(path);
+                    }
+                }
+                Err(err) => log::warn!("Error watching linked scripts: {err}"),
+            })
+            .and_then(|mut watcher| {
+                watcher.watch(dir, RecursiveMode::Recursive)?;
+                Ok(watcher)
+            });
+
+        let watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(err) => {
+                log::warn!("Failed to watch {}: {err}", dir.display());
+                return false;
+            }
+        };
+
+        // Ends once the watcher (and with it, `events_tx`) is dropped
+        std::thread::spawn(move || debounce_changes(directory_id, events_rx, sender));
+
+        self.watchers.insert(directory_id, watcher);
+        true
+    }
+
+    pub fn unwatch(&self, directory_id: i64) {
+        self.watchers.remove(&directory_id);
+    }
+}
+
+fn debounce_changes(
+    directory_id: i64,
+    events: std_mpsc::Receiver<PathBuf>,
+    sender: UnboundedSender<LinkedScriptChange>,
+) {
+    while let Ok(path) = events.recv() {
+        let mut changed = HashSet::from([path]);
+        while let Ok(path) = events.recv_timeout(DEBOUNCE) {
+            changed.insert(path);
+        }
+
+        for path in changed.into_iter().filter(|path| is_sql_file(path)) {
+            let kind = if path.exists() {
+                LinkedScriptChangeKind::Changed
+            } else {
+                LinkedScriptChangeKind::Removed
+            };
+
+            let change = LinkedScriptChange {
+                directory_id,
+                path: path.to_string_lossy().into_owned(),
+                kind,
+            };
+            if sender.send(change).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pgpad-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("reports")).unwrap();
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::write(dir.join("setup.sql"), "CREATE TABLE t (id int);").unwrap();
+        std::fs::write(dir.join("reports/monthly.SQL"), "SELECT 1;").unwrap();
+        std::fs::write(dir.join("reports/notes.md"), "# notes").unwrap();
+        std::fs::write(dir.join(".git/hidden.sql"), "SELECT 2;").unwrap();
+        dir
+    }
+
+    fn linked(dir: &Path) -> Vec<LinkedScriptDirectory> {
+        vec![LinkedScriptDirectory {
+            id: 1,
+            path: dir.to_string_lossy().into_owned(),
+            connection_id: None,
+            created_at: 0,
+        }]
+    }
+
+    #[test]
+    fn indexes_sql_files() {
+        let dir = temp_dir();
+        let scripts = index_directory(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let relative_paths: Vec<_> = scripts
+            .iter()
+            .map(|script| script.relative_path.replace('\\', "/"))
+            .collect();
+        assert_eq!(relative_paths, ["reports/monthly.SQL", "setup.sql"]);
+        assert_eq!(scripts[0].name, "monthly");
+    }
+
+    #[test]
+    fn only_resolves_scripts_in_linked_directories() {
+        let dir = temp_dir();
+        let directories = linked(&dir.join("reports"));
+
+        assert!(resolve_linked_script(&dir.join("reports/monthly.SQL"), &directories).is_ok());
+        assert!(resolve_linked_script(&dir.join("reports/notes.md"), &directories).is_err());
+        assert!(resolve_linked_script(&dir.join("setup.sql"), &directories).is_err());
+        assert!(resolve_linked_script(&dir.join("reports/../setup.sql"), &directories).is_err());
+
+        std::fs::remove_file(dir.join("reports/monthly.SQL")).unwrap();
+        let err = resolve_linked_script(&dir.join("reports/monthly.SQL"), &directories)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("deleted or moved"), "{err}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+//! Registry of long-running operations (exports, ...), so the UI can show their progress and cancel them
+//! from a single place.
+
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Export,
+    ConnectionProbe,
+    /// Copying a SQLite file to open it as a snapshot
+    Snapshot,
+    ColumnStats,
+    /// Loading a file into a table
+    Import,
+    /// Sorting the rows of a big result in place
+    Sort,
+    /// Finding the rows of a big result that match a filter
+    Filter,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationInfo {
+    pub id: Uuid,
+    pub kind: OperationKind,
+    /// e.g. "Exporting to /home/me/results.csv"
+    pub description: String,
+    /// From 0 to 1, if the operation knows how far along it is
+    pub progress: Option<f64>,
+    /// What's done so far, e.g. "1200 rows loaded", if the operation says
+    pub detail: Option<String>,
+    pub cancellable: bool,
+    pub started_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum OperationOutcome {
+    Completed,
+    Cancelled,
+    Failed { error: String },
+}
+
+/// Sent once an operation is over, whatever the outcome
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationFinished {
+    #[serde(flatten)]
+    pub operation: OperationInfo,
+    #[serde(flatten)]
+    pub outcome: OperationOutcome,
+}
+
+struct RunningOperation {
+    info: Mutex<OperationInfo>,
+    token: CancellationToken,
+}
+
+/// Keeps track of the running operations, reporting finished ones through the receiver given out by
+/// [`OperationRegistry::subscribe`]
+#[derive(Default)]
+pub struct OperationRegistry {
+    operations: DashMap<Uuid, RunningOperation>,
+    sender: Mutex<Option<UnboundedSender<OperationFinished>>>,
+}
+
+impl std::fmt::Debug for OperationRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OperationRegistry")
+    }
+}
+
+impl OperationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> UnboundedReceiver<OperationFinished> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.sender.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Registers an operation, which stays listed until the returned handle is dropped
+    pub fn start(
+        &self,
+        kind: OperationKind,
+        description: impl Into<String>,
+        cancellable: bool,
+    ) -> Operation<'_> {
+        let id = Uuid::new_v4();
+        let token = CancellationToken::new();
+        let info = OperationInfo {
+            id,
+            kind,
+            description: description.into(),
+            progress: None,
+            detail: None,
+            cancellable,
+            started_at: chrono::Utc::now().timestamp(),
+        };
+
+        self.operations.insert(
+            id,
+            RunningOperation {
+                info: Mutex::new(info),
+                token: token.clone(),
+            },
+        );
+
+        Operation {
+            registry: self,
+            id,
+            token,
+            outcome: None,
+        }
+    }
+
+    pub fn list(&self) -> Vec<OperationInfo> {
+        let mut operations: Vec<_> = self
+            .operations
+            .iter()
+            .map(|entry| entry.info.lock().unwrap().clone())
+            .collect();
+        operations.sort_by_key(|operation| operation.started_at);
+        operations
+    }
+
+    /// Requests an operation to stop. It's up to the operation to notice, so it may still finish normally.
+    pub fn cancel(&self, id: Uuid) -> Result<(), Error> {
+        let operation = self
+            .operations
+            .get(&id)
+            .ok_or_else(|| anyhow::anyhow!("Operation not found: {id}"))?;
+
+        if !operation.info.lock().unwrap().cancellable {
+            return Err(anyhow::anyhow!("This operation can't be cancelled").into());
+        }
+
+        operation.token.cancel();
+        Ok(())
+    }
+}
+
+/// Handle to a running operation. Dropping it unregisters the operation and reports its outcome.
+pub struct Operation<'a> {
+    registry: &'a OperationRegistry,
+    id: Uuid,
+    token: CancellationToken,
+    outcome: Option<OperationOutcome>,
+}
+
+impl Operation<'_> {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    pub fn set_progress(&self, done: usize, total: usize) {
+        if let Some(operation) = self.registry.operations.get(&self.id) {
+            let progress = if total == 0 {
+                1.0
+            } else {
+                done as f64 / total as f64
+            };
+            operation.info.lock().unwrap().progress = Some(progress.min(1.0));
+        }
+    }
+
+    pub fn set_detail(&self, detail: impl Into<String>) {
+        if let Some(operation) = self.registry.operations.get(&self.id) {
+            operation.info.lock().unwrap().detail = Some(detail.into());
+        }
+    }
+
+    /// Fails if the operation was cancelled. Meant to be called between units of work (e.g. pages).
+    pub fn check_cancelled(&self) -> Result<(), Error> {
+        if self.token.is_cancelled() {
+            return Err(anyhow::anyhow!("Operation cancelled").into());
+        }
+        Ok(())
+    }
+
+    /// Records the outcome of the operation, passing its result through
+    pub fn complete<T>(mut self, result: Result<T, Error>) -> Result<T, Error> {
+        self.outcome = Some(match &result {
+            Ok(_) => OperationOutcome::Completed,
+            Err(_) if self.token.is_cancelled() => OperationOutcome::Cancelled,
+            Err(err) => OperationOutcome::Failed {
+                error: err.to_string(),
+            },
+        });
+        result
+    }
+}
+
+impl Drop for Operation<'_> {
+    fn drop(&mut self) {
+        let Some((_, operation)) = self.registry.operations.remove(&self.id) else {
+            return;
+        };
+
+        let outcome = self
+            .outcome
+            .take()
+            .unwrap_or_else(|| OperationOutcome::Failed {
+                error: "The operation ended unexpectedly".into(),
+            });
+        let finished = OperationFinished {
+            operation: operation.info.into_inner().unwrap(),
+            outcome,
+        };
+
+        if let Some(sender) = self.registry.sender.lock().unwrap().as_ref() {
+            let _ = sender.send(finished);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_finished_operations() {
+        let registry = OperationRegistry::new();
+        let mut finished = registry.subscribe();
+
+        let operation = registry.start(OperationKind::Export, "Exporting", true);
+        operation.set_progress(1, 4);
+        operation.set_detail("1 page of 4");
+        let listed = registry.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].progress, Some(0.25));
+        assert_eq!(listed[0].detail.as_deref(), Some("1 page of 4"));
+
+        assert_eq!(operation.complete(Ok(())).ok(), Some(()));
+        assert!(registry.list().is_empty());
+        assert_eq!(
+            finished.try_recv().unwrap().outcome,
+            OperationOutcome::Completed
+        );
+
+        // Dropped without completing, e.g. after a panic
+        drop(registry.start(OperationKind::Export, "Exporting", true));
+        assert!(matches!(
+            finished.try_recv().unwrap().outcome,
+            OperationOutcome::Failed { .. }
+        ));
+    }
+
+    #[test]
+    fn cancels_operations() {
+        let registry = OperationRegistry::new();
+        let mut finished = registry.subscribe();
+
+        let operation = registry.start(OperationKind::Export, "Exporting", true);
+        assert!(operation.check_cancelled().is_ok());
+
+        registry.cancel(operation.id()).unwrap();
+        let result = operation.check_cancelled();
+        assert!(result.is_err());
+        assert!(operation.complete(result).is_err());
+        assert_eq!(
+            finished.try_recv().unwrap().outcome,
+            OperationOutcome::Cancelled
+        );
+
+        let uncancellable = registry.start(OperationKind::Export, "Exporting", false);
+        assert!(registry.cancel(uncancellable.id()).is_err());
+        assert!(registry.cancel(Uuid::new_v4()).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Reading `.sql` files that weren't written by pgpad, e.g. ones dropped on the window or opened through a file association.
+
+use std::{fs::File, io::Read, path::Path};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::{
+    database::parser::{find_destructive_statements, DestructiveStatements},
+    Error,
+};
+
+pub const DEFAULT_MAX_SCRIPT_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptFile {
+    pub path: String,
+    /// Decoded contents, with `\n` line endings
+    pub content: String,
+    pub encoding: TextEncoding,
+    pub size_bytes: u64,
+    /// The file is larger than the configured limit, so `content` only has its beginning
+    /// and should not be editable or runnable as-is
+    pub read_only_preview: bool,
+    /// None if the contents could not be scanned, which should be treated as "possibly destructive"
+    pub destructive_statements: Option<Vec<DestructiveStatements>>,
+}
+
+pub fn read_script_file(path: &Path, max_bytes: u64) -> Result<ScriptFile, Error> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let size_bytes = file
+        .metadata()
+        .with_context(|| format!("Failed to read metadata of {}", path.display()))?
+        .len();
+
+    let read_only_preview = size_bytes > max_bytes;
+
+    let mut bytes = Vec::new();
+    file.take(max_bytes)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let (content, encoding) = decode(&bytes);
+    let content = normalize_line_endings(&content);
+
+    let destructive_statements = match find_destructive_statements(&content) {
+        Ok(statements) => Some(statements),
+        Err(err) => {
+            log::warn!(
+                "Could not scan {} for destructive statements: {err}",
+                path.display()
+            );
+            None
+        }
+    };
+
+    Ok(ScriptFile {
+        path: path.to_string_lossy().into_owned(),
+        content,
+        encoding,
+        size_bytes,
+        read_only_preview,
+        destructive_statements,
+    })
+}
+
+fn decode(bytes: &[u8]) -> (String, TextEncoding) {
+    if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return (
+            String::from_utf8_lossy(rest).into_owned(),
+            TextEncoding::Utf8,
+        );
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        return (
+            decode_utf16(rest, u16::from_le_bytes),
+            TextEncoding::Utf16Le,
+        );
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        return (
+            decode_utf16(rest, u16::from_be_bytes),
+            TextEncoding::Utf16Be,
+        );
+    }
+
+    // BOM-less UTF-16: SQL is mostly ASCII, so every other byte is a NUL
+    if let Some(encoding) = guess_utf16(bytes) {
+        let content = match encoding {
+            TextEncoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+            _ => decode_utf16(bytes, u16::from_be_bytes),
+        };
+        return (content, encoding);
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(s) => (s.to_owned(), TextEncoding::Utf8),
+        // Only the last character is incomplete, which happens when we truncate a preview
+        Err(err) if err.error_len().is_none() => (
+            String::from_utf8_lossy(&bytes[..err.valid_up_to()]).into_owned(),
+            TextEncoding::Utf8,
+        ),
+        // Every byte is a valid Latin-1 character, and Latin-1 maps 1:1 onto the first 256 code points
+        Err(_) => (
+            bytes.iter().map(|&b| b as char).collect(),
+            TextEncoding::Latin1,
+        ),
+    }
+}
+
+fn guess_utf16(bytes: &[u8]) -> Option<TextEncoding> {
+    let sample = &bytes[..bytes.len().min(1024)];
+    if sample.len() < 2 {
+        return None;
+    }
+
+    let pairs = sample.len() / 2;
+    let even_nuls = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_nuls = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|&&b| b == 0)
+        .count();
+
+    if odd_nuls * 3 > pairs * 2 && even_nuls * 10 < pairs {
+        Some(TextEncoding::Utf16Le)
+    } else if even_nuls * 3 > pairs * 2 && odd_nuls * 10 < pairs {
+        Some(TextEncoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+fn decode_utf16(bytes: &[u8], to_u16: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|chunk| to_u16([chunk[0], chunk[1]]));
+
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn normalize_line_endings(s: &str) -> String {
+    s.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_common_encodings() {
+        assert_eq!(
+            decode("SELECT 'ç';".as_bytes()),
+            ("SELECT 'ç';".to_owned(), TextEncoding::Utf8)
+        );
+        assert_eq!(
+            decode(b"\xEF\xBB\xBFSELECT 1;"),
+            ("SELECT 1;".to_owned(), TextEncoding::Utf8)
+        );
+        assert_eq!(
+            decode(b"SELECT '\xE7';"),
+            ("SELECT 'ç';".to_owned(), TextEncoding::Latin1)
+        );
+
+        let utf16le: Vec<u8> = "SELECT 1;"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(
+            decode(&utf16le),
+            ("SELECT 1;".to_owned(), TextEncoding::Utf16Le)
+        );
+
+        let utf16be: Vec<u8> = [0xFE, 0xFF]
+            .into_iter()
+            .chain("SELECT 1;".encode_utf16().flat_map(u16::to_be_bytes))
+            .collect();
+        assert_eq!(
+            decode(&utf16be),
+            ("SELECT 1;".to_owned(), TextEncoding::Utf16Be)
+        );
+    }
+
+    #[test]
+    fn truncated_utf8_is_not_latin1() {
+        let bytes = "SELECT 'ç'".as_bytes();
+        // Cut in the middle of 'ç'
+        let (content, encoding) = decode(&bytes[..9]);
+        assert_eq!(encoding, TextEncoding::Utf8);
+        assert_eq!(content, "SELECT '");
+    }
+
+    #[test]
+    fn normalizes_line_endings() {
+        assert_eq!(
+            normalize_line_endings("SELECT 1;\r\nSELECT 2;\rSELECT 3;\n"),
+            "SELECT 1;\nSELECT 2;\nSELECT 3;\n"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Starter templates for new scripts. Built-in ones ship with pgpad, and user-defined ones (stored in
+//! [`Storage`](crate::storage::Storage)) replace the built-in ones they share a name with.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{database::types::Database, storage::Storage, Error};
+
+/// Prefix of the ids of user-defined templates, which are followed by their row id
+const USER_TEMPLATE_PREFIX: &str = "user-";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptTemplate {
+    /// e.g. `postgres-cte-window` for built-in templates, `user-3` for user-defined ones
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub body: String,
+    pub builtin: bool,
+}
+
+impl ScriptTemplate {
+    pub fn user_template_id(row_id: i64) -> String {
+        format!("{USER_TEMPLATE_PREFIX}{row_id}")
+    }
+}
+
+struct BuiltinTemplate {
+    id: &'static str,
+    db_type: Database,
+    name: &'static str,
+    description: &'static str,
+    body: &'static str,
+}
+
+impl BuiltinTemplate {
+    fn to_template(&self) -> ScriptTemplate {
+        ScriptTemplate {
+            id: self.id.to_owned(),
+            name: self.name.to_owned(),
+            description: Some(self.description.to_owned()),
+            body: self.body.to_owned(),
+            builtin: true,
+        }
+    }
+}
+
+const BUILTIN_TEMPLATES: &[BuiltinTemplate] = &[
+    BuiltinTemplate {
+        id: "postgres-cte-window",
+        db_type: Database::Postgres,
+        name: "CTE with a window function",
+        description: "Ranks rows within groups",
+        body: "WITH ranked AS (
+    SELECT
+        *,
+        row_number() OVER (PARTITION BY category ORDER BY created_at DESC) AS rank
+    FROM items
+)
+SELECT *
+FROM ranked
+WHERE rank <= 3;
+",
+    },
+    BuiltinTemplate {
+        id: "postgres-upsert",
+        db_type: Database::Postgres,
+        name: "Upsert",
+        description: "Inserts a row, or updates it if it already exists",
+        body: "INSERT INTO items (id, name)
+VALUES (1, 'example')
+ON CONFLICT (id) DO UPDATE
+SET name = EXCLUDED.name
+RETURNING *;
+",
+    },
+    BuiltinTemplate {
+        id: "postgres-transaction",
+        db_type: Database::Postgres,
+        name: "Transaction",
+        description: "Makes changes that can be checked before committing them",
+        body: "BEGIN;
+
+UPDATE items
+SET name = 'example'
+WHERE id = 1;
+
+-- Check the changes, then COMMIT or ROLLBACK
+SELECT * FROM items WHERE id = 1;
+",
+    },
+    BuiltinTemplate {
+        id: "sqlite-cte-window",
+        db_type: Database::Sqlite,
+        name: "CTE with a window function",
+        description: "Ranks rows within groups",
+        body: "WITH ranked AS (
+    SELECT
+        *,
+        row_number() OVER (PARTITION BY category ORDER BY created_at DESC) AS rank
+    FROM items
+)
+SELECT *
+FROM ranked
+WHERE rank <= 3;
+",
+    },
+    BuiltinTemplate {
+        id: "sqlite-upsert",
+        db_type: Database::Sqlite,
+        name: "Upsert",
+        description: "Inserts a row, or updates it if it already exists",
+        body: "INSERT INTO items (id, name)
+VALUES (1, 'example')
+ON CONFLICT (id) DO UPDATE
+SET name = excluded.name
+RETURNING *;
+",
+    },
+    BuiltinTemplate {
+        id: "sqlite-recursive-cte",
+        db_type: Database::Sqlite,
+        name: "Recursive CTE",
+        description: "Walks a tree of rows, e.g. categories and their parents",
+        body: "WITH RECURSIVE tree (id, parent_id, name, depth) AS (
+    SELECT id, parent_id, name, 0
+    FROM categories
+    WHERE parent_id IS NULL
+    UNION ALL
+    SELECT c.id, c.parent_id, c.name, tree.depth + 1
+    FROM categories c
+    JOIN tree ON c.parent_id = tree.id
+)
+SELECT * FROM tree ORDER BY depth, name;
+",
+    },
+];
+
+fn builtin_templates(db_type: Database) -> impl Iterator<Item = ScriptTemplate> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .filter(move |template| template.db_type == db_type)
+        .map(BuiltinTemplate::to_template)
+}
+
+/// The built-in templates, with the user-defined ones replacing those they share a name with
+fn merge_templates(
+    builtin: impl Iterator<Item = ScriptTemplate>,
+    user: Vec<ScriptTemplate>,
+) -> Vec<ScriptTemplate> {
+    let mut templates: Vec<_> = builtin
+        .filter(|template| !user.iter().any(|u| u.name == template.name))
+        .collect();
+    templates.extend(user);
+    templates
+}
+
+pub fn get_script_templates(
+    db_type: Database,
+    storage: &Storage,
+) -> Result<Vec<ScriptTemplate>, Error> {
+    let user = storage.get_script_templates(db_type)?;
+    Ok(merge_templates(builtin_templates(db_type), user))
+}
+
+pub fn find_script_template(id: &str, storage: &Storage) -> Result<ScriptTemplate, Error> {
+    let template = match id.strip_prefix(USER_TEMPLATE_PREFIX) {
+        Some(row_id) => {
+            let row_id = row_id
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid script template id: {id}"))?;
+            storage.get_script_template(row_id)?
+        }
+        None => BUILTIN_TEMPLATES
+            .iter()
+            .find(|template| template.id == id)
+            .map(BuiltinTemplate::to_template),
+    };
+
+    template.ok_or_else(|| anyhow::anyhow!("Script template not found: {id}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_templates_replace_builtins_by_name() {
+        let user = ScriptTemplate {
+            id: ScriptTemplate::user_template_id(1),
+            name: "Upsert".into(),
+            description: None,
+            body: "INSERT INTO mine ...".into(),
+            builtin: false,
+        };
+
+        let templates = merge_templates(builtin_templates(Database::Postgres), vec![user.clone()]);
+        let upserts: Vec<_> = templates.iter().filter(|t| t.name == "Upsert").collect();
+        assert_eq!(upserts, [&user]);
+        assert_eq!(
+            templates.len(),
+            builtin_templates(Database::Postgres).count()
+        );
+        assert!(templates.iter().any(|t| t.id == "postgres-transaction"));
+    }
+
+    #[test]
+    fn builtin_ids_are_unique() {
+        for (i, template) in BUILTIN_TEMPLATES.iter().enumerate() {
+            assert!(template.id.starts_with(template.db_type.as_str()));
+            assert!(!template.id.starts_with(USER_TEMPLATE_PREFIX));
+            assert!(BUILTIN_TEMPLATES[i + 1..]
+                .iter()
+                .all(|other| other.id != template.id));
+        }
+    }
+}
@@ -1,17 +1,48 @@
+use std::io::{Read, Write};
 use std::path::PathBuf;
 use std::sync::Mutex;
 
 use anyhow::Context;
-use rusqlite::{types::Type, Connection};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use rusqlite::{
+    params_from_iter,
+    types::{Type, Value as SqlValue},
+    Connection, OptionalExtension,
+};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 // Gotta match the IDs in the DB
 const DB_TYPE_POSTGRES: i32 = 1;
 const DB_TYPE_SQLITE: i32 = 2;
+const DB_TYPE_MYSQL: i32 = 3;
+
+/// Most history entries returned at once, whatever the caller asks for
+pub const MAX_HISTORY_PAGE_SIZE: i64 = 500;
+
+/// How far back running the same query again updates its earlier entry instead of adding another
+pub const HISTORY_DEDUPE_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// Drafts longer than this are stored deflated, in bytes
+const DRAFT_COMPRESSION_THRESHOLD: usize = 4 * 1024;
+
+/// Plans of history entries longer than this are stored deflated, in bytes
+const PLAN_COMPRESSION_THRESHOLD: usize = 4 * 1024;
+
+const HISTORY_SETTINGS_KEY: &str = "history_settings";
+const RESULT_CACHE_SETTINGS_KEY: &str = "result_cache_settings";
 
 use crate::{
-    database::types::{ConnectionConfig, ConnectionInfo, Permissions},
+    database::{
+        delimited::ExportOptions,
+        result_cache::ResultCacheSettings,
+        scheduler::{Schedule, ScheduledRunStatus},
+        types::{
+            rename_folder, ConnectionConfig, ConnectionInfo, Database, Environment, Permissions,
+            TimingBreakdown, FOLDER_SEPARATOR,
+        },
+    },
+    script_templates::ScriptTemplate,
     Result,
 };
 
@@ -26,6 +57,27 @@ impl Migrator {
                 include_str!("../migrations/001.sql"),
                 include_str!("../migrations/002.sql"),
                 include_str!("../migrations/003.sql"),
+                include_str!("../migrations/004.sql"),
+                include_str!("../migrations/005.sql"),
+                include_str!("../migrations/006.sql"),
+                include_str!("../migrations/007.sql"),
+                include_str!("../migrations/008.sql"),
+                include_str!("../migrations/009.sql"),
+                include_str!("../migrations/010.sql"),
+                include_str!("../migrations/011.sql"),
+                include_str!("../migrations/012.sql"),
+                include_str!("../migrations/013.sql"),
+                include_str!("../migrations/014.sql"),
+                include_str!("../migrations/015.sql"),
+                include_str!("../migrations/016.sql"),
+                include_str!("../migrations/017.sql"),
+                include_str!("../migrations/018.sql"),
+                include_str!("../migrations/019.sql"),
+                include_str!("../migrations/020.sql"),
+                include_str!("../migrations/021.sql"),
+                include_str!("../migrations/022.sql"),
+                include_str!("../migrations/023.sql"),
+                include_str!("../migrations/024.sql"),
             ],
         }
     }
@@ -98,6 +150,94 @@ pub struct QueryHistoryEntry {
     pub status: String,
     pub row_count: i64,
     pub error_message: Option<String>,
+    /// Where the time of each statement went
+    pub timings: Option<Vec<TimingBreakdown>>,
+    /// Times the query was run, the others within [`HISTORY_DEDUPE_WINDOW_SECS`] of each other having been
+    /// folded into this entry. The other fields are those of the latest run.
+    #[serde(default = "one")]
+    pub run_count: i64,
+    /// The plan of a run explained with ANALYZE, as JSON. Only loaded with [`Storage::get_query_history_entry`].
+    #[serde(default)]
+    pub plan_json: Option<String>,
+    /// Whether the entry has a plan, even when it isn't loaded
+    #[serde(default)]
+    pub has_plan: bool,
+    /// Whether the query was run by a schedule rather than from the editor
+    #[serde(default)]
+    pub scheduled: bool,
+}
+
+fn one() -> i64 {
+    1
+}
+
+/// What's kept in the query history. Pruned entries go on startup, or when pruning is asked for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistorySettings {
+    /// Whether running a query again updates its recent entry rather than adding another
+    pub dedupe: bool,
+    /// Entries kept across all connections, the newest ones
+    pub max_entries: Option<u64>,
+    /// Entries older than this are pruned
+    pub max_age_days: Option<u64>,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        Self {
+            dedupe: true,
+            max_entries: Some(50_000),
+            max_age_days: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct HistoryPruneReport {
+    pub deleted_entries: u64,
+    /// How much smaller the storage database got. It's only vacuumed if entries were deleted.
+    pub reclaimed_bytes: u64,
+}
+
+/// Narrows down the query history, e.g. when searching it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HistoryFilters {
+    /// Case-insensitive text the query must contain
+    pub text: Option<String>,
+    /// e.g. "success" or "error"
+    pub status: Option<String>,
+    /// Only entries with a plan
+    pub with_plan: bool,
+    /// Only scheduled runs, or only the others
+    pub scheduled: Option<bool>,
+}
+
+/// Narrows down saved scripts
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ScriptFilters {
+    /// A tag the script must have
+    pub tag: Option<String>,
+    pub favorites_only: bool,
+    /// Case-insensitive text the name, description or query must contain
+    pub search_text: Option<String>,
+}
+
+/// Where a page of history ends. It holds the entry's timestamp as it was when the page was read, since
+/// re-running its query moves the entry to the top of the history, and pruning removes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryCursor {
+    pub executed_at: i64,
+    pub id: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueryHistoryPage {
+    pub entries: Vec<QueryHistoryEntry>,
+    /// The last entry, to pass as `before` for the next page. None if there are no older entries.
+    pub next_cursor: Option<HistoryCursor>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -107,12 +247,128 @@ pub struct SavedQuery {
     pub description: Option<String>,
     pub query_text: String,
     pub connection_id: Option<Uuid>,
-    pub tags: Option<String>,
+    /// Kept as a JSON array
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub created_at: i64,
     pub updated_at: i64,
     pub favorite: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkedScriptDirectory {
+    pub id: i64,
+    pub path: String,
+    pub connection_id: Option<Uuid>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportTemplate {
+    pub id: i64,
+    pub name: String,
+    pub options: ExportOptions,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// Text expanded by completion when its prefix is typed. The body has tab stops like `$1`, `${1:table}` and `$0`
+/// for where the cursor ends up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    /// 0 for one that isn't saved yet
+    pub id: i64,
+    pub name: String,
+    /// e.g. `sel*`
+    pub prefix: String,
+    pub body: String,
+    /// Only offered on this connection
+    #[serde(default)]
+    pub connection_id: Option<Uuid>,
+    /// Only offered on this type of database
+    #[serde(default)]
+    pub db_type: Option<Database>,
+}
+
+/// Substituted into queries as `${name}`, see [`crate::database::variables`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryVariable {
+    /// 0 for one that isn't saved yet
+    pub id: i64,
+    pub name: String,
+    /// SQL, e.g. `'2024-01-01'` with its quotes
+    pub value: String,
+    /// Only defined on this connection, where it takes precedence over one of no connection of the same name
+    #[serde(default)]
+    pub connection_id: Option<Uuid>,
+}
+
+/// A query run in the background, see [`crate::database::scheduler`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledQuery {
+    /// 0 for one that isn't saved yet
+    pub id: i64,
+    pub name: String,
+    pub connection_id: Uuid,
+    pub query_text: String,
+    pub schedule: Schedule,
+    pub enabled: bool,
+    /// Whether runs returning rows raise an alert, e.g. for a query looking for rows that shouldn't exist
+    #[serde(default)]
+    pub alert_if_rows: bool,
+    /// Whether the statements the policy of the connection's environment holds back (e.g. a `DROP TABLE` on a
+    /// prod connection) were confirmed to run unattended
+    #[serde(default)]
+    pub confirmed_destructive: bool,
+    /// The last run, in seconds since the epoch. Left as-is when the schedule is saved.
+    #[serde(default)]
+    pub last_run_at: Option<i64>,
+    #[serde(default)]
+    pub last_status: Option<ScheduledRunStatus>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// A saved session, without its data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// e.g. the label of the window
+    pub session_id: String,
+    /// Of the frontend's format of the data
+    pub version: u32,
+    pub updated_at: i64,
+}
+
+/// The open tabs and layout of a window, as the frontend saved them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionState {
+    pub session_id: String,
+    pub version: u32,
+    pub data: String,
+    pub updated_at: i64,
+}
+
+/// The latest autosaved contents of an editor buffer with unsaved changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DraftBuffer {
+    /// Chosen by the frontend, e.g. after the window and the tab
+    pub buffer_id: String,
+    pub connection_id: Option<Uuid>,
+    pub content: String,
+    /// Offset of the cursor, as the editor counts it
+    pub cursor: u32,
+    pub updated_at: i64,
+}
+
+/// An embedding of a table, see [`crate::database::semantic_search`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaVector {
+    /// e.g. `analytics.f_customer_retention`
+    pub object_key: String,
+    pub content_hash: String,
+    pub vector: Vec<f32>,
+}
+
 #[derive(Debug)]
 pub struct Storage {
     conn: Mutex<Connection>,
@@ -153,23 +409,57 @@ impl Storage {
         let now = chrono::Utc::now().timestamp();
         let conn = self.conn.lock().unwrap();
 
-        let (db_type_id, connection_data, ca_cert_path) = match &connection.config {
+        let (
+            db_type_id,
+            connection_data,
+            ca_cert_path,
+            home_relative_path,
+            ssh_tunnel,
+            default_schema,
+        ) = match &connection.config {
             ConnectionConfig::Postgres {
                 connection_string,
                 ca_cert_path,
+                ssh_tunnel,
+                default_schema,
             } => (
                 DB_TYPE_POSTGRES,
                 connection_string.as_str(),
                 ca_cert_path.as_deref(),
+                None,
+                ssh_tunnel.as_ref().map(serde_json::to_string).transpose()?,
+                default_schema.as_deref(),
+            ),
+            ConnectionConfig::SQLite {
+                db_path,
+                home_relative_path,
+            } => (
+                DB_TYPE_SQLITE,
+                db_path.as_str(),
+                None,
+                home_relative_path.as_deref(),
+                None,
+                None,
+            ),
+            ConnectionConfig::MySQL {
+                connection_string,
+                ca_cert_path,
+                ssh_tunnel,
+            } => (
+                DB_TYPE_MYSQL,
+                connection_string.as_str(),
+                ca_cert_path.as_deref(),
+                None,
+                ssh_tunnel.as_ref().map(serde_json::to_string).transpose()?,
+                None,
             ),
-            ConnectionConfig::SQLite { db_path } => (DB_TYPE_SQLITE, db_path.as_str(), None),
         };
 
         conn.execute(
             "INSERT OR REPLACE INTO connections 
-             (id, name, connection_data, database_type_id, ca_cert_path, permissions, created_at, updated_at, sort_order) 
+             (id, name, connection_data, database_type_id, ca_cert_path, permissions, created_at, updated_at, sort_order, home_relative_path, ssh_tunnel, folder, environment, color, default_schema) 
              VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 
-                (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM connections))",
+                (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM connections), ?9, ?10, ?11, ?12, ?13, ?14)",
             (
                 &connection.id.to_string(),
                 &connection.name,
@@ -179,6 +469,12 @@ impl Storage {
                 connection.permissions.as_str(),
                 now,
                 now,
+                home_relative_path,
+                ssh_tunnel,
+                connection.folder.as_deref(),
+                connection.environment.as_ref().map(Environment::as_str),
+                connection.color.as_deref(),
+                default_schema,
             ),
         )
         .context("Failed to save connection")?;
@@ -190,22 +486,56 @@ impl Storage {
         let now = chrono::Utc::now().timestamp();
         let conn = self.conn.lock().unwrap();
 
-        let (db_type_id, connection_data, ca_cert_path) = match &connection.config {
+        let (
+            db_type_id,
+            connection_data,
+            ca_cert_path,
+            home_relative_path,
+            ssh_tunnel,
+            default_schema,
+        ) = match &connection.config {
             ConnectionConfig::Postgres {
                 connection_string,
                 ca_cert_path,
+                ssh_tunnel,
+                default_schema,
             } => (
                 DB_TYPE_POSTGRES,
                 connection_string.as_str(),
                 ca_cert_path.as_deref(),
+                None,
+                ssh_tunnel.as_ref().map(serde_json::to_string).transpose()?,
+                default_schema.as_deref(),
+            ),
+            ConnectionConfig::SQLite {
+                db_path,
+                home_relative_path,
+            } => (
+                DB_TYPE_SQLITE,
+                db_path.as_str(),
+                None,
+                home_relative_path.as_deref(),
+                None,
+                None,
+            ),
+            ConnectionConfig::MySQL {
+                connection_string,
+                ca_cert_path,
+                ssh_tunnel,
+            } => (
+                DB_TYPE_MYSQL,
+                connection_string.as_str(),
+                ca_cert_path.as_deref(),
+                None,
+                ssh_tunnel.as_ref().map(serde_json::to_string).transpose()?,
+                None,
             ),
-            ConnectionConfig::SQLite { db_path } => (DB_TYPE_SQLITE, db_path.as_str(), None),
         };
 
         let updated_rows = conn
             .execute(
                 "UPDATE connections 
-             SET name = ?2, connection_data = ?3, database_type_id = ?4, ca_cert_path = ?5, permissions = ?6, updated_at = ?7
+             SET name = ?2, connection_data = ?3, database_type_id = ?4, ca_cert_path = ?5, permissions = ?6, updated_at = ?7, home_relative_path = ?8, ssh_tunnel = ?9, folder = ?10, environment = ?11, color = ?12, default_schema = ?13
              WHERE id = ?1",
                 (
                     &connection.id.to_string(),
@@ -215,6 +545,12 @@ impl Storage {
                     ca_cert_path,
                     connection.permissions.as_str(),
                     now,
+                    home_relative_path,
+                    ssh_tunnel,
+                    connection.folder.as_deref(),
+                    connection.environment.as_ref().map(Environment::as_str),
+                    connection.color.as_deref(),
+                    default_schema,
                 ),
             )
             .context("Failed to update connection")?;
@@ -230,6 +566,8 @@ impl Storage {
     }
 
     // TODO: add `get_connection`
+    /// Sorted by folder, then by name, so that the connections of a folder and of its subfolders are next to each
+    /// other. Connections outside of folders come first.
     pub fn get_connections(&self) -> Result<Vec<ConnectionInfo>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn
@@ -237,7 +575,13 @@ impl Storage {
                 "SELECT c.id, c.name, c.connection_data, 
                         COALESCE(dt.name, 'postgres') as db_type,
                         c.ca_cert_path,
-                        COALESCE(c.permissions, 'read_write') as permissions
+                        COALESCE(c.permissions, 'read_write') as permissions,
+                        c.home_relative_path,
+                        c.ssh_tunnel,
+                        c.folder,
+                        c.environment,
+                        c.color,
+                        c.default_schema
                  FROM connections c
                  LEFT JOIN database_types dt ON c.database_type_id = dt.id
                  ORDER BY c.sort_order, c.name",
@@ -250,18 +594,37 @@ impl Storage {
                 let db_type: String = row.get(3)?;
                 let ca_cert_path: Option<String> = row.get(4)?;
                 let permissions_str: String = row.get(5)?;
+                let home_relative_path: Option<String> = row.get(6)?;
+                let ssh_tunnel = match row.get::<_, Option<String>>(7)? {
+                    Some(tunnel) => Some(serde_json::from_str(&tunnel).map_err(|err| {
+                        rusqlite::Error::FromSqlConversionFailure(7, Type::Text, Box::new(err))
+                    })?),
+                    None => None,
+                };
+
+                let default_schema: Option<String> = row.get(11)?;
 
                 let config = match db_type.as_str() {
                     "postgres" => ConnectionConfig::Postgres {
                         connection_string: connection_data,
                         ca_cert_path,
+                        ssh_tunnel,
+                        default_schema,
                     },
                     "sqlite" => ConnectionConfig::SQLite {
                         db_path: connection_data,
+                        home_relative_path,
+                    },
+                    "mysql" => ConnectionConfig::MySQL {
+                        connection_string: connection_data,
+                        ca_cert_path,
+                        ssh_tunnel,
                     },
                     _ => ConnectionConfig::Postgres {
                         connection_string: connection_data, // Default to postgres for unknown types
                         ca_cert_path,
+                        ssh_tunnel,
+                        default_schema,
                     },
                 };
 
@@ -273,9 +636,19 @@ impl Storage {
                         })?
                     },
                     name: row.get(1)?,
+                    folder: row.get(8)?,
+                    environment: row
+                        .get::<_, Option<String>>(9)?
+                        .as_deref()
+                        .and_then(Environment::from_storage_str),
+                    color: row.get(10)?,
                     permissions: Permissions::from_storage_str(&permissions_str),
                     config,
                     connected: false,
+                    snapshot: None,
+                    file_missing: false,
+                    capabilities: None,
+                    active_schema: None,
                 })
             })
             .context("Failed to query connections")?;
@@ -286,9 +659,49 @@ impl Storage {
                 .push(row.map_err(|e| anyhow::anyhow!("Failed to process connection row: {}", e))?);
         }
 
+        connections.sort_by_cached_key(|connection| {
+            let folder = connection.folder.as_ref().map(|folder| {
+                folder
+                    .split(FOLDER_SEPARATOR)
+                    .map(str::to_lowercase)
+                    .collect::<Vec<_>>()
+            });
+            (folder, connection.name.to_lowercase())
+        });
         Ok(connections)
     }
 
+    /// Renames a folder, moving its subfolders along. Returns the ids of the connections that moved.
+    pub fn rename_connection_folder(&self, old: &str, new: &str) -> Result<Vec<Uuid>> {
+        let now = chrono::Utc::now().timestamp();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let folders = tx
+            .prepare("SELECT id, folder FROM connections WHERE folder IS NOT NULL")?
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to read the folders of connections")?;
+
+        let mut moved = Vec::new();
+        for (id, folder) in folders {
+            let Some(renamed) = rename_folder(&folder, old, new) else {
+                continue;
+            };
+            tx.execute(
+                "UPDATE connections SET folder = ?2, updated_at = ?3 WHERE id = ?1",
+                (&id, renamed, now),
+            )
+            .context("Failed to move a connection to the renamed folder")?;
+            moved.push(Uuid::parse_str(&id).context("Invalid connection id")?);
+        }
+
+        tx.commit()?;
+        Ok(moved)
+    }
+
     pub fn remove_connection(&self, connection_id: &Uuid) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
@@ -310,12 +723,75 @@ impl Storage {
         Ok(())
     }
 
-    pub fn save_query_history(&self, entry: &QueryHistoryEntry) -> Result<()> {
+    /// When the connection was last opened, if ever
+    pub fn get_last_connected(&self, connection_id: &Uuid) -> Result<Option<i64>> {
         let conn = self.conn.lock().unwrap();
+        let last_connected = conn
+            .query_row(
+                "SELECT last_connected_at FROM connections WHERE id = ?1",
+                [connection_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Failed to get last connected time")?;
+        Ok(last_connected.flatten())
+    }
+
+    /// With `dedupe`, an entry of the same query on the same connection within [`HISTORY_DEDUPE_WINDOW_SECS`] is
+    /// updated with this run instead of adding another. Runs with a plan are always added, and entries with one are
+    /// never updated, so that plans can be compared over time.
+    pub fn save_query_history(&self, entry: &QueryHistoryEntry, dedupe: bool) -> Result<()> {
+        let timings_json = entry
+            .timings
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let (plan, plan_compressed) = match &entry.plan_json {
+            Some(plan) if plan.len() > PLAN_COMPRESSION_THRESHOLD => {
+                (Some(deflate(plan.as_bytes())?), true)
+            }
+            Some(plan) => (Some(plan.as_bytes().to_vec()), false),
+            None => (None, false),
+        };
+
+        let conn = self.conn.lock().unwrap();
+        if dedupe && plan.is_none() {
+            let updated = conn
+                .execute(
+                    "UPDATE query_history
+                     SET executed_at = ?3, duration_ms = ?4, status = ?5, row_count = ?6, error_message = ?7,
+                         timings = ?8, run_count = run_count + 1
+                     WHERE id = (
+                         SELECT id FROM query_history
+                         WHERE connection_id = ?1 AND query_text = ?2 AND executed_at >= ?9 AND plan IS NULL
+                           AND scheduled = ?10
+                         ORDER BY executed_at DESC, id DESC
+                         LIMIT 1
+                     )",
+                    (
+                        &entry.connection_id,
+                        &entry.query_text,
+                        entry.executed_at,
+                        entry.duration_ms,
+                        &entry.status,
+                        entry.row_count,
+                        &entry.error_message,
+                        &timings_json,
+                        entry.executed_at - HISTORY_DEDUPE_WINDOW_SECS,
+                        entry.scheduled,
+                    ),
+                )
+                .context("Failed to update query history")?;
+            if updated > 0 {
+                return Ok(());
+            }
+        }
+
         conn.execute(
             "INSERT INTO query_history 
-             (connection_id, query_text, executed_at, duration_ms, status, row_count, error_message)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+             (connection_id, query_text, executed_at, duration_ms, status, row_count, error_message, timings, plan,
+              plan_compressed, scheduled)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
             (
                 &entry.connection_id,
                 &entry.query_text,
@@ -324,48 +800,205 @@ impl Storage {
                 &entry.status,
                 entry.row_count,
                 &entry.error_message,
+                &timings_json,
+                plan,
+                plan_compressed,
+                entry.scheduled,
             ),
         )
         .context("Failed to save query history")?;
         Ok(())
     }
 
+    pub fn get_history_settings(&self) -> Result<HistorySettings> {
+        match self.get_setting(HISTORY_SETTINGS_KEY)? {
+            Some(settings) => Ok(serde_json::from_str(&settings)?),
+            None => Ok(HistorySettings::default()),
+        }
+    }
+
+    pub fn set_history_settings(&self, settings: &HistorySettings) -> Result<()> {
+        self.set_setting(HISTORY_SETTINGS_KEY, &serde_json::to_string(settings)?)
+    }
+
+    pub fn get_result_cache_settings(&self) -> Result<ResultCacheSettings> {
+        match self.get_setting(RESULT_CACHE_SETTINGS_KEY)? {
+            Some(settings) => Ok(serde_json::from_str(&settings)?),
+            None => Ok(ResultCacheSettings::default()),
+        }
+    }
+
+    pub fn set_result_cache_settings(&self, settings: &ResultCacheSettings) -> Result<()> {
+        self.set_setting(RESULT_CACHE_SETTINGS_KEY, &serde_json::to_string(settings)?)
+    }
+
+    /// Deletes the entries `settings` don't keep, as of `now` (in seconds since the epoch), then vacuums the
+    /// database so that the space they took is given back
+    pub fn prune_query_history(
+        &self,
+        settings: &HistorySettings,
+        now: i64,
+    ) -> Result<HistoryPruneReport> {
+        let conn = self.conn.lock().unwrap();
+        let mut deleted_entries = 0;
+
+        if let Some(max_age_days) = settings.max_age_days {
+            let cutoff = now.saturating_sub((max_age_days as i64).saturating_mul(24 * 60 * 60));
+            deleted_entries += conn
+                .execute("DELETE FROM query_history WHERE executed_at < ?1", [cutoff])
+                .context("Failed to prune old query history")?;
+        }
+        if let Some(max_entries) = settings.max_entries {
+            deleted_entries += conn
+                .execute(
+                    "DELETE FROM query_history WHERE id NOT IN (
+                         SELECT id FROM query_history ORDER BY executed_at DESC, id DESC LIMIT ?1
+                     )",
+                    [max_entries as i64],
+                )
+                .context("Failed to prune query history")?;
+        }
+
+        if deleted_entries == 0 {
+            return Ok(HistoryPruneReport {
+                deleted_entries: 0,
+                reclaimed_bytes: 0,
+            });
+        }
+
+        let before = database_size(&conn)?;
+        conn.execute_batch("VACUUM")
+            .context("Failed to vacuum the database")?;
+        let after = database_size(&conn)?;
+
+        Ok(HistoryPruneReport {
+            deleted_entries: deleted_entries as u64,
+            reclaimed_bytes: before.saturating_sub(after),
+        })
+    }
+
     pub fn get_query_history(
         &self,
         connection_id: &str,
         limit: Option<i64>,
     ) -> Result<Vec<QueryHistoryEntry>> {
         let limit = limit.unwrap_or(100);
+        let page =
+            self.get_query_history_page(connection_id, None, limit, &HistoryFilters::default())?;
+        Ok(page.entries)
+    }
+
+    /// Entries older than `before` (or the newest ones if None), newest first.
+    ///
+    /// Entries are ordered by `(executed_at, id)`, which is also the cursor, so that entries inserted while
+    /// paging only ever show up before the first page and are neither skipped nor repeated.
+    pub fn get_query_history_page(
+        &self,
+        connection_id: &str,
+        before: Option<HistoryCursor>,
+        page_size: i64,
+        filters: &HistoryFilters,
+    ) -> Result<QueryHistoryPage> {
+        let page_size = page_size.clamp(1, MAX_HISTORY_PAGE_SIZE);
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT id, connection_id, query_text, executed_at, duration_ms, status, row_count, error_message
-             FROM query_history 
-             WHERE connection_id = ?1 
-             ORDER BY executed_at DESC 
-             LIMIT ?2"
-        ).context("Failed to prepare query history statement")?;
+
+        let (mut conditions, mut params) = history_conditions(connection_id, filters);
+        if let Some(before) = before {
+            conditions.push("(executed_at, id) < (?, ?)");
+            params.push(before.executed_at.into());
+            params.push(before.id.into());
+        }
+        // One extra row tells whether there's a next page
+        params.push((page_size + 1).into());
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, connection_id, query_text, executed_at, duration_ms, status, row_count, error_message, timings,
+                        run_count, plan IS NOT NULL, scheduled
+                 FROM query_history
+                 WHERE {}
+                 ORDER BY executed_at DESC, id DESC
+                 LIMIT ?",
+                conditions.join(" AND ")
+            ))
+            .context("Failed to prepare query history statement")?;
 
         let rows = stmt
-            .query_map((connection_id, limit), |row| {
-                Ok(QueryHistoryEntry {
-                    id: row.get(0)?,
-                    connection_id: row.get(1)?,
-                    query_text: row.get(2)?,
-                    executed_at: row.get(3)?,
-                    duration_ms: row.get(4)?,
-                    status: row.get(5)?,
-                    row_count: row.get(6)?,
-                    error_message: row.get(7)?,
-                })
-            })
+            .query_map(params_from_iter(params), query_history_entry_from_row)
             .context("Failed to query history")?;
 
-        let mut history = Vec::new();
+        let mut entries = Vec::new();
         for row in rows {
-            history.push(row.context("Failed to process history row")?);
+            entries.push(row.context("Failed to process history row")?);
         }
 
-        Ok(history)
+        let next_cursor = if entries.len() as i64 > page_size {
+            entries.truncate(page_size as usize);
+            entries.last().map(|entry| HistoryCursor {
+                executed_at: entry.executed_at,
+                id: entry.id,
+            })
+        } else {
+            None
+        };
+
+        Ok(QueryHistoryPage {
+            entries,
+            next_cursor,
+        })
+    }
+
+    /// An entry with its plan, if it has one
+    pub fn get_query_history_entry(&self, id: i64) -> Result<Option<QueryHistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let entry = conn
+            .query_row(
+                "SELECT id, connection_id, query_text, executed_at, duration_ms, status, row_count, error_message, timings,
+                        run_count, plan IS NOT NULL, scheduled, plan, plan_compressed
+                 FROM query_history
+                 WHERE id = ?1",
+                [id],
+                |row| {
+                    let entry = query_history_entry_from_row(row)?;
+                    let plan: Option<Vec<u8>> = row.get(12)?;
+                    let compressed: bool = row.get(13)?;
+                    Ok((entry, plan, compressed))
+                },
+            )
+            .optional()
+            .context("Failed to get history entry")?;
+
+        let Some((mut entry, plan, compressed)) = entry else {
+            return Ok(None);
+        };
+        entry.plan_json = match plan {
+            Some(plan) if compressed => Some(inflate(&plan)?),
+            Some(plan) => Some(String::from_utf8(plan).context("Plan isn't valid UTF-8")?),
+            None => None,
+        };
+        Ok(Some(entry))
+    }
+
+    pub fn get_query_history_count(
+        &self,
+        connection_id: &str,
+        filters: &HistoryFilters,
+    ) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        let (conditions, params) = history_conditions(connection_id, filters);
+
+        let count = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM query_history WHERE {}",
+                    conditions.join(" AND ")
+                ),
+                params_from_iter(params),
+                |row| row.get(0),
+            )
+            .context("Failed to count query history")?;
+
+        Ok(count)
     }
 
     pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
@@ -395,140 +1028,2208 @@ impl Storage {
         Ok(())
     }
 
-    pub fn save_query(&self, query: &SavedQuery) -> Result<i64> {
+    /// Saves the session of a window. Each window saves its own, so that they don't overwrite each other's.
+    pub fn save_session(&self, session_id: &str, version: u32, data: &str) -> Result<()> {
         let now = chrono::Utc::now().timestamp();
         let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO sessions (session_id, version, data, updated_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (session_id) DO UPDATE SET
+                version = excluded.version,
+                data = excluded.data,
+                updated_at = excluded.updated_at",
+            (session_id, version, data, now),
+        )
+        .context("Failed to save session")?;
+        Ok(())
+    }
 
-        if query.id == 0 {
-            conn.execute(
-                "INSERT INTO saved_queries 
-                 (name, description, query_text, connection_id, tags, created_at, updated_at, favorite)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-                (
-                    &query.name,
-                    &query.description,
-                    &query.query_text,
-                    &query.connection_id.map(|id| id.to_string()),
-                    &query.tags,
-                    now,
-                    now,
-                    query.favorite,
-                ),
-            ).context("Failed to insert saved query")?;
-            Ok(conn.last_insert_rowid())
-        } else {
-            conn.execute(
-                "UPDATE saved_queries 
-                 SET name = ?1, description = ?2, query_text = ?3, connection_id = ?4, 
-                     tags = ?5, updated_at = ?6, favorite = ?7
-                 WHERE id = ?8",
-                (
-                    &query.name,
-                    &query.description,
-                    &query.query_text,
-                    &query.connection_id.map(|id| id.to_string()),
-                    &query.tags,
-                    now,
-                    query.favorite,
-                    query.id,
-                ),
+    pub fn get_session(&self, session_id: &str) -> Result<Option<SessionState>> {
+        let conn = self.conn.lock().unwrap();
+        let session = conn
+            .query_row(
+                "SELECT session_id, version, data, updated_at FROM sessions WHERE session_id = ?1",
+                [session_id],
+                |row| {
+                    Ok(SessionState {
+                        session_id: row.get(0)?,
+                        version: row.get(1)?,
+                        data: row.get(2)?,
+                        updated_at: row.get(3)?,
+                    })
+                },
             )
-            .context("Failed to update saved query")?;
-            Ok(query.id)
-        }
+            .optional()
+            .context("Failed to get session")?;
+        Ok(session)
     }
 
-    pub fn get_saved_queries(&self, connection_id: Option<&Uuid>) -> Result<Vec<SavedQuery>> {
+    /// The most recently saved first
+    pub fn list_sessions(&self) -> Result<Vec<SessionInfo>> {
         let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT session_id, version, updated_at
+                 FROM sessions
+                 ORDER BY updated_at DESC, session_id",
+            )
+            .context("Failed to prepare sessions statement")?;
 
-        let mut queries = Vec::new();
-
-        if let Some(conn_id) = connection_id {
-            let mut stmt = conn.prepare(
-                "SELECT id, name, description, query_text, connection_id, tags, created_at, updated_at, favorite
-                 FROM saved_queries 
-                 WHERE connection_id = ?1 OR connection_id IS NULL
-                 ORDER BY favorite DESC, created_at DESC"
-            ).context("Failed to prepare saved queries statement")?;
-
-            let rows = stmt
-                .query_map([conn_id.to_string()], |row| {
-                    Ok(SavedQuery {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        description: row.get(2)?,
-                        query_text: row.get(3)?,
-                        connection_id: {
-                            let id: Option<String> = row.get(4)?;
-                            match id {
-                                Some(id) => Some(Uuid::parse_str(&id).map_err(|err| {
-                                    rusqlite::Error::FromSqlConversionFailure(
-                                        0,
-                                        Type::Text,
-                                        Box::new(err),
-                                    )
-                                })?),
-                                None => None,
-                            }
-                        },
-                        tags: row.get(5)?,
-                        created_at: row.get(6)?,
-                        updated_at: row.get(7)?,
-                        favorite: row.get(8)?,
-                    })
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(SessionInfo {
+                    session_id: row.get(0)?,
+                    version: row.get(1)?,
+                    updated_at: row.get(2)?,
                 })
-                .context("Failed to query saved queries")?;
+            })
+            .context("Failed to query sessions")?;
 
-            for row in rows {
-                queries.push(row.context("Failed to process saved query row")?);
-            }
+        let mut sessions = Vec::new();
+        for row in rows {
+            sessions.push(row.context("Failed to process session row")?);
+        }
+
+        Ok(sessions)
+    }
+
+    pub fn delete_session(&self, session_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM sessions WHERE session_id = ?1", [session_id])
+            .context("Failed to delete session")?;
+        Ok(())
+    }
+
+    /// Saves the latest contents of an editor buffer, replacing the ones saved before
+    pub fn save_draft_buffer(
+        &self,
+        buffer_id: &str,
+        connection_id: Option<&Uuid>,
+        content: &str,
+        cursor: u32,
+    ) -> Result<()> {
+        let (content, compressed) = if content.len() > DRAFT_COMPRESSION_THRESHOLD {
+            (deflate(content.as_bytes())?, true)
         } else {
-            let mut stmt = conn.prepare(
-                "SELECT id, name, description, query_text, connection_id, tags, created_at, updated_at, favorite
-                 FROM saved_queries 
-                 ORDER BY favorite DESC, created_at DESC"
-            ).context("Failed to prepare saved queries statement")?;
-
-            let rows = stmt
-                .query_map([], |row| {
-                    Ok(SavedQuery {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        description: row.get(2)?,
-                        query_text: row.get(3)?,
-                        connection_id: {
-                            let id: Option<String> = row.get(4)?;
-                            match id {
-                                Some(id) => Some(Uuid::parse_str(&id).map_err(|err| {
-                                    rusqlite::Error::FromSqlConversionFailure(
-                                        0,
-                                        Type::Text,
-                                        Box::new(err),
-                                    )
-                                })?),
-                                None => None,
-                            }
-                        },
-                        tags: row.get(5)?,
-                        created_at: row.get(6)?,
-                        updated_at: row.get(7)?,
-                        favorite: row.get(8)?,
-                    })
-                })
-                .context("Failed to query saved queries")?;
+            (content.as_bytes().to_vec(), false)
+        };
+        let now = chrono::Utc::now().timestamp();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO draft_buffers (buffer_id, connection_id, content, compressed, cursor, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (buffer_id) DO UPDATE SET
+                connection_id = excluded.connection_id,
+                content = excluded.content,
+                compressed = excluded.compressed,
+                cursor = excluded.cursor,
+                updated_at = excluded.updated_at",
+            (
+                buffer_id,
+                connection_id.map(|id| id.to_string()),
+                content,
+                compressed,
+                cursor,
+                now,
+            ),
+        )
+        .context("Failed to save draft buffer")?;
+        Ok(())
+    }
 
-            for row in rows {
-                queries.push(row.context("Failed to process saved query row")?);
-            }
+    /// The most recently saved first
+    pub fn get_draft_buffers(&self) -> Result<Vec<DraftBuffer>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT buffer_id, connection_id, content, compressed, cursor, updated_at
+                 FROM draft_buffers
+                 ORDER BY updated_at DESC, buffer_id",
+            )
+            .context("Failed to prepare draft buffers statement")?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                    row.get::<_, bool>(3)?,
+                    row.get::<_, u32>(4)?,
+                    row.get::<_, i64>(5)?,
+                ))
+            })
+            .context("Failed to query draft buffers")?;
+
+        let mut drafts = Vec::new();
+        for row in rows {
+            let (buffer_id, connection_id, content, compressed, cursor, updated_at) =
+                row.context("Failed to process draft buffer row")?;
+            let content = if compressed {
+                inflate(&content)
+            } else {
+                String::from_utf8(content).context("Draft buffer isn't valid UTF-8")
+            };
+            let content = match content {
+                Ok(content) => content,
+                Err(e) => {
+                    log::warn!("Skipping unreadable draft buffer {buffer_id}: {e:#}");
+                    continue;
+                }
+            };
+            drafts.push(DraftBuffer {
+                buffer_id,
+                connection_id: connection_id.and_then(|id| Uuid::parse_str(&id).ok()),
+                content,
+                cursor,
+                updated_at,
+            });
         }
 
-        Ok(queries)
+        Ok(drafts)
     }
 
-    pub fn delete_saved_query(&self, id: i64) -> Result<()> {
+    pub fn discard_draft_buffer(&self, buffer_id: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM saved_queries WHERE id = ?1", [id])
-            .context("Failed to delete saved query")?;
+        conn.execute(
+            "DELETE FROM draft_buffers WHERE buffer_id = ?1",
+            [buffer_id],
+        )
+        .context("Failed to discard draft buffer")?;
+        Ok(())
+    }
+
+    /// Saves the state of a connection's Items panel, see [`crate::tree_state`]
+    pub fn save_tree_state(&self, connection_id: &Uuid, state: &str) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO tree_states (connection_id, state, updated_at) VALUES (?1, ?2, ?3)",
+            (connection_id.to_string(), state, now),
+        )
+        .context("Failed to save tree state")?;
         Ok(())
     }
+
+    pub fn get_tree_state(&self, connection_id: &Uuid) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let state = conn
+            .query_row(
+                "SELECT state FROM tree_states WHERE connection_id = ?1",
+                [connection_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Failed to get tree state")?;
+        Ok(state)
+    }
+
+    /// The vectors of a connection's semantic schema index, see [`crate::database::semantic_search`]
+    pub fn get_schema_vectors(&self, connection_id: &Uuid) -> Result<Vec<SchemaVector>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT object_key, content_hash, vector FROM schema_vectors WHERE connection_id = ?1",
+            )
+            .context("Failed to prepare schema vectors statement")?;
+
+        let rows = stmt
+            .query_map([connection_id.to_string()], |row| {
+                let bytes: Vec<u8> = row.get(2)?;
+                Ok(SchemaVector {
+                    object_key: row.get(0)?,
+                    content_hash: row.get(1)?,
+                    vector: bytes
+                        .chunks_exact(4)
+                        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                        .collect(),
+                })
+            })
+            .context("Failed to query schema vectors")?;
+
+        let mut vectors = Vec::new();
+        for row in rows {
+            vectors.push(row.context("Failed to process schema vector row")?);
+        }
+
+        Ok(vectors)
+    }
+
+    /// Saves new or changed vectors and drops those of objects that don't exist anymore, all at once
+    pub fn update_schema_vectors(
+        &self,
+        connection_id: &Uuid,
+        changed: &[SchemaVector],
+        removed: &[String],
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn
+            .transaction()
+            .context("Failed to start schema vectors transaction")?;
+        let connection_id = connection_id.to_string();
+
+        for vector in changed {
+            let bytes: Vec<u8> = vector.vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+            tx.execute(
+                "INSERT OR REPLACE INTO schema_vectors (connection_id, object_key, content_hash, vector)
+                 VALUES (?1, ?2, ?3, ?4)",
+                (&connection_id, &vector.object_key, &vector.content_hash, bytes),
+            )
+            .context("Failed to save schema vector")?;
+        }
+        for object_key in removed {
+            tx.execute(
+                "DELETE FROM schema_vectors WHERE connection_id = ?1 AND object_key = ?2",
+                (&connection_id, object_key),
+            )
+            .context("Failed to delete schema vector")?;
+        }
+
+        tx.commit()
+            .context("Failed to commit schema vectors transaction")?;
+        Ok(())
+    }
+
+    pub fn save_query(&self, query: &SavedQuery) -> Result<i64> {
+        let now = chrono::Utc::now().timestamp();
+        let tags = serde_json::to_string(&query.tags)?;
+        let conn = self.conn.lock().unwrap();
+
+        if query.id == 0 {
+            conn.execute(
+                "INSERT INTO saved_queries 
+                 (name, description, query_text, connection_id, tags, created_at, updated_at, favorite)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                (
+                    &query.name,
+                    &query.description,
+                    &query.query_text,
+                    &query.connection_id.map(|id| id.to_string()),
+                    &tags,
+                    now,
+                    now,
+                    query.favorite,
+                ),
+            ).context("Failed to insert saved query")?;
+            Ok(conn.last_insert_rowid())
+        } else {
+            conn.execute(
+                "UPDATE saved_queries 
+                 SET name = ?1, description = ?2, query_text = ?3, connection_id = ?4, 
+                     tags = ?5, updated_at = ?6, favorite = ?7
+                 WHERE id = ?8",
+                (
+                    &query.name,
+                    &query.description,
+                    &query.query_text,
+                    &query.connection_id.map(|id| id.to_string()),
+                    &tags,
+                    now,
+                    query.favorite,
+                    query.id,
+                ),
+            )
+            .context("Failed to update saved query")?;
+            Ok(query.id)
+        }
+    }
+
+    /// Scripts of a connection along with those of no connection in particular, or all of them if None.
+    /// Favorites come first, then the most recently updated.
+    pub fn get_saved_queries(
+        &self,
+        connection_id: Option<&Uuid>,
+        filters: &ScriptFilters,
+    ) -> Result<Vec<SavedQuery>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut conditions = Vec::new();
+        let mut params: Vec<SqlValue> = Vec::new();
+        if let Some(connection_id) = connection_id {
+            conditions.push("(connection_id = ? OR connection_id IS NULL)");
+            params.push(connection_id.to_string().into());
+        }
+        if let Some(tag) = &filters.tag {
+            conditions.push("EXISTS (SELECT 1 FROM json_each(saved_queries.tags) WHERE value = ?)");
+            params.push(tag.clone().into());
+        }
+        if filters.favorites_only {
+            conditions.push("favorite");
+        }
+        if let Some(text) = filters
+            .search_text
+            .as_deref()
+            .filter(|text| !text.is_empty())
+        {
+            // LIKE is case sensitive in this database, and would need its wildcards escaped
+            conditions.push(
+                "(instr(lower(name), lower(?)) > 0
+                  OR instr(lower(coalesce(description, '')), lower(?)) > 0
+                  OR instr(lower(query_text), lower(?)) > 0)",
+            );
+            params.extend(std::iter::repeat_n(SqlValue::from(text.to_owned()), 3));
+        }
+
+        let where_clause = match conditions.is_empty() {
+            true => String::new(),
+            false => format!("WHERE {}", conditions.join(" AND ")),
+        };
+        let sql = format!(
+            "SELECT id, name, description, query_text, connection_id, tags, created_at, updated_at, favorite
+             FROM saved_queries
+             {where_clause}
+             ORDER BY favorite DESC, updated_at DESC, id DESC"
+        );
+        let mut stmt = conn
+            .prepare(&sql)
+            .context("Failed to prepare saved queries statement")?;
+
+        let rows = stmt
+            .query_map(params_from_iter(params), saved_query_from_row)
+            .context("Failed to query saved queries")?;
+
+        let mut queries = Vec::new();
+        for row in rows {
+            queries.push(row.context("Failed to process saved query row")?);
+        }
+
+        Ok(queries)
+    }
+
+    /// Returns whether the script is now a favorite
+    pub fn toggle_saved_query_favorite(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let favorite = conn
+            .query_row(
+                "UPDATE saved_queries SET favorite = NOT favorite WHERE id = ?1 RETURNING favorite",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Failed to toggle favorite")?
+            .with_context(|| format!("Script not found: {id}"))?;
+        Ok(favorite)
+    }
+
+    pub fn delete_saved_query(&self, id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM saved_queries WHERE id = ?1", [id])
+            .context("Failed to delete saved query")?;
+        Ok(())
+    }
+
+    pub fn save_linked_script_directory(
+        &self,
+        path: &str,
+        connection_id: Option<&Uuid>,
+    ) -> Result<LinkedScriptDirectory> {
+        let now = chrono::Utc::now().timestamp();
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO linked_script_directories (path, connection_id, created_at)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT (path) DO UPDATE SET connection_id = excluded.connection_id",
+            (path, connection_id.map(|id| id.to_string()), now),
+        )
+        .context("Failed to save linked script directory")?;
+
+        let directory = conn
+            .query_row(
+                "SELECT id, path, connection_id, created_at FROM linked_script_directories WHERE path = ?1",
+                [path],
+                linked_script_directory_from_row,
+            )
+            .context("Failed to read back linked script directory")?;
+
+        Ok(directory)
+    }
+
+    pub fn get_linked_script_directories(&self) -> Result<Vec<LinkedScriptDirectory>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, path, connection_id, created_at
+                 FROM linked_script_directories
+                 ORDER BY path",
+            )
+            .context("Failed to prepare linked script directories statement")?;
+
+        let rows = stmt
+            .query_map([], linked_script_directory_from_row)
+            .context("Failed to query linked script directories")?;
+
+        let mut directories = Vec::new();
+        for row in rows {
+            directories.push(row.context("Failed to process linked script directory row")?);
+        }
+
+        Ok(directories)
+    }
+
+    pub fn delete_linked_script_directory(&self, id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM linked_script_directories WHERE id = ?1", [id])
+            .context("Failed to delete linked script directory")?;
+        Ok(())
+    }
+
+    /// Creates a template, or updates the one with the given id
+    pub fn save_export_template(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        options: &ExportOptions,
+    ) -> Result<ExportTemplate> {
+        let now = chrono::Utc::now().timestamp();
+        let options_json = serde_json::to_string(options)?;
+        let conn = self.conn.lock().unwrap();
+
+        let id = match id {
+            Some(id) => {
+                let updated = conn
+                    .execute(
+                        "UPDATE export_templates SET name = ?1, options = ?2, updated_at = ?3 WHERE id = ?4",
+                        (name, &options_json, now, id),
+                    )
+                    .context("Failed to update export template")?;
+                if updated == 0 {
+                    return Err(anyhow::anyhow!("Export template {id} not found").into());
+                }
+                id
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO export_templates (name, options, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?3)",
+                    (name, &options_json, now),
+                )
+                .context("Failed to save export template")?;
+                conn.last_insert_rowid()
+            }
+        };
+
+        let template = conn
+            .query_row(
+                "SELECT id, name, options, created_at, updated_at FROM export_templates WHERE id = ?1",
+                [id],
+                export_template_from_row,
+            )
+            .context("Failed to read back export template")?;
+
+        Ok(template)
+    }
+
+    pub fn get_export_templates(&self) -> Result<Vec<ExportTemplate>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, name, options, created_at, updated_at
+                 FROM export_templates
+                 ORDER BY name",
+            )
+            .context("Failed to prepare export templates statement")?;
+
+        let rows = stmt
+            .query_map([], export_template_from_row)
+            .context("Failed to query export templates")?;
+
+        let mut templates = Vec::new();
+        for row in rows {
+            templates.push(row.context("Failed to process export template row")?);
+        }
+
+        Ok(templates)
+    }
+
+    pub fn get_export_template(&self, id: i64) -> Result<Option<ExportTemplate>> {
+        let conn = self.conn.lock().unwrap();
+        let template = conn
+            .query_row(
+                "SELECT id, name, options, created_at, updated_at FROM export_templates WHERE id = ?1",
+                [id],
+                export_template_from_row,
+            )
+            .optional()
+            .context("Failed to get export template")?;
+
+        Ok(template)
+    }
+
+    /// Creates a snippet if its id is 0, or updates the one with its id
+    pub fn save_snippet(&self, snippet: &Snippet) -> Result<Snippet> {
+        let now = chrono::Utc::now().timestamp();
+        let connection_id = snippet.connection_id.map(|id| id.to_string());
+        let db_type = snippet.db_type.map(|db_type| db_type.as_str());
+        let conn = self.conn.lock().unwrap();
+
+        let id = if snippet.id == 0 {
+            conn.execute(
+                "INSERT INTO snippets (name, prefix, body, connection_id, db_type, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+                (
+                    &snippet.name,
+                    &snippet.prefix,
+                    &snippet.body,
+                    &connection_id,
+                    db_type,
+                    now,
+                ),
+            )
+            .context("Failed to save snippet")?;
+            conn.last_insert_rowid()
+        } else {
+            let updated = conn
+                .execute(
+                    "UPDATE snippets
+                     SET name = ?1, prefix = ?2, body = ?3, connection_id = ?4, db_type = ?5, updated_at = ?6
+                     WHERE id = ?7",
+                    (
+                        &snippet.name,
+                        &snippet.prefix,
+                        &snippet.body,
+                        &connection_id,
+                        db_type,
+                        now,
+                        snippet.id,
+                    ),
+                )
+                .context("Failed to update snippet")?;
+            if updated == 0 {
+                return Err(anyhow::anyhow!("Snippet {} not found", snippet.id).into());
+            }
+            snippet.id
+        };
+
+        Ok(Snippet {
+            id,
+            ..snippet.clone()
+        })
+    }
+
+    /// The snippets offered on a connection of the given type, or all of them if None. By prefix.
+    pub fn get_snippets(&self, scope: Option<(&Uuid, Database)>) -> Result<Vec<Snippet>> {
+        let conn = self.conn.lock().unwrap();
+
+        let (where_clause, params) = match scope {
+            Some((connection_id, db_type)) => (
+                "WHERE (connection_id IS NULL OR connection_id = ?1) AND (db_type IS NULL OR db_type = ?2)",
+                vec![
+                    SqlValue::from(connection_id.to_string()),
+                    SqlValue::from(db_type.as_str().to_owned()),
+                ],
+            ),
+            None => ("", vec![]),
+        };
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, name, prefix, body, connection_id, db_type
+                 FROM snippets
+                 {where_clause}
+                 ORDER BY prefix, id"
+            ))
+            .context("Failed to prepare snippets statement")?;
+
+        let rows = stmt
+            .query_map(params_from_iter(params), snippet_from_row)
+            .context("Failed to query snippets")?;
+
+        let mut snippets = Vec::new();
+        for row in rows {
+            snippets.push(row.context("Failed to process snippet row")?);
+        }
+
+        Ok(snippets)
+    }
+
+    pub fn delete_snippet(&self, id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM snippets WHERE id = ?1", [id])
+            .context("Failed to delete snippet")?;
+        Ok(())
+    }
+
+    /// Creates a variable if its id is 0, or updates the one with its id
+    pub fn save_query_variable(&self, variable: &QueryVariable) -> Result<QueryVariable> {
+        let now = chrono::Utc::now().timestamp();
+        let connection_id = variable.connection_id.map(|id| id.to_string());
+        let conn = self.conn.lock().unwrap();
+
+        let taken = conn
+            .query_row(
+                "SELECT 1 FROM query_variables
+                 WHERE name = ?1 AND COALESCE(connection_id, '') = COALESCE(?2, '') AND id <> ?3",
+                (&variable.name, &connection_id, variable.id),
+                |_| Ok(()),
+            )
+            .optional()
+            .context("Failed to look up variable")?
+            .is_some();
+        if taken {
+            return Err(
+                anyhow::anyhow!("There's already a variable named {}", variable.name).into(),
+            );
+        }
+
+        let id = if variable.id == 0 {
+            conn.execute(
+                "INSERT INTO query_variables (name, value, connection_id, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?4)",
+                (&variable.name, &variable.value, &connection_id, now),
+            )
+            .context("Failed to save variable")?;
+            conn.last_insert_rowid()
+        } else {
+            let updated = conn
+                .execute(
+                    "UPDATE query_variables SET name = ?1, value = ?2, connection_id = ?3, updated_at = ?4
+                     WHERE id = ?5",
+                    (
+                        &variable.name,
+                        &variable.value,
+                        &connection_id,
+                        now,
+                        variable.id,
+                    ),
+                )
+                .context("Failed to update variable")?;
+            if updated == 0 {
+                return Err(anyhow::anyhow!("Variable {} not found", variable.id).into());
+            }
+            variable.id
+        };
+
+        Ok(QueryVariable {
+            id,
+            ..variable.clone()
+        })
+    }
+
+    /// The variables defined on a connection, those of no connection first, or all of them if None. By name.
+    pub fn get_query_variables(&self, connection_id: Option<&Uuid>) -> Result<Vec<QueryVariable>> {
+        let conn = self.conn.lock().unwrap();
+
+        let (where_clause, params) = match connection_id {
+            Some(connection_id) => (
+                "WHERE connection_id IS NULL OR connection_id = ?1",
+                vec![SqlValue::from(connection_id.to_string())],
+            ),
+            None => ("", vec![]),
+        };
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, name, value, connection_id
+                 FROM query_variables
+                 {where_clause}
+                 ORDER BY connection_id IS NOT NULL, name, id"
+            ))
+            .context("Failed to prepare variables statement")?;
+
+        let rows = stmt
+            .query_map(params_from_iter(params), query_variable_from_row)
+            .context("Failed to query variables")?;
+
+        let mut variables = Vec::new();
+        for row in rows {
+            variables.push(row.context("Failed to process variable row")?);
+        }
+
+        Ok(variables)
+    }
+
+    pub fn delete_query_variable(&self, id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM query_variables WHERE id = ?1", [id])
+            .context("Failed to delete variable")?;
+        Ok(())
+    }
+
+    /// Creates a scheduled query if its id is 0, or updates the one with its id, keeping how its last run went
+    pub fn save_scheduled_query(&self, scheduled: &ScheduledQuery) -> Result<ScheduledQuery> {
+        let now = chrono::Utc::now().timestamp();
+        let (interval_secs, cron) = match &scheduled.schedule {
+            Schedule::Every { seconds } => (Some(*seconds as i64), None),
+            Schedule::Cron { expression } => (None, Some(expression.as_str())),
+        };
+        let conn = self.conn.lock().unwrap();
+
+        let id = if scheduled.id == 0 {
+            conn.execute(
+                "INSERT INTO scheduled_queries
+                 (name, connection_id, query_text, interval_secs, cron, enabled, alert_if_rows, confirmed_destructive,
+                  created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)",
+                (
+                    &scheduled.name,
+                    scheduled.connection_id.to_string(),
+                    &scheduled.query_text,
+                    interval_secs,
+                    cron,
+                    scheduled.enabled,
+                    scheduled.alert_if_rows,
+                    scheduled.confirmed_destructive,
+                    now,
+                ),
+            )
+            .context("Failed to save scheduled query")?;
+            conn.last_insert_rowid()
+        } else {
+            let updated = conn
+                .execute(
+                    "UPDATE scheduled_queries
+                     SET name = ?1, connection_id = ?2, query_text = ?3, interval_secs = ?4, cron = ?5, enabled = ?6,
+                         alert_if_rows = ?7, confirmed_destructive = ?8, updated_at = ?9
+                     WHERE id = ?10",
+                    (
+                        &scheduled.name,
+                        scheduled.connection_id.to_string(),
+                        &scheduled.query_text,
+                        interval_secs,
+                        cron,
+                        scheduled.enabled,
+                        scheduled.alert_if_rows,
+                        scheduled.confirmed_destructive,
+                        now,
+                        scheduled.id,
+                    ),
+                )
+                .context("Failed to update scheduled query")?;
+            if updated == 0 {
+                return Err(anyhow::anyhow!("Scheduled query {} not found", scheduled.id).into());
+            }
+            scheduled.id
+        };
+
+        let saved = conn
+            .query_row(
+                "SELECT id, name, connection_id, query_text, interval_secs, cron, enabled, alert_if_rows, last_run_at,
+                        last_status, last_error, confirmed_destructive
+                 FROM scheduled_queries
+                 WHERE id = ?1",
+                [id],
+                scheduled_query_from_row,
+            )
+            .context("Failed to read back scheduled query")?;
+        Ok(saved)
+    }
+
+    /// By name
+    pub fn get_scheduled_queries(&self) -> Result<Vec<ScheduledQuery>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, name, connection_id, query_text, interval_secs, cron, enabled, alert_if_rows, last_run_at,
+                        last_status, last_error, confirmed_destructive
+                 FROM scheduled_queries
+                 ORDER BY name, id",
+            )
+            .context("Failed to prepare scheduled queries statement")?;
+
+        let rows = stmt
+            .query_map([], scheduled_query_from_row)
+            .context("Failed to query scheduled queries")?;
+
+        let mut scheduled = Vec::new();
+        for row in rows {
+            scheduled.push(row.context("Failed to process scheduled query row")?);
+        }
+
+        Ok(scheduled)
+    }
+
+    pub fn delete_scheduled_query(&self, id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM scheduled_queries WHERE id = ?1", [id])
+            .context("Failed to delete scheduled query")?;
+        Ok(())
+    }
+
+    /// Records how the last run of a scheduled query went, `ran_at` being when it started
+    pub fn record_scheduled_run(
+        &self,
+        id: i64,
+        ran_at: i64,
+        status: ScheduledRunStatus,
+        error: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE scheduled_queries SET last_run_at = ?2, last_status = ?3, last_error = ?4 WHERE id = ?1",
+            (id, ran_at, status.as_str(), error),
+        )
+        .context("Failed to record scheduled run")?;
+        Ok(())
+    }
+
+    /// Creates a script template, or replaces the one with the same name for this database type
+    pub fn save_script_template(
+        &self,
+        db_type: Database,
+        name: &str,
+        description: Option<&str>,
+        body: &str,
+    ) -> Result<ScriptTemplate> {
+        let now = chrono::Utc::now().timestamp();
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO script_templates (db_type, name, description, body, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+             ON CONFLICT (db_type, name) DO UPDATE SET
+                description = excluded.description,
+                body = excluded.body,
+                updated_at = excluded.updated_at",
+            (db_type.as_str(), name, description, body, now),
+        )
+        .context("Failed to save script template")?;
+
+        let template = conn
+            .query_row(
+                "SELECT id, name, description, body FROM script_templates
+                 WHERE db_type = ?1 AND name = ?2",
+                (db_type.as_str(), name),
+                script_template_from_row,
+            )
+            .context("Failed to read back script template")?;
+
+        Ok(template)
+    }
+
+    pub fn get_script_templates(&self, db_type: Database) -> Result<Vec<ScriptTemplate>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, name, description, body
+                 FROM script_templates
+                 WHERE db_type = ?1
+                 ORDER BY name",
+            )
+            .context("Failed to prepare script templates statement")?;
+
+        let rows = stmt
+            .query_map([db_type.as_str()], script_template_from_row)
+            .context("Failed to query script templates")?;
+
+        let mut templates = Vec::new();
+        for row in rows {
+            templates.push(row.context("Failed to process script template row")?);
+        }
+
+        Ok(templates)
+    }
+
+    pub fn get_script_template(&self, id: i64) -> Result<Option<ScriptTemplate>> {
+        let conn = self.conn.lock().unwrap();
+        let template = conn
+            .query_row(
+                "SELECT id, name, description, body FROM script_templates WHERE id = ?1",
+                [id],
+                script_template_from_row,
+            )
+            .optional()
+            .context("Failed to get script template")?;
+
+        Ok(template)
+    }
+}
+
+fn script_template_from_row(row: &rusqlite::Row) -> rusqlite::Result<ScriptTemplate> {
+    Ok(ScriptTemplate {
+        id: ScriptTemplate::user_template_id(row.get(0)?),
+        name: row.get(1)?,
+        description: row.get(2)?,
+        body: row.get(3)?,
+        builtin: false,
+    })
+}
+
+fn saved_query_from_row(row: &rusqlite::Row) -> rusqlite::Result<SavedQuery> {
+    let connection_id = row
+        .get::<_, Option<String>>(4)?
+        .map(|id| {
+            Uuid::parse_str(&id).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(4, Type::Text, Box::new(err))
+            })
+        })
+        .transpose()?;
+    let tags = row
+        .get::<_, Option<String>>(5)?
+        .map(|tags| serde_json::from_str(&tags))
+        .transpose()
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(err)))?
+        .unwrap_or_default();
+
+    Ok(SavedQuery {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        query_text: row.get(3)?,
+        connection_id,
+        tags,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+        favorite: row.get(8)?,
+    })
+}
+
+/// Compresses large text, such as buffer contents and plans, to be stored as a blob
+fn deflate(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+
+fn inflate(bytes: &[u8]) -> anyhow::Result<String> {
+    let mut content = String::new();
+    DeflateDecoder::new(bytes).read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// In bytes, as the database's pages take
+fn database_size(conn: &Connection) -> Result<u64> {
+    let pages: i64 = conn
+        .pragma_query_value(None, "page_count", |row| row.get(0))
+        .context("Failed to read the page count of the database")?;
+    let page_size: i64 = conn
+        .pragma_query_value(None, "page_size", |row| row.get(0))
+        .context("Failed to read the page size of the database")?;
+    Ok((pages * page_size) as u64)
+}
+
+/// The `WHERE` conditions matching a connection's history entries, and their parameters
+fn history_conditions(
+    connection_id: &str,
+    filters: &HistoryFilters,
+) -> (Vec<&'static str>, Vec<SqlValue>) {
+    let mut conditions = vec!["connection_id = ?"];
+    let mut params = vec![SqlValue::from(connection_id.to_owned())];
+
+    if let Some(text) = filters.text.as_deref().filter(|text| !text.is_empty()) {
+        // LIKE is case sensitive in this database, and would need its wildcards escaped
+        conditions.push("instr(lower(query_text), lower(?)) > 0");
+        params.push(text.to_owned().into());
+    }
+    if let Some(status) = &filters.status {
+        conditions.push("status = ?");
+        params.push(status.clone().into());
+    }
+    if filters.with_plan {
+        conditions.push("plan IS NOT NULL");
+    }
+    if let Some(scheduled) = filters.scheduled {
+        conditions.push("scheduled = ?");
+        params.push(i64::from(scheduled).into());
+    }
+
+    (conditions, params)
+}
+
+fn query_history_entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<QueryHistoryEntry> {
+    let timings = row
+        .get::<_, Option<String>>(8)?
+        .map(|timings| serde_json::from_str(&timings))
+        .transpose()
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(8, Type::Text, Box::new(err)))?;
+
+    Ok(QueryHistoryEntry {
+        id: row.get(0)?,
+        connection_id: row.get(1)?,
+        query_text: row.get(2)?,
+        executed_at: row.get(3)?,
+        duration_ms: row.get(4)?,
+        status: row.get(5)?,
+        row_count: row.get(6)?,
+        error_message: row.get(7)?,
+        timings,
+        run_count: row.get(9)?,
+        plan_json: None,
+        has_plan: row.get(10)?,
+        scheduled: row.get(11)?,
+    })
+}
+
+fn export_template_from_row(row: &rusqlite::Row) -> rusqlite::Result<ExportTemplate> {
+    let options: String = row.get(2)?;
+    let options = serde_json::from_str(&options)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(err)))?;
+
+    Ok(ExportTemplate {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        options,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+    })
+}
+
+fn snippet_from_row(row: &rusqlite::Row) -> rusqlite::Result<Snippet> {
+    let connection_id = row
+        .get::<_, Option<String>>(4)?
+        .map(|id| {
+            Uuid::parse_str(&id).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(4, Type::Text, Box::new(err))
+            })
+        })
+        .transpose()?;
+    let db_type = row
+        .get::<_, Option<String>>(5)?
+        .map(|db_type| match db_type.as_str() {
+            "postgres" => Ok(Database::Postgres),
+            "sqlite" => Ok(Database::Sqlite),
+            "mysql" => Ok(Database::MySql),
+            other => Err(rusqlite::Error::FromSqlConversionFailure(
+                5,
+                Type::Text,
+                format!("Unknown database type: {other}").into(),
+            )),
+        })
+        .transpose()?;
+
+    Ok(Snippet {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        prefix: row.get(2)?,
+        body: row.get(3)?,
+        connection_id,
+        db_type,
+    })
+}
+
+fn query_variable_from_row(row: &rusqlite::Row) -> rusqlite::Result<QueryVariable> {
+    let connection_id = row
+        .get::<_, Option<String>>(3)?
+        .map(|id| {
+            Uuid::parse_str(&id).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(err))
+            })
+        })
+        .transpose()?;
+
+    Ok(QueryVariable {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        value: row.get(2)?,
+        connection_id,
+    })
+}
+
+fn scheduled_query_from_row(row: &rusqlite::Row) -> rusqlite::Result<ScheduledQuery> {
+    let connection_id: String = row.get(2)?;
+    let connection_id = Uuid::parse_str(&connection_id)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(err)))?;
+
+    let schedule = match (
+        row.get::<_, Option<i64>>(4)?,
+        row.get::<_, Option<String>>(5)?,
+    ) {
+        (_, Some(expression)) => Schedule::Cron { expression },
+        (interval_secs, None) => Schedule::Every {
+            seconds: interval_secs.unwrap_or_default() as u64,
+        },
+    };
+    let last_status = row
+        .get::<_, Option<String>>(9)?
+        .and_then(|status| ScheduledRunStatus::parse(&status));
+
+    Ok(ScheduledQuery {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        connection_id,
+        query_text: row.get(3)?,
+        schedule,
+        enabled: row.get(6)?,
+        alert_if_rows: row.get(7)?,
+        confirmed_destructive: row.get(11)?,
+        last_run_at: row.get(8)?,
+        last_status,
+        last_error: row.get(10)?,
+    })
+}
+
+fn linked_script_directory_from_row(
+    row: &rusqlite::Row,
+) -> rusqlite::Result<LinkedScriptDirectory> {
+    let connection_id: Option<String> = row.get(2)?;
+    let connection_id = connection_id
+        .map(|id| {
+            Uuid::parse_str(&id).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(err))
+            })
+        })
+        .transpose()?;
+
+    Ok(LinkedScriptDirectory {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        connection_id,
+        created_at: row.get(3)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::postgres::ssh_tunnel::{SshAuth, SshTunnelConfig};
+
+    fn history_entry(connection_id: &str, query_text: &str, executed_at: i64) -> QueryHistoryEntry {
+        QueryHistoryEntry {
+            id: 0,
+            connection_id: connection_id.to_owned(),
+            query_text: query_text.to_owned(),
+            executed_at,
+            duration_ms: Some(1),
+            status: "success".into(),
+            row_count: 0,
+            error_message: None,
+            timings: None,
+            run_count: 1,
+            plan_json: None,
+            has_plan: false,
+            scheduled: false,
+        }
+    }
+
+    #[test]
+    fn pages_through_history() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "test".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: ":memory:".into(),
+                    home_relative_path: None,
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+        let connection_id = connection_id.to_string();
+
+        // Some entries share a timestamp, so that the id breaks ties
+        for i in 0..7 {
+            let query = if i % 2 == 0 { "SELECT 1" } else { "select 2" };
+            storage
+                .save_query_history(&history_entry(&connection_id, query, i / 2), false)
+                .unwrap();
+        }
+
+        let filters = HistoryFilters::default();
+        let first = storage
+            .get_query_history_page(&connection_id, None, 3, &filters)
+            .unwrap();
+        assert_eq!(first.entries.len(), 3);
+
+        // Entries added while scrolling don't shift the next pages
+        storage
+            .save_query_history(&history_entry(&connection_id, "SELECT 3", 10), false)
+            .unwrap();
+
+        let mut seen: Vec<_> = first.entries.iter().map(|e| e.id).collect();
+        let mut cursor = first.next_cursor;
+        while let Some(before) = cursor {
+            let page = storage
+                .get_query_history_page(&connection_id, Some(before), 3, &filters)
+                .unwrap();
+            seen.extend(page.entries.iter().map(|e| e.id));
+            cursor = page.next_cursor;
+        }
+        assert_eq!(seen, [7, 6, 5, 4, 3, 2, 1]);
+
+        let filters = HistoryFilters {
+            text: Some("SELECT 2".into()),
+            status: Some("success".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            storage
+                .get_query_history_count(&connection_id, &filters)
+                .unwrap(),
+            3
+        );
+        let before = HistoryCursor {
+            executed_at: 2,
+            id: 6,
+        };
+        let page = storage
+            .get_query_history_page(&connection_id, Some(before), 2, &filters)
+            .unwrap();
+        let ids: Vec<_> = page.entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, [4, 2]);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn pages_past_entries_run_again_or_pruned() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "test".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: ":memory:".into(),
+                    home_relative_path: None,
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+        let connection_id = connection_id.to_string();
+
+        for i in 1..=7 {
+            storage
+                .save_query_history(
+                    &history_entry(&connection_id, &format!("SELECT {i}"), 100 + i),
+                    true,
+                )
+                .unwrap();
+        }
+
+        let filters = HistoryFilters::default();
+        let page = |before| {
+            let page = storage
+                .get_query_history_page(&connection_id, before, 2, &filters)
+                .unwrap();
+            let ids: Vec<_> = page.entries.iter().map(|e| e.id).collect();
+            (ids, page.next_cursor)
+        };
+
+        let (ids, cursor) = page(None);
+        assert_eq!(ids, [7, 6]);
+
+        // Running the query of the cursor's entry again moves it to the top
+        storage
+            .save_query_history(&history_entry(&connection_id, "SELECT 6", 200), true)
+            .unwrap();
+        let (ids, cursor) = page(cursor);
+        assert_eq!(ids, [5, 4]);
+
+        // Nor does pruning the cursor's entry, along with the older ones
+        let settings = HistorySettings {
+            dedupe: true,
+            max_entries: Some(3),
+            max_age_days: None,
+        };
+        storage.prune_query_history(&settings, 200).unwrap();
+        let (ids, cursor) = page(cursor);
+        assert_eq!(ids, Vec::<i64>::new());
+        assert_eq!(cursor, None);
+    }
+
+    #[test]
+    fn keeps_the_plan_of_each_explained_run() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "test".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: ":memory:".into(),
+                    home_relative_path: None,
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+        let connection_id = connection_id.to_string();
+
+        let small_plan = r#"{"plan":{"node_type":"Result"}}"#.to_owned();
+        let big_plan = format!(r#"{{"plan":"{}"}}"#, "Seq Scan ".repeat(1000));
+        let save = |executed_at: i64, plan: Option<&String>| {
+            let entry = QueryHistoryEntry {
+                plan_json: plan.cloned(),
+                ..history_entry(&connection_id, "SELECT * FROM orders", executed_at)
+            };
+            storage.save_query_history(&entry, true).unwrap();
+        };
+        save(0, Some(&small_plan));
+        // Neither folded into the entry with a plan, nor into each other
+        save(10, None);
+        save(20, Some(&big_plan));
+
+        let entries = storage.get_query_history(&connection_id, None).unwrap();
+        let listed: Vec<_> = entries
+            .iter()
+            .map(|e| (e.executed_at, e.has_plan, e.plan_json.is_some()))
+            .collect();
+        assert_eq!(
+            listed,
+            [(20, true, false), (10, false, false), (0, true, false)]
+        );
+
+        let with_plan = HistoryFilters {
+            with_plan: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            storage
+                .get_query_history_count(&connection_id, &with_plan)
+                .unwrap(),
+            2
+        );
+
+        let plan_of = |id: i64| {
+            storage
+                .get_query_history_entry(id)
+                .unwrap()
+                .unwrap()
+                .plan_json
+        };
+        assert_eq!(plan_of(entries[0].id), Some(big_plan.clone()));
+        assert_eq!(plan_of(entries[1].id), None);
+        assert_eq!(plan_of(entries[2].id), Some(small_plan));
+        assert!(storage.get_query_history_entry(-1).unwrap().is_none());
+
+        let stored: bool = storage
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT plan_compressed FROM query_history WHERE id = ?1",
+                [entries[0].id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(stored, "Long plans are deflated");
+    }
+
+    #[test]
+    fn dedupes_and_prunes_history() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "test".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: ":memory:".into(),
+                    home_relative_path: None,
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+        let connection_id = connection_id.to_string();
+        let day = HISTORY_DEDUPE_WINDOW_SECS;
+
+        let save = |query: &str, executed_at: i64, dedupe: bool| {
+            storage
+                .save_query_history(&history_entry(&connection_id, query, executed_at), dedupe)
+                .unwrap();
+        };
+        save("SELECT 1", 0, true);
+        save("SELECT 2", 10, true);
+        save("SELECT 1", 20, true);
+        // Too long after the first run to be the same entry
+        save("SELECT 1", 20 + day + 1, true);
+        save("SELECT 1", 30 + day, false);
+
+        let entries = storage.get_query_history(&connection_id, None).unwrap();
+        let runs: Vec<_> = entries
+            .iter()
+            .map(|e| (e.query_text.as_str(), e.executed_at, e.run_count))
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                ("SELECT 1", 30 + day, 1),
+                ("SELECT 1", 20 + day + 1, 1),
+                ("SELECT 1", 20, 2),
+                ("SELECT 2", 10, 1),
+            ]
+        );
+
+        // Nothing to prune, so no vacuum either
+        let keep_all = HistorySettings {
+            max_entries: None,
+            ..Default::default()
+        };
+        assert_eq!(
+            storage.prune_query_history(&keep_all, 100 * day).unwrap(),
+            HistoryPruneReport {
+                deleted_entries: 0,
+                reclaimed_bytes: 0
+            }
+        );
+
+        for i in 0..200 {
+            save(
+                &format!("SELECT {i}, '{}'", "x".repeat(1000)),
+                2 * day + i,
+                false,
+            );
+        }
+        let settings = HistorySettings {
+            dedupe: true,
+            max_entries: Some(150),
+            max_age_days: Some(2),
+        };
+        let report = storage.prune_query_history(&settings, 4 * day).unwrap();
+        // 4 older than 2 days, then 50 more beyond the newest 150
+        assert_eq!(report.deleted_entries, 54);
+        assert!(report.reclaimed_bytes > 0);
+        assert_eq!(
+            storage
+                .get_query_history_count(&connection_id, &HistoryFilters::default())
+                .unwrap(),
+            150
+        );
+
+        assert_eq!(
+            storage.get_history_settings().unwrap(),
+            HistorySettings::default()
+        );
+        storage.set_history_settings(&settings).unwrap();
+        assert_eq!(storage.get_history_settings().unwrap(), settings);
+    }
+
+    #[test]
+    fn filters_saved_queries() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let (connection_id, other_connection_id) = (Uuid::new_v4(), Uuid::new_v4());
+        for id in [connection_id, other_connection_id] {
+            storage
+                .save_connection(&ConnectionInfo {
+                    id,
+                    name: "test".into(),
+                    folder: None,
+                    environment: None,
+                    color: None,
+                    connected: false,
+                    permissions: Permissions::default(),
+                    config: ConnectionConfig::SQLite {
+                        db_path: ":memory:".into(),
+                        home_relative_path: None,
+                    },
+                    snapshot: None,
+                    file_missing: false,
+                    capabilities: None,
+                    active_schema: None,
+                })
+                .unwrap();
+        }
+
+        let save = |name: &str, query_text: &str, connection_id: Option<Uuid>, tags: &[&str]| {
+            storage
+                .save_query(&SavedQuery {
+                    id: 0,
+                    name: name.into(),
+                    description: None,
+                    query_text: query_text.into(),
+                    connection_id,
+                    tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                    created_at: 0,
+                    updated_at: 0,
+                    favorite: false,
+                })
+                .unwrap()
+        };
+        let reports = save(
+            "Reports",
+            "SELECT * FROM sales",
+            None,
+            &["reporting", "daily"],
+        );
+        let cleanup = save(
+            "Cleanup",
+            "DELETE FROM Sessions",
+            Some(connection_id),
+            &["ops"],
+        );
+        let other = save(
+            "Other",
+            "SELECT 1",
+            Some(other_connection_id),
+            &["reporting"],
+        );
+        save("Untagged", "SELECT 2", None, &[]);
+
+        let names = |filters: ScriptFilters| -> Vec<String> {
+            storage
+                .get_saved_queries(Some(&connection_id), &filters)
+                .unwrap()
+                .into_iter()
+                .map(|query| query.name)
+                .collect()
+        };
+
+        // The most recently updated first, ids breaking ties between scripts saved within the same second
+        assert_eq!(
+            names(ScriptFilters::default()),
+            ["Untagged", "Cleanup", "Reports"]
+        );
+        assert_eq!(
+            names(ScriptFilters {
+                tag: Some("reporting".into()),
+                ..Default::default()
+            }),
+            ["Reports"]
+        );
+        assert_eq!(
+            names(ScriptFilters {
+                search_text: Some("sessions".into()),
+                ..Default::default()
+            }),
+            ["Cleanup"]
+        );
+
+        assert!(storage.toggle_saved_query_favorite(reports).unwrap());
+        assert_eq!(
+            names(ScriptFilters::default()),
+            ["Reports", "Untagged", "Cleanup"]
+        );
+        assert_eq!(
+            names(ScriptFilters {
+                favorites_only: true,
+                ..Default::default()
+            }),
+            ["Reports"]
+        );
+        assert!(!storage.toggle_saved_query_favorite(reports).unwrap());
+        assert!(storage.toggle_saved_query_favorite(-1).is_err());
+
+        let all = storage
+            .get_saved_queries(None, &ScriptFilters::default())
+            .unwrap();
+        assert_eq!(all.len(), 4);
+        let tags = |id| &all.iter().find(|query| query.id == id).unwrap().tags;
+        assert_eq!(tags(reports), &["reporting", "daily"]);
+        assert_eq!(tags(cleanup), &["ops"]);
+        assert_eq!(tags(other), &["reporting"]);
+    }
+
+    #[test]
+    fn prunes_tree_states_with_their_connection() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "test".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: ":memory:".into(),
+                    home_relative_path: None,
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+
+        storage.save_tree_state(&connection_id, "{}").unwrap();
+        storage.save_tree_state(&connection_id, "[]").unwrap();
+        assert_eq!(
+            storage.get_tree_state(&connection_id).unwrap().as_deref(),
+            Some("[]")
+        );
+
+        storage.remove_connection(&connection_id).unwrap();
+        assert_eq!(storage.get_tree_state(&connection_id).unwrap(), None);
+    }
+
+    #[test]
+    fn stores_home_relative_paths() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "test".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: r"C:\Users\me\my data\café.db".into(),
+                    home_relative_path: Some("~/my data/café.db".into()),
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+        assert_eq!(storage.get_last_connected(&connection_id).unwrap(), None);
+
+        let [connection] = &storage.get_connections().unwrap()[..] else {
+            panic!("expected one connection");
+        };
+        assert!(matches!(
+            &connection.config,
+            ConnectionConfig::SQLite { db_path, home_relative_path }
+                if db_path == r"C:\Users\me\my data\café.db"
+                    && home_relative_path.as_deref() == Some("~/my data/café.db")
+        ));
+
+        storage.update_last_connected(&connection_id).unwrap();
+        assert!(storage
+            .get_last_connected(&connection_id)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn groups_connections_in_folders() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let save = |name: &str, folder: Option<&str>| {
+            let id = Uuid::new_v4();
+            storage
+                .save_connection(&ConnectionInfo {
+                    id,
+                    name: name.into(),
+                    folder: folder.map(Into::into),
+                    environment: None,
+                    color: None,
+                    connected: false,
+                    permissions: Permissions::default(),
+                    config: ConnectionConfig::SQLite {
+                        db_path: ":memory:".into(),
+                        home_relative_path: None,
+                    },
+                    snapshot: None,
+                    file_missing: false,
+                    capabilities: None,
+                    active_schema: None,
+                })
+                .unwrap();
+            id
+        };
+        save("zeta", None);
+        save("prod", Some("acme/prod"));
+        let staging = save("staging", Some("acme"));
+        save("reports", Some("acme-labs"));
+        save("alpha", None);
+        save("api", Some("acme/prod"));
+
+        let listed = |storage: &Storage| {
+            storage
+                .get_connections()
+                .unwrap()
+                .into_iter()
+                .map(|c| (c.folder, c.name))
+                .collect::<Vec<_>>()
+        };
+        let entry = |folder: Option<&str>, name: &str| (folder.map(String::from), name.to_owned());
+        // A folder's subfolders come right after it, even though `-` sorts before `/`
+        assert_eq!(
+            listed(&storage),
+            [
+                entry(None, "alpha"),
+                entry(None, "zeta"),
+                entry(Some("acme"), "staging"),
+                entry(Some("acme/prod"), "api"),
+                entry(Some("acme/prod"), "prod"),
+                entry(Some("acme-labs"), "reports"),
+            ]
+        );
+
+        let moved = storage
+            .rename_connection_folder("acme", "clients/acme")
+            .unwrap();
+        assert_eq!(moved.len(), 3);
+        assert!(moved.contains(&staging));
+        assert_eq!(
+            listed(&storage),
+            [
+                entry(None, "alpha"),
+                entry(None, "zeta"),
+                entry(Some("acme-labs"), "reports"),
+                entry(Some("clients/acme"), "staging"),
+                entry(Some("clients/acme/prod"), "api"),
+                entry(Some("clients/acme/prod"), "prod"),
+            ]
+        );
+    }
+
+    #[test]
+    fn stores_mysql_connections() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: Uuid::new_v4(),
+                name: "shop".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::MySQL {
+                    connection_string: "mysql://app@localhost/shop".into(),
+                    ca_cert_path: Some("/etc/ssl/ca.pem".into()),
+                    ssh_tunnel: Some(SshTunnelConfig {
+                        host: "bastion.example.com".into(),
+                        port: None,
+                        username: "deploy".into(),
+                        auth: SshAuth::Agent,
+                    }),
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+
+        let [connection] = &storage.get_connections().unwrap()[..] else {
+            panic!("expected one connection");
+        };
+        assert!(matches!(
+            &connection.config,
+            ConnectionConfig::MySQL { connection_string, ca_cert_path, ssh_tunnel }
+                if connection_string == "mysql://app@localhost/shop"
+                    && ca_cert_path.as_deref() == Some("/etc/ssl/ca.pem")
+                    && ssh_tunnel.as_ref().is_some_and(|tunnel| tunnel.host == "bastion.example.com")
+        ));
+    }
+
+    #[test]
+    fn stores_environments() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let mut connection = ConnectionInfo {
+            id: Uuid::new_v4(),
+            name: "billing".into(),
+            folder: None,
+            environment: Some(Environment::Prod),
+            color: Some("#d43f3a".into()),
+            connected: false,
+            permissions: Permissions::default(),
+            config: ConnectionConfig::SQLite {
+                db_path: ":memory:".into(),
+                home_relative_path: None,
+            },
+            snapshot: None,
+            file_missing: false,
+            capabilities: None,
+            active_schema: None,
+        };
+        storage.save_connection(&connection).unwrap();
+        let stored = &storage.get_connections().unwrap()[0];
+        assert_eq!(stored.environment, Some(Environment::Prod));
+        assert_eq!(stored.color.as_deref(), Some("#d43f3a"));
+
+        connection.environment = Some(Environment::Staging);
+        connection.color = None;
+        storage.update_connection(&connection).unwrap();
+        let stored = &storage.get_connections().unwrap()[0];
+        assert_eq!(stored.environment, Some(Environment::Staging));
+        assert_eq!(stored.color, None);
+    }
+
+    #[test]
+    fn scopes_query_variables() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "test".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: ":memory:".into(),
+                    home_relative_path: None,
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+
+        let variable = |name: &str, value: &str, connection_id: Option<Uuid>| QueryVariable {
+            id: 0,
+            name: name.into(),
+            value: value.into(),
+            connection_id,
+        };
+        storage
+            .save_query_variable(&variable("limit", "10", None))
+            .unwrap();
+        let mine = storage
+            .save_query_variable(&variable("limit", "100", Some(connection_id)))
+            .unwrap();
+        storage
+            .save_query_variable(&variable("day", "'monday'", None))
+            .unwrap();
+        // Same name and scope
+        storage
+            .save_query_variable(&variable("day", "'tuesday'", None))
+            .unwrap_err();
+
+        let values = |variables: Vec<QueryVariable>| -> Vec<(String, String)> {
+            variables.into_iter().map(|v| (v.name, v.value)).collect()
+        };
+        assert_eq!(
+            values(storage.get_query_variables(Some(&connection_id)).unwrap()),
+            [
+                ("day".to_owned(), "'monday'".to_owned()),
+                ("limit".to_owned(), "10".to_owned()),
+                ("limit".to_owned(), "100".to_owned()),
+            ]
+        );
+        assert_eq!(
+            storage
+                .get_query_variables(Some(&Uuid::new_v4()))
+                .unwrap()
+                .len(),
+            2
+        );
+
+        storage
+            .save_query_variable(&QueryVariable {
+                value: "1000".into(),
+                ..mine.clone()
+            })
+            .unwrap();
+        storage.delete_query_variable(mine.id).unwrap();
+        assert_eq!(storage.get_query_variables(None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn keeps_scheduled_queries_and_their_runs() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "test".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: ":memory:".into(),
+                    home_relative_path: None,
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+
+        let orphans = storage
+            .save_scheduled_query(&ScheduledQuery {
+                id: 0,
+                name: "orphans".into(),
+                connection_id,
+                query_text: "SELECT * FROM orders WHERE customer_id IS NULL".into(),
+                schedule: Schedule::Every { seconds: 300 },
+                enabled: true,
+                alert_if_rows: true,
+                confirmed_destructive: false,
+                last_run_at: None,
+                last_status: None,
+                last_error: None,
+            })
+            .unwrap();
+        assert_ne!(orphans.id, 0);
+
+        storage
+            .record_scheduled_run(orphans.id, 100, ScheduledRunStatus::Error, Some("boom"))
+            .unwrap();
+        // Saving it again leaves its last run alone
+        let updated = storage
+            .save_scheduled_query(&ScheduledQuery {
+                schedule: Schedule::Cron {
+                    expression: "0 9 * * 1-5".into(),
+                },
+                ..orphans.clone()
+            })
+            .unwrap();
+        assert_eq!(updated.last_run_at, Some(100));
+        assert_eq!(updated.last_status, Some(ScheduledRunStatus::Error));
+        assert_eq!(updated.last_error.as_deref(), Some("boom"));
+        assert_eq!(storage.get_scheduled_queries().unwrap(), [updated]);
+
+        let entry = |scheduled| QueryHistoryEntry {
+            scheduled,
+            ..history_entry(&connection_id.to_string(), "SELECT 1", 10)
+        };
+        storage.save_query_history(&entry(false), true).unwrap();
+        // Not folded into the run from the editor
+        storage.save_query_history(&entry(true), true).unwrap();
+        storage.save_query_history(&entry(true), true).unwrap();
+        let filters = HistoryFilters {
+            scheduled: Some(true),
+            ..Default::default()
+        };
+        let page = storage
+            .get_query_history_page(&connection_id.to_string(), None, 10, &filters)
+            .unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert!(page.entries[0].scheduled);
+        assert_eq!(page.entries[0].run_count, 2);
+
+        storage.delete_scheduled_query(orphans.id).unwrap();
+        assert!(storage.get_scheduled_queries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn scopes_snippets() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "test".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: ":memory:".into(),
+                    home_relative_path: None,
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+
+        // Seeded on first run
+        let defaults = storage.get_snippets(None).unwrap();
+        let select = defaults.iter().find(|s| s.prefix == "sel*").unwrap();
+        assert_eq!(select.body, "SELECT * FROM $1 LIMIT 100;");
+        assert!(defaults
+            .iter()
+            .all(|s| s.connection_id.is_none() && s.db_type.is_none()));
+
+        let snippet =
+            |prefix: &str, connection_id: Option<Uuid>, db_type: Option<Database>| Snippet {
+                id: 0,
+                name: prefix.to_uppercase(),
+                prefix: prefix.into(),
+                body: format!("-- {prefix}"),
+                connection_id,
+                db_type,
+            };
+        let mine = storage
+            .save_snippet(&snippet("mine", Some(connection_id), None))
+            .unwrap();
+        storage
+            .save_snippet(&snippet("pg", None, Some(Database::Postgres)))
+            .unwrap();
+        let lite = storage
+            .save_snippet(&snippet("lite", None, Some(Database::Sqlite)))
+            .unwrap();
+        storage
+            .save_snippet(&snippet("elsewhere", Some(Uuid::new_v4()), None))
+            .unwrap_err();
+
+        let prefixes = |snippets: Vec<Snippet>| -> Vec<String> {
+            snippets
+                .into_iter()
+                .map(|s| s.prefix)
+                .filter(|prefix| !defaults.iter().any(|d| &d.prefix == prefix))
+                .collect()
+        };
+        assert_eq!(
+            prefixes(
+                storage
+                    .get_snippets(Some((&connection_id, Database::Sqlite)))
+                    .unwrap()
+            ),
+            ["lite", "mine"]
+        );
+        assert_eq!(
+            prefixes(storage.get_snippets(None).unwrap()),
+            ["lite", "mine", "pg"]
+        );
+
+        let updated = storage
+            .save_snippet(&Snippet {
+                body: "SELECT 1;".into(),
+                ..lite.clone()
+            })
+            .unwrap();
+        assert_eq!(updated.id, lite.id);
+        assert_eq!(
+            storage
+                .get_snippets(None)
+                .unwrap()
+                .iter()
+                .find(|s| s.id == lite.id),
+            Some(&updated)
+        );
+        storage.delete_snippet(lite.id).unwrap();
+
+        // Dropped along with their connection
+        storage.remove_connection(&connection_id).unwrap();
+        let left = storage.get_snippets(None).unwrap();
+        assert!(!left.iter().any(|s| s.id == mine.id || s.id == lite.id));
+        assert_eq!(left.len(), defaults.len() + 1);
+    }
+
+    #[test]
+    fn keeps_a_session_per_window() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let migrations = Migrator::new().migrations;
+        Migrator {
+            migrations: &migrations[..17],
+        }
+        .migrate(&mut conn)
+        .unwrap();
+        conn.execute(
+            "INSERT INTO app_settings (key, value, updated_at) VALUES ('session_state', '{\"tabs\":[]}', 42)",
+            [],
+        )
+        .unwrap();
+        Migrator::new().migrate(&mut conn).unwrap();
+        let storage = Storage {
+            conn: Mutex::new(conn),
+        };
+
+        // The session of earlier versions is the main window's
+        assert_eq!(
+            storage.get_session("main").unwrap(),
+            Some(SessionState {
+                session_id: "main".into(),
+                version: 1,
+                data: r#"{"tabs":[]}"#.into(),
+                updated_at: 42,
+            })
+        );
+        assert_eq!(storage.get_setting("session_state").unwrap(), None);
+
+        storage.save_session("second", 2, "second window").unwrap();
+        storage.save_session("main", 2, "main window").unwrap();
+        storage
+            .save_session("second", 2, "second window, later")
+            .unwrap();
+        assert_eq!(
+            storage.get_session("main").unwrap().unwrap().data,
+            "main window"
+        );
+        assert_eq!(
+            storage.get_session("second").unwrap().unwrap().data,
+            "second window, later"
+        );
+
+        let sessions: Vec<_> = storage
+            .list_sessions()
+            .unwrap()
+            .into_iter()
+            .map(|session| (session.session_id, session.version))
+            .collect();
+        assert_eq!(sessions, [("main".into(), 2), ("second".into(), 2)]);
+
+        storage.delete_session("second").unwrap();
+        assert_eq!(storage.get_session("second").unwrap(), None);
+        assert_eq!(storage.list_sessions().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn keeps_the_latest_draft_of_each_buffer() {
+        let storage = Storage::new(PathBuf::from(":memory:")).unwrap();
+        let connection_id = Uuid::new_v4();
+        storage
+            .save_connection(&ConnectionInfo {
+                id: connection_id,
+                name: "test".into(),
+                folder: None,
+                environment: None,
+                color: None,
+                connected: false,
+                permissions: Permissions::default(),
+                config: ConnectionConfig::SQLite {
+                    db_path: ":memory:".into(),
+                    home_relative_path: None,
+                },
+                snapshot: None,
+                file_missing: false,
+                capabilities: None,
+                active_schema: None,
+            })
+            .unwrap();
+
+        let long = "SELECT 1;\n".repeat(1000);
+        storage
+            .save_draft_buffer("main:script-1", Some(&connection_id), "SELECT", 6)
+            .unwrap();
+        storage
+            .save_draft_buffer("main:script-1", Some(&connection_id), &long, 12)
+            .unwrap();
+        storage
+            .save_draft_buffer("main:script--1", None, "SELECT 2", 8)
+            .unwrap();
+
+        let stored: Vec<(String, bool, usize)> = {
+            let conn = storage.conn.lock().unwrap();
+            let mut stmt = conn
+                .prepare("SELECT buffer_id, compressed, length(content) FROM draft_buffers ORDER BY buffer_id")
+                .unwrap();
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        };
+        // Only the latest version of each, deflated when long
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0], ("main:script--1".into(), false, 8));
+        assert!(stored[1].1 && stored[1].2 < long.len() / 10);
+
+        let mut drafts = storage.get_draft_buffers().unwrap();
+        drafts.sort_by(|a, b| a.buffer_id.cmp(&b.buffer_id));
+        assert_eq!(drafts[0].content, "SELECT 2");
+        assert_eq!(drafts[0].connection_id, None);
+        assert_eq!(drafts[1].content, long);
+        assert_eq!(drafts[1].cursor, 12);
+        assert_eq!(drafts[1].connection_id, Some(connection_id));
+
+        // Drafts outlive their connection
+        storage.remove_connection(&connection_id).unwrap();
+        let drafts = storage.get_draft_buffers().unwrap();
+        assert_eq!(drafts.len(), 2);
+        assert!(drafts.iter().all(|draft| draft.connection_id.is_none()));
+
+        storage.discard_draft_buffer("main:script-1").unwrap();
+        let drafts = storage.get_draft_buffers().unwrap();
+        assert_eq!(drafts.len(), 1);
+        assert_eq!(drafts[0].buffer_id, "main:script--1");
+    }
 }
@@ -0,0 +1,108 @@
+//! The state of a connection's Items panel (which nodes are expanded, where it's scrolled to and which kinds of
+//! objects are shown), so that it survives restarts. States are stored in [`Storage`](crate::storage::Storage)
+//! as JSON, and versioned so that a change in how node paths are encoded can migrate older states.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::Error;
+
+/// The version of states written by this version of pgpad
+pub const TREE_STATE_VERSION: u32 = 1;
+
+/// Largest state that can be saved, in bytes of JSON
+pub const MAX_TREE_STATE_BYTES: usize = 256 * 1024;
+
+/// Most expanded nodes kept in a state
+pub const MAX_EXPANDED_NODES: usize = 5_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeState {
+    pub version: u32,
+    /// Path of each expanded node from the root of the tree, e.g. `["public", "users"]` for a table
+    pub expanded: Vec<Vec<String>>,
+    /// Scroll offset of the panel, in pixels
+    #[serde(default)]
+    pub scroll_top: f64,
+    /// The kinds of objects shown (e.g. "table"), empty if every kind is
+    #[serde(default)]
+    pub filters: Vec<String>,
+}
+
+/// Brings a state written by an older version of pgpad up to [`TREE_STATE_VERSION`]
+fn upgrade(state: Value, version: u64) -> Result<Value, Error> {
+    match version {
+        1 => Ok(state),
+        version => Err(anyhow::anyhow!("Unsupported tree state version {version}").into()),
+    }
+}
+
+/// Parses and validates a state, upgrading it if it's from an older version
+pub fn parse_tree_state(json: &str) -> Result<TreeState, Error> {
+    if json.len() > MAX_TREE_STATE_BYTES {
+        return Err(anyhow::anyhow!(
+            "The tree state is too large ({} bytes, at most {MAX_TREE_STATE_BYTES} are allowed)",
+            json.len()
+        )
+        .into());
+    }
+
+    let state: Value = serde_json::from_str(json)?;
+    let version = state
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| anyhow::anyhow!("The tree state has no version"))?;
+    let mut state: TreeState = serde_json::from_value(upgrade(state, version)?)?;
+    state.version = TREE_STATE_VERSION;
+
+    if state.expanded.len() > MAX_EXPANDED_NODES {
+        return Err(anyhow::anyhow!(
+            "Too many expanded nodes ({}, at most {MAX_EXPANDED_NODES} are allowed)",
+            state.expanded.len()
+        )
+        .into());
+    }
+    if state.expanded.iter().any(|path| path.is_empty()) {
+        return Err(anyhow::anyhow!("Expanded node paths can't be empty").into());
+    }
+    if !state.scroll_top.is_finite() || state.scroll_top < 0.0 {
+        state.scroll_top = 0.0;
+    }
+    state.expanded.dedup();
+
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_states() {
+        let state = parse_tree_state(
+            r#"{"version": 1, "expanded": [["public", "users"], ["public", "users"]], "scroll_top": -3}"#,
+        )
+        .unwrap();
+        assert_eq!(state.expanded, [["public", "users"]]);
+        assert_eq!(state.scroll_top, 0.0);
+        assert!(state.filters.is_empty());
+
+        for invalid in [
+            r#"{"expanded": []}"#,
+            r#"{"version": 99, "expanded": []}"#,
+            r#"{"version": 1, "expanded": [[]]}"#,
+            r#"{"version": 1, "expanded": "public"}"#,
+        ] {
+            assert!(parse_tree_state(invalid).is_err(), "{invalid}");
+        }
+
+        let huge = serde_json::to_string(&TreeState {
+            version: 1,
+            expanded: vec![vec!["x".repeat(100)]; MAX_EXPANDED_NODES],
+            scroll_top: 0.0,
+            filters: Vec::new(),
+        })
+        .unwrap();
+        assert!(parse_tree_state(&huge).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use std::fmt::Write;
 
 use serde::de::IgnoredAny;
-use serde_json::value::RawValue;
+use serde_json::{value::RawValue, Value};
 
 use crate::Error;
 
@@ -26,10 +26,92 @@ pub fn serialize_as_json_array<'a, I: ExactSizeIterator<Item = &'a str>>(
     Ok(RawValue::from_string(json).unwrap())
 }
 
+/// Closes the page of rows written to `buf` and hands it out, leaving `buf` empty. The page is moved out rather than
+/// copied, and `buf` gets as much room as it took, since the next page is likely to be about as big.
+pub fn finish_page(buf: &mut String, row_count: usize) -> Box<RawValue> {
+    if row_count == 0 {
+        buf.push('[');
+    }
+    buf.push(']');
+
+    let json = std::mem::replace(buf, String::with_capacity(buf.len()));
+    RawValue::from_string(json).unwrap()
+}
+
+/// Writes `s` to `buf` as a JSON string. Besides what JSON requires, every control character is escaped (e.g.
+/// U+0085), so that it shows in the results rather than being rendered.
+pub fn write_json_string(buf: &mut String, s: &str) {
+    buf.reserve(s.len() + 2);
+    buf.push('"');
+
+    // Runs of characters that need no escaping are copied at once
+    let bytes = s.as_bytes();
+    let mut start = 0;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let (c, len) = match bytes[idx] {
+            byte @ (b'"' | b'\\' | 0x00..=0x1f | 0x7f) => (char::from(byte), 1),
+            // U+0080 to U+009F, encoded as 0xC2 followed by the code point's low byte
+            0xc2 if matches!(bytes.get(idx + 1), Some(0x80..=0x9f)) => {
+                (char::from(bytes[idx + 1]), 2)
+            }
+            _ => {
+                idx += 1;
+                continue;
+            }
+        };
+
+        buf.push_str(&s[start..idx]);
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c => write!(buf, "\\u{:04x}", c as u32).expect("writing to a String doesn't fail"),
+        }
+        idx += len;
+        start = idx;
+    }
+
+    buf.push_str(&s[start..]);
+    buf.push('"');
+}
+
+/// Quotes a name for Postgres or SQLite, e.g. a table called `Order Items`
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quotes a name for MySQL, which uses backticks rather than double quotes
+pub fn quote_mysql_identifier(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
 pub fn is_json(input: &[u8]) -> bool {
     serde_json::from_slice::<IgnoredAny>(input).is_ok()
 }
 
+/// Numbers are taken as-is, and strings are accepted if they parse as a finite one (e.g. Postgres' NUMERIC, which
+/// is sent as text). Anything else is None.
+pub fn numeric_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse::<f64>().ok().filter(|n| n.is_finite()),
+        _ => None,
+    }
+}
+
+pub const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Feeds `bytes` into an FNV-1a hash, starting from [`FNV_OFFSET_BASIS`]. Unlike the standard library's hasher,
+/// it's guaranteed to stay the same between versions, so its hashes can be persisted.
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -49,6 +131,63 @@ mod tests {
         assert_eq!(serde_json::to_string(&json).unwrap(), r#"[]"#);
     }
 
+    #[test]
+    fn escapes_json_strings() {
+        // Every character escaped one at a time, which the runs have to match
+        fn escape_chars(s: &str) -> String {
+            let mut escaped = String::from("\"");
+            for c in s.chars() {
+                match c {
+                    '"' => escaped.push_str("\\\""),
+                    '\\' => escaped.push_str("\\\\"),
+                    '\n' => escaped.push_str("\\n"),
+                    '\r' => escaped.push_str("\\r"),
+                    '\t' => escaped.push_str("\\t"),
+                    c if c.is_control() => write!(&mut escaped, "\\u{:04x}", c as u32).unwrap(),
+                    c => escaped.push(c),
+                }
+            }
+            escaped.push('"');
+            escaped
+        }
+
+        for s in [
+            "",
+            "plain",
+            "\"quoted\" back\\slash",
+            "line1\nline2\r\ttab",
+            "bell\u{7}del\u{7f}nul\u{0}",
+            "next line\u{85}, \u{80}\u{9f} and \u{a0}\u{c2}",
+            "ünïcödé, 日本語 and 🦀",
+        ] {
+            let mut escaped = String::new();
+            write_json_string(&mut escaped, s);
+            assert_eq!(escaped, escape_chars(s));
+            assert_eq!(serde_json::from_str::<String>(&escaped).unwrap(), s);
+        }
+    }
+
+    #[test]
+    fn finishes_pages_without_copying_them() {
+        let mut buf = String::from("[[1],[2]");
+        let page = finish_page(&mut buf, 2);
+        assert_eq!(page.get(), "[[1],[2]]");
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= page.get().len());
+
+        assert_eq!(finish_page(&mut buf, 0).get(), "[]");
+    }
+
+    #[test]
+    fn reads_numbers_spelled_as_text() {
+        assert_eq!(numeric_value(&serde_json::json!(2)), Some(2.0));
+        assert_eq!(numeric_value(&serde_json::json!(" -12.50 ")), Some(-12.5));
+        for value in ["NaN", "inf", "-Infinity", "abc"] {
+            assert_eq!(numeric_value(&serde_json::json!(value)), None, "{value}");
+        }
+        assert_eq!(numeric_value(&serde_json::json!(true)), None);
+    }
+
     #[test]
     fn test_is_json() {
         assert!(is_json(b"{}"));
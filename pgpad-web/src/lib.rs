@@ -9,14 +9,65 @@ use axum::{
     Json, Router,
 };
 use pgpad_core::{
+    connection_file::ImportedConnection,
     database::{
+        browse::BrowsePage,
+        column_stats::ColumnStats,
+        csv_import::{CsvImportOptions, CsvImportReport},
+        definition::Definition,
+        delimited::ExportOptions,
+        diagnostics::Diagnostic,
+        error_hints::ErrorSuggestion,
+        execution_marks::StatementMarker,
+        filtering::{FilteredQuery, RowFilter},
+        formatter::{FormatSettings, FormattedSql},
+        grouping::{Aggregate, GroupedQuery},
+        hover::Hover,
+        insert_export::InsertOptions,
+        json_export::JsonFormat,
+        postgres::{
+            activity::{ActivityFilters, ServerActivity},
+            copy::CopyReport,
+            explain::{ExplainOptions, ExplainedPlan},
+            replication::ReplicationInfo,
+            stats::PostgresStats,
+        },
+        preflight::{EnvironmentPolicies, PreflightSettings, SubmitOutcome},
+        probe::ConnectionProbe,
+        query_tags::QueryTagSettings,
+        reconnect::ReconnectSettings,
+        relationship_graph::RelationshipGraph,
+        result_cache::ResultCacheSettings,
+        result_diff::ResultDiff,
+        sanitize::SanitizedSql,
+        scheduler::{ScheduledRunStatus, SCHEDULER_TICK},
+        semantic_search::{SchemaSearchResults, SemanticSearchSettings},
         services,
+        sorting::{Collation, NullsOrder, SortKey, SortedQuery},
+        sqlite::{explain::QueryPlan, join::JoinedQuery, snapshot::SnapshotRefresh},
+        table_ddl::TableDdl,
+        trace::TracedStatement,
+        transactions::TransactionInfo,
         types::{
-            ConnectionConfig, ConnectionInfo, DatabaseSchema, Permissions, QuerySnapshot,
-            QueryStatus,
+            ColumnInfo, ConnectionConfig, ConnectionInfo, Database, DatabaseSchema, Environment,
+            Permissions, QuerySnapshot, QueryStatus, ResourceLimits, RowDetailField, ServerMessage,
+            StatementInfo, SubmitOptions, TimingBreakdown,
         },
+        ConnectionHealth,
+    },
+    external_edit::{ConflictResolution, ExternalEditSession, ExternalEditorSettings},
+    linked_scripts::IndexedScriptDirectory,
+    operations::OperationInfo,
+    script_file::ScriptFile,
+    script_templates::ScriptTemplate,
+    storage::{
+        DraftBuffer, ExportTemplate, HistoryCursor, HistoryFilters, HistoryPruneReport,
+        HistorySettings, QueryHistoryPage, QueryVariable, ScheduledQuery, SessionInfo,
+        SessionState, Snippet,
     },
-    AppState, Certificates, ConnectionMonitor, QueryHistoryEntry,
+    tree_state::TreeState,
+    AppState, Certificates, ConnectionMonitor, CredentialBackendStatus, QueryHistoryEntry,
+    SecretBackend,
 };
 use rand::distr::{Alphanumeric, SampleString};
 use rfd::FileDialog;
@@ -53,21 +104,79 @@ impl WebState {
             }
         });
 
-        let app_state = state.app_state.clone();
+        let dropped_state = state.clone();
         tokio::spawn(async move {
             while let Some(connection_id) = dropped_connections.recv().await {
-                if !app_state.mark_disconnected(connection_id) {
+                let Some(was_connected) = dropped_state.app_state.mark_disconnected(connection_id)
+                else {
                     log::error!("Connection {connection_id} not found!");
                     continue;
-                }
+                };
 
                 log::info!("Connection {connection_id} marked disconnected");
+
+                // Connections that were disconnected by hand drop too
+                if was_connected {
+                    tokio::spawn(dropped_state.clone().reconnect(connection_id));
+                }
             }
         });
 
+        tokio::spawn(state.clone().run_scheduled_queries());
+
         Ok(state)
     }
 
+    /// Runs the scheduled queries that are due. There are no events to tell the UI about failures and alerts, so
+    /// they're only logged.
+    async fn run_scheduled_queries(self) {
+        let mut ticks = tokio::time::interval(SCHEDULER_TICK);
+        loop {
+            ticks.tick().await;
+            let due = match services::due_scheduled_queries(&self.app_state).await {
+                Ok(due) => due,
+                Err(e) => {
+                    log::error!("Failed to look up scheduled queries: {e}");
+                    continue;
+                }
+            };
+
+            for scheduled in due {
+                let app_state = self.app_state.clone();
+                tokio::spawn(async move {
+                    let (id, name) = (scheduled.id, scheduled.name.clone());
+                    match services::run_scheduled_query(scheduled, &app_state).await {
+                        Ok(Some(run)) if run.status == ScheduledRunStatus::Error => log::warn!(
+                            "Scheduled query {name} failed: {}",
+                            run.error.unwrap_or_default()
+                        ),
+                        Ok(Some(run)) if run.alert => {
+                            log::warn!("Scheduled query {name} returned {} rows", run.row_count)
+                        }
+                        Ok(_) => {}
+                        Err(e) => log::error!("Failed to run scheduled query {id} ({name}): {e}"),
+                    }
+                });
+            }
+        }
+    }
+
+    /// There are no events to tell the UI how reconnecting goes, so it's only logged
+    async fn reconnect(self, connection_id: Uuid) {
+        let log_status = |status| log::info!("Connection {connection_id}: {status:?}");
+        if let Err(e) = services::reconnect_to_database(
+            connection_id,
+            &self.app_state,
+            &self.connection_monitor,
+            &self.certificates,
+            log_status,
+        )
+        .await
+        {
+            log::error!("Failed to reconnect {connection_id}: {e}");
+        }
+    }
+
     pub fn auth_token(&self) -> &str {
         &self.auth_token
     }
@@ -93,45 +202,359 @@ pub fn router(static_dir: PathBuf, state: WebState) -> Router {
             "/commands/initialize_connections",
             post(initialize_connections),
         )
+        .route("/commands/initialize_workspace", post(initialize_workspace))
         .route("/commands/get_connections", post(get_connections))
-        .route("/commands/get_session_state", post(get_session_state))
-        .route("/commands/save_session_state", post(save_session_state))
+        .route("/commands/autosave_buffer", post(autosave_buffer))
+        .route("/commands/get_draft_buffers", post(get_draft_buffers))
+        .route("/commands/discard_draft_buffer", post(discard_draft_buffer))
+        .route("/commands/list_sessions", post(list_sessions))
+        .route("/commands/save_session", post(save_session))
+        .route("/commands/get_session", post(get_session))
+        .route("/commands/delete_session", post(delete_session))
+        .route("/commands/save_tree_state", post(save_tree_state))
+        .route("/commands/get_tree_state", post(get_tree_state))
+        .route(
+            "/commands/get_semantic_search_settings",
+            post(get_semantic_search_settings),
+        )
+        .route(
+            "/commands/set_semantic_search_settings",
+            post(set_semantic_search_settings),
+        )
+        .route(
+            "/commands/semantic_search_schema",
+            post(semantic_search_schema),
+        )
+        .route(
+            "/commands/get_buffer_execution_state",
+            post(get_buffer_execution_state),
+        )
         .route("/commands/test_connection", post(test_connection))
+        .route("/commands/probe_connections", post(probe_connections))
         .route("/commands/add_connection", post(add_connection))
         .route("/commands/update_connection", post(update_connection))
+        .route(
+            "/commands/rename_connection_folder",
+            post(rename_connection_folder),
+        )
+        .route("/commands/export_connections", post(export_connections))
+        .route("/commands/import_connections", post(import_connections))
         .route("/commands/remove_connection", post(remove_connection))
+        .route(
+            "/commands/relocate_database_file",
+            post(relocate_database_file),
+        )
         .route("/commands/connect_to_database", post(connect_to_database))
         .route(
             "/commands/disconnect_from_database",
             post(disconnect_from_database),
         )
+        .route(
+            "/commands/get_connection_health",
+            post(get_connection_health),
+        )
         .route("/commands/submit_query", post(submit_query))
+        .route("/commands/get_secret_backends", post(get_secret_backends))
+        .route("/commands/set_secret_backends", post(set_secret_backends))
+        .route(
+            "/commands/get_credential_backend_status",
+            post(get_credential_backend_status),
+        )
+        .route(
+            "/commands/unlock_credential_file",
+            post(unlock_credential_file),
+        )
+        .route(
+            "/commands/get_connection_resource_limits",
+            post(get_connection_resource_limits),
+        )
+        .route(
+            "/commands/set_connection_resource_limits",
+            post(set_connection_resource_limits),
+        )
+        .route(
+            "/commands/get_postgres_pool_size",
+            post(get_postgres_pool_size),
+        )
+        .route(
+            "/commands/set_postgres_pool_size",
+            post(set_postgres_pool_size),
+        )
+        .route("/commands/cancel_postgres", post(cancel_postgres))
+        .route("/commands/confirm_run", post(confirm_run))
+        .route("/commands/discard_run", post(discard_run))
+        .route("/commands/browse_table", post(browse_table))
+        .route(
+            "/commands/get_preflight_settings",
+            post(get_preflight_settings),
+        )
+        .route(
+            "/commands/set_preflight_settings",
+            post(set_preflight_settings),
+        )
+        .route(
+            "/commands/get_environment_policies",
+            post(get_environment_policies),
+        )
+        .route(
+            "/commands/set_environment_policies",
+            post(set_environment_policies),
+        )
+        .route(
+            "/commands/get_statement_savepoints",
+            post(get_statement_savepoints),
+        )
+        .route(
+            "/commands/set_statement_savepoints",
+            post(set_statement_savepoints),
+        )
+        .route(
+            "/commands/get_query_tag_settings",
+            post(get_query_tag_settings),
+        )
+        .route(
+            "/commands/set_query_tag_settings",
+            post(set_query_tag_settings),
+        )
+        .route(
+            "/commands/get_reconnect_settings",
+            post(get_reconnect_settings),
+        )
+        .route(
+            "/commands/set_reconnect_settings",
+            post(set_reconnect_settings),
+        )
+        .route("/commands/get_snapshot_mode", post(get_snapshot_mode))
+        .route("/commands/set_snapshot_mode", post(set_snapshot_mode))
+        .route("/commands/refresh_snapshot", post(refresh_snapshot))
+        .route(
+            "/commands/set_statement_tracing",
+            post(set_statement_tracing),
+        )
+        .route("/commands/get_statement_trace", post(get_statement_trace))
+        .route(
+            "/commands/clear_statement_trace",
+            post(clear_statement_trace),
+        )
         .route(
             "/commands/wait_until_renderable",
             post(wait_until_renderable),
         )
+        .route("/commands/get_statement_infos", post(get_statement_infos))
+        .route("/commands/cancel_all_queries", post(cancel_all_queries))
+        .route("/commands/begin_transaction", post(begin_transaction))
+        .route("/commands/commit_transaction", post(commit_transaction))
+        .route("/commands/rollback_transaction", post(rollback_transaction))
+        .route(
+            "/commands/get_transaction_state",
+            post(get_transaction_state),
+        )
         .route("/commands/fetch_page", post(fetch_page))
         .route("/commands/get_query_status", post(get_query_status))
         .route("/commands/get_page_count", post(get_page_count))
+        .route("/commands/request_more_rows", post(request_more_rows))
+        .route("/commands/get_timing_breakdown", post(get_timing_breakdown))
+        .route("/commands/get_row_detail", post(get_row_detail))
+        .route("/commands/get_column_stats", post(get_column_stats))
+        .route(
+            "/commands/get_table_column_stats",
+            post(get_table_column_stats),
+        )
+        .route("/commands/lsp_hover", post(lsp_hover))
+        .route("/commands/lsp_definition", post(lsp_definition))
+        .route("/commands/lsp_diagnostics", post(lsp_diagnostics))
+        .route("/commands/lsp_join_conditions", post(lsp_join_conditions))
         .route("/commands/is_query_read_only", post(is_query_read_only))
+        .route(
+            "/commands/detect_query_parameters",
+            post(detect_query_parameters),
+        )
+        .route("/commands/explain_query", post(explain_query))
+        .route("/commands/explain_sqlite_query", post(explain_sqlite_query))
         .route("/commands/get_database_schema", post(get_database_schema))
+        .route(
+            "/commands/get_relationship_graph",
+            post(get_relationship_graph),
+        )
+        .route(
+            "/commands/attach_sqlite_database",
+            post(attach_sqlite_database),
+        )
+        .route(
+            "/commands/detach_sqlite_database",
+            post(detach_sqlite_database),
+        )
+        .route("/commands/get_table_columns", post(get_table_columns))
+        .route(
+            "/commands/refresh_table_row_count",
+            post(refresh_table_row_count),
+        )
+        .route("/commands/get_table_ddl", post(get_table_ddl))
+        .route("/commands/set_active_schema", post(set_active_schema))
+        .route("/commands/suggest_error_fixes", post(suggest_error_fixes))
+        .route(
+            "/commands/get_error_suggestions_enabled",
+            post(get_error_suggestions_enabled),
+        )
+        .route(
+            "/commands/set_error_suggestions_enabled",
+            post(set_error_suggestions_enabled),
+        )
+        .route(
+            "/commands/get_postgres_replication_info",
+            post(get_postgres_replication_info),
+        )
+        .route("/commands/get_postgres_stats", post(get_postgres_stats))
+        .route("/commands/get_server_activity", post(get_server_activity))
+        .route("/commands/terminate_session", post(terminate_session))
+        .route("/commands/listen_postgres", post(listen_postgres))
+        .route("/commands/unlisten_postgres", post(unlisten_postgres))
+        .route("/commands/get_active_listens", post(get_active_listens))
+        .route(
+            "/commands/get_connection_messages",
+            post(get_connection_messages),
+        )
         .route(
             "/commands/save_query_to_history",
             post(save_query_to_history),
         )
         .route("/commands/export_page", post(export_page))
+        .route("/commands/import_csv", post(import_csv))
+        .route("/commands/copy_out_to_file", post(copy_out_to_file))
+        .route("/commands/copy_in_from_file", post(copy_in_from_file))
+        .route("/commands/export_to_xlsx", post(export_to_xlsx))
+        .route("/commands/export_query_results", post(export_query_results))
+        .route(
+            "/commands/export_query_results_json",
+            post(export_query_results_json),
+        )
+        .route("/commands/generate_inserts", post(generate_inserts))
+        .route("/commands/list_operations", post(list_operations))
+        .route("/commands/cancel_operation", post(cancel_operation))
+        .route(
+            "/commands/list_export_templates",
+            post(list_export_templates),
+        )
+        .route("/commands/save_export_template", post(save_export_template))
+        .route("/commands/group_query_results", post(group_query_results))
+        .route("/commands/sort_query_results", post(sort_query_results))
+        .route("/commands/sort_result", post(sort_result))
+        .route("/commands/filter_result", post(filter_result))
+        .route("/commands/clear_result_filter", post(clear_result_filter))
+        .route("/commands/pin_result", post(pin_result))
+        .route("/commands/unpin_result", post(unpin_result))
+        .route("/commands/join_results", post(join_results))
+        .route("/commands/diff_results", post(diff_results))
+        .route("/commands/open_script_file", post(open_script_file))
+        .route(
+            "/commands/get_recent_script_files",
+            post(get_recent_script_files),
+        )
+        .route(
+            "/commands/link_script_directory",
+            post(link_script_directory),
+        )
+        .route(
+            "/commands/unlink_script_directory",
+            post(unlink_script_directory),
+        )
+        .route(
+            "/commands/get_linked_script_directories",
+            post(get_linked_script_directories),
+        )
+        .route("/commands/read_linked_script", post(read_linked_script))
+        .route(
+            "/commands/get_external_editor_settings",
+            post(get_external_editor_settings),
+        )
+        .route(
+            "/commands/set_external_editor_settings",
+            post(set_external_editor_settings),
+        )
+        .route("/commands/edit_externally", post(edit_externally))
+        .route("/commands/sync_external_edit", post(sync_external_edit))
+        .route(
+            "/commands/resolve_external_edit",
+            post(resolve_external_edit),
+        )
+        .route("/commands/end_external_edit", post(end_external_edit))
         .route("/commands/save_script", post(save_script))
         .route("/commands/update_script", post(update_script))
+        .route("/commands/get_script_templates", post(get_script_templates))
+        .route("/commands/save_script_template", post(save_script_template))
+        .route("/commands/get_snippets", post(get_snippets))
+        .route("/commands/save_snippet", post(save_snippet))
+        .route("/commands/delete_snippet", post(delete_snippet))
+        .route("/commands/get_query_variables", post(get_query_variables))
+        .route("/commands/save_query_variable", post(save_query_variable))
+        .route(
+            "/commands/delete_query_variable",
+            post(delete_query_variable),
+        )
+        .route("/commands/preview_substitution", post(preview_substitution))
+        .route(
+            "/commands/get_scheduled_queries",
+            post(get_scheduled_queries),
+        )
+        .route("/commands/save_scheduled_query", post(save_scheduled_query))
+        .route(
+            "/commands/delete_scheduled_query",
+            post(delete_scheduled_query),
+        )
         .route("/commands/get_scripts", post(get_scripts))
+        .route("/commands/get_scripts_filtered", post(get_scripts_filtered))
+        .route(
+            "/commands/toggle_script_favorite",
+            post(toggle_script_favorite),
+        )
         .route("/commands/delete_script", post(delete_script))
         .route("/commands/get_query_history", post(get_query_history))
+        .route(
+            "/commands/get_query_history_entry",
+            post(get_query_history_entry),
+        )
+        .route(
+            "/commands/get_query_history_page",
+            post(get_query_history_page),
+        )
+        .route(
+            "/commands/get_query_history_count",
+            post(get_query_history_count),
+        )
+        .route("/commands/get_history_settings", post(get_history_settings))
+        .route("/commands/set_history_settings", post(set_history_settings))
+        .route(
+            "/commands/get_result_cache_settings",
+            post(get_result_cache_settings),
+        )
+        .route(
+            "/commands/set_result_cache_settings",
+            post(set_result_cache_settings),
+        )
+        .route("/commands/prune_query_history", post(prune_query_history))
         .route("/commands/format_sql", post(format_sql))
+        .route("/commands/sanitize_sql", post(sanitize_sql))
         .route("/commands/minimize_window", post(noop_command))
         .route("/commands/maximize_window", post(noop_command))
         .route("/commands/close_window", post(noop_command))
         .route("/commands/open_sqlite_db", post(open_sqlite_db))
         .route("/commands/save_sqlite_db", post(save_sqlite_db))
+        .route("/commands/save_xlsx_file", post(save_xlsx_file))
+        .route("/commands/save_delimited_file", post(save_delimited_file))
         .route("/commands/pick_ca_cert", post(pick_ca_cert))
+        .route(
+            "/commands/save_connections_file",
+            post(save_connections_file),
+        )
+        .route(
+            "/commands/open_connections_file",
+            post(open_connections_file),
+        )
+        .route("/commands/open_csv_file", post(open_csv_file))
+        .route(
+            "/commands/pick_script_directory",
+            post(pick_script_directory),
+        )
         .route("/commands/{command}", post(fallback_command))
         .route_layer(middleware::from_fn_with_state(
             state.clone(),
@@ -253,299 +676,2301 @@ async fn initialize_connections(State(state): State<WebState>) -> CommandResult<
     Ok(Json(()))
 }
 
-async fn get_connections(State(state): State<WebState>) -> CommandResult<Vec<ConnectionInfo>> {
+async fn initialize_workspace(State(state): State<WebState>) -> CommandResult<Vec<DraftBuffer>> {
     Ok(Json(
-        services::get_connections(state.app_state.as_ref()).await?,
+        services::initialize_workspace(state.app_state.as_ref()).await?,
     ))
 }
 
-async fn get_session_state(State(state): State<WebState>) -> CommandResult<Option<String>> {
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AutosaveBufferArgs {
+    buffer_id: String,
+    connection_id: Option<Uuid>,
+    content: String,
+    cursor: u32,
+}
+
+async fn autosave_buffer(
+    State(state): State<WebState>,
+    CommandJson(AutosaveBufferArgs {
+        buffer_id,
+        connection_id,
+        content,
+        cursor,
+    }): CommandJson<AutosaveBufferArgs>,
+) -> CommandResult<()> {
+    services::autosave_buffer(
+        &buffer_id,
+        connection_id,
+        &content,
+        cursor,
+        state.app_state.as_ref(),
+    )
+    .await?;
+    Ok(Json(()))
+}
+
+async fn get_draft_buffers(State(state): State<WebState>) -> CommandResult<Vec<DraftBuffer>> {
     Ok(Json(
-        services::get_session_state(state.app_state.as_ref()).await?,
+        services::get_draft_buffers(state.app_state.as_ref()).await?,
     ))
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct SaveSessionStateArgs {
-    session_data: String,
+struct BufferIdArgs {
+    buffer_id: String,
 }
 
-async fn save_session_state(
+async fn discard_draft_buffer(
     State(state): State<WebState>,
-    CommandJson(SaveSessionStateArgs { session_data }): CommandJson<SaveSessionStateArgs>,
+    CommandJson(BufferIdArgs { buffer_id }): CommandJson<BufferIdArgs>,
 ) -> CommandResult<()> {
-    services::save_session_state(&session_data, state.app_state.as_ref()).await?;
+    services::discard_draft_buffer(&buffer_id, state.app_state.as_ref()).await?;
     Ok(Json(()))
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct TestConnectionArgs {
-    config: ConnectionConfig,
+async fn get_connections(State(state): State<WebState>) -> CommandResult<Vec<ConnectionInfo>> {
+    Ok(Json(
+        services::get_connections(state.app_state.as_ref()).await?,
+    ))
 }
 
-async fn test_connection(
-    State(state): State<WebState>,
-    CommandJson(TestConnectionArgs { config }): CommandJson<TestConnectionArgs>,
-) -> CommandResult<bool> {
+async fn list_sessions(State(state): State<WebState>) -> CommandResult<Vec<SessionInfo>> {
     Ok(Json(
-        services::test_connection(config, &state.certificates).await?,
+        services::list_sessions(state.app_state.as_ref()).await?,
     ))
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct AddConnectionArgs {
-    name: String,
-    config: ConnectionConfig,
-    permissions: Permissions,
+struct SaveSessionArgs {
+    session_id: String,
+    version: u32,
+    data: String,
 }
 
-async fn add_connection(
+async fn save_session(
     State(state): State<WebState>,
-    CommandJson(AddConnectionArgs {
-        name,
-        config,
-        permissions,
-    }): CommandJson<AddConnectionArgs>,
-) -> CommandResult<ConnectionInfo> {
-    Ok(Json(
-        services::add_connection(name, config, permissions, state.app_state.as_ref()).await?,
-    ))
+    CommandJson(SaveSessionArgs {
+        session_id,
+        version,
+        data,
+    }): CommandJson<SaveSessionArgs>,
+) -> CommandResult<()> {
+    services::save_session(&session_id, version, &data, state.app_state.as_ref()).await?;
+    Ok(Json(()))
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct UpdateConnectionArgs {
-    conn_id: Uuid,
-    name: String,
-    config: ConnectionConfig,
-    permissions: Permissions,
+struct WindowSessionArgs {
+    session_id: String,
 }
 
-async fn update_connection(
+async fn get_session(
     State(state): State<WebState>,
-    CommandJson(UpdateConnectionArgs {
-        conn_id,
-        name,
-        config,
-        permissions,
-    }): CommandJson<UpdateConnectionArgs>,
-) -> CommandResult<ConnectionInfo> {
+    CommandJson(WindowSessionArgs { session_id }): CommandJson<WindowSessionArgs>,
+) -> CommandResult<Option<SessionState>> {
     Ok(Json(
-        services::update_connection(conn_id, name, config, permissions, state.app_state.as_ref())
-            .await?,
+        services::get_session(&session_id, state.app_state.as_ref()).await?,
     ))
 }
 
+async fn delete_session(
+    State(state): State<WebState>,
+    CommandJson(WindowSessionArgs { session_id }): CommandJson<WindowSessionArgs>,
+) -> CommandResult<()> {
+    services::delete_session(&session_id, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct ConnectionIdArgs {
+struct SaveTreeStateArgs {
     connection_id: Uuid,
+    state_json: String,
 }
 
-async fn remove_connection(
+async fn save_tree_state(
     State(state): State<WebState>,
-    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+    CommandJson(SaveTreeStateArgs {
+        connection_id,
+        state_json,
+    }): CommandJson<SaveTreeStateArgs>,
 ) -> CommandResult<()> {
-    services::remove_connection(connection_id, state.app_state.as_ref()).await?;
+    services::save_tree_state(connection_id, &state_json, state.app_state.as_ref()).await?;
     Ok(Json(()))
 }
 
-async fn connect_to_database(
+async fn get_tree_state(
     State(state): State<WebState>,
     CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
-) -> CommandResult<bool> {
+) -> CommandResult<Option<TreeState>> {
     Ok(Json(
-        services::connect_to_database(
-            connection_id,
-            state.app_state.as_ref(),
-            &state.connection_monitor,
-            &state.certificates,
-        )
-        .await?,
+        services::get_tree_state(connection_id, state.app_state.as_ref()).await?,
     ))
 }
 
-async fn disconnect_from_database(
+async fn get_semantic_search_settings(
     State(state): State<WebState>,
-    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<SemanticSearchSettings> {
+    Ok(Json(
+        services::get_semantic_search_settings(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetSemanticSearchSettingsArgs {
+    settings: SemanticSearchSettings,
+}
+
+async fn set_semantic_search_settings(
+    State(state): State<WebState>,
+    CommandJson(SetSemanticSearchSettingsArgs { settings }): CommandJson<
+        SetSemanticSearchSettingsArgs,
+    >,
 ) -> CommandResult<()> {
-    services::disconnect_from_database(connection_id, state.app_state.as_ref()).await?;
+    services::set_semantic_search_settings(settings, state.app_state.as_ref()).await?;
     Ok(Json(()))
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct SubmitQueryArgs {
+struct SemanticSearchSchemaArgs {
     connection_id: Uuid,
     query: String,
+    limit: usize,
 }
 
-async fn submit_query(
+async fn semantic_search_schema(
     State(state): State<WebState>,
-    CommandJson(SubmitQueryArgs {
+    CommandJson(SemanticSearchSchemaArgs {
         connection_id,
         query,
-    }): CommandJson<SubmitQueryArgs>,
-) -> CommandResult<Vec<usize>> {
+        limit,
+    }): CommandJson<SemanticSearchSchemaArgs>,
+) -> CommandResult<SchemaSearchResults> {
     Ok(Json(
-        services::submit_query(connection_id, &query, state.app_state.as_ref()).await?,
+        services::semantic_search_schema(connection_id, &query, limit, state.app_state.as_ref())
+            .await?,
     ))
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct QueryIdArgs {
-    query_id: usize,
+struct BufferExecutionStateArgs {
+    tab_id: String,
+    text: String,
 }
 
-async fn wait_until_renderable(
+async fn get_buffer_execution_state(
     State(state): State<WebState>,
-    CommandJson(QueryIdArgs { query_id }): CommandJson<QueryIdArgs>,
-) -> CommandResult<QuerySnapshot> {
+    CommandJson(BufferExecutionStateArgs { tab_id, text }): CommandJson<BufferExecutionStateArgs>,
+) -> CommandResult<Vec<StatementMarker>> {
     Ok(Json(
-        services::wait_until_renderable(query_id, state.app_state.as_ref()).await?,
+        services::get_buffer_execution_state(&tab_id, &text, state.app_state.as_ref()).await?,
     ))
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct FetchPageArgs {
-    query_id: usize,
-    page_index: usize,
+struct TestConnectionArgs {
+    config: ConnectionConfig,
 }
 
-async fn fetch_page(
+async fn test_connection(
     State(state): State<WebState>,
-    CommandJson(FetchPageArgs {
-        query_id,
-        page_index,
-    }): CommandJson<FetchPageArgs>,
-) -> CommandResult<Option<Box<RawValue>>> {
+    CommandJson(TestConnectionArgs { config }): CommandJson<TestConnectionArgs>,
+) -> CommandResult<bool> {
     Ok(Json(
-        services::fetch_page(query_id, page_index, state.app_state.as_ref()).await?,
+        services::test_connection(config, &state.certificates).await?,
     ))
 }
 
-async fn get_query_status(
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProbeConnectionsArgs {
+    connection_ids: Vec<Uuid>,
+    timeout_ms: u64,
+}
+
+async fn probe_connections(
+    State(state): State<WebState>,
+    CommandJson(ProbeConnectionsArgs {
+        connection_ids,
+        timeout_ms,
+    }): CommandJson<ProbeConnectionsArgs>,
+) -> CommandResult<Vec<ConnectionProbe>> {
+    Ok(Json(
+        services::probe_connections(connection_ids, timeout_ms, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AddConnectionArgs {
+    name: String,
+    folder: Option<String>,
+    environment: Option<Environment>,
+    color: Option<String>,
+    config: ConnectionConfig,
+    permissions: Permissions,
+}
+
+async fn add_connection(
+    State(state): State<WebState>,
+    CommandJson(AddConnectionArgs {
+        name,
+        folder,
+        environment,
+        color,
+        config,
+        permissions,
+    }): CommandJson<AddConnectionArgs>,
+) -> CommandResult<ConnectionInfo> {
+    Ok(Json(
+        services::add_connection(
+            name,
+            folder,
+            environment,
+            color,
+            config,
+            permissions,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateConnectionArgs {
+    conn_id: Uuid,
+    name: String,
+    folder: Option<String>,
+    environment: Option<Environment>,
+    color: Option<String>,
+    config: ConnectionConfig,
+    permissions: Permissions,
+}
+
+async fn update_connection(
+    State(state): State<WebState>,
+    CommandJson(UpdateConnectionArgs {
+        conn_id,
+        name,
+        folder,
+        environment,
+        color,
+        config,
+        permissions,
+    }): CommandJson<UpdateConnectionArgs>,
+) -> CommandResult<ConnectionInfo> {
+    Ok(Json(
+        services::update_connection(
+            conn_id,
+            name,
+            folder,
+            environment,
+            color,
+            config,
+            permissions,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RenameConnectionFolderArgs {
+    old: String,
+    new: String,
+}
+
+async fn rename_connection_folder(
+    State(state): State<WebState>,
+    CommandJson(RenameConnectionFolderArgs { old, new }): CommandJson<RenameConnectionFolderArgs>,
+) -> CommandResult<usize> {
+    Ok(Json(
+        services::rename_connection_folder(&old, &new, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportConnectionsArgs {
+    path: String,
+    connection_ids: Vec<Uuid>,
+    passphrase: Option<String>,
+}
+
+async fn export_connections(
+    State(state): State<WebState>,
+    CommandJson(ExportConnectionsArgs {
+        path,
+        connection_ids,
+        passphrase,
+    }): CommandJson<ExportConnectionsArgs>,
+) -> CommandResult<usize> {
+    Ok(Json(
+        services::export_connections(
+            &path,
+            &connection_ids,
+            passphrase.as_deref(),
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportConnectionsArgs {
+    path: String,
+    passphrase: Option<String>,
+}
+
+async fn import_connections(
+    State(state): State<WebState>,
+    CommandJson(ImportConnectionsArgs { path, passphrase }): CommandJson<ImportConnectionsArgs>,
+) -> CommandResult<Vec<ImportedConnection>> {
+    Ok(Json(
+        services::import_connections(&path, passphrase.as_deref(), state.app_state.as_ref())
+            .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConnectionIdArgs {
+    connection_id: Uuid,
+}
+
+async fn remove_connection(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<()> {
+    services::remove_connection(connection_id, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+/// Asks where the file of a SQLite connection is now. None if the dialog was dismissed.
+async fn relocate_database_file(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<Option<ConnectionInfo>> {
+    let Json(Some(db_path)) = open_sqlite_db().await? else {
+        return Ok(Json(None));
+    };
+    Ok(Json(Some(
+        services::relocate_database_file(connection_id, &db_path, state.app_state.as_ref()).await?,
+    )))
+}
+
+async fn connect_to_database(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<bool> {
+    Ok(Json(
+        services::connect_to_database(
+            connection_id,
+            state.app_state.as_ref(),
+            &state.connection_monitor,
+            &state.certificates,
+        )
+        .await?,
+    ))
+}
+
+async fn disconnect_from_database(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<()> {
+    services::disconnect_from_database(connection_id, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+/// There are no events to tell the UI when the status changes, so it's polled
+async fn get_connection_health(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<Option<ConnectionHealth>> {
+    Ok(Json(
+        services::get_connection_health(
+            connection_id,
+            state.app_state.as_ref(),
+            &state.connection_monitor,
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubmitQueryArgs {
+    connection_id: Uuid,
+    query: String,
+    #[serde(default)]
+    options: Option<SubmitOptions>,
+}
+
+async fn submit_query(
+    State(state): State<WebState>,
+    CommandJson(SubmitQueryArgs {
+        connection_id,
+        query,
+        options,
+    }): CommandJson<SubmitQueryArgs>,
+) -> CommandResult<SubmitOutcome> {
+    Ok(Json(
+        services::submit_query(
+            connection_id,
+            &query,
+            options.unwrap_or_default(),
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+async fn get_secret_backends(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<Vec<SecretBackend>> {
+    Ok(Json(
+        services::get_secret_backends(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetSecretBackendsArgs {
+    connection_id: Uuid,
+    backends: Vec<SecretBackend>,
+}
+
+async fn set_secret_backends(
+    State(state): State<WebState>,
+    CommandJson(SetSecretBackendsArgs {
+        connection_id,
+        backends,
+    }): CommandJson<SetSecretBackendsArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_secret_backends(connection_id, backends, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_credential_backend_status(
+    State(state): State<WebState>,
+) -> CommandResult<CredentialBackendStatus> {
+    Ok(Json(
+        services::get_credential_backend_status(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UnlockCredentialFileArgs {
+    passphrase: String,
+}
+
+async fn unlock_credential_file(
+    State(state): State<WebState>,
+    CommandJson(UnlockCredentialFileArgs { passphrase }): CommandJson<UnlockCredentialFileArgs>,
+) -> CommandResult<CredentialBackendStatus> {
+    Ok(Json(
+        services::unlock_credential_file(passphrase, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_connection_resource_limits(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<ResourceLimits> {
+    Ok(Json(
+        services::get_connection_resource_limits(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetConnectionResourceLimitsArgs {
+    connection_id: Uuid,
+    limits: ResourceLimits,
+}
+
+async fn set_connection_resource_limits(
+    State(state): State<WebState>,
+    CommandJson(SetConnectionResourceLimitsArgs {
+        connection_id,
+        limits,
+    }): CommandJson<SetConnectionResourceLimitsArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_connection_resource_limits(connection_id, limits, state.app_state.as_ref())
+            .await?,
+    ))
+}
+
+async fn get_postgres_pool_size(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<usize> {
+    Ok(Json(
+        services::get_postgres_pool_size(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetPostgresPoolSizeArgs {
+    connection_id: Uuid,
+    size: usize,
+}
+
+async fn set_postgres_pool_size(
+    State(state): State<WebState>,
+    CommandJson(SetPostgresPoolSizeArgs {
+        connection_id,
+        size,
+    }): CommandJson<SetPostgresPoolSizeArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_postgres_pool_size(connection_id, size, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn cancel_postgres(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::cancel_postgres(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfirmRunArgs {
+    run_id: Uuid,
+}
+
+async fn confirm_run(
+    State(state): State<WebState>,
+    CommandJson(ConfirmRunArgs { run_id }): CommandJson<ConfirmRunArgs>,
+) -> CommandResult<Vec<usize>> {
+    Ok(Json(
+        services::confirm_run(run_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn discard_run(
+    State(state): State<WebState>,
+    CommandJson(ConfirmRunArgs { run_id }): CommandJson<ConfirmRunArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::discard_run(run_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BrowseTableArgs {
+    connection_id: Uuid,
+    schema: String,
+    table: String,
+    page_index: usize,
+    page_size: usize,
+}
+
+async fn browse_table(
+    State(state): State<WebState>,
+    CommandJson(BrowseTableArgs {
+        connection_id,
+        schema,
+        table,
+        page_index,
+        page_size,
+    }): CommandJson<BrowseTableArgs>,
+) -> CommandResult<BrowsePage> {
+    Ok(Json(
+        services::browse_table(
+            connection_id,
+            &schema,
+            &table,
+            page_index,
+            page_size,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+async fn get_preflight_settings(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<PreflightSettings> {
+    Ok(Json(
+        services::get_preflight_settings(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetPreflightSettingsArgs {
+    connection_id: Uuid,
+    settings: PreflightSettings,
+}
+
+async fn set_preflight_settings(
+    State(state): State<WebState>,
+    CommandJson(SetPreflightSettingsArgs {
+        connection_id,
+        settings,
+    }): CommandJson<SetPreflightSettingsArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_preflight_settings(connection_id, settings, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_environment_policies(
+    State(state): State<WebState>,
+) -> CommandResult<EnvironmentPolicies> {
+    Ok(Json(
+        services::get_environment_policies(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetEnvironmentPoliciesArgs {
+    policies: EnvironmentPolicies,
+}
+
+async fn set_environment_policies(
+    State(state): State<WebState>,
+    CommandJson(SetEnvironmentPoliciesArgs { policies }): CommandJson<SetEnvironmentPoliciesArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_environment_policies(policies, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_statement_savepoints(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<bool> {
+    Ok(Json(
+        services::get_statement_savepoints(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetStatementSavepointsArgs {
+    connection_id: Uuid,
+    enabled: bool,
+}
+
+async fn set_statement_savepoints(
+    State(state): State<WebState>,
+    CommandJson(SetStatementSavepointsArgs {
+        connection_id,
+        enabled,
+    }): CommandJson<SetStatementSavepointsArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_statement_savepoints(connection_id, enabled, state.app_state.as_ref())
+            .await?,
+    ))
+}
+
+async fn get_query_tag_settings(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<QueryTagSettings> {
+    Ok(Json(
+        services::get_query_tag_settings(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetQueryTagSettingsArgs {
+    connection_id: Uuid,
+    settings: QueryTagSettings,
+}
+
+async fn set_query_tag_settings(
+    State(state): State<WebState>,
+    CommandJson(SetQueryTagSettingsArgs {
+        connection_id,
+        settings,
+    }): CommandJson<SetQueryTagSettingsArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_query_tag_settings(connection_id, settings, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_reconnect_settings(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<ReconnectSettings> {
+    Ok(Json(
+        services::get_reconnect_settings(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetReconnectSettingsArgs {
+    connection_id: Uuid,
+    settings: ReconnectSettings,
+}
+
+async fn set_reconnect_settings(
+    State(state): State<WebState>,
+    CommandJson(SetReconnectSettingsArgs {
+        connection_id,
+        settings,
+    }): CommandJson<SetReconnectSettingsArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_reconnect_settings(connection_id, settings, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_snapshot_mode(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<bool> {
+    Ok(Json(
+        services::get_snapshot_mode(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetSnapshotModeArgs {
+    connection_id: Uuid,
+    enabled: bool,
+}
+
+async fn set_snapshot_mode(
+    State(state): State<WebState>,
+    CommandJson(SetSnapshotModeArgs {
+        connection_id,
+        enabled,
+    }): CommandJson<SetSnapshotModeArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_snapshot_mode(connection_id, enabled, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn refresh_snapshot(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<SnapshotRefresh> {
+    Ok(Json(
+        services::refresh_snapshot(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetStatementTracingArgs {
+    connection_id: Uuid,
+    enabled: bool,
+}
+
+async fn set_statement_tracing(
+    State(state): State<WebState>,
+    CommandJson(SetStatementTracingArgs {
+        connection_id,
+        enabled,
+    }): CommandJson<SetStatementTracingArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_statement_tracing(connection_id, enabled, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetStatementTraceArgs {
+    connection_id: Uuid,
+    limit: usize,
+}
+
+async fn get_statement_trace(
+    State(state): State<WebState>,
+    CommandJson(GetStatementTraceArgs {
+        connection_id,
+        limit,
+    }): CommandJson<GetStatementTraceArgs>,
+) -> CommandResult<Vec<TracedStatement>> {
+    Ok(Json(
+        services::get_statement_trace(connection_id, limit, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn clear_statement_trace(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::clear_statement_trace(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryIdArgs {
+    query_id: usize,
+}
+
+async fn wait_until_renderable(
+    State(state): State<WebState>,
+    CommandJson(QueryIdArgs { query_id }): CommandJson<QueryIdArgs>,
+) -> CommandResult<QuerySnapshot> {
+    Ok(Json(
+        services::wait_until_renderable(query_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn cancel_all_queries(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<usize> {
+    Ok(Json(
+        services::cancel_all_queries(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransactionArgs {
+    connection_id: Uuid,
+    tab_id: String,
+}
+
+async fn begin_transaction(
+    State(state): State<WebState>,
+    CommandJson(TransactionArgs {
+        connection_id,
+        tab_id,
+    }): CommandJson<TransactionArgs>,
+) -> CommandResult<()> {
+    services::begin_transaction(connection_id, &tab_id, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+async fn commit_transaction(
+    State(state): State<WebState>,
+    CommandJson(TransactionArgs {
+        connection_id,
+        tab_id,
+    }): CommandJson<TransactionArgs>,
+) -> CommandResult<()> {
+    services::commit_transaction(connection_id, &tab_id, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+async fn rollback_transaction(
+    State(state): State<WebState>,
+    CommandJson(TransactionArgs {
+        connection_id,
+        tab_id,
+    }): CommandJson<TransactionArgs>,
+) -> CommandResult<()> {
+    services::rollback_transaction(connection_id, &tab_id, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+async fn get_transaction_state(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<Vec<TransactionInfo>> {
+    Ok(Json(
+        services::get_transaction_state(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryIdsArgs {
+    query_ids: Vec<usize>,
+}
+
+async fn get_statement_infos(
+    State(state): State<WebState>,
+    CommandJson(QueryIdsArgs { query_ids }): CommandJson<QueryIdsArgs>,
+) -> CommandResult<Vec<Option<StatementInfo>>> {
+    Ok(Json(
+        services::get_statement_infos(&query_ids, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FetchPageArgs {
+    query_id: usize,
+    page_index: usize,
+}
+
+async fn fetch_page(
+    State(state): State<WebState>,
+    CommandJson(FetchPageArgs {
+        query_id,
+        page_index,
+    }): CommandJson<FetchPageArgs>,
+) -> CommandResult<Option<Box<RawValue>>> {
+    Ok(Json(
+        services::fetch_page(query_id, page_index, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_query_status(
+    State(state): State<WebState>,
+    CommandJson(QueryIdArgs { query_id }): CommandJson<QueryIdArgs>,
+) -> CommandResult<QueryStatus> {
+    Ok(Json(
+        services::get_query_status(query_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_page_count(
+    State(state): State<WebState>,
+    CommandJson(QueryIdArgs { query_id }): CommandJson<QueryIdArgs>,
+) -> CommandResult<usize> {
+    Ok(Json(
+        services::get_page_count(query_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn request_more_rows(
+    State(state): State<WebState>,
+    CommandJson(QueryIdArgs { query_id }): CommandJson<QueryIdArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::request_more_rows(query_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_timing_breakdown(
+    State(state): State<WebState>,
+    CommandJson(QueryIdArgs { query_id }): CommandJson<QueryIdArgs>,
+) -> CommandResult<Option<TimingBreakdown>> {
+    Ok(Json(
+        services::get_timing_breakdown(query_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetRowDetailArgs {
+    query_id: usize,
+    row_index: usize,
+}
+
+async fn get_row_detail(
+    State(state): State<WebState>,
+    CommandJson(GetRowDetailArgs {
+        query_id,
+        row_index,
+    }): CommandJson<GetRowDetailArgs>,
+) -> CommandResult<Vec<RowDetailField>> {
+    Ok(Json(
+        services::get_row_detail(query_id, row_index, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetColumnStatsArgs {
+    query_id: usize,
+    column_index: usize,
+}
+
+async fn get_column_stats(
+    State(state): State<WebState>,
+    CommandJson(GetColumnStatsArgs {
+        query_id,
+        column_index,
+    }): CommandJson<GetColumnStatsArgs>,
+) -> CommandResult<ColumnStats> {
+    Ok(Json(
+        services::get_column_stats(query_id, column_index, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetTableColumnStatsArgs {
+    connection_id: Uuid,
+    schema: String,
+    table: String,
+    column: String,
+}
+
+async fn get_table_column_stats(
+    State(state): State<WebState>,
+    CommandJson(GetTableColumnStatsArgs {
+        connection_id,
+        schema,
+        table,
+        column,
+    }): CommandJson<GetTableColumnStatsArgs>,
+) -> CommandResult<ColumnStats> {
+    Ok(Json(
+        services::get_table_column_stats(
+            connection_id,
+            &schema,
+            &table,
+            &column,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConnectionQueryArgs {
+    connection_id: Uuid,
+    query: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LspPositionArgs {
+    connection_id: Uuid,
+    query: String,
+    position: usize,
+}
+
+async fn lsp_hover(
+    State(state): State<WebState>,
+    CommandJson(LspPositionArgs {
+        connection_id,
+        query,
+        position,
+    }): CommandJson<LspPositionArgs>,
+) -> CommandResult<Option<Hover>> {
+    Ok(Json(
+        services::lsp_hover(connection_id, &query, position, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn lsp_definition(
+    State(state): State<WebState>,
+    CommandJson(LspPositionArgs {
+        connection_id,
+        query,
+        position,
+    }): CommandJson<LspPositionArgs>,
+) -> CommandResult<Option<Definition>> {
+    Ok(Json(
+        services::lsp_definition(connection_id, &query, position, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn lsp_join_conditions(
+    State(state): State<WebState>,
+    CommandJson(LspPositionArgs {
+        connection_id,
+        query,
+        position,
+    }): CommandJson<LspPositionArgs>,
+) -> CommandResult<Vec<String>> {
+    Ok(Json(
+        services::lsp_join_conditions(connection_id, &query, position, state.app_state.as_ref())
+            .await?,
+    ))
+}
+
+async fn lsp_diagnostics(
+    State(state): State<WebState>,
+    CommandJson(ConnectionQueryArgs {
+        connection_id,
+        query,
+    }): CommandJson<ConnectionQueryArgs>,
+) -> CommandResult<Vec<Diagnostic>> {
+    Ok(Json(
+        services::lsp_diagnostics(connection_id, &query, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn is_query_read_only(
+    State(state): State<WebState>,
+    CommandJson(ConnectionQueryArgs {
+        connection_id,
+        query,
+    }): CommandJson<ConnectionQueryArgs>,
+) -> CommandResult<bool> {
+    Ok(Json(
+        services::is_query_read_only(connection_id, &query, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn detect_query_parameters(
+    State(state): State<WebState>,
+    CommandJson(ConnectionQueryArgs {
+        connection_id,
+        query,
+    }): CommandJson<ConnectionQueryArgs>,
+) -> CommandResult<Vec<String>> {
+    Ok(Json(
+        services::detect_query_parameters(connection_id, &query, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExplainQueryArgs {
+    connection_id: Uuid,
+    query: String,
+    cursor: Option<usize>,
+    options: ExplainOptions,
+}
+
+async fn explain_query(
+    State(state): State<WebState>,
+    CommandJson(ExplainQueryArgs {
+        connection_id,
+        query,
+        cursor,
+        options,
+    }): CommandJson<ExplainQueryArgs>,
+) -> CommandResult<ExplainedPlan> {
+    Ok(Json(
+        services::explain_query(
+            connection_id,
+            &query,
+            cursor,
+            options,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExplainSqliteQueryArgs {
+    connection_id: Uuid,
+    query: String,
+    cursor: Option<usize>,
+}
+
+async fn explain_sqlite_query(
+    State(state): State<WebState>,
+    CommandJson(ExplainSqliteQueryArgs {
+        connection_id,
+        query,
+        cursor,
+    }): CommandJson<ExplainSqliteQueryArgs>,
+) -> CommandResult<QueryPlan> {
+    Ok(Json(
+        services::explain_sqlite_query(connection_id, &query, cursor, state.app_state.as_ref())
+            .await?,
+    ))
+}
+
+async fn get_database_schema(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<DatabaseSchema> {
+    let schema = services::get_database_schema(connection_id, state.app_state.as_ref()).await?;
+    Ok(Json((*schema).clone()))
+}
+
+async fn get_relationship_graph(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<RelationshipGraph> {
+    let graph = services::get_relationship_graph(connection_id, state.app_state.as_ref()).await?;
+    Ok(Json((*graph).clone()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AttachSqliteDatabaseArgs {
+    connection_id: Uuid,
+    path: String,
+    alias: String,
+}
+
+async fn attach_sqlite_database(
+    State(state): State<WebState>,
+    CommandJson(AttachSqliteDatabaseArgs {
+        connection_id,
+        path,
+        alias,
+    }): CommandJson<AttachSqliteDatabaseArgs>,
+) -> CommandResult<DatabaseSchema> {
+    let schema =
+        services::attach_sqlite_database(connection_id, &path, &alias, state.app_state.as_ref())
+            .await?;
+    Ok(Json((*schema).clone()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DetachSqliteDatabaseArgs {
+    connection_id: Uuid,
+    alias: String,
+}
+
+async fn detach_sqlite_database(
+    State(state): State<WebState>,
+    CommandJson(DetachSqliteDatabaseArgs {
+        connection_id,
+        alias,
+    }): CommandJson<DetachSqliteDatabaseArgs>,
+) -> CommandResult<DatabaseSchema> {
+    let schema =
+        services::detach_sqlite_database(connection_id, &alias, state.app_state.as_ref()).await?;
+    Ok(Json((*schema).clone()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetTableColumnsArgs {
+    connection_id: Uuid,
+    schema: String,
+    table: String,
+}
+
+async fn get_table_columns(
+    State(state): State<WebState>,
+    CommandJson(GetTableColumnsArgs {
+        connection_id,
+        schema,
+        table,
+    }): CommandJson<GetTableColumnsArgs>,
+) -> CommandResult<Vec<ColumnInfo>> {
+    Ok(Json(
+        services::get_table_columns(connection_id, &schema, &table, state.app_state.as_ref())
+            .await?,
+    ))
+}
+
+async fn refresh_table_row_count(
+    State(state): State<WebState>,
+    CommandJson(GetTableColumnsArgs {
+        connection_id,
+        schema,
+        table,
+    }): CommandJson<GetTableColumnsArgs>,
+) -> CommandResult<i64> {
+    Ok(Json(
+        services::refresh_table_row_count(connection_id, &schema, &table, state.app_state.as_ref())
+            .await?,
+    ))
+}
+
+async fn get_table_ddl(
+    State(state): State<WebState>,
+    CommandJson(GetTableColumnsArgs {
+        connection_id,
+        schema,
+        table,
+    }): CommandJson<GetTableColumnsArgs>,
+) -> CommandResult<TableDdl> {
+    Ok(Json(
+        services::get_table_ddl(connection_id, &schema, &table, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetActiveSchemaArgs {
+    connection_id: Uuid,
+    schema: Option<String>,
+}
+
+async fn set_active_schema(
+    State(state): State<WebState>,
+    CommandJson(SetActiveSchemaArgs {
+        connection_id,
+        schema,
+    }): CommandJson<SetActiveSchemaArgs>,
+) -> CommandResult<()> {
+    services::set_active_schema(connection_id, schema, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SuggestErrorFixesArgs {
+    connection_id: Uuid,
+    query: String,
+    error: String,
+}
+
+async fn suggest_error_fixes(
+    State(state): State<WebState>,
+    CommandJson(SuggestErrorFixesArgs {
+        connection_id,
+        query,
+        error,
+    }): CommandJson<SuggestErrorFixesArgs>,
+) -> CommandResult<Vec<ErrorSuggestion>> {
+    Ok(Json(
+        services::suggest_error_fixes(connection_id, &query, &error, state.app_state.as_ref())
+            .await?,
+    ))
+}
+
+async fn get_error_suggestions_enabled(State(state): State<WebState>) -> CommandResult<bool> {
+    Ok(Json(
+        services::get_error_suggestions_enabled(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetErrorSuggestionsEnabledArgs {
+    enabled: bool,
+}
+
+async fn set_error_suggestions_enabled(
+    State(state): State<WebState>,
+    CommandJson(SetErrorSuggestionsEnabledArgs { enabled }): CommandJson<
+        SetErrorSuggestionsEnabledArgs,
+    >,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_error_suggestions_enabled(enabled, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_postgres_replication_info(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<ReplicationInfo> {
+    Ok(Json(
+        services::get_postgres_replication_info(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_postgres_stats(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<PostgresStats> {
+    Ok(Json(
+        services::get_postgres_stats(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetServerActivityArgs {
+    connection_id: Uuid,
+    filters: Option<ActivityFilters>,
+}
+
+async fn get_server_activity(
+    State(state): State<WebState>,
+    CommandJson(GetServerActivityArgs {
+        connection_id,
+        filters,
+    }): CommandJson<GetServerActivityArgs>,
+) -> CommandResult<ServerActivity> {
+    Ok(Json(
+        services::get_server_activity(
+            connection_id,
+            filters.unwrap_or_default(),
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminateSessionArgs {
+    connection_id: Uuid,
+    pid: i32,
+    force: bool,
+}
+
+async fn terminate_session(
+    State(state): State<WebState>,
+    CommandJson(TerminateSessionArgs {
+        connection_id,
+        pid,
+        force,
+    }): CommandJson<TerminateSessionArgs>,
+) -> CommandResult<bool> {
+    Ok(Json(
+        services::terminate_session(connection_id, pid, force, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChannelArgs {
+    connection_id: Uuid,
+    channel: String,
+}
+
+async fn listen_postgres(
+    State(state): State<WebState>,
+    CommandJson(ChannelArgs {
+        connection_id,
+        channel,
+    }): CommandJson<ChannelArgs>,
+) -> CommandResult<()> {
+    services::listen_postgres(connection_id, &channel, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+async fn unlisten_postgres(
+    State(state): State<WebState>,
+    CommandJson(ChannelArgs {
+        connection_id,
+        channel,
+    }): CommandJson<ChannelArgs>,
+) -> CommandResult<()> {
+    services::unlisten_postgres(connection_id, &channel, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+async fn get_active_listens(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<Vec<String>> {
+    Ok(Json(
+        services::get_active_listens(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_connection_messages(
+    State(state): State<WebState>,
+    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
+) -> CommandResult<Vec<ServerMessage>> {
+    Ok(Json(
+        services::get_connection_messages(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SaveQueryToHistoryArgs {
+    connection_id: String,
+    query: String,
+    duration_ms: Option<u64>,
+    status: String,
+    row_count: u64,
+    error_message: Option<String>,
+    timings: Option<Vec<TimingBreakdown>>,
+    plan_json: Option<String>,
+}
+
+async fn save_query_to_history(
+    State(state): State<WebState>,
+    CommandJson(SaveQueryToHistoryArgs {
+        connection_id,
+        query,
+        duration_ms,
+        status,
+        row_count,
+        error_message,
+        timings,
+        plan_json,
+    }): CommandJson<SaveQueryToHistoryArgs>,
+) -> CommandResult<()> {
+    services::save_query_to_history(
+        connection_id,
+        query,
+        duration_ms,
+        status,
+        row_count,
+        error_message,
+        timings,
+        plan_json,
+        state.app_state.as_ref(),
+    )
+    .await?;
+
+    Ok(Json(()))
+}
+
+async fn export_page(
+    State(state): State<WebState>,
+    CommandJson(FetchPageArgs {
+        query_id,
+        page_index,
+    }): CommandJson<FetchPageArgs>,
+) -> CommandResult<String> {
+    Ok(Json(
+        services::export_page(query_id, page_index, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportCsvArgs {
+    connection_id: Uuid,
+    file_path: String,
+    options: CsvImportOptions,
+}
+
+async fn import_csv(
+    State(state): State<WebState>,
+    CommandJson(ImportCsvArgs {
+        connection_id,
+        file_path,
+        options,
+    }): CommandJson<ImportCsvArgs>,
+) -> CommandResult<CsvImportReport> {
+    Ok(Json(
+        services::import_csv(connection_id, &file_path, options, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CopyFileArgs {
+    connection_id: Uuid,
+    statement: String,
+    path: String,
+}
+
+async fn copy_out_to_file(
+    State(state): State<WebState>,
+    CommandJson(CopyFileArgs {
+        connection_id,
+        statement,
+        path,
+    }): CommandJson<CopyFileArgs>,
+) -> CommandResult<CopyReport> {
+    Ok(Json(
+        services::copy_out_to_file(connection_id, &statement, &path, state.app_state.as_ref())
+            .await?,
+    ))
+}
+
+async fn copy_in_from_file(
+    State(state): State<WebState>,
+    CommandJson(CopyFileArgs {
+        connection_id,
+        statement,
+        path,
+    }): CommandJson<CopyFileArgs>,
+) -> CommandResult<CopyReport> {
+    Ok(Json(
+        services::copy_in_from_file(connection_id, &statement, &path, state.app_state.as_ref())
+            .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportToXlsxArgs {
+    query_id: usize,
+    path: String,
+}
+
+async fn export_to_xlsx(
+    State(state): State<WebState>,
+    CommandJson(ExportToXlsxArgs { query_id, path }): CommandJson<ExportToXlsxArgs>,
+) -> CommandResult<()> {
+    services::export_to_xlsx(query_id, &path, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportQueryResultsArgs {
+    query_id: usize,
+    path: String,
+    template_id: Option<i64>,
+    options: Option<ExportOptions>,
+}
+
+async fn export_query_results(
+    State(state): State<WebState>,
+    CommandJson(ExportQueryResultsArgs {
+        query_id,
+        path,
+        template_id,
+        options,
+    }): CommandJson<ExportQueryResultsArgs>,
+) -> CommandResult<()> {
+    services::export_query_results(
+        query_id,
+        &path,
+        template_id,
+        options,
+        state.app_state.as_ref(),
+    )
+    .await?;
+    Ok(Json(()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportQueryResultsJsonArgs {
+    query_id: usize,
+    path: String,
+    format: JsonFormat,
+}
+
+async fn export_query_results_json(
+    State(state): State<WebState>,
+    CommandJson(ExportQueryResultsJsonArgs {
+        query_id,
+        path,
+        format,
+    }): CommandJson<ExportQueryResultsJsonArgs>,
+) -> CommandResult<()> {
+    services::export_query_results_json(query_id, &path, format, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateInsertsArgs {
+    query_id: usize,
+    table_name: String,
+    options: InsertOptions,
+    path: Option<String>,
+}
+
+async fn generate_inserts(
+    State(state): State<WebState>,
+    CommandJson(GenerateInsertsArgs {
+        query_id,
+        table_name,
+        options,
+        path,
+    }): CommandJson<GenerateInsertsArgs>,
+) -> CommandResult<Option<String>> {
+    Ok(Json(
+        services::generate_inserts(
+            query_id,
+            &table_name,
+            options,
+            path.as_deref(),
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+async fn list_operations(State(state): State<WebState>) -> CommandResult<Vec<OperationInfo>> {
+    Ok(Json(
+        services::list_operations(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct OperationIdArgs {
+    id: Uuid,
+}
+
+async fn cancel_operation(
+    State(state): State<WebState>,
+    CommandJson(OperationIdArgs { id }): CommandJson<OperationIdArgs>,
+) -> CommandResult<()> {
+    services::cancel_operation(id, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+async fn list_export_templates(
+    State(state): State<WebState>,
+) -> CommandResult<Vec<ExportTemplate>> {
+    Ok(Json(
+        services::list_export_templates(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SaveExportTemplateArgs {
+    id: Option<i64>,
+    name: String,
+    options: ExportOptions,
+}
+
+async fn save_export_template(
+    State(state): State<WebState>,
+    CommandJson(SaveExportTemplateArgs { id, name, options }): CommandJson<SaveExportTemplateArgs>,
+) -> CommandResult<ExportTemplate> {
+    Ok(Json(
+        services::save_export_template(id, &name, options, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GroupQueryResultsArgs {
+    query_id: usize,
+    group_columns: Vec<String>,
+    aggregates: Vec<Aggregate>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JoinResultsArgs {
+    query_id_a: usize,
+    query_id_b: usize,
+    sql: String,
+    #[serde(default)]
+    acknowledge_partial: bool,
+}
+
+async fn join_results(
+    State(state): State<WebState>,
+    CommandJson(JoinResultsArgs {
+        query_id_a,
+        query_id_b,
+        sql,
+        acknowledge_partial,
+    }): CommandJson<JoinResultsArgs>,
+) -> CommandResult<JoinedQuery> {
+    Ok(Json(
+        services::join_results(
+            query_id_a,
+            query_id_b,
+            sql,
+            acknowledge_partial,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DiffResultsArgs {
+    query_id_a: usize,
+    query_id_b: usize,
+    key_columns: Vec<String>,
+}
+
+async fn diff_results(
+    State(state): State<WebState>,
+    CommandJson(DiffResultsArgs {
+        query_id_a,
+        query_id_b,
+        key_columns,
+    }): CommandJson<DiffResultsArgs>,
+) -> CommandResult<ResultDiff> {
+    Ok(Json(
+        services::diff_results(
+            query_id_a,
+            query_id_b,
+            key_columns,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+async fn group_query_results(
+    State(state): State<WebState>,
+    CommandJson(GroupQueryResultsArgs {
+        query_id,
+        group_columns,
+        aggregates,
+    }): CommandJson<GroupQueryResultsArgs>,
+) -> CommandResult<GroupedQuery> {
+    Ok(Json(
+        services::group_query_results(
+            query_id,
+            group_columns,
+            aggregates,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SortQueryResultsArgs {
+    query_id: usize,
+    keys: Vec<SortKey>,
+    #[serde(default)]
+    collation: Collation,
+}
+
+async fn sort_query_results(
+    State(state): State<WebState>,
+    CommandJson(SortQueryResultsArgs {
+        query_id,
+        keys,
+        collation,
+    }): CommandJson<SortQueryResultsArgs>,
+) -> CommandResult<SortedQuery> {
+    Ok(Json(
+        services::sort_query_results(query_id, keys, collation, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SortResultArgs {
+    query_id: usize,
+    column_index: usize,
+    descending: bool,
+    #[serde(default)]
+    nulls: NullsOrder,
+}
+
+async fn sort_result(
+    State(state): State<WebState>,
+    CommandJson(SortResultArgs {
+        query_id,
+        column_index,
+        descending,
+        nulls,
+    }): CommandJson<SortResultArgs>,
+) -> CommandResult<SortedQuery> {
+    Ok(Json(
+        services::sort_result(
+            query_id,
+            column_index,
+            descending,
+            nulls,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FilterResultArgs {
+    query_id: usize,
+    filter: RowFilter,
+}
+
+async fn filter_result(
+    State(state): State<WebState>,
+    CommandJson(FilterResultArgs { query_id, filter }): CommandJson<FilterResultArgs>,
+) -> CommandResult<FilteredQuery> {
+    Ok(Json(
+        services::filter_result(query_id, filter, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ClearResultFilterArgs {
+    query_id: usize,
+}
+
+async fn clear_result_filter(
+    State(state): State<WebState>,
+    CommandJson(ClearResultFilterArgs { query_id }): CommandJson<ClearResultFilterArgs>,
+) -> CommandResult<()> {
+    services::clear_result_filter(query_id, state.app_state.as_ref())?;
+    Ok(Json(()))
+}
+
+async fn pin_result(
     State(state): State<WebState>,
     CommandJson(QueryIdArgs { query_id }): CommandJson<QueryIdArgs>,
-) -> CommandResult<QueryStatus> {
+) -> CommandResult<()> {
+    services::pin_result(query_id, state.app_state.as_ref())?;
+    Ok(Json(()))
+}
+
+async fn unpin_result(
+    State(state): State<WebState>,
+    CommandJson(QueryIdArgs { query_id }): CommandJson<QueryIdArgs>,
+) -> CommandResult<()> {
+    services::unpin_result(query_id, state.app_state.as_ref())?;
+    Ok(Json(()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenScriptFileArgs {
+    path: String,
+}
+
+async fn open_script_file(
+    State(state): State<WebState>,
+    CommandJson(OpenScriptFileArgs { path }): CommandJson<OpenScriptFileArgs>,
+) -> CommandResult<ScriptFile> {
+    Ok(Json(
+        services::open_script_file(&path, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_recent_script_files(State(state): State<WebState>) -> CommandResult<Vec<String>> {
+    Ok(Json(
+        services::get_recent_script_files(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LinkScriptDirectoryArgs {
+    path: String,
+    connection_id: Option<Uuid>,
+}
+
+async fn link_script_directory(
+    State(state): State<WebState>,
+    CommandJson(LinkScriptDirectoryArgs {
+        path,
+        connection_id,
+    }): CommandJson<LinkScriptDirectoryArgs>,
+) -> CommandResult<IndexedScriptDirectory> {
+    Ok(Json(
+        services::link_script_directory(&path, connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct UnlinkScriptDirectoryArgs {
+    id: i64,
+}
+
+async fn unlink_script_directory(
+    State(state): State<WebState>,
+    CommandJson(UnlinkScriptDirectoryArgs { id }): CommandJson<UnlinkScriptDirectoryArgs>,
+) -> CommandResult<()> {
+    services::unlink_script_directory(id, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+async fn get_linked_script_directories(
+    State(state): State<WebState>,
+) -> CommandResult<Vec<IndexedScriptDirectory>> {
+    Ok(Json(
+        services::get_linked_script_directories(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadLinkedScriptArgs {
+    path: String,
+}
+
+async fn read_linked_script(
+    State(state): State<WebState>,
+    CommandJson(ReadLinkedScriptArgs { path }): CommandJson<ReadLinkedScriptArgs>,
+) -> CommandResult<ScriptFile> {
+    Ok(Json(
+        services::read_linked_script(&path, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_external_editor_settings(
+    State(state): State<WebState>,
+) -> CommandResult<ExternalEditorSettings> {
+    Ok(Json(
+        services::get_external_editor_settings(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetExternalEditorSettingsArgs {
+    settings: ExternalEditorSettings,
+}
+
+async fn set_external_editor_settings(
+    State(state): State<WebState>,
+    CommandJson(SetExternalEditorSettingsArgs { settings }): CommandJson<
+        SetExternalEditorSettingsArgs,
+    >,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::set_external_editor_settings(settings, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EditExternallyArgs {
+    tab_id: String,
+    title: String,
+    contents: String,
+}
+
+async fn edit_externally(
+    State(state): State<WebState>,
+    CommandJson(EditExternallyArgs {
+        tab_id,
+        title,
+        contents,
+    }): CommandJson<EditExternallyArgs>,
+) -> CommandResult<ExternalEditSession> {
+    Ok(Json(
+        services::edit_externally(tab_id, &title, &contents, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncExternalEditArgs {
+    session_id: Uuid,
+    contents: String,
+}
+
+async fn sync_external_edit(
+    State(state): State<WebState>,
+    CommandJson(SyncExternalEditArgs {
+        session_id,
+        contents,
+    }): CommandJson<SyncExternalEditArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::sync_external_edit(session_id, contents, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResolveExternalEditArgs {
+    session_id: Uuid,
+    resolution: ConflictResolution,
+}
+
+async fn resolve_external_edit(
+    State(state): State<WebState>,
+    CommandJson(ResolveExternalEditArgs {
+        session_id,
+        resolution,
+    }): CommandJson<ResolveExternalEditArgs>,
+) -> CommandResult<String> {
+    Ok(Json(
+        services::resolve_external_edit(session_id, resolution, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionIdArgs {
+    session_id: Uuid,
+}
+
+async fn end_external_edit(
+    State(state): State<WebState>,
+    CommandJson(SessionIdArgs { session_id }): CommandJson<SessionIdArgs>,
+) -> CommandResult<()> {
+    Ok(Json(
+        services::end_external_edit(session_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SaveScriptArgs {
+    name: String,
+    content: String,
+    connection_id: Option<Uuid>,
+    description: Option<String>,
+    template_id: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    favorite: bool,
+}
+
+async fn save_script(
+    State(state): State<WebState>,
+    CommandJson(SaveScriptArgs {
+        name,
+        content,
+        connection_id,
+        description,
+        template_id,
+        tags,
+        favorite,
+    }): CommandJson<SaveScriptArgs>,
+) -> CommandResult<i64> {
+    Ok(Json(
+        services::save_script(
+            name,
+            content,
+            connection_id,
+            description,
+            template_id,
+            tags,
+            favorite,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetSnippetsArgs {
+    connection_id: Option<Uuid>,
+}
+
+async fn get_snippets(
+    State(state): State<WebState>,
+    CommandJson(GetSnippetsArgs { connection_id }): CommandJson<GetSnippetsArgs>,
+) -> CommandResult<Vec<Snippet>> {
+    Ok(Json(
+        services::get_snippets(connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SaveSnippetArgs {
+    snippet: Snippet,
+}
+
+async fn save_snippet(
+    State(state): State<WebState>,
+    CommandJson(SaveSnippetArgs { snippet }): CommandJson<SaveSnippetArgs>,
+) -> CommandResult<Snippet> {
     Ok(Json(
-        services::get_query_status(query_id, state.app_state.as_ref()).await?,
+        services::save_snippet(snippet, state.app_state.as_ref()).await?,
     ))
 }
 
-async fn get_page_count(
+#[derive(Debug, Deserialize)]
+struct DeleteSnippetArgs {
+    id: i64,
+}
+
+async fn delete_snippet(
     State(state): State<WebState>,
-    CommandJson(QueryIdArgs { query_id }): CommandJson<QueryIdArgs>,
-) -> CommandResult<usize> {
+    CommandJson(DeleteSnippetArgs { id }): CommandJson<DeleteSnippetArgs>,
+) -> CommandResult<()> {
+    services::delete_snippet(id, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+async fn get_query_variables(
+    State(state): State<WebState>,
+    CommandJson(GetSnippetsArgs { connection_id }): CommandJson<GetSnippetsArgs>,
+) -> CommandResult<Vec<QueryVariable>> {
     Ok(Json(
-        services::get_page_count(query_id, state.app_state.as_ref()).await?,
+        services::get_query_variables(connection_id, state.app_state.as_ref()).await?,
     ))
 }
 
 #[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct IsQueryReadOnlyArgs {
-    connection_id: Uuid,
-    query: String,
+struct SaveQueryVariableArgs {
+    variable: QueryVariable,
 }
 
-async fn is_query_read_only(
+async fn save_query_variable(
     State(state): State<WebState>,
-    CommandJson(IsQueryReadOnlyArgs {
-        connection_id,
-        query,
-    }): CommandJson<IsQueryReadOnlyArgs>,
-) -> CommandResult<bool> {
+    CommandJson(SaveQueryVariableArgs { variable }): CommandJson<SaveQueryVariableArgs>,
+) -> CommandResult<QueryVariable> {
     Ok(Json(
-        services::is_query_read_only(connection_id, &query, state.app_state.as_ref()).await?,
+        services::save_query_variable(variable, state.app_state.as_ref()).await?,
     ))
 }
 
-async fn get_database_schema(
+async fn delete_query_variable(
     State(state): State<WebState>,
-    CommandJson(ConnectionIdArgs { connection_id }): CommandJson<ConnectionIdArgs>,
-) -> CommandResult<DatabaseSchema> {
-    let schema = services::get_database_schema(connection_id, state.app_state.as_ref()).await?;
-    Ok(Json((*schema).clone()))
+    CommandJson(DeleteSnippetArgs { id }): CommandJson<DeleteSnippetArgs>,
+) -> CommandResult<()> {
+    services::delete_query_variable(id, state.app_state.as_ref()).await?;
+    Ok(Json(()))
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct SaveQueryToHistoryArgs {
-    connection_id: String,
+struct PreviewSubstitutionArgs {
     query: String,
-    duration_ms: Option<u64>,
-    status: String,
-    row_count: u64,
-    error_message: Option<String>,
+    connection_id: Option<Uuid>,
 }
 
-async fn save_query_to_history(
+async fn preview_substitution(
     State(state): State<WebState>,
-    CommandJson(SaveQueryToHistoryArgs {
-        connection_id,
+    CommandJson(PreviewSubstitutionArgs {
         query,
-        duration_ms,
-        status,
-        row_count,
-        error_message,
-    }): CommandJson<SaveQueryToHistoryArgs>,
-) -> CommandResult<()> {
-    services::save_query_to_history(
         connection_id,
-        query,
-        duration_ms,
-        status,
-        row_count,
-        error_message,
-        state.app_state.as_ref(),
-    )
-    .await?;
+    }): CommandJson<PreviewSubstitutionArgs>,
+) -> CommandResult<String> {
+    Ok(Json(
+        services::preview_substitution(&query, connection_id, state.app_state.as_ref()).await?,
+    ))
+}
+
+async fn get_scheduled_queries(
+    State(state): State<WebState>,
+) -> CommandResult<Vec<ScheduledQuery>> {
+    Ok(Json(
+        services::get_scheduled_queries(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SaveScheduledQueryArgs {
+    scheduled: ScheduledQuery,
+}
+
+async fn save_scheduled_query(
+    State(state): State<WebState>,
+    CommandJson(SaveScheduledQueryArgs { scheduled }): CommandJson<SaveScheduledQueryArgs>,
+) -> CommandResult<ScheduledQuery> {
+    Ok(Json(
+        services::save_scheduled_query(scheduled, state.app_state.as_ref()).await?,
+    ))
+}
 
+async fn delete_scheduled_query(
+    State(state): State<WebState>,
+    CommandJson(DeleteSnippetArgs { id }): CommandJson<DeleteSnippetArgs>,
+) -> CommandResult<()> {
+    services::delete_scheduled_query(id, state.app_state.as_ref()).await?;
     Ok(Json(()))
 }
 
-async fn export_page(
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetScriptTemplatesArgs {
+    db_type: Database,
+}
+
+async fn get_script_templates(
     State(state): State<WebState>,
-    CommandJson(FetchPageArgs {
-        query_id,
-        page_index,
-    }): CommandJson<FetchPageArgs>,
-) -> CommandResult<String> {
+    CommandJson(GetScriptTemplatesArgs { db_type }): CommandJson<GetScriptTemplatesArgs>,
+) -> CommandResult<Vec<ScriptTemplate>> {
     Ok(Json(
-        services::export_page(query_id, page_index, state.app_state.as_ref()).await?,
+        services::get_script_templates(db_type, state.app_state.as_ref()).await?,
     ))
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct SaveScriptArgs {
+struct SaveScriptTemplateArgs {
+    db_type: Database,
     name: String,
-    content: String,
-    connection_id: Option<Uuid>,
     description: Option<String>,
+    body: String,
 }
 
-async fn save_script(
+async fn save_script_template(
     State(state): State<WebState>,
-    CommandJson(SaveScriptArgs {
+    CommandJson(SaveScriptTemplateArgs {
+        db_type,
         name,
-        content,
-        connection_id,
         description,
-    }): CommandJson<SaveScriptArgs>,
-) -> CommandResult<i64> {
+        body,
+    }): CommandJson<SaveScriptTemplateArgs>,
+) -> CommandResult<ScriptTemplate> {
     Ok(Json(
-        services::save_script(
-            name,
-            content,
-            connection_id,
+        services::save_script_template(
+            db_type,
+            &name,
             description,
+            &body,
             state.app_state.as_ref(),
         )
         .await?,
@@ -560,6 +2985,10 @@ struct UpdateScriptArgs {
     content: String,
     connection_id: Option<Uuid>,
     description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    favorite: bool,
 }
 
 async fn update_script(
@@ -570,6 +2999,8 @@ async fn update_script(
         content,
         connection_id,
         description,
+        tags,
+        favorite,
     }): CommandJson<UpdateScriptArgs>,
 ) -> CommandResult<()> {
     services::update_script(
@@ -578,6 +3009,8 @@ async fn update_script(
         content,
         connection_id,
         description,
+        tags,
+        favorite,
         state.app_state.as_ref(),
     )
     .await?;
@@ -599,6 +3032,51 @@ async fn get_scripts(
     ))
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetScriptsFilteredArgs {
+    connection_id: Option<Uuid>,
+    tag: Option<String>,
+    #[serde(default)]
+    favorites_only: bool,
+    search_text: Option<String>,
+}
+
+async fn get_scripts_filtered(
+    State(state): State<WebState>,
+    CommandJson(GetScriptsFilteredArgs {
+        connection_id,
+        tag,
+        favorites_only,
+        search_text,
+    }): CommandJson<GetScriptsFilteredArgs>,
+) -> CommandResult<Vec<pgpad_core::SavedQuery>> {
+    Ok(Json(
+        services::get_scripts_filtered(
+            connection_id,
+            tag,
+            favorites_only,
+            search_text,
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct ToggleScriptFavoriteArgs {
+    id: i64,
+}
+
+async fn toggle_script_favorite(
+    State(state): State<WebState>,
+    CommandJson(ToggleScriptFavoriteArgs { id }): CommandJson<ToggleScriptFavoriteArgs>,
+) -> CommandResult<bool> {
+    Ok(Json(
+        services::toggle_script_favorite(id, state.app_state.as_ref()).await?,
+    ))
+}
+
 #[derive(Debug, Deserialize)]
 struct DeleteScriptArgs {
     id: i64,
@@ -631,16 +3109,151 @@ async fn get_query_history(
     ))
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetQueryHistoryEntryArgs {
+    id: i64,
+}
+
+async fn get_query_history_entry(
+    State(state): State<WebState>,
+    CommandJson(GetQueryHistoryEntryArgs { id }): CommandJson<GetQueryHistoryEntryArgs>,
+) -> CommandResult<QueryHistoryEntry> {
+    Ok(Json(
+        services::get_query_history_entry(id, state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetQueryHistoryPageArgs {
+    connection_id: String,
+    before: Option<HistoryCursor>,
+    page_size: u32,
+    filters: Option<HistoryFilters>,
+}
+
+async fn get_query_history_page(
+    State(state): State<WebState>,
+    CommandJson(GetQueryHistoryPageArgs {
+        connection_id,
+        before,
+        page_size,
+        filters,
+    }): CommandJson<GetQueryHistoryPageArgs>,
+) -> CommandResult<QueryHistoryPage> {
+    Ok(Json(
+        services::get_query_history_page(
+            connection_id,
+            before,
+            page_size,
+            filters.unwrap_or_default(),
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetQueryHistoryCountArgs {
+    connection_id: String,
+    filters: Option<HistoryFilters>,
+}
+
+async fn get_query_history_count(
+    State(state): State<WebState>,
+    CommandJson(GetQueryHistoryCountArgs {
+        connection_id,
+        filters,
+    }): CommandJson<GetQueryHistoryCountArgs>,
+) -> CommandResult<i64> {
+    Ok(Json(
+        services::get_query_history_count(
+            connection_id,
+            filters.unwrap_or_default(),
+            state.app_state.as_ref(),
+        )
+        .await?,
+    ))
+}
+
+async fn get_history_settings(State(state): State<WebState>) -> CommandResult<HistorySettings> {
+    Ok(Json(
+        services::get_history_settings(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetHistorySettingsArgs {
+    settings: HistorySettings,
+}
+
+async fn set_history_settings(
+    State(state): State<WebState>,
+    CommandJson(SetHistorySettingsArgs { settings }): CommandJson<SetHistorySettingsArgs>,
+) -> CommandResult<()> {
+    services::set_history_settings(settings, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+async fn get_result_cache_settings(
+    State(state): State<WebState>,
+) -> CommandResult<ResultCacheSettings> {
+    Ok(Json(
+        services::get_result_cache_settings(state.app_state.as_ref()).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetResultCacheSettingsArgs {
+    settings: ResultCacheSettings,
+}
+
+async fn set_result_cache_settings(
+    State(state): State<WebState>,
+    CommandJson(SetResultCacheSettingsArgs { settings }): CommandJson<SetResultCacheSettingsArgs>,
+) -> CommandResult<()> {
+    services::set_result_cache_settings(settings, state.app_state.as_ref()).await?;
+    Ok(Json(()))
+}
+
+async fn prune_query_history(State(state): State<WebState>) -> CommandResult<HistoryPruneReport> {
+    Ok(Json(
+        services::prune_query_history(state.app_state.as_ref()).await?,
+    ))
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct FormatSqlArgs {
     query: String,
+    #[serde(default)]
+    dialect: Option<Database>,
+    #[serde(default)]
+    options: FormatSettings,
 }
 
 async fn format_sql(
-    CommandJson(FormatSqlArgs { query }): CommandJson<FormatSqlArgs>,
-) -> CommandResult<String> {
-    Ok(Json(services::format_sql(&query).await?))
+    CommandJson(FormatSqlArgs {
+        query,
+        dialect,
+        options,
+    }): CommandJson<FormatSqlArgs>,
+) -> CommandResult<FormattedSql> {
+    Ok(Json(services::format_sql(&query, dialect, options).await?))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SanitizeSqlArgs {
+    text: String,
+}
+
+async fn sanitize_sql(
+    CommandJson(SanitizeSqlArgs { text }): CommandJson<SanitizeSqlArgs>,
+) -> CommandResult<SanitizedSql> {
+    Ok(Json(services::sanitize_sql(&text).await?))
 }
 
 async fn noop_command() -> CommandResult<()> {
@@ -679,6 +3292,87 @@ async fn save_sqlite_db() -> CommandResult<Option<String>> {
     ))
 }
 
+async fn save_xlsx_file() -> CommandResult<Option<String>> {
+    Ok(Json(
+        run_file_dialog(|| {
+            FileDialog::new()
+                .set_title("Export results to Excel")
+                .set_file_name("results.xlsx")
+                .add_filter("Excel workbook", &["xlsx"])
+                .save_file()
+        })
+        .await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SaveDelimitedFileArgs {
+    extension: String,
+}
+
+async fn save_delimited_file(
+    CommandJson(SaveDelimitedFileArgs { extension }): CommandJson<SaveDelimitedFileArgs>,
+) -> CommandResult<Option<String>> {
+    Ok(Json(
+        run_file_dialog(move || {
+            FileDialog::new()
+                .set_title("Export results")
+                .set_file_name(format!("results.{extension}"))
+                .add_filter("Delimited text", &[extension.as_str()])
+                .save_file()
+        })
+        .await?,
+    ))
+}
+
+async fn pick_script_directory() -> CommandResult<Option<String>> {
+    Ok(Json(
+        run_file_dialog(|| {
+            FileDialog::new()
+                .set_title("Pick a directory of SQL scripts")
+                .pick_folder()
+        })
+        .await?,
+    ))
+}
+
+async fn save_connections_file() -> CommandResult<Option<String>> {
+    Ok(Json(
+        run_file_dialog(|| {
+            FileDialog::new()
+                .set_title("Export connections")
+                .set_file_name("connections.json")
+                .add_filter("pgpad connections", &["json"])
+                .save_file()
+        })
+        .await?,
+    ))
+}
+
+async fn open_connections_file() -> CommandResult<Option<String>> {
+    Ok(Json(
+        run_file_dialog(|| {
+            FileDialog::new()
+                .set_title("Import connections")
+                .add_filter("pgpad connections", &["json"])
+                .pick_file()
+        })
+        .await?,
+    ))
+}
+
+async fn open_csv_file() -> CommandResult<Option<String>> {
+    Ok(Json(
+        run_file_dialog(|| {
+            FileDialog::new()
+                .set_title("Import a CSV file")
+                .add_filter("Delimited text", &["csv", "tsv", "txt"])
+                .pick_file()
+        })
+        .await?,
+    ))
+}
+
 async fn pick_ca_cert() -> CommandResult<Option<String>> {
     Ok(Json(
         run_file_dialog(|| {
@@ -165,7 +165,7 @@ async fn sqlite_connection_query_flow_works_over_http_commands() {
     .await;
     assert!(read_only);
 
-    let query_ids: Vec<usize> = command_ok(
+    let submitted: Value = command_ok(
         &app,
         "submit_query",
         json!({
@@ -174,6 +174,9 @@ async fn sqlite_connection_query_flow_works_over_http_commands() {
         }),
     )
     .await;
+    assert_eq!(submitted["status"], "submitted");
+    let query_ids: Vec<usize> =
+        serde_json::from_value(submitted["query_ids"].clone()).expect("query ids");
     assert_eq!(query_ids, vec![0]);
 
     let snapshot: Value = command_ok(
@@ -236,6 +239,15 @@ async fn sqlite_connection_query_flow_works_over_http_commands() {
         "SELECT id, name FROM items ORDER BY id"
     );
 
+    let entry: Value = command_ok(
+        &app,
+        "get_query_history_entry",
+        json!({ "id": history[0]["id"] }),
+    )
+    .await;
+    assert_eq!(entry["run_count"], 1);
+    assert_eq!(entry["plan_json"], Value::Null);
+
     let script_id: i64 = command_ok(
         &app,
         "save_script",
@@ -342,3 +354,146 @@ async fn api_commands_require_auth_token() {
     let (status, _) = command(&app, "initialize_connections", json!({})).await;
     assert_eq!(status, StatusCode::OK);
 }
+
+/// Adds and connects to the SQLite database in `sqlite_dir`, returning the connection's id
+async fn connect_sqlite(app: &TestApp, sqlite_dir: &TempDir) -> String {
+    let _: Value = command_ok(app, "initialize_connections", json!({})).await;
+    let connection: Value = command_ok(
+        app,
+        "add_connection",
+        json!({
+            "name": "SQLite",
+            "config": {
+                "SQLite": {
+                    "db_path": sqlite_dir.path().join("user.sqlite")
+                }
+            },
+            "permissions": "read_write"
+        }),
+    )
+    .await;
+    let connection_id = connection["id"].as_str().expect("connection id missing");
+
+    let connected: bool = command_ok(
+        app,
+        "connect_to_database",
+        json!({ "connectionId": connection_id }),
+    )
+    .await;
+    assert!(connected);
+
+    connection_id.to_owned()
+}
+
+/// Submits a single statement, returning its query id once it completed
+async fn run_statement(app: &TestApp, connection_id: &str, query: &str) -> usize {
+    let submitted: Value = command_ok(
+        app,
+        "submit_query",
+        json!({ "connectionId": connection_id, "query": query }),
+    )
+    .await;
+    let query_ids: Vec<usize> =
+        serde_json::from_value(submitted["query_ids"].clone()).expect("query ids");
+
+    let snapshot: Value = command_ok(
+        app,
+        "wait_until_renderable",
+        json!({ "queryId": query_ids[0] }),
+    )
+    .await;
+    assert_eq!(snapshot["status"], "Completed");
+
+    query_ids[0]
+}
+
+#[tokio::test]
+async fn joins_results_of_separate_submissions() {
+    let static_dir = make_static_dir();
+    let sqlite_dir = make_sqlite_db();
+    let app_dir = tempfile::tempdir().expect("failed to create app dir");
+    let app = make_app(&static_dir, app_dir.path().join("pgpad.db"));
+    let connection_id = connect_sqlite(&app, &sqlite_dir).await;
+
+    let before = run_statement(&app, &connection_id, "SELECT id, name FROM items").await;
+    let _: Value = command_ok(&app, "pin_result", json!({ "queryId": before })).await;
+    let after = run_statement(
+        &app,
+        &connection_id,
+        "SELECT id, upper(name) AS name FROM items",
+    )
+    .await;
+    assert_ne!(before, after);
+
+    let joined: Value = command_ok(
+        &app,
+        "join_results",
+        json!({
+            "queryIdA": before,
+            "queryIdB": after,
+            "sql": "SELECT a.id, a.name, b.name FROM a JOIN b USING (id) ORDER BY id"
+        }),
+    )
+    .await;
+    assert_eq!(joined["row_count"], 2);
+
+    let page: Value = command_ok(
+        &app,
+        "fetch_page",
+        json!({ "queryId": joined["query_id"], "pageIndex": 0 }),
+    )
+    .await;
+    assert_eq!(page, json!([[1, "alpha", "ALPHA"], [2, "beta", "BETA"]]));
+
+    // Without the pin, the first result is gone once the next query is submitted
+    let _: Value = command_ok(&app, "unpin_result", json!({ "queryId": before })).await;
+    let (status, _) = command(
+        &app,
+        "fetch_page",
+        json!({ "queryId": before, "pageIndex": 0 }),
+    )
+    .await;
+    assert_ne!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn diffs_results_of_separate_submissions() {
+    let static_dir = make_static_dir();
+    let sqlite_dir = make_sqlite_db();
+    let app_dir = tempfile::tempdir().expect("failed to create app dir");
+    let app = make_app(&static_dir, app_dir.path().join("pgpad.db"));
+    let connection_id = connect_sqlite(&app, &sqlite_dir).await;
+
+    let before = run_statement(&app, &connection_id, "SELECT id, name FROM items").await;
+    let _: Value = command_ok(&app, "pin_result", json!({ "queryId": before })).await;
+    let after = run_statement(
+        &app,
+        &connection_id,
+        "SELECT id, CASE id WHEN 2 THEN 'gamma' ELSE name END AS name FROM items",
+    )
+    .await;
+
+    let diff: Value = command_ok(
+        &app,
+        "diff_results",
+        json!({ "queryIdA": before, "queryIdB": after, "keyColumns": ["id"] }),
+    )
+    .await;
+    assert_eq!(
+        (
+            &diff["changed"],
+            &diff["unchanged"],
+            &diff["added"],
+            &diff["removed"]
+        ),
+        (&json!(1), &json!(1), &json!(0), &json!(0))
+    );
+
+    let page: Vec<Value> = command_ok(
+        &app,
+        "fetch_page",
+        json!({ "queryId": diff["query_id"], "pageIndex": 0 }),
+    )
+    .await;
+    assert_eq!(page.len(), 1);
+}
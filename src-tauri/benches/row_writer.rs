@@ -0,0 +1,62 @@
+//! Writing pages of wide Postgres rows, the way a query's results are streamed to the UI.
+//!
+//! Needs Postgres' binaries to be installed, since it runs against a temporary database. Save a baseline with
+//! `cargo bench --bench row_writer -- --save-baseline main` to compare changes against.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use pgpad_core::database::postgres::row_writer::RowWriter;
+use pgtemp::PgTempDB;
+use tokio_postgres::{NoTls, Row};
+
+/// As many rows as a page holds
+const ROWS: usize = 50;
+const COLUMNS: usize = 300;
+
+/// A page of rows with `COLUMNS` columns of the common types, text with characters to escape included
+fn wide_rows(runtime: &tokio::runtime::Runtime, db: &PgTempDB) -> Vec<Row> {
+    let columns = (0..COLUMNS)
+        .map(|idx| match idx % 6 {
+            0 => format!("g + {idx} AS c{idx}"),
+            1 => format!("(g * {idx} * 1.5)::float8 AS c{idx}"),
+            2 => format!("'row ' || g || ', \"column\" {idx}\tof text' AS c{idx}"),
+            3 => format!("TIMESTAMP '2025-08-07 12:00:00' + g * INTERVAL '1 minute' AS c{idx}"),
+            4 => format!("(g % 2 = 0) AS c{idx}"),
+            _ => format!("(g * 3.25)::numeric AS c{idx}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!("SELECT {columns} FROM generate_series(1, {ROWS}) AS g");
+
+    runtime.block_on(async {
+        let (client, conn) = tokio_postgres::connect(&db.connection_uri(), NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        client.query(&sql, &[]).await.unwrap()
+    })
+}
+
+fn write_pages(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let db = runtime.block_on(PgTempDB::async_new());
+    let rows = wide_rows(&runtime, &db);
+
+    let mut group = c.benchmark_group("row_writer");
+    group.throughput(Throughput::Elements((ROWS * COLUMNS) as u64));
+    group.bench_function("postgres_wide_page", |b| {
+        // Kept between pages, as it is while a query streams
+        let mut writer = RowWriter::new();
+        b.iter(|| {
+            for row in &rows {
+                writer.add_row(row).unwrap();
+            }
+            black_box(writer.finish())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, write_pages);
+criterion_main!(benches);
@@ -1,16 +1,63 @@
 use std::sync::Arc;
 
 use pgpad_core::{
+    connection_file::ImportedConnection,
     database::{
+        browse::BrowsePage,
+        column_stats::ColumnStats,
+        csv_import::{CsvImportOptions, CsvImportReport},
+        definition::Definition,
+        delimited::ExportOptions,
+        diagnostics::Diagnostic,
+        error_hints::ErrorSuggestion,
+        execution_marks::StatementMarker,
+        filtering::{FilteredQuery, RowFilter},
+        formatter::{FormatSettings, FormattedSql},
+        grouping::{Aggregate, GroupedQuery},
+        hover::Hover,
+        insert_export::InsertOptions,
+        json_export::JsonFormat,
+        postgres::{
+            activity::{ActivityFilters, ServerActivity},
+            copy::CopyReport,
+            explain::{ExplainOptions, ExplainedPlan},
+            replication::ReplicationInfo,
+            stats::PostgresStats,
+        },
+        preflight::{EnvironmentPolicies, PreflightSettings, SubmitOutcome},
+        probe::ConnectionProbe,
+        query_tags::QueryTagSettings,
+        reconnect::ReconnectSettings,
+        relationship_graph::RelationshipGraph,
+        result_cache::ResultCacheSettings,
+        result_diff::ResultDiff,
+        sanitize::SanitizedSql,
+        semantic_search::{SchemaSearchResults, SemanticSearchSettings},
         services as core,
+        sorting::{Collation, NullsOrder, SortKey, SortedQuery},
+        sqlite::{explain::QueryPlan, join::JoinedQuery, snapshot::SnapshotRefresh},
+        table_ddl::TableDdl,
+        trace::TracedStatement,
+        transactions::TransactionInfo,
         types::{
-            ConnectionConfig, ConnectionInfo, DatabaseSchema, Permissions, QuerySnapshot,
-            QueryStatus,
+            ColumnInfo, ConnectionConfig, ConnectionInfo, Database, DatabaseSchema, Environment,
+            Permissions, QuerySnapshot, QueryStatus, ResourceLimits, RowDetailField, ServerMessage,
+            StatementInfo, SubmitOptions, TimingBreakdown,
         },
-        Certificates, ConnectionMonitor,
+        Certificates, ConnectionHealth, ConnectionMonitor,
+    },
+    external_edit::{ConflictResolution, ExternalEditSession, ExternalEditorSettings},
+    linked_scripts::IndexedScriptDirectory,
+    operations::OperationInfo,
+    script_file::ScriptFile,
+    script_templates::ScriptTemplate,
+    storage::{
+        DraftBuffer, ExportTemplate, HistoryCursor, HistoryFilters, HistoryPruneReport,
+        HistorySettings, QueryHistoryEntry, QueryHistoryPage, QueryVariable, SavedQuery,
+        ScheduledQuery, SessionInfo, SessionState, Snippet,
     },
-    storage::{QueryHistoryEntry, SavedQuery},
-    AppState,
+    tree_state::TreeState,
+    AppState, CredentialBackendStatus, SecretBackend,
 };
 use serde_json::value::RawValue;
 use uuid::Uuid;
@@ -18,24 +65,79 @@ use uuid::Uuid;
 use crate::error::Result;
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn add_connection(
     name: String,
+    folder: Option<String>,
+    environment: Option<Environment>,
+    color: Option<String>,
     config: ConnectionConfig,
     permissions: Permissions,
     state: tauri::State<'_, AppState>,
 ) -> Result<ConnectionInfo> {
-    Ok(core::add_connection(name, config, permissions, &state).await?)
+    Ok(core::add_connection(
+        name,
+        folder,
+        environment,
+        color,
+        config,
+        permissions,
+        &state,
+    )
+    .await?)
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn update_connection(
     conn_id: Uuid,
     name: String,
+    folder: Option<String>,
+    environment: Option<Environment>,
+    color: Option<String>,
     config: ConnectionConfig,
     permissions: Permissions,
     state: tauri::State<'_, AppState>,
 ) -> Result<ConnectionInfo> {
-    Ok(core::update_connection(conn_id, name, config, permissions, &state).await?)
+    Ok(core::update_connection(
+        conn_id,
+        name,
+        folder,
+        environment,
+        color,
+        config,
+        permissions,
+        &state,
+    )
+    .await?)
+}
+
+#[tauri::command]
+pub async fn rename_connection_folder(
+    old: String,
+    new: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize> {
+    Ok(core::rename_connection_folder(&old, &new, &state).await?)
+}
+
+#[tauri::command]
+pub async fn export_connections(
+    path: &str,
+    connection_ids: Vec<Uuid>,
+    passphrase: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize> {
+    Ok(core::export_connections(path, &connection_ids, passphrase.as_deref(), &state).await?)
+}
+
+#[tauri::command]
+pub async fn import_connections(
+    path: &str,
+    passphrase: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ImportedConnection>> {
+    Ok(core::import_connections(path, passphrase.as_deref(), &state).await?)
 }
 
 #[tauri::command]
@@ -56,13 +158,250 @@ pub async fn disconnect_from_database(
     Ok(core::disconnect_from_database(connection_id, &state).await?)
 }
 
+#[tauri::command]
+pub async fn get_connection_health(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+    monitor: tauri::State<'_, ConnectionMonitor>,
+) -> Result<Option<ConnectionHealth>> {
+    Ok(core::get_connection_health(connection_id, &state, &monitor).await?)
+}
+
 #[tauri::command]
 pub async fn submit_query(
     connection_id: Uuid,
     query: &str,
+    options: Option<SubmitOptions>,
+    state: tauri::State<'_, AppState>,
+) -> Result<SubmitOutcome> {
+    Ok(core::submit_query(connection_id, query, options.unwrap_or_default(), &state).await?)
+}
+
+#[tauri::command]
+pub async fn confirm_run(run_id: Uuid, state: tauri::State<'_, AppState>) -> Result<Vec<usize>> {
+    Ok(core::confirm_run(run_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn discard_run(run_id: Uuid, state: tauri::State<'_, AppState>) -> Result<()> {
+    Ok(core::discard_run(run_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn browse_table(
+    connection_id: Uuid,
+    schema: &str,
+    table: &str,
+    page_index: usize,
+    page_size: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<BrowsePage> {
+    Ok(core::browse_table(connection_id, schema, table, page_index, page_size, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_preflight_settings(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<PreflightSettings> {
+    Ok(core::get_preflight_settings(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn set_preflight_settings(
+    connection_id: Uuid,
+    settings: PreflightSettings,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::set_preflight_settings(connection_id, settings, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_environment_policies(
+    state: tauri::State<'_, AppState>,
+) -> Result<EnvironmentPolicies> {
+    Ok(core::get_environment_policies(&state).await?)
+}
+
+#[tauri::command]
+pub async fn set_environment_policies(
+    policies: EnvironmentPolicies,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::set_environment_policies(policies, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_statement_savepoints(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<bool> {
+    Ok(core::get_statement_savepoints(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn set_statement_savepoints(
+    connection_id: Uuid,
+    enabled: bool,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::set_statement_savepoints(connection_id, enabled, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_query_tag_settings(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<QueryTagSettings> {
+    Ok(core::get_query_tag_settings(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn set_query_tag_settings(
+    connection_id: Uuid,
+    settings: QueryTagSettings,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::set_query_tag_settings(connection_id, settings, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_reconnect_settings(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<ReconnectSettings> {
+    Ok(core::get_reconnect_settings(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn set_reconnect_settings(
+    connection_id: Uuid,
+    settings: ReconnectSettings,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::set_reconnect_settings(connection_id, settings, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_snapshot_mode(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<bool> {
+    Ok(core::get_snapshot_mode(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn set_snapshot_mode(
+    connection_id: Uuid,
+    enabled: bool,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::set_snapshot_mode(connection_id, enabled, &state).await?)
+}
+
+#[tauri::command]
+pub async fn refresh_snapshot(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<SnapshotRefresh> {
+    Ok(core::refresh_snapshot(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn set_statement_tracing(
+    connection_id: Uuid,
+    enabled: bool,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::set_statement_tracing(connection_id, enabled, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_statement_trace(
+    connection_id: Uuid,
+    limit: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<TracedStatement>> {
+    Ok(core::get_statement_trace(connection_id, limit, &state).await?)
+}
+
+#[tauri::command]
+pub async fn clear_statement_trace(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::clear_statement_trace(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_secret_backends(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<SecretBackend>> {
+    Ok(core::get_secret_backends(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn set_secret_backends(
+    connection_id: Uuid,
+    backends: Vec<SecretBackend>,
     state: tauri::State<'_, AppState>,
-) -> Result<Vec<usize>> {
-    Ok(core::submit_query(connection_id, query, &state).await?)
+) -> Result<()> {
+    Ok(core::set_secret_backends(connection_id, backends, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_credential_backend_status(
+    state: tauri::State<'_, AppState>,
+) -> Result<CredentialBackendStatus> {
+    Ok(core::get_credential_backend_status(&state).await?)
+}
+
+#[tauri::command]
+pub async fn unlock_credential_file(
+    passphrase: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<CredentialBackendStatus> {
+    Ok(core::unlock_credential_file(passphrase, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_connection_resource_limits(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<ResourceLimits> {
+    Ok(core::get_connection_resource_limits(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn set_connection_resource_limits(
+    connection_id: Uuid,
+    limits: ResourceLimits,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::set_connection_resource_limits(connection_id, limits, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_postgres_pool_size(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize> {
+    Ok(core::get_postgres_pool_size(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn set_postgres_pool_size(
+    connection_id: Uuid,
+    size: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::set_postgres_pool_size(connection_id, size, &state).await?)
+}
+
+#[tauri::command]
+pub async fn cancel_postgres(connection_id: Uuid, state: tauri::State<'_, AppState>) -> Result<()> {
+    Ok(core::cancel_postgres(connection_id, &state).await?)
 }
 
 #[tauri::command]
@@ -73,6 +412,57 @@ pub async fn wait_until_renderable(
     Ok(core::wait_until_renderable(query_id, &state).await?)
 }
 
+#[tauri::command]
+pub async fn cancel_all_queries(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<usize> {
+    Ok(core::cancel_all_queries(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn begin_transaction(
+    connection_id: Uuid,
+    tab_id: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::begin_transaction(connection_id, tab_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn commit_transaction(
+    connection_id: Uuid,
+    tab_id: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::commit_transaction(connection_id, tab_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn rollback_transaction(
+    connection_id: Uuid,
+    tab_id: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::rollback_transaction(connection_id, tab_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_transaction_state(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<TransactionInfo>> {
+    Ok(core::get_transaction_state(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_statement_infos(
+    query_ids: Vec<usize>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Option<StatementInfo>>> {
+    Ok(core::get_statement_infos(&query_ids, &state).await?)
+}
+
 #[tauri::command]
 pub async fn fetch_page(
     query_id: usize,
@@ -95,6 +485,48 @@ pub async fn get_page_count(query_id: usize, state: tauri::State<'_, AppState>)
     Ok(core::get_page_count(query_id, &state).await?)
 }
 
+#[tauri::command]
+pub async fn request_more_rows(query_id: usize, state: tauri::State<'_, AppState>) -> Result<()> {
+    Ok(core::request_more_rows(query_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_timing_breakdown(
+    query_id: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<TimingBreakdown>> {
+    Ok(core::get_timing_breakdown(query_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_row_detail(
+    query_id: usize,
+    row_index: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<RowDetailField>> {
+    Ok(core::get_row_detail(query_id, row_index, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_column_stats(
+    query_id: usize,
+    column_index: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<ColumnStats> {
+    Ok(core::get_column_stats(query_id, column_index, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_table_column_stats(
+    connection_id: Uuid,
+    schema: &str,
+    table: &str,
+    column: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<ColumnStats> {
+    Ok(core::get_table_column_stats(connection_id, schema, table, column, &state).await?)
+}
+
 #[tauri::command]
 pub async fn get_connections(state: tauri::State<'_, AppState>) -> Result<Vec<ConnectionInfo>> {
     Ok(core::get_connections(&state).await?)
@@ -105,6 +537,21 @@ pub async fn remove_connection(connection_id: Uuid, state: tauri::State<'_, AppS
     Ok(core::remove_connection(connection_id, &state).await?)
 }
 
+/// Asks where the file of a SQLite connection is now. None if the dialog was dismissed.
+#[tauri::command]
+pub async fn relocate_database_file(
+    connection_id: Uuid,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<ConnectionInfo>> {
+    let Some(db_path) = crate::window::commands::open_sqlite_db(app).await? else {
+        return Ok(None);
+    };
+    Ok(Some(
+        core::relocate_database_file(connection_id, &db_path, &state).await?,
+    ))
+}
+
 #[tauri::command]
 pub async fn test_connection(
     config: ConnectionConfig,
@@ -114,6 +561,16 @@ pub async fn test_connection(
 }
 
 #[tauri::command]
+pub async fn probe_connections(
+    connection_ids: Vec<Uuid>,
+    timeout_ms: u64,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ConnectionProbe>> {
+    Ok(core::probe_connections(connection_ids, timeout_ms, &state).await?)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn save_query_to_history(
     connection_id: String,
     query: String,
@@ -121,6 +578,8 @@ pub async fn save_query_to_history(
     status: String,
     row_count: u64,
     error_message: Option<String>,
+    timings: Option<Vec<TimingBreakdown>>,
+    plan_json: Option<String>,
     state: tauri::State<'_, AppState>,
 ) -> Result {
     Ok(core::save_query_to_history(
@@ -130,11 +589,21 @@ pub async fn save_query_to_history(
         status,
         row_count,
         error_message,
+        timings,
+        plan_json,
         &state,
     )
     .await?)
 }
 
+#[tauri::command]
+pub async fn get_query_history_entry(
+    id: i64,
+    state: tauri::State<'_, AppState>,
+) -> Result<QueryHistoryEntry> {
+    Ok(core::get_query_history_entry(id, &state).await?)
+}
+
 #[tauri::command]
 pub async fn get_query_history(
     connection_id: String,
@@ -145,53 +614,468 @@ pub async fn get_query_history(
 }
 
 #[tauri::command]
-pub async fn initialize_connections(state: tauri::State<'_, AppState>) -> Result {
-    Ok(core::initialize_connections(&state).await?)
+pub async fn get_query_history_page(
+    connection_id: String,
+    before: Option<HistoryCursor>,
+    page_size: u32,
+    filters: Option<HistoryFilters>,
+    state: tauri::State<'_, AppState>,
+) -> Result<QueryHistoryPage> {
+    Ok(core::get_query_history_page(
+        connection_id,
+        before,
+        page_size,
+        filters.unwrap_or_default(),
+        &state,
+    )
+    .await?)
 }
 
 #[tauri::command]
-pub async fn format_sql(query: &str) -> Result<String> {
-    Ok(core::format_sql(query).await?)
+pub async fn get_query_history_count(
+    connection_id: String,
+    filters: Option<HistoryFilters>,
+    state: tauri::State<'_, AppState>,
+) -> Result<i64> {
+    Ok(core::get_query_history_count(connection_id, filters.unwrap_or_default(), &state).await?)
 }
 
 #[tauri::command]
-pub async fn is_query_read_only(
-    connection_id: Uuid,
-    query: &str,
-    state: tauri::State<'_, AppState>,
-) -> Result<bool> {
-    Ok(core::is_query_read_only(connection_id, query, &state).await?)
+pub async fn get_history_settings(state: tauri::State<'_, AppState>) -> Result<HistorySettings> {
+    Ok(core::get_history_settings(&state).await?)
 }
 
 #[tauri::command]
-pub async fn get_database_schema(
-    connection_id: Uuid,
+pub async fn set_history_settings(
+    settings: HistorySettings,
     state: tauri::State<'_, AppState>,
-) -> Result<Arc<DatabaseSchema>> {
-    Ok(core::get_database_schema(connection_id, &state).await?)
+) -> Result {
+    Ok(core::set_history_settings(settings, &state).await?)
 }
 
 #[tauri::command]
-pub async fn save_script(
-    name: String,
-    content: String,
-    connection_id: Option<Uuid>,
-    description: Option<String>,
+pub async fn get_result_cache_settings(
     state: tauri::State<'_, AppState>,
-) -> Result<i64> {
-    Ok(core::save_script(name, content, connection_id, description, &state).await?)
+) -> Result<ResultCacheSettings> {
+    Ok(core::get_result_cache_settings(&state).await?)
 }
 
 #[tauri::command]
+pub async fn set_result_cache_settings(
+    settings: ResultCacheSettings,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::set_result_cache_settings(settings, &state).await?)
+}
+
+#[tauri::command]
+pub async fn prune_query_history(state: tauri::State<'_, AppState>) -> Result<HistoryPruneReport> {
+    Ok(core::prune_query_history(&state).await?)
+}
+
+#[tauri::command]
+pub async fn initialize_connections(state: tauri::State<'_, AppState>) -> Result {
+    Ok(core::initialize_connections(&state).await?)
+}
+
+#[tauri::command]
+pub async fn initialize_workspace(state: tauri::State<'_, AppState>) -> Result<Vec<DraftBuffer>> {
+    Ok(core::initialize_workspace(&state).await?)
+}
+
+#[tauri::command]
+pub async fn format_sql(
+    query: &str,
+    dialect: Option<Database>,
+    options: Option<FormatSettings>,
+) -> Result<FormattedSql> {
+    Ok(core::format_sql(query, dialect, options.unwrap_or_default()).await?)
+}
+
+#[tauri::command]
+pub async fn sanitize_sql(text: &str) -> Result<SanitizedSql> {
+    Ok(core::sanitize_sql(text).await?)
+}
+
+#[tauri::command]
+pub async fn lsp_hover(
+    connection_id: Uuid,
+    query: &str,
+    position: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<Hover>> {
+    Ok(core::lsp_hover(connection_id, query, position, &state).await?)
+}
+
+#[tauri::command]
+pub async fn lsp_join_conditions(
+    connection_id: Uuid,
+    query: &str,
+    position: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<String>> {
+    Ok(core::lsp_join_conditions(connection_id, query, position, &state).await?)
+}
+
+#[tauri::command]
+pub async fn lsp_diagnostics(
+    connection_id: Uuid,
+    query: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Diagnostic>> {
+    Ok(core::lsp_diagnostics(connection_id, query, &state).await?)
+}
+
+#[tauri::command]
+pub async fn lsp_definition(
+    connection_id: Uuid,
+    query: &str,
+    position: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<Definition>> {
+    Ok(core::lsp_definition(connection_id, query, position, &state).await?)
+}
+
+#[tauri::command]
+pub async fn is_query_read_only(
+    connection_id: Uuid,
+    query: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<bool> {
+    Ok(core::is_query_read_only(connection_id, query, &state).await?)
+}
+
+#[tauri::command]
+pub async fn detect_query_parameters(
+    connection_id: Uuid,
+    query: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<String>> {
+    Ok(core::detect_query_parameters(connection_id, query, &state).await?)
+}
+
+#[tauri::command]
+pub async fn explain_query(
+    connection_id: Uuid,
+    query: &str,
+    cursor: Option<usize>,
+    options: ExplainOptions,
+    state: tauri::State<'_, AppState>,
+) -> Result<ExplainedPlan> {
+    Ok(core::explain_query(connection_id, query, cursor, options, &state).await?)
+}
+
+#[tauri::command]
+pub async fn explain_sqlite_query(
+    connection_id: Uuid,
+    query: &str,
+    cursor: Option<usize>,
+    state: tauri::State<'_, AppState>,
+) -> Result<QueryPlan> {
+    Ok(core::explain_sqlite_query(connection_id, query, cursor, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_database_schema(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<Arc<DatabaseSchema>> {
+    Ok(core::get_database_schema(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_relationship_graph(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<Arc<RelationshipGraph>> {
+    Ok(core::get_relationship_graph(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn attach_sqlite_database(
+    connection_id: Uuid,
+    path: &str,
+    alias: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<Arc<DatabaseSchema>> {
+    Ok(core::attach_sqlite_database(connection_id, path, alias, &state).await?)
+}
+
+#[tauri::command]
+pub async fn detach_sqlite_database(
+    connection_id: Uuid,
+    alias: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<Arc<DatabaseSchema>> {
+    Ok(core::detach_sqlite_database(connection_id, alias, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_table_columns(
+    connection_id: Uuid,
+    schema: &str,
+    table: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ColumnInfo>> {
+    Ok(core::get_table_columns(connection_id, schema, table, &state).await?)
+}
+
+#[tauri::command]
+pub async fn refresh_table_row_count(
+    connection_id: Uuid,
+    schema: &str,
+    table: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<i64> {
+    Ok(core::refresh_table_row_count(connection_id, schema, table, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_table_ddl(
+    connection_id: Uuid,
+    schema: &str,
+    table: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<TableDdl> {
+    Ok(core::get_table_ddl(connection_id, schema, table, &state).await?)
+}
+
+#[tauri::command]
+pub async fn set_active_schema(
+    connection_id: Uuid,
+    schema: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<()> {
+    Ok(core::set_active_schema(connection_id, schema, &state).await?)
+}
+
+#[tauri::command]
+pub async fn suggest_error_fixes(
+    connection_id: Uuid,
+    query: &str,
+    error: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ErrorSuggestion>> {
+    Ok(core::suggest_error_fixes(connection_id, query, error, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_error_suggestions_enabled(state: tauri::State<'_, AppState>) -> Result<bool> {
+    Ok(core::get_error_suggestions_enabled(&state).await?)
+}
+
+#[tauri::command]
+pub async fn set_error_suggestions_enabled(
+    enabled: bool,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::set_error_suggestions_enabled(enabled, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_postgres_replication_info(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<ReplicationInfo> {
+    Ok(core::get_postgres_replication_info(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_postgres_stats(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<PostgresStats> {
+    Ok(core::get_postgres_stats(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_server_activity(
+    connection_id: Uuid,
+    filters: Option<ActivityFilters>,
+    state: tauri::State<'_, AppState>,
+) -> Result<ServerActivity> {
+    Ok(core::get_server_activity(connection_id, filters.unwrap_or_default(), &state).await?)
+}
+
+#[tauri::command]
+pub async fn terminate_session(
+    connection_id: Uuid,
+    pid: i32,
+    force: bool,
+    state: tauri::State<'_, AppState>,
+) -> Result<bool> {
+    Ok(core::terminate_session(connection_id, pid, force, &state).await?)
+}
+
+#[tauri::command]
+pub async fn listen_postgres(
+    connection_id: Uuid,
+    channel: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::listen_postgres(connection_id, channel, &state).await?)
+}
+
+#[tauri::command]
+pub async fn unlisten_postgres(
+    connection_id: Uuid,
+    channel: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::unlisten_postgres(connection_id, channel, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_active_listens(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<String>> {
+    Ok(core::get_active_listens(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_connection_messages(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ServerMessage>> {
+    Ok(core::get_connection_messages(connection_id, &state).await?)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_script(
+    name: String,
+    content: String,
+    connection_id: Option<Uuid>,
+    description: Option<String>,
+    template_id: Option<String>,
+    tags: Option<Vec<String>>,
+    favorite: Option<bool>,
+    state: tauri::State<'_, AppState>,
+) -> Result<i64> {
+    Ok(core::save_script(
+        name,
+        content,
+        connection_id,
+        description,
+        template_id,
+        tags.unwrap_or_default(),
+        favorite.unwrap_or_default(),
+        &state,
+    )
+    .await?)
+}
+
+#[tauri::command]
+pub async fn get_snippets(
+    connection_id: Option<Uuid>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Snippet>> {
+    Ok(core::get_snippets(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn save_snippet(snippet: Snippet, state: tauri::State<'_, AppState>) -> Result<Snippet> {
+    Ok(core::save_snippet(snippet, &state).await?)
+}
+
+#[tauri::command]
+pub async fn delete_snippet(id: i64, state: tauri::State<'_, AppState>) -> Result {
+    Ok(core::delete_snippet(id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_query_variables(
+    connection_id: Option<Uuid>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<QueryVariable>> {
+    Ok(core::get_query_variables(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn save_query_variable(
+    variable: QueryVariable,
+    state: tauri::State<'_, AppState>,
+) -> Result<QueryVariable> {
+    Ok(core::save_query_variable(variable, &state).await?)
+}
+
+#[tauri::command]
+pub async fn delete_query_variable(id: i64, state: tauri::State<'_, AppState>) -> Result {
+    Ok(core::delete_query_variable(id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_scheduled_queries(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ScheduledQuery>> {
+    Ok(core::get_scheduled_queries(&state).await?)
+}
+
+#[tauri::command]
+pub async fn save_scheduled_query(
+    scheduled: ScheduledQuery,
+    state: tauri::State<'_, AppState>,
+) -> Result<ScheduledQuery> {
+    Ok(core::save_scheduled_query(scheduled, &state).await?)
+}
+
+#[tauri::command]
+pub async fn delete_scheduled_query(id: i64, state: tauri::State<'_, AppState>) -> Result {
+    Ok(core::delete_scheduled_query(id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn preview_substitution(
+    query: &str,
+    connection_id: Option<Uuid>,
+    state: tauri::State<'_, AppState>,
+) -> Result<String> {
+    Ok(core::preview_substitution(query, connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_script_templates(
+    db_type: Database,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ScriptTemplate>> {
+    Ok(core::get_script_templates(db_type, &state).await?)
+}
+
+#[tauri::command]
+pub async fn save_script_template(
+    db_type: Database,
+    name: &str,
+    description: Option<String>,
+    body: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<ScriptTemplate> {
+    Ok(core::save_script_template(db_type, name, description, body, &state).await?)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn update_script(
     id: i64,
     name: String,
     content: String,
     connection_id: Option<Uuid>,
     description: Option<String>,
+    tags: Option<Vec<String>>,
+    favorite: Option<bool>,
     state: tauri::State<'_, AppState>,
 ) -> Result {
-    Ok(core::update_script(id, name, content, connection_id, description, &state).await?)
+    Ok(core::update_script(
+        id,
+        name,
+        content,
+        connection_id,
+        description,
+        tags.unwrap_or_default(),
+        favorite.unwrap_or_default(),
+        &state,
+    )
+    .await?)
 }
 
 #[tauri::command]
@@ -202,19 +1086,125 @@ pub async fn get_scripts(
     Ok(core::get_scripts(connection_id, &state).await?)
 }
 
+#[tauri::command]
+pub async fn get_scripts_filtered(
+    connection_id: Option<Uuid>,
+    tag: Option<String>,
+    favorites_only: bool,
+    search_text: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<SavedQuery>> {
+    Ok(core::get_scripts_filtered(connection_id, tag, favorites_only, search_text, &state).await?)
+}
+
+#[tauri::command]
+pub async fn toggle_script_favorite(id: i64, state: tauri::State<'_, AppState>) -> Result<bool> {
+    Ok(core::toggle_script_favorite(id, &state).await?)
+}
+
 #[tauri::command]
 pub async fn delete_script(id: i64, state: tauri::State<'_, AppState>) -> Result {
     Ok(core::delete_script(id, &state).await?)
 }
 
 #[tauri::command]
-pub async fn save_session_state(session_data: &str, state: tauri::State<'_, AppState>) -> Result {
-    Ok(core::save_session_state(session_data, &state).await?)
+pub async fn autosave_buffer(
+    buffer_id: &str,
+    connection_id: Option<Uuid>,
+    content: &str,
+    cursor: u32,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::autosave_buffer(buffer_id, connection_id, content, cursor, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_draft_buffers(state: tauri::State<'_, AppState>) -> Result<Vec<DraftBuffer>> {
+    Ok(core::get_draft_buffers(&state).await?)
+}
+
+#[tauri::command]
+pub async fn discard_draft_buffer(buffer_id: &str, state: tauri::State<'_, AppState>) -> Result {
+    Ok(core::discard_draft_buffer(buffer_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn list_sessions(state: tauri::State<'_, AppState>) -> Result<Vec<SessionInfo>> {
+    Ok(core::list_sessions(&state).await?)
+}
+
+#[tauri::command]
+pub async fn save_session(
+    session_id: &str,
+    version: u32,
+    data: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::save_session(session_id, version, data, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_session(
+    session_id: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<SessionState>> {
+    Ok(core::get_session(session_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn delete_session(session_id: &str, state: tauri::State<'_, AppState>) -> Result {
+    Ok(core::delete_session(session_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn save_tree_state(
+    connection_id: Uuid,
+    state_json: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::save_tree_state(connection_id, state_json, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_tree_state(
+    connection_id: Uuid,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<TreeState>> {
+    Ok(core::get_tree_state(connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_semantic_search_settings(
+    state: tauri::State<'_, AppState>,
+) -> Result<SemanticSearchSettings> {
+    Ok(core::get_semantic_search_settings(&state).await?)
+}
+
+#[tauri::command]
+pub async fn set_semantic_search_settings(
+    settings: SemanticSearchSettings,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::set_semantic_search_settings(settings, &state).await?)
+}
+
+#[tauri::command]
+pub async fn semantic_search_schema(
+    connection_id: Uuid,
+    query: &str,
+    limit: usize,
+    state: tauri::State<'_, AppState>,
+) -> Result<SchemaSearchResults> {
+    Ok(core::semantic_search_schema(connection_id, query, limit, &state).await?)
 }
 
 #[tauri::command]
-pub async fn get_session_state(state: tauri::State<'_, AppState>) -> Result<Option<String>> {
-    Ok(core::get_session_state(&state).await?)
+pub async fn get_buffer_execution_state(
+    tab_id: &str,
+    text: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<StatementMarker>> {
+    Ok(core::get_buffer_execution_state(tab_id, text, &state).await?)
 }
 
 #[tauri::command]
@@ -225,3 +1215,278 @@ pub async fn export_page(
 ) -> Result<String> {
     Ok(core::export_page(query_id, page_index, &state).await?)
 }
+
+#[tauri::command]
+pub async fn import_csv(
+    connection_id: Uuid,
+    file_path: &str,
+    options: CsvImportOptions,
+    state: tauri::State<'_, AppState>,
+) -> Result<CsvImportReport> {
+    Ok(core::import_csv(connection_id, file_path, options, &state).await?)
+}
+
+#[tauri::command]
+pub async fn copy_out_to_file(
+    connection_id: Uuid,
+    statement: &str,
+    path: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<CopyReport> {
+    Ok(core::copy_out_to_file(connection_id, statement, path, &state).await?)
+}
+
+#[tauri::command]
+pub async fn copy_in_from_file(
+    connection_id: Uuid,
+    statement: &str,
+    path: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<CopyReport> {
+    Ok(core::copy_in_from_file(connection_id, statement, path, &state).await?)
+}
+
+#[tauri::command]
+pub async fn export_to_xlsx(
+    query_id: usize,
+    path: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::export_to_xlsx(query_id, path, &state).await?)
+}
+
+#[tauri::command]
+pub async fn export_query_results(
+    query_id: usize,
+    path: &str,
+    template_id: Option<i64>,
+    options: Option<ExportOptions>,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::export_query_results(query_id, path, template_id, options, &state).await?)
+}
+
+#[tauri::command]
+pub async fn export_query_results_json(
+    query_id: usize,
+    path: &str,
+    format: JsonFormat,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::export_query_results_json(query_id, path, format, &state).await?)
+}
+
+#[tauri::command]
+pub async fn generate_inserts(
+    query_id: usize,
+    table_name: &str,
+    options: InsertOptions,
+    path: Option<&str>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<String>> {
+    Ok(core::generate_inserts(query_id, table_name, options, path, &state).await?)
+}
+
+#[tauri::command]
+pub async fn list_operations(state: tauri::State<'_, AppState>) -> Result<Vec<OperationInfo>> {
+    Ok(core::list_operations(&state).await?)
+}
+
+#[tauri::command]
+pub async fn cancel_operation(id: Uuid, state: tauri::State<'_, AppState>) -> Result {
+    Ok(core::cancel_operation(id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn list_export_templates(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ExportTemplate>> {
+    Ok(core::list_export_templates(&state).await?)
+}
+
+#[tauri::command]
+pub async fn save_export_template(
+    id: Option<i64>,
+    name: &str,
+    options: ExportOptions,
+    state: tauri::State<'_, AppState>,
+) -> Result<ExportTemplate> {
+    Ok(core::save_export_template(id, name, options, &state).await?)
+}
+
+#[tauri::command]
+pub async fn open_script_file(path: &str, state: tauri::State<'_, AppState>) -> Result<ScriptFile> {
+    Ok(core::open_script_file(path, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_recent_script_files(state: tauri::State<'_, AppState>) -> Result<Vec<String>> {
+    Ok(core::get_recent_script_files(&state).await?)
+}
+
+#[tauri::command]
+pub async fn group_query_results(
+    query_id: usize,
+    group_columns: Vec<String>,
+    aggregates: Vec<Aggregate>,
+    state: tauri::State<'_, AppState>,
+) -> Result<GroupedQuery> {
+    Ok(core::group_query_results(query_id, group_columns, aggregates, &state).await?)
+}
+
+#[tauri::command]
+pub async fn sort_query_results(
+    query_id: usize,
+    keys: Vec<SortKey>,
+    collation: Collation,
+    state: tauri::State<'_, AppState>,
+) -> Result<SortedQuery> {
+    Ok(core::sort_query_results(query_id, keys, collation, &state).await?)
+}
+
+#[tauri::command]
+pub async fn sort_result(
+    query_id: usize,
+    column_index: usize,
+    descending: bool,
+    nulls: Option<NullsOrder>,
+    state: tauri::State<'_, AppState>,
+) -> Result<SortedQuery> {
+    Ok(core::sort_result(
+        query_id,
+        column_index,
+        descending,
+        nulls.unwrap_or_default(),
+        &state,
+    )
+    .await?)
+}
+
+#[tauri::command]
+pub async fn filter_result(
+    query_id: usize,
+    filter: RowFilter,
+    state: tauri::State<'_, AppState>,
+) -> Result<FilteredQuery> {
+    Ok(core::filter_result(query_id, filter, &state).await?)
+}
+
+#[tauri::command]
+pub async fn clear_result_filter(query_id: usize, state: tauri::State<'_, AppState>) -> Result<()> {
+    Ok(core::clear_result_filter(query_id, &state)?)
+}
+
+#[tauri::command]
+pub async fn pin_result(query_id: usize, state: tauri::State<'_, AppState>) -> Result<()> {
+    Ok(core::pin_result(query_id, &state)?)
+}
+
+#[tauri::command]
+pub async fn unpin_result(query_id: usize, state: tauri::State<'_, AppState>) -> Result<()> {
+    Ok(core::unpin_result(query_id, &state)?)
+}
+
+#[tauri::command]
+pub async fn join_results(
+    query_id_a: usize,
+    query_id_b: usize,
+    sql: String,
+    acknowledge_partial: Option<bool>,
+    state: tauri::State<'_, AppState>,
+) -> Result<JoinedQuery> {
+    Ok(core::join_results(
+        query_id_a,
+        query_id_b,
+        sql,
+        acknowledge_partial.unwrap_or(false),
+        &state,
+    )
+    .await?)
+}
+
+#[tauri::command]
+pub async fn diff_results(
+    query_id_a: usize,
+    query_id_b: usize,
+    key_columns: Vec<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<ResultDiff> {
+    Ok(core::diff_results(query_id_a, query_id_b, key_columns, &state).await?)
+}
+
+#[tauri::command]
+pub async fn link_script_directory(
+    path: &str,
+    connection_id: Option<Uuid>,
+    state: tauri::State<'_, AppState>,
+) -> Result<IndexedScriptDirectory> {
+    Ok(core::link_script_directory(path, connection_id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn unlink_script_directory(id: i64, state: tauri::State<'_, AppState>) -> Result {
+    Ok(core::unlink_script_directory(id, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_linked_script_directories(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<IndexedScriptDirectory>> {
+    Ok(core::get_linked_script_directories(&state).await?)
+}
+
+#[tauri::command]
+pub async fn read_linked_script(
+    path: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<ScriptFile> {
+    Ok(core::read_linked_script(path, &state).await?)
+}
+
+#[tauri::command]
+pub async fn get_external_editor_settings(
+    state: tauri::State<'_, AppState>,
+) -> Result<ExternalEditorSettings> {
+    Ok(core::get_external_editor_settings(&state).await?)
+}
+
+#[tauri::command]
+pub async fn set_external_editor_settings(
+    settings: ExternalEditorSettings,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::set_external_editor_settings(settings, &state).await?)
+}
+
+#[tauri::command]
+pub async fn edit_externally(
+    tab_id: String,
+    title: &str,
+    contents: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<ExternalEditSession> {
+    Ok(core::edit_externally(tab_id, title, contents, &state).await?)
+}
+
+#[tauri::command]
+pub async fn sync_external_edit(
+    session_id: Uuid,
+    contents: String,
+    state: tauri::State<'_, AppState>,
+) -> Result {
+    Ok(core::sync_external_edit(session_id, contents, &state).await?)
+}
+
+#[tauri::command]
+pub async fn resolve_external_edit(
+    session_id: Uuid,
+    resolution: ConflictResolution,
+    state: tauri::State<'_, AppState>,
+) -> Result<String> {
+    Ok(core::resolve_external_edit(session_id, resolution, &state).await?)
+}
+
+#[tauri::command]
+pub async fn end_external_edit(session_id: Uuid, state: tauri::State<'_, AppState>) -> Result {
+    Ok(core::end_external_edit(session_id, &state).await?)
+}
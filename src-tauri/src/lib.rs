@@ -3,7 +3,21 @@ mod error;
 mod init;
 mod window;
 
-use pgpad_core::{AppState, Certificates, ConnectionMonitor};
+use pgpad_core::{
+    database::{
+        postgres::notifications::PostgresNotification,
+        reconnect::ConnectionStatusEvent,
+        scheduler::{ScheduledRun, SCHEDULER_TICK},
+        services,
+        trace::TracedStatement,
+        types::PageAvailable,
+        ConnectionHealth,
+    },
+    external_edit::ExternalEditEvent,
+    linked_scripts::LinkedScriptChange,
+    operations::OperationFinished,
+    AppState, Certificates, ConnectionMonitor,
+};
 use tauri::{Emitter, EventTarget, Manager};
 use tokio::sync::mpsc;
 use uuid::Uuid;
@@ -36,10 +50,10 @@ fn handle_dropped_connections(
                 continue;
             };
 
-            if !state.mark_disconnected(connection_id) {
+            let Some(was_connected) = state.mark_disconnected(connection_id) else {
                 log::error!("Connection {connection_id} not found!");
                 continue;
-            }
+            };
 
             if let Err(e) = handle.emit_to(EventTarget::App, "end-of-connection", connection_id) {
                 log::error!("Error emitting end-of-connection event: {e}");
@@ -47,6 +61,173 @@ fn handle_dropped_connections(
             }
 
             log::info!("End-of-connection event emitted for connection {connection_id}");
+
+            // Connections that were disconnected by hand drop too
+            if was_connected {
+                reconnect(handle.clone(), connection_id);
+            }
+        }
+    });
+}
+
+fn handle_connection_health(
+    handle: tauri::AppHandle,
+    mut health_changes: mpsc::UnboundedReceiver<ConnectionHealth>,
+) {
+    tauri::async_runtime::spawn(async move {
+        while let Some(health) = health_changes.recv().await {
+            if let Err(e) = handle.emit_to(EventTarget::App, "connection-health", health) {
+                log::error!("Error emitting connection-health event: {e}");
+            }
+        }
+    });
+}
+
+fn reconnect(handle: tauri::AppHandle, connection_id: Uuid) {
+    tauri::async_runtime::spawn(async move {
+        let state = handle.state::<AppState>();
+        let monitor = handle.state::<ConnectionMonitor>();
+        let certificates = handle.state::<Certificates>();
+
+        let emit_status = |status| {
+            let event = ConnectionStatusEvent {
+                connection_id,
+                status,
+            };
+            if let Err(e) = handle.emit_to(EventTarget::App, "connection-status", event) {
+                log::error!("Error emitting connection-status event: {e}");
+            }
+        };
+        if let Err(e) = services::reconnect_to_database(
+            connection_id,
+            &state,
+            &monitor,
+            &certificates,
+            emit_status,
+        )
+        .await
+        {
+            log::error!("Failed to reconnect {connection_id}: {e}");
+        }
+    });
+}
+
+fn handle_linked_script_changes(
+    handle: tauri::AppHandle,
+    mut changes: mpsc::UnboundedReceiver<LinkedScriptChange>,
+) {
+    tauri::async_runtime::spawn(async move {
+        while let Some(change) = changes.recv().await {
+            if let Err(e) = handle.emit_to(EventTarget::App, "linked-script-changed", change) {
+                log::error!("Error emitting linked-script-changed event: {e}");
+            }
+        }
+    });
+}
+
+fn handle_finished_operations(
+    handle: tauri::AppHandle,
+    mut finished: mpsc::UnboundedReceiver<OperationFinished>,
+) {
+    tauri::async_runtime::spawn(async move {
+        while let Some(operation) = finished.recv().await {
+            if let Err(e) = handle.emit_to(EventTarget::App, "operation-finished", operation) {
+                log::error!("Error emitting operation-finished event: {e}");
+            }
+        }
+    });
+}
+
+fn handle_traced_statements(
+    handle: tauri::AppHandle,
+    mut statements: mpsc::UnboundedReceiver<TracedStatement>,
+) {
+    tauri::async_runtime::spawn(async move {
+        while let Some(statement) = statements.recv().await {
+            if let Err(e) = handle.emit_to(EventTarget::App, "statement-traced", statement) {
+                log::error!("Error emitting statement-traced event: {e}");
+            }
+        }
+    });
+}
+
+fn handle_external_edits(
+    handle: tauri::AppHandle,
+    mut edits: mpsc::UnboundedReceiver<ExternalEditEvent>,
+) {
+    tauri::async_runtime::spawn(async move {
+        while let Some(edit) = edits.recv().await {
+            if let Err(e) = handle.emit_to(EventTarget::App, "external-edit", edit) {
+                log::error!("Error emitting external-edit event: {e}");
+            }
+        }
+    });
+}
+
+fn handle_available_pages(
+    handle: tauri::AppHandle,
+    mut pages: mpsc::UnboundedReceiver<PageAvailable>,
+) {
+    tauri::async_runtime::spawn(async move {
+        while let Some(page) = pages.recv().await {
+            if let Err(e) = handle.emit_to(EventTarget::App, "page-available", page) {
+                log::error!("Error emitting page-available event: {e}");
+            }
+        }
+    });
+}
+
+fn handle_postgres_notifications(
+    handle: tauri::AppHandle,
+    mut notifications: mpsc::UnboundedReceiver<PostgresNotification>,
+) {
+    tauri::async_runtime::spawn(async move {
+        while let Some(notification) = notifications.recv().await {
+            if let Err(e) = handle.emit_to(EventTarget::App, "postgres-notification", notification)
+            {
+                log::error!("Error emitting postgres-notification event: {e}");
+            }
+        }
+    });
+}
+
+/// Runs the scheduled queries that are due, each in a task of its own so that a slow one doesn't hold up the others
+fn run_scheduled_queries(handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut ticks = tokio::time::interval(SCHEDULER_TICK);
+        loop {
+            ticks.tick().await;
+            let due = match services::due_scheduled_queries(&handle.state::<AppState>()).await {
+                Ok(due) => due,
+                Err(e) => {
+                    log::error!("Failed to look up scheduled queries: {e}");
+                    continue;
+                }
+            };
+
+            for scheduled in due {
+                let handle = handle.clone();
+                tauri::async_runtime::spawn(async move {
+                    let (id, name) = (scheduled.id, scheduled.name.clone());
+                    let state = handle.state::<AppState>();
+                    if let Err(e) = services::run_scheduled_query(scheduled, &state).await {
+                        log::error!("Failed to run scheduled query {id} ({name}): {e}");
+                    }
+                });
+            }
+        }
+    });
+}
+
+fn handle_scheduled_runs(
+    handle: tauri::AppHandle,
+    mut runs: mpsc::UnboundedReceiver<ScheduledRun>,
+) {
+    tauri::async_runtime::spawn(async move {
+        while let Some(run) = runs.recv().await {
+            if let Err(e) = handle.emit_to(EventTarget::App, "scheduled-query-alert", run) {
+                log::error!("Error emitting scheduled-query-alert event: {e}");
+            }
         }
     });
 }
@@ -77,41 +258,209 @@ pub fn builder() -> tauri::Builder<tauri::Wry> {
             let handle = app.handle();
             let (connection_monitor, dropped_connections) = ConnectionMonitor::new();
             handle_dropped_connections(handle.clone(), dropped_connections);
+            handle_connection_health(handle.clone(), connection_monitor.subscribe());
             handle.manage(connection_monitor);
+
+            let linked_script_changes = app.state::<AppState>().script_watchers.subscribe();
+            handle_linked_script_changes(handle.clone(), linked_script_changes);
+
+            let finished_operations = app.state::<AppState>().operations.subscribe();
+            handle_finished_operations(handle.clone(), finished_operations);
+
+            let traced_statements = app.state::<AppState>().statement_traces.subscribe();
+            handle_traced_statements(handle.clone(), traced_statements);
+
+            let external_edits = app.state::<AppState>().external_edits.subscribe();
+            handle_external_edits(handle.clone(), external_edits);
+
+            let available_pages = app.state::<AppState>().stmt_manager.subscribe();
+            handle_available_pages(handle.clone(), available_pages);
+
+            let notifications = app.state::<AppState>().notifications.subscribe();
+            handle_postgres_notifications(handle.clone(), notifications);
+
+            let scheduled_runs = app.state::<AppState>().scheduler.subscribe();
+            handle_scheduled_runs(handle.clone(), scheduled_runs);
+            run_scheduled_queries(handle.clone());
             Ok(())
         })
+        .on_page_load(window::file_open::handle_page_load)
+        .on_window_event(window::file_open::handle_window_event)
         .invoke_handler(tauri::generate_handler![
             database_commands::test_connection,
+            database_commands::probe_connections,
             database_commands::add_connection,
             database_commands::update_connection,
+            database_commands::rename_connection_folder,
+            database_commands::export_connections,
+            database_commands::import_connections,
             database_commands::connect_to_database,
             database_commands::disconnect_from_database,
+            database_commands::get_connection_health,
             database_commands::submit_query,
+            database_commands::get_secret_backends,
+            database_commands::set_secret_backends,
+            database_commands::get_credential_backend_status,
+            database_commands::unlock_credential_file,
+            database_commands::get_connection_resource_limits,
+            database_commands::set_connection_resource_limits,
+            database_commands::get_postgres_pool_size,
+            database_commands::set_postgres_pool_size,
+            database_commands::cancel_postgres,
+            database_commands::confirm_run,
+            database_commands::discard_run,
+            database_commands::browse_table,
+            database_commands::get_preflight_settings,
+            database_commands::set_preflight_settings,
+            database_commands::get_environment_policies,
+            database_commands::set_environment_policies,
+            database_commands::get_statement_savepoints,
+            database_commands::set_statement_savepoints,
+            database_commands::get_query_tag_settings,
+            database_commands::set_query_tag_settings,
+            database_commands::get_reconnect_settings,
+            database_commands::set_reconnect_settings,
+            database_commands::get_snapshot_mode,
+            database_commands::set_snapshot_mode,
+            database_commands::refresh_snapshot,
+            database_commands::set_statement_tracing,
+            database_commands::get_statement_trace,
+            database_commands::clear_statement_trace,
+            database_commands::lsp_hover,
+            database_commands::lsp_definition,
+            database_commands::lsp_diagnostics,
+            database_commands::lsp_join_conditions,
             database_commands::is_query_read_only,
+            database_commands::detect_query_parameters,
+            database_commands::explain_query,
+            database_commands::explain_sqlite_query,
             database_commands::wait_until_renderable,
+            database_commands::get_statement_infos,
+            database_commands::cancel_all_queries,
+            database_commands::begin_transaction,
+            database_commands::commit_transaction,
+            database_commands::rollback_transaction,
+            database_commands::get_transaction_state,
             database_commands::fetch_page,
             database_commands::get_query_status,
             database_commands::get_page_count,
+            database_commands::request_more_rows,
+            database_commands::get_timing_breakdown,
+            database_commands::get_row_detail,
+            database_commands::get_column_stats,
+            database_commands::get_table_column_stats,
             database_commands::get_connections,
             database_commands::remove_connection,
+            database_commands::relocate_database_file,
             database_commands::initialize_connections,
+            database_commands::initialize_workspace,
             database_commands::save_query_to_history,
+            database_commands::get_query_history_entry,
             database_commands::get_query_history,
+            database_commands::get_query_history_page,
+            database_commands::get_query_history_count,
+            database_commands::get_history_settings,
+            database_commands::set_history_settings,
+            database_commands::get_result_cache_settings,
+            database_commands::set_result_cache_settings,
+            database_commands::prune_query_history,
             database_commands::get_database_schema,
+            database_commands::get_relationship_graph,
+            database_commands::attach_sqlite_database,
+            database_commands::detach_sqlite_database,
+            database_commands::get_table_columns,
+            database_commands::refresh_table_row_count,
+            database_commands::get_table_ddl,
+            database_commands::set_active_schema,
+            database_commands::suggest_error_fixes,
+            database_commands::get_error_suggestions_enabled,
+            database_commands::set_error_suggestions_enabled,
+            database_commands::get_postgres_replication_info,
+            database_commands::get_postgres_stats,
+            database_commands::get_server_activity,
+            database_commands::terminate_session,
+            database_commands::listen_postgres,
+            database_commands::unlisten_postgres,
+            database_commands::get_active_listens,
+            database_commands::get_connection_messages,
             database_commands::save_script,
             database_commands::update_script,
+            database_commands::get_script_templates,
+            database_commands::save_script_template,
+            database_commands::get_snippets,
+            database_commands::save_snippet,
+            database_commands::delete_snippet,
+            database_commands::get_query_variables,
+            database_commands::save_query_variable,
+            database_commands::delete_query_variable,
+            database_commands::preview_substitution,
+            database_commands::get_scheduled_queries,
+            database_commands::save_scheduled_query,
+            database_commands::delete_scheduled_query,
             database_commands::get_scripts,
+            database_commands::get_scripts_filtered,
+            database_commands::toggle_script_favorite,
             database_commands::delete_script,
-            database_commands::save_session_state,
-            database_commands::get_session_state,
+            database_commands::autosave_buffer,
+            database_commands::get_draft_buffers,
+            database_commands::discard_draft_buffer,
+            database_commands::list_sessions,
+            database_commands::save_session,
+            database_commands::get_session,
+            database_commands::delete_session,
+            database_commands::save_tree_state,
+            database_commands::get_tree_state,
+            database_commands::get_buffer_execution_state,
+            database_commands::get_semantic_search_settings,
+            database_commands::set_semantic_search_settings,
+            database_commands::semantic_search_schema,
             database_commands::format_sql,
+            database_commands::sanitize_sql,
             database_commands::export_page,
+            database_commands::import_csv,
+            database_commands::copy_out_to_file,
+            database_commands::copy_in_from_file,
+            database_commands::export_to_xlsx,
+            database_commands::export_query_results,
+            database_commands::export_query_results_json,
+            database_commands::generate_inserts,
+            database_commands::list_operations,
+            database_commands::cancel_operation,
+            database_commands::list_export_templates,
+            database_commands::save_export_template,
+            database_commands::group_query_results,
+            database_commands::sort_query_results,
+            database_commands::sort_result,
+            database_commands::filter_result,
+            database_commands::clear_result_filter,
+            database_commands::pin_result,
+            database_commands::unpin_result,
+            database_commands::join_results,
+            database_commands::diff_results,
+            database_commands::open_script_file,
+            database_commands::get_recent_script_files,
+            database_commands::link_script_directory,
+            database_commands::unlink_script_directory,
+            database_commands::get_linked_script_directories,
+            database_commands::read_linked_script,
+            database_commands::get_external_editor_settings,
+            database_commands::set_external_editor_settings,
+            database_commands::edit_externally,
+            database_commands::sync_external_edit,
+            database_commands::resolve_external_edit,
+            database_commands::end_external_edit,
             window::commands::minimize_window,
             window::commands::maximize_window,
             window::commands::close_window,
             window::commands::open_sqlite_db,
             window::commands::save_sqlite_db,
+            window::commands::save_xlsx_file,
+            window::commands::save_delimited_file,
             window::commands::pick_ca_cert,
+            window::commands::save_connections_file,
+            window::commands::open_connections_file,
+            window::commands::open_csv_file,
+            window::commands::pick_script_directory,
         ])
 }
 
@@ -119,6 +468,13 @@ pub fn builder() -> tauri::Builder<tauri::Wry> {
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     builder()
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|handle, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Deletes the temporary files of external editing sessions
+                handle.state::<AppState>().external_edits.end_all();
+            }
+            window::file_open::handle_run_event(handle, event);
+        });
 }
@@ -1 +1,2 @@
 pub mod commands;
+pub mod file_open;
@@ -61,6 +61,82 @@ pub async fn save_sqlite_db(app: tauri::AppHandle) -> Result<Option<String>> {
     Ok(chosen_file)
 }
 
+#[tauri::command]
+pub async fn save_xlsx_file(app: tauri::AppHandle) -> Result<Option<String>> {
+    let chosen_file = run_dialog(app, || {
+        AsyncFileDialog::new()
+            .set_title("Export results to Excel")
+            .set_file_name("results.xlsx")
+            .add_filter("Excel workbook", &["xlsx"])
+            .save_file()
+    })
+    .await?
+    .map(|file| file.path().to_string_lossy().to_string());
+
+    Ok(chosen_file)
+}
+
+#[tauri::command]
+pub async fn save_delimited_file(
+    app: tauri::AppHandle,
+    extension: String,
+) -> Result<Option<String>> {
+    let chosen_file = run_dialog(app, move || {
+        AsyncFileDialog::new()
+            .set_title("Export results")
+            .set_file_name(format!("results.{extension}"))
+            .add_filter("Delimited text", &[extension.as_str()])
+            .save_file()
+    })
+    .await?
+    .map(|file| file.path().to_string_lossy().to_string());
+
+    Ok(chosen_file)
+}
+
+#[tauri::command]
+pub async fn save_connections_file(app: tauri::AppHandle) -> Result<Option<String>> {
+    let chosen_file = run_dialog(app, || {
+        AsyncFileDialog::new()
+            .set_title("Export connections")
+            .set_file_name("connections.json")
+            .add_filter("pgpad connections", &["json"])
+            .save_file()
+    })
+    .await?
+    .map(|file| file.path().to_string_lossy().to_string());
+
+    Ok(chosen_file)
+}
+
+#[tauri::command]
+pub async fn open_connections_file(app: tauri::AppHandle) -> Result<Option<String>> {
+    let chosen_file = run_dialog(app, || {
+        AsyncFileDialog::new()
+            .set_title("Import connections")
+            .add_filter("pgpad connections", &["json"])
+            .pick_file()
+    })
+    .await?
+    .map(|file| file.path().to_string_lossy().to_string());
+
+    Ok(chosen_file)
+}
+
+#[tauri::command]
+pub async fn open_csv_file(app: tauri::AppHandle) -> Result<Option<String>> {
+    let chosen_file = run_dialog(app, || {
+        AsyncFileDialog::new()
+            .set_title("Import a CSV file")
+            .add_filter("Delimited text", &["csv", "tsv", "txt"])
+            .pick_file()
+    })
+    .await?
+    .map(|file| file.path().to_string_lossy().to_string());
+
+    Ok(chosen_file)
+}
+
 #[tauri::command]
 pub async fn pick_ca_cert(app: tauri::AppHandle) -> Result<Option<String>> {
     let chosen_file = run_dialog(app, || {
@@ -75,6 +151,19 @@ pub async fn pick_ca_cert(app: tauri::AppHandle) -> Result<Option<String>> {
     Ok(chosen_file)
 }
 
+#[tauri::command]
+pub async fn pick_script_directory(app: tauri::AppHandle) -> Result<Option<String>> {
+    let chosen_dir = run_dialog(app, || {
+        AsyncFileDialog::new()
+            .set_title("Pick a directory of SQL scripts")
+            .pick_folder()
+    })
+    .await?
+    .map(|dir| dir.path().to_string_lossy().to_string());
+
+    Ok(chosen_dir)
+}
+
 async fn run_dialog<F, Fut, T>(app: tauri::AppHandle, make_future: F) -> Result<Option<T>>
 where
     F: FnOnce() -> Fut + Send + 'static,
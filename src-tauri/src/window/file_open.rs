@@ -0,0 +1,82 @@
+//! Opening `.sql` files from outside the app: drag-and-drop onto the window,
+//! or the OS launching pgpad through a file association.
+//!
+//! Every path goes through `open_script_file`, so the frontend always gets the
+//! destructive statement summary before the user can run anything.
+
+use std::{path::PathBuf, sync::Once};
+
+use pgpad_core::{database::services as core, AppState};
+use tauri::{
+    webview::{PageLoadEvent, PageLoadPayload},
+    AppHandle, DragDropEvent, Emitter, Manager, RunEvent, Webview, Window, WindowEvent,
+};
+
+const SCRIPT_FILE_OPENED_EVENT: &str = "script-file-opened";
+
+pub fn handle_window_event(window: &Window, event: &WindowEvent) {
+    if let WindowEvent::DragDrop(DragDropEvent::Drop { paths, .. }) = event {
+        open_script_files(window.app_handle().clone(), paths.clone());
+    }
+}
+
+/// On Windows and Linux, files opened through a file association are passed as arguments.
+/// We only open them once the frontend has loaded, since it wouldn't be listening for the event before that.
+pub fn handle_page_load(webview: &Webview, payload: &PageLoadPayload<'_>) {
+    static OPENED_ARGS: Once = Once::new();
+
+    if payload.event() == PageLoadEvent::Finished {
+        OPENED_ARGS.call_once(|| {
+            let paths = std::env::args_os().skip(1).map(PathBuf::from).collect();
+            open_script_files(webview.app_handle().clone(), paths);
+        });
+    }
+}
+
+/// On macOS, files opened through a file association are delivered as an event instead
+#[allow(unused_variables)]
+pub fn handle_run_event(handle: &AppHandle, event: RunEvent) {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    if let RunEvent::Opened { urls } = event {
+        let paths = urls
+            .into_iter()
+            .filter_map(|url| url.to_file_path().ok())
+            .collect();
+        open_script_files(handle.clone(), paths);
+    }
+}
+
+fn open_script_files(handle: AppHandle, paths: Vec<PathBuf>) {
+    let paths: Vec<_> = paths
+        .into_iter()
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("sql"))
+        })
+        .collect();
+
+    if paths.is_empty() {
+        return;
+    }
+
+    tauri::async_runtime::spawn(async move {
+        let state = handle.state::<AppState>();
+
+        for path in paths {
+            let path = path.to_string_lossy();
+            let script = match core::open_script_file(&path, &state).await {
+                Ok(script) => script,
+                Err(e) => {
+                    log::error!("Failed to open {path}: {e}");
+                    continue;
+                }
+            };
+
+            if let Err(e) = handle.emit(SCRIPT_FILE_OPENED_EVENT, script) {
+                log::error!("Error emitting {SCRIPT_FILE_OPENED_EVENT} event: {e}");
+            }
+        }
+    });
+}
@@ -6,9 +6,20 @@ import {
 	lineNumbers,
 	rectangularSelection,
 	hoverTooltip,
-	tooltips
+	tooltips,
+	gutter,
+	GutterMarker,
+	type Tooltip
 } from '@codemirror/view';
-import { EditorState, type Extension, Compartment, Transaction } from '@codemirror/state';
+import {
+	EditorState,
+	type Extension,
+	Compartment,
+	Transaction,
+	StateEffect,
+	StateField,
+	RangeSet
+} from '@codemirror/state';
 import { PostgreSQL, sql } from '@codemirror/lang-sql';
 import {
 	autocompletion,
@@ -18,6 +29,7 @@ import {
 	type CompletionContext,
 	type CompletionResult,
 	type Completion,
+	snippetCompletion,
 	closeBrackets,
 	closeBracketsKeymap
 } from '@codemirror/autocomplete';
@@ -25,12 +37,18 @@ import { keymap } from '@codemirror/view';
 import { indentWithTab, history, historyKeymap, defaultKeymap } from '@codemirror/commands';
 
 import { mount, unmount } from 'svelte';
-import type { DatabaseSchema } from './commands.svelte';
+import type {
+	DatabaseSchema,
+	DatabaseType,
+	ObjectDefinition,
+	StatementMarker
+} from './commands.svelte';
 import { Commands } from './commands.svelte';
 import { registerEditorThemeCallback, theme } from './stores/theme';
 import { fontSize, fontSizeUtils } from './stores/fontSize';
 import { get } from 'svelte/store';
 import TableTooltip from './components/TableTooltip.svelte';
+import ColumnTooltip from './components/ColumnTooltip.svelte';
 
 interface ExtendedCompletion extends Completion {
 	formattedLabel?: string;
@@ -46,6 +64,7 @@ import {
 import { oneDark } from '@codemirror/theme-one-dark';
 import { highlightActiveLine, highlightActiveLineGutter } from '@codemirror/view';
 import { highlightSelectionMatches } from '@codemirror/search';
+import { linter, type Diagnostic } from '@codemirror/lint';
 
 function createThemeExtensions(theme: 'light' | 'dark') {
 	if (theme === 'light') {
@@ -130,6 +149,29 @@ function createTheme(theme: 'light' | 'dark') {
 			'.cm-gutter': {
 				minHeight: '100%'
 			},
+			'.cm-execution-gutter .cm-gutterElement': {
+				display: 'flex',
+				alignItems: 'center',
+				justifyContent: 'center',
+				width: '8px'
+			},
+			'.cm-execution-marker': {
+				width: '6px',
+				height: '6px',
+				borderRadius: '50%'
+			},
+			'.cm-execution-marker-success': {
+				backgroundColor: '#22c55e'
+			},
+			'.cm-execution-marker-error': {
+				backgroundColor: '#ef4444'
+			},
+			'.cm-execution-marker-running': {
+				backgroundColor: '#eab308'
+			},
+			'.cm-completionIcon-snippet:after': {
+				content: "'{}'"
+			},
 			'.cm-lineNumbers': {
 				minHeight: '100%',
 				margin: '0',
@@ -167,15 +209,19 @@ function generateSchemaCompletions(schema: DatabaseSchema | null): ExtendedCompl
 	}
 
 	const completions: ExtendedCompletion[] = [];
+	// Tables of the schema first in the search path are found without naming it, so they come unqualified and first
+	const activeSchema = schema.active_schema;
+	const unqualified = (tableSchema: string) =>
+		!tableSchema || tableSchema === 'public' || tableSchema === activeSchema;
 
 	for (const table of schema.tables) {
-		const tableName =
-			!table.schema || table.schema === 'public' ? table.name : `${table.schema}.${table.name}`;
+		const tableName = unqualified(table.schema) ? table.name : `${table.schema}.${table.name}`;
 		completions.push({
 			label: tableName,
 			type: 'class',
 			info: `Table: ${tableName} (${table.columns.length} columns)`,
-			detail: 'table'
+			detail: 'table',
+			boost: activeSchema && table.schema === activeSchema ? 1 : undefined
 		});
 	}
 
@@ -190,8 +236,7 @@ function generateSchemaCompletions(schema: DatabaseSchema | null): ExtendedCompl
 
 	// Add qualified column completions (table.column)
 	for (const table of schema.tables) {
-		const tableName =
-			!table.schema || table.schema === 'public' ? table.name : `${table.schema}.${table.name}`;
+		const tableName = unqualified(table.schema) ? table.name : `${table.schema}.${table.name}`;
 		for (const column of table.columns) {
 			completions.push({
 				label: `${tableName}.${column.name}`,
@@ -335,7 +380,93 @@ function formatIdentifierForCompletion(identifier: string): string {
 	return identifier;
 }
 
-function createSqlAutocompletion(schema: DatabaseSchema | null) {
+/** A snippet body with LSP-like tab stops (`$1`, `${1:table}`, `$0` for the end) as a CodeMirror template */
+function snippetTemplate(body: string): string {
+	const stops = [...body.matchAll(/\$\{?(\d+)/g)].map((match) => Number(match[1]));
+	const last = String(Math.max(0, ...stops) + 1);
+	const field = (stop: string) => (stop === '0' ? last : stop);
+
+	return body.replace(
+		/\$\{(\d+):([^}]*)\}|\$(\d+)|([{}])/g,
+		(_, stop: string, placeholder: string, bare: string, brace: string) => {
+			if (brace) return `\\${brace}`;
+			if (bare !== undefined) return `\${${field(bare)}}`;
+			return `\${${field(stop)}:${placeholder}}`;
+		}
+	);
+}
+
+/** Snippets whose prefix matches the word being typed, expanded with their tab stops */
+async function snippetCompletions(
+	context: CompletionContext,
+	getConnectionId?: () => string | null
+): Promise<CompletionResult | null> {
+	const word = context.matchBefore(/[\w*]+/);
+	if (!word) return null;
+
+	const connectionId = getConnectionId?.() ?? null;
+	try {
+		// Without a connection, only those of no connection or database type in particular
+		const snippets = (await Commands.getSnippets(connectionId)).filter(
+			(snippet) => connectionId || (!snippet.connection_id && !snippet.db_type)
+		);
+		return {
+			from: word.from,
+			options: snippets.map((snippet) =>
+				snippetCompletion(snippetTemplate(snippet.body), {
+					label: snippet.prefix,
+					detail: snippet.name,
+					info: snippet.body,
+					type: 'snippet',
+					boost: 50
+				})
+			),
+			validFor: /^[\w*]*$/
+		};
+	} catch (error) {
+		console.error('Failed to load snippets:', error);
+		return null;
+	}
+}
+
+/** Conditions from the foreign keys between the table being joined and those before it, right after `ON` */
+async function joinConditionCompletions(
+	context: CompletionContext,
+	getConnectionId?: () => string | null
+): Promise<CompletionResult | null> {
+	const connectionId = getConnectionId?.();
+	if (!connectionId) return null;
+
+	if (!context.matchBefore(/\bON\s+[\w."`]*/i)) return null;
+
+	try {
+		const conditions = await Commands.lspJoinConditions(
+			connectionId,
+			context.state.doc.toString(),
+			context.pos
+		);
+		if (conditions.length === 0) return null;
+
+		const condition = context.matchBefore(/[\w."`]*/)!;
+		return {
+			from: condition.from,
+			options: conditions.map((label) => ({
+				label,
+				type: 'text',
+				detail: 'join condition',
+				boost: 99
+			}))
+		};
+	} catch (error) {
+		console.error('Failed to suggest join conditions:', error);
+		return null;
+	}
+}
+
+function createSqlAutocompletion(
+	schema: DatabaseSchema | null,
+	getConnectionId?: () => string | null
+) {
 	const cachedCompletions = generateSchemaCompletions(schema);
 
 	const completionsByFirstChar = new Map<string, ExtendedCompletion[]>();
@@ -454,15 +585,138 @@ function createSqlAutocompletion(schema: DatabaseSchema | null) {
 					options: sortedOptions,
 					validFor: searchText.length >= 2 ? /^[\w.]*$/ : /^[\w.]{0,3}$/
 				};
-			}
+			},
+			(context: CompletionContext) => joinConditionCompletions(context, getConnectionId),
+			(context: CompletionContext) => snippetCompletions(context, getConnectionId)
 		]
 	});
 }
 
-function createTableHoverTooltip(schema: DatabaseSchema | null) {
+class ExecutionMarker extends GutterMarker {
+	constructor(readonly marker: StatementMarker) {
+		super();
+	}
+
+	eq(other: ExecutionMarker) {
+		return (
+			other.marker.status === this.marker.status &&
+			other.marker.executed_at === this.marker.executed_at
+		);
+	}
+
+	toDOM() {
+		const dot = document.createElement('div');
+		dot.className = `cm-execution-marker cm-execution-marker-${this.marker.status}`;
+		const when = new Date(this.marker.executed_at).toLocaleTimeString();
+		dot.title =
+			this.marker.status === 'running'
+				? `Running since ${when}`
+				: `${this.marker.status === 'success' ? 'Succeeded' : 'Failed'} at ${when}`;
+		return dot;
+	}
+}
+
+const setExecutionMarkers = StateEffect.define<StatementMarker[]>();
+
+/** Statements run in this tab, marked on their first line. Markers move with edits until the backend's are in. */
+const executionMarkers = StateField.define<RangeSet<GutterMarker>>({
+	create: () => RangeSet.empty,
+	update(markers, tr) {
+		for (const effect of tr.effects) {
+			if (effect.is(setExecutionMarkers)) {
+				const lines = tr.state.doc.lines;
+				return RangeSet.of(
+					effect.value
+						.filter((marker) => marker.start_line <= lines)
+						.map((marker) =>
+							new ExecutionMarker(marker).range(tr.state.doc.line(marker.start_line).from)
+						),
+					true
+				);
+			}
+		}
+		return markers.map(tr.changes);
+	}
+});
+
+function createExecutionGutter() {
+	return [
+		executionMarkers,
+		gutter({
+			class: 'cm-execution-gutter',
+			markers: (view) => view.state.field(executionMarkers)
+		})
+	];
+}
+
+function componentTooltip(
+	from: number,
+	to: number,
+	render: (target: HTMLElement) => ReturnType<typeof mount>
+): Tooltip {
+	return {
+		pos: from,
+		end: to,
+		above: true,
+		create() {
+			const dom = document.createElement('div');
+			const component = render(dom);
+
+			return {
+				dom,
+				destroy() {
+					unmount(component);
+				}
+			};
+		}
+	};
+}
+
+/** Resolves the name under the cursor with the statement's tables and aliases, on the backend */
+async function resolveHover(
+	connectionId: string,
+	schema: DatabaseSchema,
+	view: EditorView,
+	pos: number
+): Promise<Tooltip | null> {
+	const hover = await Commands.lspHover(connectionId, view.state.doc.toString(), pos);
+	if (!hover) return null;
+
+	if (hover.kind === 'column') {
+		return componentTooltip(hover.from, hover.to, (target) =>
+			mount(ColumnTooltip, {
+				target,
+				props: { name: hover.name, candidates: hover.candidates }
+			})
+		);
+	}
+
+	const table = schema.tables.find((t) => t.schema === hover.schema && t.name === hover.name);
+	if (!table) return null;
+	return componentTooltip(hover.from, hover.to, (target) =>
+		mount(TableTooltip, {
+			target,
+			props: { table, alias: hover.alias }
+		})
+	);
+}
+
+function createTableHoverTooltip(
+	schema: DatabaseSchema | null,
+	getConnectionId?: () => string | null
+) {
 	if (!schema) return [];
 
-	return hoverTooltip((view, pos) => {
+	return hoverTooltip(async (view, pos) => {
+		const connectionId = getConnectionId?.();
+		if (connectionId) {
+			try {
+				return await resolveHover(connectionId, schema, view, pos);
+			} catch (error) {
+				console.error('Failed to resolve hover:', error);
+			}
+		}
+
 		const word = view.state.wordAt(pos);
 		if (!word) return null;
 
@@ -473,27 +727,40 @@ function createTableHoverTooltip(schema: DatabaseSchema | null) {
 
 		if (!matchedTable) return null;
 
-		return {
-			pos: word.from,
-			end: word.to,
-			above: true,
-			create() {
-				const dom = document.createElement('div');
+		return componentTooltip(word.from, word.to, (target) =>
+			mount(TableTooltip, {
+				target,
+				props: { table: matchedTable }
+			})
+		);
+	});
+}
 
-				const component = mount(TableTooltip, {
-					target: dom,
-					props: { table: matchedTable }
-				});
+function createSchemaLinter(
+	schema: DatabaseSchema | null,
+	getConnectionId?: () => string | null
+) {
+	if (!schema) return [];
 
-				return {
-					dom,
-					destroy() {
-						unmount(component);
-					}
-				};
+	return linter(
+		async (view): Promise<Diagnostic[]> => {
+			const connectionId = getConnectionId?.();
+			if (!connectionId) return [];
+
+			const query = view.state.doc.toString();
+			try {
+				const diagnostics = await Commands.lspDiagnostics(connectionId, query);
+				// The document may have changed while the diagnostics were computed
+				if (view.state.doc.length !== query.length) return [];
+
+				return diagnostics;
+			} catch (error) {
+				console.error('Failed to compute diagnostics:', error);
+				return [];
 			}
-		};
-	});
+		},
+		{ delay: 750 }
+	);
 }
 
 function createFontSizeTheme(size: number) {
@@ -521,7 +788,7 @@ function editorTooltipSpace(view: EditorView) {
 	};
 }
 
-async function formatSelection(view: EditorView): Promise<boolean> {
+async function formatSelection(view: EditorView, dialect?: DatabaseType): Promise<boolean> {
 	const selection = view.state.selection.main;
 
 	let from: number;
@@ -539,10 +806,13 @@ async function formatSelection(view: EditorView): Promise<boolean> {
 	}
 
 	try {
-		const formatted = await Commands.formatSql(textToFormat);
+		const { text, warnings } = await Commands.formatSql(textToFormat, dialect);
+		for (const warning of warnings) {
+			console.warn(`Line ${warning.line}: ${warning.message}`);
+		}
 
 		view.dispatch({
-			changes: { from, to, insert: formatted }
+			changes: { from, to, insert: text }
 		});
 
 		return true;
@@ -552,6 +822,59 @@ async function formatSelection(view: EditorView): Promise<boolean> {
 	}
 }
 
+/** Jumps to the CTE under the cursor, or opens the definition of the view or routine under it */
+async function goToDefinition(
+	view: EditorView,
+	connectionId: string | null | undefined,
+	onOpenDefinition?: (definition: ObjectDefinition) => void
+): Promise<boolean> {
+	if (!connectionId) return false;
+
+	try {
+		const definition = await Commands.lspDefinition(
+			connectionId,
+			view.state.doc.toString(),
+			view.state.selection.main.head
+		);
+		if (!definition) return false;
+
+		if (definition.kind === 'local') {
+			view.dispatch({
+				selection: { anchor: definition.from, head: definition.to },
+				scrollIntoView: true
+			});
+		} else {
+			onOpenDefinition?.(definition);
+		}
+		return true;
+	} catch (error) {
+		console.error('Failed to go to definition:', error);
+		return false;
+	}
+}
+
+/** Replaces smart quotes, non-breaking spaces and zero-width characters pasted into the SQL */
+async function sanitizeDocument(view: EditorView): Promise<boolean> {
+	try {
+		const { replacements } = await Commands.sanitizeSql(view.state.doc.toString());
+		if (replacements.length === 0) return false;
+
+		// Offsets are into the text we sent, which is the document as long as it wasn't edited meanwhile
+		view.dispatch({
+			changes: replacements.map(({ offset, found, replacement }) => ({
+				from: offset,
+				to: offset + found.length,
+				insert: replacement
+			}))
+		});
+
+		return true;
+	} catch (error) {
+		console.error('Failed to sanitize SQL:', error);
+		return false;
+	}
+}
+
 export interface CreateEditorOptions {
 	container: HTMLElement;
 	value: string;
@@ -560,6 +883,12 @@ export interface CreateEditorOptions {
 	onExecuteSelection?: (selectedText: string) => void;
 	disabled?: boolean;
 	schema?: DatabaseSchema | null;
+	/** The dialect statements are formatted in, generic SQL if there's none */
+	getDialect?: () => DatabaseType | undefined;
+	/** The connection hovered names are resolved with, by table names only if there's none */
+	getConnectionId?: () => string | null;
+	/** Called with the definition of a view or routine to open, on go-to-definition */
+	onOpenDefinition?: (definition: ObjectDefinition) => void;
 }
 
 export function createEditorInstance(options: CreateEditorOptions) {
@@ -570,7 +899,10 @@ export function createEditorInstance(options: CreateEditorOptions) {
 		onExecute,
 		onExecuteSelection,
 		disabled = false,
-		schema = null
+		schema = null,
+		getDialect,
+		getConnectionId,
+		onOpenDefinition
 	} = options;
 
 	// TODO(vini): is this right?
@@ -589,6 +921,7 @@ export function createEditorInstance(options: CreateEditorOptions) {
 	const schemaCompartment = new Compartment();
 	const fontSizeCompartment = new Compartment();
 	const hoverTooltipCompartment = new Compartment();
+	const diagnosticsCompartment = new Compartment();
 
 	const extensions: Extension[] = [
 		keymap.of([
@@ -622,7 +955,24 @@ export function createEditorInstance(options: CreateEditorOptions) {
 				key: 'Ctrl-Shift-f',
 				mac: 'Cmd-Shift-f',
 				run: (view: EditorView) => {
-					formatSelection(view);
+					formatSelection(view, getDialect?.());
+					return true;
+				}
+			},
+			// F12: Go to the definition of the CTE, view or routine under the cursor
+			{
+				key: 'F12',
+				run: (view: EditorView) => {
+					goToDefinition(view, getConnectionId?.(), onOpenDefinition);
+					return true;
+				}
+			},
+			// Ctrl+Alt+F: Fix smart quotes and other characters picked up when pasting
+			{
+				key: 'Ctrl-Alt-f',
+				mac: 'Cmd-Alt-f',
+				run: (view: EditorView) => {
+					sanitizeDocument(view);
 					return true;
 				}
 			},
@@ -675,6 +1025,7 @@ export function createEditorInstance(options: CreateEditorOptions) {
 		autocompletion(),
 		highlightSpecialChars(),
 		rectangularSelection(),
+		createExecutionGutter(),
 		foldGutter(),
 		highlightActiveLine(),
 		highlightActiveLineGutter(),
@@ -684,8 +1035,9 @@ export function createEditorInstance(options: CreateEditorOptions) {
 		}),
 		keymap.of([...closeBracketsKeymap, ...defaultKeymap, ...historyKeymap]),
 		sql({ dialect: PostgreSQL }),
-		schemaCompartment.of(createSqlAutocompletion(currentSchema)),
-		hoverTooltipCompartment.of(createTableHoverTooltip(currentSchema)),
+		schemaCompartment.of(createSqlAutocompletion(currentSchema, getConnectionId)),
+		hoverTooltipCompartment.of(createTableHoverTooltip(currentSchema, getConnectionId)),
+		diagnosticsCompartment.of(createSchemaLinter(currentSchema, getConnectionId)),
 		EditorView.lineWrapping,
 		EditorView.updateListener.of((update) => {
 			if (update.docChanged) {
@@ -795,12 +1147,19 @@ export function createEditorInstance(options: CreateEditorOptions) {
 		return null;
 	};
 
+	const setExecutionState = (markers: StatementMarker[]) => {
+		view.dispatch({ effects: setExecutionMarkers.of(markers) });
+	};
+
 	const updateSchema = (newSchema: DatabaseSchema | null) => {
 		currentSchema = newSchema;
 		view.dispatch({
 			effects: [
-				schemaCompartment.reconfigure(createSqlAutocompletion(currentSchema)),
-				hoverTooltipCompartment.reconfigure(createTableHoverTooltip(currentSchema))
+				schemaCompartment.reconfigure(createSqlAutocompletion(currentSchema, getConnectionId)),
+				hoverTooltipCompartment.reconfigure(
+					createTableHoverTooltip(currentSchema, getConnectionId)
+				),
+				diagnosticsCompartment.reconfigure(createSchemaLinter(currentSchema, getConnectionId))
 			]
 		});
 	};
@@ -814,6 +1173,7 @@ export function createEditorInstance(options: CreateEditorOptions) {
 		getExecutableText,
 		getSelectedText,
 		updateSchema,
+		setExecutionState,
 		saveState,
 		restoreState,
 		zoomIn,
@@ -6,7 +6,16 @@ export type Json = string | number | boolean | null | Json[] | { [key: string]:
 export type Row = Json[];
 
 export type QueryId = number;
-export type QueryStatus = 'Pending' | 'Running' | 'Completed' | 'Error';
+/** Streaming: still running, with some pages already readable */
+export type QueryStatus = 'Pending' | 'Running' | 'Streaming' | 'Completed' | 'Error';
+
+/** Payload of the page-available event, sent for new pages of results. Pages that come in quick succession are
+ * reported together: the new ones are those from `first_new_page` on. */
+export interface PageAvailable {
+	query_id: QueryId;
+	first_new_page: number;
+	page_count: number;
+}
 export type Page = Json[][];
 
 export interface QuerySnapshot {
@@ -16,20 +25,287 @@ export interface QuerySnapshot {
 	affected_rows: number | null;
 	columns: string[] | null;
 	error: string | null;
+	ceilings_hit: ResourceCeiling[];
+	column_lineage: ColumnLineage[] | null;
+	timings: TimingBreakdown | null;
+	/** What became of the statement's savepoint, if it ran in one */
+	savepoint: SavepointOutcome | null;
+	/** Which statement of the submitted script this is, null for derived results */
+	statement: StatementInfo | null;
+}
+
+export type StatementKind = 'select' | 'dml' | 'ddl' | 'other';
+
+export interface ExplainOptions {
+	/** Runs the statement, to report actual timings and rows */
+	analyze: boolean;
+	/** Lets ANALYZE run statements that modify data, in a transaction that's rolled back */
+	analyze_writes?: boolean;
+}
+
+export interface PlanNode {
+	node_type: string;
+	relation_name: string | null;
+	startup_cost: number;
+	total_cost: number;
+	plan_rows: number;
+	plan_width: number;
+	/** Only with ANALYZE, per loop */
+	actual_rows: number | null;
+	actual_loops: number | null;
+	actual_startup_time_ms: number | null;
+	actual_total_time_ms: number | null;
+	/** The other fields of the node, as named by Postgres */
+	details: Record<string, Json>;
+	children: PlanNode[];
+}
+
+export interface ExplainedPlan {
+	statement: string;
+	analyzed: boolean;
+	rolled_back: boolean;
+	planning_time_ms: number | null;
+	execution_time_ms: number | null;
+	plan: PlanNode;
+}
+
+/** A node of an SQLite query plan, from `EXPLAIN QUERY PLAN` */
+export interface QueryPlanNode {
+	id: number;
+	/** e.g. "SCAN users" */
+	detail: string;
+	children: QueryPlanNode[];
+}
+
+export interface QueryPlan {
+	statement: string;
+	nodes: QueryPlanNode[];
+}
+
+export interface StatementInfo {
+	query_id: QueryId;
+	ordinal: number;
+	/** Byte offsets into the submitted script, without the semicolon */
+	start: number;
+	end: number;
+	kind: StatementKind;
+	/** Whether some of the result's pages were moved to disk, which makes paging through them slower */
+	spilled: boolean;
+	metrics: StatementMetrics;
+	/** Notices and warnings the server sent while the statement ran */
+	messages: ServerMessage[];
+}
+
+/** A message the server sent besides a statement's results, e.g. from Postgres' `RAISE NOTICE` */
+export interface ServerMessage {
+	/** e.g. `NOTICE` or `WARNING` */
+	severity: string;
+	text: string;
+}
+
+export interface StatementMetrics {
+	/** From the start of execution until the first page of rows */
+	first_row_ms: number | null;
+	/** From the start of execution until now, or until the end once it's over */
+	elapsed_ms: number | null;
+	rows_fetched: number;
+	/** Size of the rows fetched, as serialized into pages */
+	bytes_fetched: number;
+	/** e.g. `UPDATE 42`, once a Postgres statement is over */
+	command_tag: string | null;
+}
+
+/** `rolled_back` means the statement failed, but the transaction it ran in is still usable */
+export type SavepointOutcome = 'released' | 'rolled_back';
+
+/** Where the time of a statement went, in milliseconds */
+export interface TimingBreakdown {
+	parse_ms: number;
+	queue_ms: number;
+	execute_ms: number;
+	fetch_ms: number;
+	/** Part of execute_ms and fetch_ms */
+	serialize_ms: number;
+	total_ms: number;
+}
+
+/** One column of a result row, see Commands.getRowDetail */
+export interface RowDetailField {
+	column: string;
+	/** As named by the database, null if unknown */
+	type: string | null;
+	value: Json;
+	/** Always false for now, values are kept whole */
+	truncated: boolean;
+	/** The table the column was read from, if it's a plain column reference */
+	origin_table: string | null;
+}
+
+/** See Commands.getColumnStats */
+export interface ColumnStats {
+	column: string;
+	column_type: string | null;
+	kind: 'numeric' | 'text' | 'temporal' | 'boolean' | 'other';
+	row_count: number;
+	null_count: number;
+	distinct_count: number;
+	/** Whether there were too many distinct values to count them all */
+	distinct_count_capped: boolean;
+	min: Json | null;
+	max: Json | null;
+	/** Numeric columns only */
+	avg: number | null;
+	/** Text columns only */
+	shortest: string | null;
+	longest: string | null;
+	/** Whether the query was still running, or its result truncated */
+	partial: boolean;
+}
+
+export interface SourceColumn {
+	table: string | null;
+	column: string;
+}
+
+export type ColumnLineage =
+	| ({ kind: 'column' } & SourceColumn)
+	| { kind: 'computed'; columns: SourceColumn[] }
+	| { kind: 'unknown' };
+
+export type ResourceCeiling = 'statement_cpu_budget' | 'soft_heap_limit';
+
+export interface ResourceLimits {
+	soft_heap_limit_bytes: number | null;
+	statement_cpu_budget_ms: number | null;
+	/** Reported as a timeout rather than as a ceiling. Postgres only honors the connection's default. */
+	query_timeout_ms: number | null;
+	/** How many pages Postgres queries fetch ahead of those read */
+	fetch_ahead_pages?: number | null;
+}
+
+export type SecretBackend =
+	| { type: 'keyring' }
+	| { type: 'env'; variable: string | null }
+	| { type: 'command'; command: string; timeout_ms: number | null };
+
+export interface CredentialBackendStatus {
+	/** Where passwords typed into pgpad go: the keyring, or the encrypted file when the keyring fails */
+	active: 'keyring' | 'file';
+	keyring_available: boolean;
+	keyring_error: string | null;
+	file_exists: boolean;
+	file_unlocked: boolean;
+}
+
+export interface SubmitOptions {
+	resource_limits?: Partial<ResourceLimits>;
+	/** The script the query comes from, for query tags */
+	script_name?: string | null;
+	/** The editor tab the query comes from, whose statements get marked as run */
+	tab_id?: string | null;
+	/** Values of the query's placeholders, by name, see `detectQueryParameters` */
+	params?: Record<string, unknown>;
+}
+
+/** A statement of an editor tab that was run, with how its latest run went */
+export interface StatementMarker {
+	/** 1-based */
+	start_line: number;
+	end_line: number;
+	status: 'running' | 'success' | 'error';
+	/** Unix timestamp, in milliseconds */
+	executed_at: number;
+}
+
+export type SshAuth =
+	| { type: 'agent' }
+	| { type: 'key_file'; path: string }
+	/** The password is kept in the keyring, so it's only sent, never received */
+	| { type: 'password'; password?: string | null };
+
+export interface SshTunnelConfig {
+	host: string;
+	/** 22 when not set */
+	port?: number | null;
+	username: string;
+	auth: SshAuth;
 }
 
 export type ConnectionConfig =
-	| { Postgres: { connection_string: string; ca_cert_path?: string | null } }
-	| { SQLite: { db_path: string } };
+	| {
+			Postgres: {
+				connection_string: string;
+				ca_cert_path?: string | null;
+				ssh_tunnel?: SshTunnelConfig | null;
+				/** Put first in the search path once connected */
+				default_schema?: string | null;
+			};
+	  }
+	| { SQLite: { db_path: string; home_relative_path?: string | null } }
+	| {
+			MySQL: {
+				connection_string: string;
+				ca_cert_path?: string | null;
+				ssh_tunnel?: SshTunnelConfig | null;
+			};
+	  };
 
 export type Permissions = 'read_write' | 'protected_write' | 'read_only';
 
 export interface ConnectionInfo {
 	id: string;
 	name: string;
+	/** e.g. `acme/staging`, nested with `/`. Connections come sorted by folder, then by name. */
+	folder?: string | null;
+	environment?: Environment | null;
+	/** A hex color, e.g. `#d43f3a`, that the connection is marked with */
+	color?: string | null;
 	connected: boolean;
 	permissions: Permissions;
 	config: ConnectionConfig;
+	/** Set while the connection is opened as a snapshot of its SQLite file */
+	snapshot?: SnapshotInfo | null;
+	/** Whether the file of a SQLite connection that was opened before can't be found anymore */
+	file_missing?: boolean;
+	/** What the server of a connected Postgres connection supports */
+	capabilities?: ServerCapabilities | null;
+	/** The schema first in the search path of a connected Postgres connection, if one was picked */
+	active_schema?: string | null;
+}
+
+/** What happened to an entry of an imported connection file */
+export type ImportedConnection = { name: string } & (
+	| { status: 'created'; connection_id: string }
+	| { status: 'skipped'; reason: string }
+	| { status: 'error'; message: string }
+);
+
+export type ServerFlavor = 'postgres' | 'cockroach_db' | 'neon' | 'alloy_db';
+
+export interface ServerCapabilities {
+	flavor: ServerFlavor;
+	version: string;
+	listen_notify: boolean;
+	copy_to_stdout: boolean;
+	replication: boolean;
+	table_inheritance: boolean;
+}
+
+export interface SnapshotInfo {
+	source_path: string;
+	/** When the source was last modified before the copy, in milliseconds since the epoch */
+	source_modified_ms: number;
+	source_size: number;
+	/** Unix timestamp */
+	copied_at: number;
+	local_path: string;
+	size_bytes: number;
+}
+
+export interface SnapshotRefresh {
+	/** Whether the source had changed, and so was copied again */
+	refreshed: boolean;
+	snapshot: SnapshotInfo;
 }
 
 export interface QueryHistoryEntry {
@@ -41,6 +317,71 @@ export interface QueryHistoryEntry {
 	status: string;
 	row_count: number;
 	error_message: string | null;
+	timings: TimingBreakdown[] | null;
+	/** Times the query was run, recent reruns updating the entry rather than adding another */
+	run_count: number;
+	/** The plan of a run explained with ANALYZE, as JSON. Only loaded by `getQueryHistoryEntry`. */
+	plan_json?: string | null;
+	/** Whether the entry has a plan, even when it isn't loaded */
+	has_plan?: boolean;
+	/** Whether the query was run by a schedule rather than from the editor */
+	scheduled?: boolean;
+}
+
+export interface TransactionInfo {
+	/** The editor tab the transaction was opened from */
+	tab_id: string;
+	/** In milliseconds since the epoch */
+	started_at: number;
+}
+
+export interface HistorySettings {
+	/** Whether running a query again updates its recent entry rather than adding another */
+	dedupe: boolean;
+	/** Entries kept across all connections, the newest ones */
+	max_entries: number | null;
+	/** Entries older than this are pruned */
+	max_age_days: number | null;
+}
+
+export interface ResultCacheSettings {
+	/** Bytes of pages a result keeps in memory before moving the oldest ones to disk. Null keeps them all. */
+	spill_threshold_bytes: number | null;
+}
+
+export interface HistoryPruneReport {
+	deleted_entries: number;
+	/** How much smaller the storage database got */
+	reclaimed_bytes: number;
+}
+
+export interface HistoryFilters {
+	/** Case-insensitive text the query must contain */
+	text?: string | null;
+	status?: string | null;
+	/** Only entries with a plan */
+	with_plan?: boolean;
+	/** Only scheduled runs, or only the others */
+	scheduled?: boolean | null;
+}
+
+export interface ErrorSuggestion {
+	kind: 'did_you_mean' | 'other_table' | 'quote_identifier';
+	message: string;
+	/** What to write instead of the missing identifier */
+	replacement: string;
+}
+
+/** Where a page of history ends, as of when it was read */
+export interface HistoryCursor {
+	executed_at: number;
+	id: number;
+}
+
+export interface QueryHistoryPage {
+	entries: QueryHistoryEntry[];
+	/** Pass as `before` to get the next page, null if there are no older entries */
+	next_cursor: HistoryCursor | null;
 }
 
 export interface ColumnInfo {
@@ -51,15 +392,208 @@ export interface ColumnInfo {
 }
 
 export interface TableInfo {
+	/** The same for the same table across refreshes */
+	id: string;
 	name: string;
 	schema: string;
 	columns: ColumnInfo[];
+	/** Changes whenever the table's columns or foreign keys do */
+	content_hash: string;
+	/** Whether the columns are yet to be loaded with `getTableColumns`, `columns` is empty until then */
+	columns_pending?: boolean;
+	foreign_keys?: ForeignKey[];
+	/** About how many rows the table has, if the database can tell cheaply. Exact after `refreshTableRowCount`. */
+	row_estimate?: number | null;
+	/** The columns of the primary key, in the key's order. Empty if the table has none. */
+	primary_key?: string[];
+}
+
+export interface ForeignKey {
+	/** Paired with `referenced_columns` by position */
+	columns: string[];
+	referenced_schema: string;
+	referenced_table: string;
+	referenced_columns: string[];
+	/** The name of the constraint, null for SQLite */
+	name?: string | null;
+	on_delete?: ReferentialAction;
+	on_update?: ReferentialAction;
+}
+
+export type ReferentialAction = 'no_action' | 'restrict' | 'cascade' | 'set_null' | 'set_default';
+
+/** The tables of a connection and the foreign keys between them, for an entity-relationship diagram */
+export interface RelationshipGraph {
+	nodes: GraphNode[];
+	edges: GraphEdge[];
+	/** The `hash` of the schema it was built from */
+	schema_hash: string;
+}
+
+/** A table. Partitioned tables are a single node. */
+export interface GraphNode {
+	/** The `id` of the table */
+	id: string;
+	schema: string;
+	table: string;
+	primary_key: string[];
+	row_estimate: number | null;
+}
+
+/** A foreign key, from the table that has it to the table it references */
+export interface GraphEdge {
+	name: string | null;
+	source: EdgeEnd;
+	target: EdgeEnd;
+	on_delete: ReferentialAction;
+	on_update: ReferentialAction;
+}
+
+export interface EdgeEnd {
+	/** The `id` of the table's node */
+	node: string;
+	schema: string;
+	table: string;
+	/** Paired with the columns of the other end by position */
+	columns: string[];
+}
+
+/** Tables are sorted by schema then name, so unchanged schemas come back the same */
+export interface BrowsePage {
+	/** Its results are read like those of any other query */
+	query_id: QueryId;
+	query: string;
+	/** The columns the rows are ordered by */
+	order_by: string[];
+	/** Only counted for the first page */
+	total_rows: number | null;
+	/** Whether `total_rows` is the planner's estimate */
+	total_is_estimate: boolean;
 }
 
 export interface DatabaseSchema {
 	tables: TableInfo[];
 	schemas: string[];
 	unique_columns: string[];
+	/** When the schema was introspected, in milliseconds since the epoch */
+	generated_at: number;
+	/** Changes whenever any table does */
+	hash: string;
+	/** The schema first in the search path, whose tables are found without naming it */
+	active_schema?: string | null;
+}
+
+export interface ReplicationSlot {
+	name: string;
+	plugin: string | null;
+	slot_type: string;
+	active: boolean;
+	restart_lsn: string | null;
+	confirmed_flush_lsn: string | null;
+	retained_wal_bytes: number | null;
+}
+
+export interface Publication {
+	name: string;
+	all_tables: boolean;
+	tables: string[];
+}
+
+export interface Subscription {
+	name: string;
+	enabled: boolean;
+	publications: string[];
+	received_lsn: string | null;
+	latest_end_lsn: string | null;
+	last_msg_receipt_time: string | null;
+}
+
+export interface ReplicationInfo {
+	slots: ReplicationSlot[] | null;
+	publications: Publication[] | null;
+	subscriptions: Subscription[] | null;
+	warnings: string[];
+}
+
+export interface TableStats {
+	schema: string;
+	name: string;
+	/** With its indexes and TOAST data */
+	total_bytes: number;
+	seq_scan: number;
+	idx_scan: number | null;
+	live_rows: number;
+	dead_rows: number;
+}
+
+export interface IndexBloat {
+	schema: string;
+	index: string;
+	table: string;
+	bytes: number;
+	/** About the size the index would have if rebuilt */
+	expected_bytes: number;
+}
+
+export interface ReplicaLag {
+	application_name: string;
+	client_addr: string | null;
+	state: string | null;
+	replay_lag_bytes: number | null;
+}
+
+/** Each section is null if it couldn't be loaded, with the reason in `warnings` */
+export interface PostgresStats {
+	taken_at: number;
+	database_size_bytes: number | null;
+	cache_hit_ratio: number | null;
+	largest_tables: TableStats[] | null;
+	most_seq_scanned_tables: TableStats[] | null;
+	index_bloat_candidates: IndexBloat[] | null;
+	connections: { count: number; max_connections: number } | null;
+	replication_lag: {
+		in_recovery: boolean;
+		replay_delay_seconds: number | null;
+		replicas: ReplicaLag[];
+	} | null;
+	warnings: string[];
+}
+
+export interface ActivityFilters {
+	/** e.g. `active` or `idle in transaction` */
+	state?: string | null;
+	offset?: number;
+	limit?: number | null;
+}
+
+export interface ServerSession {
+	pid: number;
+	user: string | null;
+	database: string | null;
+	application_name: string | null;
+	state: string | null;
+	query: string | null;
+	wait_event_type: string | null;
+	wait_event: string | null;
+	xact_start: string | null;
+	query_start: string | null;
+	/** One of the connection's own clients, only closed when forced */
+	own: boolean;
+}
+
+export interface ServerActivity {
+	sessions: ServerSession[];
+	/** Sessions matching the filters, across all pages */
+	total: number;
+}
+
+/** Payload of the postgres-notification event, sent for notifications on channels listened on */
+export interface PostgresNotification {
+	connection_id: string;
+	channel: string;
+	payload: string;
+	/** Of the server process that sent the notification */
+	pid: number;
 }
 
 export interface Script {
@@ -68,23 +602,638 @@ export interface Script {
 	description: string | null;
 	query_text: string;
 	connection_id: string | null;
-	tags: string | null;
+	tags: string[];
 	created_at: number;
 	updated_at: number;
 	favorite: boolean;
 }
 
+export type AggregateFn = 'count' | 'sum' | 'avg' | 'min' | 'max';
+
+export interface Aggregate {
+	/** `*` is accepted for `count` */
+	column: string;
+	fn: AggregateFn;
+}
+
+/** How text is compared when sorting */
+export interface Collation {
+	/** Byte order, overriding the other options */
+	binary: boolean;
+	case_sensitive: boolean;
+	/** "file10" after "file2" */
+	numeric: boolean;
+}
+
+export const DEFAULT_COLLATION: Collation = {
+	binary: false,
+	case_sensitive: false,
+	numeric: false
+};
+
+export interface SortKey {
+	column: string;
+	descending?: boolean;
+	/** 'default' is like Postgres: last when ascending, first when descending */
+	nulls?: 'default' | 'first' | 'last';
+}
+
+export interface SortedQuery {
+	query_id: QueryId;
+	row_count: number;
+}
+
+/** Which rows of a result to keep. Columns are referred to by their index. */
+export type RowFilter =
+	| { op: 'and'; filters: RowFilter[] }
+	| { op: 'or'; filters: RowFilter[] }
+	| { op: 'equals'; column: number; value: string }
+	| { op: 'contains'; column: number; text: string; case_sensitive?: boolean }
+	| { op: 'regex'; column: number; pattern: string; case_sensitive?: boolean }
+	| { op: 'is_null'; column: number }
+	| { op: 'not_null'; column: number }
+	| { op: 'range'; column: number; min?: number; max?: number };
+
+export interface FilteredQuery {
+	query_id: QueryId;
+	row_count: number;
+	source_row_count: number;
+}
+
+export interface GroupedQuery {
+	query_id: QueryId;
+	group_count: number;
+	truncated_groups: boolean;
+}
+
+export interface JoinedQuery {
+	query_id: QueryId;
+	row_count: number;
+}
+
+/**
+ * Rows of two results paired on key columns. The diff's first column, `_change`, is added, removed, changed,
+ * duplicate_in_a or duplicate_in_b, and changed columns are `{ old, new }`.
+ */
+export interface ResultDiff {
+	/** Null if the results couldn't be compared */
+	query_id: QueryId | null;
+	added: number;
+	removed: number;
+	changed: number;
+	unchanged: number;
+	/** Keys found more than once in either result, whose rows are listed rather than paired */
+	duplicate_keys: number;
+	/** Set if the results don't have the same columns */
+	incomparable: { only_in_a: string[]; only_in_b: string[] } | null;
+}
+
+export interface LinkedScript {
+	path: string;
+	relative_path: string;
+	name: string;
+	modified_at: number | null;
+}
+
+export interface IndexedScriptDirectory {
+	id: number;
+	path: string;
+	connection_id: string | null;
+	created_at: number;
+	missing: boolean;
+	watched: boolean;
+	scripts: LinkedScript[];
+}
+
+export interface LinkedScriptChange {
+	directory_id: number;
+	path: string;
+	kind: 'changed' | 'removed';
+}
+
+export interface DestructiveStatements {
+	kind: string;
+	count: number;
+}
+
+export interface ScriptFile {
+	path: string;
+	content: string;
+	encoding: 'utf8' | 'utf16_le' | 'utf16_be' | 'latin1';
+	size_bytes: number;
+	read_only_preview: boolean;
+	destructive_statements: DestructiveStatements[] | null;
+}
+
+export interface TracedStatement {
+	connection_id: string;
+	/** Unix timestamp, in milliseconds */
+	timestamp: number;
+	source: 'user' | 'introspection';
+	sql: string;
+	duration_ms: number;
+	/** Rows returned or affected, null if the statement failed */
+	rows: number | null;
+}
+
+export type ConflictStrategy = 'external_wins' | 'pgpad_wins' | 'prompt';
+
+export interface ExternalEditorSettings {
+	/** e.g. `code --wait`. The OS default handler is used if unset. */
+	command: string | null;
+	conflict_strategy: ConflictStrategy;
+}
+
+export interface ExternalEditSession {
+	id: string;
+	tab_id: string;
+	path: string;
+}
+
+export type ExternalEditEvent = {
+	session_id: string;
+	tab_id: string;
+	contents: string;
+	/** `conflict` if the tab was edited too, and the user has to pick a version */
+	kind: 'changed' | 'conflict';
+};
+
+export type Environment = 'dev' | 'staging' | 'prod' | 'custom';
+
+export interface EnvironmentPolicy {
+	/** Whether schema changes, and UPDATE or DELETE statements without a WHERE clause, must be confirmed */
+	confirm_destructive: boolean;
+}
+
+/** Connections without an environment aren't held back by any policy */
+export type EnvironmentPolicies = Record<Environment, EnvironmentPolicy>;
+
+export interface PreflightSettings {
+	production: boolean;
+	estimate_cost: boolean;
+	max_total_cost: number;
+	max_estimated_rows: number;
+	timeout_ms: number;
+	warn_suspicious_characters: boolean;
+}
+
+export const TREE_STATE_VERSION = 1;
+
+/** The state of a connection's Items panel, restored when it's reopened */
+export interface TreeState {
+	version: number;
+	/** Path of each expanded node from the root, e.g. `['public', 'users']` */
+	expanded: string[][];
+	scroll_top: number;
+	/** The kinds of objects shown, empty if every kind is */
+	filters: string[];
+}
+
+export interface ReconnectSettings {
+	enabled: boolean;
+	max_retries: number;
+	/** Before the first attempt, doubling for each one after */
+	backoff_ms: number;
+	max_backoff_ms: number;
+}
+
+/** Payload of the connection-status event, sent while a dropped connection is reconnected */
+export type ConnectionStatusEvent = { connection_id: string } & (
+	| { status: 'reconnecting'; attempt: number; max_retries: number; delay_ms: number }
+	| { status: 'reconnected' }
+	| { status: 'gave-up'; error: string | null }
+);
+
+/** How the pings of a connection went, sent in the connection-health event when its status changes */
+export interface ConnectionHealth {
+	connection_id: string;
+	/** Slow if recent pings took over 500ms on average, failing if the last one failed */
+	status: 'healthy' | 'slow' | 'failing';
+	/** Unix timestamp in milliseconds */
+	last_ping_at: number;
+	/** Null if the last ping failed */
+	last_latency_ms: number | null;
+	consecutive_failures: number;
+	/** Of the last successful pings, oldest first */
+	recent_latencies_ms: number[];
+	last_error: string | null;
+}
+
+export interface QueryTagSettings {
+	enabled: boolean;
+	/** `{user}`, `{connection}`, `{script}`, `{run_id}` and `{app_version}` are replaced with their values */
+	template: string;
+}
+
+export interface SemanticSearchSettings {
+	enabled: boolean;
+	/** Word vectors in GloVe's text format or fastText's `.vec` one */
+	model_path: string | null;
+}
+
+export interface FieldMatch {
+	field: 'name' | 'schema' | 'column';
+	value: string;
+	/** Byte ranges of the matching words in `value` */
+	ranges: [number, number][];
+}
+
+export interface SchemaSearchHit {
+	schema: string;
+	name: string;
+	score: number;
+	matches: FieldMatch[];
+}
+
+export interface SchemaSearchResults {
+	/** `fuzzy` when semantic search isn't available, and tables were matched by name */
+	method: 'semantic' | 'fuzzy';
+	hits: SchemaSearchHit[];
+}
+
+export interface CostEstimate {
+	statement: string;
+	total_cost: number;
+	estimated_rows: number;
+}
+
+export interface SuspiciousCharacter {
+	/** Position in the original text, in UTF-16 code units like editor positions */
+	offset: number;
+	found: string;
+	/** e.g. "U+2019" */
+	code_point: string;
+	replacement: string;
+	context: 'code' | 'literal' | 'comment';
+}
+
+/** A column a hovered name can refer to */
+export interface ColumnCandidate {
+	schema: string;
+	table: string;
+	alias: string | null;
+	data_type: string;
+	is_nullable: boolean;
+	default_value: string | null;
+}
+
+/** What's under the cursor, with its range in the editor */
+export type Hover = { from: number; to: number } & (
+	| {
+			kind: 'table';
+			schema: string;
+			name: string;
+			alias: string | null;
+			column_count: number;
+			columns_pending: boolean;
+	  }
+	| {
+			kind: 'column';
+			name: string;
+			/** More than one if the name is ambiguous */
+			candidates: ColumnCandidate[];
+	  }
+);
+
+/** An unknown table or column, or an ambiguous column name, by its range in the editor */
+export interface SqlDiagnostic {
+	from: number;
+	to: number;
+	severity: 'error' | 'warning';
+	message: string;
+}
+
+/** A view or routine whose definition was fetched for go-to-definition */
+export interface ObjectDefinition {
+	schema: string;
+	name: string;
+	object_type: 'view' | 'materialized_view' | 'function' | 'procedure' | 'table' | 'trigger';
+	definition: string;
+}
+
+/** Where a name is defined: a CTE of the document, by its range in the editor, or an object of the database */
+export type Definition =
+	| { kind: 'local'; from: number; to: number }
+	| ({ kind: 'object' } & ObjectDefinition);
+
+export interface FormatSettings {
+	keywordCase: 'upper' | 'lower' | 'preserve';
+	/** Spaces per indentation level, unless tabs are used */
+	indentWidth: number;
+	useTabs: boolean;
+	commaStyle: 'trailing' | 'leading';
+}
+
+/** Why a statement was left as it is by the formatter */
+export interface FormatWarning {
+	line: number;
+	message: string;
+}
+
+export interface FormattedSql {
+	text: string;
+	warnings: FormatWarning[];
+}
+
+export interface SanitizedSql {
+	text: string;
+	replacements: SuspiciousCharacter[];
+	/** Characters left alone because they're in literals or comments */
+	kept: SuspiciousCharacter[];
+}
+
+/** A statement held back by the policy of its connection's environment */
+export interface GuardedStatement {
+	statement: string;
+	reason: 'unfiltered_write' | 'schema_change';
+}
+
+export interface PreflightReport {
+	expensive_statements: CostEstimate[];
+	destructive_statements: DestructiveStatements[];
+	suspicious_characters: SuspiciousCharacter[];
+	guarded_statements: GuardedStatement[];
+}
+
+export type SubmitOutcome =
+	| { status: 'submitted'; query_ids: QueryId[] }
+	| { status: 'confirmation_required'; run_id: string; report: PreflightReport };
+
+function describePreflightReport(report: PreflightReport): string {
+	const lines = report.expensive_statements.map(
+		(estimate) =>
+			`- estimated cost ${Math.round(estimate.total_cost)}, ~${Math.round(estimate.estimated_rows)} rows: ${estimate.statement.slice(0, 80)}`
+	);
+	for (const { kind, count } of report.destructive_statements) {
+		lines.push(`- ${count} ${kind} statement(s)`);
+	}
+
+	const header = lines.length > 0 ? 'This is a production connection.\n\n' : '';
+	for (const { statement, reason } of report.guarded_statements) {
+		const why = reason === 'schema_change' ? 'changes the schema' : 'changes every row';
+		lines.push(`- ${why}: ${statement.slice(0, 80)}`);
+	}
+	if (report.suspicious_characters.length > 0) {
+		const codePoints = [...new Set(report.suspicious_characters.map((c) => c.code_point))];
+		lines.push(
+			`- ${report.suspicious_characters.length} smart quote(s) or invisible character(s) outside of strings (${codePoints.join(', ')}), likely from pasting. Ctrl+Alt+F replaces them.`
+		);
+	}
+	return `${header}${lines.join('\n')}\n\nRun anyway?`;
+}
+
+export interface CsvImportOptions {
+	/** Named after the file if empty */
+	table: string;
+	/** Postgres only, the search path's if null */
+	schema: string | null;
+	/** Whether to create the table, with inferred column types, if it doesn't exist */
+	create_table: boolean;
+	delimiter: string;
+	quote_char: string;
+	header: boolean;
+	/** Unquoted fields equal to it are loaded as NULLs */
+	null_value: string;
+	/** Rows the column types are inferred from */
+	sample_rows: number;
+	batch_size: number;
+	/** Whether the first rejected row fails the whole import */
+	stop_on_error: boolean;
+}
+
+export type InferredType =
+	| 'boolean'
+	| 'integer'
+	| 'float'
+	| 'date'
+	| 'timestamp'
+	| 'timestamp_tz'
+	| 'text';
+
+export interface CsvImportReport {
+	table: string;
+	created_table: boolean;
+	/** `type` is set for the columns of a created table */
+	columns: { name: string; type: InferredType | null }[];
+	rows_loaded: number;
+	bytes_read: number;
+	rejected_count: number;
+	/** The first 1000 rejected rows, with the line each starts on */
+	rejected_rows: { line: number; message: string }[];
+}
+
+export interface CopyReport {
+	bytes: number;
+	/** As counted by the server, which only tells how many rows were copied in */
+	rows: number | null;
+}
+
+export interface OperationInfo {
+	id: string;
+	kind: 'export' | 'connection_probe' | 'snapshot' | 'column_stats' | 'import' | 'sort' | 'filter';
+	description: string;
+	/** From 0 to 1 */
+	progress: number | null;
+	/** What's done so far, e.g. "1200 rows loaded" */
+	detail?: string | null;
+	cancellable: boolean;
+	started_at: number;
+}
+
+export type OperationFinished = OperationInfo &
+	(
+		| { outcome: 'completed' }
+		| { outcome: 'cancelled' }
+		| { outcome: 'failed'; error: string }
+	);
+
+export interface ExportOptions {
+	delimiter: string;
+	quote_char: string;
+	quote_policy: 'always' | 'minimal' | 'never';
+	line_ending: 'lf' | 'crlf';
+	header: boolean;
+	null_value: string;
+	/** strftime-like, e.g. `%d.%m.%Y` */
+	date_format: string | null;
+	timestamp_format: string | null;
+	decimal_separator: string | null;
+	encoding: 'utf8' | 'utf8_bom' | 'latin1';
+	file_extension: string;
+}
+
+/** `json` is a single array of objects, `ndjson` one object per line */
+export type JsonExportFormat = 'json' | 'ndjson';
+
+export interface InsertOptions {
+	dialect: 'postgres' | 'sqlite' | 'mysql';
+	rows_per_statement: number;
+	include_nulls: boolean;
+	max_inline_bytes: number;
+}
+
+export interface ExportTemplate {
+	id: number;
+	name: string;
+	options: ExportOptions;
+	created_at: number;
+	updated_at: number;
+}
+
+export type DatabaseType = 'postgres' | 'sqlite' | 'mysql';
+
+/** The statements creating a table, with its constraints and indexes */
+export interface TableDdl {
+	schema: string;
+	table: string;
+	/** The database the statements are written for */
+	dialect: DatabaseType;
+	ddl: string;
+}
+
+export interface ScriptTemplate {
+	// e.g. `postgres-upsert` for built-in templates, `user-3` for user-defined ones
+	id: string;
+	name: string;
+	description: string | null;
+	body: string;
+	builtin: boolean;
+}
+
+/** A saved session of a window, without its data */
+export interface SessionInfo {
+	session_id: string;
+	/** Of the format of the data */
+	version: number;
+	/** In seconds since the epoch */
+	updated_at: number;
+}
+
+export interface SessionState extends SessionInfo {
+	data: string;
+}
+
+/** The latest autosaved contents of an editor buffer with unsaved changes */
+export interface DraftBuffer {
+	buffer_id: string;
+	connection_id: string | null;
+	content: string;
+	/** Offset of the cursor in the content */
+	cursor: number;
+	/** In seconds since the epoch */
+	updated_at: number;
+}
+
+/** Expanded by completion when its prefix is typed. The body has tab stops like `$1`, `${1:table}` and `$0`. */
+export interface Snippet {
+	/** 0 for one that isn't saved yet */
+	id: number;
+	name: string;
+	prefix: string;
+	body: string;
+	/** Only offered on this connection */
+	connection_id: string | null;
+	/** Only offered on this type of database */
+	db_type: DatabaseType | null;
+}
+
+/** Substituted into queries as `${name}`, or written as-is with `$${name}` */
+export interface QueryVariable {
+	/** 0 for one that isn't saved yet */
+	id: number;
+	name: string;
+	/** SQL, e.g. `'2024-01-01'` with its quotes */
+	value: string;
+	/** Only defined on this connection, where it takes precedence over one of no connection */
+	connection_id: string | null;
+}
+
+/** Every `seconds` since the last run, or when the local time matches a `minute hour day month weekday` expression */
+export type Schedule = { kind: 'every'; seconds: number } | { kind: 'cron'; expression: string };
+
+/** skipped: the connection wasn't connected */
+export type ScheduledRunStatus = 'success' | 'error' | 'skipped';
+
+/** A query run in the background */
+export interface ScheduledQuery {
+	/** 0 for one that isn't saved yet */
+	id: number;
+	name: string;
+	connection_id: string;
+	query_text: string;
+	schedule: Schedule;
+	enabled: boolean;
+	/** Whether runs returning rows raise an alert */
+	alert_if_rows: boolean;
+	/** Whether statements the environment's policy holds back were confirmed to run unattended */
+	confirmed_destructive?: boolean;
+	/** In seconds since the epoch */
+	last_run_at?: number | null;
+	last_status?: ScheduledRunStatus | null;
+	last_error?: string | null;
+}
+
+/** Sent as `scheduled-query-alert` when a scheduled run fails or returns rows it alerts on */
+export interface ScheduledRun {
+	schedule_id: number;
+	name: string;
+	connection_id: string;
+	status: ScheduledRunStatus;
+	row_count: number;
+	duration_ms: number;
+	error: string | null;
+	alert: boolean;
+}
+
+/** Whether a saved connection could be reached, see Commands.probeConnections */
+export interface ConnectionProbe {
+	connection_id: string;
+	/** auth_required: the server is up, and wants credentials that the probe didn't try */
+	status: 'reachable' | 'auth_required' | 'unreachable' | 'timeout';
+	/** null if the probe timed out */
+	latency_ms: number | null;
+	error: string | null;
+}
+
 export class Commands {
 	static async testConnection(config: ConnectionConfig): Promise<boolean> {
 		return await backend.invoke('test_connection', { config });
 	}
 
+	/** Checks which connections are reachable, without connecting to them. Fails if cancelled. */
+	static async probeConnections(
+		connectionIds: string[],
+		timeoutMs: number
+	): Promise<ConnectionProbe[]> {
+		return await backend.invoke('probe_connections', { connectionIds, timeoutMs });
+	}
+
+	/** Stops the running probeConnections calls, whose results are no longer wanted */
+	static async cancelConnectionProbes(): Promise<void> {
+		const operations = await Commands.listOperations();
+		await Promise.all(
+			operations
+				.filter((operation) => operation.kind === 'connection_probe')
+				.map((operation) => Commands.cancelOperation(operation.id))
+		);
+	}
+
 	static async addConnection(
 		name: string,
+		folder: string | null,
+		environment: Environment | null,
+		color: string | null,
 		config: ConnectionConfig,
 		permissions: Permissions
 	): Promise<ConnectionInfo> {
-		return await backend.invoke('add_connection', { name, config, permissions });
+		return await backend.invoke('add_connection', {
+			name,
+			folder,
+			environment,
+			color,
+			config,
+			permissions
+		});
 	}
 
 	static async connectToDatabase(connectionId: string): Promise<boolean> {
@@ -95,6 +1244,11 @@ export class Commands {
 		return await backend.invoke('disconnect_from_database', { connectionId });
 	}
 
+	/** Null if the connection is disconnected or wasn't pinged yet, which SQLite ones never are */
+	static async getConnectionHealth(connectionId: string): Promise<ConnectionHealth | null> {
+		return await backend.invoke('get_connection_health', { connectionId });
+	}
+
 	static async getConnections(): Promise<ConnectionInfo[]> {
 		return await backend.invoke('get_connections');
 	}
@@ -103,31 +1257,74 @@ export class Commands {
 		return await backend.invoke('remove_connection', { connectionId });
 	}
 
+	/** Asks where the file of a SQLite connection is now. Null if the dialog was dismissed. */
+	static async relocateDatabaseFile(connectionId: string): Promise<ConnectionInfo | null> {
+		return await backend.invoke('relocate_database_file', { connectionId });
+	}
+
 	static async updateConnection(
 		connectionId: string,
 		name: string,
+		folder: string | null,
+		environment: Environment | null,
+		color: string | null,
 		config: ConnectionConfig,
 		permissions: Permissions
 	): Promise<ConnectionInfo> {
 		return await backend.invoke('update_connection', {
 			connId: connectionId,
 			name,
+			folder,
+			environment,
+			color,
 			config,
 			permissions
 		});
 	}
 
+	/**
+	 * Writes connections to a file to share them. Passwords are only included, sealed, when there's a passphrase.
+	 * Returns how many connections were exported.
+	 */
+	static async exportConnections(
+		path: string,
+		connectionIds: string[],
+		passphrase: string | null = null
+	): Promise<number> {
+		return await backend.invoke('export_connections', { path, connectionIds, passphrase });
+	}
+
+	/** Creates the connections of an exported file, reporting what happened to each */
+	static async importConnections(
+		path: string,
+		passphrase: string | null = null
+	): Promise<ImportedConnection[]> {
+		return await backend.invoke('import_connections', { path, passphrase });
+	}
+
+	/** Renames a folder of connections along with its subfolders. Returns how many connections moved. */
+	static async renameConnectionFolder(oldFolder: string, newFolder: string): Promise<number> {
+		return await backend.invoke('rename_connection_folder', { old: oldFolder, new: newFolder });
+	}
+
 	static async initializeConnections(): Promise<void> {
 		return await backend.invoke('initialize_connections');
 	}
 
+	/** Loads the stored connections, returning the drafts of editor buffers left by the last run */
+	static async initializeWorkspace(): Promise<DraftBuffer[]> {
+		return await backend.invoke('initialize_workspace');
+	}
+
 	static async saveQueryToHistory(
 		connectionId: string,
 		query: string,
 		durationMs?: number,
 		status: string = 'success',
 		rowCount: number = 0,
-		errorMessage?: string
+		errorMessage?: string,
+		timings?: TimingBreakdown[],
+		planJson?: string
 	): Promise<void> {
 		await backend.invoke('save_query_to_history', {
 			connectionId,
@@ -135,29 +1332,275 @@ export class Commands {
 			durationMs,
 			status,
 			rowCount,
-			errorMessage
+			errorMessage,
+			timings,
+			planJson
+		});
+	}
+
+	/** A history entry with its plan, if it has one */
+	static async getQueryHistoryEntry(id: number): Promise<QueryHistoryEntry> {
+		return await backend.invoke('get_query_history_entry', { id });
+	}
+
+	static async getQueryHistory(connectionId: string, limit?: number): Promise<QueryHistoryEntry[]> {
+		return await backend.invoke('get_query_history', { connectionId, limit });
+	}
+
+	static async getQueryHistoryPage(
+		connectionId: string,
+		before: HistoryCursor | null,
+		pageSize: number,
+		filters?: HistoryFilters
+	): Promise<QueryHistoryPage> {
+		return await backend.invoke('get_query_history_page', {
+			connectionId,
+			before,
+			pageSize,
+			filters: filters ?? null
 		});
 	}
 
-	static async getQueryHistory(connectionId: string, limit?: number): Promise<QueryHistoryEntry[]> {
-		return await backend.invoke('get_query_history', { connectionId, limit });
+	static async getHistorySettings(): Promise<HistorySettings> {
+		return await backend.invoke('get_history_settings');
+	}
+
+	static async setHistorySettings(settings: HistorySettings): Promise<void> {
+		return await backend.invoke('set_history_settings', { settings });
+	}
+
+	static async getResultCacheSettings(): Promise<ResultCacheSettings> {
+		return await backend.invoke('get_result_cache_settings');
+	}
+
+	/** Applies to the queries run from now on */
+	static async setResultCacheSettings(settings: ResultCacheSettings): Promise<void> {
+		return await backend.invoke('set_result_cache_settings', { settings });
+	}
+
+	/** Deletes the history entries the history settings don't keep, which also happens on startup */
+	static async pruneQueryHistory(): Promise<HistoryPruneReport> {
+		return await backend.invoke('prune_query_history');
+	}
+
+	static async getQueryHistoryCount(
+		connectionId: string,
+		filters?: HistoryFilters
+	): Promise<number> {
+		return await backend.invoke('get_query_history_count', {
+			connectionId,
+			filters: filters ?? null
+		});
+	}
+
+	/** Explains the statement under the cursor, a byte offset into `query` */
+	static async explainQuery(
+		connectionId: string,
+		query: string,
+		cursor: number | null,
+		options: ExplainOptions
+	): Promise<ExplainedPlan> {
+		return await backend.invoke('explain_query', { connectionId, query, cursor, options });
+	}
+
+	/** Explains the SQLite statement under the cursor, a byte offset into `query`, with EXPLAIN QUERY PLAN */
+	static async explainSqliteQuery(
+		connectionId: string,
+		query: string,
+		cursor: number | null
+	): Promise<QueryPlan> {
+		return await backend.invoke('explain_sqlite_query', { connectionId, query, cursor });
+	}
+
+	static async getDatabaseSchema(connectionId: string): Promise<DatabaseSchema> {
+		return await backend.invoke('get_database_schema', { connectionId });
+	}
+
+	/** The tables of a connection and the foreign keys between them, refreshed along with its schema */
+	static async getRelationshipGraph(connectionId: string): Promise<RelationshipGraph> {
+		return await backend.invoke('get_relationship_graph', { connectionId });
+	}
+
+	/** Attaches a SQLite database file to a SQLite connection, its tables then being in the schema named `alias` */
+	static async attachSqliteDatabase(
+		connectionId: string,
+		path: string,
+		alias: string
+	): Promise<DatabaseSchema> {
+		return await backend.invoke('attach_sqlite_database', { connectionId, path, alias });
+	}
+
+	static async detachSqliteDatabase(connectionId: string, alias: string): Promise<DatabaseSchema> {
+		return await backend.invoke('detach_sqlite_database', { connectionId, alias });
+	}
+
+	static async getTableColumns(
+		connectionId: string,
+		schema: string,
+		table: string
+	): Promise<ColumnInfo[]> {
+		return await backend.invoke('get_table_columns', { connectionId, schema, table });
+	}
+
+	/** Counts the rows of a table exactly, updating its `row_estimate` in the cached schema */
+	static async refreshTableRowCount(
+		connectionId: string,
+		schema: string,
+		table: string
+	): Promise<number> {
+		return await backend.invoke('refresh_table_row_count', { connectionId, schema, table });
+	}
+
+	/** `CREATE TABLE` with the table's constraints, then its indexes, runnable as-is */
+	static async getTableDdl(connectionId: string, schema: string, table: string): Promise<TableDdl> {
+		return await backend.invoke('get_table_ddl', { connectionId, schema, table });
+	}
+
+	/** Puts a schema first in the search path until disconnecting, or restores the default one with null */
+	static async setActiveSchema(connectionId: string, schema: string | null): Promise<void> {
+		return await backend.invoke('set_active_schema', { connectionId, schema });
+	}
+
+	static async suggestErrorFixes(
+		connectionId: string,
+		query: string,
+		error: string
+	): Promise<ErrorSuggestion[]> {
+		return await backend.invoke('suggest_error_fixes', { connectionId, query, error });
+	}
+
+	static async getErrorSuggestionsEnabled(): Promise<boolean> {
+		return await backend.invoke('get_error_suggestions_enabled');
+	}
+
+	static async setErrorSuggestionsEnabled(enabled: boolean): Promise<void> {
+		return await backend.invoke('set_error_suggestions_enabled', { enabled });
+	}
+
+	static async getPostgresReplicationInfo(connectionId: string): Promise<ReplicationInfo> {
+		return await backend.invoke('get_postgres_replication_info', { connectionId });
+	}
+
+	/** A snapshot of the server's statistics, taken again at most every few seconds however often it's polled */
+	static async getPostgresStats(connectionId: string): Promise<PostgresStats> {
+		return await backend.invoke('get_postgres_stats', { connectionId });
+	}
+
+	/** A page of the server's sessions, longest running first */
+	static async getServerActivity(
+		connectionId: string,
+		filters?: ActivityFilters
+	): Promise<ServerActivity> {
+		return await backend.invoke('get_server_activity', {
+			connectionId,
+			filters: filters ?? null
+		});
+	}
+
+	/** Cancels the session's query, or with `force` closes the session. False if there's no such session. */
+	static async terminateSession(connectionId: string, pid: number, force: boolean): Promise<boolean> {
+		return await backend.invoke('terminate_session', { connectionId, pid, force });
+	}
+
+	/** Listens on a channel until unlistened or disconnected, even if the connection drops and comes back */
+	static async listenPostgres(connectionId: string, channel: string): Promise<void> {
+		return await backend.invoke('listen_postgres', { connectionId, channel });
+	}
+
+	static async unlistenPostgres(connectionId: string, channel: string): Promise<void> {
+		return await backend.invoke('unlisten_postgres', { connectionId, channel });
+	}
+
+	static async getActiveListens(connectionId: string): Promise<string[]> {
+		return await backend.invoke('get_active_listens', { connectionId });
+	}
+
+	/** Notices and warnings the connection got outside of statements, oldest first */
+	static async getConnectionMessages(connectionId: string): Promise<ServerMessage[]> {
+		return await backend.invoke('get_connection_messages', { connectionId });
+	}
+
+	static async saveScript(
+		name: string,
+		content: string,
+		connectionId?: string,
+		description?: string,
+		templateId?: string,
+		tags: string[] = [],
+		favorite = false
+	): Promise<number> {
+		return await backend.invoke('save_script', {
+			name,
+			content,
+			connectionId: connectionId || null,
+			description: description || null,
+			templateId: templateId || null,
+			tags,
+			favorite
+		});
+	}
+
+	/** The snippets offered on a connection, or all of them without one */
+	static async getSnippets(connectionId: string | null = null): Promise<Snippet[]> {
+		return await backend.invoke('get_snippets', { connectionId });
+	}
+
+	/** Creates the snippet if its id is 0, or updates it */
+	static async saveSnippet(snippet: Snippet): Promise<Snippet> {
+		return await backend.invoke('save_snippet', { snippet });
+	}
+
+	static async deleteSnippet(id: number): Promise<void> {
+		return await backend.invoke('delete_snippet', { id });
+	}
+
+	/** The variables defined on a connection, or all of them without one */
+	static async getQueryVariables(connectionId: string | null = null): Promise<QueryVariable[]> {
+		return await backend.invoke('get_query_variables', { connectionId });
 	}
 
-	static async getDatabaseSchema(connectionId: string): Promise<DatabaseSchema> {
-		return await backend.invoke('get_database_schema', { connectionId });
+	/** Creates the variable if its id is 0, or updates it */
+	static async saveQueryVariable(variable: QueryVariable): Promise<QueryVariable> {
+		return await backend.invoke('save_query_variable', { variable });
 	}
 
-	static async saveScript(
+	static async deleteQueryVariable(id: number): Promise<void> {
+		return await backend.invoke('delete_query_variable', { id });
+	}
+
+	static async getScheduledQueries(): Promise<ScheduledQuery[]> {
+		return await backend.invoke('get_scheduled_queries');
+	}
+
+	/** Creates the scheduled query if its id is 0, or updates it, keeping how its last run went */
+	static async saveScheduledQuery(scheduled: ScheduledQuery): Promise<ScheduledQuery> {
+		return await backend.invoke('save_scheduled_query', { scheduled });
+	}
+
+	static async deleteScheduledQuery(id: number): Promise<void> {
+		return await backend.invoke('delete_scheduled_query', { id });
+	}
+
+	/** The query with its variables substituted, as it would run */
+	static async previewSubstitution(query: string, connectionId: string | null): Promise<string> {
+		return await backend.invoke('preview_substitution', { query, connectionId });
+	}
+
+	static async getScriptTemplates(dbType: DatabaseType): Promise<ScriptTemplate[]> {
+		return await backend.invoke('get_script_templates', { dbType });
+	}
+
+	static async saveScriptTemplate(
+		dbType: DatabaseType,
 		name: string,
-		content: string,
-		connectionId?: string,
+		body: string,
 		description?: string
-	): Promise<number> {
-		return await backend.invoke('save_script', {
+	): Promise<ScriptTemplate> {
+		return await backend.invoke('save_script_template', {
+			dbType,
 			name,
-			content,
-			connectionId: connectionId || null,
-			description: description || null
+			description: description || null,
+			body
 		});
 	}
 
@@ -166,14 +1609,18 @@ export class Commands {
 		name: string,
 		content: string,
 		connectionId?: string,
-		description?: string
+		description?: string,
+		tags: string[] = [],
+		favorite = false
 	): Promise<void> {
 		return await backend.invoke('update_script', {
 			id,
 			name,
 			content,
 			connectionId: connectionId || null,
-			description: description || null
+			description: description || null,
+			tags,
+			favorite
 		});
 	}
 
@@ -181,6 +1628,24 @@ export class Commands {
 		return await backend.invoke('get_scripts', { connectionId: connectionId || null });
 	}
 
+	/** Favorites first, then the most recently updated */
+	static async getScriptsFiltered(
+		connectionId: string | null,
+		filters: { tag?: string; favoritesOnly?: boolean; searchText?: string }
+	): Promise<Script[]> {
+		return await backend.invoke('get_scripts_filtered', {
+			connectionId,
+			tag: filters.tag || null,
+			favoritesOnly: filters.favoritesOnly ?? false,
+			searchText: filters.searchText || null
+		});
+	}
+
+	/** Returns whether the script is now a favorite */
+	static async toggleScriptFavorite(id: number): Promise<boolean> {
+		return await backend.invoke('toggle_script_favorite', { id });
+	}
+
 	static async deleteScript(id: number): Promise<void> {
 		await backend.invoke('delete_script', { id });
 	}
@@ -197,12 +1662,55 @@ export class Commands {
 		await backend.invoke('close_window');
 	}
 
-	static async saveSessionState(sessionData: string): Promise<void> {
-		return await backend.invoke('save_session_state', { sessionData });
+	/** The saved sessions of every window, the most recently saved first */
+	static async listSessions(): Promise<SessionInfo[]> {
+		return await backend.invoke('list_sessions');
+	}
+
+	/** Saves the session of a window, `version` being that of the format of `data` */
+	static async saveSession(sessionId: string, version: number, data: string): Promise<void> {
+		return await backend.invoke('save_session', { sessionId, version, data });
 	}
 
-	static async getSessionState(): Promise<string | null> {
-		return await backend.invoke('get_session_state');
+	static async getSession(sessionId: string): Promise<SessionState | null> {
+		return await backend.invoke('get_session', { sessionId });
+	}
+
+	static async deleteSession(sessionId: string): Promise<void> {
+		return await backend.invoke('delete_session', { sessionId });
+	}
+
+	/** Saves the latest contents of an editor buffer, to recover them after a crash */
+	static async autosaveBuffer(
+		bufferId: string,
+		connectionId: string | null,
+		content: string,
+		cursor: number
+	): Promise<void> {
+		return await backend.invoke('autosave_buffer', { bufferId, connectionId, content, cursor });
+	}
+
+	static async getDraftBuffers(): Promise<DraftBuffer[]> {
+		return await backend.invoke('get_draft_buffers');
+	}
+
+	static async discardDraftBuffer(bufferId: string): Promise<void> {
+		return await backend.invoke('discard_draft_buffer', { bufferId });
+	}
+
+	static async saveTreeState(connectionId: string, state: TreeState): Promise<void> {
+		return await backend.invoke('save_tree_state', {
+			connectionId,
+			stateJson: JSON.stringify(state)
+		});
+	}
+
+	static async getTreeState(connectionId: string): Promise<TreeState | null> {
+		return await backend.invoke('get_tree_state', { connectionId });
+	}
+
+	static async getBufferExecutionState(tabId: string, text: string): Promise<StatementMarker[]> {
+		return await backend.invoke('get_buffer_execution_state', { tabId, text });
 	}
 
 	static async pickSqliteDbDialog(): Promise<string | null> {
@@ -213,22 +1721,316 @@ export class Commands {
 		return await backend.invoke('save_sqlite_db');
 	}
 
+	static async saveXlsxFileDialog(): Promise<string | null> {
+		return await backend.invoke('save_xlsx_file');
+	}
+
+	static async saveDelimitedFileDialog(extension: string): Promise<string | null> {
+		return await backend.invoke('save_delimited_file', { extension });
+	}
+
+	static async pickScriptDirectory(): Promise<string | null> {
+		return await backend.invoke('pick_script_directory');
+	}
+
+	static async saveConnectionsFileDialog(): Promise<string | null> {
+		return await backend.invoke('save_connections_file');
+	}
+
+	static async openConnectionsFileDialog(): Promise<string | null> {
+		return await backend.invoke('open_connections_file');
+	}
+
+	static async openCsvFileDialog(): Promise<string | null> {
+		return await backend.invoke('open_csv_file');
+	}
+
 	static async pickCaCert(): Promise<string | null> {
 		return await backend.invoke('pick_ca_cert');
 	}
 
-	static async submitQuery(connectionId: string, query: string): Promise<QueryId[]> {
-		return await backend.invoke('submit_query', { connectionId, query });
+	static async submitQuery(
+		connectionId: string,
+		query: string,
+		options?: SubmitOptions
+	): Promise<QueryId[]> {
+		const outcome: SubmitOutcome = await backend.invoke('submit_query', {
+			connectionId,
+			query,
+			options
+		});
+		if (outcome.status === 'submitted') {
+			return outcome.query_ids;
+		}
+
+		if (!confirm(describePreflightReport(outcome.report))) {
+			await Commands.discardRun(outcome.run_id);
+			throw new Error('Run cancelled');
+		}
+		return await Commands.confirmRun(outcome.run_id);
+	}
+
+	static async confirmRun(runId: string): Promise<QueryId[]> {
+		return await backend.invoke('confirm_run', { runId });
+	}
+
+	static async discardRun(runId: string): Promise<void> {
+		return await backend.invoke('discard_run', { runId });
+	}
+
+	/** Runs the query fetching a page of a table, ordered by its primary key. Pages are 0-based. */
+	static async browseTable(
+		connectionId: string,
+		schema: string,
+		table: string,
+		pageIndex: number,
+		pageSize: number
+	): Promise<BrowsePage> {
+		return await backend.invoke('browse_table', {
+			connectionId,
+			schema,
+			table,
+			pageIndex,
+			pageSize
+		});
+	}
+
+	static async getPreflightSettings(connectionId: string): Promise<PreflightSettings> {
+		return await backend.invoke('get_preflight_settings', { connectionId });
+	}
+
+	static async setPreflightSettings(
+		connectionId: string,
+		settings: PreflightSettings
+	): Promise<void> {
+		return await backend.invoke('set_preflight_settings', { connectionId, settings });
+	}
+
+	/** What runs must be confirmed on the connections of each environment */
+	static async getEnvironmentPolicies(): Promise<EnvironmentPolicies> {
+		return await backend.invoke('get_environment_policies');
+	}
+
+	static async setEnvironmentPolicies(policies: EnvironmentPolicies): Promise<void> {
+		return await backend.invoke('set_environment_policies', { policies });
+	}
+
+	/** Whether statements run in a transaction block get a savepoint each */
+	static async getStatementSavepoints(connectionId: string): Promise<boolean> {
+		return await backend.invoke('get_statement_savepoints', { connectionId });
+	}
+
+	static async setStatementSavepoints(connectionId: string, enabled: boolean): Promise<void> {
+		return await backend.invoke('set_statement_savepoints', { connectionId, enabled });
+	}
+
+	/** How the connection is reconnected after it drops */
+	static async getReconnectSettings(connectionId: string): Promise<ReconnectSettings> {
+		return await backend.invoke('get_reconnect_settings', { connectionId });
+	}
+
+	static async setReconnectSettings(
+		connectionId: string,
+		settings: ReconnectSettings
+	): Promise<void> {
+		return await backend.invoke('set_reconnect_settings', { connectionId, settings });
+	}
+
+	static async getQueryTagSettings(connectionId: string): Promise<QueryTagSettings> {
+		return await backend.invoke('get_query_tag_settings', { connectionId });
+	}
+
+	static async setQueryTagSettings(
+		connectionId: string,
+		settings: QueryTagSettings
+	): Promise<void> {
+		return await backend.invoke('set_query_tag_settings', { connectionId, settings });
+	}
+
+	static async getSemanticSearchSettings(): Promise<SemanticSearchSettings> {
+		return await backend.invoke('get_semantic_search_settings');
+	}
+
+	static async setSemanticSearchSettings(settings: SemanticSearchSettings): Promise<void> {
+		return await backend.invoke('set_semantic_search_settings', { settings });
+	}
+
+	/** Searches tables by meaning, or by name when semantic search isn't available */
+	static async semanticSearchSchema(
+		connectionId: string,
+		query: string,
+		limit: number
+	): Promise<SchemaSearchResults> {
+		return await backend.invoke('semantic_search_schema', { connectionId, query, limit });
+	}
+
+	/** Whether a SQLite connection opens a local, read-only copy of its file. Takes effect on the next connect. */
+	static async getSnapshotMode(connectionId: string): Promise<boolean> {
+		return await backend.invoke('get_snapshot_mode', { connectionId });
+	}
+
+	static async setSnapshotMode(connectionId: string, enabled: boolean): Promise<void> {
+		return await backend.invoke('set_snapshot_mode', { connectionId, enabled });
+	}
+
+	/** Copies the source of a snapshot again if it changed since */
+	static async refreshSnapshot(connectionId: string): Promise<SnapshotRefresh> {
+		return await backend.invoke('refresh_snapshot', { connectionId });
+	}
+
+	static async setStatementTracing(connectionId: string, enabled: boolean): Promise<void> {
+		return await backend.invoke('set_statement_tracing', { connectionId, enabled });
+	}
+
+	static async getStatementTrace(connectionId: string, limit: number): Promise<TracedStatement[]> {
+		return await backend.invoke('get_statement_trace', { connectionId, limit });
+	}
+
+	static async clearStatementTrace(connectionId: string): Promise<void> {
+		return await backend.invoke('clear_statement_trace', { connectionId });
+	}
+
+	static async getExternalEditorSettings(): Promise<ExternalEditorSettings> {
+		return await backend.invoke('get_external_editor_settings');
+	}
+
+	static async setExternalEditorSettings(settings: ExternalEditorSettings): Promise<void> {
+		return await backend.invoke('set_external_editor_settings', { settings });
+	}
+
+	static async editExternally(
+		tabId: string,
+		title: string,
+		contents: string
+	): Promise<ExternalEditSession> {
+		return await backend.invoke('edit_externally', { tabId, title, contents });
+	}
+
+	static async syncExternalEdit(sessionId: string, contents: string): Promise<void> {
+		return await backend.invoke('sync_external_edit', { sessionId, contents });
+	}
+
+	static async resolveExternalEdit(
+		sessionId: string,
+		resolution: 'keep_external' | 'keep_pgpad'
+	): Promise<string> {
+		return await backend.invoke('resolve_external_edit', { sessionId, resolution });
+	}
+
+	static async endExternalEdit(sessionId: string): Promise<void> {
+		return await backend.invoke('end_external_edit', { sessionId });
+	}
+
+	static async getSecretBackends(connectionId: string): Promise<SecretBackend[]> {
+		return await backend.invoke('get_secret_backends', { connectionId });
+	}
+
+	static async setSecretBackends(connectionId: string, backends: SecretBackend[]): Promise<void> {
+		return await backend.invoke('set_secret_backends', { connectionId, backends });
+	}
+
+	static async getCredentialBackendStatus(): Promise<CredentialBackendStatus> {
+		return await backend.invoke('get_credential_backend_status');
+	}
+
+	/** Creates the credential file with this passphrase if there's none yet */
+	static async unlockCredentialFile(passphrase: string): Promise<CredentialBackendStatus> {
+		return await backend.invoke('unlock_credential_file', { passphrase });
+	}
+
+	static async getConnectionResourceLimits(connectionId: string): Promise<ResourceLimits> {
+		return await backend.invoke('get_connection_resource_limits', { connectionId });
+	}
+
+	static async setConnectionResourceLimits(
+		connectionId: string,
+		limits: ResourceLimits
+	): Promise<void> {
+		return await backend.invoke('set_connection_resource_limits', { connectionId, limits });
+	}
+
+	/** Clients a Postgres connection opens, from 2 to 4. Takes effect on the next connect. */
+	static async getPostgresPoolSize(connectionId: string): Promise<number> {
+		return await backend.invoke('get_postgres_pool_size', { connectionId });
+	}
+
+	static async setPostgresPoolSize(connectionId: string, size: number): Promise<void> {
+		return await backend.invoke('set_postgres_pool_size', { connectionId, size });
+	}
+
+	/** Cancels the query running on a Postgres connection, if any */
+	static async cancelPostgres(connectionId: string): Promise<void> {
+		return await backend.invoke('cancel_postgres', { connectionId });
+	}
+
+	/** The table or column at `position`, resolved against the cached schema. Null until the schema was fetched. */
+	static async lspHover(connectionId: string, query: string, position: number): Promise<Hover | null> {
+		return await backend.invoke('lsp_hover', { connectionId, query, position });
+	}
+
+	/** Conditions for the join whose `ON` is right before `position`, from the foreign keys of the cached schema */
+	static async lspJoinConditions(
+		connectionId: string,
+		query: string,
+		position: number
+	): Promise<string[]> {
+		return await backend.invoke('lsp_join_conditions', { connectionId, query, position });
+	}
+
+	/** Unknown tables and columns of the query, checked against the cached schema. Empty until it was fetched. */
+	static async lspDiagnostics(connectionId: string, query: string): Promise<SqlDiagnostic[]> {
+		return await backend.invoke('lsp_diagnostics', { connectionId, query });
+	}
+
+	/** Where the name at `position` is defined, fetching the definition of views and routines. Null if nothing is found. */
+	static async lspDefinition(
+		connectionId: string,
+		query: string,
+		position: number
+	): Promise<Definition | null> {
+		return await backend.invoke('lsp_definition', { connectionId, query, position });
 	}
 
 	static async isQueryReadOnly(connectionId: string, query: string): Promise<boolean> {
 		return await backend.invoke('is_query_read_only', { connectionId, query });
 	}
 
+	/** The placeholders of the query's statements, e.g. `$1` or `:name`. A bare `?` is named `?1`, `?2`... */
+	static async detectQueryParameters(connectionId: string, query: string): Promise<string[]> {
+		return await backend.invoke('detect_query_parameters', { connectionId, query });
+	}
+
 	static async waitUntilRenderable(queryId: QueryId): Promise<QuerySnapshot> {
 		return await backend.invoke('wait_until_renderable', { queryId });
 	}
 
+	/** Cancels the statements of a connection that aren't over yet, returning how many were cancelled */
+	static async cancelAllQueries(connectionId: string): Promise<number> {
+		return await backend.invoke('cancel_all_queries', { connectionId });
+	}
+
+	/** Opens a transaction for a tab, which the tab's statements then run in until it's committed or rolled back */
+	static async beginTransaction(connectionId: string, tabId: string): Promise<void> {
+		return await backend.invoke('begin_transaction', { connectionId, tabId });
+	}
+
+	static async commitTransaction(connectionId: string, tabId: string): Promise<void> {
+		return await backend.invoke('commit_transaction', { connectionId, tabId });
+	}
+
+	static async rollbackTransaction(connectionId: string, tabId: string): Promise<void> {
+		return await backend.invoke('rollback_transaction', { connectionId, tabId });
+	}
+
+	/** The transactions open on a connection, oldest first */
+	static async getTransactionState(connectionId: string): Promise<TransactionInfo[]> {
+		return await backend.invoke('get_transaction_state', { connectionId });
+	}
+
+	static async getStatementInfos(queryIds: QueryId[]): Promise<(StatementInfo | null)[]> {
+		return await backend.invoke('get_statement_infos', { queryIds });
+	}
+
 	static async fetchPage(queryId: QueryId, pageIndex: number): Promise<Page | null> {
 		return await backend.invoke('fetch_page', { queryId, pageIndex });
 	}
@@ -241,11 +2043,253 @@ export class Commands {
 		return await backend.invoke('get_page_count', { queryId });
 	}
 
-	static async formatSql(query: string): Promise<string> {
-		return await backend.invoke('format_sql', { query });
+	/** Postgres queries stop fetching rows a little ahead of those read, until asked for more */
+	static async requestMoreRows(queryId: QueryId): Promise<void> {
+		return await backend.invoke('request_more_rows', { queryId });
+	}
+
+	static async getTimingBreakdown(queryId: QueryId): Promise<TimingBreakdown | null> {
+		return await backend.invoke('get_timing_breakdown', { queryId });
+	}
+
+	/** Every column of a result row, rowIndex counting from the first row across pages */
+	static async getRowDetail(queryId: QueryId, rowIndex: number): Promise<RowDetailField[]> {
+		return await backend.invoke('get_row_detail', { queryId, rowIndex });
+	}
+
+	/** Statistics of a result column over the pages fetched so far, listed as a cancellable operation */
+	static async getColumnStats(queryId: QueryId, columnIndex: number): Promise<ColumnStats> {
+		return await backend.invoke('get_column_stats', { queryId, columnIndex });
+	}
+
+	/** Statistics of a column over a whole table, computed by the server */
+	static async getTableColumnStats(
+		connectionId: string,
+		schema: string,
+		table: string,
+		column: string
+	): Promise<ColumnStats> {
+		return await backend.invoke('get_table_column_stats', { connectionId, schema, table, column });
+	}
+
+	/**
+	 * Formats the statements of a script one by one. Statements that can't be parsed in the dialect are left as
+	 * they are, with a warning.
+	 */
+	static async formatSql(
+		query: string,
+		dialect?: DatabaseType,
+		options?: Partial<FormatSettings>
+	): Promise<FormattedSql> {
+		return await backend.invoke('format_sql', { query, dialect, options });
+	}
+
+	static async sanitizeSql(text: string): Promise<SanitizedSql> {
+		return await backend.invoke('sanitize_sql', { text });
 	}
 
 	static async exportPage(queryId: QueryId, pageIndex: number): Promise<string> {
 		return await backend.invoke('export_page', { queryId, pageIndex });
 	}
+
+	/**
+	 * Loads a CSV file into a table, created with inferred column types if it doesn't exist. Its progress is
+	 * listed with the other operations, where it can be cancelled.
+	 */
+	static async importCsv(
+		connectionId: string,
+		filePath: string,
+		options: Partial<CsvImportOptions> = {}
+	): Promise<CsvImportReport> {
+		return await backend.invoke('import_csv', { connectionId, filePath, options });
+	}
+
+	/**
+	 * Runs a `COPY ... TO STDOUT`, writing its output to a file. Its progress is listed with the other
+	 * operations, where it can be cancelled.
+	 */
+	static async copyOutToFile(
+		connectionId: string,
+		statement: string,
+		path: string
+	): Promise<CopyReport> {
+		return await backend.invoke('copy_out_to_file', { connectionId, statement, path });
+	}
+
+	/** Runs a `COPY ... FROM STDIN`, reading its data from a file */
+	static async copyInFromFile(
+		connectionId: string,
+		statement: string,
+		path: string
+	): Promise<CopyReport> {
+		return await backend.invoke('copy_in_from_file', { connectionId, statement, path });
+	}
+
+	static async exportToXlsx(queryId: QueryId, path: string): Promise<void> {
+		return await backend.invoke('export_to_xlsx', { queryId, path });
+	}
+
+	/** Exports with either a saved template or inline options. Defaults to plain CSV. */
+	static async exportQueryResults(
+		queryId: QueryId,
+		path: string,
+		templateId?: number,
+		options?: Partial<ExportOptions>
+	): Promise<void> {
+		return await backend.invoke('export_query_results', {
+			queryId,
+			path,
+			templateId: templateId ?? null,
+			options: options ?? null
+		});
+	}
+
+	/** Binary values are exported as `{"$base64": "..."}` */
+	static async exportQueryResultsJson(
+		queryId: QueryId,
+		path: string,
+		format: JsonExportFormat
+	): Promise<void> {
+		return await backend.invoke('export_query_results_json', { queryId, path, format });
+	}
+
+	/** Writes INSERTs to `path` if given, and returns them otherwise */
+	static async generateInserts(
+		queryId: QueryId,
+		tableName: string,
+		options: InsertOptions,
+		path: string | null = null
+	): Promise<string | null> {
+		return await backend.invoke('generate_inserts', { queryId, tableName, options, path });
+	}
+
+	static async listOperations(): Promise<OperationInfo[]> {
+		return await backend.invoke('list_operations');
+	}
+
+	static async cancelOperation(id: string): Promise<void> {
+		return await backend.invoke('cancel_operation', { id });
+	}
+
+	static async listExportTemplates(): Promise<ExportTemplate[]> {
+		return await backend.invoke('list_export_templates');
+	}
+
+	static async saveExportTemplate(
+		id: number | null,
+		name: string,
+		options: Partial<ExportOptions>
+	): Promise<ExportTemplate> {
+		return await backend.invoke('save_export_template', { id, name, options });
+	}
+
+	static async groupQueryResults(
+		queryId: QueryId,
+		groupColumns: string[],
+		aggregates: Aggregate[]
+	): Promise<GroupedQuery> {
+		return await backend.invoke('group_query_results', { queryId, groupColumns, aggregates });
+	}
+
+	/** Sorts every cached row of a query, registering the sorted rows as a new query */
+	static async sortQueryResults(
+		queryId: QueryId,
+		keys: SortKey[],
+		collation: Collation = DEFAULT_COLLATION
+	): Promise<SortedQuery> {
+		return await backend.invoke('sort_query_results', { queryId, keys, collation });
+	}
+
+	/**
+	 * Sorts every row of a completed query in place by one of its columns, so that its pages are fetched sorted
+	 * from then on. Sorting it again supersedes a sort still in progress.
+	 */
+	static async sortResult(
+		queryId: QueryId,
+		columnIndex: number,
+		descending: boolean,
+		nulls: SortKey['nulls'] = 'default'
+	): Promise<SortedQuery> {
+		return await backend.invoke('sort_result', { queryId, columnIndex, descending, nulls });
+	}
+
+	/**
+	 * Registers the rows of a completed query that match a filter as a new query, whose pages are read from the
+	 * filtered query's. Sorting the filtered query in place afterwards invalidates it.
+	 */
+	static async filterResult(queryId: QueryId, filter: RowFilter): Promise<FilteredQuery> {
+		return await backend.invoke('filter_result', { queryId, filter });
+	}
+
+	/** Drops a query made by `filterResult`, leaving the query it filtered as it was */
+	static async clearResultFilter(queryId: QueryId): Promise<void> {
+		return await backend.invoke('clear_result_filter', { queryId });
+	}
+
+	/**
+	 * Keeps a finished result when the next query is submitted, e.g. to join or diff it with a later run's.
+	 * Pinning a filtered result pins the result it was filtered from too.
+	 */
+	static async pinResult(queryId: QueryId): Promise<void> {
+		return await backend.invoke('pin_result', { queryId });
+	}
+
+	/** Undoes `pinResult`. A result from an earlier run is dropped right away. */
+	static async unpinResult(queryId: QueryId): Promise<void> {
+		return await backend.invoke('unpin_result', { queryId });
+	}
+
+	/** Runs SQLite SQL over two cached results, loaded as tables `a` and `b` */
+	static async joinResults(
+		queryIdA: QueryId,
+		queryIdB: QueryId,
+		sql: string,
+		acknowledgePartial = false
+	): Promise<JoinedQuery> {
+		return await backend.invoke('join_results', {
+			queryIdA,
+			queryIdB,
+			sql,
+			acknowledgePartial
+		});
+	}
+
+	/** Pairs the rows of two cached results on key columns, e.g. to see what a data fix changed */
+	static async diffResults(
+		queryIdA: QueryId,
+		queryIdB: QueryId,
+		keyColumns: string[]
+	): Promise<ResultDiff> {
+		return await backend.invoke('diff_results', { queryIdA, queryIdB, keyColumns });
+	}
+
+	static async openScriptFile(path: string): Promise<ScriptFile> {
+		return await backend.invoke('open_script_file', { path });
+	}
+
+	static async getRecentScriptFiles(): Promise<string[]> {
+		return await backend.invoke('get_recent_script_files');
+	}
+
+	static async linkScriptDirectory(
+		path: string,
+		connectionId?: string
+	): Promise<IndexedScriptDirectory> {
+		return await backend.invoke('link_script_directory', {
+			path,
+			connectionId: connectionId || null
+		});
+	}
+
+	static async unlinkScriptDirectory(id: number): Promise<void> {
+		return await backend.invoke('unlink_script_directory', { id });
+	}
+
+	static async getLinkedScriptDirectories(): Promise<IndexedScriptDirectory[]> {
+		return await backend.invoke('get_linked_script_directories');
+	}
+
+	static async readLinkedScript(path: string): Promise<ScriptFile> {
+		return await backend.invoke('read_linked_script', { path });
+	}
 }
@@ -0,0 +1,77 @@
+import { Commands, type DraftBuffer } from './commands.svelte';
+import { currentSessionId } from './session';
+
+/** How long editing has to pause before a buffer is autosaved, in milliseconds */
+const AUTOSAVE_DELAY_MS = 1000;
+
+const pendingSaves = new Map<string, ReturnType<typeof setTimeout>>();
+// Tabs with a draft saved, so that discarding a draft that doesn't exist costs nothing
+const savedDrafts = new Set<string>();
+
+/** Drafts are kept per window, as `<session id>:<tab id>`, so that each window recovers its own tabs */
+function draftBufferId(tabId: string, sessionId = currentSessionId()): string {
+	return `${sessionId}:${tabId}`;
+}
+
+/** The drafts saved by the tabs of a session, with the id of their tab */
+export function draftsOfSession(
+	drafts: DraftBuffer[],
+	sessionId: string
+): Array<{ tabId: string; draft: DraftBuffer }> {
+	const prefix = `${sessionId}:`;
+	return drafts
+		.filter((draft) => draft.buffer_id.startsWith(prefix))
+		.map((draft) => ({ tabId: draft.buffer_id.slice(prefix.length), draft }));
+}
+
+/** Saves the contents of a tab with unsaved changes once editing pauses */
+export function autosaveDraft(
+	tabId: string,
+	connectionId: string | null,
+	content: string,
+	cursor: number
+): void {
+	const pending = pendingSaves.get(tabId);
+	if (pending) clearTimeout(pending);
+
+	pendingSaves.set(
+		tabId,
+		setTimeout(() => {
+			pendingSaves.delete(tabId);
+			savedDrafts.add(tabId);
+			Commands.autosaveBuffer(draftBufferId(tabId), connectionId, content, cursor).catch(
+				console.error
+			);
+		}, AUTOSAVE_DELAY_MS)
+	);
+}
+
+/** Drops the draft of a tab that was saved, closed, or has no unsaved changes anymore */
+export function discardDraft(tabId: string): void {
+	const pending = pendingSaves.get(tabId);
+	if (pending) {
+		clearTimeout(pending);
+		pendingSaves.delete(tabId);
+	}
+	if (!savedDrafts.delete(tabId)) return;
+
+	Commands.discardDraftBuffer(draftBufferId(tabId)).catch(console.error);
+}
+
+/** Moves a draft recovered from another session, or another tab, to a tab of this window */
+export function adoptDraft(draft: DraftBuffer, tabId: string): void {
+	const bufferId = draftBufferId(tabId);
+	savedDrafts.add(tabId);
+	if (draft.buffer_id === bufferId) return;
+
+	Commands.autosaveBuffer(bufferId, draft.connection_id, draft.content, draft.cursor)
+		.then(() => Commands.discardDraftBuffer(draft.buffer_id))
+		.catch(console.error);
+}
+
+/** Drops drafts that weren't recovered */
+export function discardDrafts(drafts: DraftBuffer[]): void {
+	for (const draft of drafts) {
+		Commands.discardDraftBuffer(draft.buffer_id).catch(console.error);
+	}
+}
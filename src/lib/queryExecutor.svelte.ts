@@ -3,7 +3,13 @@ import {
 	type QueryId,
 	type Page,
 	type QueryStatus,
-	type QuerySnapshot
+	type QuerySnapshot,
+	type PageAvailable,
+	type ResourceCeiling,
+	type ColumnLineage,
+	type TimingBreakdown,
+	type SubmitOptions,
+	type SavepointOutcome
 } from '$lib/commands.svelte';
 import { SvelteMap } from 'svelte/reactivity';
 
@@ -17,19 +23,29 @@ export interface QueryResultTab {
 	queryReturnsResults?: boolean;
 	affectedRows?: number;
 	columns?: string[];
+	/** Where each column came from, if known */
+	columnLineage?: ColumnLineage[] | null;
 	currentPageIndex: number;
 	currentPageData: Page | null;
 	totalPages: number | null;
 	error?: string;
+	/** Resource ceilings hit while running the query */
+	ceilingsHit?: ResourceCeiling[];
+	/** Where the time went, once the query is over */
+	timings?: TimingBreakdown | null;
+	/** What became of the statement's savepoint, if it ran in one */
+	savepoint?: SavepointOutcome | null;
 }
 
+export type QueryCompleteCallback = (totalRows: number, timings: TimingBreakdown | null) => void;
+
 export class QueryExecutor {
 	resultTabs = $state<QueryResultTab[]>([]);
 	activeResultTabId = $state<number | null>(null);
 
 	private nextResultTabId = 1;
 	private latestPageRequests = new SvelteMap<QueryId, number>();
-	private onComplete?: (totalRows: number) => void;
+	private onComplete?: QueryCompleteCallback;
 	private executionId = 0;
 	private pollingAbortController: AbortController | null = null;
 
@@ -41,10 +57,13 @@ export class QueryExecutor {
 		this.latestPageRequests.clear();
 	}
 
+	/** `submit` replaces the usual submission of `queryText`, for queries the backend builds itself */
 	async executeQuery(
 		queryText: string,
 		connectionId: string,
-		onComplete?: (totalRows: number) => void
+		onComplete?: QueryCompleteCallback,
+		options?: SubmitOptions,
+		submit?: () => Promise<QueryId[]>
 	) {
 		const currentExecutionId = ++this.executionId;
 		// Store callback for use in completion handlers
@@ -53,7 +72,9 @@ export class QueryExecutor {
 		this.stopPollingLoop();
 
 		try {
-			const queryIds = await Commands.submitQuery(connectionId, queryText.trim());
+			const queryIds = submit
+				? await submit()
+				: await Commands.submitQuery(connectionId, queryText.trim(), options);
 
 			if (currentExecutionId !== this.executionId) return;
 
@@ -130,7 +151,9 @@ export class QueryExecutor {
 			this.resultTabs[tabIndex] = {
 				...this.resultTabs[tabIndex],
 				status: 'Error',
-				error: info.error
+				error: info.error,
+				ceilingsHit: info.ceilings_hit,
+				savepoint: info.savepoint
 			};
 			this.resultTabs = [...this.resultTabs];
 			return;
@@ -141,12 +164,14 @@ export class QueryExecutor {
 				...this.resultTabs[tabIndex],
 				status: info.status,
 				queryReturnsResults: false,
-				affectedRows: info.affected_rows ?? undefined
+				affectedRows: info.affected_rows ?? undefined,
+				ceilingsHit: info.ceilings_hit,
+				timings: info.timings
 			};
 			this.resultTabs = [...this.resultTabs];
 
 			if (info.status === 'Completed') {
-				this.onComplete?.(info.affected_rows ?? 0);
+				this.onComplete?.(info.affected_rows ?? 0, info.timings);
 			}
 
 			return;
@@ -165,9 +190,12 @@ export class QueryExecutor {
 		this.resultTabs[tabIndex] = {
 			...this.resultTabs[tabIndex],
 			columns: info.columns,
+			columnLineage: info.column_lineage,
 			currentPageData: info.first_page,
 			status: info.status,
-			queryReturnsResults: true
+			queryReturnsResults: true,
+			ceilingsHit: info.ceilings_hit,
+			timings: info.timings
 		};
 		this.resultTabs = [...this.resultTabs];
 
@@ -179,7 +207,10 @@ export class QueryExecutor {
 				this.resultTabs = [...this.resultTabs];
 			}
 
-			this.onComplete?.((pageCount || 0) * 50);
+			this.onComplete?.(
+				info.statement?.metrics.rows_fetched ?? (pageCount || 0) * 50,
+				info.timings
+			);
 		} else {
 			this.startPollingLoop(executionId);
 		}
@@ -224,7 +255,11 @@ export class QueryExecutor {
 	private async runPollingLoop(executionId: number, signal: AbortSignal) {
 		while (!signal.aborted && executionId === this.executionId) {
 			const runningQueryIds = this.resultTabs
-				.filter((t) => t.status === 'Running' && t.queryReturnsResults !== false)
+				.filter(
+					(t) =>
+						(t.status === 'Running' || t.status === 'Streaming') &&
+						t.queryReturnsResults !== false
+				)
 				.map((t) => t.queryId);
 
 			if (runningQueryIds.length === 0) {
@@ -256,7 +291,8 @@ export class QueryExecutor {
 			const tab = this.resultTabs[tabIndex];
 
 			let changed = false;
-			if (tab.totalPages !== pageCount) {
+			// A page-available event may have been handled since the page count was requested
+			if (tab.totalPages === null || pageCount > tab.totalPages) {
 				this.resultTabs[tabIndex] = { ...this.resultTabs[tabIndex], totalPages: pageCount };
 				changed = true;
 			}
@@ -271,8 +307,14 @@ export class QueryExecutor {
 			}
 
 			if (status === 'Completed') {
-				const totalRows = (pageCount || 0) * 50;
-				this.onComplete?.(totalRows);
+				const totalRows = await this.fetchedRows(queryId, pageCount);
+				const timings = await Commands.getTimingBreakdown(queryId);
+				const idx = this.resultTabs.findIndex((t) => t.queryId === queryId);
+				if (idx >= 0) {
+					this.resultTabs[idx] = { ...this.resultTabs[idx], timings };
+					this.resultTabs = [...this.resultTabs];
+				}
+				this.onComplete?.(totalRows, timings ?? null);
 			}
 		} catch (error) {
 			console.error('Error polling for page count:', error);
@@ -280,6 +322,17 @@ export class QueryExecutor {
 		}
 	}
 
+	/** Rows the query fetched, or an estimate from its page count if that's unknown */
+	private async fetchedRows(queryId: QueryId, pageCount: number): Promise<number> {
+		try {
+			const [info] = (await Commands.getStatementInfos([queryId])) ?? [];
+			if (info) return info.metrics.rows_fetched;
+		} catch (error) {
+			console.error('Failed to get the rows fetched:', error);
+		}
+		return (pageCount || 0) * 50;
+	}
+
 	private async sleep(ms: number, signal?: AbortSignal): Promise<void> {
 		await new Promise<void>((resolve) => {
 			if (signal?.aborted) {
@@ -317,9 +370,42 @@ export class QueryExecutor {
 				currentPageData: page
 			};
 			this.resultTabs = [...this.resultTabs];
+
+			// Postgres queries stop fetching rows a little ahead of those read, so reading the last page fetched
+			// asks for more
+			const tab = this.resultTabs[tabIndex];
+			if (tab.status === 'Streaming' && pageIndex >= (tab.totalPages ?? 0) - 1) {
+				void this.requestMoreRows(queryId);
+			}
 		}
 	}
 
+	private async requestMoreRows(queryId: QueryId) {
+		try {
+			await Commands.requestMoreRows(queryId);
+		} catch (error) {
+			console.error('Failed to request more rows:', error);
+		}
+	}
+
+	// Extends the page count of a still-streaming query as soon as a new page is readable, instead of waiting for
+	// the next poll. Page counts only ever grow, so stale events are ignored.
+	handlePageAvailable = (event: PageAvailable) => {
+		const tabIndex = this.resultTabs.findIndex((t) => t.queryId === event.query_id);
+		if (tabIndex < 0) return;
+
+		const tab = this.resultTabs[tabIndex];
+		if (tab.status === 'Completed' || tab.status === 'Error') return;
+		if ((tab.totalPages ?? 0) >= event.page_count) return;
+
+		this.resultTabs[tabIndex] = {
+			...tab,
+			totalPages: event.page_count,
+			status: tab.status === 'Running' ? 'Streaming' : tab.status
+		};
+		this.resultTabs = [...this.resultTabs];
+	};
+
 	private generateTabTitle(query: string): string {
 		const cleaned = query.trim().replace(/\s+/g, ' ');
 		if (cleaned.length <= 30) return cleaned;
@@ -344,6 +430,7 @@ export class QueryExecutor {
 			case 'Error':
 				return 'error';
 			case 'Running':
+			case 'Streaming':
 				return 'modified';
 			default:
 				return 'normal';
@@ -10,7 +10,10 @@ vi.mock('$lib/commands.svelte', () => ({
 		waitUntilRenderable: vi.fn(),
 		fetchPage: vi.fn(),
 		getQueryStatus: vi.fn(),
-		getPageCount: vi.fn()
+		getPageCount: vi.fn(),
+		requestMoreRows: vi.fn(),
+		getStatementInfos: vi.fn(),
+		getTimingBreakdown: vi.fn()
 	}
 }));
 
@@ -22,6 +25,9 @@ const mockCommands = Commands as unknown as {
 	fetchPage: ReturnType<typeof vi.fn>;
 	getQueryStatus: ReturnType<typeof vi.fn>;
 	getPageCount: ReturnType<typeof vi.fn>;
+	requestMoreRows: ReturnType<typeof vi.fn>;
+	getStatementInfos: ReturnType<typeof vi.fn>;
+	getTimingBreakdown: ReturnType<typeof vi.fn>;
 };
 
 function createMockStatementInfo(overrides: Partial<QuerySnapshot> = {}): QuerySnapshot {
@@ -32,6 +38,10 @@ function createMockStatementInfo(overrides: Partial<QuerySnapshot> = {}): QueryS
 		affected_rows: null,
 		columns: ['id'],
 		error: null,
+		ceilings_hit: [],
+		column_lineage: null,
+		timings: null,
+		savepoint: null,
 		...overrides
 	};
 }
@@ -193,7 +203,7 @@ describe('QueryExecutor', () => {
 				queryReturnsResults: false,
 				affectedRows
 			});
-			expect(onComplete).toHaveBeenCalledWith(affectedRows);
+			expect(onComplete).toHaveBeenCalledWith(affectedRows, null);
 		});
 
 		it('should handle statement-level errors', async () => {
@@ -237,7 +247,40 @@ describe('QueryExecutor', () => {
 			await flushPromises();
 
 			// 50 rows per page * 5 pages = 250 rows
-			expect(onComplete).toHaveBeenCalledWith(250);
+			expect(onComplete).toHaveBeenCalledWith(250, null);
+		});
+
+		it('should call onComplete with the rows actually fetched when known', async () => {
+			const queryId: QueryId = 1;
+			const onComplete = vi.fn();
+
+			mockCommands.submitQuery.mockResolvedValue([queryId]);
+			mockCommands.waitUntilRenderable.mockResolvedValue(
+				createMockStatementInfo({
+					status: 'Completed',
+					statement: {
+						query_id: queryId,
+						ordinal: 0,
+						start: 0,
+						end: 19,
+						kind: 'select',
+						spilled: false,
+						metrics: {
+							first_row_ms: 1,
+							elapsed_ms: 2,
+							rows_fetched: 123,
+							bytes_fetched: 1024,
+							command_tag: 'SELECT 123'
+						}
+					}
+				})
+			);
+			mockCommands.getPageCount.mockResolvedValue(3);
+
+			await executor.executeQuery('SELECT * FROM users', 'conn-1', onComplete);
+			await flushPromises();
+
+			expect(onComplete).toHaveBeenCalledWith(123, null);
 		});
 
 		it('should not call onComplete on errors', async () => {
@@ -523,6 +566,28 @@ describe('QueryExecutor', () => {
 			});
 		});
 
+		it('should extend the page count of a streaming query as pages become available', async () => {
+			mockCommands.waitUntilRenderable.mockResolvedValue(
+				createMockStatementInfo({
+					status: 'Streaming',
+					first_page: createMockPage(2)
+				})
+			);
+			mockCommands.getQueryStatus.mockResolvedValue('Streaming');
+			mockCommands.getPageCount.mockResolvedValue(1);
+
+			await executor.executeQuery('SELECT * FROM events', 'conn-1');
+			await flushPromises();
+
+			const queryId = executor.resultTabs[0].queryId;
+			executor.handlePageAvailable({ query_id: queryId, first_new_page: 1, page_count: 3 });
+			expect(executor.resultTabs[0]).toMatchObject({ status: 'Streaming', totalPages: 3 });
+
+			// Events can arrive out of order
+			executor.handlePageAvailable({ query_id: queryId, first_new_page: 1, page_count: 2 });
+			expect(executor.resultTabs[0].totalPages).toBe(3);
+		});
+
 		it('should load next page', async () => {
 			mockCommands.getPageCount.mockResolvedValue(3);
 			const page1Data = createMockPage(3);
@@ -0,0 +1,81 @@
+import { isTauri } from '@tauri-apps/api/core';
+import { getCurrentWindow } from '@tauri-apps/api/window';
+import { Commands } from './commands.svelte';
+
+/** The version of the session format written by this version of pgpad */
+export const SESSION_VERSION = 1;
+
+const WEB_SESSION_STORAGE_KEY = 'pgpad.web.session';
+
+/** Sessions of browser tabs that weren't saved for this long are dropped, in seconds */
+const STALE_WEB_SESSION_SECS = 30 * 24 * 60 * 60;
+
+// The session that loadSession restored, which is another window's when a browser tab falls back to it
+let restoredFrom: string | null = null;
+
+/**
+ * Each window saves its own session: the app's windows by their label, which stays the same across restarts, and
+ * browser tabs by an id kept for as long as the tab is open.
+ */
+export function currentSessionId(): string {
+	if (isTauri()) return getCurrentWindow().label;
+
+	try {
+		let sessionId = sessionStorage.getItem(WEB_SESSION_STORAGE_KEY);
+		if (!sessionId) {
+			sessionId = `web-${crypto.randomUUID()}`;
+			sessionStorage.setItem(WEB_SESSION_STORAGE_KEY, sessionId);
+		}
+		return sessionId;
+	} catch {
+		return 'web';
+	}
+}
+
+/** Brings a session saved by an older version of pgpad up to SESSION_VERSION, or null if it can't be */
+function upgradeSession(version: number, data: unknown): unknown {
+	switch (version) {
+		case 1:
+			return data;
+		// Saved by a newer version of pgpad
+		default:
+			return null;
+	}
+}
+
+/** The id of the session this window was restored from, its own unless it had none */
+export function restoredSessionId(): string {
+	return restoredFrom ?? currentSessionId();
+}
+
+export async function saveSession(data: Record<string, unknown>): Promise<void> {
+	await Commands.saveSession(currentSessionId(), SESSION_VERSION, JSON.stringify(data));
+}
+
+/**
+ * The saved session of this window. A browser tab without one starts from the most recently saved session, since
+ * the id of the tab it was saved from didn't outlive that tab.
+ */
+export async function loadSession<T>(): Promise<T | null> {
+	let saved = await Commands.getSession(currentSessionId());
+
+	if (!saved && !isTauri()) {
+		const sessions = await Commands.listSessions();
+		const now = Date.now() / 1000;
+		for (const session of sessions) {
+			if (
+				session.session_id.startsWith('web-') &&
+				now - session.updated_at > STALE_WEB_SESSION_SECS
+			) {
+				await Commands.deleteSession(session.session_id);
+			}
+		}
+		if (sessions.length > 0) {
+			saved = await Commands.getSession(sessions[0].session_id);
+		}
+	}
+
+	if (!saved) return null;
+	restoredFrom = saved.session_id;
+	return upgradeSession(saved.version, JSON.parse(saved.data)) as T | null;
+}
@@ -1,6 +1,7 @@
 import { EditorState } from '@codemirror/state';
-import type { Script } from '$lib/commands.svelte';
+import { Commands, type Script } from '$lib/commands.svelte';
 import { SvelteSet } from 'svelte/reactivity';
+import { discardDraft } from '$lib/drafts';
 
 interface BaseTab {
 	id: string;
@@ -19,6 +20,12 @@ export interface ScriptTab extends BaseTab {
 	content: string;
 	editorState?: EditorState;
 	isNewScript: boolean;
+	// Set for scripts opened from a linked directory, which are read from disk
+	linkedPath?: string;
+	// Set while the script is being edited in an external editor
+	externalEditSessionId?: string;
+	// Set for definitions of database objects, which are shown but not edited
+	readOnly?: boolean;
 }
 
 export interface TableViewTab extends BaseTab {
@@ -81,6 +88,12 @@ function markSessionDirty() {
 	sessionSaveCallbacks.forEach((callback) => callback());
 }
 
+function findLinkedScriptTab(path: string): ScriptTab | undefined {
+	return tabStore.tabs.find(
+		(tab) => tab.type === 'script' && (tab as ScriptTab).linkedPath === path
+	) as ScriptTab | undefined;
+}
+
 function findScriptTab(scriptId: number): ScriptTab | undefined {
 	return tabStore.tabs.find(
 		(tab) => tab.type === 'script' && (tab as ScriptTab).scriptId === scriptId
@@ -106,6 +119,11 @@ export const tabs = {
 	get currentEditorContent(): string {
 		return tabStore.currentEditorContent;
 	},
+	get activeLinkedPath(): string | null {
+		const activeTab = this.active;
+		if (activeTab?.type !== 'script') return null;
+		return (activeTab as ScriptTab).linkedPath ?? null;
+	},
 	get nextTempId(): number {
 		return tabStore.nextTempId;
 	},
@@ -187,6 +205,10 @@ export const tabs = {
 		if (tab.type === 'script') {
 			const scriptTab = tab as ScriptTab;
 			tabStore.newScripts.delete(scriptTab.scriptId);
+			discardDraft(tabId);
+			if (scriptTab.externalEditSessionId) {
+				Commands.endExternalEdit(scriptTab.externalEditSessionId).catch(console.error);
+			}
 		}
 
 		if (tabStore.activeTabId === tabId) {
@@ -218,6 +240,56 @@ export const tabs = {
 				: newContent !== originalContent;
 
 			scriptTab.isDirty = shouldShowUnsaved;
+
+			if (scriptTab.externalEditSessionId) {
+				Commands.syncExternalEdit(scriptTab.externalEditSessionId, newContent).catch(
+					console.error
+				);
+			}
+		}
+	},
+
+	async editExternally(tabId: string): Promise<void> {
+		const tab = tabStore.tabs.find((t) => t.id === tabId);
+		if (tab?.type !== 'script') return;
+
+		const scriptTab = tab as ScriptTab;
+		if (scriptTab.externalEditSessionId) return;
+
+		const content =
+			tabStore.activeTabId === tabId ? tabStore.currentEditorContent : scriptTab.content;
+		const session = await Commands.editExternally(tabId, scriptTab.title, content);
+		scriptTab.externalEditSessionId = session.id;
+	},
+
+	async endExternalEdit(tabId: string): Promise<void> {
+		const tab = tabStore.tabs.find((t) => t.id === tabId);
+		if (tab?.type !== 'script') return;
+
+		const scriptTab = tab as ScriptTab;
+		if (!scriptTab.externalEditSessionId) return;
+
+		await Commands.endExternalEdit(scriptTab.externalEditSessionId);
+		scriptTab.externalEditSessionId = undefined;
+	},
+
+	/** Replaces a tab's contents with the ones from its external editor, or recovered from its draft */
+	applyExternalEdit(tabId: string, content: string): void {
+		const tab = tabStore.tabs.find((t) => t.id === tabId);
+		if (tab?.type !== 'script') return;
+
+		const scriptTab = tab as ScriptTab;
+		if (scriptTab.content === content) return;
+
+		scriptTab.content = content;
+		scriptTab.editorState = undefined;
+		scriptTab.isDirty = scriptTab.isNewScript
+			? content.length > 0
+			: content !== scriptTab.script.query_text;
+
+		if (tabStore.activeTabId === scriptTab.id) {
+			tabStore.currentEditorContent = content;
+			tabStore.sqlEditorRef?.setContent(content);
 		}
 	},
 
@@ -233,9 +305,19 @@ export const tabs = {
 			}
 
 			scriptTab.isDirty = false;
+			discardDraft(scriptTab.id);
 		}
 	},
 
+	/** Puts back the recovered contents of a tab. Returns false if the tab isn't open. */
+	recoverDraft(tabId: string, content: string): boolean {
+		const tab = tabStore.tabs.find((t) => t.id === tabId);
+		if (tab?.type !== 'script') return false;
+
+		this.applyExternalEdit(tabId, content);
+		return true;
+	},
+
 	updateScriptId(oldScriptId: number, newScriptId: number, updatedScript: Script): void {
 		const oldTabId = `script-${oldScriptId}`;
 		const newTabId = `script-${newScriptId}`;
@@ -245,6 +327,7 @@ export const tabs = {
 			| undefined;
 
 		if (scriptTab) {
+			discardDraft(oldTabId);
 			scriptTab.id = newTabId;
 			scriptTab.scriptId = newScriptId;
 			scriptTab.script = updatedScript;
@@ -261,21 +344,25 @@ export const tabs = {
 	},
 
 	createScriptFromHistory(historyQuery: string): void {
-		const tempId = tabStore.nextTempId--;
-
 		const existingUntitled = tabStore.scripts.filter((s) =>
 			s.name.startsWith('Untitled Script')
 		).length;
 		const name =
 			existingUntitled === 0 ? 'Untitled Script' : `Untitled Script ${existingUntitled + 1}`;
 
+		this.createScript(name, historyQuery);
+	},
+
+	createScript(name: string, queryText: string): number {
+		const tempId = tabStore.nextTempId--;
+
 		const newScript: Script = {
 			id: tempId,
 			name,
 			description: null,
-			query_text: historyQuery,
+			query_text: queryText,
 			connection_id: null,
-			tags: null,
+			tags: [],
 			created_at: Date.now() / 1000,
 			updated_at: Date.now() / 1000,
 			favorite: false
@@ -285,6 +372,64 @@ export const tabs = {
 		tabStore.newScripts.add(tempId);
 		this.openScript(newScript);
 		markSessionDirty();
+
+		return tempId;
+	},
+
+	openDefinition(title: string, definition: string): void {
+		const scriptId = this.createScript(title, definition);
+		const tab = findScriptTab(scriptId);
+		if (tab) {
+			tab.readOnly = true;
+		}
+	},
+
+	openLinkedScript(path: string, name: string, content: string): void {
+		const existingTab = findLinkedScriptTab(path);
+		if (existingTab) {
+			this.switchToTab(existingTab.id);
+			return;
+		}
+
+		const scriptId = this.createScript(name, content);
+		const scriptTab = findScriptTab(scriptId);
+		if (scriptTab) {
+			scriptTab.linkedPath = path;
+		}
+	},
+
+	/**
+	 * Refreshes the tab of a linked script after it changed on disk.
+	 * `content` is null if the file was deleted.
+	 */
+	reloadLinkedScript(path: string, content: string | null): void {
+		const scriptTab = findLinkedScriptTab(path);
+		if (!scriptTab) return;
+
+		if (content === null) {
+			if (!scriptTab.title.endsWith(' (deleted)')) {
+				scriptTab.title = `${scriptTab.title} (deleted)`;
+			}
+			return;
+		}
+
+		if (scriptTab.content === content) return;
+		if (
+			scriptTab.isDirty &&
+			!confirm(`${scriptTab.title} changed on disk. Reload it and discard your changes?`)
+		) {
+			return;
+		}
+
+		scriptTab.script.query_text = content;
+		scriptTab.content = content;
+		scriptTab.editorState = undefined;
+		scriptTab.isDirty = false;
+
+		if (tabStore.activeTabId === scriptTab.id) {
+			tabStore.currentEditorContent = content;
+			tabStore.sqlEditorRef?.setContent(content);
+		}
 	},
 
 	renameScript(tabId: string, newName: string): void {
@@ -390,7 +535,7 @@ export const tabs = {
 					description: null,
 					query_text: temp.content,
 					connection_id: null,
-					tags: null,
+					tags: [],
 					created_at: Date.now() / 1000,
 					updated_at: Date.now() / 1000,
 					favorite: false
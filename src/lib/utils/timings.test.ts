@@ -0,0 +1,25 @@
+import { describe, it, expect } from 'vitest';
+import { formatDuration, formatTimings } from './timings';
+
+describe('formatDuration', () => {
+	it('should pick a unit based on the duration', () => {
+		expect(formatDuration(0.05)).toBe('0.05ms');
+		expect(formatDuration(12.4)).toBe('12ms');
+		expect(formatDuration(8040)).toBe('8.0s');
+	});
+});
+
+describe('formatTimings', () => {
+	it('should list every phase', () => {
+		expect(
+			formatTimings({
+				parse_ms: 1,
+				queue_ms: 0.05,
+				execute_ms: 7200,
+				fetch_ms: 800,
+				serialize_ms: 120,
+				total_ms: 8001.05
+			})
+		).toBe('8.0s: parse 1ms, queue 0.05ms, execute 7.2s, fetch 800ms (serialize 120ms)');
+	});
+});
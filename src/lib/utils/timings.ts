@@ -0,0 +1,19 @@
+import type { TimingBreakdown } from '$lib/commands.svelte';
+
+export function formatDuration(ms: number): string {
+	if (ms < 1) return `${ms.toFixed(2)}ms`;
+	if (ms < 1000) return `${Math.round(ms)}ms`;
+	return `${(ms / 1000).toFixed(1)}s`;
+}
+
+/** e.g. "8.0s: parse 1ms, queue 0.05ms, execute 7.2s, fetch 800ms (serialize 120ms)" */
+export function formatTimings(timings: TimingBreakdown): string {
+	const phases = [
+		`parse ${formatDuration(timings.parse_ms)}`,
+		`queue ${formatDuration(timings.queue_ms)}`,
+		`execute ${formatDuration(timings.execute_ms)}`,
+		`fetch ${formatDuration(timings.fetch_ms)}`
+	].join(', ');
+
+	return `${formatDuration(timings.total_ms)}: ${phases} (serialize ${formatDuration(timings.serialize_ms)})`;
+}